@@ -0,0 +1,170 @@
+//! Sync pipeline throughput
+//!
+//! Times a full sync of generated transactions from a mock Monzo API into a
+//! fresh database, and the beancount ledger generated from the result. Run
+//! with `cargo bench --bench sync`; `update --profile-sync` breaks a real
+//! run down by phase.
+
+use std::time::{Duration, Instant};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use monzo_cli::{
+    client::Monzo,
+    export::{beancount::BeancountExporter, export},
+    model::DatabasePool,
+    SyncEngine,
+};
+use serde_json::{json, Value};
+use temp_dir::TempDir;
+use tokio::runtime::Runtime;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const TRANSACTIONS: u64 = 1_000;
+
+const FIXTURES: [(&str, &str); 4] = [
+    (
+        "/accounts",
+        include_str!("../src/tests/fixtures/accounts.json"),
+    ),
+    ("/pots", include_str!("../src/tests/fixtures/pots.json")),
+    (
+        "/balance",
+        include_str!("../src/tests/fixtures/balance.json"),
+    ),
+    (
+        "/ping/whoami",
+        include_str!("../src/tests/fixtures/whoami.json"),
+    ),
+];
+
+fn date(day: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 4, day)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
+
+// `TRANSACTIONS` settled card payments a minute apart from 1 April, copied
+// from the first fixture transaction
+fn transactions() -> String {
+    let fixture: Value =
+        serde_json::from_str(include_str!("../src/tests/fixtures/transactions.json")).unwrap();
+    let template = &fixture["transactions"][0];
+    let start = date(1).and_utc();
+
+    let transactions: Vec<Value> = (0..TRANSACTIONS)
+        .map(|i| {
+            let created = start + Duration::from_secs(60 * i);
+            let mut tx = template.clone();
+            tx["id"] = json!(format!("tx_bench_{i:06}"));
+            tx["amount"] = json!(-100 - i64::try_from(i).unwrap());
+            tx["local_amount"] = tx["amount"].clone();
+            tx["created"] = json!(created.to_rfc3339());
+            tx["settled"] = json!((created + Duration::from_secs(86_400)).to_rfc3339());
+            tx["updated"] = tx["settled"].clone();
+            tx
+        })
+        .collect();
+
+    json!({ "transactions": transactions }).to_string()
+}
+
+async fn mock_monzo() -> MockServer {
+    let server = MockServer::start().await;
+    let transactions = transactions();
+    let routes = FIXTURES
+        .iter()
+        .map(|&(route, body)| (route, body.to_string()))
+        .chain([("/transactions", transactions)]);
+
+    for (route, body) in routes {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
+            .mount(&server)
+            .await;
+    }
+
+    server
+}
+
+async fn empty_db(dir: &TempDir) -> DatabasePool {
+    let db_path = dir.path().join("bench.db?mode=rwc");
+    let pool = DatabasePool::new(db_path.to_str().unwrap(), 1)
+        .await
+        .unwrap();
+    pool.seed_initial_data().await.unwrap();
+    pool
+}
+
+// a single 30-day window, so every transaction is fetched once
+async fn sync(server: &MockServer, pool: DatabasePool) {
+    let monzo = Monzo::with_base_url(&server.uri(), "bench-access-token").unwrap();
+    SyncEngine::new(pool, monzo)
+        .sync(date(1), date(30))
+        .await
+        .unwrap();
+}
+
+fn sync_pipeline(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(mock_monzo());
+
+    let mut group = c.benchmark_group("sync");
+    group.throughput(Throughput::Elements(TRANSACTIONS));
+    group.sample_size(10);
+
+    // every iteration syncs into a new database, created outside the timing
+    group.bench_function("fresh database", |b| {
+        b.to_async(&runtime).iter_custom(|iters| {
+            let server = &server;
+            async move {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let dir = TempDir::with_prefix("monzo-bench").unwrap();
+                    let pool = empty_db(&dir).await;
+                    let start = Instant::now();
+                    sync(server, pool).await;
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            }
+        });
+    });
+
+    // every transaction is already stored, as in a daily update
+    let dir = TempDir::with_prefix("monzo-bench").unwrap();
+    let pool = runtime.block_on(async {
+        let pool = empty_db(&dir).await;
+        sync(&server, pool.clone()).await;
+        pool
+    });
+    group.bench_function("already stored", |b| {
+        b.to_async(&runtime).iter(|| sync(&server, pool.clone()));
+    });
+
+    group.bench_function("beancount ledger", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let mut out = Vec::new();
+            export(
+                pool.clone(),
+                &mut BeancountExporter::default(),
+                date(1),
+                date(30),
+                &mut out,
+            )
+            .await
+            .unwrap();
+            out
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, sync_pipeline);
+criterion_main!(benches);
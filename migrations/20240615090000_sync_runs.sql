@@ -0,0 +1,14 @@
+-- Audit log of update runs
+
+CREATE TABLE sync_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    started DATETIME NOT NULL,
+    finished DATETIME,
+    range_start DATETIME NOT NULL,
+    range_end DATETIME NOT NULL,
+    accounts INTEGER NOT NULL DEFAULT 0,
+    inserted INTEGER NOT NULL DEFAULT 0,
+    updated INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    error TEXT
+);
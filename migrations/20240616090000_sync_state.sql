@@ -0,0 +1,12 @@
+-- Checkpoints of the (account, window) pairs completed by each update run
+
+CREATE TABLE sync_state (
+    run_id INTEGER NOT NULL,
+    account_id TEXT NOT NULL,
+    window_start DATETIME NOT NULL,
+    window_end DATETIME NOT NULL,
+    completed DATETIME NOT NULL,
+
+    PRIMARY KEY (run_id, account_id, window_start),
+    FOREIGN KEY(run_id) REFERENCES sync_runs(id)
+);
@@ -0,0 +1,15 @@
+-- Zero-amount events such as active card checks. Kept apart from transactions
+-- so they never count towards spending totals.
+
+CREATE TABLE card_events (
+    id TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL,
+    merchant_id TEXT,
+    currency TEXT NOT NULL,
+    created DATETIME NOT NULL,
+    description TEXT NOT NULL,
+    category_id TEXT NOT NULL,
+
+    FOREIGN KEY(account_id) REFERENCES accounts(id),
+    FOREIGN KEY(merchant_id) REFERENCES merchants(id)
+);
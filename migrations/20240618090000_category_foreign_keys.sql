@@ -0,0 +1,49 @@
+-- Make categories(id) the single source of category ids. Merchants and card
+-- events stored a free-text category; they now reference categories like
+-- transactions do. Missing categories are created with their id as name.
+
+-- Migrations run inside a transaction, so foreign keys can't be switched off
+-- while the tables are rebuilt. Defer the checks to the commit instead, by
+-- which time every referenced row has been copied back.
+PRAGMA defer_foreign_keys = ON;
+
+INSERT OR IGNORE INTO categories (id, name)
+SELECT DISTINCT category, category FROM merchants;
+
+INSERT OR IGNORE INTO categories (id, name)
+SELECT DISTINCT category_id, category_id FROM card_events;
+
+INSERT OR IGNORE INTO categories (id, name)
+SELECT DISTINCT category_id, category_id FROM transactions;
+
+CREATE TEMP TABLE merchants_copy AS SELECT * FROM merchants;
+DROP TABLE merchants;
+CREATE TABLE merchants (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    category_id TEXT NOT NULL,
+
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+INSERT INTO merchants (id, name, category_id)
+SELECT id, name, category FROM merchants_copy;
+DROP TABLE merchants_copy;
+
+CREATE TEMP TABLE card_events_copy AS SELECT * FROM card_events;
+DROP TABLE card_events;
+CREATE TABLE card_events (
+    id TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL,
+    merchant_id TEXT,
+    currency TEXT NOT NULL,
+    created DATETIME NOT NULL,
+    description TEXT NOT NULL,
+    category_id TEXT NOT NULL,
+
+    FOREIGN KEY(account_id) REFERENCES accounts(id),
+    FOREIGN KEY(merchant_id) REFERENCES merchants(id),
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+INSERT INTO card_events (id, account_id, merchant_id, currency, created, description, category_id)
+SELECT id, account_id, merchant_id, currency, created, description, category_id FROM card_events_copy;
+DROP TABLE card_events_copy;
@@ -0,0 +1,18 @@
+-- Merchants are refreshed on every sync. Keep their address and when they
+-- were last reported, and log every change to a merchant's details.
+
+ALTER TABLE merchants ADD COLUMN address TEXT;
+ALTER TABLE merchants ADD COLUMN last_seen DATETIME;
+
+CREATE TABLE merchant_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    merchant_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    changed DATETIME NOT NULL,
+
+    FOREIGN KEY(merchant_id) REFERENCES merchants(id)
+);
+
+CREATE INDEX merchant_changes_merchant_id ON merchant_changes (merchant_id);
@@ -0,0 +1,9 @@
+-- Secondary indexes for reading transactions by account, date, category and
+-- merchant. (created, id) is the order used for keyset pagination.
+
+CREATE INDEX transactions_account_id_created ON transactions (account_id, created);
+CREATE INDEX transactions_created_id ON transactions (created, id);
+CREATE INDEX transactions_category_id ON transactions (category_id);
+CREATE INDEX transactions_merchant_id ON transactions (merchant_id);
+
+CREATE INDEX card_events_created_id ON card_events (created, id);
@@ -0,0 +1,48 @@
+-- Views for reporting and ad hoc queries (`monzo query`). Days and months are
+-- UTC. Pending transactions are included; card events are not.
+
+CREATE VIEW monthly_category_totals AS
+SELECT
+    strftime('%Y-%m', t.created) AS month,
+    t.category_id,
+    c.name AS category_name,
+    t.currency,
+    SUM(t.amount) AS total,
+    COUNT(*) AS count
+FROM transactions t
+JOIN categories c ON t.category_id = c.id
+GROUP BY month, t.category_id, t.currency;
+
+CREATE VIEW merchant_totals AS
+SELECT
+    m.id AS merchant_id,
+    m.name AS merchant_name,
+    m.category_id,
+    t.currency,
+    SUM(t.amount) AS total,
+    COUNT(*) AS count,
+    MIN(t.created) AS first_transaction,
+    MAX(t.created) AS last_transaction
+FROM transactions t
+JOIN merchants m ON t.merchant_id = m.id
+GROUP BY m.id, t.currency;
+
+-- The running total of synced transactions per account at the end of each day
+-- with activity. This is relative to the first synced transaction, not the
+-- account's true balance.
+CREATE VIEW daily_balances AS
+SELECT
+    day,
+    account_id,
+    currency,
+    net,
+    SUM(net) OVER (PARTITION BY account_id, currency ORDER BY day) AS balance
+FROM (
+    SELECT
+        date(created) AS day,
+        account_id,
+        currency,
+        SUM(amount) AS net
+    FROM transactions
+    GROUP BY day, account_id, currency
+);
@@ -0,0 +1,32 @@
+-- Account and pot balances recorded by `update` and `balances --refresh`
+
+CREATE TABLE balance_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    taken DATETIME NOT NULL
+);
+
+CREATE TABLE account_balances (
+    snapshot_id INTEGER NOT NULL,
+    account_id TEXT NOT NULL,
+    balance INTEGER NOT NULL,
+    total_balance INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    spend_today INTEGER NOT NULL,
+
+    PRIMARY KEY (snapshot_id, account_id),
+    FOREIGN KEY(snapshot_id) REFERENCES balance_snapshots(id),
+    FOREIGN KEY(account_id) REFERENCES accounts(id)
+);
+
+-- Pot names are copied as pots can be renamed or deleted after the snapshot
+CREATE TABLE pot_balances (
+    snapshot_id INTEGER NOT NULL,
+    account_id TEXT NOT NULL,
+    pot_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    balance INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+
+    PRIMARY KEY (snapshot_id, pot_id),
+    FOREIGN KEY(snapshot_id, account_id) REFERENCES account_balances(snapshot_id, account_id)
+);
@@ -0,0 +1,10 @@
+-- Exchange rates: on `date`, one unit of `currency` was worth `rate` units of `base`
+
+CREATE TABLE fx_rates (
+    date DATE NOT NULL,
+    currency TEXT NOT NULL,
+    base TEXT NOT NULL,
+    rate REAL NOT NULL,
+
+    PRIMARY KEY (date, currency, base)
+);
@@ -0,0 +1,4 @@
+-- Merchant coordinates, for mapping spend by location
+
+ALTER TABLE merchants ADD COLUMN latitude REAL;
+ALTER TABLE merchants ADD COLUMN longitude REAL;
@@ -0,0 +1,20 @@
+-- Accounts held outside Monzo (savings, ISAs, mortgages) and their valuations
+
+CREATE TABLE manual_accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL CHECK (kind IN ('asset', 'liability')),
+    institution TEXT,
+    currency TEXT NOT NULL,
+    created DATETIME NOT NULL
+);
+
+-- The balance of an account on a date, in minor units. Liabilities are negative.
+CREATE TABLE manual_valuations (
+    account_id INTEGER NOT NULL,
+    date DATE NOT NULL,
+    balance INTEGER NOT NULL,
+
+    PRIMARY KEY (account_id, date),
+    FOREIGN KEY(account_id) REFERENCES manual_accounts(id)
+);
@@ -0,0 +1,41 @@
+-- Transfers between the user's own accounts and pots, and card repayments,
+-- are flagged so they don't count as spending. The counterparty is kept so
+-- transfers between accounts can be recognised.
+
+ALTER TABLE transactions ADD COLUMN counterparty_account_number TEXT;
+ALTER TABLE transactions ADD COLUMN counterparty_sort_code TEXT;
+ALTER TABLE transactions ADD COLUMN is_transfer BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- pot transfers carry the pot id as their description
+UPDATE transactions SET is_transfer = TRUE
+WHERE description IN (SELECT id FROM pots);
+
+DROP VIEW monthly_category_totals;
+CREATE VIEW monthly_category_totals AS
+SELECT
+    strftime('%Y-%m', t.created) AS month,
+    t.category_id,
+    c.name AS category_name,
+    t.currency,
+    SUM(t.amount) AS total,
+    COUNT(*) AS count
+FROM transactions t
+JOIN categories c ON t.category_id = c.id
+WHERE NOT t.is_transfer
+GROUP BY month, t.category_id, t.currency;
+
+DROP VIEW merchant_totals;
+CREATE VIEW merchant_totals AS
+SELECT
+    m.id AS merchant_id,
+    m.name AS merchant_name,
+    m.category_id,
+    t.currency,
+    SUM(t.amount) AS total,
+    COUNT(*) AS count,
+    MIN(t.created) AS first_transaction,
+    MAX(t.created) AS last_transaction
+FROM transactions t
+JOIN merchants m ON t.merchant_id = m.id
+WHERE NOT t.is_transfer
+GROUP BY m.id, t.currency;
@@ -0,0 +1,13 @@
+-- People paid or paid by through Monzo-to-Monzo (P2P) payments, keyed by
+-- their Monzo user id
+
+CREATE TABLE counterparties (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    account_number TEXT,
+    sort_code TEXT
+);
+
+ALTER TABLE transactions ADD COLUMN counterparty_id TEXT REFERENCES counterparties(id);
+
+CREATE INDEX idx_transactions_counterparty_id ON transactions(counterparty_id);
@@ -0,0 +1,30 @@
+-- Friendlier names and emoji for categories, as shown in the Monzo app
+
+ALTER TABLE categories ADD COLUMN display_name TEXT;
+ALTER TABLE categories ADD COLUMN emoji TEXT;
+
+WITH built_in(id, display_name, emoji) AS (
+    VALUES
+        ('general', 'General', '📦'),
+        ('eating_out', 'Eating out', '🍔'),
+        ('expenses', 'Expenses', '💼'),
+        ('transport', 'Transport', '🚌'),
+        ('cash', 'Cash', '💵'),
+        ('bills', 'Bills', '💡'),
+        ('entertainment', 'Entertainment', '🎉'),
+        ('shopping', 'Shopping', '🛍️'),
+        ('holidays', 'Holidays', '🏖️'),
+        ('groceries', 'Groceries', '🛒'),
+        ('income', 'Income', '💰'),
+        ('savings', 'Savings', '🐷'),
+        ('transfers', 'Transfers', '🔁'),
+        ('charity', 'Charity', '💝'),
+        ('family', 'Family', '👪'),
+        ('finances', 'Finances', '📈'),
+        ('gifts', 'Gifts', '🎁'),
+        ('personal_care', 'Personal care', '🧴')
+)
+UPDATE categories
+SET display_name = built_in.display_name, emoji = built_in.emoji
+FROM built_in
+WHERE categories.id = built_in.id;
@@ -0,0 +1,5 @@
+-- Credit card repayments matched by a `[transfers.repayments]` rule record the
+-- manual liability account they pay off, so the ledger can post them there.
+
+ALTER TABLE transactions ADD COLUMN repayment_account_id INTEGER
+    REFERENCES manual_accounts(id);
@@ -0,0 +1,18 @@
+-- Categories set by hand with `transactions set`. They win over the category
+-- Monzo reports when a pending transaction is reconciled.
+CREATE TABLE category_overrides (
+    transaction_id TEXT PRIMARY KEY NOT NULL,
+    category_id TEXT NOT NULL,
+    created DATETIME NOT NULL,
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id),
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+
+CREATE TABLE transaction_tags (
+    transaction_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY(transaction_id, tag),
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+);
+
+CREATE INDEX transaction_tags_tag ON transaction_tags(tag);
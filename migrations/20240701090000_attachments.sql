@@ -0,0 +1,17 @@
+-- Receipts and other files attached to transactions in the Monzo app. `path`
+-- and `sha256` are set once `attachments download` has saved the file.
+
+CREATE TABLE attachments (
+    id TEXT PRIMARY KEY NOT NULL,
+    transaction_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    file_type TEXT NOT NULL,
+    created DATETIME NOT NULL,
+    path TEXT,
+    sha256 TEXT,
+
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+);
+
+CREATE INDEX attachments_transaction ON attachments(transaction_id);
+CREATE INDEX attachments_sha256 ON attachments(sha256);
@@ -0,0 +1,6 @@
+-- Merchant logos as reported by the API. `logo_path` is set once
+-- `merchants fetch-logos` has cached the image, and cleared when the logo
+-- changes.
+
+ALTER TABLE merchants ADD COLUMN logo TEXT;
+ALTER TABLE merchants ADD COLUMN logo_path TEXT;
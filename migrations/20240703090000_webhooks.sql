@@ -0,0 +1,17 @@
+-- Deliveries received by `listen`. A delivery of a transaction already in
+-- `webhook_deliveries` is a replay and is acknowledged without being stored
+-- again. Payloads that can't be parsed are kept in `webhook_dead_letters` to
+-- be looked at by hand.
+
+CREATE TABLE webhook_deliveries (
+    transaction_id TEXT PRIMARY KEY NOT NULL,
+    event_type TEXT NOT NULL,
+    received DATETIME NOT NULL
+);
+
+CREATE TABLE webhook_dead_letters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    received DATETIME NOT NULL,
+    body TEXT NOT NULL,
+    error TEXT NOT NULL
+);
@@ -0,0 +1,10 @@
+-- How conflicts between rows of an imported CSV export and synced
+-- transactions were resolved, keyed by the id of the row, so that importing
+-- the same export again resolves them the same way without asking.
+
+CREATE TABLE import_resolutions (
+    csv_id TEXT PRIMARY KEY NOT NULL,
+    transaction_id TEXT NOT NULL,
+    resolution TEXT NOT NULL,
+    resolved DATETIME NOT NULL
+);
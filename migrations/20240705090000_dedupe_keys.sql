@@ -0,0 +1,18 @@
+-- A key hashed from each transaction's account, currency, amount and payee,
+-- and where the transaction came from, so that the same payment arriving from
+-- the API and from a CSV export or fixture under a different id can be
+-- matched. Keys of existing transactions are filled in when the database is
+-- opened.
+
+ALTER TABLE transactions ADD COLUMN dedupe_key TEXT;
+ALTER TABLE transactions ADD COLUMN source TEXT NOT NULL DEFAULT 'api';
+
+CREATE INDEX transactions_dedupe_key ON transactions(dedupe_key);
+
+-- Ids of transactions merged into another, e.g. a CSV row merged into the
+-- transaction the API later delivered
+CREATE TABLE transaction_aliases (
+    alias_id TEXT PRIMARY KEY NOT NULL,
+    transaction_id TEXT NOT NULL,
+    source TEXT NOT NULL
+);
@@ -0,0 +1,10 @@
+-- Budget thresholds already posted to the Monzo feed. A category's 80% and
+-- 100% alerts are each posted once a month, however many syncs see them.
+
+CREATE TABLE budget_alerts (
+    category TEXT NOT NULL,
+    month TEXT NOT NULL,
+    threshold INTEGER NOT NULL,
+    posted DATETIME NOT NULL,
+    PRIMARY KEY (category, month, threshold)
+);
@@ -0,0 +1,5 @@
+-- The amount a pending transaction was first stored with, kept when a later
+-- update changes it, e.g. a hotel hold or fuel pre-authorisation that settles
+-- for a different amount. NULL if the amount never changed.
+
+ALTER TABLE transactions ADD COLUMN pending_amount INTEGER;
@@ -0,0 +1,46 @@
+-- Results of aggregate queries, reused while no transactions have been added.
+-- A new transaction raises the highest rowid, so older entries no longer
+-- match; other changes to the rows the aggregates read clear the cache
+-- through the triggers below.
+
+CREATE TABLE query_cache (
+    fingerprint TEXT PRIMARY KEY NOT NULL,
+    max_rowid INTEGER NOT NULL,
+    result TEXT NOT NULL,
+    created DATETIME NOT NULL
+);
+
+CREATE TRIGGER query_cache_transactions_update AFTER UPDATE ON transactions
+BEGIN
+    DELETE FROM query_cache;
+END;
+
+CREATE TRIGGER query_cache_transactions_delete AFTER DELETE ON transactions
+BEGIN
+    DELETE FROM query_cache;
+END;
+
+CREATE TRIGGER query_cache_accounts_insert AFTER INSERT ON accounts
+BEGIN
+    DELETE FROM query_cache;
+END;
+
+CREATE TRIGGER query_cache_accounts_update AFTER UPDATE OF owner_type, currency ON accounts
+BEGIN
+    DELETE FROM query_cache;
+END;
+
+CREATE TRIGGER query_cache_pots_insert AFTER INSERT ON pots
+BEGIN
+    DELETE FROM query_cache;
+END;
+
+CREATE TRIGGER query_cache_pots_delete AFTER DELETE ON pots
+BEGIN
+    DELETE FROM query_cache;
+END;
+
+CREATE TRIGGER query_cache_categories_update AFTER UPDATE OF name, display_name, emoji ON categories
+BEGIN
+    DELETE FROM query_cache;
+END;
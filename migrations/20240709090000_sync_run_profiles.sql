@@ -0,0 +1,7 @@
+-- Time spent in each phase of update runs made with --profile-sync, in milliseconds
+
+ALTER TABLE sync_runs ADD COLUMN fetch_ms INTEGER;
+ALTER TABLE sync_runs ADD COLUMN dedupe_ms INTEGER;
+ALTER TABLE sync_runs ADD COLUMN insert_ms INTEGER;
+ALTER TABLE sync_runs ADD COLUMN store_ms INTEGER;
+ALTER TABLE sync_runs ADD COLUMN classify_ms INTEGER;
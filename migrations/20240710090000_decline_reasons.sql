@@ -0,0 +1,4 @@
+-- Why Monzo declined a card payment, e.g. INSUFFICIENT_FUNDS. Declined
+-- payments never settle, so incremental updates don't wait for them.
+
+ALTER TABLE transactions ADD COLUMN decline_reason TEXT;
@@ -0,0 +1,9 @@
+-- Pot sweeps already deposited. A rule sweeps once a month, however many
+-- syncs `watch` runs between its day and the end of the month.
+
+CREATE TABLE sweeps (
+    rule TEXT NOT NULL,
+    month TEXT NOT NULL,
+    swept DATETIME NOT NULL,
+    PRIMARY KEY (rule, month)
+);
@@ -0,0 +1,331 @@
+-- The schema of the SQLite migrations up to 20240711090000_sweeps, for
+-- Postgres. Add a migration here alongside each new SQLite migration.
+--
+-- Integers are BIGINT so they read as i64, and the rows of the balance
+-- tables keep a `seq` so they read back in the order saved, as SQLite's rowid
+-- does. The query cache is SQLite only.
+
+CREATE TABLE accounts (
+    id TEXT PRIMARY KEY NOT NULL,
+    closed BOOLEAN NOT NULL,
+    created TIMESTAMP NOT NULL,
+    description TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    country_code TEXT NOT NULL,
+    owner_type TEXT NOT NULL,
+    account_number TEXT NOT NULL,
+    sort_code TEXT NOT NULL
+);
+
+CREATE TABLE pots (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    account_name TEXT NOT NULL,
+    balance BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    deleted BOOLEAN NOT NULL,
+    pot_type TEXT NOT NULL
+);
+
+CREATE TABLE categories (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    display_name TEXT,
+    emoji TEXT
+);
+
+CREATE TABLE merchants (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    category_id TEXT NOT NULL,
+    address TEXT,
+    last_seen TIMESTAMP,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    logo TEXT,
+    logo_path TEXT,
+
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+
+CREATE TABLE counterparties (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    account_number TEXT,
+    sort_code TEXT
+);
+
+CREATE TABLE manual_accounts (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL CHECK (kind IN ('asset', 'liability')),
+    institution TEXT,
+    currency TEXT NOT NULL,
+    created TIMESTAMP NOT NULL
+);
+
+CREATE TABLE manual_valuations (
+    account_id BIGINT NOT NULL,
+    date DATE NOT NULL,
+    balance BIGINT NOT NULL,
+
+    PRIMARY KEY (account_id, date),
+    FOREIGN KEY(account_id) REFERENCES manual_accounts(id)
+);
+
+CREATE TABLE transactions (
+    id TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL,
+    merchant_id TEXT,
+    amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    local_amount BIGINT NOT NULL,
+    local_currency TEXT NOT NULL,
+    created TIMESTAMP NOT NULL,
+    description TEXT NOT NULL,
+    notes TEXT,
+    settled TIMESTAMP,
+    updated TIMESTAMP,
+    category_id TEXT NOT NULL,
+    counterparty_account_number TEXT,
+    counterparty_sort_code TEXT,
+    is_transfer BOOLEAN NOT NULL DEFAULT FALSE,
+    counterparty_id TEXT REFERENCES counterparties(id),
+    repayment_account_id BIGINT REFERENCES manual_accounts(id),
+    dedupe_key TEXT,
+    source TEXT NOT NULL DEFAULT 'api',
+    pending_amount BIGINT,
+    decline_reason TEXT,
+
+    FOREIGN KEY(account_id) REFERENCES accounts(id),
+    FOREIGN KEY(merchant_id) REFERENCES merchants(id),
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+
+CREATE INDEX transactions_account_id_created ON transactions (account_id, created);
+CREATE INDEX transactions_created_id ON transactions (created, id);
+CREATE INDEX transactions_category_id ON transactions (category_id);
+CREATE INDEX transactions_merchant_id ON transactions (merchant_id);
+CREATE INDEX idx_transactions_counterparty_id ON transactions (counterparty_id);
+CREATE INDEX transactions_dedupe_key ON transactions (dedupe_key);
+
+CREATE TABLE sync_runs (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    started TIMESTAMP NOT NULL,
+    finished TIMESTAMP,
+    range_start TIMESTAMP NOT NULL,
+    range_end TIMESTAMP NOT NULL,
+    accounts BIGINT NOT NULL DEFAULT 0,
+    inserted BIGINT NOT NULL DEFAULT 0,
+    updated BIGINT NOT NULL DEFAULT 0,
+    skipped BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    fetch_ms BIGINT,
+    dedupe_ms BIGINT,
+    insert_ms BIGINT,
+    store_ms BIGINT,
+    classify_ms BIGINT
+);
+
+CREATE TABLE sync_state (
+    run_id BIGINT NOT NULL,
+    account_id TEXT NOT NULL,
+    window_start TIMESTAMP NOT NULL,
+    window_end TIMESTAMP NOT NULL,
+    completed TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (run_id, account_id, window_start),
+    FOREIGN KEY(run_id) REFERENCES sync_runs(id)
+);
+
+CREATE TABLE card_events (
+    id TEXT PRIMARY KEY NOT NULL,
+    account_id TEXT NOT NULL,
+    merchant_id TEXT,
+    currency TEXT NOT NULL,
+    created TIMESTAMP NOT NULL,
+    description TEXT NOT NULL,
+    category_id TEXT NOT NULL,
+
+    FOREIGN KEY(account_id) REFERENCES accounts(id),
+    FOREIGN KEY(merchant_id) REFERENCES merchants(id),
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+
+CREATE INDEX card_events_created_id ON card_events (created, id);
+
+CREATE TABLE merchant_changes (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    merchant_id TEXT NOT NULL,
+    field TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    changed TIMESTAMP NOT NULL,
+
+    FOREIGN KEY(merchant_id) REFERENCES merchants(id)
+);
+
+CREATE INDEX merchant_changes_merchant_id ON merchant_changes (merchant_id);
+
+CREATE TABLE balance_snapshots (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    taken TIMESTAMP NOT NULL
+);
+
+CREATE TABLE account_balances (
+    snapshot_id BIGINT NOT NULL,
+    account_id TEXT NOT NULL,
+    balance BIGINT NOT NULL,
+    total_balance BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    spend_today BIGINT NOT NULL,
+    seq BIGINT GENERATED ALWAYS AS IDENTITY,
+
+    PRIMARY KEY (snapshot_id, account_id),
+    FOREIGN KEY(snapshot_id) REFERENCES balance_snapshots(id),
+    FOREIGN KEY(account_id) REFERENCES accounts(id)
+);
+
+CREATE TABLE pot_balances (
+    snapshot_id BIGINT NOT NULL,
+    account_id TEXT NOT NULL,
+    pot_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    balance BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    seq BIGINT GENERATED ALWAYS AS IDENTITY,
+
+    PRIMARY KEY (snapshot_id, pot_id),
+    FOREIGN KEY(snapshot_id, account_id) REFERENCES account_balances(snapshot_id, account_id)
+);
+
+CREATE TABLE fx_rates (
+    date DATE NOT NULL,
+    currency TEXT NOT NULL,
+    base TEXT NOT NULL,
+    rate DOUBLE PRECISION NOT NULL,
+
+    PRIMARY KEY (date, currency, base)
+);
+
+CREATE TABLE category_overrides (
+    transaction_id TEXT PRIMARY KEY NOT NULL,
+    category_id TEXT NOT NULL,
+    created TIMESTAMP NOT NULL,
+
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id),
+    FOREIGN KEY(category_id) REFERENCES categories(id)
+);
+
+CREATE TABLE transaction_tags (
+    transaction_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+
+    PRIMARY KEY(transaction_id, tag),
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+);
+
+CREATE INDEX transaction_tags_tag ON transaction_tags (tag);
+
+CREATE TABLE attachments (
+    id TEXT PRIMARY KEY NOT NULL,
+    transaction_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    file_type TEXT NOT NULL,
+    created TIMESTAMP NOT NULL,
+    path TEXT,
+    sha256 TEXT,
+
+    FOREIGN KEY(transaction_id) REFERENCES transactions(id)
+);
+
+CREATE INDEX attachments_transaction ON attachments (transaction_id);
+CREATE INDEX attachments_sha256 ON attachments (sha256);
+
+CREATE TABLE webhook_deliveries (
+    transaction_id TEXT PRIMARY KEY NOT NULL,
+    event_type TEXT NOT NULL,
+    received TIMESTAMP NOT NULL
+);
+
+CREATE TABLE webhook_dead_letters (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    received TIMESTAMP NOT NULL,
+    body TEXT NOT NULL,
+    error TEXT NOT NULL
+);
+
+CREATE TABLE import_resolutions (
+    csv_id TEXT PRIMARY KEY NOT NULL,
+    transaction_id TEXT NOT NULL,
+    resolution TEXT NOT NULL,
+    resolved TIMESTAMP NOT NULL
+);
+
+CREATE TABLE transaction_aliases (
+    alias_id TEXT PRIMARY KEY NOT NULL,
+    transaction_id TEXT NOT NULL,
+    source TEXT NOT NULL
+);
+
+CREATE TABLE budget_alerts (
+    category TEXT NOT NULL,
+    month TEXT NOT NULL,
+    threshold BIGINT NOT NULL,
+    posted TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (category, month, threshold)
+);
+
+CREATE TABLE sweeps (
+    rule TEXT NOT NULL,
+    month TEXT NOT NULL,
+    swept TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (rule, month)
+);
+
+CREATE VIEW daily_balances AS
+SELECT
+    day,
+    account_id,
+    currency,
+    net,
+    SUM(net) OVER (PARTITION BY account_id, currency ORDER BY day)::BIGINT AS balance
+FROM (
+    SELECT
+        created::DATE AS day,
+        account_id,
+        currency,
+        SUM(amount)::BIGINT AS net
+    FROM transactions
+    GROUP BY 1, account_id, currency
+) daily;
+
+CREATE VIEW monthly_category_totals AS
+SELECT
+    to_char(t.created, 'YYYY-MM') AS month,
+    t.category_id,
+    MIN(c.name) AS category_name,
+    t.currency,
+    SUM(t.amount)::BIGINT AS total,
+    COUNT(*) AS count
+FROM transactions t
+JOIN categories c ON t.category_id = c.id
+WHERE NOT t.is_transfer
+GROUP BY 1, t.category_id, t.currency;
+
+CREATE VIEW merchant_totals AS
+SELECT
+    m.id AS merchant_id,
+    m.name AS merchant_name,
+    m.category_id,
+    t.currency,
+    SUM(t.amount)::BIGINT AS total,
+    COUNT(*) AS count,
+    MIN(t.created) AS first_transaction,
+    MAX(t.created) AS last_transaction
+FROM transactions t
+JOIN merchants m ON t.merchant_id = m.id
+WHERE NOT t.is_transfer
+GROUP BY m.id, t.currency;
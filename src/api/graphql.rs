@@ -0,0 +1,287 @@
+//! GraphQL schema
+//!
+//! Dashboards can fetch exactly the shape they need in one request, e.g.
+//!
+//! ```graphql
+//! {
+//!   accounts { id name currency }
+//!   transactions(since: "2024-05-01", filter: "category = groceries", limit: 20) {
+//!     created amount merchant
+//!   }
+//!   categoryTotals(since: "2024-05-01") { categoryLabel currency total }
+//! }
+//! ```
+//!
+//! `filter` takes the same expressions as `transactions set --where`.
+//! Amounts are in minor units and dates are local dates. Transactions are read
+//! a page at a time; pass the id of the last one as `after` for the next.
+
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use axum::{extract::State, Json};
+use chrono::NaiveDate;
+
+use super::{read_accounts, read_pots, Account, ApiState, Pot, Transaction, MAX_PAGE_SIZE};
+use crate::model::filter::Filter;
+
+/// The schema served on `/graphql`
+pub type ApiSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// The deepest nesting of fields a query may have
+const MAX_DEPTH: usize = 4;
+/// The most fields a query may select, counting each field once
+const MAX_COMPLEXITY: usize = 100;
+
+/// Spending and income in a category, leaving out transfers
+#[derive(Debug, Clone, SimpleObject)]
+pub struct CategoryTotal {
+    pub category: String,
+    /// The category's emoji and display name
+    pub category_label: String,
+    pub currency: String,
+    /// Net amount, negative for spending
+    pub total: i64,
+    /// Number of transactions
+    pub count: i64,
+}
+
+/// The queries the schema answers
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Stored accounts
+    async fn accounts(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Account>> {
+        Ok(read_accounts(ctx.data::<ApiState>()?).await?)
+    }
+
+    /// Stored pots
+    async fn pots(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Pot>> {
+        Ok(read_pots(&ctx.data::<ApiState>()?.pool).await?)
+    }
+
+    /// Transactions between `since` and `until`, both included, that match
+    /// `filter`, oldest first, up to 1000, starting after the transaction
+    /// with id `after`
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+        filter: Option<String>,
+        after: Option<String>,
+        #[graphql(default = 100)] limit: i64,
+    ) -> async_graphql::Result<Vec<Transaction>> {
+        let state = ctx.data::<ApiState>()?;
+        let filter = filter.as_deref().map(str::parse::<Filter>).transpose()?;
+        let (from, until) = state.range(since, until);
+        let limit = limit.clamp(0, MAX_PAGE_SIZE);
+        // a filter can leave out most of a page, so read whole pages for it
+        let page_size = if filter.is_some() {
+            MAX_PAGE_SIZE
+        } else {
+            limit
+        };
+        let service = state.pool.transactions();
+
+        let mut found = Vec::new();
+        let mut after = after;
+        while i64::try_from(found.len()).unwrap_or(i64::MAX) < limit {
+            let page = service
+                .read_export_page(from, until, after.as_deref(), page_size)
+                .await?;
+            let last_page = i64::try_from(page.len()).unwrap_or_default() < page_size;
+            after = page.last().map(|tx| tx.id.clone());
+            found.extend(
+                page.into_iter()
+                    .filter(|tx| {
+                        filter
+                            .as_ref()
+                            .is_none_or(|f| f.matches(tx, state.timezone))
+                    })
+                    .map(Transaction::from),
+            );
+            if last_page {
+                break;
+            }
+        }
+        found.truncate(usize::try_from(limit).unwrap_or_default());
+
+        Ok(found)
+    }
+
+    /// Totals by category and currency between `since` and `until`, both
+    /// included, biggest spend first
+    async fn category_totals(
+        &self,
+        ctx: &Context<'_>,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> async_graphql::Result<Vec<CategoryTotal>> {
+        let state = ctx.data::<ApiState>()?;
+        let (from, until) = state.range(since, until);
+        let totals = state
+            .pool
+            .transactions()
+            .read_category_totals(from, until)
+            .await?;
+
+        Ok(totals
+            .into_iter()
+            .map(|total| CategoryTotal {
+                category: total.category_name,
+                category_label: total.category_label,
+                currency: total.currency,
+                total: total.total,
+                count: total.count,
+            })
+            .collect())
+    }
+}
+
+/// Build the schema over the data in `state`
+#[must_use]
+pub fn schema(state: ApiState) -> ApiSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(state)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Answer a GraphQL request
+pub async fn handler(
+    State(schema): State<ApiSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write as _;
+
+    use chrono_tz::Tz;
+
+    use super::*;
+    use crate::{
+        model::{transaction::TransactionResponse, DatabasePool},
+        tests::test::test_db,
+    };
+
+    async fn spend(pool: &DatabasePool, id: &str, day: &str, category: &str, amount: i64) {
+        let tx = TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            amount,
+            currency: "GBP".to_string(),
+            category_id: category.to_string(),
+            created: NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+                .and_utc(),
+            ..Default::default()
+        };
+        pool.categories().ensure_category(category).await.unwrap();
+        pool.transactions().save_transaction(&tx).await.unwrap();
+    }
+
+    fn api_schema(pool: DatabasePool) -> ApiSchema {
+        schema(ApiState {
+            pool,
+            timezone: Tz::UTC,
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            nicknames: [("1".to_string(), "Main".to_string())].into(),
+            requests_per_minute: 60,
+        })
+    }
+
+    #[tokio::test]
+    async fn answers_queries_across_the_store() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        spend(&pool, "a", "2024-04-30", "groceries", -900).await;
+        spend(&pool, "b", "2024-05-01", "groceries", -1200).await;
+        spend(&pool, "c", "2024-05-02", "eating_out", -800).await;
+        spend(&pool, "d", "2024-05-03", "groceries", -300).await;
+        let schema = api_schema(pool);
+
+        // Act
+        let response = schema
+            .execute(
+                r#"{
+                    accounts { id name }
+                    transactions(since: "2024-05-01", filter: "category = groceries", limit: 1) {
+                        id amount categoryLabel
+                    }
+                    categoryTotals(since: "2024-05-01", until: "2024-05-02") {
+                        category total count
+                    }
+                }"#,
+            )
+            .await;
+
+        // Assert
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["accounts"][0]["name"], "Main");
+        assert_eq!(
+            data["transactions"],
+            serde_json::json!([{ "id": "b", "amount": -1200, "categoryLabel": "🛒 Groceries" }])
+        );
+        assert_eq!(
+            data["categoryTotals"],
+            serde_json::json!([
+                { "category": "groceries", "total": -1200, "count": 1 },
+                { "category": "eating_out", "total": -800, "count": 1 },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn filtered_transactions_continue_after_the_cursor() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        spend(&pool, "a", "2024-05-01", "groceries", -900).await;
+        spend(&pool, "b", "2024-05-02", "eating_out", -800).await;
+        spend(&pool, "c", "2024-05-03", "groceries", -300).await;
+        spend(&pool, "d", "2024-05-04", "groceries", -100).await;
+        let schema = api_schema(pool);
+
+        // Act
+        let response = schema
+            .execute(
+                r#"{
+                    transactions(filter: "category = groceries", after: "a", limit: 1) { id }
+                }"#,
+            )
+            .await;
+
+        // Assert
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["transactions"], serde_json::json!([{ "id": "c" }]));
+    }
+
+    #[tokio::test]
+    async fn rejects_queries_over_the_complexity_limit() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let schema = api_schema(pool);
+        let mut fields = String::new();
+        for i in 0..MAX_COMPLEXITY {
+            let _ = write!(fields, "a{i}: accounts {{ id }} ");
+        }
+
+        // Act
+        let response = schema.execute(format!("{{ {fields} }}")).await;
+
+        // Assert
+        assert!(!response.errors.is_empty());
+    }
+}
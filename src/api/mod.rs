@@ -0,0 +1,355 @@
+//! Local API
+//!
+//! [`router`] serves the stored data read-only, for dashboards and scripts:
+//! `GET /accounts`, `GET /pots` and `GET /transactions` return JSON, and
+//! `POST /graphql` answers [GraphQL](graphql) queries over the same data,
+//! plus category totals, in one request.
+//!
+//! Transactions come a page at a time, oldest first, so a request never reads
+//! the whole table, and each client can only make `requests_per_minute`
+//! requests for them or to `/graphql`, so a dashboard stuck in a loop can't
+//! keep the database busy.
+
+pub mod graphql;
+
+use std::collections::BTreeMap;
+
+use async_graphql::SimpleObject;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        account::{display_name, AccountForDB},
+        transaction::ExportTransaction,
+        DatabasePool,
+    },
+    server::{rate_limit, RateLimit},
+    timezone::start_of_day,
+};
+
+/// Transactions in a page unless a request asks for fewer
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+/// The most transactions in a page
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// What the API needs to answer requests
+#[derive(Debug, Clone)]
+pub struct ApiState {
+    pub pool: DatabasePool,
+    /// Timezone dates in requests are in
+    pub timezone: Tz,
+    /// Where date ranges start when no first day is given
+    pub start_date: NaiveDateTime,
+    /// Names shown for accounts instead of their type, by account id
+    pub nicknames: BTreeMap<String, String>,
+    /// Requests to `/transactions` and `/graphql` each client can make a minute
+    pub requests_per_minute: u32,
+}
+
+impl ApiState {
+    /// The times from the start of `since` to the end of `until`, both local
+    /// dates, defaulting to the start date and now
+    fn range(
+        &self,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> (NaiveDateTime, NaiveDateTime) {
+        let from = since.map_or(self.start_date, |date| start_of_day(date, self.timezone));
+        let until = until.and_then(|date| date.succ_opt()).map_or_else(
+            || Utc::now().naive_utc(),
+            |date| start_of_day(date, self.timezone),
+        );
+        (from, until)
+    }
+}
+
+/// A stored account
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct Account {
+    pub id: String,
+    /// The account's nickname, or else its type
+    pub name: String,
+    pub owner_type: String,
+    pub description: String,
+    pub currency: String,
+    pub closed: bool,
+    pub created: NaiveDateTime,
+}
+
+impl Account {
+    fn new(account: AccountForDB, nicknames: &BTreeMap<String, String>) -> Self {
+        Self {
+            name: display_name(nicknames, &account.id, &account.owner_type).to_string(),
+            id: account.id,
+            owner_type: account.owner_type,
+            description: account.description,
+            currency: account.currency,
+            closed: account.closed,
+            created: account.created,
+        }
+    }
+}
+
+/// A stored pot, with its balance at the last update
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct Pot {
+    pub id: String,
+    pub name: String,
+    pub pot_type: String,
+    pub balance: i64,
+    pub currency: String,
+    pub deleted: bool,
+}
+
+/// A stored transaction, with amounts in minor units
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct Transaction {
+    pub id: String,
+    pub account_id: String,
+    pub created: NaiveDateTime,
+    pub settled: Option<NaiveDateTime>,
+    pub amount: i64,
+    pub currency: String,
+    pub local_amount: i64,
+    pub local_currency: String,
+    pub description: String,
+    pub notes: Option<String>,
+    pub category: String,
+    /// The category's emoji and display name
+    pub category_label: String,
+    pub merchant: Option<String>,
+    pub pot: Option<String>,
+    pub counterparty: Option<String>,
+    pub is_transfer: bool,
+    pub tags: Vec<String>,
+}
+
+impl From<ExportTransaction> for Transaction {
+    fn from(tx: ExportTransaction) -> Self {
+        Self {
+            id: tx.id,
+            account_id: tx.account_id,
+            created: tx.created,
+            settled: tx.settled,
+            amount: tx.amount,
+            currency: tx.currency,
+            local_amount: tx.local_amount,
+            local_currency: tx.local_currency,
+            description: tx.description,
+            notes: tx.notes,
+            category: tx.category_name,
+            category_label: tx.category_label,
+            merchant: tx.merchant_name,
+            pot: tx.pot_name,
+            counterparty: tx.counterparty_name,
+            is_transfer: tx.is_transfer,
+            tags: tx
+                .tags
+                .map(|tags| tags.split(' ').map(str::to_string).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Query parameters for `/transactions`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TransactionsQuery {
+    /// First day, a local date
+    pub since: Option<NaiveDate>,
+    /// Last day, a local date
+    pub until: Option<NaiveDate>,
+    /// The `next` cursor of the previous page
+    pub after: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// A page of transactions
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionsPage {
+    pub transactions: Vec<Transaction>,
+    /// Pass as `after` for the next page; absent on the last page
+    pub next: Option<String>,
+}
+
+/// The API's routes, which need the client's address to rate limit
+/// `/transactions` and `/graphql`
+pub fn router(state: ApiState) -> Router {
+    let schema = graphql::schema(state.clone());
+    // one allowance per client, shared by both routes
+    let limit = RateLimit::new(state.requests_per_minute);
+
+    Router::new()
+        .route("/accounts", get(accounts))
+        .route("/pots", get(pots))
+        .route(
+            "/transactions",
+            get(transactions).layer(middleware::from_fn_with_state(limit.clone(), rate_limit)),
+        )
+        .with_state(state)
+        .route(
+            "/graphql",
+            post(graphql::handler)
+                .with_state(schema)
+                .layer(middleware::from_fn_with_state(limit, rate_limit)),
+        )
+}
+
+async fn accounts(State(state): State<ApiState>) -> Result<Json<Vec<Account>>, Error> {
+    Ok(Json(read_accounts(&state).await?))
+}
+
+async fn pots(State(state): State<ApiState>) -> Result<Json<Vec<Pot>>, Error> {
+    Ok(Json(read_pots(&state.pool).await?))
+}
+
+async fn transactions(
+    State(state): State<ApiState>,
+    Query(query): Query<TransactionsQuery>,
+) -> Result<Json<TransactionsPage>, Error> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let (from, until) = state.range(query.since, query.until);
+    let transactions = state
+        .pool
+        .transactions()
+        .read_export_page(from, until, query.after.as_deref(), limit)
+        .await?;
+
+    let next = transactions
+        .last()
+        .filter(|_| i64::try_from(transactions.len()).is_ok_and(|n| n == limit))
+        .map(|tx| tx.id.clone());
+    Ok(Json(TransactionsPage {
+        transactions: transactions.into_iter().map(Transaction::from).collect(),
+        next,
+    }))
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_json())).into_response()
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+async fn read_accounts(state: &ApiState) -> Result<Vec<Account>, Error> {
+    let accounts = state.pool.accounts().read_accounts().await?;
+
+    Ok(accounts
+        .into_iter()
+        .map(|account| Account::new(account, &state.nicknames))
+        .collect())
+}
+
+async fn read_pots(pool: &DatabasePool) -> Result<Vec<Pot>, Error> {
+    let pots = pool.pots().read_pots().await?;
+
+    Ok(pots
+        .into_iter()
+        .map(|pot| Pot {
+            id: pot.id,
+            name: pot.name,
+            pot_type: pot.pot_type,
+            balance: pot.balance,
+            currency: pot.currency,
+            deleted: pot.deleted,
+        })
+        .collect())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::transaction::TransactionResponse, tests::test::test_db};
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    async fn spend(pool: &DatabasePool, id: &str, day: &str) {
+        let tx = TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            amount: -500,
+            currency: "GBP".to_string(),
+            category_id: "groceries".to_string(),
+            created: date(day).and_hms_opt(12, 0, 0).unwrap().and_utc(),
+            ..Default::default()
+        };
+        pool.categories()
+            .ensure_category(&tx.category_id)
+            .await
+            .unwrap();
+        pool.transactions().save_transaction(&tx).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn pages_through_transactions_in_range() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        for (id, day) in [
+            ("a", "2024-04-30"),
+            ("b", "2024-05-01"),
+            ("c", "2024-05-02"),
+            ("d", "2024-05-03"),
+            ("e", "2024-05-04"),
+        ] {
+            spend(&pool, id, day).await;
+        }
+        let state = ApiState {
+            pool,
+            timezone: Tz::UTC,
+            start_date: date("2024-01-01").and_hms_opt(0, 0, 0).unwrap(),
+            nicknames: BTreeMap::new(),
+            requests_per_minute: 60,
+        };
+        let query = TransactionsQuery {
+            since: Some(date("2024-05-01")),
+            until: Some(date("2024-05-03")),
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        // Act
+        let Json(first) = transactions(State(state.clone()), Query(query.clone()))
+            .await
+            .unwrap();
+        let Json(second) = transactions(
+            State(state),
+            Query(TransactionsQuery {
+                after: first.next.clone(),
+                ..query
+            }),
+        )
+        .await
+        .unwrap();
+
+        // Assert
+        let ids = |page: &TransactionsPage| {
+            page.transactions
+                .iter()
+                .map(|tx| tx.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&first), vec!["b", "c"]);
+        assert_eq!(first.next.as_deref(), Some("c"));
+        assert_eq!(ids(&second), vec!["d"]);
+        assert_eq!(second.next, None);
+    }
+}
@@ -0,0 +1,396 @@
+//! Database backups
+//!
+//! [`backup`] writes a consistent copy of the database, taken with `VACUUM
+//! INTO` so it can run alongside `update`, to a [`Target`] as
+//! `monzo-<UTC time>.sqlite`, then deletes all but the newest `keep` backups
+//! there. [`fetch`] reads one back for `restore`. Backups can be
+//! [encrypted](crate::encryption), adding `.age` or `.gpg` to their name.
+//!
+//! A target is a local directory, an S3-compatible bucket
+//! (`s3://bucket/prefix`) or a `WebDAV` directory (`webdav://host/path` over
+//! HTTP, `webdavs://host/path` over HTTPS).
+
+pub mod s3;
+pub mod webdav;
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDateTime;
+use temp_dir::TempDir;
+use url::Url;
+
+use crate::{
+    configuration::Backup,
+    encryption::{decrypt, Encryption},
+    error::AppErrors as Error,
+    model::DatabasePool,
+};
+
+const PREFIX: &str = "monzo-";
+const EXTENSION: &str = ".sqlite";
+
+/// Where backups are kept
+#[derive(Debug, Clone)]
+pub enum Target {
+    Local(PathBuf),
+    S3(s3::Bucket),
+    WebDav(webdav::Share),
+}
+
+impl Target {
+    /// The target at `location`, with credentials from `settings` or the
+    /// environment
+    ///
+    /// # Errors
+    /// Will return an error if `location` isn't a valid URL or S3 credentials
+    /// are missing.
+    pub fn parse(location: &str, settings: &Backup) -> Result<Self, Error> {
+        let scheme = location.split_once("://").map(|(scheme, _)| scheme);
+        match scheme {
+            Some("s3") => {
+                let url = parse_url(location)?;
+                let bucket = url
+                    .host_str()
+                    .ok_or_else(|| Error::Error(format!("No bucket in {location}")))?;
+                Ok(Self::S3(s3::Bucket::new(bucket, url.path(), &settings.s3)?))
+            }
+            Some("webdav" | "webdavs") => {
+                let url = location.replacen("webdavs://", "https://", 1).replacen(
+                    "webdav://",
+                    "http://",
+                    1,
+                );
+                Ok(Self::WebDav(webdav::Share::new(
+                    parse_url(&url)?,
+                    &settings.webdav,
+                )))
+            }
+            Some("file") => Ok(Self::Local(PathBuf::from(&location["file://".len()..]))),
+            Some(scheme) => Err(Error::Error(format!(
+                "Unsupported backup target {scheme}://: use a directory, s3:// or webdav(s)://"
+            ))),
+            None => Ok(Self::Local(PathBuf::from(location))),
+        }
+    }
+
+    /// Names of the backups in the target, oldest first
+    ///
+    /// # Errors
+    /// Will return an error if the target can't be listed.
+    pub async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut names = match self {
+            Self::Local(dir) => {
+                if !dir.exists() {
+                    return Ok(Vec::new());
+                }
+                let mut names = Vec::new();
+                for entry in std::fs::read_dir(dir)? {
+                    names.push(entry?.file_name().to_string_lossy().into_owned());
+                }
+                names
+            }
+            Self::S3(bucket) => bucket.list().await?,
+            Self::WebDav(share) => share.list().await?,
+        };
+        names.retain(|name| is_backup_name(name));
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Write backup `name`
+    ///
+    /// # Errors
+    /// Will return an error if it can't be written.
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), Error> {
+        match self {
+            Self::Local(dir) => {
+                std::fs::create_dir_all(dir)?;
+                std::fs::write(dir.join(name), data)?;
+                Ok(())
+            }
+            Self::S3(bucket) => bucket.put(name, data).await,
+            Self::WebDav(share) => share.put(name, data).await,
+        }
+    }
+
+    /// Read backup `name`
+    ///
+    /// # Errors
+    /// Will return an error if it can't be read.
+    pub async fn get(&self, name: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Local(dir) => Ok(std::fs::read(dir.join(name))?),
+            Self::S3(bucket) => bucket.get(name).await,
+            Self::WebDav(share) => share.get(name).await,
+        }
+    }
+
+    /// Delete backup `name`
+    ///
+    /// # Errors
+    /// Will return an error if it can't be deleted.
+    pub async fn delete(&self, name: &str) -> Result<(), Error> {
+        match self {
+            Self::Local(dir) => Ok(std::fs::remove_file(dir.join(name))?),
+            Self::S3(bucket) => bucket.delete(name).await,
+            Self::WebDav(share) => share.delete(name).await,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(dir) => write!(f, "{}", dir.display()),
+            Self::S3(bucket) => write!(f, "{bucket}"),
+            Self::WebDav(share) => write!(f, "{share}"),
+        }
+    }
+}
+
+/// What a backup did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupOutcome {
+    /// The backup written
+    pub name: String,
+    pub bytes: usize,
+    /// Older backups deleted
+    pub removed: Vec<String>,
+}
+
+/// Write a copy of the database taken at `now` to `target`, encrypted if
+/// `encryption` is given, keeping the newest `keep` backups there
+///
+/// # Errors
+/// Will return an error if the copy can't be taken or encrypted, or the target
+/// can't be written.
+pub async fn backup(
+    pool: &DatabasePool,
+    target: &Target,
+    keep: usize,
+    now: NaiveDateTime,
+    encryption: Option<&Encryption>,
+) -> Result<BackupOutcome, Error> {
+    let mut name = format!("{PREFIX}{}{EXTENSION}", now.format("%Y%m%dT%H%M%SZ"));
+    let dir = TempDir::new()?;
+    let snapshot = dir.child(&name);
+    let Some(path) = snapshot.to_str() else {
+        return Err(Error::Error(
+            "The temporary path must be valid UTF-8".into(),
+        ));
+    };
+
+    sqlx::query("VACUUM INTO $1")
+        .bind(path)
+        .execute(pool.sqlite("backup")?)
+        .await?;
+    let mut data = std::fs::read(&snapshot)?;
+    if let Some(encryption) = encryption {
+        data = encryption.encrypt(&data)?;
+        name = format!("{name}.{}", encryption.extension());
+    }
+    let bytes = data.len();
+    target.put(&name, data).await?;
+
+    let names = target.list().await?;
+    let mut removed = Vec::new();
+    for old in names.iter().take(names.len().saturating_sub(keep.max(1))) {
+        target.delete(old).await?;
+        removed.push(old.clone());
+    }
+
+    Ok(BackupOutcome {
+        name,
+        bytes,
+        removed,
+    })
+}
+
+/// Read backup `name` from `target`, or its newest backup, decrypting it with
+/// the age identities in `identity_file` or the gpg keyring if it's
+/// encrypted
+///
+/// # Errors
+/// Will return an error if there are no backups, the backup can't be read or
+/// decrypted or it isn't a `SQLite` database.
+pub async fn fetch(
+    target: &Target,
+    name: Option<&str>,
+    identity_file: Option<&Path>,
+) -> Result<(String, Vec<u8>), Error> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => target
+            .list()
+            .await?
+            .pop()
+            .ok_or_else(|| Error::Error(format!("No backups in {target}")))?,
+    };
+
+    let data = decrypt(&name, target.get(&name).await?, identity_file)?;
+    if !data.starts_with(b"SQLite format 3\0") {
+        return Err(Error::Error(format!("{name} isn't a SQLite database")));
+    }
+
+    Ok((name, data))
+}
+
+/// Split a location naming a single backup into its target and name
+#[must_use]
+pub fn split_location(location: &str) -> (&str, Option<&str>) {
+    match location.trim_end_matches('/').rsplit_once('/') {
+        Some((target, name)) if is_backup_name(name) => (target, Some(name)),
+        _ if is_backup_name(location) => (".", Some(location)),
+        _ => (location, None),
+    }
+}
+
+/// Replace the database at `database_path` with `data` at `now`, keeping the
+/// current one next to it named for the time, so an earlier restore's copy is
+/// never overwritten. Returns where the current database was moved.
+///
+/// The database must not be open.
+///
+/// # Errors
+/// Will return an error if a copy for the same time already exists, or the
+/// files can't be moved or written.
+pub fn replace_database(
+    database_path: &Path,
+    data: &[u8],
+    now: NaiveDateTime,
+) -> Result<PathBuf, Error> {
+    let previous =
+        database_path.with_extension(format!("before-restore-{}", now.format("%Y%m%dT%H%M%SZ")));
+    if previous.exists() {
+        return Err(Error::Error(format!(
+            "{} already exists; try again in a second",
+            previous.display()
+        )));
+    }
+    let restored = database_path.with_extension("restoring");
+    std::fs::write(&restored, data)?;
+
+    if database_path.exists() {
+        for suffix in ["", "-wal", "-shm"] {
+            let file = PathBuf::from(format!("{}{suffix}", database_path.display()));
+            if file.exists() {
+                std::fs::rename(&file, format!("{}{suffix}", previous.display()))?;
+            }
+        }
+    }
+    std::fs::rename(&restored, database_path)?;
+
+    Ok(previous)
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn is_backup_name(name: &str) -> bool {
+    let name = name
+        .strip_suffix(".age")
+        .or_else(|| name.strip_suffix(".gpg"))
+        .unwrap_or(name);
+    name.strip_prefix(PREFIX)
+        .and_then(|name| name.strip_suffix(EXTENSION))
+        .is_some_and(|time| NaiveDateTime::parse_from_str(time, "%Y%m%dT%H%M%SZ").is_ok())
+}
+
+fn parse_url(location: &str) -> Result<Url, Error> {
+    Url::parse(location).map_err(|e| Error::Error(format!("Invalid backup target {location}: {e}")))
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::tests::test::test_db;
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 7, 3)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn splits_locations_naming_a_backup() {
+        assert_eq!(
+            split_location("s3://bucket/monzo/monzo-20240703T090000Z.sqlite"),
+            ("s3://bucket/monzo", Some("monzo-20240703T090000Z.sqlite"))
+        );
+        assert_eq!(
+            split_location("s3://bucket/monzo/"),
+            ("s3://bucket/monzo/", None)
+        );
+        assert_eq!(
+            split_location("monzo-20240703T090000Z.sqlite"),
+            (".", Some("monzo-20240703T090000Z.sqlite"))
+        );
+        assert!(is_backup_name("monzo-20240703T090000Z.sqlite.age"));
+        assert!(!is_backup_name("monzo-latest.sqlite"));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_newest_backups() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let dir = TempDir::new().unwrap();
+        let target = Target::parse(dir.path().to_str().unwrap(), &Backup::default()).unwrap();
+        std::fs::write(dir.child("notes.txt"), "not a backup").unwrap();
+
+        // Act
+        for hour in 1..=3 {
+            backup(&pool, &target, 2, at(hour), None).await.unwrap();
+        }
+        let outcome = backup(&pool, &target, 2, at(4), None).await.unwrap();
+        let (latest, data) = fetch(&target, None, None).await.unwrap();
+
+        // Assert
+        assert_eq!(outcome.name, "monzo-20240703T040000Z.sqlite");
+        assert_eq!(outcome.removed, vec!["monzo-20240703T020000Z.sqlite"]);
+        assert_eq!(
+            target.list().await.unwrap(),
+            vec![
+                "monzo-20240703T030000Z.sqlite",
+                "monzo-20240703T040000Z.sqlite"
+            ]
+        );
+        assert_eq!(latest, outcome.name);
+        assert_eq!(data.len(), outcome.bytes);
+        assert!(dir.child("notes.txt").exists());
+    }
+
+    #[test]
+    fn replaces_the_database_keeping_the_previous_one() {
+        // Arrange
+        let dir = TempDir::new().unwrap();
+        let database = dir.child("db.sqlite");
+        std::fs::write(&database, "old").unwrap();
+        let at = |minute| {
+            NaiveDate::from_ymd_opt(2024, 7, 3)
+                .unwrap()
+                .and_hms_opt(9, minute, 0)
+                .unwrap()
+        };
+
+        // Act
+        let first = replace_database(&database, b"new", at(0)).unwrap();
+        let second = replace_database(&database, b"newer", at(5)).unwrap();
+
+        // Assert
+        assert_eq!(std::fs::read(&database).unwrap(), b"newer");
+        assert_eq!(first, dir.child("db.before-restore-20240703T090000Z"));
+        assert_eq!(std::fs::read(first).unwrap(), b"old");
+        assert_eq!(second, dir.child("db.before-restore-20240703T090500Z"));
+        assert_eq!(std::fs::read(second).unwrap(), b"new");
+        assert!(replace_database(&database, b"newest", at(5)).is_err());
+        assert_eq!(std::fs::read(&database).unwrap(), b"newer");
+    }
+}
@@ -0,0 +1,155 @@
+//! `WebDAV` backup targets, such as Nextcloud, ownCloud or a NAS
+
+use std::fmt;
+
+use regex::Regex;
+use reqwest::{Method, RequestBuilder, Response};
+use url::Url;
+
+use crate::{configuration::WebDav, error::AppErrors as Error};
+
+/// A `WebDAV` directory
+#[derive(Debug, Clone)]
+pub struct Share {
+    /// Ends with `/`
+    url: Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Share {
+    /// The directory at `url`, with credentials from the environment or
+    /// `settings`
+    #[must_use]
+    pub fn new(mut url: Url, settings: &WebDav) -> Self {
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Self {
+            url,
+            username: std::env::var("MONZO_WEBDAV_USERNAME")
+                .ok()
+                .or_else(|| settings.username.clone()),
+            password: std::env::var("MONZO_WEBDAV_PASSWORD")
+                .ok()
+                .or_else(|| settings.password.clone()),
+        }
+    }
+
+    /// Names of the files in the directory
+    pub(super) async fn list(&self) -> Result<Vec<String>, Error> {
+        let propfind = Method::from_bytes(b"PROPFIND").expect("valid method");
+        let request = self
+            .request(propfind, self.url.clone())
+            .header("Depth", "1");
+        let response = request.send().await?;
+        // A directory that doesn't exist yet holds no backups
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let body = check(response)?.text().await?;
+
+        Ok(file_names(&body))
+    }
+
+    pub(super) async fn put(&self, name: &str, data: Vec<u8>) -> Result<(), Error> {
+        let mkcol = Method::from_bytes(b"MKCOL").expect("valid method");
+        // Fails harmlessly when the directory already exists
+        let _ = self.request(mkcol, self.url.clone()).send().await;
+
+        let response = self
+            .request(Method::PUT, self.file(name)?)
+            .body(data)
+            .send()
+            .await?;
+        check(response)?;
+        Ok(())
+    }
+
+    pub(super) async fn get(&self, name: &str) -> Result<Vec<u8>, Error> {
+        let response = self.request(Method::GET, self.file(name)?).send().await?;
+        Ok(check(response)?.bytes().await?.to_vec())
+    }
+
+    pub(super) async fn delete(&self, name: &str) -> Result<(), Error> {
+        let response = self
+            .request(Method::DELETE, self.file(name)?)
+            .send()
+            .await?;
+        check(response)?;
+        Ok(())
+    }
+
+    fn file(&self, name: &str) -> Result<Url, Error> {
+        self.url
+            .join(name)
+            .map_err(|e| Error::Error(format!("Invalid backup name {name}: {e}")))
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        let request = reqwest::Client::new().request(method, url);
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.url)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn check(response: Response) -> Result<Response, Error> {
+    if !response.status().is_success() {
+        return Err(Error::ReqwestError(format!(
+            "{}: {}",
+            response.url(),
+            response.status()
+        )));
+    }
+
+    Ok(response)
+}
+
+// The last path segment of each `href` in a PROPFIND response
+fn file_names(body: &str) -> Vec<String> {
+    let href =
+        Regex::new(r"<(?:[A-Za-z]+:)?href>([^<]+)</(?:[A-Za-z]+:)?href>").expect("valid regex");
+    href.captures_iter(body)
+        .filter_map(|captures| {
+            let path = captures[1].trim_end_matches('/');
+            path.rsplit('/').next().map(str::to_string)
+        })
+        .collect()
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_file_names_from_a_propfind_response() {
+        // Arrange
+        let body = r#"<?xml version="1.0"?>
+            <d:multistatus xmlns:d="DAV:">
+              <d:response><d:href>/remote.php/dav/files/me/monzo/</d:href></d:response>
+              <d:response><d:href>/remote.php/dav/files/me/monzo/monzo-20240703T090000Z.sqlite</d:href></d:response>
+              <d:response><d:href>/remote.php/dav/files/me/monzo/notes.txt</d:href></d:response>
+            </d:multistatus>"#;
+
+        // Act
+        let names = file_names(body);
+
+        // Assert
+        assert_eq!(
+            names,
+            vec!["monzo", "monzo-20240703T090000Z.sqlite", "notes.txt"]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! External categorisation
+//!
+//! Monzo leaves many transactions in `general`. A [`Categoriser`] chooses a
+//! category for them instead, e.g. a local LLM service behind a webhook
+//! configured under `[categoriser]`:
+//!
+//! ```toml
+//! [categoriser]
+//! url = "http://localhost:8080/categorise"
+//! uncategorised = ["general"]
+//! ```
+//!
+//! Each uncategorised transaction is posted as JSON with its `id`,
+//! `merchant`, `description`, `amount` (minor units) and `currency`. The
+//! service replies `{"category": "groceries"}`, or a null category to leave
+//! it alone. Chosen categories are stored as overrides, like those set with
+//! `transactions set`, so they're only asked for once.
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tracing_log::log::{info, warn};
+
+use crate::{
+    configuration::Categorisation,
+    error::AppErrors as Error,
+    model::{
+        edit::{TransactionEdit, Uncategorised},
+        DatabasePool,
+    },
+};
+
+/// Chooses categories for transactions
+#[async_trait]
+pub trait Categoriser: Send + Sync {
+    /// The category id or name for a transaction, or `None` to leave it
+    ///
+    /// # Errors
+    /// Will return an error if the categoriser can't be reached.
+    async fn categorise(&self, transaction: &Uncategorised) -> Result<Option<String>, Error>;
+}
+
+/// Posts the transaction as JSON and reads `{"category": ...}` from the reply
+pub struct WebhookCategoriser {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookCategoriser {
+    #[must_use]
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct WebhookReply {
+    category: Option<String>,
+}
+
+/// What categorising changed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CategoriseSummary {
+    /// Transactions given a new category
+    pub categorised: usize,
+    /// Transactions the categoriser left alone
+    pub unchanged: usize,
+    /// Transactions given a category that doesn't exist
+    pub unknown: usize,
+}
+
+/// A categoriser and the categories it replaces
+pub struct AutoCategoriser {
+    categoriser: Box<dyn Categoriser>,
+    uncategorised: Vec<String>,
+}
+
+impl AutoCategoriser {
+    #[must_use]
+    pub fn new(categoriser: Box<dyn Categoriser>, uncategorised: Vec<String>) -> Self {
+        Self {
+            categoriser,
+            uncategorised,
+        }
+    }
+
+    /// The categoriser configured in `[categoriser]`, if any
+    #[must_use]
+    pub fn from_settings(settings: &Categorisation) -> Option<Self> {
+        settings.url.as_ref().map(|url| {
+            Self::new(
+                Box::new(WebhookCategoriser::new(url.clone())),
+                settings.uncategorised.clone(),
+            )
+        })
+    }
+
+    /// Categorise the stored transactions that are uncategorised
+    ///
+    /// # Errors
+    /// Will return an error if the categoriser can't be reached or the
+    /// database can't be written.
+    pub async fn run(&self, pool: &DatabasePool) -> Result<CategoriseSummary, Error> {
+        let categories = pool.categories();
+        let mut summary = CategoriseSummary::default();
+
+        for transaction in pool.read_uncategorised(&self.uncategorised).await? {
+            let Some(choice) = self.categoriser.categorise(&transaction).await? else {
+                summary.unchanged += 1;
+                continue;
+            };
+            let Some(category) = categories.find_category(&choice).await? else {
+                warn!(
+                    "Categoriser chose unknown category '{choice}' for {}",
+                    transaction.id
+                );
+                summary.unknown += 1;
+                continue;
+            };
+            if self.uncategorised.contains(&category.id)
+                || self.uncategorised.contains(&category.name)
+            {
+                summary.unchanged += 1;
+                continue;
+            }
+            pool.edit_transactions(
+                std::slice::from_ref(&transaction.id),
+                &TransactionEdit {
+                    category: Some(category.id),
+                    tags: Vec::new(),
+                },
+            )
+            .await?;
+            summary.categorised += 1;
+        }
+        info!("Categorised transactions: {summary:?}");
+
+        Ok(summary)
+    }
+}
+
+#[async_trait]
+impl Categoriser for WebhookCategoriser {
+    #[tracing::instrument(name = "Categorise by webhook", skip(self, transaction), fields(tx_id = %transaction.id))]
+    async fn categorise(&self, transaction: &Uncategorised) -> Result<Option<String>, Error> {
+        let reply: WebhookReply = self
+            .client
+            .post(&self.url)
+            .json(transaction)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(reply.category.filter(|c| !c.is_empty()))
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{model::category::Category, tests::test::test_db};
+
+    #[tokio::test]
+    async fn stores_categories_from_the_webhook() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        pool.categories()
+            .save_category(&Category {
+                id: "groceries".to_string(),
+                name: "groceries".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/categorise"))
+            .and(body_partial_json(json!({"id": "1"})))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"category": "groceries"})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/categorise"))
+            .and(body_partial_json(json!({"id": "2"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"category": null})))
+            .mount(&server)
+            .await;
+        let categoriser = AutoCategoriser::from_settings(&Categorisation {
+            url: Some(format!("{}/categorise", server.uri())),
+            uncategorised: vec!["category_1".to_string()],
+        })
+        .unwrap();
+
+        // Act
+        let first = categoriser.run(&pool).await.unwrap();
+        let second = categoriser.run(&pool).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            first,
+            CategoriseSummary {
+                categorised: 1,
+                unchanged: 1,
+                unknown: 0,
+            }
+        );
+        assert_eq!(second.categorised + second.unchanged, 1);
+        let transaction = pool.transactions().read_transaction("1").await.unwrap();
+        assert_eq!(transaction.category_id, "groceries");
+    }
+}
@@ -0,0 +1,152 @@
+//! Command aliases
+//!
+//! `[alias]` in the configuration names command lines that are typed often:
+//!
+//! ```toml
+//! [alias]
+//! week = "compare --period week --against year"
+//! groceries = "transactions set --where 'category = groceries'"
+//! ```
+//!
+//! [`expand`] replaces an alias in the command position with its words before
+//! clap parses the arguments, so `monzo-cli week -q` runs
+//! `monzo-cli compare --period week --against year -q`. An alias may start with
+//! another alias, but built-in commands can't be redefined.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::CommandFactory;
+
+use super::Cli;
+use crate::error::AppErrors as Error;
+
+/// `args`, with the program name first, with any alias in the command
+/// position expanded
+///
+/// # Errors
+/// Will return an error if an alias can't be split into words or refers to
+/// itself.
+pub fn expand(args: Vec<String>, aliases: &BTreeMap<String, String>) -> Result<Vec<String>, Error> {
+    if aliases.is_empty() {
+        return Ok(args);
+    }
+
+    let cli = Cli::command();
+    let commands: BTreeSet<&str> = cli
+        .get_subcommands()
+        .flat_map(|command| std::iter::once(command.get_name()).chain(command.get_all_aliases()))
+        .chain(["help"])
+        .collect();
+    // Global options such as `--record <DIR>` take the next argument
+    let takes_value = |arg: &str| {
+        cli.get_arguments().any(|option| {
+            option.get_action().takes_values()
+                && (option
+                    .get_long()
+                    .is_some_and(|long| arg == format!("--{long}"))
+                    || option
+                        .get_short()
+                        .is_some_and(|short| arg == format!("-{short}")))
+        })
+    };
+
+    let mut args = args;
+    let mut expanded = BTreeSet::new();
+    loop {
+        let mut position = 1;
+        while let Some(arg) = args.get(position) {
+            if arg == "--" || !arg.starts_with('-') {
+                break;
+            }
+            position += if takes_value(arg) { 2 } else { 1 };
+        }
+
+        let Some(name) = args
+            .get(position)
+            .filter(|name| !commands.contains(name.as_str()))
+        else {
+            return Ok(args);
+        };
+        let Some(alias) = aliases.get(name) else {
+            return Ok(args);
+        };
+        if !expanded.insert(name.clone()) {
+            return Err(Error::Error(format!("Alias `{name}` refers to itself")));
+        }
+        let words = shlex::split(alias)
+            .filter(|words| !words.is_empty())
+            .ok_or_else(|| Error::Error(format!("Alias `{name}` isn't a valid command line")))?;
+
+        args.splice(position..=position, words);
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        shlex::split(line).unwrap()
+    }
+
+    fn aliases() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (
+                "week".to_string(),
+                "compare --period week --against year".to_string(),
+            ),
+            (
+                "christmas".to_string(),
+                "week --date 2023-12-25".to_string(),
+            ),
+            (
+                "groceries".to_string(),
+                "transactions set --where 'category = groceries'".to_string(),
+            ),
+            ("update".to_string(), "update --all".to_string()),
+            ("loop".to_string(), "again".to_string()),
+            ("again".to_string(), "loop".to_string()),
+        ])
+    }
+
+    #[test]
+    fn expands_aliases_in_the_command_position() {
+        assert_eq!(
+            expand(
+                args("monzo-cli -q --record tapes week --date 2024-07-03"),
+                &aliases()
+            )
+            .unwrap(),
+            args("monzo-cli -q --record tapes compare --period week --against year --date 2024-07-03")
+        );
+        assert_eq!(
+            expand(args("monzo-cli groceries"), &aliases()).unwrap(),
+            vec![
+                "monzo-cli",
+                "transactions",
+                "set",
+                "--where",
+                "category = groceries"
+            ]
+        );
+        assert_eq!(
+            expand(args("monzo-cli christmas"), &aliases()).unwrap(),
+            args("monzo-cli compare --period week --against year --date 2023-12-25")
+        );
+    }
+
+    #[test]
+    fn leaves_commands_and_arguments_alone() {
+        for line in [
+            "monzo-cli update",
+            "monzo-cli export week",
+            "monzo-cli --record week",
+            "monzo-cli",
+        ] {
+            assert_eq!(expand(args(line), &aliases()).unwrap(), args(line));
+        }
+        assert!(expand(args("monzo-cli loop"), &aliases()).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! List accounts
+//!
+//! This command prints the stored accounts with their details and transaction
+//! counts, as a table or JSON. Account numbers and sort codes are masked
+//! unless `--reveal` is given or `privacy.mask_account_numbers` is off. With
+//! `refresh` the accounts are fetched from Monzo first, picking up new and
+//! closed accounts. If Monzo can't be reached the stored accounts are listed
+//! with a warning.
+
+use std::collections::BTreeMap;
+
+use chrono_tz::Tz;
+
+use crate::{
+    cli::output,
+    client::Monzo,
+    engine::{AccountDetails, Reporter},
+    error::AppErrors as Error,
+    model::DatabasePool,
+    timezone::to_local,
+};
+
+/// List accounts
+///
+/// # Errors
+/// Will return errors if the database cannot be read, or with `refresh` if
+/// the Monzo API fails other than by being unreachable.
+pub async fn accounts(
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    refresh: bool,
+    json: bool,
+    mask_account_numbers: bool,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let reporter = Reporter::new(connection_pool, monzo);
+    let mut accounts = if refresh {
+        match reporter.refresh_accounts().await {
+            Ok(accounts) => accounts,
+            Err(e) if e.is_offline() => {
+                output::warn_offline(&e);
+                reporter.accounts().await?
+            }
+            Err(e) => return Err(e),
+        }
+    } else {
+        reporter.accounts().await?
+    };
+    if mask_account_numbers {
+        for details in &mut accounts {
+            details.account = details.account.masked();
+        }
+    }
+
+    if output::is_quiet() {
+        return Ok(());
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&accounts)?);
+    } else {
+        print_accounts(&accounts, timezone, nicknames);
+    }
+
+    Ok(())
+}
+
+fn print_accounts(accounts: &[AccountDetails], timezone: Tz, nicknames: &BTreeMap<String, String>) {
+    println!(
+        "{:<28} {:<12} {:<10} {:<10} {:<9} {:<4} {:<11} {:<7} {:>12}",
+        "ID", "NAME", "TYPE", "NUMBER", "SORT CODE", "CCY", "CREATED", "STATUS", "TRANSACTIONS"
+    );
+    for details in accounts {
+        let account = &details.account;
+        println!(
+            "{:<28} {:<12} {:<10} {:<10} {:<9} {:<4} {:<11} {:<7} {:>12}",
+            account.id,
+            account.name(nicknames),
+            account.owner_type,
+            account.account_number,
+            account.sort_code,
+            account.currency,
+            to_local(account.created, timezone).format("%Y-%m-%d"),
+            if account.closed { "closed" } else { "open" },
+            details.transactions
+        );
+        if !account.description.is_empty() {
+            println!("  {}", account.description);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Balance alerts
+//!
+//! `alerts check` compares the latest stored balances with the minimums in
+//! `[alerts.min_balance]`, notifies and exits non-zero if any are below them.
+//! The same check runs after each `update` and `balances --refresh`, where it
+//! only notifies.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    cli::output,
+    client::Monzo,
+    currency,
+    engine::{low_balances, BalanceAlert, Reporter},
+    error::AppErrors as Error,
+    model::DatabasePool,
+    notify::{Notification, Notifiers},
+};
+
+/// Check the latest balances, returning an error if any are too low
+///
+/// # Errors
+/// Will return [`Error::LowBalance`] if a balance is below its minimum, or
+/// other errors if no balances are stored or a notification can't be sent.
+pub async fn alerts_check(
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    min_balance: &BTreeMap<String, f64>,
+    notifiers: &Notifiers,
+) -> Result<(), Error> {
+    let alerts = read_alerts(connection_pool, monzo, min_balance).await?;
+
+    if !output::is_quiet() {
+        if alerts.is_empty() {
+            println!("All balances are above their minimum");
+        }
+        for alert in &alerts {
+            println!("{}", describe(alert)?);
+        }
+    }
+    if alerts.is_empty() {
+        return Ok(());
+    }
+
+    notifiers.send(&notification(&alerts)?).await?;
+    Err(Error::LowBalance(alerts.len()))
+}
+
+/// Notify if any of the latest balances are too low. Problems are reported
+/// as warnings so they never fail the command that took the snapshot.
+pub async fn alerts_notify(
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    min_balance: &BTreeMap<String, f64>,
+    notifiers: &Notifiers,
+) {
+    if min_balance.is_empty() {
+        return;
+    }
+
+    let result = async {
+        let alerts = read_alerts(connection_pool, monzo, min_balance).await?;
+        if !alerts.is_empty() {
+            notifiers.send(&notification(&alerts)?).await?;
+        }
+        Ok::<_, Error>(())
+    }
+    .await;
+    if let Err(e) = result {
+        eprintln!("Balance alerts not sent: {e}");
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+async fn read_alerts(
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    min_balance: &BTreeMap<String, f64>,
+) -> Result<Vec<BalanceAlert>, Error> {
+    let report = Reporter::new(connection_pool, monzo)
+        .balances()
+        .await?
+        .ok_or_else(|| {
+            Error::Error(
+                "No balances stored. Run `balances --refresh` or `update` to fetch them".into(),
+            )
+        })?;
+
+    Ok(low_balances(&report, min_balance))
+}
+
+fn describe(alert: &BalanceAlert) -> Result<String, Error> {
+    Ok(format!(
+        "{} is {}, below {}",
+        alert.name,
+        currency::display(alert.balance, &alert.currency)?,
+        currency::display(alert.minimum, &alert.currency)?
+    ))
+}
+
+fn notification(alerts: &[BalanceAlert]) -> Result<Notification, Error> {
+    Ok(Notification {
+        title: "Low balance".to_string(),
+        body: alerts
+            .iter()
+            .map(describe)
+            .collect::<Result<Vec<_>, _>>()?
+            .join("\n"),
+    })
+}
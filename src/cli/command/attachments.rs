@@ -0,0 +1,41 @@
+//! Transaction attachments
+//!
+//! `download` saves the receipts and other files attached to transactions in
+//! the Monzo app as `<dir>/<year>/<month>/<transaction id>-<n>.<ext>`. Files
+//! already downloaded, or with the same content as one that was, are not
+//! written again.
+
+use std::path::Path;
+
+use chrono_tz::Tz;
+use colored::Colorize;
+
+use crate::{
+    cli::output, engine::download_attachments, error::AppErrors as Error, model::DatabasePool,
+};
+
+/// Download the attachments of synced transactions into `dir`
+///
+/// # Errors
+/// Will return errors if the database can't be read, a file can't be fetched
+/// or it can't be written.
+pub async fn attachments_download(
+    connection_pool: DatabasePool,
+    dir: &Path,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let downloads = download_attachments(&connection_pool, dir, timezone).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} attachments to {}, {} already downloaded, {} duplicates",
+            "Downloaded".green(),
+            downloads.downloaded,
+            dir.display(),
+            downloads.existing,
+            downloads.duplicates
+        );
+    }
+
+    Ok(())
+}
@@ -4,23 +4,17 @@
 //! for an authorisation token, and persist it to the configuration file.
 
 use std::collections::HashMap;
-use std::io::Write;
 use std::option::Option;
 use std::sync::Arc;
 use tokio::sync::watch;
 use url::Url;
 use uuid::Uuid;
 
-use crate::configuration::{get_config, AccessTokens};
+use crate::configuration::{get_config, save_access_tokens, AccessTokens};
 use crate::error::AppErrors as Error;
-use crate::routes::oauth_callback;
+use crate::routes::{oauth_callback, AuthorisationState};
 use axum::{routing::get, Router};
 
-#[derive(Clone)]
-pub struct AuthorisationState {
-    pub token_tx: Arc<watch::Sender<Option<AccessTokens>>>,
-}
-
 /// Authenticate with Monzo
 ///
 /// # Errors
@@ -29,13 +23,7 @@ pub struct AuthorisationState {
 pub async fn auth() -> Result<(), Error> {
     let access_tokens = get_access_tokens().await?;
 
-    let mut config = get_config()?;
-    config.access_tokens = access_tokens;
-    let mut file = std::fs::File::create("configuration.toml")?;
-    let toml_string = toml::to_string_pretty(&config)?;
-    file.write_all(toml_string.as_bytes())?;
-
-    Ok(())
+    save_access_tokens(&access_tokens)
 }
 
 // Get the access tokens.
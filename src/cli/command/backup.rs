@@ -0,0 +1,114 @@
+//! Back up and restore the database
+//!
+//! `backup` writes a copy of the database to a local directory, an
+//! S3-compatible bucket or a `WebDAV` share and keeps the newest few there.
+//! Backups can be encrypted to an age recipient or a gpg key. `restore`
+//! replaces the database with the newest backup in a target, or a named one,
+//! keeping the current database next to it.
+
+use std::path::Path;
+
+use chrono::Utc;
+use colored::Colorize;
+use dialoguer::Confirm;
+
+use crate::{
+    backup::{self, replace_database, split_location, Target},
+    cli::output,
+    configuration::Backup,
+    encryption::Encryption,
+    error::AppErrors as Error,
+    model::DatabasePool,
+};
+
+/// Back up the database to `to`, or the configured target, keeping the newest
+/// `keep` backups there, encrypted if `encryption` is given
+///
+/// # Errors
+/// Will return an error if no target is given or configured, or the backup
+/// can't be encrypted or written.
+pub async fn backup(
+    pool: DatabasePool,
+    to: Option<&str>,
+    keep: Option<usize>,
+    encryption: Option<&Encryption>,
+    settings: &Backup,
+) -> Result<(), Error> {
+    let location = to.or(settings.target.as_deref()).ok_or_else(|| {
+        Error::Error("Give a target with --to or set `[backup] target`".to_string())
+    })?;
+    let target = Target::parse(location, settings)?;
+
+    let outcome = backup::backup(
+        &pool,
+        &target,
+        keep.unwrap_or(settings.keep),
+        Utc::now().naive_utc(),
+        encryption,
+    )
+    .await?;
+
+    if !output::is_quiet() {
+        println!(
+            "Backed up {} bytes as {} in {target}",
+            outcome.bytes,
+            outcome.name.green()
+        );
+        for name in &outcome.removed {
+            println!("Removed {name}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the database at `database_path` with the newest backup in `from`,
+/// or the backup `from` names, and reopen it. Encrypted backups are decrypted
+/// with the age identities in `identity_file` or the gpg keyring.
+///
+/// # Errors
+/// Will return an error if the database isn't `SQLite`, the backup can't be
+/// read or decrypted, isn't a database, or the user aborts.
+pub async fn restore(
+    pool: DatabasePool,
+    from: &str,
+    yes: bool,
+    identity_file: Option<&Path>,
+    database_path: &str,
+    max_connections: u32,
+    settings: &Backup,
+) -> Result<DatabasePool, Error> {
+    pool.sqlite("restore")?;
+    let (location, name) = split_location(from);
+    let target = Target::parse(location, settings)?;
+    let (name, data) = backup::fetch(&target, name, identity_file).await?;
+
+    if !yes && !confirm_restore(&name)? {
+        return Err(Error::AbortError);
+    }
+
+    pool.close().await;
+    let previous = replace_database(Path::new(database_path), &data, Utc::now().naive_utc())?;
+    if !output::is_quiet() {
+        println!(
+            "Restored {name}; the previous database is {}",
+            previous.display()
+        );
+    }
+
+    DatabasePool::new(database_path, max_connections).await
+}
+
+fn confirm_restore(name: &str) -> Result<bool, Error> {
+    println!("Restoring {name}");
+    println!(
+        "{} {}",
+        "WARNING".red(),
+        "This replaces the database with the backup".bold()
+    );
+    let confirmation = Confirm::new()
+        .with_prompt("Do you want to continue?")
+        .interact()?;
+
+    Ok(confirmation)
+}
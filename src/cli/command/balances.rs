@@ -1,61 +1,143 @@
 //! Get balances
 //!
-//! This command will fetch the balances of all accounts
-//! and print them to the console.
+//! This command prints the balances of all accounts stored by the last
+//! `update` or refresh, and how old they are. With `refresh` they are fetched
+//! from Monzo first, unless it can't be reached, when the stored balances are
+//! shown with a warning. Totals are given per currency and, if a base currency is
+//! configured, converted to it with the latest stored exchange rates.
 
-use rusty_money::{iso, Money};
+use std::collections::BTreeMap;
 
+use chrono::{Duration, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::cli::output;
 use crate::client::Monzo;
+use crate::currency;
+use crate::engine::{BalanceReport, Reporter};
 use crate::error::AppErrors as Error;
+use crate::model::DatabasePool;
+use crate::timezone::to_local;
 
 /// Get balances
 ///
 /// # Errors
-/// Will return errors if the Monzo API cannot be reached.
+/// Will return errors if no balances are stored, or with `refresh` if the
+/// Monzo API fails other than by being unreachable.
 ///
-pub async fn balances() -> Result<(), Error> {
-    let monzo = Monzo::new()?;
+pub async fn balances(
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    refresh: bool,
+    base_currency: Option<&str>,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+    mask_account_numbers: bool,
+) -> Result<(), Error> {
+    let reporter = Reporter::new(connection_pool, monzo);
+    let refreshed = if refresh {
+        match reporter.refresh_balances().await {
+            Ok(report) => Some(report),
+            Err(e) if e.is_offline() => {
+                output::warn_offline(&e);
+                None
+            }
+            Err(e) => return Err(e),
+        }
+    } else {
+        None
+    };
+    let mut report = match refreshed {
+        Some(report) => report,
+        None => reporter.balances().await?.ok_or_else(|| {
+            Error::Error(
+                "No balances stored. Run `balances --refresh` or `update` to fetch them".into(),
+            )
+        })?,
+    };
+    if mask_account_numbers {
+        for entry in &mut report.accounts {
+            entry.account = entry.account.masked();
+        }
+    }
 
-    let mut balance_total = 0;
+    if !output::is_quiet() {
+        // only worth converting if there's another currency
+        let converted = match base_currency {
+            Some(base) if report.totals().keys().any(|code| code != base) => {
+                let rates = reporter.fx_rates(base).await?;
+                Some((base, report.total_in(base, &rates)))
+            }
+            _ => None,
+        };
+        print_balances(
+            &report,
+            converted.as_ref().map(|(base, total)| (*base, total)),
+            timezone,
+            nicknames,
+        )?;
+    }
+
+    Ok(())
+}
 
+fn print_balances(
+    report: &BalanceReport,
+    converted: Option<(&str, &Result<i64, Error>)>,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
     println!("{:>44}", "BALANCES");
+    println!(
+        "as of {} ({})",
+        to_local(report.taken, timezone).format("%Y-%m-%d %H:%M"),
+        age(report.taken, Utc::now().naive_utc())
+    );
     println!("--------------------------------------------");
 
-    // Display accounts
-    for account in monzo.accounts().await? {
-        let balance = monzo.balance(&account.id).await?;
-        balance_total += balance.balance;
-
-        let Some(iso_code) = iso::find(&balance.currency) else {
-            return Err(Error::CurrencyNotFound(balance.currency));
-        };
-        let balance_fmt = Money::from_minor(balance.balance, iso_code).to_string();
-        let spend_today_fmt = Money::from_minor(balance.spend_today, iso_code).to_string();
+    for entry in &report.accounts {
+        let balance = &entry.balance;
+        let balance_fmt = currency::display(balance.balance, &balance.currency)?;
+        let spend_today_fmt = currency::display(balance.spend_today, &balance.currency)?;
 
         println!(
             "{:<8} ({}) : {:>11} {:>10}",
-            account.owner_type, account.account_number, balance_fmt, spend_today_fmt,
+            entry.account.name(nicknames),
+            entry.account.account_number,
+            balance_fmt,
+            spend_today_fmt,
         );
 
         // Display pots
-        for pot in monzo.pots(&account.id).await? {
-            if pot.deleted {
-                continue;
-            }
-            balance_total += pot.balance;
-            let Some(iso_code) = iso::find(&balance.currency) else {
-                return Err(Error::CurrencyNotFound(balance.currency));
-            };
-            let balance_fmt = Money::from_minor(pot.balance, iso_code).to_string();
-
+        for pot in &entry.pots {
+            let balance_fmt = currency::display(pot.balance, &pot.currency)?;
             println!("- {:<18}: {:>11}", pot.name.to_lowercase(), balance_fmt);
         }
     }
     println!("--------------------------------------------");
-    println!(
-        "Total: {:>26}",
-        Money::from_minor(balance_total, iso::GBP).to_string() // TODO: Use the account currency
-    );
+    for (code, total) in report.totals() {
+        println!("Total {code}: {:>22}", currency::display(total, &code)?);
+    }
+    if let Some((base, total)) = converted {
+        match total {
+            Ok(total) => println!("Total in {base}: {:>19}", currency::display(*total, base)?),
+            Err(e) => println!("Total in {base}: n/a ({e})"),
+        }
+    }
 
     Ok(())
 }
+
+// How long ago `taken` was, to the nearest minute, hour or day
+fn age(taken: NaiveDateTime, now: NaiveDateTime) -> String {
+    let age = now - taken;
+    if age < Duration::minutes(1) {
+        "just now".to_string()
+    } else if age < Duration::hours(1) {
+        format!("{} min ago", age.num_minutes())
+    } else if age < Duration::days(1) {
+        format!("{} h ago", age.num_hours())
+    } else {
+        format!("{} days ago", age.num_days())
+    }
+}
@@ -0,0 +1,109 @@
+//! Beancount ledger tools
+//!
+//! `beancount ledger` writes the ledger, the same as `export beancount`.
+//! `beancount sync-back` reads the categories and tags changed in a ledger
+//! back into the database, for entries exported with their transaction id.
+
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use colored::Colorize;
+
+use super::export::export;
+use crate::{
+    cli::output,
+    configuration::{Calendar, Filters},
+    error::AppErrors as Error,
+    export::ledger::SyncBack,
+    model::DatabasePool,
+};
+
+/// Write a beancount ledger of the transactions created between `since` and
+/// `until` to `output_path`, a file or a ledger directory, or stdout
+///
+/// # Errors
+/// Will return errors if the data cannot be read or the output cannot be
+/// written.
+#[allow(clippy::too_many_arguments)]
+pub async fn beancount_ledger(
+    connection_pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    output_path: Option<&Path>,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+    accounts: &[String],
+    calendar: &Calendar,
+    filters: &Filters,
+    mask_account_numbers: bool,
+) -> Result<(), Error> {
+    export(
+        connection_pool,
+        "beancount",
+        since,
+        until,
+        output_path,
+        timezone,
+        nicknames,
+        accounts,
+        calendar,
+        filters,
+        mask_account_numbers,
+        None,
+    )
+    .await
+}
+
+/// Store the categories and tags changed in `ledger`, or only show them if
+/// `dry_run`
+///
+/// # Errors
+/// Will return errors if the ledger can't be read or the database can't be
+/// read or written.
+pub async fn beancount_sync_back(
+    connection_pool: DatabasePool,
+    ledger: &Path,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let text = std::fs::read_to_string(ledger)
+        .map_err(|e| Error::Error(format!("Can't read {}: {e}", ledger.display())))?;
+    let sync = SyncBack::read(&connection_pool, &text).await?;
+
+    if !dry_run {
+        sync.apply(&connection_pool).await?;
+    }
+
+    for (id, name) in &sync.unknown_categories {
+        eprintln!("{id}: no category matches '{name}'");
+    }
+    if !sync.missing.is_empty() {
+        eprintln!(
+            "{} ledger entries aren't in the database",
+            sync.missing.len()
+        );
+    }
+    if output::is_quiet() {
+        return Ok(());
+    }
+    for change in &sync.changes {
+        let mut parts = Vec::new();
+        if let Some((from, to)) = &change.category {
+            parts.push(format!("{from} -> {}", to.green()));
+        }
+        if !change.tags.is_empty() {
+            let tags: Vec<String> = change.tags.iter().map(|t| format!("#{t}")).collect();
+            parts.push(format!("+{}", tags.join(" ")));
+        }
+        println!(
+            "{} {}: {}",
+            change.id.dimmed(),
+            change.description,
+            parts.join(", ")
+        );
+    }
+    let verb = if dry_run { "Would update" } else { "Updated" };
+    println!("{verb} {} transactions", sync.changes.len());
+
+    Ok(())
+}
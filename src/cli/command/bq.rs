@@ -0,0 +1,72 @@
+//! Beancount queries
+//!
+//! This command writes the beancount export to a temporary ledger, with the
+//! annotations in `beancount.yaml`, and runs a query over it with `bean-query`
+//! from [beanquery](https://github.com/beancount/beanquery), e.g. balances by
+//! account or spending by month, without keeping a ledger file up to date.
+
+use std::{collections::BTreeMap, fs::File, io::BufWriter};
+
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use temp_dir::TempDir;
+use tokio::process::Command;
+
+use super::export::create_exporter;
+use crate::{
+    configuration::Filters,
+    error::AppErrors as Error,
+    export::{self, beancount::GENERATED},
+    model::DatabasePool,
+};
+
+/// The beanquery command line tool
+const BEAN_QUERY: &str = "bean-query";
+
+/// Run `query` with `bean-query` over the transactions created between
+/// `since` and `until`
+///
+/// # Errors
+/// Will return errors if the ledger can't be written, `bean-query` isn't
+/// installed or the query fails.
+#[allow(clippy::too_many_arguments)]
+pub async fn bq(
+    connection_pool: DatabasePool,
+    query: &str,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+    filters: &Filters,
+) -> Result<(), Error> {
+    let mut exporter = create_exporter("beancount", timezone, nicknames, &[])?;
+    if filters.beancount {
+        exporter.set_min_amount(connection_pool.min_amount());
+    }
+    let dir = TempDir::with_prefix("monzo-bq")?;
+    let ledger = dir.path().join(GENERATED);
+    {
+        let mut out = BufWriter::new(File::create(&ledger)?);
+        export::export(connection_pool, exporter.as_mut(), since, until, &mut out).await?;
+    }
+
+    let status = match Command::new(BEAN_QUERY)
+        .arg(&ledger)
+        .arg(query)
+        .status()
+        .await
+    {
+        Ok(status) => status,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::Error(format!(
+                "{BEAN_QUERY} wasn't found. Install it with `pip install beanquery`"
+            )))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !status.success() {
+        return Err(Error::Error(format!("{BEAN_QUERY} failed with {status}")));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! Budgets
+//!
+//! `budget status` prints each category's budget for a month with what has
+//! been spent and what's left. `budget envelopes` compares the envelope pots
+//! with what's left of their budgets this month and, with `--top-up`, deposits
+//! any shortfall into them.
+//!
+//! With `feed_alerts`, each `update` posts to the Monzo feed when a category
+//! first reaches 80% and 100% of its budget in a month.
+
+use chrono::{Datelike, NaiveDate, Utc};
+
+use crate::{
+    cli::output,
+    client::Monzo,
+    currency,
+    engine::{BudgetPlan, BudgetStatus, Envelope, Reporter, ALERT_THRESHOLDS},
+    error::AppErrors as Error,
+    model::DatabasePool,
+};
+
+/// Print the budgets for the month containing `month`
+///
+/// # Errors
+/// Will return errors if no budgets are configured or the database cannot be
+/// read.
+pub async fn budget_status(plan: &BudgetPlan, month: NaiveDate) -> Result<(), Error> {
+    let statuses = plan.status(month).await?;
+
+    if !output::is_quiet() {
+        println!("{}", month.format("%B %Y"));
+        print_statuses(&statuses)?;
+    }
+
+    Ok(())
+}
+
+/// Print the envelope pots against their budgets, topping them up if asked
+///
+/// # Errors
+/// Will return errors if the budgets or balances cannot be read, an envelope
+/// pot doesn't exist, or a deposit fails.
+pub async fn budget_envelopes(
+    plan: &BudgetPlan,
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    today: NaiveDate,
+    top_up: bool,
+) -> Result<(), Error> {
+    let statuses = plan.status(today).await?;
+    let reporter = Reporter::new(connection_pool, monzo.clone());
+    let report = if top_up {
+        reporter.refresh_balances().await?
+    } else {
+        reporter.balances().await?.ok_or_else(|| {
+            Error::Error(
+                "No balances stored. Run `balances --refresh` or `update` to fetch them".into(),
+            )
+        })?
+    };
+    let envelopes = plan.envelopes(&statuses, &report)?;
+
+    if !output::is_quiet() {
+        print_envelopes(&envelopes)?;
+    }
+
+    if top_up {
+        let month = today.format("%Y-%m");
+        for envelope in envelopes.iter().filter(|e| e.top_up() > 0) {
+            let amount = envelope.top_up();
+            // the same top-up in the same month is only made once
+            let dedupe_id = format!("budget-{}-{month}-{amount}", envelope.pot_id);
+            monzo
+                .deposit_into_pot(&envelope.pot_id, &envelope.account_id, amount, &dedupe_id)
+                .await?;
+            if !output::is_quiet() {
+                println!(
+                    "Deposited {} into {}",
+                    currency::display(amount, &envelope.currency)?,
+                    envelope.pot_name
+                );
+            }
+        }
+    } else if !output::is_quiet() && envelopes.iter().any(|e| e.top_up() > 0) {
+        println!("\nRun with --top-up to deposit the shortfalls");
+    }
+
+    Ok(())
+}
+
+/// Post budgets that reached a threshold this month to the feed of
+/// `account_id`, or the first open account. Problems are reported as warnings
+/// so they never fail the sync.
+pub async fn budget_feed_alerts(
+    plan: &BudgetPlan,
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    today: NaiveDate,
+    account_id: Option<&str>,
+) {
+    if let Err(e) = post_feed_alerts(plan, connection_pool, monzo, today, account_id).await {
+        eprintln!("Budget alerts not posted: {e}");
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+async fn post_feed_alerts(
+    plan: &BudgetPlan,
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    today: NaiveDate,
+    account_id: Option<&str>,
+) -> Result<(), Error> {
+    let account_id = if let Some(account_id) = account_id {
+        account_id.to_string()
+    } else {
+        connection_pool
+            .accounts()
+            .read_accounts()
+            .await?
+            .into_iter()
+            .find(|account| !account.closed)
+            .map(|account| account.id)
+            .ok_or_else(|| Error::Error("No open account to post to".into()))?
+    };
+    let month = today.with_day(1).unwrap_or(today);
+    let alert_service = connection_pool.budget_alerts();
+
+    for status in plan.status(today).await? {
+        let Some(reached) = status.threshold_reached() else {
+            continue;
+        };
+        if alert_service
+            .is_posted(&status.category, month, reached)
+            .await?
+        {
+            continue;
+        }
+
+        let (title, body) = feed_item(&status, reached)?;
+        monzo.create_feed_item(&account_id, &title, &body).await?;
+        // a budget that jumps straight past 100% doesn't also get an 80% alert
+        for threshold in ALERT_THRESHOLDS.into_iter().filter(|t| *t <= reached) {
+            alert_service
+                .record_posted(&status.category, month, threshold, Utc::now().naive_utc())
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn feed_item(status: &BudgetStatus, threshold: i64) -> Result<(String, String), Error> {
+    let available = currency::display(status.available(), &status.currency)?;
+    Ok(if threshold >= 100 {
+        (
+            format!("{} budget spent", status.category),
+            format!(
+                "{} of {available} spent this month",
+                currency::display(status.spent, &status.currency)?
+            ),
+        )
+    } else {
+        (
+            format!("{} budget {threshold}% spent", status.category),
+            format!(
+                "{} of {available} left this month",
+                currency::display(status.remaining(), &status.currency)?
+            ),
+        )
+    })
+}
+
+fn print_statuses(statuses: &[BudgetStatus]) -> Result<(), Error> {
+    println!(
+        "{:<20}{:>12}{:>12}{:>12}{:>12}",
+        "CATEGORY", "BUDGET", "CARRIED", "SPENT", "LEFT"
+    );
+    println!("{}", "-".repeat(20 + 12 * 4));
+    for status in statuses {
+        println!(
+            "{:<20}{:>12}{:>12}{:>12}{:>12}",
+            status.category,
+            currency::display(status.budget, &status.currency)?,
+            currency::display(status.carried, &status.currency)?,
+            currency::display(status.spent, &status.currency)?,
+            currency::display(status.remaining(), &status.currency)?
+        );
+    }
+
+    Ok(())
+}
+
+fn print_envelopes(envelopes: &[Envelope]) -> Result<(), Error> {
+    println!(
+        "{:<20}{:<20}{:>12}{:>12}{:>12}",
+        "CATEGORY", "POT", "BALANCE", "LEFT", "TOP UP"
+    );
+    println!("{}", "-".repeat(40 + 12 * 3));
+    for envelope in envelopes {
+        println!(
+            "{:<20}{:<20}{:>12}{:>12}{:>12}",
+            envelope.category,
+            envelope.pot_name,
+            currency::display(envelope.balance, &envelope.currency)?,
+            currency::display(envelope.target, &envelope.currency)?,
+            currency::display(envelope.top_up(), &envelope.currency)?
+        );
+    }
+
+    Ok(())
+}
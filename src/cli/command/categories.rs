@@ -0,0 +1,93 @@
+//! Category maintenance
+//!
+//! `audit` checks the stored categories against `categories.yaml` and the
+//! categories named in the configuration. With `--fix` it stores categories
+//! that transactions use but are missing, and renames categories whose name
+//! changed in `categories.yaml`.
+
+use colored::Colorize;
+
+use crate::{cli::output, engine::CategoryAudit, error::AppErrors as Error, model::DatabasePool};
+
+/// Print the problems found by `audit`, then fix what can be fixed if `fix`
+/// is set
+///
+/// # Errors
+/// Will return errors if the database can't be written.
+pub async fn categories_audit(
+    connection_pool: DatabasePool,
+    audit: &CategoryAudit,
+    fix: bool,
+) -> Result<(), Error> {
+    if !output::is_quiet() {
+        print_audit(audit);
+    }
+    if !fix {
+        return Ok(());
+    }
+
+    let fixes = audit.fix(&connection_pool).await?;
+    if !output::is_quiet() {
+        println!(
+            "{} {} missing categories and renamed {}",
+            "Stored".green(),
+            fixes.created,
+            fixes.renamed
+        );
+    }
+
+    Ok(())
+}
+
+fn print_audit(audit: &CategoryAudit) {
+    if audit.is_clean() {
+        println!("{}", "No problems found".green());
+        return;
+    }
+
+    print_section(
+        "Used by transactions but not stored (fix with --fix)",
+        audit
+            .missing
+            .iter()
+            .map(|m| format!("{:<40} {} transactions", m.id, m.count)),
+    );
+    print_section(
+        "Renamed in categories.yaml (fix with --fix)",
+        audit
+            .stale
+            .iter()
+            .map(|s| format!("{:<40} {} -> {}", s.id, s.stored, s.configured)),
+    );
+    print_section(
+        "Custom categories without a name (add them to categories.yaml)",
+        audit.unnamed.iter().cloned(),
+    );
+    print_section(
+        "In categories.yaml but never used",
+        audit
+            .unused
+            .iter()
+            .map(|(id, name)| format!("{id:<40} {name}")),
+    );
+    print_section(
+        "Settings naming an unknown category",
+        audit
+            .unknown
+            .iter()
+            .map(|u| format!("[{}] {}", u.setting, u.name)),
+    );
+    print_section("Conflicts", audit.conflicts.iter().cloned());
+}
+
+// Print a titled list, or nothing if it's empty
+fn print_section(title: &str, lines: impl Iterator<Item = String>) {
+    let mut lines = lines.peekable();
+    if lines.peek().is_none() {
+        return;
+    }
+    println!("{}", title.bold());
+    for line in lines {
+        println!("  {line}");
+    }
+}
@@ -0,0 +1,110 @@
+//! Spending comparison
+//!
+//! This command prints spending by category in the current week or month
+//! against the previous one, or the same one a year earlier, with the change
+//! in money and as a percentage. Categories whose increase accounts for a rise
+//! in total spending are marked.
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use colored::Colorize;
+
+use crate::{
+    cli::output,
+    currency,
+    engine::{compare::DateRange, Baseline, Comparison, Period},
+    error::AppErrors as Error,
+    locale,
+    model::DatabasePool,
+};
+
+/// Compare spending in the `period` up to `date` with the `baseline` period
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn compare(
+    connection_pool: DatabasePool,
+    period: Period,
+    baseline: Baseline,
+    date: NaiveDate,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let comparison = Comparison::build(connection_pool, period, baseline, date, timezone).await?;
+
+    if !output::is_quiet() {
+        print_comparison(&comparison)?;
+    }
+
+    Ok(())
+}
+
+fn print_comparison(comparison: &Comparison) -> Result<(), Error> {
+    println!(
+        "Spending {} against {}\n",
+        dates(comparison.current),
+        dates(comparison.previous)
+    );
+    if comparison.categories.is_empty() {
+        println!("No spending in either period");
+        return Ok(());
+    }
+
+    let drivers = comparison.drivers();
+    println!(
+        "  {:<24}{:>14}{:>14}{:>14}{:>9}",
+        "CATEGORY", "PREVIOUS", "CURRENT", "CHANGE", "%"
+    );
+    println!("{}", "-".repeat(2 + 24 + 14 * 3 + 9));
+    for category in &comparison.categories {
+        let marker = if drivers.contains(&category) {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{marker} {:<24}{:>14}{:>14}{}{:>9}",
+            category.category_label,
+            currency::display(category.previous, &category.currency)?,
+            currency::display(category.current, &category.currency)?,
+            change(category.delta(), &category.currency)?,
+            category
+                .percent()
+                .map_or_else(|| "new".to_string(), |p| format!("{p:+.0}%"))
+        );
+    }
+    println!("{}", "-".repeat(2 + 24 + 14 * 3 + 9));
+    for (code, current, previous) in comparison.totals() {
+        println!(
+            "  {:<24}{:>14}{:>14}{}",
+            "TOTAL",
+            currency::display(previous, &code)?,
+            currency::display(current, &code)?,
+            change(current - previous, &code)?
+        );
+    }
+
+    if !drivers.is_empty() {
+        let names: Vec<&str> = drivers.iter().map(|c| c.category_label.as_str()).collect();
+        println!("\n* Driving the increase: {}", names.join(", "));
+    }
+
+    Ok(())
+}
+
+// A signed change, red when spending went up and green when it went down
+fn change(delta: i64, code: &str) -> Result<String, Error> {
+    let text = format!("{:>14}", currency::display(delta, code)?);
+    Ok(match delta {
+        d if d > 0 => text.red().to_string(),
+        d if d < 0 => text.green().to_string(),
+        _ => text,
+    })
+}
+
+fn dates(range: DateRange) -> String {
+    format!(
+        "{} to {}",
+        locale::current().date(range.first),
+        locale::current().date(range.last)
+    )
+}
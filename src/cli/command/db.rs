@@ -0,0 +1,129 @@
+//! Database maintenance
+//!
+//! `seed` loads accounts, pots, categories and transactions from a fixture
+//! file, e.g. data migrated from another tool. `prune` applies the retention
+//! settings to the update history and merchant change log. `classify` marks
+//! which transactions are transfers rather than spending. `migrate-to` copies
+//! the database to Postgres.
+
+use std::path::Path;
+
+use colored::Colorize;
+
+#[cfg(feature = "postgres")]
+use crate::model::postgres::redact;
+use crate::{
+    cli::output,
+    configuration::Retention,
+    error::AppErrors as Error,
+    model::{fixture::Fixture, transfer::TransferRules, DatabasePool},
+};
+
+/// Load a JSON or YAML fixture into the database
+///
+/// # Errors
+/// Will return errors if the fixture can't be read, is invalid, or can't be saved.
+pub async fn db_seed(
+    connection_pool: DatabasePool,
+    path: &Path,
+    rules: &TransferRules,
+) -> Result<(), Error> {
+    let fixture = Fixture::from_path(path)?;
+    let summary = connection_pool.load_fixture(&fixture).await?;
+    connection_pool.classify_transfers(rules).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} accounts, {} pots, {} categories, {} transactions and {} exchange rates ({} already present)",
+            "Loaded".green(),
+            summary.accounts,
+            summary.pots,
+            summary.categories,
+            summary.transactions,
+            summary.fx_rates,
+            summary.skipped
+        );
+    }
+
+    Ok(())
+}
+
+/// Delete housekeeping data past its retention period and vacuum
+///
+/// # Errors
+/// Will return errors if the database can't be written.
+pub async fn db_prune(connection_pool: DatabasePool, retention: &Retention) -> Result<(), Error> {
+    let summary = connection_pool.prune(retention).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} update runs, {} checkpoints and {} merchant changes, reclaiming {} KiB",
+            "Pruned".green(),
+            summary.runs,
+            summary.checkpoints,
+            summary.merchant_changes,
+            summary.reclaimed_bytes / 1024
+        );
+    }
+
+    Ok(())
+}
+
+/// Mark every transaction that is a transfer according to `rules`
+///
+/// # Errors
+/// Will return errors if the database can't be written.
+pub async fn db_classify(
+    connection_pool: DatabasePool,
+    rules: &TransferRules,
+) -> Result<(), Error> {
+    let summary = connection_pool.classify_transfers(rules).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} transactions as transfers, {} of them repayments ({} changed)",
+            "Marked".green(),
+            summary.transfers,
+            summary.repayments,
+            summary.changed
+        );
+    }
+
+    Ok(())
+}
+
+/// Copy the database to the empty Postgres database at `target`, showing each
+/// table's progress
+///
+/// # Errors
+/// Will return errors if the target can't be reached or isn't empty, or the
+/// copy fails or doesn't match afterwards.
+#[cfg(feature = "postgres")]
+pub async fn db_migrate_to(connection_pool: DatabasePool, target: &str) -> Result<(), Error> {
+    use std::io::Write;
+
+    let quiet = output::is_quiet();
+    let copies = connection_pool
+        .migrate_to(target, |copy| {
+            if !quiet {
+                eprint!("\r{:<24} {:>8} / {}", copy.table, copy.copied, copy.rows);
+                if copy.copied >= copy.rows {
+                    eprintln!();
+                }
+                let _ = std::io::stderr().flush();
+            }
+        })
+        .await?;
+
+    if !quiet {
+        println!(
+            "{} {} rows in {} tables to {}, and checked the row counts",
+            "Copied".green(),
+            copies.iter().map(|c| c.copied).sum::<i64>(),
+            copies.len(),
+            redact(target)
+        );
+    }
+
+    Ok(())
+}
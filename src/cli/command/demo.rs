@@ -0,0 +1,39 @@
+//! Demo data
+//!
+//! This command fills an empty database with generated data, for trying the
+//! reports and exports before authorising the Monzo API.
+
+use chrono::{Months, Utc};
+use colored::Colorize;
+
+use crate::{
+    cli::output,
+    demo::{self, DemoOptions},
+    error::AppErrors as Error,
+    model::DatabasePool,
+};
+
+/// Seed the database with `years` of generated data ending today
+///
+/// # Errors
+/// Will return errors if the database isn't empty or the data can't be saved.
+pub async fn demo_seed(connection_pool: DatabasePool, seed: u64, years: u32) -> Result<(), Error> {
+    let end = Utc::now().date_naive();
+    let start = end
+        .checked_sub_months(Months::new(12 * years))
+        .ok_or_else(|| Error::Error(format!("Can't generate {years} years of data")))?;
+
+    let summary = demo::seed(connection_pool, DemoOptions { seed, start, end }).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} accounts, {} pots and {} transactions from {start} to {end}",
+            "Seeded".green(),
+            summary.accounts,
+            summary.pots,
+            summary.transactions
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Spending digest
+//!
+//! This command summarises spending over the last day, week or month, prints
+//! it and sends it through the configured notifications. It's meant to be run
+//! from cron, e.g. every Sunday evening after an update.
+
+use chrono::{Duration, Months, Utc};
+
+use crate::{
+    cli::{output, DigestPeriod},
+    engine::Digest,
+    error::AppErrors as Error,
+    locale,
+    model::DatabasePool,
+    notify::{Notification, Notifiers},
+};
+
+/// Summarise spending over `period`, ending now
+///
+/// # Errors
+/// Will return errors if the database cannot be read or a notification can't
+/// be sent.
+pub async fn digest(
+    connection_pool: DatabasePool,
+    period: DigestPeriod,
+    notifiers: &Notifiers,
+) -> Result<(), Error> {
+    let until = Utc::now().naive_utc();
+    let (since, name) = match period {
+        DigestPeriod::Day => (until - Duration::days(1), "Daily"),
+        DigestPeriod::Week => (until - Duration::days(7), "Weekly"),
+        DigestPeriod::Month => (
+            until
+                .checked_sub_months(Months::new(1))
+                .unwrap_or(until - Duration::days(30)),
+            "Monthly",
+        ),
+    };
+
+    let digest = Digest::build(connection_pool, since, until).await?;
+    let notification = Notification {
+        title: format!(
+            "{name} spending {} to {}",
+            locale::current().day_month(since.date()),
+            locale::current().day_month(until.date())
+        ),
+        body: digest.render()?,
+    };
+
+    if !output::is_quiet() {
+        print!("{}\n\n{}", notification.title, notification.body);
+    }
+    notifiers.send(&notification).await?;
+
+    Ok(())
+}
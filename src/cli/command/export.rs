@@ -0,0 +1,285 @@
+//! Export transactions
+//!
+//! This command writes transactions in one of the registered export formats to
+//! a file or stdout, with the events and notes in `beancount.yaml`. A beancount
+//! export to a directory writes `generated.beancount` there, and creates a
+//! `main.beancount` including it and a `manual.beancount` if they're missing.
+//! With `--diff` the export is regenerated in memory and compared with the
+//! existing output file, which is left untouched. With `--encrypt` or `--gpg`
+//! the export is [encrypted](crate::encryption) before it's written.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use colored::Colorize;
+use similar::{ChangeTag, TextDiff};
+
+use crate::{
+    cli::output,
+    configuration::{Calendar, Filters},
+    encryption::Encryption,
+    error::AppErrors as Error,
+    export::{
+        self,
+        annotations::{Annotations, ANNOTATIONS_FILE},
+        anonymise::anonymise,
+        Exporter, Registry,
+    },
+    model::DatabasePool,
+};
+
+/// The format name of the anonymised database copy
+const ANONYMISED: &str = "anonymised";
+
+/// Export transactions created between `since` and `until` in `format`,
+/// encrypted if `encryption` is given
+///
+/// # Errors
+/// Will return errors if the format is unknown, the data cannot be read or
+/// encrypted, or the output cannot be written.
+#[allow(clippy::too_many_arguments)]
+pub async fn export(
+    connection_pool: DatabasePool,
+    format: &str,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    output_path: Option<&Path>,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+    accounts: &[String],
+    calendar: &Calendar,
+    filters: &Filters,
+    mask_account_numbers: bool,
+    encryption: Option<&Encryption>,
+) -> Result<(), Error> {
+    if format == ANONYMISED {
+        return export_anonymised(&connection_pool, output_path, encryption).await;
+    }
+
+    let mut exporter = create_exporter(format, timezone, nicknames, accounts)?;
+    exporter.set_calendar(calendar);
+    exporter.set_mask_account_numbers(mask_account_numbers);
+    if filters.beancount {
+        exporter.set_min_amount(connection_pool.min_amount());
+    }
+
+    if let Some(encryption) = encryption {
+        if output_path.is_some_and(Path::is_dir) {
+            return Err(Error::Error(
+                "A beancount ledger directory can't be encrypted; export to a file".into(),
+            ));
+        }
+        let mut plaintext = Vec::new();
+        let count = export::export(
+            connection_pool,
+            exporter.as_mut(),
+            since,
+            until,
+            &mut plaintext,
+        )
+        .await?;
+        let ciphertext = encryption.encrypt(&plaintext)?;
+        if let Some(path) = output_path {
+            std::fs::write(path, ciphertext)?;
+            if !output::is_quiet() {
+                eprintln!(
+                    "Exported {count} transactions to {} ({})",
+                    path.display(),
+                    encryption.extension()
+                );
+            }
+        } else {
+            std::io::Write::write_all(&mut std::io::stdout(), &ciphertext)?;
+        }
+    } else if let Some(path) = output_path {
+        let file = output_file(format, path)?;
+        let mut out = BufWriter::new(File::create(&file)?);
+        let count =
+            export::export(connection_pool, exporter.as_mut(), since, until, &mut out).await?;
+        if !output::is_quiet() {
+            eprintln!("Exported {count} transactions to {}", file.display());
+        }
+        #[cfg(feature = "beancount")]
+        if file != path {
+            for created in crate::export::beancount::create_layout(path)? {
+                if !output::is_quiet() {
+                    eprintln!("Created {}", created.display());
+                }
+            }
+        }
+    } else {
+        let mut out = BufWriter::new(std::io::stdout());
+        export::export(connection_pool, exporter.as_mut(), since, until, &mut out).await?;
+    }
+
+    Ok(())
+}
+
+/// Print a unified diff between the existing `output_path` and what exporting
+/// transactions created between `since` and `until` in `format` would write
+/// there now
+///
+/// # Errors
+/// Will return errors if the format is unknown or not text, the data cannot be
+/// read or the output file cannot be read.
+#[allow(clippy::too_many_arguments)]
+pub async fn export_diff(
+    connection_pool: DatabasePool,
+    format: &str,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    output_path: &Path,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+    accounts: &[String],
+    calendar: &Calendar,
+    filters: &Filters,
+    mask_account_numbers: bool,
+) -> Result<(), Error> {
+    if format == ANONYMISED {
+        return Err(Error::Error(
+            "The anonymised export is a database file and can't be diffed".into(),
+        ));
+    }
+
+    let mut exporter = create_exporter(format, timezone, nicknames, accounts)?;
+    exporter.set_calendar(calendar);
+    exporter.set_mask_account_numbers(mask_account_numbers);
+    if filters.beancount {
+        exporter.set_min_amount(connection_pool.min_amount());
+    }
+
+    let mut regenerated = Vec::new();
+    export::export(
+        connection_pool,
+        exporter.as_mut(),
+        since,
+        until,
+        &mut regenerated,
+    )
+    .await?;
+    let regenerated = String::from_utf8(regenerated)
+        .map_err(|_| Error::Error(format!("The {format} export isn't text")))?;
+    let output_path = output_file(format, output_path)?;
+    let existing = if output_path.exists() {
+        std::fs::read_to_string(&output_path)?
+    } else {
+        String::new()
+    };
+
+    let name = output_path.display().to_string();
+    if existing == regenerated {
+        if !output::is_quiet() {
+            eprintln!("No changes to {name}");
+        }
+        return Ok(());
+    }
+
+    let diff = TextDiff::from_lines(&existing, &regenerated);
+    let unified = diff
+        .unified_diff()
+        .context_radius(3)
+        .header(&name, &format!("{name} (regenerated)"))
+        .to_string();
+    for line in unified.lines() {
+        let line = match line.chars().next() {
+            Some('+') if !line.starts_with("+++") => line.green(),
+            Some('-') if !line.starts_with("---") => line.red(),
+            Some('@') => line.cyan(),
+            _ => line.normal(),
+        };
+        println!("{line}");
+    }
+    if !output::is_quiet() {
+        let (mut added, mut removed) = (0, 0);
+        for change in diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => added += 1,
+                ChangeTag::Delete => removed += 1,
+                ChangeTag::Equal => (),
+            }
+        }
+        eprintln!("{added} lines added, {removed} removed. Run without --diff to write {name}");
+    }
+
+    Ok(())
+}
+
+// An exporter for `format`, with the annotations in `beancount.yaml` and any
+// accounts chosen on the command line in place of its `include_accounts`
+pub(crate) fn create_exporter(
+    format: &str,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+    accounts: &[String],
+) -> Result<Box<dyn Exporter>, Error> {
+    let mut exporter = Registry::default().create(format)?;
+    exporter.set_timezone(timezone);
+    exporter.set_nicknames(nicknames);
+    let mut annotations = Annotations::from_file(Path::new(ANNOTATIONS_FILE))?;
+    if !accounts.is_empty() {
+        annotations.include_accounts = accounts.to_vec();
+    }
+    exporter.set_annotations(&annotations);
+
+    Ok(exporter)
+}
+
+// The file to export to. A directory holds a beancount ledger layout, with
+// the export in its generated file.
+fn output_file(format: &str, path: &Path) -> Result<PathBuf, Error> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    #[cfg(feature = "beancount")]
+    if format == "beancount" {
+        return Ok(path.join(crate::export::beancount::GENERATED));
+    }
+    #[cfg(not(feature = "beancount"))]
+    let _ = format;
+
+    Err(Error::Error(format!(
+        "{} is a directory. Only beancount can be exported to a directory",
+        path.display()
+    )))
+}
+
+// Write an anonymised copy of the whole database for bug reports
+async fn export_anonymised(
+    connection_pool: &DatabasePool,
+    output_path: Option<&Path>,
+    encryption: Option<&Encryption>,
+) -> Result<(), Error> {
+    let Some(path) = output_path else {
+        return Err(Error::Error(
+            "The anonymised export is a database file and needs --output".into(),
+        ));
+    };
+
+    let summary = if let Some(encryption) = encryption {
+        let dir = temp_dir::TempDir::new()?;
+        let plaintext = dir.child("anonymised.sqlite");
+        let summary = anonymise(connection_pool, &plaintext).await?;
+        std::fs::write(path, encryption.encrypt(&std::fs::read(&plaintext)?)?)?;
+        summary
+    } else {
+        anonymise(connection_pool, path).await?
+    };
+    if !output::is_quiet() {
+        eprintln!(
+            "Anonymised {} accounts, {} merchants and {} transactions into {}",
+            summary.accounts,
+            summary.merchants,
+            summary.transactions,
+            path.display()
+        );
+    }
+
+    Ok(())
+}
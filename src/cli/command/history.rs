@@ -0,0 +1,64 @@
+//! Sync history
+//!
+//! This command lists previous update runs from the audit log, making gaps in
+//! the synced data easier to spot.
+
+use chrono_tz::Tz;
+
+use crate::{
+    cli::output,
+    error::AppErrors as Error,
+    model::{sync_run::SyncRun, DatabasePool},
+    timezone::to_local,
+};
+
+/// List the most recent update runs
+///
+/// # Errors
+/// Will return errors if the runs cannot be read from the database.
+pub async fn history(connection_pool: DatabasePool, limit: i64, timezone: Tz) -> Result<(), Error> {
+    let runs = connection_pool.sync_runs().read_runs(limit).await?;
+
+    if !output::is_quiet() {
+        print_runs(&runs, timezone);
+    }
+
+    Ok(())
+}
+
+fn print_runs(runs: &[SyncRun], timezone: Tz) {
+    println!(
+        "{:>5} {:<19} {:>8} {:<23} {:>4} {:>8} {:>8} {:>8}  STATUS",
+        "RUN", "STARTED", "SECS", "RANGE", "ACCS", "INSERTED", "UPDATED", "SKIPPED"
+    );
+    println!("{}", "-".repeat(100));
+
+    for run in runs {
+        let secs = run.finished.map_or(String::from("-"), |finished| {
+            (finished - run.started).num_seconds().to_string()
+        });
+        let range = format!(
+            "{} to {}",
+            run.range_start.format("%Y-%m-%d"),
+            run.range_end.format("%Y-%m-%d")
+        );
+        let status = match (&run.error, run.finished) {
+            (Some(e), _) => format!("failed: {e}"),
+            (None, Some(_)) => "ok".to_string(),
+            (None, None) => "incomplete".to_string(),
+        };
+
+        println!(
+            "{:>5} {:<19} {:>8} {:<23} {:>4} {:>8} {:>8} {:>8}  {}",
+            run.id,
+            to_local(run.started, timezone).format("%Y-%m-%d %H:%M:%S"),
+            secs,
+            range,
+            run.accounts,
+            run.inserted,
+            run.updated,
+            run.skipped,
+            status
+        );
+    }
+}
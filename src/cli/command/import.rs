@@ -0,0 +1,105 @@
+//! Import a Monzo CSV export
+//!
+//! This command stores the rows of a CSV export from the Monzo app that were
+//! never synced as transactions of an account. Rows that look like a synced
+//! transaction are resolved by the configured policy, or by asking.
+
+use std::path::Path;
+
+use chrono_tz::Tz;
+use colored::Colorize;
+use console::Term;
+use dialoguer::Select;
+
+use crate::{
+    cli::output,
+    configuration::ConflictPolicy,
+    currency,
+    engine::{Conflict, CsvImport, Resolution},
+    error::AppErrors as Error,
+    model::DatabasePool,
+};
+
+/// Import the CSV export at `csv` into `account_id`, resolving conflicts by
+/// `policy`
+///
+/// # Errors
+/// Will return an error if the account doesn't exist, the export can't be
+/// read, or a conflict needs asking about outside a terminal.
+pub async fn import(
+    pool: DatabasePool,
+    csv: &Path,
+    account_id: &str,
+    policy: ConflictPolicy,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let accounts = pool.accounts().read_accounts().await?;
+    if !accounts.iter().any(|account| account.id == account_id) {
+        return Err(Error::Error(format!("No account with id '{account_id}'")));
+    }
+    let file = std::fs::File::open(csv)
+        .map_err(|e| Error::Error(format!("Can't open '{}': {e}", csv.display())))?;
+
+    let import = CsvImport::run(pool, file, account_id, timezone, |conflict| {
+        resolve(conflict, policy)
+    })
+    .await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} transactions, {} already synced, {} conflicts resolved",
+            "Imported:".green(),
+            import.imported.len(),
+            import.synced,
+            import.resolved.len()
+        );
+        for (conflict, resolution) in &import.resolved {
+            println!("  {} {resolution}", conflict.row.id);
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve(conflict: &Conflict, policy: ConflictPolicy) -> Result<Resolution, Error> {
+    match policy {
+        ConflictPolicy::KeepApi => return Ok(Resolution::KeepApi),
+        ConflictPolicy::KeepCsv => return Ok(Resolution::KeepCsv),
+        ConflictPolicy::KeepBoth => return Ok(Resolution::KeepBoth),
+        ConflictPolicy::Ask => {}
+    }
+    if !Term::stderr().is_term() {
+        return Err(Error::Error(format!(
+            "CSV row {} looks like transaction {}: pass --conflicts or set `[import] conflicts` to resolve it",
+            conflict.row.id, conflict.transaction.id
+        )));
+    }
+
+    let row = &conflict.row;
+    let tx = &conflict.transaction;
+    eprintln!(
+        "{}\n  CSV    {} {:<30} {:>12}\n  Synced {} {:<30} {:>12}",
+        "This row looks like a synced transaction:".bold(),
+        row.created.format("%Y-%m-%d %H:%M"),
+        row.name,
+        currency::display(row.amount, &row.currency)?,
+        conflict.stored.format("%Y-%m-%d %H:%M"),
+        tx.merchant_name.as_deref().unwrap_or(&tx.description),
+        currency::display(tx.amount, &tx.currency)?
+    );
+    let choice = Select::new()
+        .with_prompt("Keep")
+        .items(&[
+            "the synced transaction",
+            "the CSV's amount and date",
+            "both",
+        ])
+        .default(0)
+        .interact()?;
+
+    Ok(match choice {
+        0 => Resolution::KeepApi,
+        1 => Resolution::KeepCsv,
+        _ => Resolution::KeepBoth,
+    })
+}
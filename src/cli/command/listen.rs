@@ -0,0 +1,51 @@
+//! Receive transaction webhooks
+//!
+//! This command serves the [webhook receiver](crate::webhook) until Ctrl-C,
+//! over HTTPS if `[server]` has a certificate, storing each transaction Monzo
+//! delivers as it happens. The database is only
+//! locked while a delivery is stored, so other commands can be used in between.
+
+use colored::Colorize;
+
+use crate::{
+    cli::output,
+    configuration::Server,
+    error::AppErrors as Error,
+    model::DatabasePool,
+    server::{run, scheme},
+    webhook::{router, WebhookState},
+};
+
+/// Serve the webhook receiver on `address` until interrupted
+///
+/// # Errors
+/// Will return an error if the address can't be bound or the TLS files can't
+/// be read.
+pub async fn listen(
+    pool: DatabasePool,
+    address: &str,
+    secret: Option<String>,
+    database_path: &str,
+    settings: &Server,
+) -> Result<(), Error> {
+    if secret.is_none() {
+        eprintln!(
+            "{} no `[webhooks] secret` is set, so deliveries aren't checked",
+            "WARNING:".yellow()
+        );
+    }
+
+    let app = router(WebhookState {
+        pool,
+        secret,
+        database_path: Some(database_path.to_string()),
+    });
+    if !output::is_quiet() {
+        println!(
+            "Listening for webhooks on {}://{address}/webhook",
+            scheme(settings)
+        );
+    }
+
+    run(app, address, settings).await
+}
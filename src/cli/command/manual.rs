@@ -0,0 +1,130 @@
+//! Manual accounts
+//!
+//! These commands add accounts held outside Monzo, record their balances and
+//! list them.
+
+use chrono::NaiveDate;
+use colored::Colorize;
+
+use crate::{
+    cli::output,
+    currency,
+    error::AppErrors as Error,
+    model::{
+        manual::{ManualAccountKind, ManualValuation},
+        DatabasePool,
+    },
+};
+
+/// Add a manual account
+///
+/// # Errors
+/// Will return errors if the kind is unknown, or an account with the name exists.
+pub async fn manual_add_account(
+    connection_pool: DatabasePool,
+    name: &str,
+    kind: &str,
+    currency: &str,
+    institution: Option<&str>,
+) -> Result<(), Error> {
+    let kind: ManualAccountKind = kind
+        .parse()
+        .map_err(|_| Error::Error(format!("Unknown account kind '{kind}'")))?;
+    if currency::display(0, currency).is_err() {
+        return Err(Error::CurrencyNotFound(currency.to_string()));
+    }
+
+    connection_pool
+        .manual_accounts()
+        .add_account(name, kind, institution, currency)
+        .await?;
+
+    if !output::is_quiet() {
+        println!("{} {kind} account {name}", "Added".green());
+    }
+
+    Ok(())
+}
+
+/// Record the balance of a manual account. `amount` is the amount owed for a
+/// liability.
+///
+/// # Errors
+/// Will return errors if the account doesn't exist or the amount is invalid.
+pub async fn manual_add_valuation(
+    connection_pool: DatabasePool,
+    account: &str,
+    amount: &str,
+    date: NaiveDate,
+) -> Result<(), Error> {
+    let service = connection_pool.manual_accounts();
+    let Some(account) = service.get_account(account).await? else {
+        return Err(Error::Error(format!(
+            "No manual account named '{account}'. Add it with `manual add-account`"
+        )));
+    };
+
+    let amount = currency::parse(amount, &account.currency)?;
+    let balance = match account.kind {
+        ManualAccountKind::Asset => amount,
+        ManualAccountKind::Liability => -amount,
+    };
+    service
+        .add_valuation(&ManualValuation {
+            account_id: account.id,
+            date,
+            balance,
+        })
+        .await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} at {} on {date}",
+            "Valued".green(),
+            account.name,
+            currency::display(balance, &account.currency)?
+        );
+    }
+
+    Ok(())
+}
+
+/// List manual accounts with their latest valuation
+///
+/// # Errors
+/// Will return errors if the accounts cannot be read from the database.
+pub async fn manual_list(connection_pool: DatabasePool) -> Result<(), Error> {
+    let service = connection_pool.manual_accounts();
+    let accounts = service.read_accounts().await?;
+    let latest = service.latest_valuations().await?;
+
+    if output::is_quiet() {
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:<9} {:<16} {:>14} {:<10}",
+        "NAME", "KIND", "INSTITUTION", "BALANCE", "VALUED"
+    );
+    println!("{}", "-".repeat(73));
+    for account in &accounts {
+        let valuation = latest.iter().find(|v| v.account_id == account.id);
+        let (balance, date) = match valuation {
+            Some(v) => (
+                currency::display(v.balance, &account.currency)?,
+                v.date.to_string(),
+            ),
+            None => ("-".to_string(), "never".to_string()),
+        };
+        println!(
+            "{:<20} {:<9} {:<16} {:>14} {:<10}",
+            account.name,
+            account.kind,
+            account.institution.as_deref().unwrap_or(""),
+            balance,
+            date
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! Merchants
+//!
+//! `fetch-logos` caches the logos of merchants seen in transactions as
+//! `<dir>/<merchant id>.<ext>`. The map report shows cached logos in place of
+//! fetching them from Monzo each time it is opened.
+
+use std::path::Path;
+
+use colored::Colorize;
+
+use crate::{cli::output, engine::fetch_logos, error::AppErrors as Error, model::DatabasePool};
+
+/// Download the merchant logos that aren't cached into `dir`
+///
+/// # Errors
+/// Will return errors if the database can't be read, a logo can't be fetched
+/// or it can't be written.
+pub async fn merchants_fetch_logos(connection_pool: DatabasePool, dir: &Path) -> Result<(), Error> {
+    let dir = std::path::absolute(dir)?;
+    let downloads = fetch_logos(&connection_pool, &dir).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} logos to {}, {} already cached",
+            "Downloaded".green(),
+            downloads.downloaded,
+            dir.display(),
+            downloads.existing
+        );
+    }
+
+    Ok(())
+}
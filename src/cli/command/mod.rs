@@ -1,9 +1,89 @@
+pub mod accounts;
+pub mod alerts;
+pub mod attachments;
+#[cfg(feature = "auth-server")]
 pub mod auth;
+pub mod backup;
 pub mod balances;
+#[cfg(feature = "beancount")]
+pub mod beancount;
+#[cfg(feature = "beancount")]
+pub mod bq;
+pub mod budget;
+pub mod categories;
+pub mod compare;
+pub mod db;
+#[cfg(feature = "demo")]
+pub mod demo;
+pub mod digest;
+pub mod export;
+pub mod history;
+pub mod import;
+#[cfg(feature = "server")]
+pub mod listen;
+pub mod manual;
+pub mod merchants;
+pub mod networth;
+pub mod pots;
+pub mod project;
+pub mod query;
+pub mod reconcile;
+pub mod report;
 pub mod reset;
+#[cfg(feature = "server")]
+pub mod serve;
+pub mod service;
+pub mod statement;
+pub mod today;
+pub mod transactions;
 pub mod update;
+pub mod verify;
+pub mod watch;
 
+pub use accounts::accounts;
+pub use alerts::{alerts_check, alerts_notify};
+pub use attachments::attachments_download;
+#[cfg(feature = "auth-server")]
 pub use auth::auth;
+pub use backup::{backup, restore};
 pub use balances::balances;
+#[cfg(feature = "beancount")]
+pub use beancount::{beancount_ledger, beancount_sync_back};
+#[cfg(feature = "beancount")]
+pub use bq::bq;
+pub use budget::{budget_envelopes, budget_feed_alerts, budget_status};
+pub use categories::categories_audit;
+pub use compare::compare;
+#[cfg(feature = "postgres")]
+pub use db::db_migrate_to;
+pub use db::{db_classify, db_prune, db_seed};
+#[cfg(feature = "demo")]
+pub use demo::demo_seed;
+pub use digest::digest;
+pub use export::{export, export_diff};
+pub use history::history;
+pub use import::import;
+#[cfg(feature = "server")]
+pub use listen::listen;
+pub use manual::{manual_add_account, manual_add_valuation, manual_list};
+pub use merchants::merchants_fetch_logos;
+pub use networth::networth;
+pub use pots::pots_sweep;
+pub use project::project;
+pub use query::query;
+pub use reconcile::reconcile;
+pub use report::{
+    report, report_drift, report_heatmap, report_people, report_run, report_savings, report_trips,
+};
 pub use reset::reset;
+#[cfg(feature = "server")]
+pub use serve::serve;
+pub use service::{service_install, service_status, service_uninstall};
+pub use statement::statement;
+pub use today::today;
+pub use transactions::{
+    card_events_list, transactions_categorise, transactions_list, transactions_set,
+};
 pub use update::update;
+pub use verify::verify;
+pub use watch::watch;
@@ -0,0 +1,81 @@
+//! Net worth
+//!
+//! This command adds the latest stored Monzo balances to the latest valuation
+//! of each manual account, with totals per currency and, if a base currency is
+//! configured, converted to it.
+
+use crate::{
+    cli::output,
+    client::Monzo,
+    currency,
+    engine::{NetWorth, Reporter},
+    error::AppErrors as Error,
+    model::DatabasePool,
+};
+
+/// Print net worth
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn networth(
+    connection_pool: DatabasePool,
+    monzo: Monzo,
+    base_currency: Option<&str>,
+) -> Result<(), Error> {
+    let reporter = Reporter::new(connection_pool, monzo);
+    let net_worth = reporter.net_worth().await?;
+
+    if !output::is_quiet() {
+        let converted = match base_currency {
+            Some(base) if net_worth.totals().keys().any(|code| code != base) => {
+                let rates = reporter.fx_rates(base).await?;
+                Some((base, net_worth.total_in(base, &rates)))
+            }
+            _ => None,
+        };
+        print_net_worth(&net_worth, converted)?;
+    }
+
+    Ok(())
+}
+
+fn print_net_worth(
+    net_worth: &NetWorth,
+    converted: Option<(&str, Result<i64, Error>)>,
+) -> Result<(), Error> {
+    println!("{:>44}", "NET WORTH");
+    println!("--------------------------------------------");
+
+    match &net_worth.balances {
+        Some(report) => {
+            for (code, total) in report.totals() {
+                println!(
+                    "{:<28}: {:>14}",
+                    format!("monzo (as of {})", report.taken.format("%Y-%m-%d")),
+                    currency::display(total, &code)?
+                );
+            }
+        }
+        None => println!("{:<28}: {:>14}", "monzo", "not fetched"),
+    }
+    for (account, valuation) in &net_worth.manual {
+        println!(
+            "{:<28}: {:>14}",
+            format!("{} ({})", account.name.to_lowercase(), valuation.date),
+            currency::display(valuation.balance, &account.currency)?
+        );
+    }
+
+    println!("--------------------------------------------");
+    for (code, total) in net_worth.totals() {
+        println!("Total {code}: {:>22}", currency::display(total, &code)?);
+    }
+    if let Some((base, total)) = converted {
+        match total {
+            Ok(total) => println!("Total in {base}: {:>19}", currency::display(total, base)?),
+            Err(e) => println!("Total in {base}: n/a ({e})"),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! Pot automations
+//!
+//! `pots sweep` moves each account's balance above its threshold into a pot,
+//! by the rules under `[[pots.sweeps]]`, once a month from the rule's day.
+//! `watch` runs the same sweep after each sync.
+
+use chrono::NaiveDate;
+
+use crate::{
+    cli::output,
+    currency,
+    engine::{Sweep, Sweeper},
+    error::AppErrors as Error,
+};
+
+/// Sweep balances into pots, or only show the sweeps if `dry_run`
+///
+/// # Errors
+/// Will return errors if the balances can't be fetched, a rule names an
+/// account or pot that doesn't exist, or a deposit fails.
+pub async fn pots_sweep(sweeper: &Sweeper, today: NaiveDate, dry_run: bool) -> Result<(), Error> {
+    let sweeps = sweeper.run(today, dry_run).await?;
+
+    if !output::is_quiet() {
+        if sweeps.is_empty() {
+            println!("Nothing to sweep");
+        }
+        for sweep in &sweeps {
+            let verb = if dry_run { "Would sweep" } else { "Swept" };
+            println!("{verb} {}", describe(sweep)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// A sweep as `£220.50 into Savings`
+///
+/// # Errors
+/// Will return an error if the sweep's currency is unknown.
+pub fn describe(sweep: &Sweep) -> Result<String, Error> {
+    Ok(format!(
+        "{} into {}",
+        currency::display(sweep.amount, &sweep.currency)?,
+        sweep.pot_name
+    ))
+}
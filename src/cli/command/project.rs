@@ -0,0 +1,105 @@
+//! Balance projection
+//!
+//! This command estimates each account's balance at the end of the month from
+//! its latest stored balance, the bills still due and the usual day-to-day
+//! spending, and what's safe to spend with the bills still paid.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+
+use crate::{
+    cli::output,
+    currency,
+    engine::{self, Projection},
+    error::AppErrors as Error,
+    locale,
+    model::{account::display_name, DatabasePool},
+};
+
+/// Print end of month projections, averaging spending over the `window_days`
+/// before `today`
+///
+/// # Errors
+/// Will return errors if the database cannot be read or no balances have been
+/// stored.
+pub async fn project(
+    connection_pool: DatabasePool,
+    today: NaiveDate,
+    window_days: u64,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let projections = engine::project(connection_pool, today, window_days, timezone).await?;
+
+    if !output::is_quiet() {
+        for (i, projection) in projections.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            print_projection(projection, nicknames)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_projection(
+    projection: &Projection,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let code = &projection.currency;
+    let name = display_name(nicknames, &projection.account_id, &projection.account_name);
+
+    println!(
+        "{:<30}{:>14}",
+        format!(
+            "{name} to {}",
+            locale::current().day_month(projection.month_end)
+        ),
+        "AMOUNT"
+    );
+    println!("{}", "-".repeat(44));
+    println!(
+        "{:<30}{:>14}",
+        "Balance now",
+        currency::display(projection.balance, code)?
+    );
+    for bill in &projection.bills {
+        println!(
+            "{:<30}{:>14}",
+            format!(
+                "  {} due {}",
+                bill.payee,
+                locale::current().day_month(bill.due)
+            ),
+            currency::display(-bill.amount, code)?
+        );
+    }
+    println!(
+        "{:<30}{:>14}",
+        format!(
+            "Spending {}/day x {} days",
+            currency::display(projection.daily_spend, code)?,
+            projection.days_left()
+        ),
+        currency::display(-projection.discretionary(), code)?
+    );
+    println!("{}", "-".repeat(44));
+    println!(
+        "{:<30}{:>14}",
+        "End of month",
+        currency::display(projection.end_of_month(), code)?
+    );
+    println!(
+        "{:<30}{:>14}",
+        format!(
+            "Safe to spend ({}/day)",
+            currency::display(projection.daily_allowance(), code)?
+        ),
+        currency::display(projection.safe_to_spend(), code)?
+    );
+
+    Ok(())
+}
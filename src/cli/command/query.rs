@@ -0,0 +1,90 @@
+//! Ad hoc queries
+//!
+//! This command runs read-only SQL against the database and prints the result
+//! as a table, whose columns and order can be chosen with `--columns` and
+//! `--sort`, or as CSV. The reporting views (`monthly_category_totals`,
+//! `merchant_totals` and `daily_balances`) are a good place to start.
+
+use std::fmt::Write;
+
+use crate::{
+    cli::{
+        output::{self, Cell, Column, Table},
+        QueryFormat,
+    },
+    error::AppErrors as Error,
+    locale,
+    model::{query::QueryResult, DatabasePool},
+};
+
+/// Run a read-only query and print the result
+///
+/// # Errors
+/// Will return errors if the SQL is invalid or tries to write.
+pub async fn query(
+    connection_pool: DatabasePool,
+    sql: &str,
+    format: QueryFormat,
+) -> Result<(), Error> {
+    let result = connection_pool.query_read_only(sql).await?;
+
+    if !output::is_quiet() {
+        match format {
+            QueryFormat::Table => output::page(&table(&result)?),
+            QueryFormat::Csv => print_csv(&result)?,
+        }
+    }
+
+    Ok(())
+}
+
+// Numbers sort as numbers, everything else as text
+fn table(result: &QueryResult) -> Result<String, Error> {
+    let mut table = Table::new(result.columns.iter().map(|c| Column::new(c)).collect());
+    for row in &result.rows {
+        table.push(
+            row.iter()
+                .map(|v| {
+                    let text = v.as_deref().unwrap_or("");
+                    match text.parse::<f64>() {
+                        Ok(number) => Cell::number(text.to_string(), number),
+                        Err(_) => text.into(),
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let mut text = table.render()?;
+    let _ = writeln!(text, "({} rows)", result.rows.len());
+
+    Ok(text)
+}
+
+// Numbers are written with the locale's decimal point. Where that's a comma,
+// fields are separated by semicolons, as spreadsheets in those locales expect.
+fn print_csv(result: &QueryResult) -> Result<(), Error> {
+    let locale = locale::current();
+    let delimiter = if locale.decimal_point() == "," {
+        b';'
+    } else {
+        b','
+    };
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(std::io::stdout());
+    writer
+        .write_record(&result.columns)
+        .map_err(|e| Error::Error(e.to_string()))?;
+    for row in &result.rows {
+        writer
+            .write_record(row.iter().map(|v| match v.as_deref() {
+                Some(v) if v.parse::<f64>().is_ok() => locale.decimal(v),
+                v => v.unwrap_or("").to_string(),
+            }))
+            .map_err(|e| Error::Error(e.to_string()))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Reconcile a Monzo CSV export
+//!
+//! This command lists the differences between a CSV export from the Monzo
+//! app and the synced transactions, and fails if there are any, so it can be
+//! run after an export to check the sync hasn't missed anything.
+
+use colored::Colorize;
+
+use crate::{cli::output, currency, engine::Reconciliation, error::AppErrors as Error};
+
+/// Print the differences found by a reconciliation
+///
+/// # Errors
+/// Will return an error if there are differences or a currency is unknown.
+pub fn reconcile(reconciliation: &Reconciliation) -> Result<(), Error> {
+    if !output::is_quiet() {
+        print_reconciliation(reconciliation)?;
+    }
+
+    if reconciliation.is_clean() {
+        Ok(())
+    } else {
+        Err(Error::Error(format!(
+            "{} difference(s) between the CSV export and the database",
+            reconciliation.differences()
+        )))
+    }
+}
+
+fn print_reconciliation(reconciliation: &Reconciliation) -> Result<(), Error> {
+    if reconciliation.is_clean() {
+        println!(
+            "{} all {} transactions match",
+            "Reconciled:".green(),
+            reconciliation.compared
+        );
+        return Ok(());
+    }
+
+    let mut missing = Vec::new();
+    for tx in &reconciliation.missing {
+        missing.push(format!(
+            "{} {} {:<30} {:>12}",
+            tx.created.format("%Y-%m-%d %H:%M"),
+            tx.id,
+            tx.name,
+            currency::display(tx.amount, &tx.currency)?
+        ));
+    }
+    print_section(
+        "In the export but never synced (run `update --all`)",
+        &missing,
+    );
+
+    let mut mismatches = Vec::new();
+    for mismatch in &reconciliation.amount_mismatches {
+        let tx = &mismatch.transaction;
+        mismatches.push(format!(
+            "{} {} {:<30} export {} stored {}",
+            tx.created.format("%Y-%m-%d %H:%M"),
+            tx.id,
+            tx.name,
+            currency::display(tx.amount, &tx.currency)?,
+            currency::display(mismatch.stored, &tx.currency)?
+        ));
+    }
+    print_section("Amounts differ", &mismatches);
+
+    let drift: Vec<String> = reconciliation
+        .date_drift
+        .iter()
+        .map(|drift| {
+            format!(
+                "{} {:<30} export {} stored {}",
+                drift.transaction.id,
+                drift.transaction.name,
+                drift.transaction.created.format("%Y-%m-%d %H:%M:%S"),
+                drift.stored.format("%Y-%m-%d %H:%M:%S")
+            )
+        })
+        .collect();
+    print_section("Dates differ", &drift);
+
+    let unexpected: Vec<String> = reconciliation
+        .unexpected
+        .iter()
+        .map(|(id, created)| format!("{} {id}", created.format("%Y-%m-%d %H:%M")))
+        .collect();
+    print_section("Stored but not in the export", &unexpected);
+
+    Ok(())
+}
+
+// Print a titled list, or nothing if it's empty
+fn print_section(title: &str, lines: &[String]) {
+    if lines.is_empty() {
+        return;
+    }
+    println!("{}", title.bold());
+    for line in lines {
+        println!("  {line}");
+    }
+}
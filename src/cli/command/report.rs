@@ -0,0 +1,618 @@
+//! Reports
+//!
+//! The household report combines the profiles of several people, printing
+//! spending by category with a column per person and a household total. It can
+//! also write a shared beancount ledger in which each person has their own
+//! accounts.
+//!
+//! The people report shows money sent to and received from each person paid
+//! over Monzo.
+//!
+//! The savings report shows how much went into savings pots each month against
+//! income, as a savings rate.
+//!
+//! The trips report groups spending abroad into trips and can tag each trip's
+//! transactions.
+//!
+//! The drift report lists transactions that settled for a different amount
+//! than they were pending for, with the difference.
+//!
+//! The heatmap shows spending by weekday and hour, shaded from the quietest
+//! hour to the busiest, and can also draw it as a PNG image.
+//!
+//! `report run` prints a report defined under `[reports]` in the
+//! configuration, as a table, CSV or JSON.
+
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use chrono::{NaiveDateTime, Weekday};
+use chrono_tz::Tz;
+
+use crate::{
+    cli::output::{self, Cell, Column, Table},
+    configuration::{ReportFormat, Savings},
+    currency,
+    engine::{
+        find_trips, savings::savings_rate, CustomReport, Heatmap, Household,
+        HouseholdCategoryTotal, SavingsReport, Trip,
+    },
+    error::AppErrors as Error,
+    locale,
+    model::{
+        account::display_name, counterparty::PersonTotal, edit::TransactionEdit,
+        transaction::SettlementDrift, DatabasePool,
+    },
+    timezone::to_local,
+};
+
+/// Print category totals for the household between `since` and `until`,
+/// optionally writing a shared beancount ledger to `beancount`
+///
+/// # Errors
+/// Will return errors if a database cannot be read or the ledger cannot be
+/// written.
+pub async fn report(
+    household: &Household,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    beancount: Option<&Path>,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let totals = household.category_totals(since, until).await?;
+
+    if !output::is_quiet() {
+        let names: Vec<&str> = household
+            .profiles()
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        output::page(&totals_table(&names, &totals)?);
+    }
+
+    if let Some(path) = beancount {
+        write_ledger(household, since, until, path, timezone, nicknames).await?;
+    }
+
+    Ok(())
+}
+
+/// Print money sent to and received from each person between `since` and
+/// `until`
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn report_people(
+    pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Result<(), Error> {
+    let people = pool.counterparties().read_people(since, until).await?;
+
+    if !output::is_quiet() {
+        print_people(&people)?;
+    }
+
+    Ok(())
+}
+
+/// Print monthly savings against income between `since` and `until`
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn report_savings(
+    pool: DatabasePool,
+    settings: &Savings,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let report = SavingsReport::build(pool, settings, since, until, timezone).await?;
+
+    if !output::is_quiet() {
+        print_savings(&report)?;
+    }
+
+    Ok(())
+}
+
+fn print_savings(report: &SavingsReport) -> Result<(), Error> {
+    if report.months.is_empty() {
+        println!("No income or savings in this period");
+        return Ok(());
+    }
+
+    println!(
+        "{:<10}{:>14}{:>14}{:>8}",
+        "MONTH", "INCOME", "SAVED", "RATE"
+    );
+    println!("{}", "-".repeat(10 + 14 * 2 + 8));
+    for month in &report.months {
+        println!(
+            "{:<10}{:>14}{:>14}{:>8}",
+            month.month.format("%Y-%m"),
+            currency::display(month.income, &month.currency)?,
+            currency::display(month.saved, &month.currency)?,
+            rate(month.rate())
+        );
+    }
+    println!("{}", "-".repeat(10 + 14 * 2 + 8));
+    for (code, income, saved) in report.totals() {
+        println!(
+            "{:<10}{:>14}{:>14}{:>8}",
+            "TOTAL",
+            currency::display(income, &code)?,
+            currency::display(saved, &code)?,
+            rate(savings_rate(income, saved))
+        );
+    }
+
+    if !report.pots.is_empty() {
+        println!("\n{:<24}{:>14}", "POT", "SAVED");
+        for ((name, code), saved) in &report.pots {
+            println!("{name:<24}{:>14}", currency::display(*saved, code)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print spending abroad between `since` and `until` by trip, optionally
+/// tagging each trip's transactions
+///
+/// # Errors
+/// Will return errors if the database cannot be read or written.
+pub async fn report_trips(
+    pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    max_gap_days: i64,
+    tag: bool,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let trips = find_trips(pool.clone(), since, until, max_gap_days, timezone).await?;
+
+    if tag {
+        for trip in &trips {
+            let edit = TransactionEdit::new(None, vec![trip.tag()])?;
+            pool.edit_transactions(&trip.transaction_ids, &edit).await?;
+        }
+    }
+
+    if !output::is_quiet() {
+        print_trips(&trips)?;
+        if tag && !trips.is_empty() {
+            println!("\nTagged {} trips", trips.len());
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the transactions created between `since` and `until` that settled
+/// for a different amount than they were pending for
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn report_drift(
+    pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let drift = pool
+        .transactions()
+        .read_settlement_drift(since, until)
+        .await?;
+
+    if !output::is_quiet() {
+        if drift.is_empty() {
+            println!("No transactions settled for a different amount in this period");
+        } else {
+            output::page(&drift_table(&drift, timezone, nicknames)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print spending in `currency` between `since` and `until` by weekday and
+/// hour, optionally drawing it as a PNG image at `png`
+///
+/// # Errors
+/// Will return errors if the database cannot be read or the image cannot be
+/// written.
+pub async fn report_heatmap(
+    pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    currency: &str,
+    timezone: Tz,
+    png: Option<&Path>,
+) -> Result<(), Error> {
+    let heatmap = Heatmap::build(pool, since, until, currency, timezone).await?;
+
+    if let Some(path) = png {
+        write_heatmap_png(&heatmap, path)?;
+    }
+    if !output::is_quiet() {
+        output::page(&heatmap_table(&heatmap)?);
+        if let Some(path) = png {
+            println!("\nWrote {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn drift_table(
+    drift: &[SettlementDrift],
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<String, Error> {
+    let amount = |amount: i64, code: &str| -> Result<Cell, Error> {
+        Ok(Cell::number(
+            currency::display(amount, code)?,
+            amount as f64,
+        ))
+    };
+    let mut table = Table::new(vec![
+        Column::new("CREATED").alias("date"),
+        Column::new("SETTLED"),
+        Column::new("ACCOUNT"),
+        Column::new("DESCRIPTION").alias("merchant"),
+        Column::new("PENDING").right(),
+        Column::new("AMOUNT").right(),
+        Column::new("DIFFERENCE").right(),
+    ]);
+    let mut totals: BTreeMap<&str, i64> = BTreeMap::new();
+    for tx in drift {
+        table.push(vec![
+            locale::current()
+                .date(to_local(tx.created, timezone).date())
+                .into(),
+            locale::current()
+                .date(to_local(tx.settled, timezone).date())
+                .into(),
+            display_name(nicknames, &tx.account_id, &tx.account_name).into(),
+            tx.merchant_name
+                .as_deref()
+                .unwrap_or(&tx.description)
+                .into(),
+            amount(tx.pending_amount, &tx.currency)?,
+            amount(tx.amount, &tx.currency)?,
+            amount(tx.difference(), &tx.currency)?,
+        ]);
+        *totals.entry(&tx.currency).or_default() += tx.difference();
+    }
+
+    let mut text = table.render()?;
+    for (code, total) in totals {
+        let _ = writeln!(
+            text,
+            "Total difference in {code}: {}",
+            currency::display(total, code)?
+        );
+    }
+
+    Ok(text)
+}
+
+// Shades from no spending to the busiest hour
+const SHADES: [&str; 5] = ["  ", "░░", "▒▒", "▓▓", "██"];
+
+// Pixels per hour in the image, and its colours from no spending to the most
+const PNG_CELL: u32 = 24;
+const PNG_EMPTY: [u8; 3] = [0xf2, 0xf2, 0xf2];
+const PNG_FULL: [u8; 3] = [0xe6, 0x39, 0x46];
+
+fn heatmap_table(heatmap: &Heatmap) -> Result<String, Error> {
+    let max = heatmap.max();
+    let mut table = String::new();
+    let _ = write!(table, "{:<5}", "");
+    for hour in (0..24).step_by(3) {
+        let _ = write!(table, "{hour:<6}");
+    }
+    let _ = writeln!(table, "{:>12}", "TOTAL");
+
+    for (day, hours) in heatmap.spend.iter().enumerate() {
+        let weekday = Weekday::try_from(u8::try_from(day).unwrap_or_default())
+            .map_err(|e| Error::Error(e.to_string()))?;
+        let _ = write!(table, "{:<5}", locale::current().weekday(weekday));
+        for spend in hours {
+            let _ = write!(table, "{}", SHADES[shade(*spend, max, SHADES.len() - 1)]);
+        }
+        let _ = writeln!(
+            table,
+            "{:>12}",
+            currency::display(heatmap.day_total(day), &heatmap.currency)?
+        );
+    }
+
+    if let Some((day, hour)) = heatmap.busiest() {
+        let weekday = Weekday::try_from(u8::try_from(day).unwrap_or_default())
+            .map_err(|e| Error::Error(e.to_string()))?;
+        let _ = writeln!(
+            table,
+            "\nBusiest: {} {hour:02}:00-{:02}:00, {}",
+            locale::current().weekday(weekday),
+            (hour + 1) % 24,
+            currency::display(heatmap.max(), &heatmap.currency)?
+        );
+    } else {
+        let _ = writeln!(table, "\nNo spending in this period");
+    }
+
+    Ok(table)
+}
+
+// Draw a cell per hour, a row per weekday from Monday, without labels
+fn write_heatmap_png(heatmap: &Heatmap, path: &Path) -> Result<(), Error> {
+    let (width, height) = (24 * PNG_CELL, 7 * PNG_CELL);
+    let max = heatmap.max();
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let spend = heatmap.spend[(y / PNG_CELL) as usize][(x / PNG_CELL) as usize];
+            // a white pixel's gap around each cell
+            if x % PNG_CELL == 0 || y % PNG_CELL == 0 {
+                pixels.extend([0xff; 3]);
+                continue;
+            }
+            let level = shade(spend, max, 255);
+            for (empty, full) in PNG_EMPTY.iter().zip(PNG_FULL) {
+                let (empty, full) = (i64::from(*empty), i64::from(full));
+                let level = i64::try_from(level).unwrap_or_default();
+                let value = empty + (full - empty) * level / 255;
+                pixels.push(u8::try_from(value).unwrap_or_default());
+            }
+        }
+    }
+
+    let file = std::fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| Error::Error(format!("Can't write '{}': {e}", path.display())))?;
+    writer
+        .write_image_data(&pixels)
+        .map_err(|e| Error::Error(format!("Can't write '{}': {e}", path.display())))?;
+
+    Ok(())
+}
+
+// The level from 0 to `levels` of `spend` against the busiest hour. Any
+// spending at all gets at least the first level.
+fn shade(spend: i64, max: i64, levels: usize) -> usize {
+    if spend <= 0 || max <= 0 {
+        return 0;
+    }
+    let levels = i64::try_from(levels).unwrap_or(i64::MAX);
+    let level = (spend * levels + max - 1) / max;
+    usize::try_from(level.clamp(1, levels)).unwrap_or_default()
+}
+
+fn print_trips(trips: &[Trip]) -> Result<(), Error> {
+    if trips.is_empty() {
+        println!("No foreign currency spending in this period");
+        return Ok(());
+    }
+
+    for (i, trip) in trips.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let currencies: Vec<&str> = trip.currencies.iter().map(String::as_str).collect();
+        println!(
+            "{} to {} ({}, {} transactions)",
+            locale::current().date(trip.first),
+            locale::current().date(trip.last),
+            currencies.join(", "),
+            trip.transaction_ids.len()
+        );
+        println!("{}", "-".repeat(24 + 14 * 2));
+        for spend in &trip.spending {
+            println!(
+                "{:<24}{:>14}{:>14}",
+                spend.category_label,
+                currency::display(spend.local_amount, &spend.local_currency)?,
+                currency::display(spend.amount, &spend.currency)?
+            );
+        }
+        for (code, total) in trip.totals() {
+            println!(
+                "{:<24}{:>14}{:>14}",
+                "TOTAL",
+                "",
+                currency::display(total, code)?
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a report defined in the configuration
+///
+/// # Errors
+/// Will return errors if a currency is unknown or the output can't be
+/// written.
+pub fn report_run(report: &CustomReport, format: ReportFormat) -> Result<(), Error> {
+    if output::is_quiet() {
+        return Ok(());
+    }
+    match format {
+        ReportFormat::Table => output::page(&custom_table(report)?),
+        ReportFormat::Csv => print_custom_csv(report)?,
+        ReportFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+    }
+
+    Ok(())
+}
+
+fn custom_table(report: &CustomReport) -> Result<String, Error> {
+    let locale = locale::current();
+    let mut text = match &report.description {
+        Some(description) => format!("{description}\n"),
+        None => String::new(),
+    };
+    let _ = writeln!(
+        text,
+        "{} to {}",
+        locale.date(report.first),
+        locale.date(report.last)
+    );
+
+    let mut table = Table::new(vec![
+        Column::new("GROUP"),
+        Column::new("COUNT").right(),
+        Column::new("TOTAL").right(),
+    ]);
+    for row in &report.rows {
+        #[allow(clippy::cast_precision_loss)]
+        table.push(vec![
+            row.group.as_str().into(),
+            Cell::number(row.count.to_string(), row.count as f64),
+            Cell::number(
+                currency::display(row.total, &row.currency)?,
+                row.total as f64,
+            ),
+        ]);
+    }
+    for (code, total) in report.totals() {
+        #[allow(clippy::cast_precision_loss)]
+        table.push(vec![
+            "Total".into(),
+            "".into(),
+            Cell::number(currency::display(total, code)?, total as f64),
+        ]);
+    }
+    text.push_str(&table.render()?);
+
+    Ok(text)
+}
+
+fn print_custom_csv(report: &CustomReport) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    let mut write = |record: [&str; 4]| {
+        writer
+            .write_record(record)
+            .map_err(|e| Error::Error(e.to_string()))
+    };
+    write(["group", "currency", "count", "total"])?;
+    for row in &report.rows {
+        write([
+            &row.group,
+            &row.currency,
+            &row.count.to_string(),
+            &currency::decimal(row.total, &row.currency),
+        ])?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn rate(rate: Option<f64>) -> String {
+    rate.map_or_else(|| "-".to_string(), |r| format!("{r:.0}%"))
+}
+
+fn print_people(people: &[PersonTotal]) -> Result<(), Error> {
+    if people.is_empty() {
+        println!("No payments to or from people in this period");
+        return Ok(());
+    }
+
+    println!(
+        "{:<24}{:>14}{:>14}{:>14}{:>8}",
+        "NAME", "SENT", "RECEIVED", "NET", "COUNT"
+    );
+    println!("{}", "-".repeat(24 + 14 * 3 + 8));
+    for person in people {
+        println!(
+            "{:<24}{:>14}{:>14}{:>14}{:>8}",
+            person.name,
+            currency::display(person.sent, &person.currency)?,
+            currency::display(person.received, &person.currency)?,
+            currency::display(person.received - person.sent, &person.currency)?,
+            person.count
+        );
+    }
+
+    Ok(())
+}
+
+fn totals_table(names: &[&str], totals: &[HouseholdCategoryTotal]) -> Result<String, Error> {
+    let mut table = format!("{:<20}", "CATEGORY");
+    for name in names {
+        let _ = write!(table, "{:>14}", name.to_uppercase());
+    }
+    let _ = writeln!(table, "{:>14}", "TOTAL");
+    let _ = writeln!(table, "{}", "-".repeat(20 + 14 * (names.len() + 1)));
+
+    for total in totals {
+        let _ = write!(table, "{:<20}", total.category_label);
+        for amount in &total.by_profile {
+            let _ = write!(
+                table,
+                "{:>14}",
+                currency::display(*amount, &total.currency)?
+            );
+        }
+        let _ = writeln!(
+            table,
+            "{:>14}",
+            currency::display(total.total(), &total.currency)?
+        );
+    }
+
+    Ok(table)
+}
+
+#[cfg(feature = "beancount")]
+async fn write_ledger(
+    household: &Household,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    path: &Path,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    use std::{fs::File, io::BufWriter};
+
+    use crate::export::{
+        annotations::{Annotations, ANNOTATIONS_FILE},
+        beancount::BeancountExporter,
+        export_profiles, Exporter,
+    };
+
+    let mut exporter = BeancountExporter::default();
+    exporter.set_timezone(timezone);
+    exporter.set_nicknames(nicknames);
+    exporter.set_annotations(&Annotations::from_file(Path::new(ANNOTATIONS_FILE))?);
+    let mut out = BufWriter::new(File::create(path)?);
+    let count =
+        export_profiles(household.profiles(), &mut exporter, since, until, &mut out).await?;
+    if !output::is_quiet() {
+        eprintln!("Exported {count} transactions to {}", path.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "beancount"))]
+#[allow(clippy::unused_async)] // matches the beancount signature
+async fn write_ledger(
+    _household: &Household,
+    _since: NaiveDateTime,
+    _until: NaiveDateTime,
+    _path: &Path,
+    _timezone: Tz,
+    _nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    Err(Error::Error("Built without beancount support".into()))
+}
@@ -0,0 +1,46 @@
+//! Serve the local API
+//!
+//! This command serves the stored data read-only, as JSON and
+//! [GraphQL](crate::api::graphql), until Ctrl-C. Requests need the `[server]`
+//! bearer token, which is generated and saved to the configuration file the
+//! first time. It doesn't lock the database, so `update` and `watch` keep
+//! working while it runs.
+
+use axum::middleware;
+
+use crate::{
+    api::{router, ApiState},
+    cli::output,
+    configuration::{get_config, save_config, Server},
+    error::AppErrors as Error,
+    server::{generate_token, require_token, run, scheme},
+};
+
+/// Serve the API on `address` until interrupted
+///
+/// # Errors
+/// Will return an error if the address can't be bound, the TLS files can't
+/// be read, or a new token can't be saved.
+pub async fn serve(state: ApiState, address: &str, settings: &Server) -> Result<(), Error> {
+    let token = if let Some(token) = &settings.token {
+        token.clone()
+    } else {
+        let token = generate_token();
+        let mut config = get_config()?;
+        config.server.token = Some(token.clone());
+        save_config(&config)?;
+        // shown even when quiet, as it's needed to use the API
+        println!("Generated an API token and saved it to configuration.toml: {token}");
+        token
+    };
+
+    let app = router(state).layer(middleware::from_fn_with_state(token, require_token));
+    if !output::is_quiet() {
+        println!(
+            "Serving the API on {}://{address} (GraphQL on /graphql)",
+            scheme(settings)
+        );
+    }
+
+    run(app, address, settings).await
+}
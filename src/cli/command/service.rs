@@ -0,0 +1,73 @@
+//! Scheduled updates
+//!
+//! `service install` sets up a user-level systemd timer, or a launchd agent on
+//! macOS, that runs `update` from the current directory every `--interval`.
+//! `service uninstall` removes it and `service status` shows whether it's
+//! installed and when it runs next.
+
+use colored::Colorize;
+
+use crate::{
+    cli::output,
+    error::AppErrors as Error,
+    service::{parse_interval, UpdateService},
+};
+
+/// Install the update service
+///
+/// # Errors
+/// Will return errors if the interval is invalid, or the service can't be
+/// written or enabled.
+pub fn service_install(interval: &str) -> Result<(), Error> {
+    let seconds = parse_interval(interval)?;
+    let executable = std::env::current_exe()?;
+    let directory = std::env::current_dir()?;
+
+    let files = UpdateService::for_current_user()?.install(&executable, &directory, seconds)?;
+
+    if !output::is_quiet() {
+        for path in files {
+            println!("{} {}", "Wrote".green(), path.display());
+        }
+        println!("Updating every {interval} from {}", directory.display());
+    }
+
+    Ok(())
+}
+
+/// Remove the update service
+///
+/// # Errors
+/// Will return errors if the service can't be disabled or removed.
+pub fn service_uninstall() -> Result<(), Error> {
+    let files = UpdateService::for_current_user()?.uninstall()?;
+
+    if !output::is_quiet() {
+        if files.is_empty() {
+            println!("The service isn't installed");
+        }
+        for path in files {
+            println!("{} {}", "Removed".green(), path.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Print whether the update service is installed and its schedule
+///
+/// # Errors
+/// Will return errors if the service manager can't be run.
+pub fn service_status() -> Result<(), Error> {
+    let (installed, report) = UpdateService::for_current_user()?.status()?;
+
+    if !output::is_quiet() {
+        if installed {
+            println!("Installed\n\n{}", report.trim_end());
+        } else {
+            println!("Not installed. Run `service install` to update on a schedule");
+        }
+    }
+
+    Ok(())
+}
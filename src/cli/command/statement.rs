@@ -0,0 +1,118 @@
+//! Account statement
+//!
+//! This command writes a bank statement style document for one account and
+//! month: the opening balance, each transaction with the running balance, and
+//! the closing balance. CSV goes to stdout unless `--output` is given; a PDF
+//! is written to `statement-<account>-<YYYY-MM>.pdf` by default.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+
+use crate::{
+    cli::output,
+    error::AppErrors as Error,
+    export::statement::Statement,
+    model::{account::AccountForDB, DatabasePool},
+};
+
+/// Write the statement of `account` for the month starting `month`, as a PDF
+/// if `pdf` is set or else as CSV
+///
+/// # Errors
+/// Will return errors if the account can't be found, no balances are stored,
+/// or the output can't be written.
+#[allow(clippy::too_many_arguments)]
+pub async fn statement(
+    connection_pool: DatabasePool,
+    account: Option<&str>,
+    month: NaiveDate,
+    pdf: bool,
+    output_path: Option<&Path>,
+    mask_account_numbers: bool,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let accounts = connection_pool.accounts().read_accounts().await?;
+    let mut account = find_account(&accounts, account, nicknames)?.clone();
+    if mask_account_numbers {
+        account = account.masked();
+    }
+    let name = account.name(nicknames).to_string();
+    let statement = Statement::build(connection_pool, account, &name, month, timezone).await?;
+
+    let path = match output_path {
+        Some(path) => Some(path.to_path_buf()),
+        None if pdf => Some(PathBuf::from(format!(
+            "statement-{}-{}.pdf",
+            name.to_lowercase().replace(' ', "-"),
+            month.format("%Y-%m")
+        ))),
+        None => None,
+    };
+    let mut out: Box<dyn Write> = match &path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+    if pdf {
+        statement.write_pdf(&mut out)?;
+    } else {
+        statement.write_csv(&mut out)?;
+    }
+    out.flush()?;
+
+    if let Some(path) = path {
+        if !output::is_quiet() {
+            eprintln!(
+                "Wrote the {} statement for {} ({} transactions) to {}",
+                name,
+                month.format("%B %Y"),
+                statement.lines.len(),
+                path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// The account named by its id, type or nickname, or the only account if no
+// name is given
+fn find_account<'a>(
+    accounts: &'a [AccountForDB],
+    name: Option<&str>,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<&'a AccountForDB, Error> {
+    let matches: Vec<&AccountForDB> = accounts
+        .iter()
+        .filter(|a| {
+            name.is_none_or(|name| {
+                a.id == name
+                    || a.owner_type.eq_ignore_ascii_case(name)
+                    || a.name(nicknames).eq_ignore_ascii_case(name)
+            })
+        })
+        .collect();
+
+    match (matches.as_slice(), name) {
+        ([account], _) => Ok(account),
+        ([], Some(name)) => Err(Error::Error(format!("No account named '{name}'"))),
+        ([], None) => Err(Error::Error(
+            "No accounts stored: run `update` first".into(),
+        )),
+        (_, Some(name)) => Err(Error::Error(format!(
+            "'{name}' matches several accounts; give its id instead"
+        ))),
+        (_, None) => Err(Error::Error(
+            "There are several accounts; choose one with `--account`".into(),
+        )),
+    }
+}
@@ -0,0 +1,127 @@
+//! Safe to spend today
+//!
+//! This command prints one line with today's spending, what the monthly
+//! budgets leave for the rest of the day and the bills due soon, e.g.
+//! `£12.40 spent · £27.60 left of £40.00 · Netflix £10.99 tomorrow`, for a
+//! shell prompt or status bar. `--json` prints the same for widgets.
+
+use chrono::{Datelike, NaiveDate};
+use chrono_tz::Tz;
+
+use crate::{
+    cli::output,
+    currency,
+    engine::{BudgetPlan, Today},
+    error::AppErrors as Error,
+    locale,
+    model::DatabasePool,
+};
+
+/// Print today's spending, allowance and the bills due in the `days` after
+/// `date`
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn today(
+    connection_pool: DatabasePool,
+    plan: &BudgetPlan,
+    date: NaiveDate,
+    days: u64,
+    json: bool,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let today = Today::build(connection_pool, plan, date, days, timezone).await?;
+
+    if output::is_quiet() {
+        return Ok(());
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&today)?);
+    } else {
+        println!("{}", render(&today)?);
+    }
+
+    Ok(())
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// The summary on one line
+fn render(today: &Today) -> Result<String, Error> {
+    let code = &today.currency;
+    let mut parts = vec![format!("{} spent", currency::display(today.spent, code)?)];
+    if let Some(allowance) = &today.allowance {
+        parts.push(format!(
+            "{} left of {}",
+            currency::display(allowance.remaining(), code)?,
+            currency::display(allowance.allowance, code)?
+        ));
+    }
+    for bill in &today.bills {
+        parts.push(format!(
+            "{} {} {}",
+            bill.payee,
+            currency::display(bill.amount, code)?,
+            when(bill.due, today.date)
+        ));
+    }
+
+    Ok(parts.join(" · "))
+}
+
+// When `due` is, relative to `today`
+fn when(due: NaiveDate, today: NaiveDate) -> String {
+    match (due - today).num_days() {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        _ => locale::current().weekday(due.weekday()),
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Bill, DailyAllowance};
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn renders_one_line() {
+        // Arrange
+        let today = Today {
+            date: date("2024-06-14"),
+            currency: "GBP".to_string(),
+            spent: 1240,
+            allowance: Some(DailyAllowance {
+                currency: "GBP".to_string(),
+                allowance: 4000,
+                spent: 1240,
+            }),
+            bills: vec![
+                Bill {
+                    payee: "Netflix".to_string(),
+                    due: date("2024-06-15"),
+                    amount: 1099,
+                },
+                Bill {
+                    payee: "Gym".to_string(),
+                    due: date("2024-06-17"),
+                    amount: 3500,
+                },
+            ],
+        };
+
+        // Act
+        let line = render(&today).unwrap();
+
+        // Assert
+        assert_eq!(
+            line,
+            "£12.40 spent · £27.60 left of £40.00 · Netflix £10.99 tomorrow · Gym £35.00 Mon"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Stored transactions
+//!
+//! This command lists the transactions in the database, or with `--events` the
+//! zero-amount card events that are kept out of the transactions table. `set`
+//! changes the category or tags of every transaction matching a filter, and
+//! `categorise` asks the configured categoriser about uncategorised ones.
+//! Lists longer than the terminal are shown through the pager, with the
+//! columns and order chosen by `--columns` and `--sort`.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use chrono_tz::Tz;
+use colored::Colorize;
+
+use crate::{
+    categorise::AutoCategoriser,
+    cli::output::{self, Cell, Column, Table},
+    currency::display_decimal,
+    error::AppErrors as Error,
+    model::{
+        account::display_name, card_event::CardEvent, edit::TransactionEdit, filter::Filter,
+        transaction::ExportTransaction, transfer::TransferRules, DatabasePool,
+    },
+    timezone::to_local,
+};
+
+/// List up to `limit` transactions created between `since` and `until`,
+/// starting after the transaction with id `after`
+///
+/// # Errors
+/// Will return errors if the transactions cannot be read from the database.
+pub async fn transactions_list(
+    connection_pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    limit: i64,
+    after: Option<&str>,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let transactions = connection_pool
+        .transactions()
+        .read_export_page(since, until, after, limit)
+        .await?;
+
+    if !output::is_quiet() {
+        let mut table = transactions_table(&transactions, timezone, nicknames)?;
+        if let Some(last) = transactions.last() {
+            if i64::try_from(transactions.len()).is_ok_and(|n| n == limit) {
+                let _ = writeln!(
+                    table,
+                    "More transactions may follow: use --after {}",
+                    last.id
+                );
+            }
+        }
+        output::page(&table);
+    }
+
+    Ok(())
+}
+
+/// List the card events created between `since` and `until`
+///
+/// # Errors
+/// Will return errors if the events cannot be read from the database.
+pub async fn card_events_list(
+    connection_pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let events = connection_pool
+        .card_events()
+        .read_card_events(since, until)
+        .await?;
+
+    if !output::is_quiet() {
+        output::page(&events_table(&events, timezone, nicknames)?);
+    }
+
+    Ok(())
+}
+
+/// List the transactions matching `filter` and, if `apply` is set, make
+/// `edit` to all of them in one database transaction
+///
+/// # Errors
+/// Will return errors if the transactions cannot be read or written.
+pub async fn transactions_set(
+    connection_pool: DatabasePool,
+    filter: &Filter,
+    edit: &TransactionEdit,
+    apply: bool,
+    rules: &TransferRules,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    // stored dates compare as text, so the last date needs a four digit year
+    let last = NaiveDate::from_ymd_opt(9999, 12, 31)
+        .unwrap_or_default()
+        .and_time(NaiveTime::MIN);
+    let transactions: Vec<ExportTransaction> = connection_pool
+        .transactions()
+        .read_export_data(NaiveDateTime::default(), last)
+        .await?
+        .into_iter()
+        .filter(|tx| filter.matches(tx, timezone))
+        .collect();
+
+    if transactions.is_empty() {
+        if !output::is_quiet() {
+            println!("No transactions match");
+        }
+        return Ok(());
+    }
+
+    let mut changes = Vec::new();
+    if let Some(category) = &edit.category {
+        changes.push(format!("set category {category}"));
+    }
+    if !edit.tags.is_empty() {
+        changes.push(format!("tag {}", edit.tags.join(", ")));
+    }
+    let changes = changes.join(" and ");
+
+    if !apply {
+        if !output::is_quiet() {
+            output::page(&transactions_table(&transactions, timezone, nicknames)?);
+            println!(
+                "\n{} transactions match. Run again with --yes to {changes}",
+                transactions.len()
+            );
+        }
+        return Ok(());
+    }
+
+    let ids: Vec<String> = transactions.into_iter().map(|tx| tx.id).collect();
+    let summary = connection_pool.edit_transactions(&ids, edit).await?;
+    // category rules may make transactions transfers, or no longer
+    connection_pool.classify_transfers(rules).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} transactions to {changes}: {} recategorised, {} tags added",
+            "Edited".green(),
+            ids.len(),
+            summary.recategorised,
+            summary.tagged
+        );
+    }
+
+    Ok(())
+}
+
+/// Ask `categoriser` for the categories of the stored uncategorised
+/// transactions
+///
+/// # Errors
+/// Will return errors if the categoriser can't be reached or the database
+/// can't be written.
+pub async fn transactions_categorise(
+    connection_pool: DatabasePool,
+    categoriser: &AutoCategoriser,
+) -> Result<(), Error> {
+    let summary = categoriser.run(&connection_pool).await?;
+
+    if !output::is_quiet() {
+        println!(
+            "{} {} transactions, {} left alone, {} given unknown categories",
+            "Categorised".green(),
+            summary.categorised,
+            summary.unchanged,
+            summary.unknown
+        );
+    }
+
+    Ok(())
+}
+
+fn transactions_table(
+    transactions: &[ExportTransaction],
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<String, Error> {
+    let mut table = Table::new(vec![
+        Column::new("CREATED").alias("date"),
+        Column::new("ACCOUNT"),
+        Column::new("AMOUNT").right(),
+        Column::new("CCY").alias("currency"),
+        Column::new("CATEGORY"),
+        Column::new("DESCRIPTION").alias("merchant"),
+        Column::new("STATUS"),
+    ]);
+
+    for tx in transactions {
+        let description = tx.merchant_name.as_deref().unwrap_or(&tx.description);
+        let status = if tx.settled.is_some() {
+            "settled"
+        } else {
+            "pending"
+        };
+
+        #[allow(clippy::cast_precision_loss)]
+        let amount = Cell::number(display_decimal(tx.amount, &tx.currency), tx.amount as f64);
+        table.push(vec![
+            to_local(tx.created, timezone)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+                .into(),
+            display_name(nicknames, &tx.account_id, &tx.account_name).into(),
+            amount,
+            tx.currency.as_str().into(),
+            tx.category_label.as_str().into(),
+            description.into(),
+            status.into(),
+        ]);
+    }
+
+    table.render()
+}
+
+fn events_table(
+    events: &[CardEvent],
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<String, Error> {
+    let mut table = Table::new(vec![
+        Column::new("CREATED").alias("date"),
+        Column::new("ACCOUNT"),
+        Column::new("CATEGORY"),
+        Column::new("DESCRIPTION").alias("merchant"),
+    ]);
+
+    for event in events {
+        table.push(vec![
+            to_local(event.created, timezone)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+                .into(),
+            display_name(nicknames, &event.account_id, &event.account_name).into(),
+            event.category_id.as_str().into(),
+            event
+                .merchant_name
+                .as_deref()
+                .unwrap_or(&event.description)
+                .into(),
+        ]);
+    }
+
+    table.render()
+}
@@ -3,127 +3,298 @@
 //! This command will fetch transactions from Monzo. By default, it will fetch
 //! all transactions since the last. Flag `--all` can be used to reset the
 //! database and refetch all transactions.
+//!
+//! Ctrl-C stops the update after the current window has been saved. Flag
+//! `--resume` continues an interrupted or failed update from its checkpoint.
+//!
+//! The table of transactions ends with credits, debits and net for each
+//! account and currency, and totals for each currency. With `--format json` a
+//! summary of the run is printed instead, for scripts and monitoring.
+//!
+//! Flag `--profile-sync` times each phase of the sync, records the timings
+//! against the run and prints a breakdown, so slow phases can be reported.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, NaiveDateTime, Utc};
-use rusty_money::{iso, Money};
-use serde::Deserialize;
-use tracing_log::log::{error, info};
+use chrono_tz::Tz;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    client::Monzo,
-    date_ranges,
+    cli::{output, UpdateFormat},
+    configuration::PotDescriptions,
+    currency,
+    engine::SyncSummary,
+    engine::{SyncEngine, SyncEvent, SyncStats},
     error::AppErrors as Error,
     model::{
-        account::{AccountForDB, Service as AccountService, SqliteAccountService},
-        category::{Category, Service as CategoryService, SqliteCategoryService},
+        balance::{Balance, PotBalance},
         merchant::Merchant,
-        pot::{Pot, Service, SqlitePotService},
-        transaction::{
-            Service as TransactionService, SqliteTransactionService, TransactionResponse,
-        },
-        DatabasePool,
+        sync_run::SyncProfile,
+        transaction::TransactionResponse,
     },
 };
 
-/// Update transactions
-///
-/// This function will fetch transactions from Monzo between the given dates,
-/// print them to the console, and persist them to the database.
-///
-/// # Errors
-/// Will return errors if the transactions cannot be fetched or persisted.
-pub async fn update(
-    connection_pool: DatabasePool,
+/// The run summary printed by `--format json`
+#[derive(Serialize)]
+struct RunReport<'a> {
+    run_id: i64,
+    interrupted: bool,
     since: NaiveDateTime,
-    before: NaiveDateTime,
-) -> Result<(), Error> {
-    let (accounts, account_names) = get_accounts(connection_pool.clone()).await?;
-    persist_accounts(connection_pool.clone(), &accounts).await?;
-
-    let (pots, pot_names) = get_pots(connection_pool.clone(), &accounts).await?;
-    persist_pots(connection_pool.clone(), &pots).await?;
+    until: NaiveDateTime,
+    duration_seconds: f64,
+    totals: SyncStats,
+    accounts: Vec<AccountReport<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile_ms: Option<ProfileReport>,
+}
 
-    let txs_resp = get_sorted_transactions(&accounts, since, before).await?;
-    persist_categories(connection_pool.clone(), &txs_resp).await?;
-    persist_transactions(connection_pool.clone(), &txs_resp).await?;
+/// Phase timings in `--format json`, in milliseconds
+#[derive(Serialize)]
+struct ProfileReport {
+    fetch: u128,
+    dedupe: u128,
+    insert: u128,
+    store: u128,
+    classify: u128,
+}
 
-    print_transactions(&txs_resp, &account_names, &pot_names)?;
+impl From<&SyncProfile> for ProfileReport {
+    fn from(profile: &SyncProfile) -> Self {
+        Self {
+            fetch: profile.fetch.as_millis(),
+            dedupe: profile.dedupe.as_millis(),
+            insert: profile.insert.as_millis(),
+            store: profile.store.as_millis(),
+            classify: profile.classify.as_millis(),
+        }
+    }
+}
 
-    Ok(())
+#[derive(Serialize)]
+struct AccountReport<'a> {
+    id: &'a str,
+    name: &'a str,
+    #[serde(flatten)]
+    stats: SyncStats,
+    balance: Option<&'a Balance>,
+    pots: &'a [PotBalance],
 }
 
-// Get all accounts
-#[tracing::instrument(name = "get accounts")]
-async fn get_accounts(
-    connection_pool: DatabasePool,
-) -> Result<(Vec<AccountForDB>, HashMap<String, String>), Error> {
-    let monzo = Monzo::new()?;
-    let accounts = monzo.accounts().await?;
-    // convert account response to account for db
-    let accounts: Vec<AccountForDB> = accounts.into_iter().map(|account| account.into()).collect();
-    let account_names = monzo.account_description_from_id().await?;
-
-    Ok((accounts, account_names))
+/// Money in and out, in minor units
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Totals {
+    credits: i64,
+    debits: i64,
 }
 
-// Get all pots
-#[tracing::instrument(name = "get pots")]
-async fn get_pots(
-    connection_pool: DatabasePool,
-    accounts: &Vec<AccountForDB>,
-) -> Result<(Vec<Pot>, HashMap<String, String>), Error> {
-    let monzo = Monzo::new()?;
-    let pot_names = monzo.pot_description_from_id().await?;
-
-    let mut pots: Vec<Pot> = Vec::new();
-    for account in accounts {
-        let account_pots = monzo.pots(&account.id).await?;
-        for pot_resp in account_pots {
-            pots.push(Pot::from((pot_resp, account.owner_type.clone())));
+impl Totals {
+    fn add(&mut self, amount: i64) {
+        if amount >= 0 {
+            self.credits += amount;
+        } else {
+            self.debits += amount;
         }
     }
 
-    Ok((pots, pot_names))
+    fn net(self) -> i64 {
+        self.credits + self.debits
+    }
 }
 
-// Get all transactions sorted by date
-#[tracing::instrument(name = "get sorted transactions")]
-async fn get_sorted_transactions(
-    accounts: &Vec<AccountForDB>,
+/// Update transactions
+///
+/// This function will use `engine` to fetch transactions from Monzo between
+/// the given dates, print them to the console, and persist them to the
+/// database. If `resume` is set the dates are ignored and the last
+/// interrupted run is continued.
+///
+/// # Errors
+/// Will return errors if the transactions cannot be fetched or persisted, or
+/// if the update is interrupted.
+#[allow(clippy::too_many_arguments)]
+pub async fn update(
+    engine: SyncEngine,
     since: NaiveDateTime,
     before: NaiveDateTime,
-) -> Result<Vec<TransactionResponse>, Error> {
-    let monzo = Monzo::new()?;
-    let mut txs_resp: Vec<TransactionResponse> = Vec::new();
+    resume: bool,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+    pot_descriptions: &PotDescriptions,
+    format: UpdateFormat,
+) -> Result<(), Error> {
+    let started = Instant::now();
+    let (events_tx, events_rx) = mpsc::channel(64);
+    let renderer = tokio::spawn(render_events(events_rx));
+
+    let cancel = CancellationToken::new();
+    let ctrl_c = tokio::spawn(cancel_on_ctrl_c(cancel.clone()));
 
-    const DAYS: i64 = 30;
+    let engine = engine.with_events(events_tx).with_cancellation(cancel);
+    let result = if resume {
+        engine.resume().await
+    } else {
+        engine.sync(since, before).await
+    };
 
-    let date_ranges = date_ranges(since, before, DAYS);
+    // dropping the engine closes the channel so the renderer can finish
+    drop(engine);
+    ctrl_c.abort();
+    _ = renderer.await;
+    let summary = result?;
+
+    if !output::is_quiet() {
+        let mut account_names = summary.account_names.clone();
+        account_names.extend(nicknames.clone());
+        match format {
+            UpdateFormat::Table => {
+                print_transactions(
+                    &summary.transactions,
+                    &account_names,
+                    &summary.pot_names,
+                    pot_descriptions,
+                    timezone,
+                )?;
+                print_summary(&summary, started.elapsed());
+                if let Some(profile) = &summary.profile {
+                    print_profile(profile, summary.stats);
+                }
+            }
+            UpdateFormat::Json => {
+                let report = run_report(&summary, &account_names, started.elapsed());
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+        }
+    }
 
-    for account in accounts {
-        for (since, before) in date_ranges.clone() {
-            let transactions = monzo
-                .transactions(&account.id, &since, &before, None)
-                .await?;
+    if summary.interrupted {
+        return Err(Error::Interrupted);
+    }
 
-            info!("Fetched {} transactions", &transactions.len());
+    Ok(())
+}
 
-            for tx in transactions {
-                if tx.amount == 0 || tx.settled.is_none() {
-                    continue;
-                }
+// Cancel the sync on the first Ctrl-C so the current window is saved, and
+// exit immediately on the second.
+async fn cancel_on_ctrl_c(cancel: CancellationToken) {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        eprintln!("Interrupted: finishing the current window. Press Ctrl-C again to abort");
+        cancel.cancel();
+    }
+    if tokio::signal::ctrl_c().await.is_ok() {
+        std::process::exit(130);
+    }
+}
 
-                txs_resp.push(tx);
-            }
+// Render sync progress to stderr
+async fn render_events(mut events: mpsc::Receiver<SyncEvent>) {
+    while let Some(event) = events.recv().await {
+        if output::is_quiet() {
+            continue;
+        }
+        match event {
+            SyncEvent::AccountStarted { account_id } => eprintln!("Syncing {account_id}"),
+            SyncEvent::WindowFetched {
+                since,
+                before,
+                count,
+                ..
+            } => eprintln!(
+                "  {} to {}: {count} transactions",
+                since.format("%Y-%m-%d"),
+                before.format("%Y-%m-%d")
+            ),
+            SyncEvent::TransactionUpserted { .. } | SyncEvent::SyncCompleted { .. } => (),
         }
     }
+}
 
-    // sort by date
-    txs_resp.sort_by(|a, b| a.created.cmp(&b.created));
+// Print a one line summary of the run
+fn print_summary(summary: &SyncSummary, elapsed: Duration) {
+    let stats = summary.stats;
+    println!(
+        "Run #{}: {} accounts, {} fetched, {} inserted, {} updated, {} skipped, {} card events in {:.1}s",
+        summary.run_id,
+        stats.accounts,
+        stats.fetched,
+        stats.inserted,
+        stats.updated,
+        stats.skipped,
+        stats.events,
+        elapsed.as_secs_f64()
+    );
+}
 
-    Ok(txs_resp)
+// Print the time spent in each phase, with the transactions handled per second
+fn print_profile(profile: &SyncProfile, stats: SyncStats) {
+    let total = profile.total().as_secs_f64();
+    println!("Sync profile:");
+    for (phase, duration, handled) in [
+        ("fetch", profile.fetch, Some(stats.fetched)),
+        (
+            "dedupe",
+            profile.dedupe,
+            Some(stats.fetched - stats.inserted),
+        ),
+        ("insert", profile.insert, Some(stats.inserted)),
+        ("store", profile.store, None),
+        ("classify", profile.classify, None),
+    ] {
+        let secs = duration.as_secs_f64();
+        let share = if total > 0.0 {
+            secs / total * 100.0
+        } else {
+            0.0
+        };
+        #[allow(clippy::cast_precision_loss)]
+        let rate = handled
+            .filter(|_| secs > 0.0)
+            .map(|n| format!("  {:.0} transactions/s", n as f64 / secs))
+            .unwrap_or_default();
+        println!("  {phase:<9} {secs:>8.3}s {share:>5.1}%{rate}");
+    }
+}
+
+// Collect the per-account counts and balances of the run
+fn run_report<'a>(
+    summary: &'a SyncSummary,
+    account_names: &'a HashMap<String, String>,
+    elapsed: Duration,
+) -> RunReport<'a> {
+    let accounts = summary
+        .accounts
+        .iter()
+        .map(|account| {
+            let snapshot = summary.balances.iter().find(|s| s.account_id == account.id);
+            AccountReport {
+                id: &account.id,
+                name: account_names
+                    .get(&account.id)
+                    .map_or(account.id.as_str(), String::as_str),
+                stats: summary
+                    .account_stats
+                    .get(&account.id)
+                    .copied()
+                    .unwrap_or_default(),
+                balance: snapshot.map(|s| &s.balance),
+                pots: snapshot.map_or(&[], |s| s.pots.as_slice()),
+            }
+        })
+        .collect();
+
+    RunReport {
+        run_id: summary.run_id,
+        interrupted: summary.interrupted,
+        since: summary.since,
+        until: summary.before,
+        duration_seconds: elapsed.as_secs_f64(),
+        totals: summary.stats,
+        accounts,
+        profile_ms: summary.profile.as_ref().map(ProfileReport::from),
+    }
 }
 
 /// Print the transactions to the console
@@ -131,6 +302,8 @@ fn print_transactions(
     transactions: &Vec<TransactionResponse>,
     account_names: &HashMap<String, String>,
     pot_names: &HashMap<String, String>,
+    pot_descriptions: &PotDescriptions,
+    timezone: Tz,
 ) -> Result<(), Error> {
     println!("{:>85}", "TRANSACTIONS");
     println!(
@@ -138,7 +311,7 @@ fn print_transactions(
     );
 
     for tx in transactions {
-        let date_fmt = format_date(&tx.created);
+        let date_fmt = format_date(&tx.created, timezone);
 
         let account_name_fmt = format_account_name(account_names, &tx.account_id);
         let pot_fmt = format_pot(pot_names, &tx.description);
@@ -148,118 +321,89 @@ fn print_transactions(
         let local_amount_fmt =
             local_amount_with_currency(tx.local_amount, &tx.currency, &tx.local_currency)?;
 
-        let merchant_fmt = format_merchant(&tx.merchant);
+        let merchant_fmt = format_merchant(tx.merchant.as_ref());
 
         let notes = match &tx.notes {
             Some(d) => d,
             None => "",
         };
 
-        let description_fmt = format_description(notes, &tx.description, pot_names);
+        let description_fmt = format_description(notes, tx, pot_names, pot_descriptions);
 
         println!(
             "{date_fmt:<11} {account_name_fmt:<8} {pot_fmt:<25} {credit_fmt:>12} {debit_fmt:>12} {local_amount_fmt:>12} {merchant_fmt:>30}  {description_fmt:<30} ",
         );
     }
 
-    Ok(())
-}
-
-async fn persist_accounts(
-    connection_pool: DatabasePool,
-    accounts: &Vec<AccountForDB>,
-) -> Result<(), Error> {
-    let account_service = SqliteAccountService::new(connection_pool.clone());
-    for account in accounts {
-        match account_service.save_account(account).await {
-            Ok(()) => info!("Added account: {}", account.id),
-            Err(Error::Duplicate(_)) => (),
-            Err(e) => {
-                error!("Adding account: {}", account.id);
-                return Err(e);
-            }
-        }
+    if !transactions.is_empty() {
+        print_totals(transactions, account_names)?;
     }
 
     Ok(())
 }
 
-async fn persist_pots(connection_pool: DatabasePool, pots: &Vec<Pot>) -> Result<(), Error> {
-    let pot_service = SqlitePotService::new(connection_pool.clone());
-    for pot in pots {
-        match pot_service.save_pot(pot).await {
-            Ok(()) => info!("Added pot: {}", pot.id),
-            Err(Error::Duplicate(_)) => (),
-            Err(e) => {
-                error!("Adding pot: {}", pot.id);
-                return Err(e);
-            }
-        }
-    }
-
-    Ok(())
-}
-
-async fn persist_categories(
-    connection_pool: DatabasePool,
+/// Print credits, debits and net below the credit and debit columns, for each
+/// account and currency and then for each currency
+fn print_totals(
     transactions: &[TransactionResponse],
+    account_names: &HashMap<String, String>,
 ) -> Result<(), Error> {
-    let category_service = SqliteCategoryService::new(connection_pool.clone());
+    let (by_account, by_currency) = totals(transactions);
 
-    let categories_config = Categories::from_config()?;
-    let custom_categories = categories_config.custom_categories;
-
-    for tx_resp in transactions {
-        let category_id = tx_resp.category.clone();
-        let category_name = get_category_name(&custom_categories, &category_id);
-        let category = Category {
-            id: category_id,
-            name: category_name,
-        };
-        match category_service.save_category(&category).await {
-            Ok(_) => (),
-            Err(Error::Duplicate(_)) => (),
-            Err(e) => return Err(Error::DbError(e.to_string())),
-        }
+    println!(
+        "---------------------------------------------------------------------------------------------------------------------"
+    );
+    println!(
+        "{:<46} {:>12} {:>12} {:>12}",
+        "", "CREDITS", "DEBITS", "NET"
+    );
+    for ((account_id, iso_code), totals) in &by_account {
+        let label = format!(
+            "{} {iso_code}",
+            format_account_name(account_names, account_id)
+        );
+        print_totals_line(&label, *totals, iso_code)?;
+    }
+    for (iso_code, totals) in &by_currency {
+        print_totals_line(&format!("Total {iso_code}"), *totals, iso_code)?;
     }
 
     Ok(())
 }
 
-// Map a category name from the cateogy_id in the transaction that Monzo uses for custom categories
-fn get_category_name(opt_map: &Option<HashMap<String, String>>, key: &str) -> String {
-    opt_map
-        .as_ref()
-        .and_then(|map| map.get(&key.to_lowercase()).cloned())
-        .unwrap_or(key.to_string())
+fn print_totals_line(label: &str, totals: Totals, iso_code: &str) -> Result<(), Error> {
+    println!(
+        "{label:<46} {:>12} {:>12} {:>12}",
+        currency::display(totals.credits, iso_code)?,
+        currency::display(totals.debits, iso_code)?,
+        currency::display(totals.net(), iso_code)?
+    );
+
+    Ok(())
 }
 
-async fn persist_transactions(
-    connection_pool: DatabasePool,
+/// Totals by account and currency, and by currency alone
+fn totals(
     transactions: &[TransactionResponse],
-) -> Result<(), Error> {
-    let tx_service = SqliteTransactionService::new(connection_pool.clone());
-
-    for tx_resp in transactions {
-        match tx_service.save_transaction(&tx_resp).await {
-            Ok(()) => info!("Added transaction: {}", tx_resp.id),
-            Err(Error::Duplicate(_)) => (),
-            Err(e) => {
-                error!("Adding transaction: {}", tx_resp.id);
-                return Err(e);
-            }
-        }
+) -> (BTreeMap<(String, String), Totals>, BTreeMap<String, Totals>) {
+    let mut by_account: BTreeMap<(String, String), Totals> = BTreeMap::new();
+    let mut by_currency: BTreeMap<String, Totals> = BTreeMap::new();
+    for tx in transactions {
+        by_account
+            .entry((tx.account_id.clone(), tx.currency.clone()))
+            .or_default()
+            .add(tx.amount);
+        by_currency
+            .entry(tx.currency.clone())
+            .or_default()
+            .add(tx.amount);
     }
 
-    Ok(())
+    (by_account, by_currency)
 }
 
 fn amount_with_currency(amount: i64, iso_code: &str) -> Result<String, Error> {
-    let Some(iso_code) = iso::find(iso_code) else {
-        return Err(Error::CurrencyNotFound(iso_code.to_string()));
-    };
-
-    Ok(Money::from_minor(amount, iso_code).to_string())
+    currency::display(amount, iso_code)
 }
 
 fn local_amount_with_currency(
@@ -271,15 +415,11 @@ fn local_amount_with_currency(
         return Ok(String::new());
     }
 
-    let Some(iso_code) = iso::find(local_iso_code) else {
-        return Err(Error::CurrencyNotFound(iso_code.to_string()));
-    };
-
-    Ok(format!("({})", Money::from_minor(amount, iso_code)))
+    Ok(format!("({})", currency::display(amount, local_iso_code)?))
 }
 
-fn format_date(date: &DateTime<Utc>) -> String {
-    date.format("%Y-%m-%d").to_string()
+fn format_date(date: &DateTime<Utc>, timezone: Tz) -> String {
+    date.with_timezone(&timezone).format("%Y-%m-%d").to_string()
 }
 
 fn format_account_name(account_names: &HashMap<String, String>, account_id: &str) -> String {
@@ -314,7 +454,7 @@ fn format_debit(amount: i64, amount_str: &str) -> String {
     }
 }
 
-fn format_merchant(merchant: &Option<Merchant>) -> String {
+fn format_merchant(merchant: Option<&Merchant>) -> String {
     match merchant {
         Some(merchant) => merchant.name.clone(),
         None => String::new(),
@@ -323,44 +463,19 @@ fn format_merchant(merchant: &Option<Merchant>) -> String {
 
 fn format_description(
     notes: &str,
-    description: &str,
+    tx: &TransactionResponse,
     pot_names: &HashMap<String, String>,
+    pot_descriptions: &PotDescriptions,
 ) -> String {
-    // substitute the description with the pot name if it exists
-    let description_with_pot_name = match pot_names.get(description) {
-        Some(pot_name) => format!("Pot:{}", pot_name.clone()),
-        None => description.to_string(),
+    // describe pot transfers, which have the pot id as their description
+    let description_with_pot_name = match pot_names.get(&tx.description) {
+        Some(pot_name) => pot_descriptions.describe(pot_name, tx.amount),
+        None => tx.description.clone(),
     };
 
-    let description_fmt = match notes.len() {
+    match notes.len() {
         0 => description_with_pot_name,
         _ => notes.to_string(),
-    };
-
-    description_fmt.to_string()
-}
-
-#[derive(Debug, Deserialize)]
-struct Categories {
-    custom_categories: Option<HashMap<String, String>>,
-}
-
-impl Categories {
-    pub fn from_config() -> Result<Self, Error> {
-        let cfg = config::Config::builder()
-            .add_source(config::File::new(
-                "categories.yaml",
-                config::FileFormat::Yaml,
-            ))
-            .build()?;
-
-        match cfg.try_deserialize::<Categories>() {
-            Ok(custom_categories) => Ok(custom_categories),
-            Err(e) => {
-                println!("{}", e.to_string());
-                Err(Error::ConfigurationError(e))
-            }
-        }
     }
 }
 
@@ -394,6 +509,41 @@ mod tests {
         assert_eq!(res, "($100.00)");
     }
 
+    #[test]
+    fn totals_split_by_account_and_currency() {
+        // Arrange
+        let tx = |account_id: &str, amount, currency: &str| TransactionResponse {
+            account_id: account_id.to_string(),
+            amount,
+            currency: currency.to_string(),
+            ..Default::default()
+        };
+        let transactions = vec![
+            tx("acc_1", 10_000, "GBP"),
+            tx("acc_1", -2_500, "GBP"),
+            tx("acc_2", -1_000, "GBP"),
+            tx("acc_2", -3_000, "EUR"),
+        ];
+
+        // Act
+        let (by_account, by_currency) = totals(&transactions);
+
+        // Assert
+        assert_eq!(by_account.len(), 3);
+        assert_eq!(
+            by_account[&("acc_1".to_string(), "GBP".to_string())].net(),
+            7_500
+        );
+        assert_eq!(
+            by_currency["GBP"],
+            Totals {
+                credits: 10_000,
+                debits: -3_500
+            }
+        );
+        assert_eq!(by_currency["EUR"].net(), -3_000);
+    }
+
     #[test]
     fn test_local_amount_error() {
         let res = local_amount_with_currency(10000, "USD", "XXX");
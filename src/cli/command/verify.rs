@@ -0,0 +1,99 @@
+//! Verify stored balances
+//!
+//! This command reconstructs each account's running balance from its
+//! transactions, lists the windows where it diverges from the balances stored
+//! by `update`, and suggests how far back to sync again. Like `reconcile`, it
+//! fails if it finds any, so it can be run after an update.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use colored::Colorize;
+
+use crate::{
+    cli::output,
+    currency,
+    engine::Verification,
+    error::AppErrors as Error,
+    model::DatabasePool,
+    timezone::{local_date, to_local},
+};
+
+/// Print the gaps found by a verification, and the `update` that should
+/// fill them
+///
+/// # Errors
+/// Will return an error if there are gaps, the database can't be read or a
+/// currency is unknown.
+pub async fn verify(
+    connection_pool: DatabasePool,
+    verification: &Verification,
+    today: NaiveDate,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    if !output::is_quiet() {
+        let accounts = connection_pool.accounts().read_accounts().await?;
+        let name = |account_id: &str| {
+            accounts
+                .iter()
+                .find(|a| a.id == account_id)
+                .map_or_else(|| account_id.to_string(), |a| a.name(nicknames).to_string())
+        };
+        print_verification(verification, today, timezone, name)?;
+    }
+
+    if verification.is_clean() {
+        Ok(())
+    } else {
+        Err(Error::Error(format!(
+            "{} gap(s) between the stored balances and the transactions",
+            verification.gaps.len()
+        )))
+    }
+}
+
+fn print_verification(
+    verification: &Verification,
+    today: NaiveDate,
+    timezone: Tz,
+    name: impl Fn(&str) -> String,
+) -> Result<(), Error> {
+    if verification.compared == 0 {
+        println!("Nothing to verify: each `update` stores balances, and two are needed");
+        return Ok(());
+    }
+    if verification.is_clean() {
+        println!(
+            "{} {} stored balances across {} account(s) match the transactions",
+            "Verified:".green(),
+            verification.compared,
+            verification.accounts
+        );
+        return Ok(());
+    }
+
+    println!("{}", "Balances that don't match the transactions".bold());
+    for gap in &verification.gaps {
+        let code = &gap.currency;
+        println!(
+            "  {:<12} {} to {}  stored {} reconstructed {} ({} missing)",
+            name(&gap.account_id),
+            to_local(gap.since, timezone).format("%Y-%m-%d %H:%M"),
+            to_local(gap.until, timezone).format("%Y-%m-%d %H:%M"),
+            currency::display(gap.stored, code)?,
+            currency::display(gap.reconstructed, code)?,
+            currency::display(gap.difference(), code)?
+        );
+    }
+    if let Some(since) = verification.resync_from() {
+        let days = (today - local_date(since, timezone)).num_days() + 1;
+        println!(
+            "Sync again from {} to fill the gaps: monzo-cli update --days {days}",
+            local_date(since, timezone)
+        );
+    }
+
+    Ok(())
+}
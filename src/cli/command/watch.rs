@@ -0,0 +1,110 @@
+//! Watch for new transactions
+//!
+//! This command keeps updating the last `default_days_to_update` days on the
+//! `[watch]` schedule, printing a line per sync. The database is only locked
+//! while a sync runs, so other commands can be used in between; a sync that
+//! finds the database locked is skipped. Ctrl-C stops watching after the
+//! current window has been saved.
+//!
+//! After each sync, any pot sweeps that are due are made.
+
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    cli::{command::pots, output},
+    engine::{Schedule, Sweeper, SyncEngine},
+    error::AppErrors as Error,
+    lock::DatabaseLock,
+    timezone::to_local,
+};
+
+/// Sync with `engine` on `schedule` until interrupted, sweeping pots with
+/// `sweeper` after each sync
+///
+/// # Errors
+/// Will return an error if the lock file can't be opened. Failed syncs and
+/// sweeps are reported and retried on the schedule.
+pub async fn watch(
+    engine: SyncEngine,
+    sweeper: Sweeper,
+    mut schedule: Schedule,
+    database_path: &str,
+    days: i64,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let cancel = CancellationToken::new();
+    let ctrl_c = tokio::spawn(cancel_on_ctrl_c(cancel.clone()));
+    let engine = engine.with_cancellation(cancel.clone());
+
+    while !cancel.is_cancelled() {
+        let now = Utc::now().naive_utc();
+        let (found_new, outcome) = match DatabaseLock::acquire(database_path) {
+            Ok(_lock) => match engine.sync(now - Duration::days(days), now).await {
+                Ok(summary) => (
+                    summary.stats.inserted > 0,
+                    format!(
+                        "{} new, {} updated{}",
+                        summary.stats.inserted,
+                        summary.stats.updated,
+                        sweep(&sweeper, to_local(now, timezone).date()).await
+                    ),
+                ),
+                Err(e) => {
+                    // errors are shown even when quiet
+                    eprintln!("Update failed: {e}");
+                    (false, "update failed".to_string())
+                }
+            },
+            Err(Error::Locked(_)) => (false, "database in use, skipped".to_string()),
+            Err(e) => return Err(e),
+        };
+
+        schedule.record(found_new);
+        let local = to_local(now, timezone);
+        let next = schedule.next_run(local);
+        if !output::is_quiet() {
+            println!(
+                "{} {outcome}; next sync at {}",
+                local.format("%Y-%m-%d %H:%M"),
+                next.format("%H:%M")
+            );
+        }
+
+        let wait = (next - local).to_std().unwrap_or_default();
+        tokio::select! {
+            () = tokio::time::sleep(wait) => (),
+            () = cancel.cancelled() => (),
+        }
+    }
+
+    ctrl_c.abort();
+    Ok(())
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// Make the sweeps that are due, describing them for the sync's line
+async fn sweep(sweeper: &Sweeper, today: NaiveDate) -> String {
+    if !sweeper.is_due(today) {
+        return String::new();
+    }
+    match sweeper.run(today, false).await {
+        Ok(sweeps) => sweeps
+            .iter()
+            .filter_map(|sweep| pots::describe(sweep).ok())
+            .fold(String::new(), |line, sweep| line + ", swept " + &sweep),
+        Err(e) => {
+            eprintln!("Sweep failed: {e}");
+            ", sweep failed".to_string()
+        }
+    }
+}
+
+async fn cancel_on_ctrl_c(cancel: CancellationToken) {
+    if tokio::signal::ctrl_c().await.is_ok() {
+        eprintln!("Stopping after the current sync");
+        cancel.cancel();
+    }
+}
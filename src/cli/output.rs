@@ -0,0 +1,409 @@
+//! Console output controls
+//!
+//! Global switches set once from the command line flags and consulted by the
+//! commands before printing tables or progress messages, [`Table`] for tables
+//! whose columns and order can be chosen with `--columns` and `--sort`, and
+//! [`page`] for tables that may not fit on the screen.
+
+use std::{
+    cmp::Ordering as CmpOrdering,
+    fmt::Write as _,
+    io::Write,
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use console::Term;
+
+use crate::error::AppErrors as Error;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PAGER: AtomicBool = AtomicBool::new(true);
+static VIEW: OnceLock<TableView> = OnceLock::new();
+
+/// Warn that Monzo couldn't be reached, so the data shown is what the last
+/// update stored
+pub fn warn_offline(error: &Error) {
+    if !is_quiet() {
+        eprintln!("Offline: {error}. Showing the data stored by the last update");
+    }
+}
+
+/// Suppress tables and informational messages. Errors are still printed.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Returns true if informational output should be suppressed
+#[must_use]
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Allow long tables to be shown through a pager
+pub fn set_pager(enabled: bool) {
+    PAGER.store(enabled, Ordering::Relaxed);
+}
+
+/// Print `text`, through `$PAGER` (`less` by default) if stdout is a terminal
+/// that it doesn't fit on, as git does
+pub fn page(text: &str) {
+    let term = Term::stdout();
+    let height = term
+        .is_term()
+        .then(|| term.size_checked())
+        .flatten()
+        .map(|(rows, _)| usize::from(rows));
+    if !needs_pager(text, height, PAGER.load(Ordering::Relaxed)) || !run_pager(text) {
+        print!("{text}");
+    }
+}
+
+// Whether `text` should be paged on a terminal `height` rows tall, or `None`
+// if stdout isn't a terminal
+fn needs_pager(text: &str, height: Option<usize>, enabled: bool) -> bool {
+    enabled && height.is_some_and(|height| text.lines().count() >= height)
+}
+
+// Show `text` in the pager, returning false if it couldn't be started
+fn run_pager(text: &str) -> bool {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    let Some((program, args)) = shlex::split(&pager)
+        .filter(|words| !words.is_empty())
+        .map(|mut words| (words.remove(0), words))
+    else {
+        return false;
+    };
+    if program == "cat" {
+        return false;
+    }
+
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        // Quit if it fits after all, keep colours, and leave the text on screen
+        command.env("LESS", "FRX");
+    }
+    let Ok(mut child) = command.spawn() else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Fails when the user quits before reading everything
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let _ = child.wait();
+
+    true
+}
+
+/// Which columns of a table to show, and which one to sort its rows by
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableView {
+    /// Column names in the order to show them, or every column if empty
+    pub columns: Vec<String>,
+    pub sort: Option<SortKey>,
+}
+
+/// A column to sort by, parsed from `amount` or `amount:desc`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, direction) = s.split_once(':').unwrap_or((s, "asc"));
+        let descending = match direction.to_lowercase().as_str() {
+            "asc" => false,
+            "desc" => true,
+            _ => {
+                return Err(format!(
+                    "Sort direction '{direction}' should be asc or desc"
+                ))
+            }
+        };
+        if column.is_empty() {
+            return Err("Sort column is missing".to_string());
+        }
+
+        Ok(Self {
+            column: column.to_string(),
+            descending,
+        })
+    }
+}
+
+/// Choose the columns and order of tables built with [`Table`]. The first
+/// view set applies for the rest of the run.
+pub fn set_table_view(view: TableView) {
+    _ = VIEW.set(view);
+}
+
+/// A column of a [`Table`]
+#[derive(Debug, Clone)]
+pub struct Column {
+    name: String,
+    aliases: Vec<String>,
+    right: bool,
+}
+
+impl Column {
+    /// A left-aligned column headed `name`, which `--columns` and `--sort`
+    /// match in any case
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            aliases: Vec::new(),
+            right: false,
+        }
+    }
+
+    /// Align the column's values to the right, as for amounts
+    #[must_use]
+    pub fn right(mut self) -> Self {
+        self.right = true;
+        self
+    }
+
+    /// Another name `--columns` and `--sort` accept for the column
+    #[must_use]
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_lowercase());
+        self
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.aliases.contains(&name.to_lowercase())
+    }
+}
+
+/// A value in a [`Table`], sorted by number if it has one and by text if not
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    text: String,
+    number: Option<f64>,
+}
+
+impl Cell {
+    /// A number shown as `text`, e.g. an amount in the locale's format
+    #[must_use]
+    pub fn number(text: String, number: f64) -> Self {
+        Self {
+            text,
+            number: Some(number),
+        }
+    }
+
+    fn compare(&self, other: &Self) -> CmpOrdering {
+        match (self.number, other.number) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            _ => self.text.cmp(&other.text),
+        }
+    }
+}
+
+impl<T: Into<String>> From<T> for Cell {
+    fn from(text: T) -> Self {
+        Self {
+            text: text.into(),
+            number: None,
+        }
+    }
+}
+
+/// A table whose columns are sized to fit, shown with the `--columns` and
+/// `--sort` chosen on the command line
+#[derive(Debug, Clone)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    #[must_use]
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a row with a cell for each column
+    pub fn push(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    /// The table as text, with the view set by [`set_table_view`]
+    ///
+    /// # Errors
+    /// Will return an error if the view names a column the table doesn't have.
+    pub fn render(&self) -> Result<String, Error> {
+        self.render_view(VIEW.get().unwrap_or(&TableView::default()))
+    }
+
+    fn render_view(&self, view: &TableView) -> Result<String, Error> {
+        let mut rows: Vec<&Vec<Cell>> = self.rows.iter().collect();
+        if let Some(sort) = &view.sort {
+            let index = self.column_index(&sort.column)?;
+            rows.sort_by(|a, b| {
+                let order = a[index].compare(&b[index]);
+                if sort.descending {
+                    order.reverse()
+                } else {
+                    order
+                }
+            });
+        }
+        let shown = if view.columns.is_empty() {
+            (0..self.columns.len()).collect()
+        } else {
+            view.columns
+                .iter()
+                .map(|name| self.column_index(name))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let widths: Vec<usize> = shown
+            .iter()
+            .map(|&i| {
+                rows.iter()
+                    .map(|row| row[i].text.chars().count())
+                    .chain([self.columns[i].name.len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let line = |values: Vec<&str>| {
+            values
+                .iter()
+                .zip(&shown)
+                .zip(&widths)
+                .map(|((value, &i), width)| {
+                    if self.columns[i].right {
+                        format!("{value:>width$}")
+                    } else {
+                        format!("{value:<width$}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+
+        let mut table = String::new();
+        let header = shown
+            .iter()
+            .map(|&i| self.columns[i].name.as_str())
+            .collect();
+        let _ = writeln!(table, "{}", line(header));
+        let _ = writeln!(
+            table,
+            "{}",
+            "-".repeat(widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1))
+        );
+        for row in rows {
+            let _ = writeln!(
+                table,
+                "{}",
+                line(shown.iter().map(|&i| row[i].text.as_str()).collect())
+            );
+        }
+
+        Ok(table)
+    }
+
+    fn column_index(&self, name: &str) -> Result<usize, Error> {
+        self.columns
+            .iter()
+            .position(|column| column.is_named(name))
+            .ok_or_else(|| {
+                let names: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|column| column.name.to_lowercase())
+                    .collect();
+                Error::Error(format!(
+                    "No column '{name}' in this table. Choose from: {}",
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// The log filter to use for a given `--verbose` count
+#[must_use]
+pub fn log_level(verbose: u8) -> &'static str {
+    match verbose {
+        0 => "error",
+        1 => "info",
+        _ => "debug",
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_text_taller_than_the_terminal() {
+        let text = "header\n".repeat(30);
+
+        assert!(needs_pager(&text, Some(24), true));
+        assert!(!needs_pager(&text, Some(50), true));
+        assert!(!needs_pager(&text, None, true));
+        assert!(!needs_pager(&text, Some(24), false));
+    }
+
+    #[test]
+    fn tables_show_the_chosen_columns_in_the_chosen_order() {
+        // Arrange
+        let mut table = Table::new(vec![
+            Column::new("DATE"),
+            Column::new("DESCRIPTION").alias("merchant"),
+            Column::new("AMOUNT").right(),
+        ]);
+        for (date, merchant, amount) in [
+            ("2024-05-01", "Tesco", -2_550),
+            ("2024-05-02", "Salary", 250_000),
+            ("2024-05-03", "Costa", -350),
+        ] {
+            table.push(vec![
+                date.into(),
+                merchant.into(),
+                Cell::number(
+                    format!("{:.2}", f64::from(amount) / 100.0),
+                    f64::from(amount),
+                ),
+            ]);
+        }
+        let view = TableView {
+            columns: vec!["merchant".to_string(), "Amount".to_string()],
+            sort: Some("amount:desc".parse().unwrap()),
+        };
+
+        // Act
+        let text = table.render_view(&view).unwrap();
+        let unknown = table.render_view(&TableView {
+            columns: vec!["payee".to_string()],
+            sort: None,
+        });
+
+        // Assert
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "DESCRIPTION   AMOUNT");
+        assert_eq!(lines[2], "Salary       2500.00");
+        assert_eq!(lines[4], "Tesco         -25.50");
+        assert!(unknown.is_err());
+    }
+}
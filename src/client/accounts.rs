@@ -20,8 +20,7 @@ impl Monzo {
     pub async fn accounts(&self) -> Result<Vec<AccountResponse>, Error> {
         let url = format!("{}accounts", self.base_url);
         info!("url: {}", url);
-        let response = self.client.get(&url).send().await?;
-        let accounts: Accounts = Self::handle_response(response).await?;
+        let accounts: Accounts = self.get(&url).await?;
 
         Ok(accounts.accounts)
     }
@@ -44,27 +43,28 @@ impl Monzo {
 
 #[cfg(test)]
 mod test {
-    use crate::tests::{self, test::get_client};
+    use crate::tests::mock::{MockMonzo, ACCOUNT_ID};
 
     #[tokio::test]
-    // #[ignore]
     async fn accounts_work() {
         // Arrange
-        let db = tests::test::test_db().await;
-        let monzo = get_client();
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
         // Act
         let accounts = monzo.accounts().await.unwrap();
         // Assert
-        assert!(accounts.len() > 0);
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].id, ACCOUNT_ID);
     }
 
     #[tokio::test]
     async fn account_hash_works() {
         // Arrange
-        let monzo = get_client();
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
         // Act
         let companies = monzo.account_description_from_id().await.unwrap();
         // Assert
-        println!("{:#?}", companies);
+        assert_eq!(companies[ACCOUNT_ID], "personal");
     }
 }
@@ -0,0 +1,217 @@
+//! Access token refresh
+//!
+//! Monzo access tokens expire after a few hours. A client created from the
+//! configuration keeps the refresh token that came with its access token, and
+//! when a request is refused with a 401 it exchanges it at `oauth2/token` for
+//! new tokens, writes them back to `configuration.toml` and sends the request
+//! again. Refresh tokens can only be used once, so the new one has to be saved
+//! for the next run. Only the `[access_tokens]` table is rewritten, and the
+//! file is replaced in one step, so a crash mid-write can't lose the token.
+
+use std::sync::PoisonError;
+
+use reqwest::{RequestBuilder, StatusCode};
+use tracing_log::log::{info, warn};
+
+use super::Monzo;
+use crate::configuration::{save_access_tokens, AccessTokens, OathCredentials};
+use crate::error::AppErrors as Error;
+
+/// The access token in use, and what's needed to refresh it
+#[derive(Debug)]
+pub(super) struct Session {
+    pub access_token: String,
+    refresh: Option<Refresh>,
+}
+
+#[derive(Debug)]
+struct Refresh {
+    credentials: OathCredentials,
+    token: String,
+    /// Write refreshed tokens to `configuration.toml`
+    save: bool,
+}
+
+impl Session {
+    pub fn new(access_token: &str) -> Self {
+        Self {
+            access_token: access_token.to_string(),
+            refresh: None,
+        }
+    }
+}
+
+impl Monzo {
+    /// Exchange `tokens.refresh_token` for new tokens when the access token is
+    /// refused, and send the request again. The new tokens are kept for the
+    /// life of the client.
+    #[must_use]
+    pub fn with_token_refresh(self, credentials: OathCredentials, tokens: &AccessTokens) -> Self {
+        {
+            let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+            session.access_token.clone_from(&tokens.access_token);
+            session.refresh = Some(Refresh {
+                credentials,
+                token: tokens.refresh_token.clone(),
+                save: false,
+            });
+        }
+        self
+    }
+
+    /// Also write refreshed tokens to `configuration.toml`
+    #[must_use]
+    pub fn saving_tokens(self) -> Self {
+        if let Some(refresh) = self
+            .session
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .refresh
+            .as_mut()
+        {
+            refresh.save = true;
+        }
+        self
+    }
+
+    // Send the request that `request` builds with the access token, and again
+    // with a refreshed one if it's refused
+    pub(super) async fn send(
+        &self,
+        request: impl Fn(&str) -> RequestBuilder,
+    ) -> Result<(StatusCode, String), Error> {
+        let token = self.access_token();
+        let response = request(&token).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status != StatusCode::UNAUTHORIZED || !self.refresh_access_token(&token).await? {
+            return Ok((status, body));
+        }
+
+        let response = request(&self.access_token()).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        Ok((status, body))
+    }
+
+    fn access_token(&self) -> String {
+        self.session
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .access_token
+            .clone()
+    }
+
+    // Replace the `refused` access token, unless another request already has.
+    // Returns false if the client can't refresh tokens.
+    async fn refresh_access_token(&self, refused: &str) -> Result<bool, Error> {
+        // one refresh at a time, as each refresh token can only be used once
+        let _refreshing = self.refreshing.lock().await;
+        let form = {
+            let session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+            if session.access_token != refused {
+                return Ok(true);
+            }
+            let Some(refresh) = &session.refresh else {
+                return Ok(false);
+            };
+            [
+                ("grant_type", "refresh_token".to_string()),
+                ("client_id", refresh.credentials.client_id.clone()),
+                ("client_secret", refresh.credentials.client_secret.clone()),
+                ("refresh_token", refresh.token.clone()),
+            ]
+        };
+
+        info!("Refreshing the access token");
+        let url = format!("{}oauth2/token", self.base_url);
+        let response = self.client.post(&url).form(&form).send().await?;
+        if !response.status().is_success() {
+            let body = response.text().await?;
+            warn!("Refreshing the access token failed: {body}");
+            return Err(Error::AccessTokenError(format!(
+                "The access token expired and couldn't be refreshed: {body}"
+            )));
+        }
+        let tokens: AccessTokens = response.json().await?;
+
+        let save = {
+            let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+            session.access_token.clone_from(&tokens.access_token);
+            session.refresh.as_mut().is_some_and(|refresh| {
+                refresh.token.clone_from(&tokens.refresh_token);
+                refresh.save
+            })
+        };
+        if save {
+            save_access_tokens(&tokens)?;
+        }
+
+        Ok(true)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use wiremock::{
+        matchers::{body_string_contains, header, method, path},
+        Mock, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::tests::mock::{MockMonzo, ACCESS_TOKEN};
+
+    #[tokio::test]
+    async fn refused_requests_are_sent_again_with_a_refreshed_token() {
+        // Arrange
+        let mock = MockMonzo::start().await;
+        Mock::given(path("/accounts"))
+            .and(header("authorization", "Bearer expired"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("unauthorized"))
+            .mount(mock.server())
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/oauth2/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=refresh-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": ACCESS_TOKEN,
+                "client_id": "oauth2client_1",
+                "expires_in": 21600,
+                "refresh_token": "refresh-2",
+                "token_type": "Bearer",
+                "user_id": "user_1",
+            })))
+            .expect(1)
+            .mount(mock.server())
+            .await;
+        let credentials = OathCredentials {
+            client_id: "oauth2client_1".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: "http://127.0.0.1:3000/oauth/callback".to_string(),
+        };
+        let tokens = AccessTokens {
+            access_token: "expired".to_string(),
+            client_id: "oauth2client_1".to_string(),
+            expires_in: 21600,
+            refresh_token: "refresh-1".to_string(),
+            token_type: "Bearer".to_string(),
+            user_id: "user_1".to_string(),
+        };
+        let monzo = Monzo::with_base_url(&mock.server().uri(), "expired")
+            .unwrap()
+            .with_token_refresh(credentials, &tokens);
+
+        // Act
+        let accounts = monzo.accounts().await.unwrap();
+        let again = monzo.accounts().await.unwrap();
+
+        // Assert
+        assert!(!accounts.is_empty());
+        assert_eq!(accounts.len(), again.len());
+        assert_eq!(monzo.access_token(), ACCESS_TOKEN);
+    }
+}
@@ -13,8 +13,7 @@ impl Monzo {
     /// Will return errors if authentication fails or the Monzo API cannot be reached.
     pub async fn balance(&self, account_id: &str) -> Result<Balance, Error> {
         let url = format!("{}balance?account_id={}", self.base_url, account_id);
-        let response = self.client.get(&url).send().await?;
-        let balance: Balance = Self::handle_response(response).await?;
+        let balance: Balance = self.get(&url).await?;
 
         Ok(balance)
     }
@@ -24,17 +23,16 @@ impl Monzo {
 
 #[cfg(test)]
 mod test {
-    use crate::tests::test::get_client;
+    use crate::tests::mock::{MockMonzo, ACCOUNT_ID};
 
     #[tokio::test]
-    #[ignore]
     async fn balances_work() {
-        let monzo = get_client();
-        let accounts = monzo.accounts().await.unwrap();
-        let account_id = &accounts[0].id;
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
 
-        let balance = monzo.balance(account_id).await.unwrap();
+        let balance = monzo.balance(ACCOUNT_ID).await.unwrap();
 
         assert_eq!(balance.currency, "GBP");
+        assert_eq!(balance.balance, 5000);
     }
 }
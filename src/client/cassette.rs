@@ -0,0 +1,175 @@
+//! HTTP record/replay
+//!
+//! In record mode every API response is written to a cassette directory as one
+//! JSON file per request; in replay mode responses are read back from it and the
+//! network is never touched. A cassette lets a deserialisation failure be
+//! reproduced from a bug report, or a demo run without credentials.
+//!
+//! Requests are stored relative to the API base URL and never include headers,
+//! so the access token is not written. Account numbers, sort codes and user and
+//! client ids in response bodies are redacted.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing_log::log::info;
+
+use crate::error::AppErrors as Error;
+
+/// Response fields that are replaced with [`REDACTED`] when recording
+const SENSITIVE_FIELDS: [&str; 4] = ["account_number", "sort_code", "user_id", "client_id"];
+const REDACTED: &str = "REDACTED";
+
+/// Where responses are recorded to or replayed from
+#[derive(Debug, Clone)]
+pub enum Cassette {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// A recorded request and its response
+#[derive(Debug, Serialize, Deserialize)]
+struct Interaction {
+    request: String,
+    status: u16,
+    body: Value,
+}
+
+/// Write a sanitised response to the cassette directory
+///
+/// # Errors
+/// Will return an error if the directory or file can't be written.
+pub(crate) fn record(
+    dir: &Path,
+    request: &str,
+    status: StatusCode,
+    body: &str,
+) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+
+    let mut body = serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string()));
+    redact(&mut body);
+    let interaction = Interaction {
+        request: request.to_string(),
+        status: status.as_u16(),
+        body,
+    };
+
+    let path = dir.join(file_name(request));
+    info!("Recording {} to {}", request, path.display());
+    fs::write(&path, serde_json::to_string_pretty(&interaction)?)?;
+
+    Ok(())
+}
+
+/// Read a recorded response from the cassette directory
+///
+/// # Errors
+/// Will return an error if no response was recorded for the request.
+pub(crate) fn replay(dir: &Path, request: &str) -> Result<(StatusCode, String), Error> {
+    let path = dir.join(file_name(request));
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Err(Error::Error(format!(
+            "No recorded response for '{request}' in {}",
+            dir.display()
+        )));
+    };
+    let interaction: Interaction = serde_json::from_str(&contents)?;
+
+    let status = StatusCode::from_u16(interaction.status)
+        .map_err(|e| Error::Error(format!("Invalid status in {}: {e}", path.display())))?;
+    let body = match interaction.body {
+        Value::String(s) => s,
+        other => other.to_string(),
+    };
+
+    Ok((status, body))
+}
+
+// One file per request, named after the path and query
+fn file_name(request: &str) -> String {
+    let name: String = request
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{name}.json")
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_FIELDS.contains(&key.as_str()) && value.is_string() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => (),
+    }
+}
+
+// -- Tests ---------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        client::Monzo,
+        tests::mock::{MockMonzo, ACCOUNT_ID},
+    };
+
+    #[tokio::test]
+    async fn replays_recorded_responses() {
+        // Arrange
+        let dir = temp_dir::TempDir::new().unwrap();
+        let mock = MockMonzo::start().await;
+        let recorder = mock
+            .client()
+            .with_cassette(Cassette::Record(dir.path().to_path_buf()));
+        let original = recorder.pots(ACCOUNT_ID).await.unwrap();
+
+        // Act
+        let player = Monzo::with_base_url("http://127.0.0.1:9", "none")
+            .unwrap()
+            .with_cassette(Cassette::Replay(dir.path().to_path_buf()));
+        let replayed = player.pots(ACCOUNT_ID).await.unwrap();
+
+        // Assert
+        assert_eq!(original.len(), replayed.len());
+        assert_eq!(original[0].name, replayed[0].name);
+        assert!(player.balance(ACCOUNT_ID).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn redacts_account_details() {
+        // Arrange
+        let dir = temp_dir::TempDir::new().unwrap();
+        let mock = MockMonzo::start().await;
+        let recorder = mock
+            .client()
+            .with_cassette(Cassette::Record(dir.path().to_path_buf()));
+
+        // Act
+        recorder.accounts().await.unwrap();
+
+        // Assert
+        let contents = fs::read_to_string(dir.path().join(file_name("accounts"))).unwrap();
+        assert!(contents.contains(ACCOUNT_ID));
+        assert!(!contents.contains("12345678"));
+        assert!(contents.contains(REDACTED));
+    }
+}
@@ -0,0 +1,76 @@
+//! Feed related functions
+//!
+//! This module posts items to an account's feed in the Monzo app.
+
+use serde::de::IgnoredAny;
+
+use super::Monzo;
+use crate::error::AppErrors as Error;
+
+/// Shown beside feed items, which Monzo requires an image for
+const FEED_IMAGE_URL: &str = "https://monzo.com/favicon.ico";
+
+impl Monzo {
+    /// Post a basic item with `title` and `body` to the feed of `account_id`
+    ///
+    /// # Errors
+    /// Will return errors if authentication fails, the Monzo API cannot be
+    /// reached or the item is refused.
+    pub async fn create_feed_item(
+        &self,
+        account_id: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<(), Error> {
+        let url = format!("{}feed", self.base_url);
+        let _: IgnoredAny = self
+            .post_form(
+                &url,
+                &[
+                    ("account_id", account_id),
+                    ("type", "basic"),
+                    ("params[title]", title),
+                    ("params[body]", body),
+                    ("params[image_url]", FEED_IMAGE_URL),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+// -- Tests ---------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use wiremock::{
+        matchers::{body_string_contains, method, path},
+        Mock, ResponseTemplate,
+    };
+
+    use crate::tests::mock::{MockMonzo, ACCOUNT_ID};
+
+    #[tokio::test]
+    async fn create_feed_item_works() {
+        // Arrange
+        let mock = MockMonzo::start().await;
+        Mock::given(method("POST"))
+            .and(path("/feed"))
+            .and(body_string_contains("type=basic"))
+            .and(body_string_contains("params%5Btitle%5D=Groceries"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("{}", "application/json"))
+            .expect(1)
+            .mount(mock.server())
+            .await;
+
+        // Act
+        let result = mock
+            .client()
+            .create_feed_item(ACCOUNT_ID, "Groceries", "80% spent")
+            .await;
+
+        // Assert
+        assert!(result.is_ok());
+    }
+}
@@ -3,16 +3,23 @@
 
 use crate::error::AppErrors as Error;
 use core::fmt;
-use reqwest::header::{self, HeaderMap, HeaderValue};
-use reqwest::Response;
+use reqwest::header::HeaderValue;
+use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
-use tracing_log::log::{error, info};
+use std::sync::{Arc, Mutex};
+use tracing_log::log::{debug, error, info};
 
 use crate::configuration::get_config;
+use crate::model::account::redact_account_numbers;
+use auth::Session;
+use cassette::Cassette;
 
 mod accounts;
+mod auth;
 mod balances;
+pub mod cassette;
+mod feed;
 mod pots;
 pub mod transactions;
 mod whoami;
@@ -30,55 +37,195 @@ impl fmt::Display for ErrorJson {
     }
 }
 
+#[derive(Clone)]
 pub struct Monzo {
     base_url: String,
     client: reqwest::Client,
+    session: Arc<Mutex<Session>>,
+    refreshing: Arc<tokio::sync::Mutex<()>>,
+    cassette: Option<Cassette>,
+    mask_account_numbers: bool,
 }
 
 impl Monzo {
-    /// Create a new Monzo client
+    /// Create a new Monzo client that refreshes the access token when it
+    /// expires, saving the new tokens to the configuration file
     ///
     /// # Errors
     /// Will return an error if the auth header can't be created or the client can't be built.
     pub fn new() -> Result<Self, Error> {
-        let base_url = "https://api.monzo.com/".to_string();
-
         let config = get_config()?;
-        let mut headers = HeaderMap::new();
-        let auth_header_value = format!("Bearer {}", config.access_tokens.access_token);
-        headers.insert(
-            header::AUTHORIZATION,
-            HeaderValue::from_str(&auth_header_value)?,
-        );
-
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()?;
-
-        Ok(Monzo { base_url, client })
+
+        Ok(
+            Self::with_base_url(&config.api_base_url, &config.access_tokens.access_token)?
+                .with_token_refresh(config.oath_credentials, &config.access_tokens)
+                .saving_tokens()
+                .with_masking(config.privacy.mask_account_numbers),
+        )
     }
 
-    #[tracing::instrument(name = "Handle response", skip(response), fields(url=%response.url()))]
-    async fn handle_response<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
-        if response.status().is_success() {
+    /// Create a client for the API at `base_url`, e.g. a mock server in tests
+    ///
+    /// # Errors
+    /// Will return an error if the auth header can't be created or the client can't be built.
+    pub fn with_base_url(base_url: &str, access_token: &str) -> Result<Self, Error> {
+        let base_url = format!("{}/", base_url.trim_end_matches('/'));
+
+        // the token is sent with each request, but checked here
+        HeaderValue::from_str(&format!("Bearer {access_token}"))?;
+
+        let client = reqwest::Client::builder().build()?;
+
+        Ok(Monzo {
+            base_url,
+            client,
+            session: Arc::new(Mutex::new(Session::new(access_token))),
+            refreshing: Arc::new(tokio::sync::Mutex::new(())),
+            cassette: None,
+            mask_account_numbers: true,
+        })
+    }
+
+    /// Record responses to, or replay them from, a cassette directory
+    #[must_use]
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// Mask account numbers and sort codes in responses written to the log
+    /// when they can't be read, as they are by default
+    #[must_use]
+    pub fn with_masking(mut self, mask_account_numbers: bool) -> Self {
+        self.mask_account_numbers = mask_account_numbers;
+        self
+    }
+
+    // GET a url and deserialise the response, going through the cassette if set
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        let request = url.strip_prefix(&self.base_url).unwrap_or(url);
+
+        let (status, body) = if let Some(Cassette::Replay(dir)) = &self.cassette {
+            cassette::replay(dir, request)?
+        } else {
+            let (status, body) = self
+                .send(|token| self.client.get(url).bearer_auth(token))
+                .await?;
+            if let Some(Cassette::Record(dir)) = &self.cassette {
+                cassette::record(dir, request, status, &body)?;
+            }
+            (status, body)
+        };
+
+        self.handle_response(url, status, body)
+    }
+
+    // PUT a form and deserialise the response. Writes bypass the cassette, and
+    // are refused when replaying one.
+    async fn put_form<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        form: &[(&str, &str)],
+    ) -> Result<T, Error> {
+        if let Some(Cassette::Replay(_)) = &self.cassette {
+            return Err(Error::Error(
+                "Can't change anything at Monzo while replaying a cassette".into(),
+            ));
+        }
+
+        let (status, body) = self
+            .send(|token| self.client.put(url).bearer_auth(token).form(form))
+            .await?;
+
+        self.handle_response(url, status, body)
+    }
+
+    // POST a form and deserialise the response, with the same cassette rules
+    // as `put_form`
+    async fn post_form<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        form: &[(&str, &str)],
+    ) -> Result<T, Error> {
+        if let Some(Cassette::Replay(_)) = &self.cassette {
+            return Err(Error::Error(
+                "Can't change anything at Monzo while replaying a cassette".into(),
+            ));
+        }
+
+        let (status, body) = self
+            .send(|token| self.client.post(url).bearer_auth(token).form(form))
+            .await?;
+
+        self.handle_response(url, status, body)
+    }
+
+    #[tracing::instrument(name = "Handle response", skip(self, body))]
+    fn handle_response<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        status: StatusCode,
+        body: String,
+    ) -> Result<T, Error> {
+        let redacted = || {
+            if self.mask_account_numbers {
+                redact_account_numbers(&body)
+            } else {
+                body.clone()
+            }
+        };
+
+        if status.is_success() {
             info!("Response is successful");
-            let j = response.text().await?;
-            let jd = &mut serde_json::Deserializer::from_str(&j);
+            let jd = &mut serde_json::Deserializer::from_str(&body);
             let result = match serde_path_to_error::deserialize(jd) {
                 Ok(result) => result,
                 Err(e) => {
                     error!("unable to parse response: {}", e);
-                    println!("->> Response content: {}", j);
+                    debug!("Response content: {}", redacted());
                     return Err(Error::HandlerError(e.to_string()));
                 }
             };
             Ok(result)
+        } else if status == StatusCode::UNAUTHORIZED {
+            error!("Unauthorised: {:?}", body);
+            Err(Error::AccessTokenError(body))
         } else {
             // set up serde_path_to_error
             // TODO: Implement error handling for Monzo API
-            let j = response.text().await?;
-            error!("Response error: {:?}", j);
-            Err(Error::HandlerError(j.to_string()))
+            error!("Response error: {:?}", redacted());
+            Err(Error::HandlerError(body))
         }
     }
 }
+
+// -- Tests ---------------------------------------------------------------------
+
+#[cfg(test)]
+mod test {
+    use wiremock::{matchers::path, Mock, ResponseTemplate};
+
+    use super::*;
+    use crate::tests::mock::MockMonzo;
+
+    #[test]
+    fn base_url_gets_a_trailing_slash() {
+        let monzo = Monzo::with_base_url("http://localhost:1234", "token").unwrap();
+
+        assert_eq!(monzo.base_url, "http://localhost:1234/");
+    }
+
+    #[tokio::test]
+    async fn unauthorised_is_an_access_token_error() {
+        let mock = MockMonzo::start().await;
+        let monzo = Monzo::with_base_url(&mock.server().uri(), "expired").unwrap();
+        Mock::given(path("/accounts"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("unauthorized"))
+            .mount(mock.server())
+            .await;
+
+        let result = monzo.accounts().await;
+
+        assert!(matches!(result, Err(Error::AccessTokenError(_))));
+    }
+}
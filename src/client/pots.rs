@@ -15,12 +15,37 @@ impl Monzo {
     /// Will return errors if authentication fails or the Monzo API cannot be reached.
     pub async fn pots(&self, account_id: &str) -> Result<Vec<PotResponse>, Error> {
         let url = format!("{}pots?current_account_id={}", self.base_url, account_id);
-        let response = self.client.get(&url).send().await?;
-        let pots: Pots = Self::handle_response(response).await?;
+        let pots: Pots = self.get(&url).await?;
 
         Ok(pots.pots)
     }
 
+    /// Move `amount` minor units from `source_account_id` into a pot. Monzo
+    /// ignores a repeated `dedupe_id`, so retrying can't deposit twice.
+    ///
+    /// # Errors
+    /// Will return errors if authentication fails, the Monzo API cannot be
+    /// reached or the deposit is refused.
+    pub async fn deposit_into_pot(
+        &self,
+        pot_id: &str,
+        source_account_id: &str,
+        amount: i64,
+        dedupe_id: &str,
+    ) -> Result<PotResponse, Error> {
+        let url = format!("{}pots/{pot_id}/deposit", self.base_url);
+        let amount = amount.to_string();
+        self.put_form(
+            &url,
+            &[
+                ("source_account_id", source_account_id),
+                ("amount", &amount),
+                ("dedupe_id", dedupe_id),
+            ],
+        )
+        .await
+    }
+
     /// Generate a hash of pot IDs and descriptions
     ///
     /// # Errors
@@ -42,15 +67,56 @@ impl Monzo {
 
 #[cfg(test)]
 mod test {
+    use wiremock::{
+        matchers::{body_string_contains, method, path},
+        Mock, ResponseTemplate,
+    };
 
-    use crate::tests::test::get_client;
+    use crate::tests::mock::{MockMonzo, ACCOUNT_ID};
 
     #[tokio::test]
-    #[ignore]
     async fn pots_work() {
-        let monzo = get_client();
-        let pots = monzo.pots("acc_0000AdNaq81vwtbTBedL06").await.unwrap();
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
+
+        let pots = monzo.pots(ACCOUNT_ID).await.unwrap();
+
+        assert_eq!(pots.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn deposit_into_pot_works() {
+        // Arrange
+        let mock = MockMonzo::start().await;
+        Mock::given(method("PUT"))
+            .and(path("/pots/pot_1/deposit"))
+            .and(body_string_contains("amount=2500"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"id":"pot_1","name":"Food","balance":7500,"currency":"GBP","deleted":false,"type":"default"}"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(mock.server())
+            .await;
+
+        // Act
+        let pot = mock
+            .client()
+            .deposit_into_pot("pot_1", ACCOUNT_ID, 2500, "budget-1")
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(pot.balance, 7500);
+    }
+
+    #[tokio::test]
+    async fn pot_hash_works() {
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
+
+        let pots = monzo.pot_description_from_id().await.unwrap();
 
-        assert!(pots.len() > 0);
+        assert_eq!(pots["pot_0000778xxfgh4iu8z83nWb"], "Savings");
     }
 }
@@ -29,9 +29,7 @@ impl Monzo {
         );
         info!("url: {}", url);
 
-        let response = self.client.get(&url).send().await?;
-
-        let transactions: TransactionsResponse = Self::handle_response(response).await?;
+        let transactions: TransactionsResponse = self.get(&url).await?;
         let txs_response = transactions.transactions;
 
         Ok(txs_response)
@@ -46,18 +44,17 @@ mod test {
 
     use crate::{
         model::transaction::TransactionResponse,
-        tests::{self, test::get_client},
+        tests::mock::{MockMonzo, ACCOUNT_ID},
     };
 
     use crate::date_ranges;
 
     #[tokio::test]
     async fn transactions_work() {
-        let monzo = get_client();
-        let pool = tests::test::test_db().await;
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
 
         let mut txs: Vec<TransactionResponse> = Vec::new();
-        let account_id = "acc_0000AdNaq81vwtbTBedL06";
 
         let start =
             NaiveDateTime::parse_from_str("2024-04-01 12:23:00", "%Y-%m-%d %H:%M:%S").unwrap();
@@ -66,17 +63,42 @@ mod test {
 
         let monthly_intervals = date_ranges(start, end, 30);
 
-        println!("->> {:?}", monthly_intervals.clone());
-
         for (since, before) in monthly_intervals.clone() {
             let transactions = monzo
-                .transactions(account_id, &since, &before, None)
+                .transactions(ACCOUNT_ID, &since, &before, None)
                 .await
                 .unwrap();
 
             txs.extend(transactions);
         }
 
-        assert!(txs.len() > 0);
+        // the mock returns the same page for each window
+        assert_eq!(txs.len(), 4 * monthly_intervals.len());
+        assert_eq!(
+            txs[0].merchant.as_ref().unwrap().name,
+            "The De Beauvoir Deli Co."
+        );
+        assert!(txs[2].settled.is_none());
+    }
+
+    #[tokio::test]
+    async fn transactions_request_the_window() {
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
+        let since =
+            NaiveDateTime::parse_from_str("2024-04-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let before =
+            NaiveDateTime::parse_from_str("2024-05-01 00:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        monzo
+            .transactions(ACCOUNT_ID, &since, &before, Some(50))
+            .await
+            .unwrap();
+
+        let requests = mock.server().received_requests().await.unwrap();
+        let query = requests[0].url.query().unwrap();
+        assert!(query.contains("since=2024-04-01T00:00:00Z"));
+        assert!(query.contains("before=2024-05-01T00:00:00Z"));
+        assert!(query.contains("limit=50"));
     }
 }
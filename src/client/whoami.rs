@@ -20,8 +20,7 @@ impl Monzo {
     /// Will return errors if authentication fails or the endpoint can't be reached.
     pub async fn whoami(&self) -> Result<WhoAmI, Error> {
         let url = format!("{}ping/whoami", self.base_url);
-        let response = self.client.get(&url).send().await?;
-        let whoami: WhoAmI = Self::handle_response(response).await?;
+        let whoami: WhoAmI = self.get(&url).await?;
 
         Ok(whoami)
     }
@@ -29,20 +28,15 @@ impl Monzo {
 
 #[cfg(test)]
 mod test {
-    use crate::tests::test::get_client;
+    use crate::tests::mock::MockMonzo;
 
     #[tokio::test]
-    #[ignore]
     async fn whoami_work() {
-        let monzo = get_client();
-        match monzo.whoami().await {
-            Ok(who_am_i) => {
-                println!("->> OK {:#?}", who_am_i);
-            }
-            Err(e) => {
-                println!("->> FAIL {:?}", e);
-            }
-        }
-        // assert!(who_am_i.authenticated);
+        let mock = MockMonzo::start().await;
+        let monzo = mock.client();
+
+        let who_am_i = monzo.whoami().await.unwrap();
+
+        assert!(who_am_i.authenticated);
     }
 }
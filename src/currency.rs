@@ -0,0 +1,200 @@
+//! Minor unit conversion
+//!
+//! Monzo reports amounts as integers in the currency's minor unit. Most
+//! currencies have two decimal places but not all: JPY has none and KWD has
+//! three. Convert through these functions rather than dividing by 100.
+
+use rusty_money::iso;
+
+use crate::{
+    error::AppErrors as Error,
+    locale::{self, Locale},
+};
+
+/// Decimal places used when a currency isn't in the ISO 4217 table
+const DEFAULT_EXPONENT: u32 = 2;
+
+/// Number of decimal places in the currency's minor unit, e.g. 2 for GBP and
+/// 0 for JPY
+#[must_use]
+pub fn exponent(iso_code: &str) -> u32 {
+    iso::find(iso_code).map_or(DEFAULT_EXPONENT, |currency| currency.exponent)
+}
+
+/// Format an amount in minor units as a plain decimal string, e.g.
+/// -1234 GBP -> "-12.34", -600000 JPY -> "-600000"
+#[must_use]
+pub fn decimal(minor: i64, iso_code: &str) -> String {
+    let exponent = exponent(iso_code);
+    let sign = if minor < 0 { "-" } else { "" };
+    let minor = minor.unsigned_abs();
+    if exponent == 0 {
+        return format!("{sign}{minor}");
+    }
+
+    let scale = 10_u64.pow(exponent);
+    format!(
+        "{sign}{}.{:0width$}",
+        minor / scale,
+        minor % scale,
+        width = exponent as usize
+    )
+}
+
+/// Format an amount in minor units for display in the configured locale,
+/// with the currency symbol and thousands separators, e.g. -123456 GBP ->
+/// "-£1,234.56"
+///
+/// # Errors
+/// Will return an error if the currency isn't in the ISO 4217 table.
+pub fn display(minor: i64, iso_code: &str) -> Result<String, Error> {
+    display_in(minor, iso_code, locale::current())
+}
+
+/// Format an amount in minor units for display in `locale`, e.g. -123456 EUR
+/// -> "-1.234,56 €" in `de_DE`
+///
+/// # Errors
+/// Will return an error if the currency isn't in the ISO 4217 table.
+pub fn display_in(minor: i64, iso_code: &str, locale: Locale) -> Result<String, Error> {
+    let Some(currency) = iso::find(iso_code) else {
+        return Err(Error::CurrencyNotFound(iso_code.to_string()));
+    };
+
+    let decimal = decimal(minor.abs(), iso_code);
+    let (whole, fraction) = decimal.split_once('.').unwrap_or((&decimal, ""));
+    let mut number = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            number.push_str(locale.thousands_separator());
+        }
+        number.push(digit);
+    }
+    if !fraction.is_empty() {
+        number.push_str(locale.decimal_point());
+        number.push_str(fraction);
+    }
+
+    let sign = if minor < 0 { "-" } else { "" };
+    let (precedes, spaced) = locale.symbol_position();
+    let space = if spaced { " " } else { "" };
+    Ok(if precedes {
+        format!("{sign}{}{space}{number}", currency.symbol)
+    } else {
+        format!("{sign}{number}{space}{}", currency.symbol)
+    })
+}
+
+/// Format an amount in minor units as a decimal with the configured locale's
+/// decimal point, e.g. -1234 EUR -> "-12,34" in `de_DE`
+#[must_use]
+pub fn display_decimal(minor: i64, iso_code: &str) -> String {
+    locale::current().decimal(&decimal(minor, iso_code))
+}
+
+/// Convert an amount in minor units of `from` to minor units of `to`, where
+/// one unit of `from` is worth `rate` units of `to`, rounding to the nearest
+/// minor unit
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+pub fn convert(minor: i64, from: &str, to: &str, rate: f64) -> i64 {
+    let scale = f64::from(10_u32.pow(exponent(to))) / f64::from(10_u32.pow(exponent(from)));
+    (minor as f64 * rate * scale).round() as i64
+}
+
+/// Convert an amount in major units, e.g. 12.5 GBP, to minor units (1250),
+/// rounding to the nearest minor unit
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn from_major(amount: f64, iso_code: &str) -> i64 {
+    (amount * f64::from(10_u32.pow(exponent(iso_code)))).round() as i64
+}
+
+/// Parse a decimal amount such as "-1234.5" into minor units, e.g. -123450
+/// for GBP
+///
+/// # Errors
+/// Will return an error if the amount isn't a number or has more decimal
+/// places than the currency.
+pub fn parse(amount: &str, iso_code: &str) -> Result<i64, Error> {
+    let invalid = || Error::Error(format!("Invalid {iso_code} amount '{amount}'"));
+    let exponent = exponent(iso_code) as usize;
+
+    let trimmed = amount.trim().replace(',', "");
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.as_str()),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || fraction.len() > exponent
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    let minor: i64 = format!("{whole}{fraction:0<exponent$}")
+        .parse()
+        .map_err(|_| invalid())?;
+    Ok(if negative { -minor } else { minor })
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_uses_the_currency_exponent() {
+        assert_eq!(decimal(-1234, "GBP"), "-12.34");
+        assert_eq!(decimal(5, "GBP"), "0.05");
+        assert_eq!(decimal(0, "GBP"), "0.00");
+        assert_eq!(decimal(-600_000, "JPY"), "-600000");
+        assert_eq!(decimal(1234, "KWD"), "1.234");
+        assert_eq!(decimal(1234, "XXX_UNKNOWN"), "12.34");
+    }
+
+    #[test]
+    fn display_uses_the_currency_exponent() {
+        assert_eq!(display(-600_000, "JPY").unwrap(), "-¥600,000");
+        assert!(display(1, "XXX_UNKNOWN").is_err());
+    }
+
+    #[test]
+    fn display_in_follows_the_locale() {
+        let de: Locale = "de_DE".parse().unwrap();
+        let nl: Locale = "nl_NL".parse().unwrap();
+        assert_eq!(
+            display_in(-123_456, "GBP", Locale::default()).unwrap(),
+            "-£1,234.56"
+        );
+        assert_eq!(
+            display_in(123_456_789, "EUR", Locale::default()).unwrap(),
+            "€1,234,567.89"
+        );
+        assert_eq!(display_in(-123_456, "EUR", de).unwrap(), "-1.234,56 €");
+        assert_eq!(display_in(99, "EUR", nl).unwrap(), "€ 0,99");
+    }
+
+    #[test]
+    fn convert_rescales_between_exponents() {
+        assert_eq!(convert(-150_000, "JPY", "GBP", 0.0052), -78_000);
+        assert_eq!(convert(1000, "EUR", "GBP", 0.855), 855);
+        assert_eq!(convert(100, "GBP", "KWD", 0.39), 390);
+    }
+
+    #[test]
+    fn parse_uses_the_currency_exponent() {
+        assert_eq!(parse("-1234.5", "GBP").unwrap(), -123_450);
+        assert_eq!(parse("1,000", "GBP").unwrap(), 100_000);
+        assert_eq!(parse(".05", "GBP").unwrap(), 5);
+        assert_eq!(parse("600000", "JPY").unwrap(), 600_000);
+        assert!(parse("1.5", "JPY").is_err());
+        assert!(parse("12.345", "GBP").is_err());
+        assert!(parse("abc", "GBP").is_err());
+    }
+}
@@ -0,0 +1,602 @@
+//! Demo data
+//!
+//! Generates plausible accounts, pots and transactions so the reports and
+//! exports can be tried without authorising the Monzo API, and screenshots
+//! don't expose real finances. Generation is driven by a seeded random number
+//! generator: the same seed and date range always produce the same data.
+
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use fake::{faker::company::en::CompanyName, Fake};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        account::AccountForDB,
+        balance::{AccountSnapshot, Balance, PotBalance},
+        category::Category,
+        counterparty::Counterparty,
+        fx_rate::FxRate,
+        merchant::{Address, Merchant},
+        pot::Pot,
+        transaction::TransactionResponse,
+        transfer::TransferRules,
+        DatabasePool,
+    },
+};
+
+const GROCERIES: [&str; 6] = ["Tesco", "Sainsbury's", "Waitrose", "Aldi", "Lidl", "Co-op"];
+const EATING_OUT: [&str; 7] = [
+    "Pret A Manger",
+    "Greggs",
+    "Costa Coffee",
+    "Nando's",
+    "Wagamama",
+    "Dishoom",
+    "Franco Manca",
+];
+const TRANSPORT: [&str; 3] = ["Transport for London", "Trainline", "Uber"];
+const ENTERTAINMENT: [&str; 3] = ["Odeon", "Ticketmaster", "Steam"];
+const HOLIDAY: [&str; 4] = ["Carrefour", "Café de Flore", "Monoprix", "SNCF"];
+/// Friends paid and paid by over Monzo: (user id, name)
+const FRIENDS: [(&str, &str); 3] = [
+    ("user_demo_jamie", "Jamie"),
+    ("user_demo_priya", "Priya"),
+    ("user_demo_sam", "Sam"),
+];
+
+/// Fixed monthly bills: (day of month, description, merchant, category, pence)
+const BILLS: [(u32, &str, Option<&str>, &str, i64); 6] = [
+    (1, "COUNCIL TAX", None, "bills", -16_400),
+    (3, "OCTOPUS ENERGY", Some("Octopus Energy"), "bills", -9_800),
+    (8, "BT BROADBAND", Some("BT"), "bills", -3_500),
+    (12, "GIFFGAFF", Some("giffgaff"), "bills", -1_200),
+    (15, "NETFLIX.COM", Some("Netflix"), "entertainment", -1_099),
+    (20, "SPOTIFY", Some("Spotify"), "entertainment", -1_199),
+];
+
+/// What to generate
+#[derive(Debug, Clone, Copy)]
+pub struct DemoOptions {
+    /// Seed for the random number generator
+    pub seed: u64,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// What was generated
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DemoSummary {
+    pub accounts: usize,
+    pub pots: usize,
+    pub transactions: usize,
+}
+
+/// Populate an empty database with generated data
+///
+/// # Errors
+/// Will return an error if the database already contains accounts, or the data
+/// can't be saved.
+pub async fn seed(pool: DatabasePool, options: DemoOptions) -> Result<DemoSummary, Error> {
+    let account_service = pool.accounts();
+    if !account_service.read_accounts().await?.is_empty() {
+        return Err(Error::Error(
+            "The database already contains accounts. \
+             Point `database_path` at a new file or run `reset` first"
+                .into(),
+        ));
+    }
+
+    let mut generator = Generator::new(options.seed);
+    let accounts = generator.accounts(options.start);
+    let pots = generator.pots();
+    let transactions = generator.transactions(&accounts, &pots, options.start, options.end);
+
+    for account in &accounts {
+        account_service.save_account(account).await?;
+    }
+
+    let pot_service = pool.pots();
+    for pot in &pots {
+        pot_service.save_pot(pot).await?;
+    }
+
+    let category_service = pool.categories();
+    let mut categories: Vec<&str> = transactions
+        .iter()
+        .map(|tx| tx.category_id.as_str())
+        .collect();
+    categories.sort_unstable();
+    categories.dedup();
+    for id in categories {
+        let category = Category {
+            id: id.to_string(),
+            name: id.to_string(),
+            ..Default::default()
+        };
+        category_service.save_category(&category).await?;
+    }
+
+    let tx_service = pool.transactions();
+    let rate_service = pool.fx_rates();
+    for tx in &transactions {
+        tx_service.save_transaction(tx).await?;
+        if let Some(rate) = FxRate::implied(tx) {
+            rate_service.save_rate(&rate).await?;
+        }
+    }
+
+    pool.classify_transfers(&TransferRules::default()).await?;
+
+    // so `balances` has something to show without a refresh
+    pool.balances()
+        .save_snapshot(
+            at(options.end, 18, 0),
+            &snapshot(&accounts, &pots, &transactions),
+        )
+        .await?;
+
+    Ok(DemoSummary {
+        accounts: accounts.len(),
+        pots: pots.len(),
+        transactions: transactions.len(),
+    })
+}
+
+// Account balances as the net of their transactions, with the open pots
+// belonging to the personal account
+fn snapshot(
+    accounts: &[AccountForDB],
+    pots: &[Pot],
+    transactions: &[TransactionResponse],
+) -> Vec<AccountSnapshot> {
+    accounts
+        .iter()
+        .map(|account| {
+            let net: i64 = transactions
+                .iter()
+                .filter(|tx| tx.account_id == account.id)
+                .map(|tx| tx.amount)
+                .sum();
+            let pots = pots
+                .iter()
+                .filter(|pot| !pot.deleted && pot.account_name == account.owner_type)
+                .map(|pot| PotBalance {
+                    pot_id: pot.id.clone(),
+                    name: pot.name.clone(),
+                    balance: pot.balance,
+                    currency: pot.currency.clone(),
+                })
+                .collect();
+            AccountSnapshot {
+                account_id: account.id.clone(),
+                balance: Balance {
+                    balance: net,
+                    total_balance: net,
+                    currency: account.currency.clone(),
+                    spend_today: 0,
+                },
+                pots,
+            }
+        })
+        .collect()
+}
+
+struct Generator {
+    rng: ChaCha8Rng,
+    merchants: HashMap<String, Merchant>,
+}
+
+impl Generator {
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            merchants: HashMap::new(),
+        }
+    }
+
+    // A Monzo style identifier, e.g. "acc_0000" followed by random characters
+    fn id(&mut self, prefix: &str) -> String {
+        const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+        let suffix: String = (0..18)
+            .map(|_| char::from(*CHARS.choose(&mut self.rng).unwrap_or(&b'0')))
+            .collect();
+        format!("{prefix}_0000{suffix}")
+    }
+
+    fn accounts(&mut self, start: NaiveDate) -> Vec<AccountForDB> {
+        let created = start.and_time(chrono::NaiveTime::MIN) - Duration::days(30);
+        ["personal", "joint"]
+            .into_iter()
+            .map(|owner_type| AccountForDB {
+                id: self.id("acc"),
+                closed: false,
+                created,
+                description: self.id("user"),
+                currency: "GBP".to_string(),
+                country_code: "GB".to_string(),
+                owner_type: owner_type.to_string(),
+                account_number: format!("{:08}", self.rng.gen_range(0..100_000_000)),
+                sort_code: "040004".to_string(),
+            })
+            .collect()
+    }
+
+    fn pots(&mut self) -> Vec<Pot> {
+        [
+            ("Savings", false),
+            ("Holiday", false),
+            ("Old car fund", true),
+        ]
+        .into_iter()
+        .map(|(name, deleted)| Pot {
+            id: self.id("pot"),
+            name: name.to_string(),
+            balance: if deleted {
+                0
+            } else {
+                self.rng.gen_range(50_000..500_000)
+            },
+            currency: "GBP".to_string(),
+            deleted,
+            pot_type: "default".to_string(),
+            account_name: "personal".to_string(),
+        })
+        .collect()
+    }
+
+    fn merchant(&mut self, name: &str, category: &str) -> Merchant {
+        if let Some(merchant) = self.merchants.get(name) {
+            return merchant.clone();
+        }
+        // shops and restaurants are placed around London, or Paris on holiday
+        let centre = if HOLIDAY.contains(&name) {
+            Some((48.857, 2.352))
+        } else if GROCERIES.contains(&name) || EATING_OUT.contains(&name) {
+            Some((51.507, -0.128))
+        } else {
+            None
+        };
+        let address = centre.map(|(latitude, longitude)| Address {
+            latitude: latitude + self.rng.gen_range(-0.05..0.05),
+            longitude: longitude + self.rng.gen_range(-0.08..0.08),
+            formatted: name.to_string(),
+            ..Default::default()
+        });
+        let merchant = Merchant {
+            id: self.id("merch"),
+            name: name.to_string(),
+            category_id: category.to_string(),
+            logo: None,
+            address,
+        };
+        self.merchants.insert(name.to_string(), merchant.clone());
+        merchant
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn transactions(
+        &mut self,
+        accounts: &[AccountForDB],
+        pots: &[Pot],
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Vec<TransactionResponse> {
+        let personal = accounts[0].id.clone();
+        let joint = accounts[1].id.clone();
+        let savings = pots[0].id.clone();
+        let holiday_month = self.rng.gen_range(5..=9);
+
+        let mut txs = Vec::new();
+        let mut day = start;
+        while day < end {
+            let weekend = matches!(day.weekday(), Weekday::Sat | Weekday::Sun);
+
+            // -- monthly -------------------------------------------------------
+
+            if day.day() == 25 {
+                let salary = self.rng.gen_range(245_000..265_000);
+                txs.push(self.tx(&personal, day, 9, salary, "ACME LTD SALARY", None, "income"));
+                let to_joint = self.tx(
+                    &personal,
+                    day,
+                    10,
+                    -120_000,
+                    "JOINT ACCOUNT",
+                    None,
+                    "transfers",
+                );
+                let from_personal =
+                    self.tx(&joint, day, 10, 120_000, "JOINT ACCOUNT", None, "transfers");
+                txs.push(TransactionResponse {
+                    counterparty: Some(counterparty(&accounts[1])),
+                    ..to_joint
+                });
+                txs.push(TransactionResponse {
+                    counterparty: Some(counterparty(&accounts[0])),
+                    ..from_personal
+                });
+                txs.push(self.tx(&personal, day, 11, -20_000, &savings, None, "savings"));
+            }
+            if day.day() == 1 {
+                txs.push(self.tx(&joint, day, 8, -115_000, "RENT SO LETTINGS", None, "bills"));
+            }
+            for (bill_day, description, merchant, category, amount) in BILLS {
+                if day.day() == bill_day {
+                    let account = if category == "bills" {
+                        &joint
+                    } else {
+                        &personal
+                    };
+                    txs.push(self.tx(account, day, 7, amount, description, merchant, category));
+                }
+            }
+
+            // -- day to day ----------------------------------------------------
+
+            if self.rng.gen_bool(if weekend { 0.6 } else { 0.3 }) {
+                let name = *GROCERIES.choose(&mut self.rng).unwrap_or(&GROCERIES[0]);
+                let account = if self.rng.gen_bool(0.5) {
+                    &joint
+                } else {
+                    &personal
+                };
+                let amount = -self.rng.gen_range(800..9_500);
+                txs.push(self.tx(
+                    account,
+                    day,
+                    18,
+                    amount,
+                    &name.to_uppercase(),
+                    Some(name),
+                    "groceries",
+                ));
+            }
+            if self.rng.gen_bool(0.45) {
+                let name = *EATING_OUT.choose(&mut self.rng).unwrap_or(&EATING_OUT[0]);
+                let amount = -self.rng.gen_range(300..4_500);
+                txs.push(self.tx(
+                    &personal,
+                    day,
+                    13,
+                    amount,
+                    &name.to_uppercase(),
+                    Some(name),
+                    "eating_out",
+                ));
+            }
+            if !weekend && self.rng.gen_bool(0.7) {
+                let amount = -self.rng.gen_range(280..850);
+                txs.push(self.tx(
+                    &personal,
+                    day,
+                    8,
+                    amount,
+                    "TFL TRAVEL CH",
+                    Some(TRANSPORT[0]),
+                    "transport",
+                ));
+            }
+            if self.rng.gen_bool(0.04) {
+                let name = *TRANSPORT[1..]
+                    .choose(&mut self.rng)
+                    .unwrap_or(&TRANSPORT[1]);
+                let amount = -self.rng.gen_range(1_200..12_000);
+                txs.push(self.tx(
+                    &personal,
+                    day,
+                    20,
+                    amount,
+                    &name.to_uppercase(),
+                    Some(name),
+                    "transport",
+                ));
+            }
+            if self.rng.gen_bool(0.12) {
+                let name: String = CompanyName().fake_with_rng(&mut self.rng);
+                let amount = -self.rng.gen_range(500..15_000);
+                txs.push(self.tx(
+                    &personal,
+                    day,
+                    15,
+                    amount,
+                    &name.to_uppercase(),
+                    Some(&name),
+                    "shopping",
+                ));
+            }
+            if weekend && self.rng.gen_bool(0.15) {
+                let name = *ENTERTAINMENT
+                    .choose(&mut self.rng)
+                    .unwrap_or(&ENTERTAINMENT[0]);
+                let amount = -self.rng.gen_range(900..6_000);
+                txs.push(self.tx(
+                    &personal,
+                    day,
+                    19,
+                    amount,
+                    &name.to_uppercase(),
+                    Some(name),
+                    "entertainment",
+                ));
+            }
+            if weekend && self.rng.gen_bool(0.1) {
+                let (user_id, name) = *FRIENDS.choose(&mut self.rng).unwrap_or(&FRIENDS[0]);
+                let amount = self.rng.gen_range(500..4_000);
+                let amount = if self.rng.gen_bool(0.6) {
+                    -amount
+                } else {
+                    amount
+                };
+                let tx = self.tx(&personal, day, 22, amount, name, None, "general");
+                txs.push(TransactionResponse {
+                    counterparty: Some(Counterparty {
+                        name: Some(name.to_string()),
+                        user_id: Some(user_id.to_string()),
+                        ..Default::default()
+                    }),
+                    ..tx
+                });
+            }
+
+            // -- a week abroad each year ---------------------------------------
+
+            if day.month() == holiday_month && (10..17).contains(&day.day()) {
+                for _ in 0..self.rng.gen_range(1..4) {
+                    let name = *HOLIDAY.choose(&mut self.rng).unwrap_or(&HOLIDAY[0]);
+                    let local = -self.rng.gen_range(400..9_000);
+                    let mut tx = self.tx(
+                        &personal,
+                        day,
+                        14,
+                        local * 100 / 117,
+                        &name.to_uppercase(),
+                        Some(name),
+                        "holidays",
+                    );
+                    tx.local_amount = local;
+                    tx.local_currency = "EUR".to_string();
+                    txs.push(tx);
+                }
+            }
+
+            day += Duration::days(1);
+        }
+
+        txs
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn tx(
+        &mut self,
+        account_id: &str,
+        day: NaiveDate,
+        hour: u32,
+        amount: i64,
+        description: &str,
+        merchant: Option<&str>,
+        category: &str,
+    ) -> TransactionResponse {
+        let minute = self.rng.gen_range(0..60);
+        let created = Utc.from_utc_datetime(&at(day, hour, minute));
+        let merchant = merchant.map(|name| self.merchant(name, category));
+
+        TransactionResponse {
+            id: self.id("tx"),
+            account_id: account_id.to_string(),
+            merchant,
+            amount,
+            currency: "GBP".to_string(),
+            local_amount: amount,
+            local_currency: "GBP".to_string(),
+            created,
+            description: description.to_string(),
+            notes: None,
+            settled: Some(created + Duration::days(1)),
+            updated: Some(created + Duration::days(1)),
+            category_id: category.to_string(),
+            counterparty: None,
+            attachments: None,
+            decline_reason: None,
+        }
+    }
+}
+
+// The other side of a transfer to or from `account`
+fn counterparty(account: &AccountForDB) -> Counterparty {
+    Counterparty {
+        account_number: Some(account.account_number.clone()),
+        sort_code: Some(account.sort_code.clone()),
+        name: Some(account.description.clone()),
+        ..Default::default()
+    }
+}
+
+fn at(day: NaiveDate, hour: u32, minute: u32) -> NaiveDateTime {
+    day.and_hms_opt(hour, minute, 0)
+        .unwrap_or(day.and_time(chrono::NaiveTime::MIN))
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    async fn empty_db() -> (DatabasePool, temp_dir::TempDir) {
+        let dir = temp_dir::TempDir::with_prefix("monzo-demo").unwrap();
+        let db_path = dir.path().join("demo.db?mode=rwc");
+        let pool = DatabasePool::new(db_path.to_str().unwrap(), 1)
+            .await
+            .unwrap();
+        (pool, dir)
+    }
+
+    fn options(seed: u64) -> DemoOptions {
+        DemoOptions {
+            seed,
+            start: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+        }
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let generate = |seed| {
+            let mut generator = Generator::new(seed);
+            let accounts = generator.accounts(options(seed).start);
+            let pots = generator.pots();
+            generator.transactions(&accounts, &pots, options(seed).start, options(seed).end)
+        };
+
+        let first = generate(7);
+        let second = generate(7);
+        let other = generate(8);
+
+        assert!(!first.is_empty());
+        assert_eq!(
+            first
+                .iter()
+                .map(|tx| (&tx.id, tx.amount))
+                .collect::<Vec<_>>(),
+            second
+                .iter()
+                .map(|tx| (&tx.id, tx.amount))
+                .collect::<Vec<_>>()
+        );
+        assert_ne!(first[0].id, other[0].id);
+    }
+
+    #[tokio::test]
+    async fn seed_populates_an_empty_database() {
+        // Arrange
+        let (pool, _tmp) = empty_db().await;
+
+        // Act
+        let summary = seed(pool.clone(), options(42)).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.accounts, 2);
+        assert_eq!(summary.pots, 3);
+        let txs = pool.transactions().read_transactions().await.unwrap();
+        assert_eq!(txs.len(), summary.transactions);
+        let snapshot = pool
+            .balances()
+            .read_latest_snapshot()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.accounts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn seed_refuses_a_populated_database() {
+        let (pool, _tmp) = test_db().await;
+
+        assert!(seed(pool, options(42)).await.is_err());
+    }
+}
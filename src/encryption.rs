@@ -0,0 +1,194 @@
+//! Encryption of exports and backups
+//!
+//! Exports and backups written to shared drives or cloud storage can be
+//! encrypted to an [age](https://age-encryption.org) recipient, natively, or
+//! to a key in the gpg keyring with the `gpg` command, so only the holder of
+//! the matching private key can read them.
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use crate::error::AppErrors as Error;
+
+/// How a file is encrypted
+#[derive(Debug, Clone)]
+pub enum Encryption {
+    /// To an age public key, `age1...`
+    Age(age::x25519::Recipient),
+    /// To a key in the gpg keyring, by id, fingerprint or email
+    Gpg(String),
+}
+
+impl Encryption {
+    /// The encryption chosen by `--encrypt age:<recipient>` or `--gpg <keyid>`,
+    /// if either
+    ///
+    /// # Errors
+    /// Will return an error if both are given or the recipient isn't valid.
+    pub fn from_options(encrypt: Option<&str>, gpg: Option<&str>) -> Result<Option<Self>, Error> {
+        match (encrypt, gpg) {
+            (Some(_), Some(_)) => Err(Error::Error(
+                "Choose one of --encrypt and --gpg".to_string(),
+            )),
+            (Some(encrypt), None) => encrypt.parse().map(Some),
+            (None, Some(key)) => Ok(Some(Self::Gpg(key.to_string()))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The extension of encrypted files
+    #[must_use]
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Age(_) => "age",
+            Self::Gpg(_) => "gpg",
+        }
+    }
+
+    /// Encrypt `plaintext`
+    ///
+    /// # Errors
+    /// Will return an error if encryption fails or `gpg` can't be run.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Age(recipient) => age::encrypt(recipient, plaintext)
+                .map_err(|e| Error::Error(format!("Can't encrypt: {e}"))),
+            Self::Gpg(key) => gpg(
+                &["--encrypt", "--recipient", key, "--trust-model", "always"],
+                plaintext,
+            ),
+        }
+    }
+}
+
+impl FromStr for Encryption {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("age", recipient)) => age::x25519::Recipient::from_str(recipient)
+                .map(Self::Age)
+                .map_err(|e| Error::Error(format!("Invalid age recipient {recipient}: {e}"))),
+            Some(("gpg", key)) => Ok(Self::Gpg(key.to_string())),
+            _ => Err(Error::Error(format!(
+                "Unknown encryption {s}: use age:<recipient> or gpg:<keyid>"
+            ))),
+        }
+    }
+}
+
+/// Decrypt a file ending in `.age`, with the identities in `identity_file`,
+/// or `.gpg`, with the gpg keyring. Other files are returned unchanged.
+///
+/// # Errors
+/// Will return an error if an age file is given no identity file or can't be
+/// decrypted with it, or `gpg` fails.
+pub fn decrypt(name: &str, data: Vec<u8>, identity_file: Option<&Path>) -> Result<Vec<u8>, Error> {
+    let extension = Path::new(name)
+        .extension()
+        .map(std::ffi::OsStr::to_ascii_lowercase);
+    if extension
+        .as_ref()
+        .is_some_and(|extension| extension == "gpg")
+    {
+        return gpg(&["--decrypt"], &data);
+    }
+    if extension.is_none_or(|extension| extension != "age") {
+        return Ok(data);
+    }
+
+    let Some(identity_file) = identity_file else {
+        return Err(Error::Error(format!(
+            "{name} is encrypted with age: give its identity file with --identity"
+        )));
+    };
+    let failed = |e: &dyn std::fmt::Display| Error::Error(format!("Can't decrypt {name}: {e}"));
+    let identities = age::IdentityFile::from_file(identity_file.display().to_string())?
+        .into_identities()
+        .map_err(|e| failed(&e))?;
+    let decryptor = age::Decryptor::new_buffered(&data[..]).map_err(|e| failed(&e))?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|identity| identity.as_ref() as _))
+        .map_err(|e| failed(&e))?;
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)?;
+
+    Ok(plaintext)
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// Run gpg with `args`, piping `input` through it
+fn gpg(args: &[&str], input: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut child = Command::new("gpg")
+        .args(["--batch", "--yes", "--quiet", "--output", "-"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Error(format!("Can't run gpg: {e}")))?;
+
+    // Write from another thread so gpg can't block on a full stdout
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    writer.join().expect("the writer doesn't panic")?;
+
+    if !output.status.success() {
+        return Err(Error::Error(format!(
+            "gpg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(output.stdout)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use age::secrecy::ExposeSecret;
+    use temp_dir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn encrypts_to_an_age_recipient() {
+        // Arrange
+        let identity = age::x25519::Identity::generate();
+        let dir = TempDir::new().unwrap();
+        let identity_file = dir.child("key.txt");
+        std::fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+        let encryption =
+            Encryption::from_options(Some(&format!("age:{}", identity.to_public())), None)
+                .unwrap()
+                .unwrap();
+
+        // Act
+        let ciphertext = encryption.encrypt(b"2024-07-03 * \"Tesco\"").unwrap();
+        let plaintext = decrypt("export.age", ciphertext.clone(), Some(&identity_file)).unwrap();
+
+        // Assert
+        assert!(!ciphertext.windows(5).any(|w| w == b"Tesco"));
+        assert_eq!(plaintext, b"2024-07-03 * \"Tesco\"");
+        assert!(decrypt("export.age", ciphertext, None).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_encryption() {
+        assert!("age:not-a-key".parse::<Encryption>().is_err());
+        assert!("rot13:key".parse::<Encryption>().is_err());
+        assert!(Encryption::from_options(Some("gpg:me"), Some("me")).is_err());
+        assert!(matches!(
+            Encryption::from_options(None, Some("me@example.com")),
+            Ok(Some(Encryption::Gpg(key))) if key == "me@example.com"
+        ));
+    }
+}
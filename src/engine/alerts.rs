@@ -0,0 +1,127 @@
+//! Balance alerts
+//!
+//! Compares balances with the minimums under `[alerts.min_balance]`. Each key
+//! names an account, by id or type (e.g. `personal`), or a pot by name, and
+//! the value is the lowest balance allowed in major units.
+
+use std::collections::BTreeMap;
+
+use crate::{currency, engine::BalanceReport};
+
+/// An account or pot whose balance is below its minimum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceAlert {
+    /// The configured name that matched
+    pub name: String,
+    pub balance: i64,
+    pub minimum: i64,
+    pub currency: String,
+}
+
+/// The accounts and pots in `report` below their minimum in `min_balance`
+#[must_use]
+pub fn low_balances(
+    report: &BalanceReport,
+    min_balance: &BTreeMap<String, f64>,
+) -> Vec<BalanceAlert> {
+    let mut alerts = Vec::new();
+    for (name, minimum) in min_balance {
+        for account in &report.accounts {
+            let matches_account = account.account.id == *name
+                || account.account.owner_type.eq_ignore_ascii_case(name);
+            if matches_account {
+                push_if_low(
+                    &mut alerts,
+                    name,
+                    account.balance.balance,
+                    *minimum,
+                    &account.balance.currency,
+                );
+            }
+            for pot in &account.pots {
+                if pot.pot_id == *name || pot.name.eq_ignore_ascii_case(name) {
+                    push_if_low(&mut alerts, name, pot.balance, *minimum, &pot.currency);
+                }
+            }
+        }
+    }
+    alerts
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn push_if_low(alerts: &mut Vec<BalanceAlert>, name: &str, balance: i64, minimum: f64, code: &str) {
+    let minimum = currency::from_major(minimum, code);
+    if balance < minimum {
+        alerts.push(BalanceAlert {
+            name: name.to_string(),
+            balance,
+            minimum,
+            currency: code.to_string(),
+        });
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        engine::AccountBalance,
+        model::{
+            account::AccountForDB,
+            balance::{Balance, PotBalance},
+        },
+    };
+
+    fn report() -> BalanceReport {
+        BalanceReport {
+            accounts: vec![AccountBalance {
+                account: AccountForDB {
+                    id: "acc_1".to_string(),
+                    owner_type: "personal".to_string(),
+                    ..Default::default()
+                },
+                balance: Balance {
+                    balance: 4_500,
+                    currency: "GBP".to_string(),
+                    ..Default::default()
+                },
+                pots: vec![PotBalance {
+                    pot_id: "pot_1".to_string(),
+                    name: "Bills".to_string(),
+                    balance: 20_000,
+                    currency: "GBP".to_string(),
+                }],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn alerts_on_accounts_and_pots_below_their_minimum() {
+        // Arrange
+        let min_balance = BTreeMap::from([
+            ("Personal".to_string(), 50.0),
+            ("bills".to_string(), 250.0),
+            ("holiday".to_string(), 100.0),
+        ]);
+
+        // Act
+        let alerts = low_balances(&report(), &min_balance);
+
+        // Assert
+        let names: Vec<_> = alerts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["Personal", "bills"]);
+        assert_eq!(alerts[0].minimum, 5_000);
+        assert_eq!(alerts[1].balance, 20_000);
+    }
+
+    #[test]
+    fn no_alerts_at_or_above_the_minimum() {
+        let min_balance = BTreeMap::from([("acc_1".to_string(), 45.0)]);
+
+        assert!(low_balances(&report(), &min_balance).is_empty());
+    }
+}
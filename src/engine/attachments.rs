@@ -0,0 +1,180 @@
+//! Attachment downloads
+//!
+//! [`download_attachments`] saves the stored attachments of transactions under
+//! a directory as `<year>/<month>/<transaction id>-<n>.<ext>`, numbered in the
+//! order they were attached. Attachments already downloaded are skipped, and a
+//! file with the same content as one already saved is recorded at that path
+//! rather than written again.
+
+use std::{collections::HashMap, path::Path};
+
+use chrono_tz::Tz;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::AppErrors as Error,
+    model::{attachment::Attachment, DatabasePool},
+    timezone::local_date,
+};
+
+/// What downloading attachments did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentDownloads {
+    /// Files written
+    pub downloaded: usize,
+    /// Attachments whose file was downloaded before
+    pub existing: usize,
+    /// Attachments with the same content as a file already saved
+    pub duplicates: usize,
+}
+
+/// Download the stored attachments that haven't been downloaded into `dir`
+///
+/// # Errors
+/// Will return errors if the database can't be read, a file can't be fetched
+/// or it can't be written.
+pub async fn download_attachments(
+    pool: &DatabasePool,
+    dir: &Path,
+    timezone: Tz,
+) -> Result<AttachmentDownloads, Error> {
+    let service = pool.attachments();
+    let client = reqwest::Client::new();
+    let mut downloads = AttachmentDownloads::default();
+    let mut numbers: HashMap<String, usize> = HashMap::new();
+
+    for attachment in service.read_attachments().await? {
+        let number = numbers
+            .entry(attachment.transaction_id.clone())
+            .and_modify(|n| *n += 1)
+            .or_insert(1);
+        if attachment
+            .path
+            .as_deref()
+            .is_some_and(|p| Path::new(p).exists())
+        {
+            downloads.existing += 1;
+            continue;
+        }
+
+        let contents = client
+            .get(&attachment.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let sha256 = format!("{:x}", Sha256::digest(&contents));
+
+        if let Some(path) = service.find_download(&sha256).await? {
+            if Path::new(&path).exists() {
+                service.set_download(&attachment.id, &path, &sha256).await?;
+                downloads.duplicates += 1;
+                continue;
+            }
+        }
+
+        let path = dir.join(file_name(&attachment, *number, timezone));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &contents)?;
+        service
+            .set_download(&attachment.id, &path.to_string_lossy(), &sha256)
+            .await?;
+        downloads.downloaded += 1;
+    }
+
+    Ok(downloads)
+}
+
+// `<year>/<month>/<transaction id>-<n>.<ext>`, dated by the transaction
+fn file_name(attachment: &Attachment, number: usize, timezone: Tz) -> String {
+    let date = local_date(attachment.transaction_created, timezone);
+    format!(
+        "{}/{}-{number}.{}",
+        date.format("%Y/%m"),
+        attachment.transaction_id,
+        extension(&attachment.file_type)
+    )
+}
+
+// The file extension for a MIME type
+fn extension(file_type: &str) -> &str {
+    match file_type {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "application/pdf" => "pdf",
+        other => other
+            .strip_prefix("image/")
+            .filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or("bin"),
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use wiremock::{matchers::path, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::{model::attachment::AttachmentResponse, tests::test::test_db};
+
+    #[tokio::test]
+    async fn downloads_each_file_once() {
+        // Arrange
+        let (pool, tmp) = test_db().await;
+        let server = MockServer::start().await;
+        Mock::given(path("/receipt.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"receipt".to_vec()))
+            .mount(&server)
+            .await;
+        let attachment = |id: &str| AttachmentResponse {
+            id: id.to_string(),
+            file_url: format!("{}/receipt.jpg", server.uri()),
+            file_type: "image/jpeg".to_string(),
+            created: Utc.with_ymd_and_hms(2024, 5, 2, 12, 0, 0).unwrap(),
+        };
+        let service = pool.attachments();
+        service
+            .save_attachments("1", &[attachment("attach_1")])
+            .await
+            .unwrap();
+        service
+            .save_attachments("2", &[attachment("attach_2")])
+            .await
+            .unwrap();
+        let dir = tmp.path().join("attachments");
+
+        // Act
+        let first = download_attachments(&pool, &dir, Tz::UTC).await.unwrap();
+        let second = download_attachments(&pool, &dir, Tz::UTC).await.unwrap();
+
+        // Assert
+        let saved = dir.join("1970/01/1-1.jpg");
+        assert_eq!(std::fs::read(&saved).unwrap(), b"receipt");
+        assert!(!dir.join("1970/01/2-1.jpg").exists());
+        assert_eq!(
+            first,
+            AttachmentDownloads {
+                downloaded: 1,
+                existing: 0,
+                duplicates: 1,
+            }
+        );
+        assert_eq!(second.existing, 2);
+        let attachments = service.read_attachments().await.unwrap();
+        assert!(attachments
+            .iter()
+            .all(|a| a.path.as_deref() == Some(saved.to_str().unwrap())));
+    }
+
+    #[test]
+    fn extensions_follow_the_file_type() {
+        assert_eq!(extension("image/jpeg"), "jpg");
+        assert_eq!(extension("image/png"), "png");
+        assert_eq!(extension("application/pdf"), "pdf");
+        assert_eq!(extension("text/plain"), "bin");
+    }
+}
@@ -0,0 +1,286 @@
+//! Category consistency checks
+//!
+//! `categories.yaml` names Monzo's custom categories, and the configuration
+//! refers to categories by name. The two drift apart as Monzo adds custom
+//! categories: a category stored before it was named in `categories.yaml`
+//! keeps its id as its name, and rules can name categories that never occur.
+//! [`CategoryAudit`] finds these problems and fixes those the database can.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    configuration::{Budgets, CategoryDisplay, Transfers},
+    error::AppErrors as Error,
+    model::{category::Category, DatabasePool},
+};
+
+/// Prefix of the ids Monzo gives custom categories
+const CUSTOM: &str = "category_";
+
+/// A category id used by transactions but missing from the categories table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingCategory {
+    pub id: String,
+    /// The name in `categories.yaml`, or else the id
+    pub name: String,
+    pub count: i64,
+}
+
+/// A category whose stored name differs from `categories.yaml`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleName {
+    pub id: String,
+    pub stored: String,
+    pub configured: String,
+}
+
+/// A setting naming a category that doesn't exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownReference {
+    /// The configuration section, e.g. `budgets.monthly`
+    pub setting: String,
+    pub name: String,
+}
+
+/// What fixing an audit changed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AuditFixes {
+    pub created: usize,
+    pub renamed: usize,
+}
+
+/// The problems found in the stored categories and their configuration
+#[derive(Debug, Default, Clone)]
+pub struct CategoryAudit {
+    /// Category ids used by transactions or card events that aren't stored.
+    /// Fixed by storing them.
+    pub missing: Vec<MissingCategory>,
+    /// Stored names that differ from `categories.yaml`. Fixed by renaming.
+    pub stale: Vec<StaleName>,
+    /// Custom categories without a name in `categories.yaml`
+    pub unnamed: Vec<String>,
+    /// `categories.yaml` entries, id and name, that no transaction uses
+    pub unused: Vec<(String, String)>,
+    /// Settings naming a category that doesn't exist
+    pub unknown: Vec<UnknownReference>,
+    /// Rules that contradict each other
+    pub conflicts: Vec<String>,
+}
+
+impl CategoryAudit {
+    /// Check the stored categories against `custom_categories`, the
+    /// `categories.yaml` names keyed by lowercase id, and the categories
+    /// named by the `[categories]`, `[budgets]` and `[transfers]` settings
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read.
+    pub async fn run(
+        pool: &DatabasePool,
+        custom_categories: &HashMap<String, String>,
+        display: &BTreeMap<String, CategoryDisplay>,
+        budgets: &Budgets,
+        transfers: &Transfers,
+    ) -> Result<Self, Error> {
+        let service = pool.categories();
+        let categories = service.read_categories().await?;
+        let usage = service.read_usage().await?;
+        let stored: HashMap<String, &Category> = categories
+            .iter()
+            .map(|c| (c.id.to_lowercase(), c))
+            .collect();
+        let used: HashMap<String, i64> = usage
+            .iter()
+            .map(|u| (u.id.to_lowercase(), u.count))
+            .collect();
+
+        let mut audit = Self::default();
+        for u in &usage {
+            if !stored.contains_key(&u.id.to_lowercase()) {
+                audit.missing.push(MissingCategory {
+                    id: u.id.clone(),
+                    name: custom_categories
+                        .get(&u.id.to_lowercase())
+                        .cloned()
+                        .unwrap_or_else(|| u.id.clone()),
+                    count: u.count,
+                });
+            }
+        }
+        for category in &categories {
+            match custom_categories.get(&category.id.to_lowercase()) {
+                Some(configured) if *configured != category.name => {
+                    audit.stale.push(StaleName {
+                        id: category.id.clone(),
+                        stored: category.name.clone(),
+                        configured: configured.clone(),
+                    });
+                }
+                None if category.id.starts_with(CUSTOM) && category.name == category.id => {
+                    audit.unnamed.push(category.id.clone());
+                }
+                _ => (),
+            }
+        }
+
+        let mut configured: Vec<_> = custom_categories.iter().collect();
+        configured.sort();
+        for (id, name) in &configured {
+            if !used.contains_key(*id) {
+                audit.unused.push(((*id).clone(), (*name).clone()));
+            }
+        }
+
+        // names as they will be once fixed, so renames aren't reported twice
+        let exists = |name: &str| {
+            categories.iter().any(|c| {
+                c.id.eq_ignore_ascii_case(name)
+                    || c.name.eq_ignore_ascii_case(name)
+                    || custom_categories
+                        .get(&c.id.to_lowercase())
+                        .is_some_and(|n| n.eq_ignore_ascii_case(name))
+            })
+        };
+        let references = display
+            .keys()
+            .map(|name| ("categories", name))
+            .chain(budgets.monthly.keys().map(|n| ("budgets.monthly", n)))
+            .chain(budgets.envelopes.keys().map(|n| ("budgets.envelopes", n)))
+            .chain(
+                transfers
+                    .categories
+                    .iter()
+                    .map(|n| ("transfers.categories", n)),
+            );
+        for (setting, name) in references {
+            if !exists(name) {
+                audit.unknown.push(UnknownReference {
+                    setting: setting.to_string(),
+                    name: name.clone(),
+                });
+            }
+        }
+
+        let mut names: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for (id, name) in &configured {
+            names.entry(name.to_lowercase()).or_default().push(id);
+        }
+        for (name, ids) in names.iter().filter(|(_, ids)| ids.len() > 1) {
+            audit.conflicts.push(format!(
+                "categories.yaml names {} '{name}', so rules by name apply to all of them",
+                ids.join(", ")
+            ));
+        }
+        for budgeted in budgets.monthly.keys() {
+            if transfers
+                .categories
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(budgeted))
+            {
+                audit.conflicts.push(format!(
+                    "'{budgeted}' has a budget but is a transfer category, so nothing counts as spent"
+                ));
+            }
+        }
+
+        Ok(audit)
+    }
+
+    /// Whether no problems were found
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty()
+            && self.stale.is_empty()
+            && self.unnamed.is_empty()
+            && self.unused.is_empty()
+            && self.unknown.is_empty()
+            && self.conflicts.is_empty()
+    }
+
+    /// Store the missing categories and rename the stale ones. The other
+    /// problems need the configuration to change.
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be written.
+    pub async fn fix(&self, pool: &DatabasePool) -> Result<AuditFixes, Error> {
+        let service = pool.categories();
+        let mut fixes = AuditFixes::default();
+
+        for missing in &self.missing {
+            let category = Category {
+                id: missing.id.clone(),
+                name: missing.name.clone(),
+                ..Default::default()
+            };
+            match service.save_category(&category).await {
+                Ok(()) | Err(Error::Duplicate(_)) => fixes.created += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        for stale in &self.stale {
+            service
+                .rename_category(&stale.id, &stale.configured)
+                .await?;
+            fixes.renamed += 1;
+        }
+
+        Ok(fixes)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    async fn audit(pool: &DatabasePool, custom: &HashMap<String, String>) -> CategoryAudit {
+        let budgets = Budgets {
+            monthly: BTreeMap::from([("Car".to_string(), 100.0), ("Horses".to_string(), 50.0)]),
+            ..Default::default()
+        };
+        CategoryAudit::run(
+            pool,
+            custom,
+            &BTreeMap::new(),
+            &budgets,
+            &Transfers::default(),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn finds_and_fixes_drift() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = pool.categories();
+        service.ensure_category("category_0000Car").await.unwrap();
+        service.ensure_category("category_0000New").await.unwrap();
+        let custom = HashMap::from([
+            ("category_0000car".to_string(), "Car".to_string()),
+            ("category_0000old".to_string(), "Gone".to_string()),
+        ]);
+
+        // Act
+        let before = audit(&pool, &custom).await;
+        let fixes = before.fix(&pool).await.unwrap();
+        let after = audit(&pool, &custom).await;
+
+        // Assert
+        assert_eq!(before.stale.len(), 1);
+        assert_eq!(before.stale[0].configured, "Car");
+        assert_eq!(before.unnamed, vec!["category_0000New"]);
+        assert_eq!(
+            before.unused,
+            vec![
+                ("category_0000car".to_string(), "Car".to_string()),
+                ("category_0000old".to_string(), "Gone".to_string())
+            ]
+        );
+        assert_eq!(before.unknown.len(), 1);
+        assert_eq!(before.unknown[0].name, "Horses");
+        assert_eq!(fixes.renamed, 1);
+        assert!(after.stale.is_empty());
+    }
+}
@@ -0,0 +1,470 @@
+//! Monthly category budgets
+//!
+//! Each category under `[budgets.monthly]` gets a fixed amount per month. With
+//! `rollover`, whatever is left at the end of a month is added to the next
+//! month's budget, counting from the configured `start_date`; overspending
+//! isn't carried. Transfers don't count as spending, and refunds reduce it.
+//!
+//! Categories under `[budgets.envelopes]` keep their budget in a pot. The pot
+//! should hold what's left of the month's budget, and any shortfall can be
+//! topped up from the pot's account.
+//!
+//! A category reaching one of the [`ALERT_THRESHOLDS`] of its budget can be
+//! posted to the Monzo feed.
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::{
+    configuration::Budgets,
+    currency,
+    engine::BalanceReport,
+    error::AppErrors as Error,
+    model::{transaction::CategoryTotal, DatabasePool},
+    timezone::start_of_day,
+};
+
+/// Percentages of a budget at which an alert is posted
+pub const ALERT_THRESHOLDS: [i64; 2] = [80, 100];
+
+/// A category's budget for one month, in minor units
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetStatus {
+    pub category: String,
+    pub currency: String,
+    pub budget: i64,
+    /// Left over from previous months when rolling over
+    pub carried: i64,
+    /// Net spending in the category, as a positive number
+    pub spent: i64,
+}
+
+impl BudgetStatus {
+    /// The budget including anything carried over
+    #[must_use]
+    pub fn available(&self) -> i64 {
+        self.budget + self.carried
+    }
+
+    /// What's left to spend, negative if overspent
+    #[must_use]
+    pub fn remaining(&self) -> i64 {
+        self.available() - self.spent
+    }
+
+    /// The highest of the [`ALERT_THRESHOLDS`] that spending has reached
+    #[must_use]
+    pub fn threshold_reached(&self) -> Option<i64> {
+        if self.spent <= 0 {
+            return None;
+        }
+        let percent = if self.available() > 0 {
+            self.spent * 100 / self.available()
+        } else {
+            100
+        };
+        ALERT_THRESHOLDS
+            .into_iter()
+            .rev()
+            .find(|threshold| percent >= *threshold)
+    }
+}
+
+/// What the month's budgets leave for one day, in minor units
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DailyAllowance {
+    pub currency: String,
+    /// What's left of the budgets at the start of the day, spread over the
+    /// days left in the month
+    pub allowance: i64,
+    /// Spending in the budgeted categories during the day, as a positive
+    /// number
+    pub spent: i64,
+}
+
+impl DailyAllowance {
+    /// What's left to spend today, negative if overspent
+    #[must_use]
+    pub fn remaining(&self) -> i64 {
+        self.allowance - self.spent
+    }
+}
+
+/// A pot holding a category's budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub category: String,
+    pub pot_id: String,
+    pub pot_name: String,
+    /// The account the pot belongs to, which top-ups come from
+    pub account_id: String,
+    pub balance: i64,
+    /// What's left of the month's budget, which the pot should hold
+    pub target: i64,
+    pub currency: String,
+}
+
+impl Envelope {
+    /// The amount needed to bring the pot up to its target
+    #[must_use]
+    pub fn top_up(&self) -> i64 {
+        (self.target - self.balance).max(0)
+    }
+}
+
+/// Works out budgets from the stored transactions
+pub struct BudgetPlan {
+    pool: DatabasePool,
+    budgets: Budgets,
+    currency: String,
+    start: NaiveDate,
+    timezone: Tz,
+}
+
+impl BudgetPlan {
+    /// Budgets in `currency`, rolling over from the month containing `start`
+    #[must_use]
+    pub fn new(
+        pool: DatabasePool,
+        budgets: Budgets,
+        currency: &str,
+        start: NaiveDate,
+        timezone: Tz,
+    ) -> Self {
+        Self {
+            pool,
+            budgets,
+            currency: currency.to_string(),
+            start: first_of_month(start),
+            timezone,
+        }
+    }
+
+    /// The currency budgets are kept in
+    #[must_use]
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Budget, carry over and spending for each category in the month
+    /// containing `month`
+    ///
+    /// # Errors
+    /// Will return an error if no budgets are configured or the database
+    /// cannot be read.
+    pub async fn status(&self, month: NaiveDate) -> Result<Vec<BudgetStatus>, Error> {
+        if self.budgets.monthly.is_empty() {
+            return Err(Error::Error(
+                "No budgets configured. Add them under `[budgets.monthly]`".into(),
+            ));
+        }
+
+        let month = first_of_month(month);
+        let mut current = if self.budgets.rollover {
+            self.start.min(month)
+        } else {
+            month
+        };
+        let mut carried = vec![0; self.budgets.monthly.len()];
+        loop {
+            let statuses = self.month_status(current, &carried).await?;
+            if current >= month {
+                return Ok(statuses);
+            }
+            carried = statuses.iter().map(|s| s.remaining().max(0)).collect();
+            current = next_month(current);
+        }
+    }
+
+    /// What the budgets leave to spend on `day`, or `None` if no budgets are
+    /// configured
+    ///
+    /// # Errors
+    /// Will return an error if the database cannot be read.
+    pub async fn daily_allowance(&self, day: NaiveDate) -> Result<Option<DailyAllowance>, Error> {
+        if self.budgets.monthly.is_empty() {
+            return Ok(None);
+        }
+
+        let remaining: i64 = self
+            .status(day)
+            .await?
+            .iter()
+            .map(BudgetStatus::remaining)
+            .sum();
+        let totals = self
+            .pool
+            .transactions()
+            .read_category_totals(
+                start_of_day(day, self.timezone),
+                start_of_day(day + Duration::days(1), self.timezone) - Duration::nanoseconds(1),
+            )
+            .await?;
+        let spent: i64 = self
+            .budgets
+            .monthly
+            .keys()
+            .map(|category| self.spent(&totals, category))
+            .sum();
+        let days_left = (next_month(first_of_month(day)) - day).num_days();
+
+        Ok(Some(DailyAllowance {
+            currency: self.currency.clone(),
+            allowance: (remaining + spent).max(0) / days_left.max(1),
+            spent,
+        }))
+    }
+
+    /// The envelope pots in `report` with what's left of their budgets
+    ///
+    /// # Errors
+    /// Will return an error if an envelope names a category without a budget
+    /// or a pot that isn't in the report.
+    pub fn envelopes(
+        &self,
+        statuses: &[BudgetStatus],
+        report: &BalanceReport,
+    ) -> Result<Vec<Envelope>, Error> {
+        let mut envelopes = Vec::new();
+        for (category, pot_name) in &self.budgets.envelopes {
+            let status = statuses
+                .iter()
+                .find(|s| s.category.eq_ignore_ascii_case(category))
+                .ok_or_else(|| {
+                    Error::Error(format!("Envelope '{category}' has no monthly budget"))
+                })?;
+            let (account, pot) = report
+                .accounts
+                .iter()
+                .flat_map(|a| a.pots.iter().map(move |p| (a, p)))
+                .find(|(_, p)| p.pot_id == *pot_name || p.name.eq_ignore_ascii_case(pot_name))
+                .ok_or_else(|| Error::Error(format!("No open pot named '{pot_name}'")))?;
+
+            envelopes.push(Envelope {
+                category: status.category.clone(),
+                pot_id: pot.pot_id.clone(),
+                pot_name: pot.name.clone(),
+                account_id: account.account.id.clone(),
+                balance: pot.balance,
+                target: status.remaining().max(0),
+                currency: pot.currency.clone(),
+            });
+        }
+
+        Ok(envelopes)
+    }
+
+    async fn month_status(
+        &self,
+        month: NaiveDate,
+        carried: &[i64],
+    ) -> Result<Vec<BudgetStatus>, Error> {
+        let from = start_of_day(month, self.timezone);
+        let until = start_of_day(next_month(month), self.timezone) - Duration::nanoseconds(1);
+        let totals = self
+            .pool
+            .transactions()
+            .read_category_totals(from, until)
+            .await?;
+
+        Ok(self
+            .budgets
+            .monthly
+            .iter()
+            .zip(carried)
+            .map(|((category, amount), carried)| BudgetStatus {
+                category: category.clone(),
+                currency: self.currency.clone(),
+                budget: currency::from_major(*amount, &self.currency),
+                carried: *carried,
+                spent: self.spent(&totals, category),
+            })
+            .collect())
+    }
+
+    // Net spending in `category` among `totals`, as a positive number
+    fn spent(&self, totals: &[CategoryTotal], category: &str) -> i64 {
+        totals
+            .iter()
+            .filter(|t| {
+                t.category_name.eq_ignore_ascii_case(category) && t.currency == self.currency
+            })
+            .map(|t| -t.total)
+            .sum::<i64>()
+            .max(0)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+fn next_month(month: NaiveDate) -> NaiveDate {
+    month
+        .checked_add_months(Months::new(1))
+        .unwrap_or(NaiveDate::MAX)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{
+        engine::AccountBalance,
+        model::{
+            account::AccountForDB,
+            balance::{Balance, PotBalance},
+            transaction::TransactionResponse,
+        },
+        tests::test::test_db,
+    };
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn budgets(rollover: bool) -> Budgets {
+        Budgets {
+            rollover,
+            monthly: BTreeMap::from([("Category_1".to_string(), 100.0)]),
+            envelopes: BTreeMap::from([("category_1".to_string(), "Food".to_string())]),
+            ..Default::default()
+        }
+    }
+
+    async fn spend(pool: &DatabasePool, id: &str, day: &str, amount: i64) {
+        let tx = TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount,
+            currency: "GBP".to_string(),
+            created: Utc.from_utc_datetime(&date(day).and_hms_opt(12, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        pool.transactions().save_transaction(&tx).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rolls_over_what_is_left_but_not_overspending() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        spend(&pool, "jan", "2024-01-10", -6_000).await;
+        spend(&pool, "feb", "2024-02-10", -16_000).await;
+        spend(&pool, "mar", "2024-03-31", -2_500).await;
+        spend(&pool, "refund", "2024-03-15", 500).await;
+
+        // Act
+        let with = BudgetPlan::new(
+            pool.clone(),
+            budgets(true),
+            "GBP",
+            date("2024-01-05"),
+            Tz::UTC,
+        )
+        .status(date("2024-03-20"))
+        .await
+        .unwrap();
+        let without = BudgetPlan::new(pool, budgets(false), "GBP", date("2024-01-05"), Tz::UTC)
+            .status(date("2024-02-01"))
+            .await
+            .unwrap();
+
+        // Assert
+        // January leaves 40.00, February spends 160.00 of 140.00
+        assert_eq!(with[0].carried, 0);
+        assert_eq!(with[0].spent, 2_000);
+        assert_eq!(with[0].remaining(), 8_000);
+        assert_eq!(without[0].carried, 0);
+        assert_eq!(without[0].remaining(), -6_000);
+    }
+
+    #[tokio::test]
+    async fn daily_allowance_spreads_what_was_left_this_morning() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        spend(&pool, "early", "2024-06-03", -4_000).await;
+        spend(&pool, "today", "2024-06-21", -500).await;
+
+        // Act
+        let allowance = BudgetPlan::new(pool, budgets(false), "GBP", date("2024-01-05"), Tz::UTC)
+            .daily_allowance(date("2024-06-21"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Assert
+        // 60.00 left over the 10 days from the 21st
+        assert_eq!(allowance.allowance, 600);
+        assert_eq!(allowance.spent, 500);
+        assert_eq!(allowance.remaining(), 100);
+    }
+
+    #[test]
+    fn thresholds_are_reached_at_80_and_100_percent() {
+        // Arrange
+        let status = |spent| BudgetStatus {
+            category: "Category_1".to_string(),
+            currency: "GBP".to_string(),
+            budget: 8_000,
+            carried: 2_000,
+            spent,
+        };
+
+        // Act
+        let reached: Vec<_> = [0, 7_999, 8_000, 9_999, 12_000]
+            .into_iter()
+            .map(|spent| status(spent).threshold_reached())
+            .collect();
+
+        // Assert
+        assert_eq!(reached, vec![None, None, Some(80), Some(80), Some(100)]);
+    }
+
+    #[tokio::test]
+    async fn envelopes_top_up_to_what_is_left() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let plan = BudgetPlan::new(pool, budgets(false), "GBP", date("2024-01-01"), Tz::UTC);
+        let statuses = vec![BudgetStatus {
+            category: "Category_1".to_string(),
+            currency: "GBP".to_string(),
+            budget: 10_000,
+            carried: 0,
+            spent: 2_500,
+        }];
+        let report = BalanceReport {
+            accounts: vec![AccountBalance {
+                account: AccountForDB {
+                    id: "acc_1".to_string(),
+                    ..Default::default()
+                },
+                pots: vec![PotBalance {
+                    pot_id: "pot_1".to_string(),
+                    name: "Food".to_string(),
+                    balance: 5_000,
+                    currency: "GBP".to_string(),
+                }],
+                balance: Balance::default(),
+            }],
+            ..Default::default()
+        };
+
+        // Act
+        let envelopes = plan.envelopes(&statuses, &report).unwrap();
+
+        // Assert
+        assert_eq!(envelopes.len(), 1);
+        assert_eq!(envelopes[0].account_id, "acc_1");
+        assert_eq!(envelopes[0].target, 7_500);
+        assert_eq!(envelopes[0].top_up(), 2_500);
+    }
+}
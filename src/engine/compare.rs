@@ -0,0 +1,326 @@
+//! Spending comparison
+//!
+//! Compares spending by category in one calendar week or month with an earlier
+//! one. The period containing a date is compared up to that date with the same
+//! number of days at the start of the other period, so a month half way
+//! through is measured against the first half of last month rather than all of
+//! it. As in the [digest](super::digest), transfers and money coming in don't
+//! count as spending.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use chrono_tz::Tz;
+
+use crate::{error::AppErrors as Error, model::DatabasePool, timezone::start_of_day};
+
+/// Calendar periods to compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// Monday to Sunday
+    Week,
+    Month,
+}
+
+/// The period to compare with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Baseline {
+    /// The period before
+    Previous,
+    /// The same period a year earlier
+    Year,
+}
+
+/// A range of days, both included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+}
+
+/// Spending in a category in both periods, as positive numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryChange {
+    pub category_label: String,
+    pub currency: String,
+    pub current: i64,
+    pub previous: i64,
+}
+
+impl CategoryChange {
+    /// How much more was spent in the current period
+    #[must_use]
+    pub fn delta(&self) -> i64 {
+        self.current - self.previous
+    }
+
+    /// The change as a percentage of the earlier spend, if there was any
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn percent(&self) -> Option<f64> {
+        (self.previous != 0).then(|| self.delta() as f64 * 100.0 / self.previous as f64)
+    }
+}
+
+/// Spending by category in two periods
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub current: DateRange,
+    pub previous: DateRange,
+    /// Every category with spending in either period, biggest increase first
+    pub categories: Vec<CategoryChange>,
+}
+
+impl Comparison {
+    /// Compare spending in the `period` containing `date`, up to `date`, with
+    /// the matching days of the `baseline` period
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn build(
+        pool: DatabasePool,
+        period: Period,
+        baseline: Baseline,
+        date: NaiveDate,
+        timezone: Tz,
+    ) -> Result<Self, Error> {
+        let (current, previous) = ranges(period, baseline, date);
+        let service = pool.transactions();
+
+        let mut spend: BTreeMap<(String, String), (i64, i64)> = BTreeMap::new();
+        for (range, is_current) in [(current, true), (previous, false)] {
+            let since = start_of_day(range.first, timezone);
+            let until = start_of_day(range.last + Days::new(1), timezone);
+            for tx in service.read_report_data(since, until).await? {
+                // the query's range includes its end
+                if tx.amount >= 0 || tx.is_transfer || tx.created >= until {
+                    continue;
+                }
+                let (current, previous) =
+                    spend.entry((tx.category_label, tx.currency)).or_default();
+                if is_current {
+                    *current -= tx.amount;
+                } else {
+                    *previous -= tx.amount;
+                }
+            }
+        }
+
+        let mut categories: Vec<CategoryChange> = spend
+            .into_iter()
+            .map(
+                |((category_label, currency), (current, previous))| CategoryChange {
+                    category_label,
+                    currency,
+                    current,
+                    previous,
+                },
+            )
+            .collect();
+        categories.sort_by_key(|c| std::cmp::Reverse(c.delta()));
+
+        Ok(Self {
+            current,
+            previous,
+            categories,
+        })
+    }
+
+    /// Total spend in each period by currency, as (currency, current, previous)
+    #[must_use]
+    pub fn totals(&self) -> Vec<(String, i64, i64)> {
+        let mut totals: BTreeMap<&str, (i64, i64)> = BTreeMap::new();
+        for category in &self.categories {
+            let (current, previous) = totals.entry(&category.currency).or_default();
+            *current += category.current;
+            *previous += category.previous;
+        }
+        totals
+            .into_iter()
+            .map(|(code, (current, previous))| (code.to_string(), current, previous))
+            .collect()
+    }
+
+    /// The categories driving an increase in spending: for each currency whose
+    /// total went up, the categories that went up, biggest first, until they
+    /// cover the increase
+    #[must_use]
+    pub fn drivers(&self) -> Vec<&CategoryChange> {
+        let mut drivers = Vec::new();
+        for (code, current, previous) in self.totals() {
+            let increase = current - previous;
+            let mut covered = 0;
+            for category in &self.categories {
+                if covered >= increase {
+                    break;
+                }
+                if category.currency == code && category.delta() > 0 {
+                    covered += category.delta();
+                    drivers.push(category);
+                }
+            }
+        }
+        drivers
+    }
+}
+
+// The current period up to `date`, and the matching days of the baseline period
+fn ranges(period: Period, baseline: Baseline, date: NaiveDate) -> (DateRange, DateRange) {
+    let first = match period {
+        Period::Week => date - Days::new(u64::from(date.weekday().num_days_from_monday())),
+        Period::Month => date.with_day(1).unwrap_or(date),
+    };
+    let current = DateRange { first, last: date };
+
+    let (previous_first, previous_end) = match (period, baseline) {
+        (Period::Week, Baseline::Previous) => (first - Days::new(7), first - Days::new(1)),
+        (Period::Month, Baseline::Previous) => {
+            (first - Months::new(1), first.pred_opt().unwrap_or(first))
+        }
+        (Period::Week, Baseline::Year) => {
+            // the same ISO week of the year before, or its last week
+            let year = first.iso_week().year() - 1;
+            let week = first.iso_week().week();
+            let monday = NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Mon)
+                .or_else(|| NaiveDate::from_isoywd_opt(year, week - 1, chrono::Weekday::Mon))
+                .unwrap_or(first - Days::new(364));
+            (monday, monday + Days::new(6))
+        }
+        (Period::Month, Baseline::Year) => {
+            let previous_first = first - Months::new(12);
+            (
+                previous_first,
+                (previous_first + Months::new(1))
+                    .pred_opt()
+                    .unwrap_or(previous_first),
+            )
+        }
+    };
+    let elapsed = Days::new(u64::try_from((date - first).num_days()).unwrap_or_default());
+    let previous = DateRange {
+        first: previous_first,
+        last: (previous_first + elapsed).min(previous_end),
+    };
+
+    (current, previous)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::transaction::TransactionResponse, tests::test::test_db};
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn range(first: &str, last: &str) -> DateRange {
+        DateRange {
+            first: date(first),
+            last: date(last),
+        }
+    }
+
+    async fn spend(pool: &DatabasePool, id: &str, day: &str, category: &str, amount: i64) {
+        let tx = TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            amount,
+            currency: "GBP".to_string(),
+            category_id: category.to_string(),
+            created: date(day).and_hms_opt(12, 0, 0).unwrap().and_utc(),
+            ..Default::default()
+        };
+        pool.categories().ensure_category(category).await.unwrap();
+        pool.transactions().save_transaction(&tx).await.unwrap();
+    }
+
+    #[test]
+    fn compares_the_same_days_of_each_period() {
+        assert_eq!(
+            ranges(Period::Month, Baseline::Previous, date("2024-03-31")),
+            (
+                range("2024-03-01", "2024-03-31"),
+                range("2024-02-01", "2024-02-29")
+            )
+        );
+        assert_eq!(
+            ranges(Period::Month, Baseline::Previous, date("2024-03-10")),
+            (
+                range("2024-03-01", "2024-03-10"),
+                range("2024-02-01", "2024-02-10")
+            )
+        );
+        assert_eq!(
+            ranges(Period::Week, Baseline::Previous, date("2024-05-08")),
+            (
+                range("2024-05-06", "2024-05-08"),
+                range("2024-04-29", "2024-05-01")
+            )
+        );
+        assert_eq!(
+            ranges(Period::Month, Baseline::Year, date("2024-02-29")),
+            (
+                range("2024-02-01", "2024-02-29"),
+                range("2023-02-01", "2023-02-28")
+            )
+        );
+        assert_eq!(
+            ranges(Period::Week, Baseline::Year, date("2024-05-08")),
+            (
+                range("2024-05-06", "2024-05-08"),
+                range("2023-05-08", "2023-05-10")
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_the_categories_driving_an_increase() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        spend(&pool, "a1", "2024-04-05", "1", -1000).await;
+        spend(&pool, "a2", "2024-04-20", "1", -5000).await;
+        spend(&pool, "a3", "2024-04-08", "eating_out", -1500).await;
+        spend(&pool, "b1", "2024-05-05", "1", -4000).await;
+        spend(&pool, "b2", "2024-05-06", "groceries", -3000).await;
+        spend(&pool, "b3", "2024-05-07", "eating_out", -500).await;
+        spend(&pool, "b4", "2024-05-08", "1", 10000).await;
+
+        // Act
+        let comparison = Comparison::build(
+            pool,
+            Period::Month,
+            Baseline::Previous,
+            date("2024-05-10"),
+            Tz::UTC,
+        )
+        .await
+        .unwrap();
+
+        // Assert
+        let changes: Vec<(&str, i64, i64)> = comparison
+            .categories
+            .iter()
+            .map(|c| (c.category_label.as_str(), c.current, c.previous))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("category_1", 4000, 1000),
+                ("🛒 Groceries", 3000, 0),
+                ("🍔 Eating out", 500, 1500),
+            ]
+        );
+        assert_eq!(comparison.categories[0].percent(), Some(300.0));
+        assert_eq!(comparison.categories[1].percent(), None);
+        assert_eq!(comparison.totals(), vec![("GBP".to_string(), 7500, 2500)]);
+        let drivers: Vec<&str> = comparison
+            .drivers()
+            .iter()
+            .map(|c| c.category_label.as_str())
+            .collect();
+        assert_eq!(drivers, vec!["category_1", "🛒 Groceries"]);
+    }
+}
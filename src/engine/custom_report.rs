@@ -0,0 +1,275 @@
+//! Reports defined in configuration
+//!
+//! A `[reports.<name>]` table answers a recurring question, such as eating out
+//! on the business account this quarter, with a filter expression, the dates
+//! to cover and what to total by. `report run <name>` runs it.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Days, Months, NaiveDate};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::{
+    configuration::{GroupBy, ReportDefinition, ReportPeriod},
+    error::AppErrors as Error,
+    model::{account::display_name, filter::Filter, transaction::ExportTransaction, DatabasePool},
+    timezone::{local_date, start_of_day},
+};
+
+/// The group for transactions without a merchant or tag
+const NONE: &str = "(none)";
+
+/// The transactions of one group in one currency
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CustomReportRow {
+    pub group: String,
+    pub currency: String,
+    pub count: usize,
+    /// In minor units, negative for spending
+    pub total: i64,
+}
+
+/// The result of running a report definition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CustomReport {
+    pub name: String,
+    pub description: Option<String>,
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+    /// Dates in order for dates, otherwise the most spent first
+    pub rows: Vec<CustomReportRow>,
+}
+
+impl CustomReport {
+    /// Run the report `name` as at `today`, starting from `start` unless the
+    /// definition says otherwise
+    ///
+    /// # Errors
+    /// Will return an error if the filter is invalid or the database can't be
+    /// read.
+    pub async fn run(
+        pool: DatabasePool,
+        name: &str,
+        definition: &ReportDefinition,
+        start: NaiveDate,
+        today: NaiveDate,
+        timezone: Tz,
+        nicknames: &BTreeMap<String, String>,
+    ) -> Result<Self, Error> {
+        let filter = definition
+            .filter
+            .as_deref()
+            .map(str::parse::<Filter>)
+            .transpose()
+            .map_err(|e| Error::Error(format!("Report '{name}': {e}")))?;
+        let (first, last) = match definition.period {
+            Some(period) => period_dates(period, today),
+            None => (
+                definition.since.unwrap_or(start),
+                definition.until.unwrap_or(today),
+            ),
+        };
+
+        let transactions = pool
+            .transactions()
+            .read_report_data(
+                start_of_day(first, timezone),
+                start_of_day(last + Days::new(1), timezone),
+            )
+            .await?;
+        let transactions: Vec<&ExportTransaction> = transactions
+            .iter()
+            .filter(|tx| local_date(tx.created, timezone) <= last)
+            .filter(|tx| filter.as_ref().is_none_or(|f| f.matches(tx, timezone)))
+            .collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            description: definition.description.clone(),
+            first,
+            last,
+            rows: group(&transactions, definition.group_by, timezone, nicknames),
+        })
+    }
+
+    /// The total of each currency
+    #[must_use]
+    pub fn totals(&self) -> BTreeMap<&str, i64> {
+        let mut totals = BTreeMap::new();
+        for row in &self.rows {
+            *totals.entry(row.currency.as_str()).or_default() += row.total;
+        }
+        totals
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// The first and last day of `period` as at `today`. Periods up to today end
+// today.
+fn period_dates(period: ReportPeriod, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let week = today - Days::new(u64::from(today.weekday().num_days_from_monday()));
+    let month = today.with_day(1).unwrap_or(today);
+    let quarter = month.with_month0(month.month0() / 3 * 3).unwrap_or(month);
+    let year = month.with_month(1).unwrap_or(month);
+    let day_before = |date: NaiveDate| date.pred_opt().unwrap_or(date);
+
+    match period {
+        ReportPeriod::ThisWeek => (week, today),
+        ReportPeriod::ThisMonth => (month, today),
+        ReportPeriod::ThisQuarter => (quarter, today),
+        ReportPeriod::ThisYear => (year, today),
+        ReportPeriod::LastWeek => (week - Days::new(7), day_before(week)),
+        ReportPeriod::LastMonth => (month - Months::new(1), day_before(month)),
+        ReportPeriod::LastQuarter => (quarter - Months::new(3), day_before(quarter)),
+        ReportPeriod::LastYear => (year - Months::new(12), day_before(year)),
+    }
+}
+
+// Total `transactions` by group and currency
+fn group(
+    transactions: &[&ExportTransaction],
+    group_by: GroupBy,
+    timezone: Tz,
+    nicknames: &BTreeMap<String, String>,
+) -> Vec<CustomReportRow> {
+    let mut groups: BTreeMap<(String, String), (usize, i64)> = BTreeMap::new();
+    for tx in transactions {
+        let date = local_date(tx.created, timezone);
+        let keys = match group_by {
+            GroupBy::Category => vec![tx.category_label.clone()],
+            GroupBy::Merchant => vec![tx
+                .merchant_name
+                .clone()
+                .or_else(|| tx.counterparty_name.clone())
+                .unwrap_or_else(|| NONE.to_string())],
+            GroupBy::Account => {
+                vec![display_name(nicknames, &tx.account_id, &tx.account_name).to_string()]
+            }
+            GroupBy::Tag => {
+                let tags: Vec<String> = tx
+                    .tags
+                    .as_deref()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect();
+                if tags.is_empty() {
+                    vec![NONE.to_string()]
+                } else {
+                    tags
+                }
+            }
+            GroupBy::Day => vec![date.to_string()],
+            GroupBy::Week => vec![format!(
+                "{}-W{:02}",
+                date.iso_week().year(),
+                date.iso_week().week()
+            )],
+            GroupBy::Month => vec![date.format("%Y-%m").to_string()],
+        };
+        for key in keys {
+            let entry = groups.entry((key, tx.currency.clone())).or_default();
+            entry.0 += 1;
+            entry.1 += tx.amount;
+        }
+    }
+
+    let mut rows: Vec<CustomReportRow> = groups
+        .into_iter()
+        .map(|((group, currency), (count, total))| CustomReportRow {
+            group,
+            currency,
+            count,
+            total,
+        })
+        .collect();
+    if !matches!(group_by, GroupBy::Day | GroupBy::Week | GroupBy::Month) {
+        rows.sort_by_key(|row| row.total);
+    }
+    rows
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn periods_are_calendar_periods() {
+        // Arrange
+        let today = date("2024-05-15");
+
+        // Act
+        let dates = |period| period_dates(period, today);
+
+        // Assert
+        assert_eq!(dates(ReportPeriod::ThisWeek), (date("2024-05-13"), today));
+        assert_eq!(
+            dates(ReportPeriod::ThisQuarter),
+            (date("2024-04-01"), today)
+        );
+        assert_eq!(
+            dates(ReportPeriod::LastWeek),
+            (date("2024-05-06"), date("2024-05-12"))
+        );
+        assert_eq!(
+            dates(ReportPeriod::LastQuarter),
+            (date("2024-01-01"), date("2024-03-31"))
+        );
+        assert_eq!(
+            dates(ReportPeriod::LastYear),
+            (date("2023-01-01"), date("2023-12-31"))
+        );
+    }
+
+    #[tokio::test]
+    async fn totals_filtered_transactions_by_group() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET amount = -500, created = '2024-05-02 12:00:00' WHERE id = '1';
+             UPDATE transactions SET amount = -1500, created = '2024-06-03 12:00:00' WHERE id = '2'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let definition = ReportDefinition {
+            filter: Some("amount < -10".to_string()),
+            group_by: GroupBy::Month,
+            period: Some(ReportPeriod::ThisQuarter),
+            ..Default::default()
+        };
+
+        // Act
+        let report = CustomReport::run(
+            pool,
+            "spending",
+            &definition,
+            date("2024-01-01"),
+            date("2024-06-15"),
+            Tz::UTC,
+            &BTreeMap::new(),
+        )
+        .await
+        .unwrap();
+
+        // Assert
+        assert_eq!(
+            report
+                .rows
+                .iter()
+                .map(|row| (row.group.as_str(), row.count, row.total))
+                .collect::<Vec<_>>(),
+            vec![("2024-06", 1, -1500)]
+        );
+        assert_eq!(report.totals()["GBP"], -1500);
+    }
+}
@@ -0,0 +1,153 @@
+//! Spending digest
+//!
+//! A short summary of spending over a period, small enough to read in a
+//! notification: the total, the top categories and the biggest transactions.
+//! Transfers and money coming in don't count as spending.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use chrono::NaiveDateTime;
+
+use crate::{
+    currency,
+    error::AppErrors as Error,
+    model::{transaction::ExportTransaction, DatabasePool},
+};
+
+/// How many categories and transactions the digest lists
+const TOP: usize = 3;
+
+/// Spending between two dates
+#[derive(Debug, Default)]
+pub struct Digest {
+    pub since: NaiveDateTime,
+    pub until: NaiveDateTime,
+    /// Total spend in minor units, by currency, as a positive number
+    pub totals: BTreeMap<String, i64>,
+    /// The categories with the most spend, as (category, currency, spend)
+    pub top_categories: Vec<(String, String, i64)>,
+    /// The largest payments, biggest first
+    pub biggest: Vec<ExportTransaction>,
+}
+
+impl Digest {
+    /// Summarise spending between `since` and `until`
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn build(
+        pool: DatabasePool,
+        since: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Self, Error> {
+        let mut spending: Vec<ExportTransaction> = pool
+            .transactions()
+            .read_report_data(since, until)
+            .await?
+            .into_iter()
+            .filter(|tx| tx.amount < 0 && !tx.is_transfer)
+            .collect();
+
+        let mut totals = BTreeMap::new();
+        let mut categories: BTreeMap<(String, String), i64> = BTreeMap::new();
+        for tx in &spending {
+            *totals.entry(tx.currency.clone()).or_default() -= tx.amount;
+            *categories
+                .entry((tx.category_label.clone(), tx.currency.clone()))
+                .or_default() -= tx.amount;
+        }
+
+        let mut top_categories: Vec<_> = categories
+            .into_iter()
+            .map(|((category, currency), spend)| (category, currency, spend))
+            .collect();
+        top_categories.sort_by_key(|(_, _, spend)| std::cmp::Reverse(*spend));
+        top_categories.truncate(TOP);
+
+        spending.sort_by_key(|tx| tx.amount);
+        spending.truncate(TOP);
+
+        Ok(Self {
+            since,
+            until,
+            totals,
+            top_categories,
+            biggest: spending,
+        })
+    }
+
+    /// The digest as plain text, one fact per line
+    ///
+    /// # Errors
+    /// Will return an error if a currency is unknown.
+    pub fn render(&self) -> Result<String, Error> {
+        let mut text = String::new();
+
+        if self.totals.is_empty() {
+            text.push_str("No spending\n");
+            return Ok(text);
+        }
+        for (code, total) in &self.totals {
+            let _ = writeln!(text, "Spent {}", currency::display(*total, code)?);
+        }
+
+        text.push_str("\nTop categories\n");
+        for (category, code, spend) in &self.top_categories {
+            let _ = writeln!(text, "  {category}: {}", currency::display(*spend, code)?);
+        }
+
+        text.push_str("\nBiggest payments\n");
+        for tx in &self.biggest {
+            let payee = tx.merchant_name.as_deref().unwrap_or(&tx.description);
+            let _ = writeln!(
+                text,
+                "  {} {payee}: {}",
+                tx.created.format("%a %d %b"),
+                currency::display(-tx.amount, &tx.currency)?
+            );
+        }
+
+        Ok(text)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{model::fixture::Fixture, tests::test::test_db};
+
+    const FIXTURE: &str = r#"
+        transactions:
+          - { id: "3", account_id: "1", amount: -1250, currency: GBP, local_amount: -1250, local_currency: GBP, created: "2024-06-10T12:00:00Z", description: TESCO, category: "1" }
+          - { id: "4", account_id: "1", amount: -400, currency: GBP, local_amount: -400, local_currency: GBP, created: "2024-06-11T12:00:00Z", description: PRET, category: "1" }
+          - { id: "5", account_id: "1", amount: 5000, currency: GBP, local_amount: 5000, local_currency: GBP, created: "2024-06-12T12:00:00Z", description: SALARY, category: "1" }
+    "#;
+
+    #[tokio::test]
+    async fn digest_summarises_spending() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        pool.load_fixture(&Fixture::from_yaml(FIXTURE).unwrap())
+            .await
+            .unwrap();
+        let since = NaiveDateTime::default();
+        let until = Utc::now().naive_utc();
+
+        // Act
+        let digest = Digest::build(pool, since, until).await.unwrap();
+
+        // Assert
+        assert_eq!(digest.totals.get("GBP"), Some(&1650));
+        assert_eq!(
+            digest.top_categories,
+            vec![("category_1".to_string(), "GBP".to_string(), 1650)]
+        );
+        let ids: Vec<_> = digest.biggest.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, vec!["3", "4"]);
+        assert!(digest.render().unwrap().starts_with("Spent £16.50\n"));
+    }
+}
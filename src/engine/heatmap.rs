@@ -0,0 +1,132 @@
+//! Spending heatmap
+//!
+//! Adds up spending by local weekday and hour, showing when in the week money
+//! goes out, e.g. food deliveries on weekend evenings. Only spending in one
+//! currency counts; transfers and refunds don't.
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use chrono_tz::Tz;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{transaction::ExportTransaction, DatabasePool},
+    timezone::to_local,
+};
+
+/// Spending in minor units, as positive numbers, by weekday from Monday and
+/// hour from midnight
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heatmap {
+    pub currency: String,
+    pub spend: [[i64; 24]; 7],
+}
+
+impl Heatmap {
+    /// Spending in `currency` between `since` and `until`
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn build(
+        pool: DatabasePool,
+        since: NaiveDateTime,
+        until: NaiveDateTime,
+        currency: &str,
+        timezone: Tz,
+    ) -> Result<Self, Error> {
+        let transactions = pool.transactions().read_report_data(since, until).await?;
+
+        Ok(Self::from_transactions(&transactions, currency, timezone))
+    }
+
+    /// Spending in `currency` among `transactions`
+    #[must_use]
+    pub fn from_transactions(
+        transactions: &[ExportTransaction],
+        currency: &str,
+        timezone: Tz,
+    ) -> Self {
+        let mut spend = [[0; 24]; 7];
+        for tx in transactions
+            .iter()
+            .filter(|tx| tx.amount < 0 && !tx.is_transfer && tx.currency == currency)
+        {
+            let local = to_local(tx.created, timezone);
+            let day = local.weekday().num_days_from_monday() as usize;
+            spend[day][local.hour() as usize] -= tx.amount;
+        }
+
+        Self {
+            currency: currency.to_string(),
+            spend,
+        }
+    }
+
+    /// The most spent in any one hour of the week
+    #[must_use]
+    pub fn max(&self) -> i64 {
+        self.spend
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// The weekday from Monday and hour with the most spending, if any
+    #[must_use]
+    pub fn busiest(&self) -> Option<(usize, usize)> {
+        let max = self.max();
+        (max > 0)
+            .then(|| {
+                (0..7)
+                    .flat_map(|day| (0..24).map(move |hour| (day, hour)))
+                    .find(|&(day, hour)| self.spend[day][hour] == max)
+            })
+            .flatten()
+    }
+
+    /// Total spending on the weekday from Monday
+    #[must_use]
+    pub fn day_total(&self, day: usize) -> i64 {
+        self.spend[day].iter().sum()
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(created: &str, amount: i64, is_transfer: bool) -> ExportTransaction {
+        ExportTransaction {
+            created: NaiveDateTime::parse_from_str(created, "%Y-%m-%d %H:%M").unwrap(),
+            amount,
+            currency: "GBP".to_string(),
+            is_transfer,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn spending_is_bucketed_by_local_weekday_and_hour() {
+        // Arrange
+        // 1 June 2024 was a Saturday; London is an hour ahead of UTC
+        let transactions = vec![
+            tx("2024-06-01 18:30", -2_500, false),
+            tx("2024-06-01 18:50", -1_500, false),
+            tx("2024-06-01 19:10", -10_000, true),
+            tx("2024-06-02 23:30", -700, false),
+            tx("2024-06-03 09:00", 5_000, false),
+        ];
+
+        // Act
+        let heatmap = Heatmap::from_transactions(&transactions, "GBP", Tz::Europe__London);
+
+        // Assert
+        assert_eq!(heatmap.spend[5][19], 4_000);
+        assert_eq!(heatmap.spend[0][0], 700);
+        assert_eq!(heatmap.day_total(5), 4_000);
+        assert_eq!(heatmap.busiest(), Some((5, 19)));
+    }
+}
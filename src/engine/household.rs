@@ -0,0 +1,174 @@
+//! Household reports
+//!
+//! Combines the databases of several people, e.g. a couple who each run their
+//! own profile. Each database is opened with its own pool and the results are
+//! merged in memory, keeping track of whose data is whose.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+
+use crate::{error::AppErrors as Error, model::DatabasePool};
+
+/// Spending or income in one category and currency, per person
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HouseholdCategoryTotal {
+    pub category_name: String,
+    /// The category's emoji and display name, from the first profile using it
+    pub category_label: String,
+    pub currency: String,
+    /// Total in minor units for each profile, in profile order
+    pub by_profile: Vec<i64>,
+}
+
+impl HouseholdCategoryTotal {
+    /// Total across the household in minor units
+    #[must_use]
+    pub fn total(&self) -> i64 {
+        self.by_profile.iter().sum()
+    }
+}
+
+/// The databases of several people, by profile name
+pub struct Household {
+    profiles: Vec<(String, DatabasePool)>,
+}
+
+impl Household {
+    /// Open the databases of the named profiles, in the order given
+    ///
+    /// # Errors
+    /// Will return an error if a name isn't in `profiles` or a database can't
+    /// be opened.
+    pub async fn open(
+        profiles: &BTreeMap<String, String>,
+        names: &[String],
+        max_connections: u32,
+    ) -> Result<Self, Error> {
+        let mut opened = Vec::new();
+        for name in names {
+            let Some(path) = profiles.get(name) else {
+                return Err(Error::Error(format!(
+                    "Unknown profile '{name}'. Configured profiles: {}",
+                    profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )));
+            };
+            opened.push((
+                name.clone(),
+                DatabasePool::new(path, max_connections).await?,
+            ));
+        }
+
+        Ok(Self { profiles: opened })
+    }
+
+    /// Leave transactions smaller than `min_amount` minor units out of each
+    /// person's totals
+    #[must_use]
+    pub fn with_min_amount(self, min_amount: i64) -> Self {
+        Self {
+            profiles: self
+                .profiles
+                .into_iter()
+                .map(|(name, pool)| (name, pool.with_min_amount(min_amount)))
+                .collect(),
+        }
+    }
+
+    /// A household of already open databases
+    #[must_use]
+    pub fn from_pools(profiles: Vec<(String, DatabasePool)>) -> Self {
+        Self { profiles }
+    }
+
+    /// The profile names and their databases
+    #[must_use]
+    pub fn profiles(&self) -> &[(String, DatabasePool)] {
+        &self.profiles
+    }
+
+    /// Category totals of transactions created between the given dates, with
+    /// a column per person, ordered by category and currency
+    ///
+    /// # Errors
+    /// Will return errors if a database cannot be read.
+    pub async fn category_totals(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<HouseholdCategoryTotal>, Error> {
+        let mut merged: BTreeMap<(String, String), Vec<i64>> = BTreeMap::new();
+        let mut labels: BTreeMap<String, String> = BTreeMap::new();
+
+        for (index, (_, pool)) in self.profiles.iter().enumerate() {
+            let totals = pool
+                .transactions()
+                .read_category_totals(from, until)
+                .await?;
+            for total in totals {
+                labels
+                    .entry(total.category_name.clone())
+                    .or_insert(total.category_label);
+                merged
+                    .entry((total.category_name, total.currency))
+                    .or_insert_with(|| vec![0; self.profiles.len()])[index] += total.total;
+            }
+        }
+
+        Ok(merged
+            .into_iter()
+            .map(
+                |((category_name, currency), by_profile)| HouseholdCategoryTotal {
+                    category_label: labels
+                        .get(&category_name)
+                        .cloned()
+                        .unwrap_or_else(|| category_name.clone()),
+                    category_name,
+                    currency,
+                    by_profile,
+                },
+            )
+            .collect())
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn totals_have_a_column_per_profile() {
+        // Arrange
+        let (alex, _alex_tmp) = test_db().await;
+        let (sam, _sam_tmp) = test_db().await;
+        let household = Household::from_pools(vec![("alex".into(), alex), ("sam".into(), sam)]);
+
+        // Act
+        let totals = household
+            .category_totals(NaiveDateTime::default(), chrono::Utc::now().naive_utc())
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].category_name, "category_1");
+        assert_eq!(totals[0].by_profile.len(), 2);
+        assert_eq!(totals[0].by_profile[0], totals[0].by_profile[1]);
+        assert_eq!(totals[0].total(), 2 * totals[0].by_profile[0]);
+    }
+
+    #[tokio::test]
+    async fn unknown_profile_is_an_error() {
+        // Arrange
+        let profiles = BTreeMap::from([("alex".to_string(), "alex.sqlite".to_string())]);
+
+        // Act
+        let result = Household::open(&profiles, &["sam".to_string()], 1).await;
+
+        // Assert
+        assert!(matches!(result, Err(Error::Error(msg)) if msg.contains("alex")));
+    }
+}
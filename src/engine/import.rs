@@ -0,0 +1,451 @@
+//! Import from Monzo's CSV export
+//!
+//! Rows of a CSV export that were never synced, for example because they
+//! predate the API's 90 day limit, are stored as transactions of an account.
+//!
+//! A row can look like a synced transaction with a different id: one with the
+//! same dedupe key a few days apart, or the same payee on the same day with a
+//! different amount. Rather than storing a likely duplicate, each such
+//! conflict is resolved by keeping the synced transaction, correcting it from
+//! the CSV, or keeping both. Resolutions are recorded by row id, so importing
+//! the same export again gives the same result.
+
+use std::{collections::HashSet, fmt, io::Read, str::FromStr};
+
+use chrono::{Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use super::reconcile::{read_csv, CsvTransaction};
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        dedupe::{normalise_payee, Source, TOLERANCE_DAYS},
+        import::ImportResolution,
+        transaction::{ExportTransaction, TransactionResponse},
+        DatabasePool,
+    },
+    timezone::{to_local, to_utc},
+};
+
+/// How a conflict between a CSV row and a synced transaction is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Skip the row
+    KeepApi,
+    /// Correct the synced transaction's amount and time from the row
+    KeepCsv,
+    /// Store the row as another transaction
+    KeepBoth,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::KeepApi => "keep-api",
+            Self::KeepCsv => "keep-csv",
+            Self::KeepBoth => "keep-both",
+        })
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep-api" => Ok(Self::KeepApi),
+            "keep-csv" => Ok(Self::KeepCsv),
+            "keep-both" => Ok(Self::KeepBoth),
+            _ => Err(Error::Error(format!("Unknown import resolution '{s}'"))),
+        }
+    }
+}
+
+/// A CSV row that looks like a synced transaction
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub row: CsvTransaction,
+    pub transaction: ExportTransaction,
+    /// The transaction's time, converted to local time
+    pub stored: NaiveDateTime,
+}
+
+/// The outcome of importing a CSV export
+#[derive(Debug, Default, Clone)]
+pub struct CsvImport {
+    /// Rows stored as new transactions
+    pub imported: Vec<CsvTransaction>,
+    /// Rows whose transaction was already synced
+    pub synced: usize,
+    /// Conflicts and how they were resolved, including those resolved by an
+    /// earlier import
+    pub resolved: Vec<(Conflict, Resolution)>,
+}
+
+impl CsvImport {
+    /// Store the rows of a CSV export that aren't synced as transactions of
+    /// `account_id`, calling `resolve` for each conflict with a synced
+    /// transaction that hasn't been resolved before. Times in the export are
+    /// local to `timezone`.
+    ///
+    /// # Errors
+    /// Will return an error if the export can't be parsed, the database can't
+    /// be read or written, or `resolve` fails.
+    pub async fn run(
+        pool: DatabasePool,
+        csv: impl Read,
+        account_id: &str,
+        timezone: Tz,
+        mut resolve: impl FnMut(&Conflict) -> Result<Resolution, Error>,
+    ) -> Result<Self, Error> {
+        let rows: Vec<CsvTransaction> = read_csv(csv)?
+            .into_iter()
+            .filter(|row| row.amount != 0)
+            .collect();
+        let (Some(first), Some(last)) = (
+            rows.iter().map(|row| row.created).min(),
+            rows.iter().map(|row| row.created).max(),
+        ) else {
+            return Ok(Self::default());
+        };
+
+        let transactions = pool.transactions();
+        let imports = pool.import_resolutions();
+        let synced: HashSet<String> = transactions
+            .read_transactions()
+            .await?
+            .into_iter()
+            .map(|tx| tx.id)
+            .collect();
+        let row_ids: HashSet<&str> = rows.iter().map(|row| row.id.as_str()).collect();
+        let window = Duration::days(TOLERANCE_DAYS);
+        let candidates: Vec<ExportTransaction> = transactions
+            .read_export_data(
+                to_utc(first, timezone) - window,
+                to_utc(last, timezone) + window,
+            )
+            .await?
+            .into_iter()
+            .filter(|tx| tx.account_id == account_id && !row_ids.contains(tx.id.as_str()))
+            .collect();
+
+        let mut import = Self::default();
+        let mut claimed = HashSet::new();
+        for row in rows {
+            if synced.contains(&row.id) || pool.resolve_alias(&row.id).await?.is_some() {
+                import.synced += 1;
+                continue;
+            }
+
+            let recorded = imports.read_resolution(&row.id).await?;
+            let lookalike = candidates
+                .iter()
+                .filter(|tx| !claimed.contains(tx.id.as_str()))
+                .find(|tx| match &recorded {
+                    Some(recorded) => tx.id == recorded.transaction_id,
+                    None => looks_like(&row, tx, timezone),
+                });
+            let Some(transaction) = lookalike else {
+                // a resolved row whose transaction has since moved is left alone
+                if recorded.is_some() {
+                    continue;
+                }
+                save_row(pool.clone(), &row, account_id, timezone).await?;
+                import.imported.push(row);
+                continue;
+            };
+            claimed.insert(transaction.id.as_str());
+
+            let conflict = Conflict {
+                stored: to_local(transaction.created, timezone),
+                transaction: transaction.clone(),
+                row,
+            };
+            let resolution = if let Some(recorded) = recorded {
+                recorded.resolution.parse()?
+            } else {
+                let resolution = resolve(&conflict)?;
+                imports
+                    .save_resolution(&ImportResolution {
+                        csv_id: conflict.row.id.clone(),
+                        transaction_id: transaction.id.clone(),
+                        resolution: resolution.to_string(),
+                        resolved: Utc::now().naive_utc(),
+                    })
+                    .await?;
+                resolution
+            };
+
+            match resolution {
+                Resolution::KeepApi => {}
+                Resolution::KeepCsv => {
+                    imports
+                        .overwrite_transaction(
+                            &transaction.id,
+                            conflict.row.amount,
+                            to_utc(conflict.row.created, timezone),
+                        )
+                        .await?;
+                }
+                Resolution::KeepBoth => {
+                    save_row(pool.clone(), &conflict.row, account_id, timezone).await?;
+                    import.imported.push(conflict.row.clone());
+                }
+            }
+            import.resolved.push((conflict, resolution));
+        }
+
+        Ok(import)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// Whether a row is probably the same payment as a synced transaction: one with
+// the same dedupe key within the tolerance, or the same payee on the same day
+// with a different amount
+fn looks_like(row: &CsvTransaction, tx: &ExportTransaction, timezone: Tz) -> bool {
+    let payee = [&tx.merchant_name, &tx.counterparty_name]
+        .into_iter()
+        .flatten()
+        .next()
+        .unwrap_or(&tx.description);
+    let name = normalise_payee(&row.name);
+    if name.is_empty() || name != normalise_payee(payee) || tx.currency != row.currency {
+        return false;
+    }
+
+    let stored = to_local(tx.created, timezone);
+    if tx.amount == row.amount {
+        (stored - row.created).num_days().abs() <= TOLERANCE_DAYS
+    } else {
+        stored.date() == row.created.date()
+    }
+}
+
+// Store a row as a transaction, in the category with the row's category name
+async fn save_row(
+    pool: DatabasePool,
+    row: &CsvTransaction,
+    account_id: &str,
+    timezone: Tz,
+) -> Result<(), Error> {
+    let categories = pool.categories();
+    let id = match row.category.to_lowercase().replace(' ', "_") {
+        id if id.is_empty() => "general".to_string(),
+        id => id,
+    };
+    let found = categories
+        .read_categories()
+        .await?
+        .into_iter()
+        .find(|category| {
+            category.id == id
+                || [Some(&category.name), category.display_name.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .any(|name| name.eq_ignore_ascii_case(&row.category))
+        });
+    let category_id = if let Some(category) = found {
+        category.id
+    } else {
+        categories.ensure_category(&id).await?;
+        id
+    };
+
+    let created = Utc.from_utc_datetime(&to_utc(row.created, timezone));
+    pool.transactions()
+        .save_transaction_from(
+            &TransactionResponse {
+                id: row.id.clone(),
+                account_id: account_id.to_string(),
+                amount: row.amount,
+                currency: row.currency.clone(),
+                local_amount: row.amount,
+                local_currency: row.currency.clone(),
+                created,
+                description: row.name.clone(),
+                settled: Some(created),
+                category_id,
+                ..Default::default()
+            },
+            Source::Csv,
+        )
+        .await
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{account::AccountForDB, merchant::Merchant},
+        tests::test::test_db,
+    };
+
+    const HEADER: &str = "Transaction ID,Date,Time,Type,Name,Emoji,Category,Amount,Currency,Local amount,Local currency,Notes and #tags,Address,Receipt,Description,Category split,Money Out,Money In";
+
+    #[tokio::test]
+    async fn resolves_lookalikes_and_remembers_how() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        pool.accounts()
+            .save_account(&AccountForDB {
+                id: "acc_1".to_string(),
+                currency: "GBP".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        pool.categories().ensure_category("bills").await.unwrap();
+        let service = pool.transactions();
+        for (id, amount, created, merchant) in [
+            ("tx_rent", -95000, "2024-06-01T08:00:00Z", "Landlord"),
+            ("tx_shop", -1200, "2024-06-02T09:30:00Z", "Corner Shop"),
+        ] {
+            service
+                .save_transaction(&TransactionResponse {
+                    id: id.to_string(),
+                    account_id: "acc_1".to_string(),
+                    category_id: "bills".to_string(),
+                    amount,
+                    currency: "GBP".to_string(),
+                    created: created.parse().unwrap(),
+                    merchant: Some(Merchant {
+                        id: format!("merch_{id}"),
+                        name: merchant.to_string(),
+                        category_id: "bills".to_string(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        // times are local, an hour ahead of UTC in June
+        let csv = [
+            HEADER,
+            "csv_rent,03/06/2024,09:00:00,Payment,Landlord,,Bills,-950.00,GBP,-950.00,GBP,,,,RENT,,950.00,",
+            "csv_shop,02/06/2024,10:30:00,Card payment,Corner Shop,,Bills,-12.50,GBP,-12.50,GBP,,,,SHOP,,12.50,",
+            "csv_cafe,02/06/2024,11:00:00,Card payment,Cafe,,Eating out,-3.20,GBP,-3.20,GBP,,,,CAFE,,3.20,",
+        ]
+        .join("\n");
+        let mut asked = Vec::new();
+
+        // Act
+        let import = CsvImport::run(
+            pool.clone(),
+            csv.as_bytes(),
+            "acc_1",
+            Tz::Europe__London,
+            |conflict| {
+                asked.push(conflict.transaction.id.clone());
+                Ok(if conflict.row.id == "csv_rent" {
+                    Resolution::KeepApi
+                } else {
+                    Resolution::KeepCsv
+                })
+            },
+        )
+        .await
+        .unwrap();
+        let again = CsvImport::run(
+            pool.clone(),
+            csv.as_bytes(),
+            "acc_1",
+            Tz::Europe__London,
+            |_| Err(Error::Error("asked again".to_string())),
+        )
+        .await
+        .unwrap();
+
+        // Assert
+        assert_eq!(asked, vec!["tx_rent", "tx_shop"]);
+        assert_eq!(import.imported.len(), 1);
+        assert_eq!(import.imported[0].id, "csv_cafe");
+        assert_eq!(
+            service.read_transaction("tx_shop").await.unwrap().amount,
+            -1250
+        );
+        assert_eq!(again.synced, 1);
+        assert!(again.imported.is_empty());
+        assert_eq!(
+            again
+                .resolved
+                .iter()
+                .map(|(_, resolution)| *resolution)
+                .collect::<Vec<_>>(),
+            vec![Resolution::KeepApi, Resolution::KeepCsv]
+        );
+    }
+
+    #[tokio::test]
+    async fn api_transactions_absorb_imported_duplicates() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        pool.accounts()
+            .save_account(&AccountForDB {
+                id: "acc_1".to_string(),
+                currency: "GBP".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let csv = [
+            HEADER,
+            "csv_cafe,02/06/2024,11:00:00,Card payment,Cafe Nero,,Eating out,-3.20,GBP,-3.20,GBP,,,,CAFE,,3.20,",
+        ]
+        .join("\n");
+        let fail = |_: &Conflict| Err(Error::Error("asked".to_string()));
+        CsvImport::run(
+            pool.clone(),
+            csv.as_bytes(),
+            "acc_1",
+            Tz::Europe__London,
+            fail,
+        )
+        .await
+        .unwrap();
+        let service = pool.transactions();
+
+        // Act
+        service
+            .save_transaction(&TransactionResponse {
+                id: "tx_cafe".to_string(),
+                account_id: "acc_1".to_string(),
+                category_id: "eating_out".to_string(),
+                amount: -320,
+                currency: "GBP".to_string(),
+                created: "2024-06-03T12:00:00Z".parse().unwrap(),
+                description: "CAFE NERO 0417 LONDON".to_string(),
+                merchant: Some(Merchant {
+                    id: "merch_nero".to_string(),
+                    name: "Cafe Nero".to_string(),
+                    category_id: "eating_out".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let again = CsvImport::run(
+            pool.clone(),
+            csv.as_bytes(),
+            "acc_1",
+            Tz::Europe__London,
+            fail,
+        )
+        .await
+        .unwrap();
+
+        // Assert
+        assert!(service.read_transaction("csv_cafe").await.is_err());
+        assert_eq!(
+            pool.resolve_alias("csv_cafe").await.unwrap().as_deref(),
+            Some("tx_cafe")
+        );
+        assert_eq!(again.synced, 1);
+        assert!(again.imported.is_empty());
+    }
+}
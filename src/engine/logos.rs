@@ -0,0 +1,145 @@
+//! Merchant logo cache
+//!
+//! [`fetch_logos`] downloads the logos of stored merchants into a directory as
+//! `<merchant id>.<ext>` and records each file against its merchant, so
+//! reports can show the logo without fetching it. Logos already cached are
+//! skipped; a merchant whose logo changes is fetched again.
+
+use std::path::Path;
+
+use reqwest::header::CONTENT_TYPE;
+
+use crate::{error::AppErrors as Error, model::DatabasePool};
+
+/// What fetching logos did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LogoDownloads {
+    /// Logos written
+    pub downloaded: usize,
+    /// Logos cached before
+    pub existing: usize,
+}
+
+/// Download the merchant logos that aren't cached in `dir`
+///
+/// # Errors
+/// Will return errors if the database can't be read, a logo can't be fetched
+/// or it can't be written.
+pub async fn fetch_logos(pool: &DatabasePool, dir: &Path) -> Result<LogoDownloads, Error> {
+    let service = pool.merchants();
+    let client = reqwest::Client::new();
+    let mut downloads = LogoDownloads::default();
+
+    for logo in service.read_logos().await? {
+        if logo
+            .logo_path
+            .as_deref()
+            .is_some_and(|p| Path::new(p).exists())
+        {
+            downloads.existing += 1;
+            continue;
+        }
+
+        let response = client.get(&logo.logo).send().await?.error_for_status()?;
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let contents = response.bytes().await?;
+
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "{}.{}",
+            logo.merchant_id,
+            extension(&logo.logo, content_type.as_deref())
+        ));
+        std::fs::write(&path, &contents)?;
+        service
+            .set_logo_path(&logo.merchant_id, &path.to_string_lossy())
+            .await?;
+        downloads.downloaded += 1;
+    }
+
+    Ok(downloads)
+}
+
+// The file extension of a logo, from its content type or else its URL
+fn extension(url: &str, content_type: Option<&str>) -> String {
+    let from_type = content_type
+        .and_then(|t| t.split(';').next())
+        .and_then(|t| t.trim().strip_prefix("image/"))
+        .map(|ext| match ext {
+            "jpeg" => "jpg",
+            "svg+xml" => "svg",
+            other => other,
+        });
+    let from_url = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit_once('/'))
+        .and_then(|(_, name)| name.rsplit_once('.'))
+        .map(|(_, ext)| ext);
+
+    from_type
+        .or(from_url)
+        .filter(|ext| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("png")
+        .to_lowercase()
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{matchers::path, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::{model::merchant::Merchant, tests::test::test_db};
+
+    #[tokio::test]
+    async fn fetches_each_logo_once() {
+        // Arrange
+        let (pool, tmp) = test_db().await;
+        let server = MockServer::start().await;
+        Mock::given(path("/logo"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-type", "image/jpeg")
+                    .set_body_bytes(b"logo".to_vec()),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let service = pool.merchants();
+        service
+            .save_merchant(&Merchant {
+                id: "merch_1".to_string(),
+                logo: Some(format!("{}/logo", server.uri())),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let dir = tmp.path().join("logos");
+
+        // Act
+        let first = fetch_logos(&pool, &dir).await.unwrap();
+        let second = fetch_logos(&pool, &dir).await.unwrap();
+
+        // Assert
+        let saved = dir.join("merch_1.jpg");
+        assert_eq!(std::fs::read(&saved).unwrap(), b"logo");
+        assert_eq!(first.downloaded, 1);
+        assert_eq!(second.existing, 1);
+        let logos = service.read_logos().await.unwrap();
+        assert_eq!(logos[0].logo_path.as_deref(), saved.to_str());
+    }
+
+    #[test]
+    fn extensions_follow_the_content_type_or_url() {
+        assert_eq!(extension("https://x/a.PNG", None), "png");
+        assert_eq!(extension("https://x/a", Some("image/svg+xml")), "svg");
+        assert_eq!(extension("https://x/a.gif?size=64", None), "gif");
+        assert_eq!(extension("https://x/a", None), "png");
+    }
+}
@@ -0,0 +1,62 @@
+//! Library facade
+//!
+//! High level operations for programs embedding the crate. Each type takes a
+//! [`DatabasePool`](crate::model::DatabasePool) and a [`Monzo`](crate::client::Monzo)
+//! client and returns data structures; presentation is left to the caller.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), monzo_cli::error::AppErrors> {
+//! use monzo_cli::{client::Monzo, configuration::get_config, model::DatabasePool, SyncEngine};
+//!
+//! let config = get_config()?;
+//! let pool = DatabasePool::new_from_config(config.clone()).await?;
+//! let engine = SyncEngine::new(pool, Monzo::new()?);
+//! let summary = engine.sync(config.start_date, chrono::Utc::now().naive_utc()).await?;
+//! println!("Synced {} transactions", summary.transactions.len());
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod alerts;
+pub mod attachments;
+pub mod audit;
+pub mod budget;
+pub mod compare;
+pub mod custom_report;
+pub mod digest;
+pub mod heatmap;
+pub mod household;
+pub mod import;
+pub mod logos;
+pub mod projection;
+pub mod reconcile;
+pub mod report;
+pub mod savings;
+pub mod schedule;
+pub mod sweep;
+pub mod sync;
+pub mod today;
+pub mod trips;
+pub mod verify;
+
+pub use alerts::{low_balances, BalanceAlert};
+pub use attachments::{download_attachments, AttachmentDownloads};
+pub use audit::{AuditFixes, CategoryAudit};
+pub use budget::{BudgetPlan, BudgetStatus, DailyAllowance, Envelope, ALERT_THRESHOLDS};
+pub use compare::{Baseline, CategoryChange, Comparison, Period};
+pub use custom_report::{CustomReport, CustomReportRow};
+pub use digest::Digest;
+pub use heatmap::Heatmap;
+pub use household::{Household, HouseholdCategoryTotal};
+pub use import::{Conflict, CsvImport, Resolution};
+pub use logos::{fetch_logos, LogoDownloads};
+pub use projection::{project, upcoming_bills, Bill, Projection};
+pub use reconcile::Reconciliation;
+pub use report::{AccountBalance, AccountDetails, BalanceReport, NetWorth, Reporter};
+pub use savings::{SavingsMonth, SavingsReport};
+pub use schedule::{QuietHours, Schedule};
+pub use sweep::{plan_sweeps, Sweep, Sweeper};
+pub use sync::{SyncEngine, SyncEvent, SyncStats, SyncSummary};
+pub use today::Today;
+pub use trips::{find_trips, Trip, TripSpend};
+pub use verify::{BalanceGap, Verification};
@@ -0,0 +1,351 @@
+//! End of month balance projection
+//!
+//! Starts from an account's latest stored balance, brought up to date with the
+//! transactions since it was stored, then takes off the bills still due this
+//! month and the usual day-to-day spending for the days left.
+//!
+//! A bill is a payment to the same payee in each of the last two months; it's
+//! due on the day of the month it was last paid, unless it's been paid this
+//! month already. Day-to-day spending is the average daily spend over a recent
+//! window, leaving out bills and transfers.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, Days, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{transaction::ExportTransaction, DatabasePool},
+    timezone::{local_date, start_of_day},
+};
+
+/// A recurring payment still due this month
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Bill {
+    pub payee: String,
+    pub due: NaiveDate,
+    /// What was paid last time, as a positive number
+    pub amount: i64,
+}
+
+/// An account's expected balance at the end of the month
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Projection {
+    pub account_id: String,
+    /// The account's type, e.g. `personal`
+    pub account_name: String,
+    pub currency: String,
+    /// The balance now
+    pub balance: i64,
+    pub today: NaiveDate,
+    pub month_end: NaiveDate,
+    pub bills: Vec<Bill>,
+    /// Average day-to-day spend per day, as a positive number
+    pub daily_spend: i64,
+}
+
+impl Projection {
+    /// Days left in the month, including today
+    #[must_use]
+    pub fn days_left(&self) -> i64 {
+        (self.month_end - self.today).num_days() + 1
+    }
+
+    /// The bills still due, together
+    #[must_use]
+    pub fn bills_total(&self) -> i64 {
+        self.bills.iter().map(|bill| bill.amount).sum()
+    }
+
+    /// Day-to-day spending expected over the rest of the month
+    #[must_use]
+    pub fn discretionary(&self) -> i64 {
+        self.daily_spend * self.days_left()
+    }
+
+    /// The balance expected at the end of the month
+    #[must_use]
+    pub fn end_of_month(&self) -> i64 {
+        self.balance - self.bills_total() - self.discretionary()
+    }
+
+    /// What can be spent before the end of the month with the bills still
+    /// paid
+    #[must_use]
+    pub fn safe_to_spend(&self) -> i64 {
+        self.balance - self.bills_total()
+    }
+
+    /// `safe_to_spend` spread over the days left
+    #[must_use]
+    pub fn daily_allowance(&self) -> i64 {
+        self.safe_to_spend() / self.days_left()
+    }
+}
+
+/// Project the balance of each open account with a stored balance to the end
+/// of the month containing `today`, averaging spending over the `window_days`
+/// before it
+///
+/// # Errors
+/// Will return errors if the database cannot be read or no balances have been
+/// stored.
+pub async fn project(
+    pool: DatabasePool,
+    today: NaiveDate,
+    window_days: u64,
+    timezone: Tz,
+) -> Result<Vec<Projection>, Error> {
+    let snapshot = pool
+        .balances()
+        .read_latest_snapshot()
+        .await?
+        .ok_or_else(|| Error::Error("No balances stored: run `update` first".into()))?;
+    let accounts = pool.accounts().read_accounts().await?;
+
+    let first_of_month = today.with_day(1).unwrap_or(today);
+    let since = (first_of_month - Months::new(2)).min(today - Days::new(window_days));
+    let now = Utc::now().naive_utc();
+    let service = pool.transactions();
+    let transactions = service
+        .read_report_data(start_of_day(since, timezone), now)
+        .await?;
+    let since_snapshot = service.read_export_data(snapshot.taken, now).await?;
+
+    let mut projections = Vec::new();
+    for account in accounts.iter().filter(|a| !a.closed) {
+        let Some(stored) = snapshot
+            .accounts
+            .iter()
+            .find(|s| s.account_id == account.id)
+        else {
+            continue;
+        };
+        // only the account's own currency moves its balance
+        let currency = &stored.balance.currency;
+        let balance = stored.balance.balance
+            + since_snapshot
+                .iter()
+                .filter(|tx| {
+                    tx.account_id == account.id
+                        && tx.created > snapshot.taken
+                        && &tx.currency == currency
+                })
+                .map(|tx| tx.amount)
+                .sum::<i64>();
+        let history: Vec<&ExportTransaction> = transactions
+            .iter()
+            .filter(|tx| tx.account_id == account.id && &tx.currency == currency)
+            .collect();
+
+        projections.push(projection(
+            (account.id.clone(), account.owner_type.clone()),
+            currency.clone(),
+            balance,
+            &history,
+            today,
+            window_days,
+            timezone,
+        ));
+    }
+
+    Ok(projections)
+}
+
+/// The bills of all accounts due in the `days` after `today`, or on it, and
+/// still unpaid this month
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn upcoming_bills(
+    pool: DatabasePool,
+    today: NaiveDate,
+    days: u64,
+    timezone: Tz,
+) -> Result<Vec<Bill>, Error> {
+    let first_of_month = today.with_day(1).unwrap_or(today);
+    let transactions = pool
+        .transactions()
+        .read_report_data(
+            start_of_day(first_of_month - Months::new(2), timezone),
+            Utc::now().naive_utc(),
+        )
+        .await?;
+    let history: Vec<&ExportTransaction> = transactions.iter().collect();
+
+    let (mut bills, _) = bills_due(&spending(&history, timezone), today);
+    bills.retain(|bill| bill.due <= today + Days::new(days));
+
+    Ok(bills)
+}
+
+fn projection(
+    (account_id, account_name): (String, String),
+    currency: String,
+    balance: i64,
+    history: &[&ExportTransaction],
+    today: NaiveDate,
+    window_days: u64,
+    timezone: Tz,
+) -> Projection {
+    let first_of_month = today.with_day(1).unwrap_or(today);
+    let month_end = (first_of_month + Months::new(1))
+        .pred_opt()
+        .unwrap_or(today);
+    let spending = spending(history, timezone);
+    let (bills, recurring) = bills_due(&spending, today);
+
+    let window_start = today - Days::new(window_days);
+    let window_spend: i64 = spending
+        .iter()
+        .filter(|(date, payee, _)| {
+            *date >= window_start && *date < today && !recurring.contains(payee)
+        })
+        .map(|(_, _, amount)| amount)
+        .sum();
+    let daily_spend = window_spend / i64::try_from(window_days.max(1)).unwrap_or(i64::MAX);
+
+    Projection {
+        account_id,
+        account_name,
+        currency,
+        balance,
+        today,
+        month_end,
+        bills,
+        daily_spend,
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// The payments in `history` as (local date, payee, positive amount)
+fn spending<'a>(history: &[&'a ExportTransaction], timezone: Tz) -> Vec<(NaiveDate, &'a str, i64)> {
+    history
+        .iter()
+        .filter(|tx| tx.amount < 0 && !tx.is_transfer)
+        .map(|tx| {
+            let payee = tx.merchant_name.as_deref().unwrap_or(&tx.description);
+            (local_date(tx.created, timezone), payee, -tx.amount)
+        })
+        .collect()
+}
+
+// The bills still due in the month containing `today`, soonest first, and the
+// payees paid in each of the last two months
+fn bills_due<'a>(
+    spending: &[(NaiveDate, &'a str, i64)],
+    today: NaiveDate,
+) -> (Vec<Bill>, BTreeSet<&'a str>) {
+    let first_of_month = today.with_day(1).unwrap_or(today);
+    let month_end = (first_of_month + Months::new(1))
+        .pred_opt()
+        .unwrap_or(today);
+
+    // the months each payee was paid in, and their latest payment
+    let mut paid: BTreeMap<&str, (BTreeSet<NaiveDate>, NaiveDate, i64)> = BTreeMap::new();
+    for &(date, payee, amount) in spending {
+        let month = date.with_day(1).unwrap_or(date);
+        let (months, last, last_amount) = paid.entry(payee).or_default();
+        months.insert(month);
+        if date >= *last {
+            *last = date;
+            *last_amount = amount;
+        }
+    }
+    let previous = first_of_month - Months::new(1);
+    let recurring: BTreeMap<&str, (bool, NaiveDate, i64)> = paid
+        .into_iter()
+        .filter(|(_, (months, _, _))| {
+            months.contains(&previous) && months.contains(&(previous - Months::new(1)))
+        })
+        .map(|(payee, (months, last, amount))| {
+            (payee, (months.contains(&first_of_month), last, amount))
+        })
+        .collect();
+
+    let mut bills: Vec<Bill> = recurring
+        .iter()
+        .filter(|(_, (paid_this_month, _, _))| !paid_this_month)
+        .map(|(payee, (_, last, amount))| {
+            let due = first_of_month
+                .with_day(last.day())
+                .unwrap_or(month_end)
+                .max(today);
+            Bill {
+                payee: (*payee).to_string(),
+                due,
+                amount: *amount,
+            }
+        })
+        .collect();
+    bills.sort_by_key(|bill| bill.due);
+
+    (bills, recurring.into_keys().collect())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn tx(day: &str, payee: &str, amount: i64) -> ExportTransaction {
+        ExportTransaction {
+            created: date(day).and_hms_opt(12, 0, 0).unwrap(),
+            amount,
+            currency: "GBP".to_string(),
+            merchant_name: Some(payee.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn takes_off_bills_due_and_usual_spending() {
+        // Arrange
+        let history = [
+            tx("2024-04-03", "Netflix", -1099),
+            tx("2024-05-03", "Netflix", -1099),
+            tx("2024-06-03", "Netflix", -1099),
+            tx("2024-04-20", "Gym", -3000),
+            tx("2024-05-21", "Gym", -3500),
+            tx("2024-05-25", "Bakery", -1500),
+            tx("2024-06-05", "Tesco", -6000),
+            tx("2024-06-08", "Cafe", -1500),
+        ];
+        let history: Vec<&ExportTransaction> = history.iter().collect();
+
+        // Act
+        let projection = projection(
+            ("acc_1".to_string(), "personal".to_string()),
+            "GBP".to_string(),
+            100_000,
+            &history,
+            date("2024-06-10"),
+            30,
+            Tz::UTC,
+        );
+
+        // Assert
+        assert_eq!(
+            projection.bills,
+            vec![Bill {
+                payee: "Gym".to_string(),
+                due: date("2024-06-21"),
+                amount: 3500,
+            }]
+        );
+        // Bakery, Tesco and Cafe over 30 days
+        assert_eq!(projection.daily_spend, 300);
+        assert_eq!(projection.days_left(), 21);
+        assert_eq!(projection.end_of_month(), 100_000 - 3500 - 300 * 21);
+        assert_eq!(projection.safe_to_spend(), 96_500);
+        assert_eq!(projection.daily_allowance(), 96_500 / 21);
+    }
+}
@@ -0,0 +1,255 @@
+//! Reconciliation against Monzo's CSV export
+//!
+//! The Monzo app can export an account's transactions as CSV. Comparing that
+//! export with the synced transactions, matched by transaction id, catches
+//! transactions a sync silently missed, such as a skipped page, as well as
+//! amounts and dates that have drifted since they were stored.
+//!
+//! Zero-amount rows, such as active card checks, are stored as card events
+//! rather than transactions and are skipped.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::{currency, error::AppErrors as Error, model::DatabasePool, timezone::to_local};
+
+/// Times further apart than this count as drifted
+const DRIFT_SECONDS: i64 = 60;
+
+/// A row of a Monzo CSV export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvTransaction {
+    pub id: String,
+    /// Local time, as shown in the app
+    pub created: NaiveDateTime,
+    pub name: String,
+    /// Amount in minor units, negative for spending
+    pub amount: i64,
+    pub currency: String,
+    /// Category name as shown in the app, e.g. "Eating out"
+    pub category: String,
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    #[serde(rename = "Transaction ID")]
+    id: String,
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Time")]
+    time: String,
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Amount")]
+    amount: String,
+    #[serde(rename = "Currency")]
+    currency: String,
+    #[serde(rename = "Category", default)]
+    category: String,
+}
+
+/// A transaction whose stored amount differs from the export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmountMismatch {
+    pub transaction: CsvTransaction,
+    pub stored: i64,
+}
+
+/// A transaction whose stored time differs from the export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateDrift {
+    pub transaction: CsvTransaction,
+    /// Stored time, converted to local time
+    pub stored: NaiveDateTime,
+}
+
+/// The differences between a CSV export and the database
+#[derive(Debug, Default, Clone)]
+pub struct Reconciliation {
+    /// Rows compared, excluding zero-amount rows
+    pub compared: usize,
+    /// Rows in the export that were never synced
+    pub missing: Vec<CsvTransaction>,
+    pub amount_mismatches: Vec<AmountMismatch>,
+    pub date_drift: Vec<DateDrift>,
+    /// Ids and local times of stored transactions of the exported account,
+    /// within the exported dates, that aren't in the export
+    pub unexpected: Vec<(String, NaiveDateTime)>,
+}
+
+impl Reconciliation {
+    /// Compare the transactions in a CSV export with the stored ones, with
+    /// stored times converted to `timezone`
+    ///
+    /// # Errors
+    /// Will return an error if the export can't be parsed or the database
+    /// can't be read.
+    pub async fn run(pool: DatabasePool, csv: impl Read, timezone: Tz) -> Result<Self, Error> {
+        let exported: Vec<CsvTransaction> = read_csv(csv)?
+            .into_iter()
+            .filter(|tx| tx.amount != 0)
+            .collect();
+        let stored = pool.transactions().read_transactions().await?;
+        let by_id: HashMap<&str, _> = stored.iter().map(|tx| (tx.id.as_str(), tx)).collect();
+
+        let mut reconciliation = Self {
+            compared: exported.len(),
+            ..Default::default()
+        };
+        let mut accounts = HashSet::new();
+        for tx in &exported {
+            let Some(stored) = by_id.get(tx.id.as_str()) else {
+                reconciliation.missing.push(tx.clone());
+                continue;
+            };
+            accounts.insert(stored.account_id.as_str());
+            if stored.amount != tx.amount {
+                reconciliation.amount_mismatches.push(AmountMismatch {
+                    transaction: tx.clone(),
+                    stored: stored.amount,
+                });
+            }
+            let local = to_local(stored.created, timezone);
+            if (local - tx.created).num_seconds().abs() > DRIFT_SECONDS {
+                reconciliation.date_drift.push(DateDrift {
+                    transaction: tx.clone(),
+                    stored: local,
+                });
+            }
+        }
+
+        let first = exported.iter().map(|tx| tx.created).min();
+        let last = exported.iter().map(|tx| tx.created).max();
+        if let (Some(first), Some(last)) = (first, last) {
+            let ids: HashSet<&str> = exported.iter().map(|tx| tx.id.as_str()).collect();
+            reconciliation.unexpected = stored
+                .iter()
+                .filter(|tx| accounts.contains(tx.account_id.as_str()))
+                .filter(|tx| tx.amount != 0 && !ids.contains(tx.id.as_str()))
+                .map(|tx| (tx.id.clone(), to_local(tx.created, timezone)))
+                .filter(|(_, local)| (first..=last).contains(local))
+                .collect();
+        }
+
+        Ok(reconciliation)
+    }
+
+    /// Whether the export and the database agree
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.differences() == 0
+    }
+
+    /// The number of differences found
+    #[must_use]
+    pub fn differences(&self) -> usize {
+        self.missing.len()
+            + self.amount_mismatches.len()
+            + self.date_drift.len()
+            + self.unexpected.len()
+    }
+}
+
+/// Read the transactions in a Monzo CSV export
+///
+/// # Errors
+/// Will return an error if a row lacks a column of the export or has an
+/// invalid date, time or amount.
+pub fn read_csv(csv: impl Read) -> Result<Vec<CsvTransaction>, Error> {
+    let mut reader = csv::Reader::from_reader(csv);
+    let mut transactions = Vec::new();
+    for (i, row) in reader.deserialize::<CsvRow>().enumerate() {
+        // the header is line 1
+        let line = i + 2;
+        let row = row.map_err(|e| Error::Error(format!("Invalid CSV export: {e}")))?;
+        let date = NaiveDate::parse_from_str(&row.date, "%d/%m/%Y")
+            .map_err(|_| Error::Error(format!("Invalid date '{}' on line {line}", row.date)))?;
+        let time = NaiveTime::parse_from_str(&row.time, "%H:%M:%S")
+            .map_err(|_| Error::Error(format!("Invalid time '{}' on line {line}", row.time)))?;
+        transactions.push(CsvTransaction {
+            amount: currency::parse(&row.amount, &row.currency)?,
+            id: row.id,
+            created: date.and_time(time),
+            name: row.name,
+            currency: row.currency,
+            category: row.category,
+        });
+    }
+
+    Ok(transactions)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::transaction::TransactionResponse, tests::test::test_db};
+
+    const HEADER: &str = "Transaction ID,Date,Time,Type,Name,Emoji,Category,Amount,Currency,Local amount,Local currency,Notes and #tags,Address,Receipt,Description,Category split,Money Out,Money In";
+
+    #[tokio::test]
+    async fn reports_differences() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = pool.transactions();
+        for (id, amount, created) in [
+            ("tx_a", -350, "2024-06-01T09:00:00Z"),
+            ("tx_b", -1200, "2024-06-01T09:30:00Z"),
+            ("tx_c", -500, "2024-06-01T10:00:00Z"),
+        ] {
+            service
+                .save_transaction(&TransactionResponse {
+                    id: id.to_string(),
+                    account_id: "1".to_string(),
+                    category_id: "1".to_string(),
+                    amount,
+                    currency: "GBP".to_string(),
+                    created: created.parse().unwrap(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        // times are local, an hour ahead of UTC in June
+        let csv = [
+            HEADER,
+            "tx_b,01/06/2024,10:30:00,Card payment,Shop,,General,-12.34,GBP,-12.34,GBP,,,,SHOP,,12.34,",
+            "tx_missing,01/06/2024,10:45:00,Card payment,Shop,,General,-1.00,GBP,-1.00,GBP,,,,SHOP,,1.00,",
+            "tx_check,01/06/2024,10:50:00,Card payment,Shop,,General,0.00,GBP,0.00,GBP,,,,SHOP,,,",
+            "tx_a,01/06/2024,12:00:00,Card payment,Shop,,General,-3.50,GBP,-3.50,GBP,,,,SHOP,,3.50,",
+        ]
+        .join("\n");
+
+        // Act
+        let reconciliation = Reconciliation::run(pool, csv.as_bytes(), Tz::Europe__London)
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(reconciliation.compared, 3);
+        assert_eq!(reconciliation.missing.len(), 1);
+        assert_eq!(reconciliation.missing[0].id, "tx_missing");
+        assert_eq!(reconciliation.amount_mismatches.len(), 1);
+        assert_eq!(reconciliation.amount_mismatches[0].transaction.id, "tx_b");
+        assert_eq!(reconciliation.amount_mismatches[0].stored, -1200);
+        assert_eq!(reconciliation.date_drift.len(), 1);
+        assert_eq!(reconciliation.date_drift[0].transaction.id, "tx_a");
+        assert_eq!(reconciliation.unexpected.len(), 1);
+        assert_eq!(reconciliation.unexpected[0].0, "tx_c");
+        assert_eq!(reconciliation.differences(), 4);
+    }
+
+    #[test]
+    fn rejects_invalid_rows() {
+        let csv = format!("{HEADER}\ntx_1,31/02/2024,10:00:00,,Shop,,,-1.00,GBP,,,,,,,,,");
+
+        assert!(read_csv(csv.as_bytes()).is_err());
+    }
+}
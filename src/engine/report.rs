@@ -0,0 +1,429 @@
+//! Reports
+//!
+//! Gathers the data behind the console reports and returns it as plain
+//! structures for the caller to render.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    client::Monzo,
+    currency,
+    error::AppErrors as Error,
+    model::{
+        account::AccountForDB,
+        balance::{AccountSnapshot, Balance, PotBalance},
+        manual::{ManualAccount, ManualValuation},
+        transaction::TransactionForDB,
+        DatabasePool,
+    },
+};
+
+/// The balance of an account and its open pots
+#[derive(Debug)]
+pub struct AccountBalance {
+    pub account: AccountForDB,
+    pub balance: Balance,
+    pub pots: Vec<PotBalance>,
+}
+
+/// Balances for all accounts
+#[derive(Debug, Default)]
+pub struct BalanceReport {
+    /// When the balances were fetched from Monzo
+    pub taken: NaiveDateTime,
+    pub accounts: Vec<AccountBalance>,
+}
+
+impl BalanceReport {
+    /// Sum of all account and pot balances in minor units, by currency
+    #[must_use]
+    pub fn totals(&self) -> BTreeMap<String, i64> {
+        let mut totals = BTreeMap::new();
+        for account in &self.accounts {
+            *totals.entry(account.balance.currency.clone()).or_default() += account.balance.balance;
+            for pot in &account.pots {
+                *totals.entry(pot.currency.clone()).or_default() += pot.balance;
+            }
+        }
+        totals
+    }
+
+    /// Sum of all balances converted to `base` in minor units, where `rates`
+    /// holds the value in `base` of one unit of each other currency
+    ///
+    /// # Errors
+    /// Will return an error naming the currencies without a rate.
+    pub fn total_in(&self, base: &str, rates: &HashMap<String, f64>) -> Result<i64, Error> {
+        convert_totals(&self.totals(), base, rates)
+    }
+}
+
+/// A stored account and how many transactions it has
+#[derive(Debug, Serialize)]
+pub struct AccountDetails {
+    #[serde(flatten)]
+    pub account: AccountForDB,
+    pub transactions: i64,
+}
+
+/// Monzo balances and the latest valuation of each manual account
+#[derive(Debug, Default)]
+pub struct NetWorth {
+    /// The latest stored Monzo balances, if any
+    pub balances: Option<BalanceReport>,
+    pub manual: Vec<(ManualAccount, ManualValuation)>,
+}
+
+impl NetWorth {
+    /// Assets less liabilities in minor units, by currency
+    #[must_use]
+    pub fn totals(&self) -> BTreeMap<String, i64> {
+        let mut totals = self
+            .balances
+            .as_ref()
+            .map(BalanceReport::totals)
+            .unwrap_or_default();
+        for (account, valuation) in &self.manual {
+            *totals.entry(account.currency.clone()).or_default() += valuation.balance;
+        }
+        totals
+    }
+
+    /// Net worth converted to `base` in minor units, see [`BalanceReport::total_in`]
+    ///
+    /// # Errors
+    /// Will return an error naming the currencies without a rate.
+    pub fn total_in(&self, base: &str, rates: &HashMap<String, f64>) -> Result<i64, Error> {
+        convert_totals(&self.totals(), base, rates)
+    }
+}
+
+/// Builds reports from the database and the Monzo API
+pub struct Reporter {
+    pool: DatabasePool,
+    monzo: Monzo,
+}
+
+impl Reporter {
+    #[must_use]
+    pub fn new(pool: DatabasePool, monzo: Monzo) -> Self {
+        Self { pool, monzo }
+    }
+
+    /// The most recently stored balances, or `None` if none have been stored
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn balances(&self) -> Result<Option<BalanceReport>, Error> {
+        let Some(snapshot) = self.pool.balances().read_latest_snapshot().await? else {
+            return Ok(None);
+        };
+
+        let mut accounts = self.pool.accounts().read_accounts().await?;
+        let accounts = snapshot
+            .accounts
+            .into_iter()
+            .filter_map(|entry| {
+                let index = accounts.iter().position(|a| a.id == entry.account_id)?;
+                Some(AccountBalance {
+                    account: accounts.swap_remove(index),
+                    balance: entry.balance,
+                    pots: entry.pots,
+                })
+            })
+            .collect();
+
+        Ok(Some(BalanceReport {
+            taken: snapshot.taken,
+            accounts,
+        }))
+    }
+
+    /// Fetch the current balances of all accounts and their open pots from
+    /// Monzo and store them
+    ///
+    /// # Errors
+    /// Will return errors if the Monzo API cannot be reached or the balances
+    /// cannot be stored.
+    pub async fn refresh_balances(&self) -> Result<BalanceReport, Error> {
+        let accounts = self.fetch_accounts().await?;
+        let snapshot = fetch_balances(&self.monzo, &accounts).await?;
+        self.pool
+            .balances()
+            .save_snapshot(Utc::now().naive_utc(), &snapshot)
+            .await?;
+
+        self.balances()
+            .await?
+            .ok_or_else(|| Error::DbError("Balance snapshot was not saved".into()))
+    }
+
+    /// The stored accounts with their transaction counts
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn accounts(&self) -> Result<Vec<AccountDetails>, Error> {
+        let mut accounts = self.pool.accounts().read_accounts().await?;
+        accounts.sort_by_key(|account| account.created);
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        for total in self.pool.transactions().read_account_totals().await? {
+            *counts.entry(total.account_id).or_default() += total.count;
+        }
+
+        Ok(accounts
+            .into_iter()
+            .map(|account| AccountDetails {
+                transactions: counts.get(&account.id).copied().unwrap_or_default(),
+                account,
+            })
+            .collect())
+    }
+
+    /// Fetch the accounts from Monzo and store them, updating any details
+    /// that have changed, then return them as [`Reporter::accounts`]
+    ///
+    /// # Errors
+    /// Will return errors if the Monzo API cannot be reached or the database
+    /// cannot be written.
+    pub async fn refresh_accounts(&self) -> Result<Vec<AccountDetails>, Error> {
+        let account_service = self.pool.accounts();
+        for account in self.fetch_accounts().await? {
+            account_service.update_account(&account).await?;
+        }
+        self.accounts().await
+    }
+
+    // Fetch the accounts from Monzo, saving any that are new
+    async fn fetch_accounts(&self) -> Result<Vec<AccountForDB>, Error> {
+        let account_service = self.pool.accounts();
+        let accounts: Vec<AccountForDB> = self
+            .monzo
+            .accounts()
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        for account in &accounts {
+            match account_service.save_account(account).await {
+                Ok(()) | Err(Error::Duplicate(_)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(accounts)
+    }
+
+    /// The latest stored balances with the latest valuation of every manual
+    /// account. Manual accounts without a valuation are left out.
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn net_worth(&self) -> Result<NetWorth, Error> {
+        let service = self.pool.manual_accounts();
+        let mut accounts = service.read_accounts().await?;
+        let manual = service
+            .latest_valuations()
+            .await?
+            .into_iter()
+            .filter_map(|valuation| {
+                let index = accounts.iter().position(|a| a.id == valuation.account_id)?;
+                Some((accounts.swap_remove(index), valuation))
+            })
+            .collect();
+
+        Ok(NetWorth {
+            balances: self.balances().await?,
+            manual,
+        })
+    }
+
+    /// The latest stored rate into `base` of each currency
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn fx_rates(&self, base: &str) -> Result<HashMap<String, f64>, Error> {
+        self.pool.fx_rates().latest_rates(base).await
+    }
+
+    /// Stored transactions created between the given dates
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn transactions(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<TransactionForDB>, Error> {
+        self.pool
+            .transactions()
+            .read_transactions_for_dates(from, until)
+            .await
+    }
+}
+
+// Sum per-currency totals into `base`, naming any currency without a rate
+fn convert_totals(
+    totals: &BTreeMap<String, i64>,
+    base: &str,
+    rates: &HashMap<String, f64>,
+) -> Result<i64, Error> {
+    let mut total = 0;
+    let mut missing = Vec::new();
+    for (code, amount) in totals {
+        if code == base {
+            total += amount;
+        } else if let Some(rate) = rates.get(code) {
+            total += currency::convert(*amount, code, base, *rate);
+        } else {
+            missing.push(code.as_str());
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(total)
+    } else {
+        Err(Error::Error(format!(
+            "No exchange rate to {base} for {}",
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Fetch the balance and open pots of each account
+pub(crate) async fn fetch_balances(
+    monzo: &Monzo,
+    accounts: &[AccountForDB],
+) -> Result<Vec<AccountSnapshot>, Error> {
+    let mut snapshot = Vec::new();
+
+    for account in accounts {
+        let balance = monzo.balance(&account.id).await?;
+        let pots = monzo
+            .pots(&account.id)
+            .await?
+            .into_iter()
+            .filter(|pot| !pot.deleted)
+            .map(|pot| PotBalance {
+                pot_id: pot.id,
+                name: pot.name,
+                balance: pot.balance,
+                currency: pot.currency,
+            })
+            .collect();
+
+        snapshot.push(AccountSnapshot {
+            account_id: account.id.clone(),
+            balance,
+            pots,
+        });
+    }
+
+    Ok(snapshot)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{mock::MockMonzo, test::test_db};
+
+    fn report() -> BalanceReport {
+        let account = |balance, currency: &str, pots: Vec<i64>| AccountBalance {
+            account: AccountForDB::default(),
+            balance: Balance {
+                balance,
+                currency: currency.to_string(),
+                ..Default::default()
+            },
+            pots: pots
+                .into_iter()
+                .map(|balance| PotBalance {
+                    balance,
+                    currency: currency.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        BalanceReport {
+            accounts: vec![
+                account(1000, "GBP", vec![250]),
+                account(-200, "GBP", vec![]),
+                account(5000, "EUR", vec![1000]),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn totals_include_pots_by_currency() {
+        let totals = report().totals();
+
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals["GBP"], 1050);
+        assert_eq!(totals["EUR"], 6000);
+    }
+
+    #[test]
+    fn totals_keep_pots_in_their_own_currency() {
+        let mut report = report();
+        report.accounts[0].pots[0].currency = "EUR".to_string();
+
+        let totals = report.totals();
+
+        assert_eq!(totals["GBP"], 800);
+        assert_eq!(totals["EUR"], 6250);
+    }
+
+    #[test]
+    fn total_in_converts_with_rates() {
+        let report = report();
+        let rates = HashMap::from([("EUR".to_string(), 0.85)]);
+
+        assert_eq!(report.total_in("GBP", &rates).unwrap(), 1050 + 5100);
+        assert!(report.total_in("GBP", &HashMap::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn refreshed_balances_are_read_back_offline() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        let reporter = Reporter::new(pool.clone(), mock.client());
+        assert!(reporter.balances().await.unwrap().is_none());
+        let refreshed = reporter.refresh_balances().await.unwrap();
+
+        // Act
+        let offline = Monzo::with_base_url("http://127.0.0.1:9", "none").unwrap();
+        let stored = Reporter::new(pool, offline)
+            .balances()
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Assert
+        assert_eq!(stored.taken, refreshed.taken);
+        assert_eq!(stored.totals(), refreshed.totals());
+        assert_eq!(stored.accounts[0].balance.balance, 5000);
+    }
+
+    #[tokio::test]
+    async fn accounts_have_transaction_counts() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        let reporter = Reporter::new(pool, mock.client());
+
+        // Act
+        let accounts = reporter.refresh_accounts().await.unwrap();
+
+        // Assert
+        let counts: Vec<_> = accounts
+            .iter()
+            .map(|details| (details.account.id.as_str(), details.transactions))
+            .collect();
+        assert_eq!(counts, vec![("acc_00009237aqC8c5umZmrRdh", 0), ("1", 2)]);
+    }
+}
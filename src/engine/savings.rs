@@ -0,0 +1,235 @@
+//! Savings rate
+//!
+//! Money paid into savings pots each month, net of withdrawals, against the
+//! month's income. A savings pot is one Monzo reports with a savings type, or
+//! one named under `[savings]`. Income is money coming in that isn't a
+//! transfer, so moving money out of a pot or from another of the user's
+//! accounts doesn't count.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+
+use crate::{
+    configuration::Savings, error::AppErrors as Error, model::DatabasePool, timezone::local_date,
+};
+
+/// Income and savings in one month and currency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavingsMonth {
+    /// The first day of the month
+    pub month: NaiveDate,
+    pub currency: String,
+    pub income: i64,
+    /// Paid into savings pots less withdrawn from them
+    pub saved: i64,
+}
+
+impl SavingsMonth {
+    /// Savings as a percentage of income, if there was any
+    #[must_use]
+    pub fn rate(&self) -> Option<f64> {
+        savings_rate(self.income, self.saved)
+    }
+}
+
+/// `saved` as a percentage of `income`, if there was any
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn savings_rate(income: i64, saved: i64) -> Option<f64> {
+    (income > 0).then(|| saved as f64 * 100.0 / income as f64)
+}
+
+/// Savings per month
+#[derive(Debug, Clone, Default)]
+pub struct SavingsReport {
+    /// Oldest month first
+    pub months: Vec<SavingsMonth>,
+    /// Net paid into each savings pot, by pot name and currency
+    pub pots: BTreeMap<(String, String), i64>,
+}
+
+impl SavingsReport {
+    /// Total income and savings over all months by currency, as
+    /// (currency, income, saved)
+    #[must_use]
+    pub fn totals(&self) -> Vec<(String, i64, i64)> {
+        let mut totals: BTreeMap<&str, (i64, i64)> = BTreeMap::new();
+        for month in &self.months {
+            let (income, saved) = totals.entry(&month.currency).or_default();
+            *income += month.income;
+            *saved += month.saved;
+        }
+        totals
+            .into_iter()
+            .map(|(code, (income, saved))| (code.to_string(), income, saved))
+            .collect()
+    }
+
+    /// Report savings between `since` and `until`, by month in `timezone`
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn build(
+        pool: DatabasePool,
+        settings: &Savings,
+        since: NaiveDateTime,
+        until: NaiveDateTime,
+        timezone: Tz,
+    ) -> Result<Self, Error> {
+        let mut months: BTreeMap<(NaiveDate, String), (i64, i64)> = BTreeMap::new();
+        let month = |created| {
+            let date = local_date(created, timezone);
+            date.with_day(1).unwrap_or(date)
+        };
+
+        for tx in pool.transactions().read_report_data(since, until).await? {
+            if tx.amount > 0 && !tx.is_transfer {
+                months
+                    .entry((month(tx.created), tx.currency))
+                    .or_default()
+                    .0 += tx.amount;
+            }
+        }
+
+        let mut pots = BTreeMap::new();
+        for movement in pool.pots().read_pot_movements(since, until).await? {
+            if !is_savings_pot(
+                settings,
+                &movement.pot_id,
+                &movement.pot_name,
+                &movement.pot_type,
+            ) {
+                continue;
+            }
+            months
+                .entry((month(movement.created), movement.currency.clone()))
+                .or_default()
+                .1 -= movement.amount;
+            *pots
+                .entry((movement.pot_name, movement.currency))
+                .or_default() -= movement.amount;
+        }
+
+        let months = months
+            .into_iter()
+            .map(|((month, currency), (income, saved))| SavingsMonth {
+                month,
+                currency,
+                income,
+                saved,
+            })
+            .collect();
+
+        Ok(Self { months, pots })
+    }
+}
+
+// Monzo reports ordinary pots as `default` and savings pots with a type such
+// as `flexible_savings`
+fn is_savings_pot(settings: &Savings, id: &str, name: &str, pot_type: &str) -> bool {
+    pot_type.contains("savings")
+        || settings
+            .pots
+            .iter()
+            .any(|pot| pot == id || pot.eq_ignore_ascii_case(name))
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{
+        model::{pot::Pot, transaction::TransactionResponse, transfer::TransferRules},
+        tests::test::test_db,
+    };
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    async fn pay(pool: &DatabasePool, id: &str, day: &str, description: &str, amount: i64) {
+        let tx = TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount,
+            currency: "GBP".to_string(),
+            description: description.to_string(),
+            created: Utc.from_utc_datetime(&date(day).and_hms_opt(12, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        pool.transactions().save_transaction(&tx).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn saving_is_net_pot_contributions_over_income() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        pool.pots()
+            .save_pot(&Pot {
+                id: "pot_savings".to_string(),
+                name: "Rainy day".to_string(),
+                currency: "GBP".to_string(),
+                pot_type: "flexible_savings".to_string(),
+                account_name: "personal".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        pay(&pool, "a1", "2024-04-25", "ACME SALARY", 200_000).await;
+        pay(&pool, "a2", "2024-04-26", "pot_savings", -30_000).await;
+        pay(&pool, "a3", "2024-04-27", "1", -10_000).await;
+        pay(&pool, "b1", "2024-05-25", "ACME SALARY", 250_000).await;
+        pay(&pool, "b2", "2024-05-26", "pot_savings", -60_000).await;
+        pay(&pool, "b3", "2024-05-28", "pot_savings", 10_000).await;
+        pool.classify_transfers(&TransferRules::default())
+            .await
+            .unwrap();
+        let settings = Savings {
+            pots: vec!["pot_name".to_string()],
+        };
+
+        // Act
+        let report = SavingsReport::build(
+            pool,
+            &settings,
+            date("2024-04-01").and_hms_opt(0, 0, 0).unwrap(),
+            date("2024-06-01").and_hms_opt(0, 0, 0).unwrap(),
+            Tz::UTC,
+        )
+        .await
+        .unwrap();
+
+        // Assert
+        assert_eq!(
+            report.months,
+            vec![
+                SavingsMonth {
+                    month: date("2024-04-01"),
+                    currency: "GBP".to_string(),
+                    income: 200_000,
+                    saved: 40_000,
+                },
+                SavingsMonth {
+                    month: date("2024-05-01"),
+                    currency: "GBP".to_string(),
+                    income: 250_000,
+                    saved: 50_000,
+                },
+            ]
+        );
+        assert_eq!(report.months[0].rate(), Some(20.0));
+        assert_eq!(report.totals(), vec![("GBP".to_string(), 450_000, 90_000)]);
+        assert_eq!(
+            report
+                .pots
+                .get(&("Rainy day".to_string(), "GBP".to_string())),
+            Some(&80_000)
+        );
+    }
+}
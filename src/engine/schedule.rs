@@ -0,0 +1,166 @@
+//! When `watch` syncs
+//!
+//! Syncs run every `interval_minutes`. Each sync that finds no new
+//! transactions doubles the wait, up to `max_interval_minutes`, so quiet
+//! periods such as overnight use fewer API calls; the first new transaction
+//! brings it back to the interval. No syncs start during the quiet hours.
+
+use std::str::FromStr;
+
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+
+use crate::{configuration::Watch, error::AppErrors as Error};
+
+/// A daily period without syncs, which may span midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Whether `time` falls in the quiet hours
+    #[must_use]
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = Error;
+
+    /// Parse `HH:MM-HH:MM`, e.g. `23:00-07:00`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Error(format!("Invalid quiet hours '{s}', expected HH:MM-HH:MM"));
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+/// The wait between syncs, adapting to how often transactions arrive
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    interval: Duration,
+    max_interval: Duration,
+    quiet_hours: Option<QuietHours>,
+    delay: Duration,
+}
+
+impl Schedule {
+    /// A schedule from the `[watch]` configuration
+    ///
+    /// # Errors
+    /// Will return an error if the intervals aren't positive or the quiet
+    /// hours can't be parsed.
+    pub fn new(watch: &Watch) -> Result<Self, Error> {
+        if watch.interval_minutes <= 0 || watch.max_interval_minutes < watch.interval_minutes {
+            return Err(Error::Error(
+                "`interval_minutes` must be positive and at most `max_interval_minutes`".into(),
+            ));
+        }
+        let interval = Duration::minutes(watch.interval_minutes);
+
+        Ok(Self {
+            interval,
+            max_interval: Duration::minutes(watch.max_interval_minutes),
+            quiet_hours: watch.quiet_hours.as_deref().map(str::parse).transpose()?,
+            delay: interval,
+        })
+    }
+
+    /// Adjust the wait after a sync that did or didn't find new transactions
+    pub fn record(&mut self, found_new: bool) {
+        self.delay = if found_new {
+            self.interval
+        } else {
+            (self.delay * 2).min(self.max_interval)
+        };
+    }
+
+    /// When to sync next, in local time, after a sync at `now`
+    #[must_use]
+    pub fn next_run(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let next = now + self.delay;
+        match self.quiet_hours {
+            Some(quiet) if quiet.contains(next.time()) => {
+                let end = next.date().and_time(quiet.end);
+                if end > next {
+                    end
+                } else {
+                    end + Duration::days(1)
+                }
+            }
+            _ => next,
+        }
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn schedule(quiet_hours: Option<&str>) -> Schedule {
+        Schedule::new(&Watch {
+            interval_minutes: 15,
+            max_interval_minutes: 60,
+            quiet_hours: quiet_hours.map(str::to_string),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn backs_off_until_transactions_arrive() {
+        // Arrange
+        let mut schedule = schedule(None);
+        let now = at("2024-06-01 12:00");
+
+        // Act
+        let mut delays = Vec::new();
+        for found_new in [false, false, false, true] {
+            schedule.record(found_new);
+            delays.push((schedule.next_run(now) - now).num_minutes());
+        }
+
+        // Assert
+        assert_eq!(delays, vec![30, 60, 60, 15]);
+    }
+
+    #[test]
+    fn waits_out_quiet_hours_across_midnight() {
+        let schedule = schedule(Some("23:00-07:00"));
+
+        assert_eq!(
+            schedule.next_run(at("2024-06-01 22:50")),
+            at("2024-06-02 07:00")
+        );
+        assert_eq!(
+            schedule.next_run(at("2024-06-02 03:00")),
+            at("2024-06-02 07:00")
+        );
+        assert_eq!(
+            schedule.next_run(at("2024-06-02 07:00")),
+            at("2024-06-02 07:15")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_quiet_hours() {
+        assert!("23:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! Pot sweeps
+//!
+//! Each rule under `[[pots.sweeps]]` moves whatever an account holds above a
+//! threshold into one of its pots, once a month from the rule's day, by
+//! default the last of the month. A sweep is recorded once it's deposited, so
+//! a rule that has swept this month is skipped until the next, and it's
+//! deposited with a dedupe id for the month, so Monzo ignores any repeat.
+
+use chrono::{Datelike, Months, NaiveDate, Utc};
+
+use crate::{
+    client::Monzo,
+    configuration::SweepRule,
+    currency,
+    engine::{BalanceReport, Reporter},
+    error::AppErrors as Error,
+    model::DatabasePool,
+};
+
+/// Money to move from an account into a pot, in minor units
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sweep {
+    pub account_id: String,
+    pub pot_id: String,
+    pub pot_name: String,
+    pub amount: i64,
+    pub currency: String,
+    /// Monzo ignores a second deposit with the same id
+    pub dedupe_id: String,
+    /// The rule that made the sweep, as `account/pot`
+    pub rule: String,
+}
+
+/// Sweeps balances into pots by the configured rules
+pub struct Sweeper {
+    pool: DatabasePool,
+    monzo: Monzo,
+    rules: Vec<SweepRule>,
+}
+
+impl Sweeper {
+    #[must_use]
+    pub fn new(pool: DatabasePool, monzo: Monzo, rules: Vec<SweepRule>) -> Self {
+        Self { pool, monzo, rules }
+    }
+
+    /// Whether any rule is due on `today`
+    #[must_use]
+    pub fn is_due(&self, today: NaiveDate) -> bool {
+        self.rules.iter().any(|rule| is_due(rule, today))
+    }
+
+    /// Refresh the balances and work out the sweeps due on `today` by rules
+    /// that haven't swept this month, making and recording the deposits
+    /// unless `dry_run`
+    ///
+    /// # Errors
+    /// Will return an error if the balances can't be fetched, a rule names an
+    /// account or pot that doesn't exist, or a deposit fails.
+    pub async fn run(&self, today: NaiveDate, dry_run: bool) -> Result<Vec<Sweep>, Error> {
+        let service = self.pool.sweeps();
+        let mut rules = Vec::new();
+        for rule in self.rules.iter().filter(|rule| is_due(rule, today)) {
+            if !service.is_swept(&rule_key(rule), today).await? {
+                rules.push(rule.clone());
+            }
+        }
+        if rules.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let report = Reporter::new(self.pool.clone(), self.monzo.clone())
+            .refresh_balances()
+            .await?;
+        let sweeps = plan_sweeps(&rules, &report, today)?;
+        if !dry_run {
+            for sweep in &sweeps {
+                self.monzo
+                    .deposit_into_pot(
+                        &sweep.pot_id,
+                        &sweep.account_id,
+                        sweep.amount,
+                        &sweep.dedupe_id,
+                    )
+                    .await?;
+                service
+                    .record_swept(&sweep.rule, today, Utc::now().naive_utc())
+                    .await?;
+            }
+        }
+
+        Ok(sweeps)
+    }
+}
+
+/// The sweeps the `rules` due on `today` make from the balances in `report`
+///
+/// # Errors
+/// Will return an error if a rule names an account, or a pot of it, that
+/// isn't in the report.
+pub fn plan_sweeps(
+    rules: &[SweepRule],
+    report: &BalanceReport,
+    today: NaiveDate,
+) -> Result<Vec<Sweep>, Error> {
+    let mut sweeps = Vec::new();
+    for rule in rules.iter().filter(|rule| is_due(rule, today)) {
+        let account = report
+            .accounts
+            .iter()
+            .find(|a| {
+                !a.account.closed
+                    && (a.account.id == rule.account
+                        || a.account.owner_type.eq_ignore_ascii_case(&rule.account))
+            })
+            .ok_or_else(|| Error::Error(format!("No open account '{}'", rule.account)))?;
+        let pot = account
+            .pots
+            .iter()
+            .find(|p| p.pot_id == rule.pot || p.name.eq_ignore_ascii_case(&rule.pot))
+            .ok_or_else(|| {
+                Error::Error(format!(
+                    "No pot '{}' in account '{}'",
+                    rule.pot, rule.account
+                ))
+            })?;
+
+        let code = &account.balance.currency;
+        let amount = account.balance.balance - currency::from_major(rule.keep, code);
+        if amount > 0 {
+            sweeps.push(Sweep {
+                account_id: account.account.id.clone(),
+                pot_id: pot.pot_id.clone(),
+                pot_name: pot.name.clone(),
+                amount,
+                currency: code.clone(),
+                dedupe_id: format!("sweep-{}-{}", pot.pot_id, today.format("%Y-%m")),
+                rule: rule_key(rule),
+            });
+        }
+    }
+
+    Ok(sweeps)
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// A rule as configured, to record its sweeps by
+fn rule_key(rule: &SweepRule) -> String {
+    format!(
+        "{}/{}",
+        rule.account.to_lowercase(),
+        rule.pot.to_lowercase()
+    )
+}
+
+fn is_due(rule: &SweepRule, today: NaiveDate) -> bool {
+    let last = last_day_of_month(today);
+    today.day() >= rule.day.unwrap_or(last).min(last)
+}
+
+fn last_day_of_month(date: NaiveDate) -> u32 {
+    date.with_day(1)
+        .and_then(|first| first.checked_add_months(Months::new(1)))
+        .and_then(|next| next.pred_opt())
+        .map_or(31, |last| last.day())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path_regex},
+        Mock, ResponseTemplate,
+    };
+
+    use crate::{
+        engine::AccountBalance,
+        model::{
+            account::AccountForDB,
+            balance::{Balance, PotBalance},
+        },
+        tests::{
+            mock::{MockMonzo, ACCOUNT_ID},
+            test::test_db,
+        },
+    };
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn report() -> BalanceReport {
+        BalanceReport {
+            accounts: vec![AccountBalance {
+                account: AccountForDB {
+                    id: "acc_1".to_string(),
+                    owner_type: "personal".to_string(),
+                    ..Default::default()
+                },
+                balance: Balance {
+                    balance: 72_050,
+                    currency: "GBP".to_string(),
+                    ..Default::default()
+                },
+                pots: vec![PotBalance {
+                    pot_id: "pot_1".to_string(),
+                    name: "Savings".to_string(),
+                    balance: 100_000,
+                    currency: "GBP".to_string(),
+                }],
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sweeps_what_is_above_the_threshold_at_month_end() {
+        // Arrange
+        let rule = |day| SweepRule {
+            account: "Personal".to_string(),
+            pot: "savings".to_string(),
+            keep: 500.0,
+            day,
+        };
+
+        // Act
+        let early = plan_sweeps(&[rule(None)], &report(), date("2024-02-28")).unwrap();
+        let month_end = plan_sweeps(&[rule(None)], &report(), date("2024-02-29")).unwrap();
+        let past_end = plan_sweeps(&[rule(Some(31))], &report(), date("2024-02-29")).unwrap();
+
+        // Assert
+        assert!(early.is_empty());
+        assert_eq!(month_end.len(), 1);
+        assert_eq!(month_end[0].amount, 22_050);
+        assert_eq!(month_end[0].dedupe_id, "sweep-pot_1-2024-02");
+        assert_eq!(past_end, month_end);
+    }
+
+    #[tokio::test]
+    async fn a_rule_sweeps_once_a_month() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        Mock::given(method("PUT"))
+            .and(path_regex("^/pots/.+/deposit$"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"{"id":"pot_1","name":"Savings","balance":137700,"currency":"GBP","deleted":false,"type":"default"}"#,
+                "application/json",
+            ))
+            .expect(1)
+            .mount(mock.server())
+            .await;
+        let rule = SweepRule {
+            account: ACCOUNT_ID.to_string(),
+            pot: "Savings".to_string(),
+            keep: 10.0,
+            day: Some(25),
+        };
+        let sweeper = Sweeper::new(pool, mock.client(), vec![rule]);
+
+        // Act
+        let first = sweeper.run(date("2024-02-27"), false).await.unwrap();
+        let second = sweeper.run(date("2024-02-28"), false).await.unwrap();
+
+        // Assert
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].amount, 4000);
+        assert!(second.is_empty());
+    }
+}
@@ -0,0 +1,871 @@
+//! Synchronise the local database with Monzo
+//!
+//! Fetches accounts, pots and transactions from the API and persists them,
+//! returning what was synced rather than printing it.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing_log::log::{error, info, warn};
+
+use crate::{
+    categorise::AutoCategoriser,
+    client::Monzo,
+    configuration::CategoryDisplay,
+    date_ranges,
+    engine::report::fetch_balances,
+    error::AppErrors as Error,
+    model::{
+        account::AccountForDB,
+        balance::AccountSnapshot,
+        category::Category,
+        fx_rate::FxRate,
+        pot::Pot,
+        sync_run::{RunCounts, SyncProfile},
+        transaction::{SyncPoint, TransactionResponse, Upsert},
+        transfer::TransferRules,
+        DatabasePool,
+    },
+};
+
+/// Error recorded against a run that was stopped before completion
+const INTERRUPTED: &str = "interrupted";
+
+/// Progress events emitted while a sync runs
+#[derive(Debug, Clone)]
+pub enum SyncEvent {
+    /// Started fetching transactions for an account
+    AccountStarted { account_id: String },
+    /// A date window was fetched for an account
+    WindowFetched {
+        account_id: String,
+        since: NaiveDateTime,
+        before: NaiveDateTime,
+        count: usize,
+    },
+    /// A transaction was written to the database
+    TransactionUpserted { transaction_id: String },
+    /// The sync finished
+    SyncCompleted { summary: SyncStats },
+}
+
+/// Counts describing a sync run, or one account's part in it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SyncStats {
+    pub accounts: usize,
+    pub fetched: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    /// New zero-amount card events
+    pub events: usize,
+}
+
+impl From<SyncStats> for RunCounts {
+    fn from(stats: SyncStats) -> Self {
+        let count = |n: usize| i64::try_from(n).unwrap_or(i64::MAX);
+        Self {
+            accounts: count(stats.accounts),
+            inserted: count(stats.inserted),
+            updated: count(stats.updated),
+            skipped: count(stats.skipped),
+        }
+    }
+}
+
+/// The result of a sync run
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    /// Id of the run in the `sync_runs` audit log
+    pub run_id: i64,
+    /// True if the run was cancelled before all windows were fetched
+    pub interrupted: bool,
+    pub stats: SyncStats,
+    /// Counts for each account, by account id
+    pub account_stats: BTreeMap<String, SyncStats>,
+    /// The requested date range
+    pub since: NaiveDateTime,
+    pub before: NaiveDateTime,
+    /// The balances stored at the start of the run
+    pub balances: Vec<AccountSnapshot>,
+    pub accounts: Vec<AccountForDB>,
+    /// Account id -> account name
+    pub account_names: HashMap<String, String>,
+    /// Pot id -> pot name
+    pub pot_names: HashMap<String, String>,
+    /// Non-zero transactions fetched in the requested window, sorted by date.
+    /// Zero-amount card events are stored separately and not included.
+    pub transactions: Vec<TransactionResponse>,
+    /// Time spent in each phase, if profiling
+    pub profile: Option<SyncProfile>,
+}
+
+/// Downloads data from Monzo and persists it to a database
+pub struct SyncEngine {
+    pool: DatabasePool,
+    monzo: Monzo,
+    events: Option<mpsc::Sender<SyncEvent>>,
+    cancel: CancellationToken,
+    transfer_rules: TransferRules,
+    category_display: BTreeMap<String, CategoryDisplay>,
+    categoriser: Option<AutoCategoriser>,
+    incremental: bool,
+    refresh: bool,
+    profile: bool,
+}
+
+impl SyncEngine {
+    #[must_use]
+    pub fn new(pool: DatabasePool, monzo: Monzo) -> Self {
+        Self {
+            pool,
+            monzo,
+            events: None,
+            cancel: CancellationToken::new(),
+            transfer_rules: TransferRules::default(),
+            category_display: BTreeMap::new(),
+            categoriser: None,
+            incremental: false,
+            refresh: false,
+            profile: false,
+        }
+    }
+
+    /// Mark transactions matching these rules as transfers, as well as pot
+    /// transfers and payments between the user's own accounts
+    #[must_use]
+    pub fn with_transfer_rules(mut self, rules: TransferRules) -> Self {
+        self.transfer_rules = rules;
+        self
+    }
+
+    /// Show categories with these display names and emoji, keyed by category
+    /// id or name, instead of the built-in ones
+    #[must_use]
+    pub fn with_category_display(mut self, display: BTreeMap<String, CategoryDisplay>) -> Self {
+        self.category_display = display;
+        self
+    }
+
+    /// Ask this categoriser for the categories of uncategorised transactions
+    /// after syncing
+    #[must_use]
+    pub fn with_categoriser(mut self, categoriser: AutoCategoriser) -> Self {
+        self.categoriser = Some(categoriser);
+        self
+    }
+
+    /// Fetch each account's transactions from its newest stored one, or its
+    /// oldest still pending, rather than from the start of the range. The
+    /// range still limits how far back to go.
+    #[must_use]
+    pub fn incremental(mut self) -> Self {
+        self.incremental = true;
+        self
+    }
+
+    /// Update every stored transaction in the range that differs from Monzo's,
+    /// even if Monzo doesn't report a newer update
+    #[must_use]
+    pub fn refresh(mut self) -> Self {
+        self.refresh = true;
+        self
+    }
+
+    /// Time each phase of the sync and record the timings against the run
+    #[must_use]
+    pub fn profile(mut self) -> Self {
+        self.profile = true;
+        self
+    }
+
+    /// Send progress events to the given channel while syncing
+    #[must_use]
+    pub fn with_events(mut self, events: mpsc::Sender<SyncEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    // Send an event to the subscriber, if any. A closed channel is not an error.
+    async fn emit(&self, event: SyncEvent) {
+        if let Some(events) = &self.events {
+            _ = events.send(event).await;
+        }
+    }
+
+    /// Stop at the next window boundary once the token is cancelled. Completed
+    /// windows are checkpointed so the run can be resumed.
+    #[must_use]
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Fetch accounts, pots and the transactions between the given dates and
+    /// persist them to the database. The run is recorded in the `sync_runs` table.
+    ///
+    /// # Errors
+    /// Will return errors if the data cannot be fetched or persisted.
+    pub async fn sync(
+        &self,
+        since: NaiveDateTime,
+        before: NaiveDateTime,
+    ) -> Result<SyncSummary, Error> {
+        let run_service = self.pool.sync_runs();
+        let run_id = run_service.start_run(since, before).await?;
+
+        self.execute(run_id, since, before, &HashSet::new()).await
+    }
+
+    /// Continue the most recent run if it failed or was interrupted, skipping
+    /// the windows it had already completed.
+    ///
+    /// # Errors
+    /// Will return errors if there is no run to resume, or the data cannot be
+    /// fetched or persisted.
+    pub async fn resume(&self) -> Result<SyncSummary, Error> {
+        let run_service = self.pool.sync_runs();
+        let run = match run_service.read_runs(1).await?.pop() {
+            Some(run) if run.error.is_some() || run.finished.is_none() => run,
+            _ => {
+                return Err(Error::Error(
+                    "There is no interrupted update to resume".into(),
+                ))
+            }
+        };
+        let completed = run_service.completed_windows(run.id).await?;
+        info!(
+            "Resuming run {} with {} completed windows",
+            run.id,
+            completed.len()
+        );
+
+        self.execute(run.id, run.range_start, run.range_end, &completed)
+            .await
+    }
+
+    // Run the sync, recording the outcome against the given run
+    async fn execute(
+        &self,
+        run_id: i64,
+        since: NaiveDateTime,
+        before: NaiveDateTime,
+        completed: &HashSet<(String, NaiveDateTime)>,
+    ) -> Result<SyncSummary, Error> {
+        let run_service = self.pool.sync_runs();
+        // counts of the windows committed so far, should the run fail
+        let mut stats = SyncStats::default();
+
+        match self.run(run_id, since, before, completed, &mut stats).await {
+            Ok(mut summary) => {
+                let error = summary.interrupted.then(|| INTERRUPTED.to_string());
+                run_service
+                    .finish_run(run_id, summary.stats.into(), error)
+                    .await?;
+                if let Some(profile) = &summary.profile {
+                    run_service.save_profile(run_id, profile).await?;
+                }
+                summary.run_id = run_id;
+                Ok(summary)
+            }
+            Err(e) => {
+                // the sync error matters more than the bookkeeping
+                if let Err(finish_error) = run_service
+                    .finish_run(run_id, stats.into(), Some(e.to_string()))
+                    .await
+                {
+                    error!("Recording the failed run {run_id}: {finish_error}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    async fn run(
+        &self,
+        run_id: i64,
+        since: NaiveDateTime,
+        before: NaiveDateTime,
+        completed: &HashSet<(String, NaiveDateTime)>,
+        stats: &mut SyncStats,
+    ) -> Result<SyncSummary, Error> {
+        const DAYS: i64 = 30;
+
+        let mut profile = SyncProfile::default();
+        let mut clock = Instant::now();
+
+        let (accounts, account_names) = self.get_accounts().await?;
+        stats.accounts = accounts.len();
+        profile.fetch += lap(&mut clock);
+        self.persist_accounts(&accounts).await?;
+        profile.store += lap(&mut clock);
+
+        let (pots, pot_names) = self.get_pots(&accounts).await?;
+        profile.fetch += lap(&mut clock);
+        self.persist_pots(&pots).await?;
+        profile.store += lap(&mut clock);
+        let balances = fetch_balances(&self.monzo, &accounts).await?;
+        profile.fetch += lap(&mut clock);
+        self.persist_balances(&balances).await?;
+
+        let custom_categories = Categories::from_config()?.custom_categories;
+        let run_service = self.pool.sync_runs();
+        let sync_points = if self.incremental {
+            self.pool.transactions().read_sync_points().await?
+        } else {
+            Vec::new()
+        };
+
+        let mut transactions: Vec<TransactionResponse> = Vec::new();
+        let mut interrupted = false;
+        let mut account_stats: BTreeMap<String, SyncStats> = BTreeMap::new();
+        profile.store += lap(&mut clock);
+
+        'accounts: for account in &accounts {
+            self.emit(SyncEvent::AccountStarted {
+                account_id: account.id.clone(),
+            })
+            .await;
+
+            let account_since = account_start(since, &account.id, &sync_points);
+            for (window_start, window_end) in date_ranges(account_since, before, DAYS) {
+                if completed.contains(&(account.id.clone(), window_start)) {
+                    continue;
+                }
+                if self.cancel.is_cancelled() {
+                    interrupted = true;
+                    break 'accounts;
+                }
+
+                let window = self
+                    .get_transactions(&account.id, window_start, window_end)
+                    .await?;
+                profile.fetch += lap(&mut clock);
+                self.persist_categories(&window, custom_categories.as_ref())
+                    .await?;
+                let (card_events, window): (Vec<_>, Vec<_>) =
+                    window.into_iter().partition(|tx| tx.amount == 0);
+                let window_events = self.persist_card_events(&card_events).await?;
+                profile.store += lap(&mut clock);
+                let (window_inserted, window_updated) =
+                    self.persist_transactions(&window, &mut profile).await?;
+                clock = Instant::now();
+                stats.fetched += window.len();
+                stats.inserted += window_inserted;
+                stats.updated += window_updated;
+                stats.skipped += window.len() - window_inserted - window_updated;
+                stats.events += window_events;
+                self.persist_rates(&window).await?;
+                let account_stat = account_stats.entry(account.id.clone()).or_default();
+                account_stat.accounts = 1;
+                account_stat.fetched += window.len();
+                account_stat.inserted += window_inserted;
+                account_stat.updated += window_updated;
+                account_stat.skipped += window.len() - window_inserted - window_updated;
+                account_stat.events += window_events;
+                run_service
+                    .checkpoint(run_id, &account.id, window_start, window_end)
+                    .await?;
+
+                profile.store += lap(&mut clock);
+
+                transactions.extend(window);
+            }
+        }
+        clock = Instant::now();
+
+        self.pool.classify_transfers(&self.transfer_rules).await?;
+        // an unreachable categoriser shouldn't stop the sync
+        if let Some(categoriser) = &self.categoriser {
+            if let Err(e) = categoriser.run(&self.pool).await {
+                warn!("Categorising transactions failed: {e}");
+            }
+        }
+        self.pool
+            .categories()
+            .apply_display(&self.category_display)
+            .await?;
+        profile.classify += lap(&mut clock);
+
+        // sort by date
+        transactions.sort_by_key(|tx| tx.created);
+
+        self.emit(SyncEvent::SyncCompleted { summary: *stats })
+            .await;
+
+        Ok(SyncSummary {
+            run_id,
+            interrupted,
+            stats: *stats,
+            account_stats,
+            since,
+            before,
+            balances,
+            accounts,
+            account_names,
+            pot_names,
+            transactions,
+            profile: self.profile.then_some(profile),
+        })
+    }
+
+    // Get all accounts
+    #[tracing::instrument(name = "get accounts", skip(self))]
+    async fn get_accounts(&self) -> Result<(Vec<AccountForDB>, HashMap<String, String>), Error> {
+        let accounts = self.monzo.accounts().await?;
+        // convert account response to account for db
+        let accounts: Vec<AccountForDB> = accounts.into_iter().map(Into::into).collect();
+        let account_names = self.monzo.account_description_from_id().await?;
+
+        Ok((accounts, account_names))
+    }
+
+    // Get all pots
+    #[tracing::instrument(name = "get pots", skip(self, accounts))]
+    async fn get_pots(
+        &self,
+        accounts: &Vec<AccountForDB>,
+    ) -> Result<(Vec<Pot>, HashMap<String, String>), Error> {
+        let pot_names = self.monzo.pot_description_from_id().await?;
+
+        let mut pots: Vec<Pot> = Vec::new();
+        for account in accounts {
+            let account_pots = self.monzo.pots(&account.id).await?;
+            for pot_resp in account_pots {
+                pots.push(Pot::from((pot_resp, account.owner_type.clone())));
+            }
+        }
+
+        Ok((pots, pot_names))
+    }
+
+    // Get the transactions of an account in one date window
+    #[tracing::instrument(name = "get transactions", skip(self))]
+    async fn get_transactions(
+        &self,
+        account_id: &str,
+        since: NaiveDateTime,
+        before: NaiveDateTime,
+    ) -> Result<Vec<TransactionResponse>, Error> {
+        let transactions = self
+            .monzo
+            .transactions(account_id, &since, &before, None)
+            .await?;
+
+        info!("Fetched {} transactions", &transactions.len());
+        self.emit(SyncEvent::WindowFetched {
+            account_id: account_id.to_string(),
+            since,
+            before,
+            count: transactions.len(),
+        })
+        .await;
+
+        Ok(transactions)
+    }
+
+    async fn persist_accounts(&self, accounts: &Vec<AccountForDB>) -> Result<(), Error> {
+        let account_service = self.pool.accounts();
+        for account in accounts {
+            match account_service.save_account(account).await {
+                Ok(()) => info!("Added account: {}", account.id),
+                Err(Error::Duplicate(_)) => (),
+                Err(e) => {
+                    error!("Adding account: {}", account.id);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn persist_pots(&self, pots: &Vec<Pot>) -> Result<(), Error> {
+        let pot_service = self.pool.pots();
+        for pot in pots {
+            match pot_service.save_pot(pot).await {
+                Ok(()) => info!("Added pot: {}", pot.id),
+                Err(Error::Duplicate(_)) => (),
+                Err(e) => {
+                    error!("Adding pot: {}", pot.id);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Record the current balances so `balances` can show them offline
+    async fn persist_balances(&self, snapshot: &[AccountSnapshot]) -> Result<(), Error> {
+        self.pool
+            .balances()
+            .save_snapshot(chrono::Utc::now().naive_utc(), snapshot)
+            .await?;
+
+        Ok(())
+    }
+
+    // Record the exchange rates applied to foreign currency transactions
+    async fn persist_rates(&self, transactions: &[TransactionResponse]) -> Result<(), Error> {
+        let rate_service = self.pool.fx_rates();
+        for rate in transactions.iter().filter_map(FxRate::implied) {
+            rate_service.save_rate(&rate).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn persist_categories(
+        &self,
+        transactions: &[TransactionResponse],
+        custom_categories: Option<&HashMap<String, String>>,
+    ) -> Result<(), Error> {
+        let category_service = self.pool.categories();
+
+        for tx_resp in transactions {
+            let category_id = tx_resp.category_id.clone();
+            let category_name = get_category_name(custom_categories, &category_id);
+            let category = Category {
+                id: category_id,
+                name: category_name,
+                ..Default::default()
+            };
+            match category_service.save_category(&category).await {
+                Ok(()) | Err(Error::Duplicate(_)) => (),
+                Err(e) => return Err(Error::DbError(e.to_string())),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the number of card events inserted
+    async fn persist_card_events(&self, events: &[TransactionResponse]) -> Result<usize, Error> {
+        let event_service = self.pool.card_events();
+        let mut inserted = 0;
+
+        for event in events {
+            match event_service.save_card_event(event).await {
+                Ok(()) => inserted += 1,
+                Err(Error::Duplicate(_)) => (),
+                Err(e) => {
+                    error!("Adding card event: {}", event.id);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    // Insert new transactions and update changed stored ones, adding the time
+    // taken to the profile
+    // Returns the number of transactions inserted and updated
+    async fn persist_transactions(
+        &self,
+        transactions: &[TransactionResponse],
+        profile: &mut SyncProfile,
+    ) -> Result<(usize, usize), Error> {
+        let tx_service = self.pool.transactions();
+        let attachment_service = self.pool.attachments();
+        let mut inserted = 0;
+        let mut updated = 0;
+
+        for tx_resp in transactions {
+            let mut clock = Instant::now();
+            let upsert = tx_service
+                .save_or_update_transaction(tx_resp, self.refresh)
+                .await;
+            match upsert {
+                Ok(Upsert::Inserted) => profile.insert += lap(&mut clock),
+                _ => profile.dedupe += lap(&mut clock),
+            }
+            match upsert {
+                Ok(Upsert::Inserted) => {
+                    info!("Added transaction: {}", tx_resp.id);
+                    inserted += 1;
+                    self.emit(SyncEvent::TransactionUpserted {
+                        transaction_id: tx_resp.id.clone(),
+                    })
+                    .await;
+                }
+                Ok(Upsert::Updated) => {
+                    updated += 1;
+                    self.emit(SyncEvent::TransactionUpserted {
+                        transaction_id: tx_resp.id.clone(),
+                    })
+                    .await;
+                }
+                Ok(Upsert::Unchanged) => (),
+                Err(e) => {
+                    error!("Adding transaction: {}", tx_resp.id);
+                    return Err(e);
+                }
+            }
+            // attachments can be added after the transaction was first synced
+            if let Some(attachments) = &tx_resp.attachments {
+                attachment_service
+                    .save_attachments(&tx_resp.id, attachments)
+                    .await?;
+            }
+            profile.store += lap(&mut clock);
+        }
+
+        Ok((inserted, updated))
+    }
+}
+
+// The time since `clock`, restarting it
+fn lap(clock: &mut Instant) -> Duration {
+    let elapsed = clock.elapsed();
+    *clock = Instant::now();
+    elapsed
+}
+
+// Where to start fetching an account's transactions: `since`, or later in an
+// incremental update if the account's transactions are already stored
+fn account_start(
+    since: NaiveDateTime,
+    account_id: &str,
+    sync_points: &[SyncPoint],
+) -> NaiveDateTime {
+    sync_points
+        .iter()
+        .find(|point| point.account_id == account_id)
+        .map_or(since, |point| point.start().max(since))
+}
+
+// Map a category name from the cateogy_id in the transaction that Monzo uses for custom categories
+fn get_category_name(opt_map: Option<&HashMap<String, String>>, key: &str) -> String {
+    opt_map
+        .and_then(|map| map.get(&key.to_lowercase()).cloned())
+        .unwrap_or(key.to_string())
+}
+
+/// The custom category names in `categories.yaml`, keyed by lowercase id
+///
+/// # Errors
+/// Will return an error if `categories.yaml` can't be read.
+pub fn custom_categories() -> Result<HashMap<String, String>, Error> {
+    Ok(Categories::from_config()?
+        .custom_categories
+        .unwrap_or_default())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(from = "CategoriesFile")]
+struct Categories {
+    custom_categories: Option<HashMap<String, String>>,
+}
+
+// `categories.yaml` maps ids to names at the top level, as in the README, or
+// under `custom_categories`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CategoriesFile {
+    Flat(HashMap<String, String>),
+    Nested {
+        custom_categories: Option<HashMap<String, String>>,
+    },
+}
+
+impl From<CategoriesFile> for Categories {
+    fn from(file: CategoriesFile) -> Self {
+        let custom_categories = match file {
+            CategoriesFile::Flat(names) => Some(names),
+            CategoriesFile::Nested { custom_categories } => custom_categories,
+        };
+        Self { custom_categories }
+    }
+}
+
+impl Categories {
+    pub fn from_config() -> Result<Self, Error> {
+        let cfg = config::Config::builder()
+            .add_source(config::File::new(
+                "categories.yaml",
+                config::FileFormat::Yaml,
+            ))
+            .build()?;
+
+        match cfg.try_deserialize::<Categories>() {
+            Ok(custom_categories) => Ok(custom_categories),
+            Err(e) => {
+                println!("{e}");
+                Err(Error::ConfigurationError(e))
+            }
+        }
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{
+        mock::{MockMonzo, ACCOUNT_ID},
+        test::test_db,
+    };
+    use wiremock::{
+        matchers::{method, path},
+        Mock, ResponseTemplate,
+    };
+
+    fn date(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[tokio::test]
+    async fn sync_persists_non_zero_transactions() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        let engine = SyncEngine::new(pool.clone(), mock.client());
+
+        // Act
+        let summary = engine
+            .sync(date("2024-04-01 00:00:00"), date("2024-04-20 00:00:00"))
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(summary.stats.accounts, 1);
+        assert_eq!(summary.stats.inserted, 3);
+        let txs = pool.transactions().read_transactions().await.unwrap();
+        // two seeded transactions plus the non-zero fixtures, including the pending one
+        assert_eq!(txs.len(), 5);
+        let pending = txs
+            .iter()
+            .find(|tx| tx.id == "tx_00008zL2INM3xZ41THuRF4")
+            .unwrap();
+        assert!(pending.settled.is_none());
+        // the zero-amount card check is kept apart from the transactions
+        assert_eq!(summary.stats.events, 1);
+        assert_eq!(summary.account_stats[ACCOUNT_ID], summary.stats);
+        assert_eq!(summary.balances.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sync_skips_existing_transactions() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        let engine = SyncEngine::new(pool, mock.client());
+        let (since, before) = (date("2024-04-01 00:00:00"), date("2024-04-20 00:00:00"));
+        engine.sync(since, before).await.unwrap();
+
+        // Act
+        let summary = engine.sync(since, before).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.stats.inserted, 0);
+        assert_eq!(summary.stats.updated, 0);
+        assert_eq!(summary.stats.skipped, 3);
+        assert_eq!(summary.stats.events, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_runs_record_the_windows_already_committed() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        // the first window is served, the second fails
+        Mock::given(method("GET"))
+            .and(path("/transactions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                include_str!("../tests/fixtures/transactions.json"),
+                "application/json",
+            ))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(mock.server())
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/transactions"))
+            .respond_with(ResponseTemplate::new(500))
+            .with_priority(2)
+            .mount(mock.server())
+            .await;
+        let engine = SyncEngine::new(pool.clone(), mock.client());
+
+        // Act
+        let result = engine
+            .sync(date("2024-04-01 00:00:00"), date("2024-06-01 00:00:00"))
+            .await;
+
+        // Assert
+        assert!(result.is_err());
+        let run = pool.sync_runs().read_runs(1).await.unwrap().remove(0);
+        assert!(run.error.is_some());
+        assert_eq!(run.accounts, 1);
+        assert_eq!(run.inserted, 3);
+    }
+
+    #[tokio::test]
+    async fn incremental_sync_starts_from_the_oldest_pending_transaction() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        let (since, before) = (date("2024-04-01 00:00:00"), date("2024-06-01 00:00:00"));
+        SyncEngine::new(pool.clone(), mock.client())
+            .sync(since, before)
+            .await
+            .unwrap();
+        let (events_tx, mut events_rx) = mpsc::channel(64);
+        let engine = SyncEngine::new(pool, mock.client())
+            .incremental()
+            .with_events(events_tx);
+
+        // Act
+        engine.sync(since, before).await.unwrap();
+        drop(engine);
+
+        // Assert
+        let mut starts = Vec::new();
+        while let Some(event) = events_rx.recv().await {
+            if let SyncEvent::WindowFetched { since, .. } = event {
+                starts.push(since);
+            }
+        }
+        // the pending transaction of 1 May is older than the newest, of 2 May
+        assert_eq!(starts.first(), Some(&date("2024-05-01 12:00:00")));
+    }
+
+    #[tokio::test]
+    async fn profiled_runs_record_their_phase_timings() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mock = MockMonzo::start().await;
+        let (since, before) = (date("2024-04-01 00:00:00"), date("2024-06-01 00:00:00"));
+        SyncEngine::new(pool.clone(), mock.client())
+            .sync(since, before)
+            .await
+            .unwrap();
+
+        // Act
+        let profiled = SyncEngine::new(pool.clone(), mock.client())
+            .profile()
+            .sync(since, before)
+            .await
+            .unwrap();
+
+        // Assert
+        let profile = profiled.profile.unwrap();
+        assert!(profile.fetch > Duration::ZERO);
+        // every transaction was already stored
+        assert!(profile.dedupe > Duration::ZERO);
+        assert_eq!(profile.insert, Duration::ZERO);
+        let runs = pool.sync_runs().read_runs(2).await.unwrap();
+        assert!(runs[0].profile().is_some());
+        assert!(runs[1].profile().is_none());
+    }
+}
@@ -0,0 +1,69 @@
+//! Safe to spend today
+//!
+//! What's been spent so far today, what the monthly budgets leave for the rest
+//! of it, and the bills due in the next few days. Small enough to show in a
+//! shell prompt or status bar. Transfers and money coming in don't count as
+//! spending.
+
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::{
+    engine::{
+        budget::{BudgetPlan, DailyAllowance},
+        projection::{upcoming_bills, Bill},
+    },
+    error::AppErrors as Error,
+    model::DatabasePool,
+    timezone::start_of_day,
+};
+
+/// Spending and allowance for one day, in minor units
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Today {
+    pub date: NaiveDate,
+    pub currency: String,
+    /// Spending in `currency` so far today, as a positive number
+    pub spent: i64,
+    /// What the budgets leave for today, if any are configured
+    pub allowance: Option<DailyAllowance>,
+    /// Bills due over the next few days, soonest first
+    pub bills: Vec<Bill>,
+}
+
+impl Today {
+    /// Summarise `date`, with the bills due in the `bill_days` after it
+    ///
+    /// # Errors
+    /// Will return errors if the database cannot be read.
+    pub async fn build(
+        pool: DatabasePool,
+        plan: &BudgetPlan,
+        date: NaiveDate,
+        bill_days: u64,
+        timezone: Tz,
+    ) -> Result<Self, Error> {
+        let allowance = plan.daily_allowance(date).await?;
+        let currency = plan.currency().to_string();
+        let until = (start_of_day(date + Duration::days(1), timezone) - Duration::nanoseconds(1))
+            .min(Utc::now().naive_utc());
+        let spent = pool
+            .transactions()
+            .read_report_data(start_of_day(date, timezone), until)
+            .await?
+            .iter()
+            .filter(|tx| tx.amount < 0 && !tx.is_transfer && tx.currency == currency)
+            .map(|tx| -tx.amount)
+            .sum();
+        let bills = upcoming_bills(pool, date, bill_days, timezone).await?;
+
+        Ok(Self {
+            date,
+            currency,
+            spent,
+            allowance,
+            bills,
+        })
+    }
+}
@@ -0,0 +1,190 @@
+//! Trips abroad
+//!
+//! A trip is a run of transactions in a currency other than the account's,
+//! with no more than a few days between one and the next. Spending in the
+//! account's own currency during a trip, such as a subscription, doesn't end
+//! it. Transfers aren't counted.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{transaction::ExportTransaction, DatabasePool},
+    timezone::local_date,
+};
+
+/// Spending in one category and local currency during a trip, as positive
+/// numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TripSpend {
+    pub category_label: String,
+    pub local_currency: String,
+    pub local_amount: i64,
+    /// In the account's currency
+    pub currency: String,
+    pub amount: i64,
+}
+
+/// A run of foreign currency transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trip {
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+    /// The local currencies spent in
+    pub currencies: BTreeSet<String>,
+    pub transaction_ids: Vec<String>,
+    /// By category, then local currency
+    pub spending: Vec<TripSpend>,
+}
+
+impl Trip {
+    /// The tag given to the trip's transactions, named after its first day
+    #[must_use]
+    pub fn tag(&self) -> String {
+        format!("trip-{}", self.first)
+    }
+
+    /// Total spend in the account's currency, by currency
+    #[must_use]
+    pub fn totals(&self) -> BTreeMap<&str, i64> {
+        let mut totals = BTreeMap::new();
+        for spend in &self.spending {
+            *totals.entry(spend.currency.as_str()).or_default() += spend.amount;
+        }
+        totals
+    }
+}
+
+/// Find the trips between `since` and `until`, splitting them where more than
+/// `max_gap_days` pass without a foreign currency transaction
+///
+/// # Errors
+/// Will return errors if the database cannot be read.
+pub async fn find_trips(
+    pool: DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    max_gap_days: i64,
+    timezone: Tz,
+) -> Result<Vec<Trip>, Error> {
+    let transactions = pool.transactions().read_report_data(since, until).await?;
+
+    Ok(group_trips(&transactions, max_gap_days, timezone))
+}
+
+// Group the foreign transactions, which are read oldest first, into trips
+fn group_trips(transactions: &[ExportTransaction], max_gap_days: i64, timezone: Tz) -> Vec<Trip> {
+    let mut runs: Vec<Vec<&ExportTransaction>> = Vec::new();
+    let mut last_date: Option<NaiveDate> = None;
+
+    for tx in transactions
+        .iter()
+        .filter(|tx| !tx.is_transfer && tx.local_currency != tx.currency)
+    {
+        let date = local_date(tx.created, timezone);
+        match (runs.last_mut(), last_date) {
+            (Some(run), Some(last)) if (date - last).num_days() <= max_gap_days => run.push(tx),
+            _ => runs.push(vec![tx]),
+        }
+        last_date = Some(date);
+    }
+
+    runs.into_iter().map(|run| trip(&run, timezone)).collect()
+}
+
+fn trip(run: &[&ExportTransaction], timezone: Tz) -> Trip {
+    let mut spending: BTreeMap<(String, String, String), (i64, i64)> = BTreeMap::new();
+    for tx in run {
+        let (local_amount, amount) = spending
+            .entry((
+                tx.category_label.clone(),
+                tx.local_currency.clone(),
+                tx.currency.clone(),
+            ))
+            .or_default();
+        *local_amount -= tx.local_amount;
+        *amount -= tx.amount;
+    }
+
+    Trip {
+        first: local_date(run[0].created, timezone),
+        last: local_date(run[run.len() - 1].created, timezone),
+        currencies: run.iter().map(|tx| tx.local_currency.clone()).collect(),
+        transaction_ids: run.iter().map(|tx| tx.id.clone()).collect(),
+        spending: spending
+            .into_iter()
+            .map(
+                |((category_label, local_currency, currency), (local_amount, amount))| TripSpend {
+                    category_label,
+                    local_currency,
+                    local_amount,
+                    currency,
+                    amount,
+                },
+            )
+            .collect(),
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(id: &str, day: &str, local_currency: &str, local_amount: i64) -> ExportTransaction {
+        ExportTransaction {
+            id: id.to_string(),
+            created: NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            amount: local_amount * 85 / 100,
+            currency: "GBP".to_string(),
+            local_amount,
+            local_currency: local_currency.to_string(),
+            category_label: "🍔 Eating out".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn gaps_between_foreign_spending_split_trips() {
+        // Arrange
+        let transactions = [
+            tx("1", "2024-05-01", "EUR", -2000),
+            tx("2", "2024-05-02", "GBP", -500),
+            tx("3", "2024-05-04", "EUR", -1000),
+            tx("4", "2024-05-05", "CHF", -3000),
+            tx("5", "2024-08-10", "USD", -4000),
+        ];
+
+        // Act
+        let trips = group_trips(&transactions, 3, Tz::UTC);
+
+        // Assert
+        assert_eq!(trips.len(), 2);
+        assert_eq!(trips[0].tag(), "trip-2024-05-01");
+        assert_eq!(trips[0].last.to_string(), "2024-05-05");
+        assert_eq!(trips[0].transaction_ids, vec!["1", "3", "4"]);
+        assert_eq!(
+            trips[0].currencies.iter().collect::<Vec<_>>(),
+            vec!["CHF", "EUR"]
+        );
+        assert_eq!(
+            trips[0].spending[1],
+            TripSpend {
+                category_label: "🍔 Eating out".to_string(),
+                local_currency: "EUR".to_string(),
+                local_amount: 3000,
+                currency: "GBP".to_string(),
+                amount: 2550,
+            }
+        );
+        assert_eq!(trips[0].totals().get("GBP"), Some(&5100));
+        assert_eq!(trips[1].tag(), "trip-2024-08-10");
+    }
+}
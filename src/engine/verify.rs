@@ -0,0 +1,210 @@
+//! Running balance verification
+//!
+//! Every `update` stores the balance Monzo reports for each account. Between
+//! two of these snapshots an account's balance should change by exactly the
+//! sum of the transactions stored for it in between, so starting from one
+//! snapshot and adding the transactions should reconstruct the next. Where it
+//! doesn't, transactions are missing or have the wrong amount, and syncing the
+//! window again should fill the gap.
+//!
+//! Each window is checked on its own, starting from the stored balance, so one
+//! gap doesn't make every later snapshot look wrong.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDateTime;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{balance::StoredBalance, transaction::PotTotal, DatabasePool},
+};
+
+/// A window in which an account's transactions don't add up to the change in
+/// its stored balance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceGap {
+    pub account_id: String,
+    /// When the balance the window starts from was stored
+    pub since: NaiveDateTime,
+    /// When the balance that doesn't match was stored
+    pub until: NaiveDateTime,
+    /// The balance stored at `until`, in minor units
+    pub stored: i64,
+    /// The balance at `since` plus the transactions in between
+    pub reconstructed: i64,
+    pub currency: String,
+}
+
+impl BalanceGap {
+    /// The total of the transactions missing from the window, e.g. -500 for
+    /// an unsynced £5 payment
+    #[must_use]
+    pub fn difference(&self) -> i64 {
+        self.stored - self.reconstructed
+    }
+}
+
+/// The result of checking stored balances against the transactions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// Accounts with at least two stored balances
+    pub accounts: usize,
+    /// Stored balances compared with a reconstructed one
+    pub compared: usize,
+    pub gaps: Vec<BalanceGap>,
+}
+
+impl Verification {
+    /// Reconstruct each stored balance from the one before and the
+    /// transactions in between
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read.
+    pub async fn run(pool: DatabasePool) -> Result<Self, Error> {
+        let balances = pool.balances().read_account_balances().await?;
+        let transactions = pool.transactions();
+
+        // one query per window covers every account
+        let taken: BTreeSet<NaiveDateTime> = balances.iter().map(|b| b.taken).collect();
+        let mut totals = BTreeMap::new();
+        for (since, until) in taken.iter().zip(taken.iter().skip(1)) {
+            let window = transactions.read_pot_totals(*since, *until).await?;
+            totals.insert((*since, *until), window);
+        }
+
+        Ok(verify(&balances, |since, until| {
+            totals.get(&(since, until)).map_or(&[][..], Vec::as_slice)
+        }))
+    }
+
+    /// True if every stored balance matches its transactions
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.gaps.is_empty()
+    }
+
+    /// When the earliest gap starts, the point to sync again from
+    #[must_use]
+    pub fn resync_from(&self) -> Option<NaiveDateTime> {
+        self.gaps.iter().map(|gap| gap.since).min()
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// Compare consecutive stored balances of each account, given the transaction
+// totals of each window
+fn verify<'a>(
+    balances: &[StoredBalance],
+    totals: impl Fn(NaiveDateTime, NaiveDateTime) -> &'a [PotTotal],
+) -> Verification {
+    let mut by_account: BTreeMap<&str, Vec<&StoredBalance>> = BTreeMap::new();
+    for balance in balances {
+        by_account
+            .entry(balance.account_id.as_str())
+            .or_default()
+            .push(balance);
+    }
+
+    let mut verification = Verification::default();
+    for (account_id, history) in by_account {
+        if history.len() < 2 {
+            continue;
+        }
+        verification.accounts += 1;
+        for pair in history.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            // money into a pot is a negative amount on its account, so every
+            // total counts
+            let change: i64 = totals(start.taken, end.taken)
+                .iter()
+                .filter(|t| t.account_id == account_id && t.currency == end.currency)
+                .map(|t| t.total)
+                .sum();
+            verification.compared += 1;
+            if start.balance + change != end.balance {
+                verification.gaps.push(BalanceGap {
+                    account_id: account_id.to_string(),
+                    since: start.taken,
+                    until: end.taken,
+                    stored: end.balance,
+                    reconstructed: start.balance + change,
+                    currency: end.currency.clone(),
+                });
+            }
+        }
+    }
+
+    verification
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::balance::{AccountSnapshot, Balance},
+        tests::test::test_db,
+    };
+
+    fn datetime(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[tokio::test]
+    async fn flags_windows_whose_transactions_dont_add_up() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET amount = -500, created = '2024-06-05 12:00:00' WHERE id = '1';
+             UPDATE transactions SET amount = 2000, created = '2024-06-15 12:00:00' WHERE id = '2'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let service = pool.balances();
+        for (taken, balance) in [
+            ("2024-06-01 08:00:00", 10_000),
+            ("2024-06-10 08:00:00", 9_500),
+            // £20 in, but a £5 payment was never synced
+            ("2024-06-20 08:00:00", 11_000),
+        ] {
+            let snapshot = AccountSnapshot {
+                account_id: "1".to_string(),
+                balance: Balance {
+                    balance,
+                    currency: "GBP".to_string(),
+                    ..Default::default()
+                },
+                pots: vec![],
+            };
+            service
+                .save_snapshot(datetime(taken), &[snapshot])
+                .await
+                .unwrap();
+        }
+
+        // Act
+        let verification = Verification::run(pool).await.unwrap();
+
+        // Assert
+        assert_eq!((verification.accounts, verification.compared), (1, 2));
+        assert_eq!(
+            verification.gaps,
+            vec![BalanceGap {
+                account_id: "1".to_string(),
+                since: datetime("2024-06-10 08:00:00"),
+                until: datetime("2024-06-20 08:00:00"),
+                stored: 11_000,
+                reconstructed: 11_500,
+                currency: "GBP".to_string(),
+            }]
+        );
+        assert_eq!(verification.gaps[0].difference(), -500);
+        assert_eq!(
+            verification.resync_from(),
+            Some(datetime("2024-06-10 08:00:00"))
+        );
+    }
+}
@@ -36,6 +36,9 @@ pub enum AppErrors {
     #[error("Server error")]
     ServerError,
 
+    #[error("Monzo can't be reached: {0}")]
+    Offline(String),
+
     #[error("Invalid header value {0}")]
     InvalidHeaderValue(#[from] reqwest::header::InvalidHeaderValue),
 
@@ -46,6 +49,9 @@ pub enum AppErrors {
     #[error("Failed to deserialise toml")]
     TomlError(#[from] toml::ser::Error),
 
+    #[error("Failed to (de)serialise json: {0}")]
+    JsonError(#[from] serde_json::Error),
+
     #[error("Configuration error")]
     ConfigurationError(#[from] config::ConfigError),
 
@@ -59,6 +65,11 @@ pub enum AppErrors {
     #[error("Database error")]
     DbError(String),
 
+    #[error(
+        "Another instance is using the database (lock file {0}). Try again when it has finished"
+    )]
+    Locked(String),
+
     #[error("Migration error")]
     MigrationError(#[from] sqlx::migrate::MigrateError),
 
@@ -66,16 +77,180 @@ pub enum AppErrors {
     #[error("Command aborted")]
     AbortError,
 
+    #[error("Update interrupted. Run `update --resume` to continue")]
+    Interrupted,
+
+    #[error("{0} balance(s) below their alert threshold")]
+    LowBalance(usize),
+
     #[error("Currency not found: {0}")]
     CurrencyNotFound(String),
 
+    #[cfg(feature = "cli")]
     #[error("Input error")]
     InputError(#[from] dialoguer::Error),
 }
 
+/// Broad classes of failure, each with a documented process exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    General,
+    Auth,
+    Network,
+    Database,
+    Config,
+    Busy,
+    Interrupted,
+    Alert,
+}
+
+impl ErrorCategory {
+    /// The process exit code for this category (see README "Exit codes")
+    #[must_use]
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorCategory::General => 1,
+            ErrorCategory::Auth => 3,
+            ErrorCategory::Network => 4,
+            ErrorCategory::Database => 5,
+            ErrorCategory::Config => 6,
+            ErrorCategory::Alert => 8,
+            ErrorCategory::Busy => 75,
+            ErrorCategory::Interrupted => 130,
+        }
+    }
+
+    /// A stable identifier for machine-readable output
+    #[must_use]
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCategory::General => "general",
+            ErrorCategory::Auth => "auth",
+            ErrorCategory::Network => "network",
+            ErrorCategory::Database => "database",
+            ErrorCategory::Config => "config",
+            ErrorCategory::Busy => "busy",
+            ErrorCategory::Interrupted => "interrupted",
+            ErrorCategory::Alert => "alert",
+        }
+    }
+
+    /// A suggestion for resolving errors of this category
+    #[must_use]
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            ErrorCategory::General => None,
+            ErrorCategory::Auth => Some("Run `monzo-cli auth` to reauthorise the application"),
+            ErrorCategory::Network => Some("Check the network connection and try again"),
+            ErrorCategory::Database => {
+                Some("Check `database_path` in configuration.toml or run `monzo-cli reset`")
+            }
+            ErrorCategory::Config => Some("Check configuration.toml in the current directory"),
+            ErrorCategory::Busy => Some("Wait for the other instance to finish"),
+            ErrorCategory::Interrupted => Some("Run `monzo-cli update --resume`"),
+            ErrorCategory::Alert => Some("Top up the account or change `[alerts.min_balance]`"),
+        }
+    }
+}
+
+impl AppErrors {
+    /// Classify the error so callers can react to the failure mode
+    #[must_use]
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            AppErrors::AccessTokenError(_)
+            | AppErrors::AuthCodeExchangeError
+            | AppErrors::AuthorisationFailure(_) => ErrorCategory::Auth,
+            AppErrors::HandlerError(_)
+            | AppErrors::ReqwestError(_)
+            | AppErrors::ServerError
+            | AppErrors::Offline(_)
+            | AppErrors::InvalidHeaderValue(_) => ErrorCategory::Network,
+            AppErrors::QueryError(_)
+            | AppErrors::Duplicate(_)
+            | AppErrors::DbError(_)
+            | AppErrors::MigrationError(_) => ErrorCategory::Database,
+            AppErrors::TomlError(_) | AppErrors::ConfigurationError(_) => ErrorCategory::Config,
+            AppErrors::Locked(_) => ErrorCategory::Busy,
+            AppErrors::Interrupted => ErrorCategory::Interrupted,
+            AppErrors::LowBalance(_) => ErrorCategory::Alert,
+            _ => ErrorCategory::General,
+        }
+    }
+
+    /// True if the network or the API couldn't be reached, so commands that
+    /// can fall back to stored data should
+    #[must_use]
+    pub fn is_offline(&self) -> bool {
+        matches!(self, AppErrors::Offline(_))
+    }
+
+    /// The process exit code for this error
+    #[must_use]
+    pub fn exit_code(&self) -> u8 {
+        self.category().exit_code()
+    }
+
+    /// The error as a JSON object of `code`, `message` and `hint`
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        let category = self.category();
+        serde_json::json!({
+            "code": category.code(),
+            "message": self.to_string(),
+            "hint": category.hint(),
+        })
+    }
+}
+
 // Implementing From<reqwest::Error> for MyError
 impl From<reqwest::Error> for AppErrors {
     fn from(error: reqwest::Error) -> Self {
-        AppErrors::ReqwestError(error.to_string())
+        if error.is_connect() || error.is_timeout() {
+            AppErrors::Offline(error.to_string())
+        } else {
+            AppErrors::ReqwestError(error.to_string())
+        }
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_distinct_per_category() {
+        assert_eq!(AppErrors::AccessTokenError(String::new()).exit_code(), 3);
+        assert_eq!(AppErrors::ServerError.exit_code(), 4);
+        assert_eq!(AppErrors::DbError(String::new()).exit_code(), 5);
+        assert_eq!(
+            AppErrors::ConfigurationError(config::ConfigError::Frozen).exit_code(),
+            6
+        );
+        assert_eq!(AppErrors::LowBalance(1).exit_code(), 8);
+        assert_eq!(AppErrors::AbortError.exit_code(), 1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_hosts_are_offline() {
+        // nothing listens on the discard port
+        let error = AppErrors::from(reqwest::get("http://127.0.0.1:9").await.unwrap_err());
+
+        assert!(error.is_offline());
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn json_has_code_message_and_hint() {
+        let json = AppErrors::Locked("db.sqlite.lock".to_string()).to_json();
+
+        assert_eq!(json["code"], "busy");
+        assert!(json["message"].as_str().unwrap().contains("db.sqlite.lock"));
+        assert!(json["hint"].is_string());
+
+        let json = AppErrors::Error("x".to_string()).to_json();
+        assert!(json["hint"].is_null());
     }
 }
@@ -0,0 +1,132 @@
+//! Ledger annotations from `beancount.yaml`
+//!
+//! Context that isn't in the transactions, such as moving house or changing
+//! job, can be declared once in `beancount.yaml` instead of being added to
+//! each generated ledger by hand:
+//!
+//! ```yaml
+//! events:
+//!   - date: 2024-03-01
+//!     type: location
+//!     description: Manchester
+//! notes:
+//!   - date: 2024-05-01
+//!     account: Assets:Monzo:Personal
+//!     comment: Salary paid here from May
+//! institutions:
+//!   uk_business: Tide
+//!   Amex Gold: AmericanExpress
+//! include_accounts:
+//!   - Personal
+//! exclude_pots:
+//!   - Tax
+//! ```
+//!
+//! Beancount writes them as `event` and `note` directives; other formats
+//! ignore them. `institutions` replaces the institution component of ledger
+//! account names, and `include_accounts` and `exclude_pots` limit which
+//! accounts and pots the ledger covers.
+
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::Path};
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::error::AppErrors as Error;
+
+/// The file annotations are read from, in the current directory
+pub const ANNOTATIONS_FILE: &str = "beancount.yaml";
+
+/// A dated change in circumstances, e.g. `location` becoming `Manchester`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LedgerEvent {
+    pub date: NaiveDate,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub description: String,
+}
+
+/// A dated comment on a ledger account
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LedgerNote {
+    pub date: NaiveDate,
+    pub account: String,
+    pub comment: String,
+}
+
+/// Events, notes and institution names to write into a ledger
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Annotations {
+    #[serde(default)]
+    pub events: Vec<LedgerEvent>,
+    #[serde(default)]
+    pub notes: Vec<LedgerNote>,
+    /// Institution names keyed by Monzo account id, Monzo account type (e.g.
+    /// `uk_business`) or manual account name
+    #[serde(default)]
+    pub institutions: BTreeMap<String, String>,
+    /// Monzo accounts to export by id, type or nickname, or all if empty
+    #[serde(default)]
+    pub include_accounts: Vec<String>,
+    /// Pots whose transfers are left out, by name
+    #[serde(default)]
+    pub exclude_pots: Vec<String>,
+}
+
+impl Annotations {
+    /// Read annotations from `path`, or none if it doesn't exist
+    ///
+    /// # Errors
+    /// Will return an error if the file can't be read or isn't valid.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::from_yaml(&contents)
+                .map_err(|e| Error::Error(format!("Invalid {}: {e}", path.display()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parse annotations from YAML
+    ///
+    /// # Errors
+    /// Will return an error if the YAML isn't valid annotations.
+    pub fn from_yaml(contents: &str) -> Result<Self, serde_yaml::Error> {
+        // an empty file is no annotations
+        if contents.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(contents)
+    }
+
+    /// The institution of a Monzo account: an override for its id, then for
+    /// its type, then `Monzo`
+    #[must_use]
+    pub fn institution<'a>(&'a self, account_id: &str, owner_type: &str) -> &'a str {
+        self.institutions
+            .get(account_id)
+            .or_else(|| self.institutions.get(owner_type))
+            .map_or("Monzo", String::as_str)
+    }
+
+    /// Whether a Monzo account is exported, matching `include_accounts`
+    /// against its id, type and name
+    #[must_use]
+    pub fn includes_account(&self, account_id: &str, owner_type: &str, name: &str) -> bool {
+        self.include_accounts.is_empty()
+            || self
+                .include_accounts
+                .iter()
+                .any(|a| a == account_id || a == owner_type || a.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether transfers to and from a pot are exported
+    #[must_use]
+    pub fn includes_pot(&self, pot: &str) -> bool {
+        !self
+            .exclude_pots
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(pot))
+    }
+}
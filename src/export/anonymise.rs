@@ -0,0 +1,378 @@
+//! Anonymised database copy
+//!
+//! Copies the database for attaching to bug reports without leaking financial
+//! data. In the copy:
+//!
+//! - merchant names are replaced with a salted hash, so the same merchant still
+//!   groups together but can't be looked up, and their addresses, logos and
+//!   change log are removed,
+//! - transaction descriptions and notes are removed, except descriptions that
+//!   name a pot (these link pot transfers to their pot), as are card event
+//!   descriptions,
+//! - account numbers, sort codes and account descriptions are removed, and
+//!   manual accounts are renamed after their id,
+//! - people paid over Monzo are renamed and their account details removed,
+//! - webhook payloads kept as dead letters are removed,
+//! - amounts, pot balances, stored balance snapshots and manual valuations are
+//!   jittered by up to 10%, with a transaction's pending amount scaled by the
+//!   same factor as its settled one,
+//! - dedupe keys, which hash the real amount and payee, are recomputed from
+//!   the anonymised ones, and the ids of merged transactions and how imported
+//!   rows were resolved are removed.
+//!
+//! The salt is random and not stored, so hashes differ between copies.
+
+use std::{fmt::Write, path::Path};
+
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::{error::AppErrors as Error, model::DatabasePool};
+
+/// Largest relative change applied to an amount, in basis points
+const JITTER_BASIS_POINTS: i64 = 1000;
+
+/// Rows changed in the copy
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AnonymiseSummary {
+    pub accounts: u64,
+    pub merchants: u64,
+    pub transactions: u64,
+}
+
+/// Write an anonymised copy of the database to `destination`
+///
+/// # Errors
+/// Will return an error if `destination` exists or the copy can't be written.
+pub async fn anonymise(pool: &DatabasePool, destination: &Path) -> Result<AnonymiseSummary, Error> {
+    if destination.exists() {
+        return Err(Error::Error(format!(
+            "{} already exists",
+            destination.display()
+        )));
+    }
+    let Some(destination) = destination.to_str() else {
+        return Err(Error::Error("The output path must be valid UTF-8".into()));
+    };
+
+    sqlx::query("VACUUM INTO $1")
+        .bind(destination)
+        .execute(pool.sqlite("export anonymise")?)
+        .await?;
+
+    let copy = DatabasePool::new(destination, 1).await?;
+    let db = copy.db();
+    let salt = uuid::Uuid::new_v4().to_string();
+
+    let accounts = sqlx::query!(
+        r"
+            UPDATE accounts
+            SET account_number = '', sort_code = '', description = ''
+        "
+    )
+    .execute(db)
+    .await?
+    .rows_affected();
+
+    let merchants = anonymise_merchants(db, &salt).await?;
+
+    let transactions = sqlx::query!(
+        r"
+            UPDATE transactions
+            SET
+                description = CASE
+                    WHEN description IN (SELECT id FROM pots) THEN description
+                    ELSE ''
+                END,
+                notes = NULL,
+                counterparty_account_number = NULL,
+                counterparty_sort_code = NULL
+        "
+    )
+    .execute(db)
+    .await?
+    .rows_affected();
+
+    jitter_transactions(db).await?;
+    anonymise_balances(db).await?;
+    sqlx::query!("UPDATE manual_accounts SET name = 'manual_' || id, institution = NULL")
+        .execute(db)
+        .await?;
+
+    sqlx::query!(
+        r"
+            UPDATE counterparties
+            SET name = 'person_' || rowid, account_number = NULL, sort_code = NULL
+        "
+    )
+    .execute(db)
+    .await?;
+
+    sqlx::query!("UPDATE card_events SET description = ''")
+        .execute(db)
+        .await?;
+    sqlx::query!("DELETE FROM transaction_tags")
+        .execute(db)
+        .await?;
+    // attachment urls grant access to the files
+    sqlx::query!("DELETE FROM attachments").execute(db).await?;
+
+    // the keys hash the real amount and payee, which are few enough to guess
+    sqlx::query!("UPDATE transactions SET dedupe_key = NULL")
+        .execute(db)
+        .await?;
+    copy.set_dedupe_keys(None).await?;
+    sqlx::query!("DELETE FROM transaction_aliases")
+        .execute(db)
+        .await?;
+    // csv ids are the ids of rows in the user's own exports
+    sqlx::query!("DELETE FROM import_resolutions")
+        .execute(db)
+        .await?;
+
+    // raw webhook payloads, with every field of the transaction
+    sqlx::query!("DELETE FROM webhook_dead_letters")
+        .execute(db)
+        .await?;
+
+    // audit log errors can quote API responses
+    sqlx::query!("UPDATE sync_runs SET error = 'redacted' WHERE error IS NOT NULL")
+        .execute(db)
+        .await?;
+
+    db.close().await;
+
+    Ok(AnonymiseSummary {
+        accounts,
+        merchants,
+        transactions,
+    })
+}
+
+// Jitter transaction amounts by one factor per transaction
+async fn jitter_transactions(db: &SqlitePool) -> Result<(), Error> {
+    // one factor per row, materialised so every amount of a row shares it
+    sqlx::query(&format!(
+        r"
+            CREATE TEMP TABLE jitter AS
+            SELECT id, 1.0 + ((abs(random()) % {range}) - {max}) / 10000.0 AS factor
+            FROM transactions
+        ",
+        range = 2 * JITTER_BASIS_POINTS + 1,
+        max = JITTER_BASIS_POINTS
+    ))
+    .execute(db)
+    .await?;
+    sqlx::query(
+        r"
+            UPDATE transactions
+            SET
+                amount = CAST(ROUND(amount * (SELECT factor FROM jitter j WHERE j.id = transactions.id)) AS INTEGER),
+                local_amount = CAST(ROUND(local_amount * (SELECT factor FROM jitter j WHERE j.id = transactions.id)) AS INTEGER),
+                pending_amount = CAST(ROUND(pending_amount * (SELECT factor FROM jitter j WHERE j.id = transactions.id)) AS INTEGER)
+        ",
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+// Jitter every stored balance other than transaction amounts
+async fn anonymise_balances(db: &SqlitePool) -> Result<(), Error> {
+    for table in [
+        "pots",
+        "account_balances",
+        "pot_balances",
+        "manual_valuations",
+    ] {
+        sqlx::query(&format!(
+            r"
+                UPDATE {table}
+                SET balance = CAST(ROUND(balance * (1.0 + ((abs(random()) % {range}) - {max}) / 10000.0)) AS INTEGER)
+            ",
+            range = 2 * JITTER_BASIS_POINTS + 1,
+            max = JITTER_BASIS_POINTS
+        ))
+        .execute(db)
+        .await?;
+    }
+    sqlx::query!("UPDATE account_balances SET total_balance = balance, spend_today = 0")
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+// Hash merchant names and drop anything that would identify them again,
+// returning the number of merchants
+async fn anonymise_merchants(db: &SqlitePool, salt: &str) -> Result<u64, Error> {
+    let merchants = sqlx::query!("SELECT id, name FROM merchants")
+        .fetch_all(db)
+        .await?;
+    for merchant in &merchants {
+        let name = hash(salt, &merchant.name);
+        sqlx::query!(
+            r"
+                UPDATE merchants
+                SET name = $1, address = NULL, latitude = NULL, longitude = NULL, logo = NULL, logo_path = NULL
+                WHERE id = $2
+            ",
+            name,
+            merchant.id
+        )
+        .execute(db)
+        .await?;
+    }
+
+    // the change log holds the original names
+    sqlx::query!("DELETE FROM merchant_changes")
+        .execute(db)
+        .await?;
+
+    Ok(u64::try_from(merchants.len()).unwrap_or(u64::MAX))
+}
+
+fn hash(salt: &str, value: &str) -> String {
+    let digest = Sha256::digest(format!("{salt}{value}"));
+    let mut name = String::from("merchant_");
+    for byte in &digest[..5] {
+        let _ = write!(name, "{byte:02x}");
+    }
+    name
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{dedupe::dedupe_key, fixture::Fixture},
+        tests::test::test_db,
+    };
+
+    const FIXTURE: &str = r#"
+        transactions:
+          - id: tx_a
+            account_id: "1"
+            amount: -10000
+            currency: GBP
+            local_amount: -11700
+            local_currency: EUR
+            created: "2024-02-01T10:00:00Z"
+            description: CARREFOUR PARIS 12 RUE DE RIVOLI
+            notes: birthday present for Sam
+            category: "1"
+            merchant: { id: merch_1, name: Carrefour, category: shopping }
+          - id: tx_b
+            account_id: "1"
+            amount: -5000
+            currency: GBP
+            local_amount: -5000
+            local_currency: GBP
+            created: "2024-02-02T10:00:00Z"
+            description: "1"
+            category: "1"
+    "#;
+
+    #[tokio::test]
+    async fn copy_is_anonymised() {
+        // Arrange
+        let (pool, tmp) = test_db().await;
+        pool.load_fixture(&Fixture::from_yaml(FIXTURE).unwrap())
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+                INSERT INTO webhook_dead_letters (received, body, error)
+                VALUES ('2024-02-01 10:00:00', '{"description": "CARREFOUR"}', 'invalid');
+                INSERT INTO transaction_aliases (alias_id, transaction_id, source)
+                VALUES ('csv_carrefour', 'tx_a', 'csv');
+                INSERT INTO import_resolutions (csv_id, transaction_id, resolution, resolved)
+                VALUES ('csv_carrefour', 'tx_a', 'keep-api', '2024-02-03 10:00:00');
+                UPDATE transactions SET pending_amount = -8000 WHERE id = 'tx_a';
+                UPDATE merchants
+                SET logo = 'https://mondo-logo-cache.appspot.com/carrefour.png',
+                    logo_path = 'logos/merch_1.png'
+                WHERE id = 'merch_1';
+            "#,
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let destination = tmp.path().join("anonymised.db");
+
+        // Act
+        let summary = anonymise(&pool, &destination).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.merchants, 1);
+        let copy = DatabasePool::new(destination.to_str().unwrap(), 1)
+            .await
+            .unwrap();
+
+        let accounts = copy.accounts().read_accounts().await.unwrap();
+        assert_eq!(accounts[0].account_number, "");
+        assert_eq!(accounts[0].sort_code, "");
+
+        let merchant = copy
+            .merchants()
+            .get_merchant("merch_1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(merchant.name.starts_with("merchant_"));
+        assert_eq!(merchant.logo, None);
+        let logo_path: Option<String> =
+            sqlx::query_scalar("SELECT logo_path FROM merchants WHERE id = 'merch_1'")
+                .fetch_one(copy.db())
+                .await
+                .unwrap();
+        assert_eq!(logo_path, None);
+
+        let aliases: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transaction_aliases")
+            .fetch_one(copy.db())
+            .await
+            .unwrap();
+        assert_eq!(aliases, 0);
+        let resolutions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM import_resolutions")
+            .fetch_one(copy.db())
+            .await
+            .unwrap();
+        assert_eq!(resolutions, 0);
+        let dead_letters: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM webhook_dead_letters")
+            .fetch_one(copy.db())
+            .await
+            .unwrap();
+        assert_eq!(dead_letters, 0);
+
+        let service = copy.transactions();
+        let tx = service.read_transaction("tx_a").await.unwrap();
+        assert_eq!(tx.description, "");
+        assert_eq!(
+            tx.dedupe_key.as_deref(),
+            Some(dedupe_key("1", "GBP", tx.amount, &merchant.name).as_str())
+        );
+        assert_eq!(tx.notes, None);
+        assert!((-11000..=-9000).contains(&tx.amount));
+        // amount, local amount and pending amount are scaled by the same factor
+        assert!((tx.local_amount * 100 / tx.amount - 117).abs() <= 1);
+        let pending = tx.pending_amount.unwrap();
+        assert!((pending * 100 / tx.amount - 80).abs() <= 1);
+        let pot_transfer = service.read_transaction("tx_b").await.unwrap();
+        assert_eq!(pot_transfer.description, "1");
+
+        // the source is untouched
+        let original = pool.transactions().read_transaction("tx_a").await.unwrap();
+        assert_eq!(original.amount, -10000);
+        assert_ne!(original.dedupe_key, tx.dedupe_key);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_overwrite() {
+        let (pool, tmp) = test_db().await;
+
+        assert!(anonymise(&pool, tmp.path()).await.is_err());
+    }
+}
@@ -0,0 +1,1086 @@
+//! Beancount ledger
+//!
+//! Each Monzo account becomes `Assets:Monzo:<Name>`, after its nickname or
+//! else its type, with its pots as sub-accounts. Spending is posted to
+//! `Expenses:<Category>` and money coming in to `Income:<Category>`, except
+//! Monzo-to-Monzo payments, which go to `Assets:People:<Name>` so each
+//! person's balance shows what is owed between you. Manual
+//! accounts become `Assets|Liabilities:<Institution>:<Name>`. Institutions
+//! in `beancount.yaml` replace `Monzo` or a manual account's institution.
+//! `include_accounts` and `exclude_pots` there leave out other accounts'
+//! transactions and transfers to and from the excluded pots. Each
+//! valuation is written as a `pad` from `Equity:Valuations` followed by a
+//! `balance` assertion. Card repayments matched by a `[transfers.repayments]`
+//! rule are posted to the manual account they pay off.
+//!
+//! When the export starts after an account was opened, its balance and its
+//! pots' balances then, derived from the latest stored balances, are posted
+//! from `Equity:OpeningBalances` the day before and asserted on the first day,
+//! so a ledger of partial history balances from the start. The last balances
+//! stored in each month are asserted the next day, so `bean-check` finds any
+//! transactions missing from the sync. An account's assertion includes its
+//! pots, as beancount's does its sub-accounts.
+//!
+//! In a household ledger the person is added to each account, e.g.
+//! `Assets:Monzo:Alex:Personal` and `Expenses:Groceries:Alex`, so totals roll
+//! up per category across everyone.
+//!
+//! Events and account notes from `beancount.yaml` are written after the `open`
+//! directives.
+//!
+//! `open` directives need the first date an account is used, so entries are
+//! collected and written out in `finish`.
+//!
+//! The same entries can be written in hledger syntax instead, see
+//! [`hledger`](super::hledger).
+//!
+//! Exported to a directory, the ledger is written to `generated.beancount`
+//! and [`create_layout`] adds a `main.beancount` including it and a
+//! `manual.beancount` for hand-written entries. Both are created once and
+//! never overwritten.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Duration, NaiveDate};
+use chrono_tz::Tz;
+
+use super::{annotations::Annotations, hledger, Exporter, OpeningBalance};
+use crate::{
+    currency::decimal,
+    error::AppErrors as Error,
+    model::{
+        account::{display_name, AccountForDB},
+        manual::{ManualAccount, ManualAccountKind, ManualValuation},
+        transaction::ExportTransaction,
+    },
+    timezone::local_date,
+};
+
+/// Where valuation differences of manual accounts are balanced from
+const VALUATIONS: &str = "Equity:Valuations";
+/// Where the balances of accounts at the start of the ledger come from
+const OPENING_BALANCES: &str = "Equity:OpeningBalances";
+
+/// The regenerated ledger in a ledger directory
+pub const GENERATED: &str = "generated.beancount";
+/// The ledger including the generated and manual ledgers
+pub const MAIN: &str = "main.beancount";
+/// Entries written by hand
+pub const MANUAL: &str = "manual.beancount";
+
+/// The syntax a ledger is written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Beancount,
+    Hledger,
+}
+
+/// An entry of the ledger, before it is written in either syntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Entry {
+    /// A transaction whose last posting, without an amount, balances the
+    /// others
+    Transaction {
+        payee: Option<String>,
+        narration: String,
+        tags: Vec<String>,
+        /// e.g. the Monzo transaction id
+        metadata: Vec<(&'static str, String)>,
+        /// Accounts and amounts such as `-12.50 GBP`
+        postings: Vec<(String, Option<String>)>,
+    },
+    /// Bring `account` to `balance` from `from`, for the next day's
+    /// balance assertion
+    Pad {
+        account: String,
+        from: String,
+        balance: i64,
+        currency: String,
+    },
+    /// `account`'s balance, including its sub-accounts, at the start of the
+    /// day
+    Balance {
+        account: String,
+        balance: i64,
+        currency: String,
+    },
+}
+
+impl Syntax {
+    // An `open` directive
+    fn open(self, account: &str, date: NaiveDate) -> String {
+        match self {
+            Self::Beancount => format!("{date} open {account}"),
+            Self::Hledger => hledger::open(account, date),
+        }
+    }
+
+    // An `event` directive
+    fn event(self, date: NaiveDate, kind: &str, description: &str) -> String {
+        match self {
+            Self::Beancount => format!(
+                "{date} event \"{}\" \"{}\"",
+                escape(kind),
+                escape(description)
+            ),
+            Self::Hledger => hledger::event(date, kind, description),
+        }
+    }
+
+    // A `note` directive
+    fn note(self, date: NaiveDate, account: &str, comment: &str) -> String {
+        match self {
+            Self::Beancount => format!("{date} note {account} \"{}\"", escape(comment)),
+            Self::Hledger => hledger::note(date, account, comment),
+        }
+    }
+
+    // An entry, ending in a newline
+    fn entry(self, date: NaiveDate, entry: &Entry) -> String {
+        match self {
+            Self::Beancount => beancount_entry(date, entry),
+            Self::Hledger => hledger::entry(date, entry),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BeancountExporter {
+    syntax: Syntax,
+    timezone: Option<Tz>,
+    /// Account names by account id, used instead of the account type
+    nicknames: BTreeMap<String, String>,
+    /// Ledger account component of the person whose data is being received
+    owner: Option<String>,
+    /// Monzo account id -> ledger account
+    accounts: HashMap<String, String>,
+    /// Manual account id -> ledger account
+    manual_accounts: HashMap<i64, String>,
+    /// Ledger account -> date it is opened
+    opened: BTreeMap<String, NaiveDate>,
+    annotations: Annotations,
+    /// Smallest absolute amount in minor units of an entry
+    min_amount: i64,
+    entries: Vec<(NaiveDate, Entry)>,
+}
+
+impl BeancountExporter {
+    /// An exporter writing the ledger in hledger syntax
+    #[must_use]
+    pub fn hledger() -> Self {
+        Self {
+            syntax: Syntax::Hledger,
+            ..Self::default()
+        }
+    }
+
+    // The `:<Person>` component of the current owner, if any
+    fn person(&self) -> String {
+        self.owner
+            .as_ref()
+            .map(|owner| format!(":{owner}"))
+            .unwrap_or_default()
+    }
+
+    // `<Root>:<Institution>[:<Person>]:<Name>`, the ledger account of an
+    // account held at an institution
+    fn institution_account(&self, root: &str, institution: &str, name: &str) -> String {
+        format!(
+            "{root}:{}{}:{}",
+            component(institution),
+            self.person(),
+            component(name)
+        )
+    }
+
+    // The ledger account of an account's or pot's balance, unless it's left
+    // out
+    fn balance_account(&self, balance: &OpeningBalance) -> Option<String> {
+        let account = self.accounts.get(&balance.account_id)?;
+        match &balance.pot {
+            Some(pot) if !self.annotations.includes_pot(pot) => None,
+            Some(pot) => Some(format!("{account}:{}", component(pot))),
+            None => Some(account.clone()),
+        }
+    }
+
+    // A balance assertion on the ledger account of `balance`, one of
+    // `balances`. An account's includes its pots, as they're sub-accounts.
+    fn assertion(
+        &self,
+        name: String,
+        balance: &OpeningBalance,
+        balances: &[OpeningBalance],
+    ) -> Entry {
+        let pots: i64 = if balance.pot.is_none() {
+            balances
+                .iter()
+                .filter(|b| {
+                    b.account_id == balance.account_id
+                        && b.date == balance.date
+                        && b.currency == balance.currency
+                        && b.pot
+                            .as_deref()
+                            .is_some_and(|pot| self.annotations.includes_pot(pot))
+                })
+                .map(|b| b.balance)
+                .sum()
+        } else {
+            0
+        };
+        Entry::Balance {
+            account: name,
+            balance: balance.balance + pots,
+            currency: balance.currency.clone(),
+        }
+    }
+
+    // Open `account` on `date`, or earlier if it's already open
+    fn open(&mut self, account: &str, date: NaiveDate) {
+        self.opened
+            .entry(account.to_string())
+            .and_modify(|opened| *opened = (*opened).min(date))
+            .or_insert(date);
+    }
+}
+
+impl Exporter for BeancountExporter {
+    fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = Some(timezone);
+    }
+
+    fn timezone(&self) -> Tz {
+        self.timezone.unwrap_or(Tz::UTC)
+    }
+
+    fn set_nicknames(&mut self, nicknames: &BTreeMap<String, String>) {
+        self.nicknames.clone_from(nicknames);
+    }
+
+    fn set_annotations(&mut self, annotations: &Annotations) {
+        self.annotations.clone_from(annotations);
+    }
+
+    fn set_min_amount(&mut self, min_amount: i64) {
+        self.min_amount = min_amount;
+    }
+
+    fn set_owner(&mut self, owner: &str) {
+        self.owner = Some(component(owner));
+    }
+
+    fn accounts(&mut self, _out: &mut dyn Write, accounts: &[AccountForDB]) -> Result<(), Error> {
+        let timezone = self.timezone.unwrap_or(Tz::UTC);
+        for account in accounts {
+            let nickname = account.name(&self.nicknames);
+            if !self
+                .annotations
+                .includes_account(&account.id, &account.owner_type, nickname)
+            {
+                continue;
+            }
+            let name = self.institution_account(
+                "Assets",
+                self.annotations
+                    .institution(&account.id, &account.owner_type),
+                nickname,
+            );
+            self.open(&name, local_date(account.created, timezone));
+            self.accounts.insert(account.id.clone(), name);
+        }
+        Ok(())
+    }
+
+    fn opening_balances(
+        &mut self,
+        _out: &mut dyn Write,
+        balances: &[OpeningBalance],
+    ) -> Result<(), Error> {
+        let timezone = self.timezone();
+        for opening in balances {
+            let Some(name) = self.balance_account(opening) else {
+                continue;
+            };
+            // the balance assertion is checked at the start of the first day,
+            // after the opening transaction the day before
+            let date = local_date(opening.date, timezone);
+            let opened = date - Duration::days(1);
+            self.open(&name, opened);
+            if opening.balance != 0 {
+                self.open(OPENING_BALANCES, opened);
+                self.entries.push((
+                    opened,
+                    Entry::Transaction {
+                        payee: None,
+                        narration: "Opening balance".to_string(),
+                        tags: vec![],
+                        metadata: vec![],
+                        postings: vec![
+                            (
+                                name.clone(),
+                                Some(amount(opening.balance, &opening.currency)),
+                            ),
+                            (OPENING_BALANCES.to_string(), None),
+                        ],
+                    },
+                ));
+            }
+            let assertion = self.assertion(name, opening, balances);
+            self.entries.push((date, assertion));
+        }
+        Ok(())
+    }
+
+    fn balance_assertions(
+        &mut self,
+        _out: &mut dyn Write,
+        balances: &[OpeningBalance],
+    ) -> Result<(), Error> {
+        let timezone = self.timezone();
+        for balance in balances {
+            let Some(name) = self.balance_account(balance) else {
+                continue;
+            };
+            let date = local_date(balance.date, timezone);
+            self.open(&name, date);
+            let assertion = self.assertion(name, balance, balances);
+            self.entries.push((date, assertion));
+        }
+        Ok(())
+    }
+
+    fn manual_accounts(
+        &mut self,
+        _out: &mut dyn Write,
+        accounts: &[ManualAccount],
+        valuations: &[ManualValuation],
+    ) -> Result<(), Error> {
+        let timezone = self.timezone.unwrap_or(Tz::UTC);
+        for account in accounts {
+            let root = match account.kind {
+                ManualAccountKind::Asset => "Assets",
+                ManualAccountKind::Liability => "Liabilities",
+            };
+            let institution = self
+                .annotations
+                .institutions
+                .get(&account.name)
+                .or(account.institution.as_ref())
+                .map_or("Manual", String::as_str);
+            let name = self.institution_account(root, institution, &account.name);
+            self.open(&name, local_date(account.created, timezone));
+            self.manual_accounts.insert(account.id, name.clone());
+
+            for valuation in valuations.iter().filter(|v| v.account_id == account.id) {
+                // balance assertions apply at the start of the day, so pad the day before
+                let pad_date = valuation.date - Duration::days(1);
+                self.open(&name, pad_date);
+                self.open(VALUATIONS, pad_date);
+                self.entries.push((
+                    pad_date,
+                    Entry::Pad {
+                        account: name.clone(),
+                        from: VALUATIONS.to_string(),
+                        balance: valuation.balance,
+                        currency: account.currency.clone(),
+                    },
+                ));
+                self.entries.push((
+                    valuation.date,
+                    Entry::Balance {
+                        account: name.clone(),
+                        balance: valuation.balance,
+                        currency: account.currency.clone(),
+                    },
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, _out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+        let nickname = display_name(&self.nicknames, &tx.account_id, &tx.account_name);
+        if !self
+            .annotations
+            .includes_account(&tx.account_id, &tx.account_name, nickname)
+            || tx
+                .pot_name
+                .as_deref()
+                .is_some_and(|pot| !self.annotations.includes_pot(pot))
+            || tx.amount.abs() < self.min_amount
+        {
+            return Ok(());
+        }
+        let date = local_date(tx.created, self.timezone.unwrap_or(Tz::UTC));
+        let person = self.person();
+        let account = self
+            .accounts
+            .get(&tx.account_id)
+            .cloned()
+            .unwrap_or_else(|| {
+                self.institution_account(
+                    "Assets",
+                    self.annotations
+                        .institution(&tx.account_id, &tx.account_name),
+                    nickname,
+                )
+            });
+        let repaid = tx
+            .repayment_account_id
+            .and_then(|id| self.manual_accounts.get(&id));
+        let counter = match (repaid, &tx.pot_name, tx.amount < 0) {
+            (Some(card), _, _) => card.clone(),
+            (None, Some(pot), _) => format!("{account}:{}", component(pot)),
+            (None, None, _) if tx.counterparty_name.is_some() && !tx.is_transfer => format!(
+                "Assets:People:{}{person}",
+                component(tx.counterparty_name.as_deref().unwrap_or_default())
+            ),
+            (None, None, true) => format!("Expenses:{}{person}", component(&tx.category_name)),
+            (None, None, false) => format!("Income:{}{person}", component(&tx.category_name)),
+        };
+        self.open(&account, date);
+        self.open(&counter, date);
+
+        let payee = tx
+            .merchant_name
+            .as_deref()
+            .or(tx.pot_name.as_deref())
+            .or(tx.counterparty_name.as_deref())
+            .unwrap_or(&tx.description);
+        let mut metadata = vec![("id", tx.id.clone())];
+        if let Some(notes) = tx.notes.as_deref().filter(|n| !n.is_empty()) {
+            metadata.push(("notes", notes.to_string()));
+        }
+        self.entries.push((
+            date,
+            Entry::Transaction {
+                payee: Some(payee.to_string()),
+                narration: tx.description.clone(),
+                tags: tx
+                    .tags
+                    .as_deref()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect(),
+                metadata,
+                postings: vec![
+                    (account, Some(amount(tx.amount, &tx.currency))),
+                    (counter, None),
+                ],
+            },
+        ));
+
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        writeln!(
+            out,
+            "; Generated by monzo-cli. Changes will be overwritten.\n"
+        )?;
+
+        let mut opened: Vec<_> = self.opened.iter().collect();
+        opened.sort_by_key(|&(name, date)| (*date, name.clone()));
+        for (name, date) in opened {
+            writeln!(out, "{}", self.syntax.open(name, *date))?;
+        }
+
+        let mut directives: Vec<(NaiveDate, String)> = self
+            .annotations
+            .events
+            .iter()
+            .map(|event| {
+                let directive = self
+                    .syntax
+                    .event(event.date, &event.kind, &event.description);
+                (event.date, directive)
+            })
+            .chain(self.annotations.notes.iter().map(|note| {
+                let directive = self.syntax.note(note.date, &note.account, &note.comment);
+                (note.date, directive)
+            }))
+            .collect();
+        directives.sort();
+        if !directives.is_empty() {
+            writeln!(out)?;
+        }
+        for (_, directive) in directives {
+            writeln!(out, "{directive}")?;
+        }
+
+        // stable, so entries on the same day keep their order
+        self.entries.sort_by_key(|(date, _)| *date);
+        for (date, entry) in &self.entries {
+            writeln!(out)?;
+            write!(out, "{}", self.syntax.entry(*date, entry))?;
+        }
+
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Create `main.beancount` and `manual.beancount` in `dir` if they don't
+/// exist, returning the files created
+///
+/// # Errors
+/// Will return an error if a file can't be written.
+pub fn create_layout(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let files = [
+        (
+            MAIN,
+            format!(
+                "; Created by monzo-cli and never overwritten. Open this file in beancount.\n\n\
+                 include \"{GENERATED}\"\n\
+                 include \"{MANUAL}\"\n"
+            ),
+        ),
+        (
+            MANUAL,
+            format!(
+                "; Created by monzo-cli and never overwritten. Entries here are kept when\n\
+                 ; {GENERATED} is regenerated.\n"
+            ),
+        ),
+    ];
+
+    let mut created = Vec::new();
+    for (name, contents) in files {
+        let path = dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(contents.as_bytes())?;
+                created.push(path);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(created)
+}
+
+/// A ledger account name component, e.g. `eating_out` -> `EatingOut`.
+/// Components must start with a capital letter or digit.
+pub(crate) fn component(name: &str) -> String {
+    // capitalise each word but keep acronyms such as ISA intact
+    let component: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect();
+    if component.is_empty() {
+        "Other".to_string()
+    } else {
+        component
+    }
+}
+
+/// An amount with its currency, e.g. `-12.50 GBP`
+pub(crate) fn amount(minor: i64, currency: &str) -> String {
+    format!("{} {currency}", decimal(minor, currency))
+}
+
+// An entry in beancount syntax
+fn beancount_entry(date: NaiveDate, entry: &Entry) -> String {
+    match entry {
+        Entry::Transaction {
+            payee,
+            narration,
+            tags,
+            metadata,
+            postings,
+        } => {
+            let mut text = format!("{date} *");
+            if let Some(payee) = payee {
+                let _ = write!(text, " \"{}\"", escape(payee));
+            }
+            let _ = write!(text, " \"{}\"", escape(narration));
+            for tag in tags {
+                let _ = write!(text, " #{tag}");
+            }
+            text.push('\n');
+            for (key, value) in metadata {
+                let _ = writeln!(text, "  {key}: \"{}\"", escape(value));
+            }
+            for (account, amount) in postings {
+                let _ = match amount {
+                    Some(amount) => writeln!(text, "  {account}  {amount}"),
+                    None => writeln!(text, "  {account}"),
+                };
+            }
+            text
+        }
+        Entry::Pad { account, from, .. } => format!("{date} pad {account} {from}\n"),
+        Entry::Balance {
+            account,
+            balance,
+            currency,
+        } => format!("{date} balance {account} {}\n", amount(*balance, currency)),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', " ")
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn writes_transactions_and_opens_accounts() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            created: date("2024-01-01 00:00:00"),
+            ..Default::default()
+        };
+        let tx = ExportTransaction {
+            id: "tx_1".to_string(),
+            account_id: "acc_1".to_string(),
+            created: date("2024-05-01 12:00:00"),
+            amount: -1250,
+            currency: "GBP".to_string(),
+            description: "TESCO \"EXPRESS\"".to_string(),
+            category_name: "eating_out".to_string(),
+            merchant_name: Some("Tesco".to_string()),
+            tags: Some("food weekly".to_string()),
+            ..Default::default()
+        };
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("2024-01-01 open Assets:Monzo:Personal\n"));
+        assert!(ledger.contains("2024-05-01 open Expenses:EatingOut\n"));
+        assert!(ledger.contains("2024-05-01 * \"Tesco\" \"TESCO \\\"EXPRESS\\\"\" #food #weekly\n"));
+        assert!(ledger.contains("  Assets:Monzo:Personal  -12.50 GBP\n  Expenses:EatingOut\n"));
+    }
+
+    #[test]
+    fn posts_p2p_payments_to_people() {
+        // Arrange
+        let tx = ExportTransaction {
+            id: "tx_1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            created: date("2024-05-01 12:00:00"),
+            amount: -2000,
+            currency: "GBP".to_string(),
+            description: "Pizza".to_string(),
+            category_name: "general".to_string(),
+            counterparty_name: Some("Jamie Lee".to_string()),
+            ..Default::default()
+        };
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("2024-05-01 * \"Jamie Lee\" \"Pizza\"\n"));
+        assert!(ledger.contains("  Assets:Monzo:Personal  -20.00 GBP\n  Assets:People:JamieLee\n"));
+    }
+
+    #[test]
+    fn writes_manual_valuations_as_balance_assertions() {
+        // Arrange
+        let mortgage = ManualAccount {
+            id: 1,
+            name: "Home mortgage".to_string(),
+            kind: ManualAccountKind::Liability,
+            institution: Some("Nationwide".to_string()),
+            currency: "GBP".to_string(),
+            created: date("2024-03-01 09:00:00"),
+        };
+        let valuation = ManualValuation {
+            account_id: 1,
+            date: NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            balance: -15_000_000,
+        };
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter
+            .manual_accounts(&mut out, &[mortgage], &[valuation])
+            .unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        let name = "Liabilities:Nationwide:HomeMortgage";
+        assert_eq!(component("ISA"), "ISA");
+        assert!(ledger.contains(&format!("2024-01-30 open {name}\n")));
+        assert!(ledger.contains(&format!("2024-01-30 pad {name} Equity:Valuations\n")));
+        assert!(ledger.contains(&format!("2024-01-31 balance {name} -150000.00 GBP\n")));
+    }
+
+    #[test]
+    fn posts_repayments_to_the_card() {
+        // Arrange
+        let card = ManualAccount {
+            id: 2,
+            name: "Gold".to_string(),
+            kind: ManualAccountKind::Liability,
+            institution: Some("Amex".to_string()),
+            currency: "GBP".to_string(),
+            created: date("2024-01-01 00:00:00"),
+        };
+        let tx = ExportTransaction {
+            id: "tx_1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            created: date("2024-05-01 12:00:00"),
+            amount: -45_000,
+            currency: "GBP".to_string(),
+            description: "AMERICAN EXPRESS".to_string(),
+            category_name: "bills".to_string(),
+            is_transfer: true,
+            repayment_account_id: Some(2),
+            ..Default::default()
+        };
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.manual_accounts(&mut out, &[card], &[]).unwrap();
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains(
+            "  Assets:Monzo:Personal  -450.00 GBP
+  Liabilities:Amex:Gold
+"
+        ));
+        assert!(!ledger.contains("Expenses:Bills"));
+    }
+
+    #[test]
+    fn institutions_replace_account_institutions() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_2".to_string(),
+            created: date("2024-01-01 09:00:00"),
+            owner_type: "uk_business".to_string(),
+            ..Default::default()
+        };
+        let card = ManualAccount {
+            id: 2,
+            name: "Gold".to_string(),
+            kind: ManualAccountKind::Liability,
+            institution: Some("Amex".to_string()),
+            currency: "GBP".to_string(),
+            created: date("2024-01-01 00:00:00"),
+        };
+        let annotations = Annotations::from_yaml(
+            "institutions:
+  uk_business: Tide
+  Gold: american_express
+",
+        )
+        .unwrap();
+        let mut exporter = BeancountExporter::default();
+        exporter.set_annotations(&annotations);
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter.manual_accounts(&mut out, &[card], &[]).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("2024-01-01 open Assets:Tide:UkBusiness\n"));
+        assert!(ledger.contains("2024-01-01 open Liabilities:AmericanExpress:Gold\n"));
+    }
+
+    #[test]
+    fn leaves_out_excluded_accounts_and_pots() {
+        // Arrange
+        let accounts = ["personal", "uk_business"].map(|owner_type| AccountForDB {
+            id: format!("acc_{owner_type}"),
+            owner_type: owner_type.to_string(),
+            created: date("2024-01-01 00:00:00"),
+            ..Default::default()
+        });
+        let tx = |id: &str, account: &str, pot: Option<&str>| ExportTransaction {
+            id: id.to_string(),
+            account_id: format!("acc_{account}"),
+            account_name: account.to_string(),
+            created: date("2024-05-01 12:00:00"),
+            amount: -1000,
+            currency: "GBP".to_string(),
+            description: id.to_string(),
+            category_name: "general".to_string(),
+            pot_name: pot.map(str::to_string),
+            ..Default::default()
+        };
+        let annotations = Annotations::from_yaml(
+            "include_accounts: [personal]
+exclude_pots: [tax]
+",
+        )
+        .unwrap();
+        let mut exporter = BeancountExporter::default();
+        exporter.set_annotations(&annotations);
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &accounts).unwrap();
+        for tx in [
+            tx("tx_lunch", "personal", None),
+            tx("tx_holiday", "personal", Some("Holiday")),
+            tx("tx_tax", "personal", Some("Tax")),
+            tx("tx_invoice", "uk_business", None),
+        ] {
+            exporter.emit(&mut out, &tx).unwrap();
+        }
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("\"tx_lunch\""));
+        assert!(ledger.contains("\"tx_holiday\""));
+        assert!(!ledger.contains("tx_tax"));
+        assert!(!ledger.contains("tx_invoice"));
+        assert!(!ledger.contains("UkBusiness"));
+    }
+
+    #[test]
+    fn opens_with_derived_balances() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            created: date("2023-01-01 00:00:00"),
+            ..Default::default()
+        };
+        let opening = |pot: Option<&str>, balance| OpeningBalance {
+            account_id: "acc_1".to_string(),
+            pot: pot.map(str::to_string),
+            date: date("2024-06-01 00:00:00"),
+            balance,
+            currency: "GBP".to_string(),
+        };
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter
+            .opening_balances(
+                &mut out,
+                &[opening(None, 10_500), opening(Some("Savings"), 0)],
+            )
+            .unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("2024-05-31 open Assets:Monzo:Personal:Savings\n"));
+        assert!(ledger.contains(
+            "2024-05-31 * \"Opening balance\"
+  Assets:Monzo:Personal  105.00 GBP
+  Equity:OpeningBalances
+"
+        ));
+        assert!(ledger.contains("2024-06-01 balance Assets:Monzo:Personal 105.00 GBP\n"));
+        assert!(ledger.contains("2024-06-01 balance Assets:Monzo:Personal:Savings 0.00 GBP\n"));
+        assert!(!ledger.contains("Savings  "));
+    }
+
+    #[test]
+    fn asserts_stored_balances_including_pots() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            created: date("2023-01-01 00:00:00"),
+            ..Default::default()
+        };
+        let balance = |pot: Option<&str>, balance| OpeningBalance {
+            account_id: "acc_1".to_string(),
+            pot: pot.map(str::to_string),
+            date: date("2024-06-21 00:00:00"),
+            balance,
+            currency: "GBP".to_string(),
+        };
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter
+            .balance_assertions(
+                &mut out,
+                &[balance(None, 10_000), balance(Some("Savings"), 2_500)],
+            )
+            .unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("2024-06-21 open Assets:Monzo:Personal:Savings\n"));
+        assert!(ledger.contains("2024-06-21 balance Assets:Monzo:Personal 125.00 GBP\n"));
+        assert!(ledger.contains("2024-06-21 balance Assets:Monzo:Personal:Savings 25.00 GBP\n"));
+    }
+
+    #[test]
+    fn writes_events_and_notes_after_opening_accounts() {
+        // Arrange
+        let annotations = Annotations::from_yaml(
+            r#"
+events:
+  - date: 2024-03-01
+    type: location
+    description: "Manchester"
+notes:
+  - date: 2024-02-01
+    account: Assets:Monzo:Personal
+    comment: Salary paid here
+"#,
+        )
+        .unwrap();
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            created: date("2024-01-01 00:00:00"),
+            ..Default::default()
+        };
+        let mut exporter = BeancountExporter::default();
+        exporter.set_annotations(&annotations);
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.ends_with(
+            "2024-01-01 open Assets:Monzo:Personal
+
+2024-02-01 note Assets:Monzo:Personal \"Salary paid here\"
+2024-03-01 event \"location\" \"Manchester\"
+"
+        ));
+    }
+
+    #[test]
+    fn layout_keeps_existing_files() {
+        // Arrange
+        let dir = temp_dir::TempDir::with_prefix("monzo-ledger").unwrap();
+        let manual = dir.path().join(MANUAL);
+
+        // Act
+        let first = create_layout(dir.path()).unwrap();
+        std::fs::write(&manual, "2024-01-01 open Assets:Cash\n").unwrap();
+        let second = create_layout(dir.path()).unwrap();
+
+        // Assert
+        assert_eq!(first, vec![dir.path().join(MAIN), manual.clone()]);
+        assert!(second.is_empty());
+        let main = std::fs::read_to_string(dir.path().join(MAIN)).unwrap();
+        assert!(main.contains("include \"generated.beancount\"\ninclude \"manual.beancount\"\n"));
+        assert_eq!(
+            std::fs::read_to_string(&manual).unwrap(),
+            "2024-01-01 open Assets:Cash\n"
+        );
+    }
+
+    #[test]
+    fn household_ledgers_have_per_person_accounts() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            created: date("2024-01-01 00:00:00"),
+            ..Default::default()
+        };
+        let tx = ExportTransaction {
+            id: "tx_1".to_string(),
+            account_id: "acc_1".to_string(),
+            created: date("2024-05-01 12:00:00"),
+            amount: -1250,
+            currency: "GBP".to_string(),
+            category_name: "groceries".to_string(),
+            ..Default::default()
+        };
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.set_owner("alex");
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("2024-01-01 open Assets:Monzo:Alex:Personal\n"));
+        assert!(ledger
+            .contains("  Assets:Monzo:Alex:Personal  -12.50 GBP\n  Expenses:Groceries:Alex\n"));
+    }
+
+    #[test]
+    fn accounts_are_named_by_nickname() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            created: date("2024-01-01 00:00:00"),
+            ..Default::default()
+        };
+        let nicknames = BTreeMap::from([("acc_1".to_string(), "everyday spending".to_string())]);
+        let mut exporter = BeancountExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.set_nicknames(&nicknames);
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("2024-01-01 open Assets:Monzo:EverydaySpending\n"));
+    }
+}
@@ -0,0 +1,149 @@
+//! Comma-separated values
+//!
+//! One row per transaction, with the names of its account, merchant, category
+//! and pot alongside the amounts, for spreadsheets and scripts. Amounts are
+//! decimal in their currency and dates are in the display timezone.
+
+use std::{collections::BTreeMap, io::Write};
+
+use chrono_tz::Tz;
+
+use super::Exporter;
+use crate::{
+    currency::decimal,
+    error::AppErrors as Error,
+    model::{account::display_name, transaction::ExportTransaction},
+    timezone::to_local,
+};
+
+const HEADER: [&str; 15] = [
+    "id",
+    "date",
+    "time",
+    "account",
+    "description",
+    "merchant",
+    "category",
+    "pot",
+    "amount",
+    "currency",
+    "local_amount",
+    "local_currency",
+    "transfer",
+    "tags",
+    "notes",
+];
+
+#[derive(Debug, Default)]
+pub struct CsvExporter {
+    timezone: Option<Tz>,
+    nicknames: BTreeMap<String, String>,
+}
+
+impl Exporter for CsvExporter {
+    fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = Some(timezone);
+    }
+
+    fn set_nicknames(&mut self, nicknames: &BTreeMap<String, String>) {
+        self.nicknames.clone_from(nicknames);
+    }
+
+    fn init(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        write_record(out, &HEADER)
+    }
+
+    fn emit(&mut self, out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+        let created = to_local(tx.created, self.timezone.unwrap_or(Tz::UTC));
+        write_record(
+            out,
+            &[
+                &tx.id,
+                &created.format("%Y-%m-%d").to_string(),
+                &created.format("%H:%M:%S").to_string(),
+                display_name(&self.nicknames, &tx.account_id, &tx.account_name),
+                &tx.description,
+                tx.merchant_name.as_deref().unwrap_or_default(),
+                &tx.category_name,
+                tx.pot_name.as_deref().unwrap_or_default(),
+                &decimal(tx.amount, &tx.currency),
+                &tx.currency,
+                &decimal(tx.local_amount, &tx.local_currency),
+                &tx.local_currency,
+                if tx.is_transfer { "true" } else { "false" },
+                tx.tags.as_deref().unwrap_or_default(),
+                tx.notes.as_deref().unwrap_or_default(),
+            ],
+        )
+    }
+}
+
+// Write a row, quoting fields as needed
+fn write_record(out: &mut dyn Write, record: &[&str]) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer
+        .write_record(record)
+        .map_err(|e| Error::Error(e.to_string()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn writes_a_header_and_a_row_per_transaction() {
+        // Arrange
+        let tx = ExportTransaction {
+            id: "tx_1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            // 00:30 BST on 2 May
+            created: NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(23, 30, 0)
+                .unwrap(),
+            settled: None,
+            amount: -1250,
+            currency: "GBP".to_string(),
+            local_amount: -1250,
+            local_currency: "GBP".to_string(),
+            description: "TESCO STORES".to_string(),
+            notes: Some("milk, eggs".to_string()),
+            category_name: "groceries".to_string(),
+            category_label: "🛒 Groceries".to_string(),
+            merchant_name: Some("Tesco".to_string()),
+            pot_name: None,
+            is_transfer: false,
+            counterparty_name: None,
+            repayment_account_id: None,
+            tags: None,
+            latitude: None,
+            longitude: None,
+            merchant_logo: None,
+        };
+        let mut exporter = CsvExporter::default();
+        exporter.set_timezone(chrono_tz::Europe::London);
+        exporter.set_nicknames(&BTreeMap::from([("acc_1".to_string(), "Main".to_string())]));
+        let mut out = Vec::new();
+
+        // Act
+        exporter.init(&mut out).unwrap();
+        exporter.emit(&mut out, &tx).unwrap();
+
+        // Assert
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("id,date,time,account,"));
+        assert_eq!(
+            lines[1],
+            "tx_1,2024-05-02,00:30:00,Main,TESCO STORES,Tesco,groceries,,-12.50,GBP,-12.50,GBP,false,,\"milk, eggs\""
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Merchant locations as `GeoJSON`
+//!
+//! Writes a `FeatureCollection` with a point for each transaction at a merchant
+//! with known coordinates, so spending can be mapped. Transactions without a
+//! location (online merchants, transfers) are left out. A merchant's logo is
+//! linked as a `file://` URL once `merchants fetch-logos` has cached it.
+
+use std::io::Write;
+
+use chrono_tz::Tz;
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use super::Exporter;
+use crate::{
+    currency::decimal, error::AppErrors as Error, model::transaction::ExportTransaction,
+    timezone::local_date,
+};
+
+#[derive(Debug, Default)]
+pub struct GeoJsonExporter {
+    features: Vec<Value>,
+    timezone: Option<Tz>,
+}
+
+impl GeoJsonExporter {
+    /// The features collected so far as a `FeatureCollection`
+    pub(crate) fn collection(&self) -> Value {
+        json!({
+            "type": "FeatureCollection",
+            "features": self.features,
+        })
+    }
+}
+
+impl Exporter for GeoJsonExporter {
+    fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = Some(timezone);
+    }
+
+    fn emit(&mut self, _out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+        if let Some(feature) = feature(tx, self.timezone.unwrap_or(Tz::UTC)) {
+            self.features.push(feature);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        serde_json::to_writer_pretty(&mut *out, &self.collection())?;
+        writeln!(out)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+// A point feature for a transaction at a located merchant
+fn feature(tx: &ExportTransaction, timezone: Tz) -> Option<Value> {
+    let (Some(latitude), Some(longitude)) = (tx.latitude, tx.longitude) else {
+        return None;
+    };
+    // a decimal string always parses; fall back to null rather than fail
+    let amount: Option<f64> = decimal(tx.amount, &tx.currency).parse().ok();
+
+    Some(json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [longitude, latitude],
+        },
+        "properties": {
+            "id": tx.id,
+            "date": local_date(tx.created, timezone).to_string(),
+            "amount": amount,
+            "currency": tx.currency,
+            "merchant": tx.merchant_name,
+            "category": tx.category_label,
+            "logo": tx.merchant_logo.as_deref().and_then(logo_url),
+        },
+    }))
+}
+
+// A cached logo as a file URL, or the logo URL the API reported
+fn logo_url(logo: &str) -> Option<String> {
+    if logo.starts_with("http://") || logo.starts_with("https://") {
+        return Some(logo.to_string());
+    }
+    Url::from_file_path(logo).ok().map(String::from)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_located_transactions_only() {
+        // Arrange
+        let located = ExportTransaction {
+            id: "tx_1".to_string(),
+            amount: -1250,
+            currency: "GBP".to_string(),
+            category_name: "groceries".to_string(),
+            merchant_name: Some("Tesco".to_string()),
+            latitude: Some(51.5),
+            longitude: Some(-0.12),
+            ..Default::default()
+        };
+        let online = ExportTransaction {
+            id: "tx_2".to_string(),
+            ..Default::default()
+        };
+        let mut exporter = GeoJsonExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.emit(&mut out, &located).unwrap();
+        exporter.emit(&mut out, &online).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let collection: Value = serde_json::from_slice(&out).unwrap();
+        let features = collection["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["geometry"]["coordinates"], json!([-0.12, 51.5]));
+        assert_eq!(features[0]["properties"]["amount"], json!(-12.5));
+        assert_eq!(features[0]["properties"]["merchant"], "Tesco");
+        assert_eq!(features[0]["properties"]["logo"], Value::Null);
+    }
+
+    #[test]
+    fn links_cached_logos_as_files() {
+        assert_eq!(
+            logo_url("/tmp/logos/merch_1.png").as_deref(),
+            Some("file:///tmp/logos/merch_1.png")
+        );
+        assert_eq!(
+            logo_url("https://example.com/logo.png").as_deref(),
+            Some("https://example.com/logo.png")
+        );
+        assert_eq!(logo_url("logos/merch_1.png"), None);
+    }
+}
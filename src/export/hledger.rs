@@ -0,0 +1,166 @@
+//! hledger journal
+//!
+//! The beancount ledger's accounts and entries written in hledger syntax, for
+//! `export hledger`. Accounts are declared with `account` directives tagged
+//! with the date they open, and transaction ids and notes become tags.
+//! hledger has no `pad` or `balance` directives: a pad is a balance
+//! assignment and a balance assertion a posting of nothing that asserts the
+//! balance including sub-accounts. Events and account notes, which hledger
+//! has no directives for, are written as comments.
+
+use std::fmt::Write as _;
+
+use chrono::NaiveDate;
+
+use super::beancount::{amount, Entry};
+
+/// An `account` directive
+pub(crate) fn open(account: &str, date: NaiveDate) -> String {
+    format!("account {account}  ; opened:{date}")
+}
+
+/// An event, as a comment
+pub(crate) fn event(date: NaiveDate, kind: &str, description: &str) -> String {
+    format!("; {date} {}: {}", text(kind), text(description))
+}
+
+/// A note on an account, as a comment
+pub(crate) fn note(date: NaiveDate, account: &str, comment: &str) -> String {
+    format!("; {date} {account}: {}", text(comment))
+}
+
+/// An entry in hledger syntax, ending in a newline
+pub(crate) fn entry(date: NaiveDate, entry: &Entry) -> String {
+    match entry {
+        Entry::Transaction {
+            payee,
+            narration,
+            tags,
+            metadata,
+            postings,
+        } => {
+            let mut entry = match payee {
+                Some(payee) => format!(
+                    "{date} * {} | {}",
+                    text(payee).replace('|', "/"),
+                    text(narration)
+                ),
+                None => format!("{date} * {}", text(narration)),
+            };
+            if !tags.is_empty() {
+                let tags: Vec<String> = tags.iter().map(|tag| format!("{tag}:")).collect();
+                let _ = write!(entry, "  ; {}", tags.join(", "));
+            }
+            entry.push('\n');
+            for (key, value) in metadata {
+                let _ = writeln!(entry, "    ; {key}: {}", text(value));
+            }
+            for (account, amount) in postings {
+                let _ = match amount {
+                    Some(amount) => writeln!(entry, "    {account}  {amount}"),
+                    None => writeln!(entry, "    {account}"),
+                };
+            }
+            entry
+        }
+        Entry::Pad {
+            account,
+            from,
+            balance,
+            currency,
+        } => format!(
+            "{date} * Padding\n    {account}  = {}\n    {from}\n",
+            amount(*balance, currency)
+        ),
+        Entry::Balance {
+            account,
+            balance,
+            currency,
+        } => format!(
+            "{date} Balance assertion\n    {account}  0 {currency} =* {}\n",
+            amount(*balance, currency)
+        ),
+    }
+}
+
+// Free text on one line, without starting a comment
+fn text(value: &str) -> String {
+    value.replace(['\r', '\n'], " ").replace(';', ",")
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use chrono::NaiveDateTime;
+
+    use crate::{
+        export::{beancount::BeancountExporter, Exporter, OpeningBalance},
+        model::{account::AccountForDB, transaction::ExportTransaction},
+    };
+
+    fn date(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn writes_the_ledger_in_hledger_syntax() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            created: date("2023-01-01 00:00:00"),
+            ..Default::default()
+        };
+        let opening = OpeningBalance {
+            account_id: "acc_1".to_string(),
+            pot: None,
+            date: date("2024-06-01 00:00:00"),
+            balance: 10_000,
+            currency: "GBP".to_string(),
+        };
+        let tx = ExportTransaction {
+            id: "tx_1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            created: date("2024-06-02 12:00:00"),
+            amount: -1250,
+            currency: "GBP".to_string(),
+            description: "TESCO STORES; 123".to_string(),
+            notes: Some("weekly shop".to_string()),
+            category_name: "groceries".to_string(),
+            merchant_name: Some("Tesco".to_string()),
+            tags: Some("food home".to_string()),
+            ..Default::default()
+        };
+        let mut exporter = BeancountExporter::hledger();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter.opening_balances(&mut out, &[opening]).unwrap();
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.finish(&mut out).unwrap();
+        out.flush().unwrap();
+
+        // Assert
+        let ledger = String::from_utf8(out).unwrap();
+        assert!(ledger.contains("account Assets:Monzo:Personal  ; opened:2023-01-01\n"));
+        assert!(ledger.contains(
+            "2024-05-31 * Opening balance\n    Assets:Monzo:Personal  100.00 GBP\n    Equity:OpeningBalances\n"
+        ));
+        assert!(ledger.contains(
+            "2024-06-01 Balance assertion\n    Assets:Monzo:Personal  0 GBP =* 100.00 GBP\n"
+        ));
+        assert!(ledger.contains(
+            "2024-06-02 * Tesco | TESCO STORES, 123  ; food:, home:
+    ; id: tx_1
+    ; notes: weekly shop
+    Assets:Monzo:Personal  -12.50 GBP
+    Expenses:Groceries
+"
+        ));
+    }
+}
@@ -0,0 +1,250 @@
+//! iCalendar
+//!
+//! Writes an all-day event on the local date of each large transaction and
+//! bill payment, so spending can be reviewed alongside other calendars. A
+//! transaction is large if it moves at least `[calendar] threshold` in or out
+//! of an account; bills are those in `[calendar] categories`, whatever their
+//! amount. Transfers between the user's own accounts are left out.
+
+use std::{collections::BTreeMap, io::Write};
+
+use chrono::{Days, NaiveDateTime};
+use chrono_tz::Tz;
+
+use super::Exporter;
+use crate::{
+    configuration::Calendar,
+    currency::{self, decimal},
+    error::AppErrors as Error,
+    model::{account::display_name, transaction::ExportTransaction},
+    timezone::local_date,
+};
+
+// Longest line in octets before it's folded
+const LINE_LENGTH: usize = 75;
+
+#[derive(Debug, Default)]
+pub struct IcsExporter {
+    timezone: Option<Tz>,
+    nicknames: BTreeMap<String, String>,
+    calendar: Calendar,
+}
+
+impl IcsExporter {
+    // Whether `tx` gets an event
+    fn is_listed(&self, tx: &ExportTransaction) -> bool {
+        if tx.is_transfer {
+            return false;
+        }
+        let is_bill = self
+            .calendar
+            .categories
+            .iter()
+            .any(|category| category.eq_ignore_ascii_case(&tx.category_name));
+        is_bill || tx.amount.abs() >= currency::from_major(self.calendar.threshold, &tx.currency)
+    }
+}
+
+impl Exporter for IcsExporter {
+    fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = Some(timezone);
+    }
+
+    fn set_nicknames(&mut self, nicknames: &BTreeMap<String, String>) {
+        self.nicknames.clone_from(nicknames);
+    }
+
+    fn set_calendar(&mut self, calendar: &Calendar) {
+        self.calendar = calendar.clone();
+    }
+
+    fn init(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        for line in [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "PRODID:-//monzo-cli//Transactions//EN",
+            "CALSCALE:GREGORIAN",
+            "X-WR-CALNAME:Monzo",
+        ] {
+            write_line(out, line)?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+        if !self.is_listed(tx) {
+            return Ok(());
+        }
+
+        let payee = [
+            tx.merchant_name.as_deref(),
+            tx.counterparty_name.as_deref(),
+            Some(tx.description.as_str()),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|name| !name.is_empty())
+        .unwrap_or(&tx.category_label);
+        let amount = currency::display(tx.amount, &tx.currency)
+            .unwrap_or_else(|_| format!("{} {}", decimal(tx.amount, &tx.currency), tx.currency));
+        let mut description = vec![
+            tx.category_label.clone(),
+            display_name(&self.nicknames, &tx.account_id, &tx.account_name).to_string(),
+        ];
+        description.extend(tx.notes.clone().filter(|notes| !notes.is_empty()));
+
+        let date = local_date(tx.created, self.timezone.unwrap_or(Tz::UTC));
+        let next_day = date + Days::new(1);
+        for line in [
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@monzo-cli", tx.id),
+            format!("DTSTAMP:{}", utc_stamp(tx.created)),
+            format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+            format!("DTEND;VALUE=DATE:{}", next_day.format("%Y%m%d")),
+            format!("SUMMARY:{}", escape(&format!("{amount} {payee}"))),
+            format!("DESCRIPTION:{}", escape(&description.join("\n"))),
+            format!("CATEGORIES:{}", escape(&tx.category_name)),
+            "TRANSP:TRANSPARENT".to_string(),
+            "END:VEVENT".to_string(),
+        ] {
+            write_line(out, &line)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        write_line(out, "END:VCALENDAR")?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn utc_stamp(time: NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+// Escape a TEXT value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Write a content line ending in CRLF, folded so no line is longer than
+// `LINE_LENGTH` octets
+fn write_line(out: &mut dyn Write, line: &str) -> Result<(), Error> {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LENGTH {
+            out.write_all(b"\r\n ")?;
+            length = 1;
+        }
+        write!(out, "{c}")?;
+        length += c.len_utf8();
+    }
+    out.write_all(b"\r\n")?;
+    Ok(())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn transaction(id: &str, amount: i64, category: &str) -> ExportTransaction {
+        ExportTransaction {
+            id: id.to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            // 00:30 BST on 2 May
+            created: NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(23, 30, 0)
+                .unwrap(),
+            settled: None,
+            amount,
+            currency: "GBP".to_string(),
+            local_amount: amount,
+            local_currency: "GBP".to_string(),
+            description: "DD PAYMENT".to_string(),
+            notes: None,
+            category_name: category.to_string(),
+            category_label: category.to_string(),
+            merchant_name: Some("Octopus, Energy".to_string()),
+            pot_name: None,
+            is_transfer: false,
+            counterparty_name: None,
+            repayment_account_id: None,
+            tags: None,
+            latitude: None,
+            longitude: None,
+            merchant_logo: None,
+        }
+    }
+
+    #[test]
+    fn lists_large_transactions_and_bills() {
+        // Arrange
+        let mut exporter = IcsExporter::default();
+        exporter.set_timezone(chrono_tz::Europe::London);
+        exporter.set_calendar(&Calendar {
+            threshold: 50.0,
+            categories: vec!["bills".to_string()],
+        });
+        let mut out = Vec::new();
+
+        // Act
+        exporter.init(&mut out).unwrap();
+        for tx in [
+            transaction("small", -1250, "groceries"),
+            transaction("bill", -4500, "bills"),
+            transaction("large", -12000, "shopping"),
+            transaction("salary", 250_000, "income"),
+            ExportTransaction {
+                is_transfer: true,
+                ..transaction("pot", -50000, "savings")
+            },
+        ] {
+            exporter.emit(&mut out, &tx).unwrap();
+        }
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let text = String::from_utf8(out).unwrap();
+        let uids: Vec<_> = text.lines().filter(|l| l.starts_with("UID:")).collect();
+        assert_eq!(
+            uids,
+            vec![
+                "UID:bill@monzo-cli",
+                "UID:large@monzo-cli",
+                "UID:salary@monzo-cli"
+            ]
+        );
+        assert!(text.contains("DTSTART;VALUE=DATE:20240502\r\nDTEND;VALUE=DATE:20240503\r\n"));
+        assert!(text.contains("SUMMARY:-£45.00 Octopus\\, Energy\r\n"));
+        assert!(text.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        // Arrange
+        let line = format!("DESCRIPTION:{}", "é".repeat(50));
+        let mut out = Vec::new();
+
+        // Act
+        write_line(&mut out, &line).unwrap();
+
+        // Assert
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.split("\r\n").all(|l| l.len() <= LINE_LENGTH));
+        assert_eq!(text.replace("\r\n ", ""), format!("{line}\r\n"));
+    }
+}
@@ -0,0 +1,297 @@
+//! Classifications read back from a beancount ledger
+//!
+//! Entries exported to beancount carry the transaction's id as `id` (or
+//! `monzo-id`) metadata. Categories changed by moving an entry's posting to
+//! another `Expenses:` or `Income:` account, and tags added to its header
+//! line, are read back and stored as if made with `transactions set`, so the
+//! work done in an editor or fava shows in later reports and exports.
+//!
+//! Tags removed in the ledger aren't removed from the database, and postings
+//! to accounts that aren't categories, such as pots and people, are ignored.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveTime};
+
+use super::beancount::component;
+use crate::{
+    error::AppErrors as Error,
+    model::{edit::TransactionEdit, DatabasePool},
+};
+
+/// A ledger entry with a transaction id
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub id: String,
+    /// The category component of its `Expenses:` or `Income:` posting
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// A change to a stored transaction found in the ledger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerChange {
+    pub id: String,
+    pub description: String,
+    /// The category name it had and the one it gets
+    pub category: Option<(String, String)>,
+    /// Tags to add
+    pub tags: Vec<String>,
+}
+
+/// What reading a ledger found
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncBack {
+    pub changes: Vec<LedgerChange>,
+    /// Entries whose posting account isn't a known category, by id
+    pub unknown_categories: Vec<(String, String)>,
+    /// Entries with ids that aren't stored
+    pub missing: Vec<String>,
+}
+
+impl SyncBack {
+    /// Compare the entries of `ledger` with the stored transactions
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read.
+    pub async fn read(pool: &DatabasePool, ledger: &str) -> Result<Self, Error> {
+        let categories: HashMap<String, String> = pool
+            .categories()
+            .read_categories()
+            .await?
+            .into_iter()
+            .map(|category| (component(&category.name), category.name))
+            .collect();
+        let last = NaiveDate::from_ymd_opt(9999, 12, 31)
+            .unwrap_or_default()
+            .and_time(NaiveTime::MIN);
+        let transactions: HashMap<String, _> = pool
+            .transactions()
+            .read_export_data(chrono::NaiveDateTime::default(), last)
+            .await?
+            .into_iter()
+            .map(|tx| (tx.id.clone(), tx))
+            .collect();
+
+        let mut sync = Self::default();
+        for entry in parse_ledger(ledger) {
+            let id = match pool.resolve_alias(&entry.id).await? {
+                Some(id) => id,
+                None => entry.id.clone(),
+            };
+            let Some(tx) = transactions.get(&id) else {
+                sync.missing.push(entry.id);
+                continue;
+            };
+
+            let mut category = None;
+            if let Some(name) = entry
+                .category
+                .filter(|c| *c != component(&tx.category_name))
+            {
+                match categories.get(&name) {
+                    // transfers are posted to pots and people, not categories
+                    Some(to) if !tx.is_transfer => {
+                        category = Some((tx.category_name.clone(), to.clone()));
+                    }
+                    Some(_) => {}
+                    None => sync.unknown_categories.push((id.clone(), name)),
+                }
+            }
+            let stored: Vec<&str> = tx
+                .tags
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect();
+            let tags: Vec<String> = entry
+                .tags
+                .into_iter()
+                .filter(|tag| !stored.contains(&tag.as_str()))
+                .collect();
+
+            if category.is_some() || !tags.is_empty() {
+                sync.changes.push(LedgerChange {
+                    id,
+                    description: tx
+                        .merchant_name
+                        .clone()
+                        .unwrap_or_else(|| tx.description.clone()),
+                    category,
+                    tags,
+                });
+            }
+        }
+
+        Ok(sync)
+    }
+
+    /// Store the changes as category overrides and tags
+    ///
+    /// # Errors
+    /// Will return an error if a tag is invalid or the database can't be
+    /// written.
+    pub async fn apply(&self, pool: &DatabasePool) -> Result<(), Error> {
+        for change in &self.changes {
+            let edit = TransactionEdit::new(
+                change.category.as_ref().map(|(_, to)| to.clone()),
+                change.tags.clone(),
+            )?;
+            pool.edit_transactions(std::slice::from_ref(&change.id), &edit)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The entries of a ledger that carry a transaction id
+#[must_use]
+pub fn parse_ledger(ledger: &str) -> Vec<LedgerEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<LedgerEntry> = None;
+    for line in ledger.lines() {
+        let line = strip_comment(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with([' ', '\t']) {
+            entries.extend(current.take().filter(|entry| !entry.id.is_empty()));
+            if let Some(tags) = transaction_tags(line) {
+                current = Some(LedgerEntry {
+                    tags,
+                    ..Default::default()
+                });
+            }
+            continue;
+        }
+        let Some(entry) = current.as_mut() else {
+            continue;
+        };
+
+        let line = line.trim();
+        if let Some((key, value)) = line.split_once(':').filter(|(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '-')
+        }) {
+            if key == "id" || key == "monzo-id" {
+                entry.id = value.trim().trim_matches('"').to_string();
+            }
+        } else if let Some(account) = line.split_whitespace().next() {
+            let mut parts = account.split(':');
+            if let (Some("Expenses" | "Income"), Some(category)) = (parts.next(), parts.next()) {
+                entry.category = Some(category.to_string());
+            }
+        }
+    }
+    entries.extend(current.filter(|entry| !entry.id.is_empty()));
+
+    entries
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// The tags of a transaction's header line, or `None` if it isn't one
+fn transaction_tags(line: &str) -> Option<Vec<String>> {
+    let mut words = line.split_whitespace();
+    NaiveDate::parse_from_str(words.next()?, "%Y-%m-%d").ok()?;
+    if !matches!(words.next()?, "*" | "!" | "txn") {
+        return None;
+    }
+
+    Some(
+        unquoted(line)
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('#'))
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+// `line` without its quoted strings
+fn unquoted(line: &str) -> String {
+    let mut text = String::new();
+    let (mut quoted, mut escaped) = (false, false);
+    for c in line.chars() {
+        match (quoted, escaped, c) {
+            (true, false, '\\') => escaped = true,
+            (true, true, _) => escaped = false,
+            (_, _, '"') => {
+                quoted = !quoted;
+                text.push(' ');
+            }
+            (false, _, c) => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+// `line` up to a `;` comment outside a quoted string
+fn strip_comment(line: &str) -> &str {
+    let (mut quoted, mut escaped) = (false, false);
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if quoted && !escaped => {
+                escaped = true;
+                continue;
+            }
+            '"' if !escaped => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_with_ids_are_read_with_their_category_and_tags() {
+        // Arrange
+        let ledger = r#"
+; Generated by monzo-cli. Changes will be overwritten.
+
+2024-05-01 open Assets:Monzo:Personal
+2024-05-03 * "Tesco" "TESCO #123 \"big\" shop" #holiday #work ; moved
+  id: "tx_1"
+  Assets:Monzo:Personal  -12.50 GBP
+  Expenses:Holidays
+
+2024-05-04 * "Pret" "PRET"
+  monzo-id: "tx_2"
+  notes: "lunch: with Sam"
+  Assets:Monzo:Personal  -4.20 GBP
+  Expenses:EatingOut:Alex
+
+2024-05-05 * "Hand written"
+  Assets:Cash  -1.00 GBP
+  Expenses:Groceries
+"#;
+
+        // Act
+        let entries = parse_ledger(ledger);
+
+        // Assert
+        assert_eq!(
+            entries,
+            vec![
+                LedgerEntry {
+                    id: "tx_1".to_string(),
+                    category: Some("Holidays".to_string()),
+                    tags: vec!["holiday".to_string(), "work".to_string()],
+                },
+                LedgerEntry {
+                    id: "tx_2".to_string(),
+                    category: Some("EatingOut".to_string()),
+                    tags: vec![],
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! HTML map report
+//!
+//! A standalone page plotting the [`geojson`](super::geojson) features on
+//! openstreetmap.org tiles with Leaflet, one circle per transaction sized by
+//! the amount. Leaflet and the map tiles are loaded from the web when the page
+//! is opened; the transactions are embedded in the page. Popups show the
+//! merchant's logo when one is known.
+
+use std::io::Write;
+
+use chrono_tz::Tz;
+
+use super::{geojson::GeoJsonExporter, Exporter};
+use crate::{error::AppErrors as Error, model::transaction::ExportTransaction};
+
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Spending map</title>
+<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+<style>
+html, body, #map { height: 100%; margin: 0; }
+.logo { width: 32px; height: 32px; border-radius: 50%; float: left; margin-right: 8px; }
+</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+const spending = __FEATURES__;
+const map = L.map("map");
+L.tileLayer("https://tile.openstreetmap.org/{z}/{x}/{y}.png", {
+  attribution: "&copy; OpenStreetMap contributors",
+}).addTo(map);
+const layer = L.geoJSON(spending, {
+  pointToLayer: (feature, latlng) => L.circleMarker(latlng, {
+    radius: 4 + Math.sqrt(Math.abs(feature.properties.amount || 0)),
+  }),
+  onEachFeature: (feature, marker) => {
+    const p = feature.properties;
+    const popup = document.createElement("div");
+    if (p.logo) {
+      const logo = document.createElement("img");
+      logo.className = "logo";
+      logo.src = p.logo;
+      popup.appendChild(logo);
+    }
+    const text = document.createElement("div");
+    text.innerText = `${p.merchant || ""}\n${p.category}\n${p.date}\n${p.amount} ${p.currency}`;
+    popup.appendChild(text);
+    marker.bindPopup(popup);
+  },
+}).addTo(map);
+if (spending.features.length > 0) {
+  map.fitBounds(layer.getBounds(), { padding: [20, 20] });
+} else {
+  map.setView([51.5, -0.12], 5);
+}
+</script>
+</body>
+</html>
+"#;
+
+#[derive(Debug, Default)]
+pub struct MapExporter {
+    geojson: GeoJsonExporter,
+}
+
+impl Exporter for MapExporter {
+    fn set_timezone(&mut self, timezone: Tz) {
+        self.geojson.set_timezone(timezone);
+    }
+
+    fn emit(&mut self, out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+        self.geojson.emit(out, tx)
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        // keep merchant names from closing the script element
+        let features = self.geojson.collection().to_string().replace("</", "<\\/");
+        out.write_all(TEMPLATE.replace("__FEATURES__", &features).as_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embeds_features_safely() {
+        // Arrange
+        let tx = ExportTransaction {
+            merchant_name: Some("</script><b>".to_string()),
+            currency: "GBP".to_string(),
+            latitude: Some(48.85),
+            longitude: Some(2.35),
+            ..Default::default()
+        };
+        let mut exporter = MapExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("[2.35,48.85]"));
+        assert_eq!(html.matches("</script>").count(), 2);
+    }
+}
@@ -0,0 +1,624 @@
+//! Export formats
+//!
+//! Every output format implements [`Exporter`] and is looked up by name in a
+//! [`Registry`]. The [`export`] driver reads accounts and transactions from the
+//! database and feeds them to the exporter in order: `init`, `accounts`,
+//! `opening_balances`, `balance_assertions`, `manual_accounts`, one `emit` per
+//! transaction (grouped by account, oldest first), then `finish`. [`export_profiles`] does the same for a household,
+//! calling `set_owner` before each person's accounts and transactions.
+//!
+//! New formats only need an `Exporter` implementation and a `register` call;
+//! the sync and query code is untouched.
+//!
+//! [`anonymise`] is separate: it writes a scrubbed copy of the whole database
+//! rather than a stream of transactions.
+//!
+//! ```no_run
+//! # async fn run(pool: monzo_cli::model::DatabasePool) -> Result<(), monzo_cli::error::AppErrors> {
+//! use monzo_cli::export::{export, Registry};
+//!
+//! let mut exporter = Registry::default().create("qif")?;
+//! let until = chrono::Utc::now().naive_utc();
+//! let since = until - chrono::Duration::days(30);
+//! export(pool, exporter.as_mut(), since, until, &mut std::io::stdout()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod annotations;
+pub mod anonymise;
+#[cfg(feature = "beancount")]
+pub mod beancount;
+pub mod csv;
+pub mod geojson;
+#[cfg(feature = "beancount")]
+pub mod hledger;
+pub mod ics;
+#[cfg(feature = "beancount")]
+pub mod ledger;
+pub mod map;
+pub mod ofx;
+pub mod pdf;
+pub mod qif;
+pub mod statement;
+
+use std::{collections::BTreeMap, io::Write};
+
+use chrono::{Datelike, Duration, NaiveDateTime};
+use chrono_tz::Tz;
+
+use self::annotations::Annotations;
+use crate::{
+    configuration::Calendar,
+    error::AppErrors as Error,
+    model::{
+        account::AccountForDB,
+        balance::BalanceSnapshot,
+        manual::{ManualAccount, ManualValuation},
+        transaction::ExportTransaction,
+        DatabasePool,
+    },
+    timezone::{local_date, start_of_day},
+};
+
+/// The balance of a Monzo account, or one of its pots, at the start of an
+/// export or of a day with a balance assertion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpeningBalance {
+    pub account_id: String,
+    /// The pot's name, or `None` for the account itself
+    pub pot: Option<String>,
+    pub date: NaiveDateTime,
+    pub balance: i64,
+    pub currency: String,
+}
+
+/// An output format
+///
+/// Only `emit` is required; formats without a header, account section or
+/// trailer can rely on the default no-op implementations.
+pub trait Exporter: Send {
+    /// Set the timezone used for dates, where the format has no way to
+    /// record one. Called before `init`; without it dates are in UTC.
+    fn set_timezone(&mut self, _timezone: Tz) {}
+
+    /// The timezone set with `set_timezone`, which balance assertions are
+    /// dated in. Formats without balance assertions can leave it UTC.
+    fn timezone(&self) -> Tz {
+        Tz::UTC
+    }
+
+    /// Set the person whose accounts and transactions follow, when several
+    /// people's data is exported together. Formats that can't tell people
+    /// apart ignore it.
+    fn set_owner(&mut self, _owner: &str) {}
+
+    /// Set the names to use for accounts instead of their type, by account
+    /// id. Called before `init`.
+    fn set_nicknames(&mut self, _nicknames: &BTreeMap<String, String>) {}
+
+    /// Set the events and notes to write alongside the transactions. Called
+    /// before `init`; formats without such directives ignore them.
+    fn set_annotations(&mut self, _annotations: &Annotations) {}
+
+    /// Set which transactions a calendar lists. Called before `init`; other
+    /// formats ignore it.
+    fn set_calendar(&mut self, _calendar: &Calendar) {}
+
+    /// Leave out transactions smaller than `min_amount` minor units, in or
+    /// out. Called before `init`; only beancount uses it.
+    fn set_min_amount(&mut self, _min_amount: i64) {}
+
+    /// Show only the last digits of account numbers and sort codes. Called
+    /// before `init`; only OFX writes them.
+    fn set_mask_account_numbers(&mut self, _mask: bool) {}
+
+    /// Write any preamble
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    fn init(&mut self, _out: &mut dyn Write) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Receive the accounts being exported, before any transactions
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    fn accounts(&mut self, _out: &mut dyn Write, _accounts: &[AccountForDB]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Receive the balances of accounts and pots when the export starts,
+    /// derived from the latest stored balances. Accounts opened after the
+    /// start, or without stored balances, have none.
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    fn opening_balances(
+        &mut self,
+        _out: &mut dyn Write,
+        _balances: &[OpeningBalance],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Receive the balances of accounts and pots at the start of the day after
+    /// the last stored balances of each month in the export, derived from
+    /// those balances, to assert against the transactions
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    fn balance_assertions(
+        &mut self,
+        _out: &mut dyn Write,
+        _balances: &[OpeningBalance],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Receive the accounts held outside Monzo and their valuations, after
+    /// `balance_assertions`
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    fn manual_accounts(
+        &mut self,
+        _out: &mut dyn Write,
+        _accounts: &[ManualAccount],
+        _valuations: &[ManualValuation],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Write a single transaction
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    fn emit(&mut self, out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error>;
+
+    /// Write any trailer and flush
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Creates a fresh exporter
+pub type Factory = fn() -> Box<dyn Exporter>;
+
+/// Exporters keyed by format name
+pub struct Registry {
+    factories: BTreeMap<&'static str, Factory>,
+}
+
+impl Registry {
+    /// An empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Add a format, replacing any existing format with the same name
+    pub fn register(&mut self, name: &'static str, factory: Factory) {
+        self.factories.insert(name, factory);
+    }
+
+    /// Create an exporter for the named format
+    ///
+    /// # Errors
+    /// Will return an error if no format is registered under `name`.
+    pub fn create(&self, name: &str) -> Result<Box<dyn Exporter>, Error> {
+        match self.factories.get(name) {
+            Some(factory) => Ok(factory()),
+            None => Err(Error::Error(format!(
+                "Unknown export format '{name}'. Available formats: {}",
+                self.names().collect::<Vec<_>>().join(", ")
+            ))),
+        }
+    }
+
+    /// The registered format names, in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.factories.keys().copied()
+    }
+}
+
+impl Default for Registry {
+    /// A registry holding the built-in formats
+    fn default() -> Self {
+        let mut registry = Self::new();
+        #[cfg(feature = "beancount")]
+        registry.register("beancount", || {
+            Box::new(beancount::BeancountExporter::default())
+        });
+        registry.register("csv", || Box::new(csv::CsvExporter::default()));
+        #[cfg(feature = "beancount")]
+        registry.register("hledger", || {
+            Box::new(beancount::BeancountExporter::hledger())
+        });
+        registry.register("geojson", || Box::new(geojson::GeoJsonExporter::default()));
+        registry.register("ics", || Box::new(ics::IcsExporter::default()));
+        registry.register("map", || Box::new(map::MapExporter::default()));
+        registry.register("ofx", || Box::new(ofx::OfxExporter::default()));
+        registry.register("qif", || Box::new(qif::QifExporter::default()));
+        registry
+    }
+}
+
+/// Export the transactions created between `since` and `until`
+/// Returns the number of transactions written
+///
+/// # Errors
+/// Will return an error if the database can't be read or the output can't be written.
+pub async fn export(
+    pool: DatabasePool,
+    exporter: &mut dyn Exporter,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    out: &mut (dyn Write + Send),
+) -> Result<usize, Error> {
+    exporter.init(out)?;
+    let count = feed(pool, exporter, since, until, out).await?;
+    exporter.finish(out)?;
+
+    Ok(count)
+}
+
+/// Export the transactions of several people into one output, e.g. a shared
+/// ledger. Returns the number of transactions written
+///
+/// # Errors
+/// Will return an error if a database can't be read or the output can't be written.
+pub async fn export_profiles(
+    profiles: &[(String, DatabasePool)],
+    exporter: &mut dyn Exporter,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    out: &mut (dyn Write + Send),
+) -> Result<usize, Error> {
+    exporter.init(out)?;
+    let mut count = 0;
+    for (name, pool) in profiles {
+        exporter.set_owner(name);
+        count += feed(pool.clone(), exporter, since, until, out).await?;
+    }
+    exporter.finish(out)?;
+
+    Ok(count)
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// Feed the accounts and transactions of one database to the exporter
+async fn feed(
+    pool: DatabasePool,
+    exporter: &mut dyn Exporter,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    out: &mut (dyn Write + Send),
+) -> Result<usize, Error> {
+    let accounts = pool.accounts().read_accounts().await?;
+    let manual_service = pool.manual_accounts();
+    let manual_accounts = manual_service.read_accounts().await?;
+    let valuations = manual_service.read_valuations().await?;
+    let opening_balances = opening_balances(&pool, &accounts, since).await?;
+    let assertions = balance_assertions(&pool, since, until, exporter.timezone()).await?;
+    let transactions = pool.transactions().read_export_data(since, until).await?;
+
+    exporter.accounts(out, &accounts)?;
+    exporter.opening_balances(out, &opening_balances)?;
+    exporter.balance_assertions(out, &assertions)?;
+    exporter.manual_accounts(out, &manual_accounts, &valuations)?;
+    for tx in &transactions {
+        exporter.emit(out, tx)?;
+    }
+
+    Ok(transactions.len())
+}
+
+// The balances at `since` of the accounts opened before it: the latest stored
+// balances less the transactions between `since` and when they were stored
+async fn opening_balances(
+    pool: &DatabasePool,
+    accounts: &[AccountForDB],
+    since: NaiveDateTime,
+) -> Result<Vec<OpeningBalance>, Error> {
+    let Some(snapshot) = pool.balances().read_latest_snapshot().await? else {
+        return Ok(Vec::new());
+    };
+
+    let mut balances = balances_at(pool, &snapshot, since).await?;
+    balances.retain(|balance| {
+        accounts
+            .iter()
+            .any(|a| a.id == balance.account_id && a.created < since)
+    });
+
+    Ok(balances)
+}
+
+// The balances at the start of the day after the last snapshot of each month
+// taken between `since` and `until`, in `timezone`
+async fn balance_assertions(
+    pool: &DatabasePool,
+    since: NaiveDateTime,
+    until: NaiveDateTime,
+    timezone: Tz,
+) -> Result<Vec<OpeningBalance>, Error> {
+    let snapshots = pool.balances().read_snapshots(since, until).await?;
+    let mut monthly = BTreeMap::new();
+    for snapshot in &snapshots {
+        let day = local_date(snapshot.taken, timezone);
+        monthly.insert((day.year(), day.month()), snapshot);
+    }
+
+    let mut balances = Vec::new();
+    for snapshot in monthly.into_values() {
+        let at = start_of_day(
+            local_date(snapshot.taken, timezone) + Duration::days(1),
+            timezone,
+        );
+        // transactions after `until` aren't exported to reach it
+        if at <= until {
+            balances.extend(balances_at(pool, snapshot, at).await?);
+        }
+    }
+
+    Ok(balances)
+}
+
+// The balances of a snapshot's accounts and pots at `at`: the stored balances
+// with the transactions between when they were stored and `at` added, or taken
+// back if they were stored after it
+async fn balances_at(
+    pool: &DatabasePool,
+    snapshot: &BalanceSnapshot,
+    at: NaiveDateTime,
+) -> Result<Vec<OpeningBalance>, Error> {
+    let (from, until, sign) = if snapshot.taken < at {
+        (snapshot.taken, at, -1)
+    } else {
+        (at, snapshot.taken, 1)
+    };
+    let totals = pool.transactions().read_pot_totals(from, until).await?;
+    let total = |account_id: &str, pot_id: Option<&str>, currency: &str| -> i64 {
+        totals
+            .iter()
+            .filter(|t| {
+                t.account_id == account_id
+                    && (pot_id.is_none() || t.pot_id.as_deref() == pot_id)
+                    && t.currency == currency
+            })
+            .map(|t| t.total)
+            .sum::<i64>()
+            * sign
+    };
+
+    let mut balances = Vec::new();
+    for entry in &snapshot.accounts {
+        balances.push(OpeningBalance {
+            account_id: entry.account_id.clone(),
+            pot: None,
+            date: at,
+            balance: entry.balance.balance
+                - total(&entry.account_id, None, &entry.balance.currency),
+            currency: entry.balance.currency.clone(),
+        });
+        // money into a pot is a negative amount on its account
+        balances.extend(entry.pots.iter().map(|pot| OpeningBalance {
+            account_id: entry.account_id.clone(),
+            pot: Some(pot.name.clone()),
+            date: at,
+            balance: pot.balance + total(&entry.account_id, Some(&pot.pot_id), &pot.currency),
+            currency: pot.currency.clone(),
+        }));
+    }
+
+    Ok(balances)
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::balance::{AccountSnapshot, Balance, PotBalance},
+        tests::test::test_db,
+    };
+
+    #[test]
+    fn registry_reports_unknown_formats() {
+        let registry = Registry::default();
+
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec![
+                "beancount",
+                "csv",
+                "geojson",
+                "hledger",
+                "ics",
+                "map",
+                "ofx",
+                "qif"
+            ]
+        );
+        assert!(registry.create("qif").is_ok());
+        assert!(registry.create("nope").is_err());
+    }
+
+    #[tokio::test]
+    async fn export_feeds_every_transaction() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let mut exporter = Registry::default().create("qif").unwrap();
+        let since = chrono::NaiveDateTime::default();
+        let until = chrono::Utc::now().naive_utc();
+        let mut out = Vec::new();
+
+        // Act
+        let count = export(pool, exporter.as_mut(), since, until, &mut out)
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(count, 2);
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.matches("^\n").count(), 3);
+    }
+
+    #[tokio::test]
+    async fn opening_balances_take_back_later_transactions() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET description = '1', amount = -500, created = '2024-07-01' WHERE id = '2'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let snapshot = AccountSnapshot {
+            account_id: "1".to_string(),
+            balance: Balance {
+                balance: 10_000,
+                currency: "GBP".to_string(),
+                ..Default::default()
+            },
+            pots: vec![PotBalance {
+                pot_id: "1".to_string(),
+                name: "Savings".to_string(),
+                balance: 1734,
+                currency: "GBP".to_string(),
+            }],
+        };
+        pool.balances()
+            .save_snapshot(chrono::Utc::now().naive_utc(), &[snapshot])
+            .await
+            .unwrap();
+        let accounts = pool.accounts().read_accounts().await.unwrap();
+        let since = chrono::NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        // Act
+        let balances = opening_balances(&pool, &accounts, since).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            balances
+                .iter()
+                .map(|b| (b.pot.as_deref(), b.balance))
+                .collect::<Vec<_>>(),
+            vec![(None, 10_500), (Some("Savings"), 1234)]
+        );
+    }
+
+    #[tokio::test]
+    async fn asserts_the_last_balances_of_each_month() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET amount = -500, created = '2024-06-20 18:00:00' WHERE id = '1'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let datetime = |s| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        let service = pool.balances();
+        for (taken, balance) in [
+            ("2024-06-10 10:00:00", 10_000),
+            ("2024-06-20 10:00:00", 9_000),
+            ("2024-07-05 10:00:00", 8_000),
+        ] {
+            let snapshot = AccountSnapshot {
+                account_id: "1".to_string(),
+                balance: Balance {
+                    balance,
+                    currency: "GBP".to_string(),
+                    ..Default::default()
+                },
+                pots: vec![],
+            };
+            service
+                .save_snapshot(datetime(taken), &[snapshot])
+                .await
+                .unwrap();
+        }
+
+        // Act
+        let balances = balance_assertions(
+            &pool,
+            datetime("2024-06-01 00:00:00"),
+            datetime("2024-07-05 23:00:00"),
+            Tz::UTC,
+        )
+        .await
+        .unwrap();
+
+        // Assert
+        assert_eq!(
+            balances
+                .iter()
+                .map(|b| (b.date, b.balance))
+                .collect::<Vec<_>>(),
+            // July's are taken after the last day exported
+            vec![(datetime("2024-06-21 00:00:00"), 8_500)]
+        );
+    }
+
+    #[tokio::test]
+    async fn opening_balances_leave_out_other_currencies() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET amount = -500, created = '2024-07-01' WHERE id = '1';
+             UPDATE transactions SET description = '1', currency = 'EUR', amount = -300, created = '2024-07-01' WHERE id = '2'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let snapshot = AccountSnapshot {
+            account_id: "1".to_string(),
+            balance: Balance {
+                balance: 10_000,
+                currency: "GBP".to_string(),
+                ..Default::default()
+            },
+            pots: vec![PotBalance {
+                pot_id: "1".to_string(),
+                name: "Euros".to_string(),
+                balance: 1734,
+                currency: "EUR".to_string(),
+            }],
+        };
+        pool.balances()
+            .save_snapshot(chrono::Utc::now().naive_utc(), &[snapshot])
+            .await
+            .unwrap();
+        let accounts = pool.accounts().read_accounts().await.unwrap();
+        let since = chrono::NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        // Act
+        let balances = opening_balances(&pool, &accounts, since).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            balances
+                .iter()
+                .map(|b| (b.pot.as_deref(), b.balance, b.currency.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(None, 10_500, "GBP"), (Some("Euros"), 1434, "EUR")]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Open Financial Exchange (OFX 2.1)
+//!
+//! Writes one bank statement per account. Statements need their date range up
+//! front, so transactions are collected and written out in `finish`.
+
+use std::{collections::BTreeMap, io::Write};
+
+use chrono::{NaiveDateTime, Utc};
+
+use super::Exporter;
+use crate::{
+    currency::decimal,
+    error::AppErrors as Error,
+    model::{account::AccountForDB, transaction::ExportTransaction},
+};
+
+const HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<?OFX OFXHEADER="200" VERSION="211" SECURITY="NONE" OLDFILEUID="NONE" NEWFILEUID="NONE"?>"#;
+
+#[derive(Debug)]
+struct BankAccount {
+    bank_id: String,
+    account_id: String,
+    currency: String,
+}
+
+#[derive(Debug, Default)]
+pub struct OfxExporter {
+    accounts: BTreeMap<String, BankAccount>,
+    statements: BTreeMap<String, Vec<ExportTransaction>>,
+    mask_account_numbers: bool,
+}
+
+impl Exporter for OfxExporter {
+    fn set_mask_account_numbers(&mut self, mask: bool) {
+        self.mask_account_numbers = mask;
+    }
+
+    fn accounts(&mut self, _out: &mut dyn Write, accounts: &[AccountForDB]) -> Result<(), Error> {
+        for account in accounts {
+            let account = if self.mask_account_numbers {
+                account.masked()
+            } else {
+                account.clone()
+            };
+            self.accounts.insert(
+                account.id.clone(),
+                BankAccount {
+                    bank_id: account.sort_code.replace('-', ""),
+                    account_id: account.account_number,
+                    currency: account.currency,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, _out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+        self.statements
+            .entry(tx.account_id.clone())
+            .or_default()
+            .push(tx.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        writeln!(out, "{HEADER}")?;
+        writeln!(out, "<OFX>")?;
+        writeln!(
+            out,
+            "<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\
+             <DTSERVER>{}</DTSERVER><LANGUAGE>ENG</LANGUAGE></SONRS></SIGNONMSGSRSV1>",
+            timestamp(Utc::now().naive_utc())
+        )?;
+        writeln!(out, "<BANKMSGSRSV1>")?;
+
+        for (account_id, transactions) in &self.statements {
+            let (Some(first), Some(last)) = (transactions.first(), transactions.last()) else {
+                continue;
+            };
+            let account = self.accounts.get(account_id);
+
+            writeln!(
+                out,
+                "<STMTTRNRS><TRNUID>0</TRNUID><STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>"
+            )?;
+            writeln!(
+                out,
+                "<STMTRS><CURDEF>{}</CURDEF>",
+                account.map_or(first.currency.as_str(), |a| a.currency.as_str())
+            )?;
+            writeln!(
+                out,
+                "<BANKACCTFROM><BANKID>{}</BANKID><ACCTID>{}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>",
+                escape(account.map_or("", |a| a.bank_id.as_str())),
+                escape(account.map_or(account_id.as_str(), |a| a.account_id.as_str())),
+            )?;
+            writeln!(
+                out,
+                "<BANKTRANLIST><DTSTART>{}</DTSTART><DTEND>{}</DTEND>",
+                timestamp(first.created),
+                timestamp(last.created)
+            )?;
+
+            for tx in transactions {
+                write_transaction(out, tx)?;
+            }
+
+            writeln!(out, "</BANKTRANLIST></STMTRS></STMTTRNRS>")?;
+        }
+
+        writeln!(out, "</BANKMSGSRSV1>")?;
+        writeln!(out, "</OFX>")?;
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+fn write_transaction(out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+    let kind = if tx.amount < 0 { "DEBIT" } else { "CREDIT" };
+    let name = tx.merchant_name.as_deref().unwrap_or(&tx.description);
+
+    write!(
+        out,
+        "<STMTTRN><TRNTYPE>{kind}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{}</TRNAMT>\
+         <FITID>{}</FITID><NAME>{}</NAME>",
+        timestamp(tx.settled.unwrap_or(tx.created)),
+        decimal(tx.amount, &tx.currency),
+        escape(&tx.id),
+        // NAME is limited to 32 characters by the specification
+        escape(&name.chars().take(32).collect::<String>()),
+    )?;
+    if let Some(notes) = tx.notes.as_deref().filter(|n| !n.is_empty()) {
+        write!(out, "<MEMO>{}</MEMO>", escape(notes))?;
+    }
+    writeln!(out, "</STMTTRN>")?;
+
+    Ok(())
+}
+
+fn timestamp(dt: NaiveDateTime) -> String {
+    dt.format("%Y%m%d%H%M%S").to_string()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_a_statement_per_account() {
+        // Arrange
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            currency: "GBP".to_string(),
+            account_number: "12345678".to_string(),
+            sort_code: "04-00-04".to_string(),
+            ..Default::default()
+        };
+        let tx = ExportTransaction {
+            id: "tx_1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            created: NaiveDateTime::default(),
+            settled: None,
+            amount: -999,
+            currency: "GBP".to_string(),
+            local_amount: -999,
+            local_currency: "GBP".to_string(),
+            description: "M&S".to_string(),
+            notes: None,
+            category_name: "groceries".to_string(),
+            category_label: "🛒 Groceries".to_string(),
+            merchant_name: None,
+            pot_name: None,
+            is_transfer: false,
+            counterparty_name: None,
+            repayment_account_id: None,
+            tags: None,
+            latitude: None,
+            longitude: None,
+            merchant_logo: None,
+        };
+        let mut exporter = OfxExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.accounts(&mut out, &[account]).unwrap();
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.finish(&mut out).unwrap();
+
+        // Assert
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("<BANKID>040004</BANKID><ACCTID>12345678</ACCTID>"));
+        assert!(text.contains("<TRNTYPE>DEBIT</TRNTYPE>"));
+        assert!(text.contains("<TRNAMT>-9.99</TRNAMT>"));
+        assert!(text.contains("<NAME>M&amp;S</NAME>"));
+        assert!(text.trim_end().ends_with("</OFX>"));
+    }
+}
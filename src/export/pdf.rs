@@ -0,0 +1,207 @@
+//! Plain text PDF documents
+//!
+//! Just enough PDF to print lines of monospaced text on A4 pages, such as a
+//! statement, without a PDF library. Text uses the standard Courier fonts,
+//! which every reader has, in the Windows-1252 encoding, so `£` and `€` print
+//! but characters outside it are replaced with `?`.
+
+use std::{fmt::Write as _, io::Write};
+
+use crate::error::AppErrors as Error;
+
+/// Characters that fit across a page
+pub const LINE_WIDTH: usize = 90;
+
+const FONT_SIZE: f64 = 9.0;
+const LEADING: f64 = 11.0;
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 52.0;
+
+/// A line of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub text: String,
+    pub bold: bool,
+}
+
+impl Line {
+    #[must_use]
+    pub fn plain(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            bold: false,
+        }
+    }
+
+    #[must_use]
+    pub fn bold(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            bold: true,
+        }
+    }
+}
+
+/// Write `lines` as a PDF, starting a new page when one is full. Each page
+/// after the first starts with `header` and ends with its page number.
+///
+/// # Errors
+/// Will return an error if the output can't be written.
+pub fn write_pdf(out: &mut dyn Write, lines: &[Line], header: &[Line]) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let per_page = ((PAGE_HEIGHT - 2.0 * MARGIN) / LEADING) as usize - 2;
+    let mut pages: Vec<Vec<&Line>> = vec![Vec::new()];
+    for line in lines {
+        if pages.last().is_some_and(|page| page.len() >= per_page) {
+            pages.push(header.iter().collect());
+        }
+        if let Some(page) = pages.last_mut() {
+            page.push(line);
+        }
+    }
+
+    // objects 1-4 are the catalog, page tree and fonts, then each page and its
+    // contents
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len())
+                .map(|i| format!("{} 0 R", 5 + 2 * i))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+        font("Courier"),
+        font("Courier-Bold"),
+    ];
+    for (i, page) in pages.iter().enumerate() {
+        let contents = contents(page, i + 1, pages.len());
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            6 + 2 * i
+        ));
+        // one byte per character once encoded
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{contents}\nendstream",
+            contents.chars().count()
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", i + 1).bytes());
+        pdf.extend(object.chars().map(win_ansi));
+        pdf.extend(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+    for offset in offsets {
+        pdf.extend(format!("{offset:010} 00000 n \n").bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .bytes(),
+    );
+    out.write_all(&pdf)?;
+
+    Ok(())
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn font(name: &str) -> String {
+    format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>")
+}
+
+// The content stream drawing a page's lines, with its number at the foot
+fn contents(lines: &[&Line], page: usize, pages: usize) -> String {
+    let mut stream = format!(
+        "BT\n{LEADING} TL\n{MARGIN} {} Td\n",
+        PAGE_HEIGHT - MARGIN - FONT_SIZE
+    );
+    let mut bold = None;
+    for line in lines {
+        if bold != Some(line.bold) {
+            let font = if line.bold { "F2" } else { "F1" };
+            let _ = writeln!(stream, "/{font} {FONT_SIZE} Tf");
+            bold = Some(line.bold);
+        }
+        let _ = writeln!(stream, "({}) Tj T*", escape(&line.text));
+    }
+    stream.push_str("ET\n");
+    if pages > 1 {
+        let number = format!("Page {page} of {pages}");
+        let _ = write!(
+            stream,
+            "BT\n/F1 {FONT_SIZE} Tf\n{MARGIN} {} Td\n({number}) Tj\nET",
+            MARGIN / 2.0
+        );
+    }
+    stream
+}
+
+// Escape the characters that end or escape a PDF string
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+}
+
+// The Windows-1252 byte for `c`, or `?` if it has none
+fn win_ansi(c: char) -> u8 {
+    match c {
+        '€' => 0x80,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '–' => 0x96,
+        '—' => 0x97,
+        c => u8::try_from(u32::from(c))
+            .ok()
+            .filter(|b| !(0x80..0xA0).contains(b))
+            .unwrap_or(b'?'),
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|w| w == needle)
+    }
+
+    #[test]
+    fn writes_a_page_per_screenful_with_a_valid_xref() {
+        // Arrange
+        let lines: Vec<Line> = (0..100).map(|i| Line::plain(format!("£{i} (x)"))).collect();
+        let mut out = Vec::new();
+
+        // Act
+        write_pdf(&mut out, &lines, &[Line::bold("Header")]).unwrap();
+
+        // Assert
+        assert!(out.starts_with(b"%PDF-1.4"));
+        assert!(find(&out, b"/Count 2").is_some());
+        assert!(find(&out, b"(\xA399 \\(x\\)) Tj").is_some());
+        assert!(find(&out, b"(Page 2 of 2) Tj").is_some());
+        // each xref entry points at its object
+        let xref = find(&out, b"xref\n").unwrap();
+        let table = String::from_utf8(out[xref..].to_vec()).unwrap();
+        for (i, entry) in table.lines().skip(3).take(8).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(out[offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()));
+        }
+    }
+}
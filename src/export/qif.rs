@@ -0,0 +1,233 @@
+//! Quicken Interchange Format
+//!
+//! Each account is written as an `!Account` block followed by its `!Type:Bank`
+//! transactions, which most desktop finance packages can import directly.
+//! QIF has no currencies, so transactions in a currency other than their
+//! account's are left out with a warning.
+
+use std::{collections::BTreeMap, io::Write};
+
+use chrono_tz::Tz;
+use tracing_log::log::warn;
+
+use super::Exporter;
+use crate::{
+    currency::decimal,
+    error::AppErrors as Error,
+    model::{
+        account::{display_name, AccountForDB},
+        transaction::ExportTransaction,
+    },
+    timezone::local_date,
+};
+
+#[derive(Debug, Default)]
+pub struct QifExporter {
+    current_account: Option<String>,
+    timezone: Option<Tz>,
+    nicknames: BTreeMap<String, String>,
+    // the currency of each account's block
+    currencies: BTreeMap<String, String>,
+}
+
+impl Exporter for QifExporter {
+    fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = Some(timezone);
+    }
+
+    fn set_nicknames(&mut self, nicknames: &BTreeMap<String, String>) {
+        self.nicknames.clone_from(nicknames);
+    }
+
+    fn accounts(&mut self, _out: &mut dyn Write, accounts: &[AccountForDB]) -> Result<(), Error> {
+        for account in accounts {
+            self.currencies
+                .insert(account.id.clone(), account.currency.clone());
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, out: &mut dyn Write, tx: &ExportTransaction) -> Result<(), Error> {
+        let currency = self
+            .currencies
+            .entry(tx.account_id.clone())
+            .or_insert_with(|| tx.currency.clone());
+        if *currency != tx.currency {
+            warn!(
+                "Leaving {} out of the QIF export: it's in {}, its account in {currency}",
+                tx.id, tx.currency
+            );
+            return Ok(());
+        }
+
+        if self.current_account.as_deref() != Some(tx.account_id.as_str()) {
+            writeln!(
+                out,
+                "!Account\nN{}\nTBank\n^\n!Type:Bank",
+                single_line(display_name(
+                    &self.nicknames,
+                    &tx.account_id,
+                    &tx.account_name
+                ))
+            )?;
+            self.current_account = Some(tx.account_id.clone());
+        }
+
+        let payee = tx.merchant_name.as_deref().unwrap_or(&tx.description);
+
+        let date = local_date(tx.created, self.timezone.unwrap_or(Tz::UTC));
+        writeln!(out, "D{}", date.format("%d/%m/%Y"))?;
+        writeln!(out, "T{}", decimal(tx.amount, &tx.currency))?;
+        writeln!(out, "P{}", single_line(payee))?;
+        writeln!(out, "L{}", category(&tx.category_name))?;
+        if let Some(notes) = tx.notes.as_deref().filter(|n| !n.is_empty()) {
+            writeln!(out, "M{}", single_line(notes))?;
+        }
+        writeln!(out, "^")?;
+
+        Ok(())
+    }
+}
+
+// QIF fields are line-delimited
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+// `:` separates subcategories and `/` a class in the category field
+fn category(s: &str) -> String {
+    single_line(s).replace([':', '/'], "-")
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn writes_account_header_once() {
+        // Arrange
+        let tx = ExportTransaction {
+            id: "1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            created: NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            settled: None,
+            amount: -1250,
+            currency: "GBP".to_string(),
+            local_amount: -1250,
+            local_currency: "GBP".to_string(),
+            description: "TESCO STORES".to_string(),
+            notes: Some("weekly\nshop".to_string()),
+            category_name: "groceries".to_string(),
+            category_label: "🛒 Groceries".to_string(),
+            merchant_name: Some("Tesco".to_string()),
+            pot_name: None,
+            is_transfer: false,
+            counterparty_name: None,
+            repayment_account_id: None,
+            tags: None,
+            latitude: None,
+            longitude: None,
+            merchant_logo: None,
+        };
+        let mut exporter = QifExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.emit(&mut out, &tx).unwrap();
+
+        // Assert
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.matches("!Account").count(), 1);
+        assert!(text.contains("D01/05/2024\nT-12.50\nPTesco\nLgroceries\nMweekly shop\n^\n"));
+    }
+
+    #[test]
+    fn dates_are_in_the_display_timezone() {
+        // Arrange
+        let tx = ExportTransaction {
+            id: "1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            // 00:30 BST on 2 May
+            created: NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(23, 30, 0)
+                .unwrap(),
+            settled: None,
+            amount: -1250,
+            currency: "GBP".to_string(),
+            local_amount: -1250,
+            local_currency: "GBP".to_string(),
+            description: "TESCO STORES".to_string(),
+            notes: None,
+            category_name: "groceries".to_string(),
+            category_label: "🛒 Groceries".to_string(),
+            merchant_name: None,
+            pot_name: None,
+            is_transfer: false,
+            counterparty_name: None,
+            repayment_account_id: None,
+            tags: None,
+            latitude: None,
+            longitude: None,
+            merchant_logo: None,
+        };
+        let mut exporter = QifExporter::default();
+        exporter.set_timezone(chrono_tz::Europe::London);
+        let mut out = Vec::new();
+
+        // Act
+        exporter.emit(&mut out, &tx).unwrap();
+
+        // Assert
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("D02/05/2024\n"));
+    }
+
+    #[test]
+    fn names_and_categories_are_sanitised_and_other_currencies_left_out() {
+        // Arrange
+        let tx = ExportTransaction {
+            id: "1".to_string(),
+            account_id: "acc_1".to_string(),
+            account_name: "joint\naccount".to_string(),
+            created: NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+            amount: -1250,
+            currency: "GBP".to_string(),
+            local_amount: -1250,
+            local_currency: "GBP".to_string(),
+            description: "EDF".to_string(),
+            category_name: "bills: gas/electric".to_string(),
+            ..Default::default()
+        };
+        let euros = ExportTransaction {
+            id: "2".to_string(),
+            currency: "EUR".to_string(),
+            ..tx.clone()
+        };
+        let mut exporter = QifExporter::default();
+        let mut out = Vec::new();
+
+        // Act
+        exporter.emit(&mut out, &tx).unwrap();
+        exporter.emit(&mut out, &euros).unwrap();
+
+        // Assert
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("!Account\nNjoint account\n"));
+        assert!(text.contains("Lbills- gas-electric\n"));
+        assert_eq!(text.matches("\nD").count(), 1);
+    }
+}
@@ -0,0 +1,340 @@
+//! Monthly account statements
+//!
+//! A bank statement style document for one account and calendar month: the
+//! opening balance, each transaction in date order with the balance after it,
+//! and the closing balance. Balances are worked back from the latest stored
+//! balance, as for export opening balances, so `update` should have stored
+//! one. Written as CSV, or as a PDF for landlords, visa applications and the
+//! like.
+
+use std::io::Write;
+
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+use super::{
+    opening_balances,
+    pdf::{write_pdf, Line, LINE_WIDTH},
+};
+use crate::{
+    currency,
+    error::AppErrors as Error,
+    locale,
+    model::{account::AccountForDB, transaction::ExportTransaction, DatabasePool},
+    timezone::{local_date, start_of_day},
+};
+
+/// A transaction on a statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementLine {
+    pub date: NaiveDate,
+    pub description: String,
+    pub amount: i64,
+    /// The balance after the transaction
+    pub balance: i64,
+}
+
+/// An account's transactions for one month, in minor units
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub account: AccountForDB,
+    /// The account's nickname, or else its type
+    pub name: String,
+    /// The first day of the month
+    pub month: NaiveDate,
+    pub currency: String,
+    pub opening_balance: i64,
+    pub lines: Vec<StatementLine>,
+}
+
+impl Statement {
+    /// The statement of `account` for the month starting `month`
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read or no balances
+    /// have been stored.
+    pub async fn build(
+        pool: DatabasePool,
+        account: AccountForDB,
+        name: &str,
+        month: NaiveDate,
+        timezone: Tz,
+    ) -> Result<Self, Error> {
+        if pool.balances().read_latest_snapshot().await?.is_none() {
+            return Err(Error::Error(
+                "No balances stored: run `update` first".into(),
+            ));
+        }
+
+        let month = month.with_day(1).unwrap_or(month);
+        let since = start_of_day(month, timezone);
+        let until = (start_of_day(month + Months::new(1), timezone) - Duration::nanoseconds(1))
+            .min(Utc::now().naive_utc());
+        // an account opened during the month starts from nothing
+        let opening_balance = opening_balances(&pool, std::slice::from_ref(&account), since)
+            .await?
+            .into_iter()
+            .find(|b| b.account_id == account.id && b.pot.is_none())
+            .map_or(0, |b| b.balance);
+        let transactions = pool.transactions().read_export_data(since, until).await?;
+
+        let mut balance = opening_balance;
+        let lines = transactions
+            .iter()
+            .filter(|tx| {
+                tx.account_id == account.id && tx.currency == account.currency && tx.amount != 0
+            })
+            .map(|tx| {
+                balance += tx.amount;
+                StatementLine {
+                    date: local_date(tx.created, timezone),
+                    description: description(tx),
+                    amount: tx.amount,
+                    balance,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            currency: account.currency.clone(),
+            name: name.to_string(),
+            account,
+            month,
+            opening_balance,
+            lines,
+        })
+    }
+
+    /// The balance at the end of the month, or now for this month
+    #[must_use]
+    pub fn closing_balance(&self) -> i64 {
+        self.lines
+            .last()
+            .map_or(self.opening_balance, |line| line.balance)
+    }
+
+    /// Money paid in, as a positive number
+    #[must_use]
+    pub fn paid_in(&self) -> i64 {
+        self.lines.iter().map(|l| l.amount.max(0)).sum()
+    }
+
+    /// Money paid out, as a positive number
+    #[must_use]
+    pub fn paid_out(&self) -> i64 {
+        self.lines.iter().map(|l| (-l.amount).max(0)).sum()
+    }
+
+    /// Write the statement as CSV: the opening balance, a row per
+    /// transaction and the closing balance
+    ///
+    /// # Errors
+    /// Will return an error if the output can't be written.
+    pub fn write_csv(&self, out: &mut dyn Write) -> Result<(), Error> {
+        let code = &self.currency;
+        let last_day = self.last_day();
+        let mut writer = csv::Writer::from_writer(out);
+        let mut write = |record: [&str; 4]| {
+            writer
+                .write_record(record)
+                .map_err(|e| Error::Error(e.to_string()))
+        };
+        write(["date", "description", "amount", "balance"])?;
+        write([
+            &self.month.to_string(),
+            "Opening balance",
+            "",
+            &currency::decimal(self.opening_balance, code),
+        ])?;
+        for line in &self.lines {
+            write([
+                &line.date.to_string(),
+                &line.description,
+                &currency::decimal(line.amount, code),
+                &currency::decimal(line.balance, code),
+            ])?;
+        }
+        write([
+            &last_day.to_string(),
+            "Closing balance",
+            "",
+            &currency::decimal(self.closing_balance(), code),
+        ])?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Write the statement as a PDF
+    ///
+    /// # Errors
+    /// Will return an error if a currency is unknown or the output can't be
+    /// written.
+    pub fn write_pdf(&self, out: &mut dyn Write) -> Result<(), Error> {
+        let code = &self.currency;
+        let locale = locale::current();
+        let amount = |minor: i64| currency::display(minor, code);
+        let header = Line::bold(format!(
+            "{:<11}{:<37}{:>14}{:>14}{:>14}",
+            "Date", "Description", "Paid out", "Paid in", "Balance"
+        ));
+        let rule = Line::plain("-".repeat(LINE_WIDTH));
+
+        let mut lines = vec![
+            Line::bold(format!("Statement: {}", self.name)),
+            Line::plain(format!(
+                "{} to {}",
+                locale.date(self.month),
+                locale.date(self.last_day())
+            )),
+            Line::plain(format!(
+                "Account number {}  Sort code {}  Currency {code}",
+                self.account.account_number, self.account.sort_code
+            )),
+            Line::plain(""),
+            Line::plain(format!(
+                "{:<30}{:>14}",
+                "Opening balance",
+                amount(self.opening_balance)?
+            )),
+            Line::plain(format!("{:<30}{:>14}", "Paid in", amount(self.paid_in())?)),
+            Line::plain(format!(
+                "{:<30}{:>14}",
+                "Paid out",
+                amount(self.paid_out())?
+            )),
+            Line::plain(format!(
+                "{:<30}{:>14}",
+                "Closing balance",
+                amount(self.closing_balance())?
+            )),
+            Line::plain(""),
+            header.clone(),
+            rule.clone(),
+            Line::plain(format!(
+                "{:<11}{:<37}{:>14}{:>14}{:>14}",
+                locale.date(self.month),
+                "Opening balance",
+                "",
+                "",
+                amount(self.opening_balance)?
+            )),
+        ];
+        for line in &self.lines {
+            let (paid_out, paid_in) = if line.amount < 0 {
+                (amount(-line.amount)?, String::new())
+            } else {
+                (String::new(), amount(line.amount)?)
+            };
+            lines.push(Line::plain(format!(
+                "{:<11}{:<37}{:>14}{:>14}{:>14}",
+                locale.date(line.date),
+                truncate(&line.description, 36),
+                paid_out,
+                paid_in,
+                amount(line.balance)?
+            )));
+        }
+        lines.push(rule.clone());
+        lines.push(Line::bold(format!(
+            "{:<11}{:<37}{:>14}{:>14}{:>14}",
+            locale.date(self.last_day()),
+            "Closing balance",
+            amount(self.paid_out())?,
+            amount(self.paid_in())?,
+            amount(self.closing_balance())?
+        )));
+
+        write_pdf(out, &lines, &[header, rule])
+    }
+
+    // The last day of the month, or today for this month
+    fn last_day(&self) -> NaiveDate {
+        let end = (self.month + Months::new(1))
+            .pred_opt()
+            .unwrap_or(self.month);
+        end.min(Utc::now().date_naive())
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// What a statement calls a transaction. Pot transfers are described by the
+// pool's templates.
+fn description(tx: &ExportTransaction) -> String {
+    if tx.pot_name.is_some() {
+        return tx.description.clone();
+    }
+    tx.merchant_name
+        .clone()
+        .or_else(|| tx.counterparty_name.clone())
+        .unwrap_or_else(|| tx.description.clone())
+}
+
+// `text` cut to `width` characters
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width - 1).collect();
+    cut.push('~');
+    cut
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::balance::{AccountSnapshot, Balance},
+        tests::test::test_db,
+    };
+
+    #[tokio::test]
+    async fn running_balance_starts_from_the_opening_balance() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET amount = -500, created = '2024-06-05 12:00:00' WHERE id = '1';
+             UPDATE transactions SET amount = 2000, created = '2024-06-10 12:00:00', description = 'Salary'
+             WHERE id = '2'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let snapshot = AccountSnapshot {
+            account_id: "1".to_string(),
+            balance: Balance {
+                balance: 10_000,
+                currency: "GBP".to_string(),
+                ..Default::default()
+            },
+            pots: vec![],
+        };
+        pool.balances()
+            .save_snapshot(Utc::now().naive_utc(), &[snapshot])
+            .await
+            .unwrap();
+        let account = pool.accounts().read_accounts().await.unwrap().remove(0);
+        let june = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+
+        // Act
+        let statement = Statement::build(pool, account, "personal", june, Tz::UTC)
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(statement.opening_balance, 8_500);
+        assert_eq!(
+            statement
+                .lines
+                .iter()
+                .map(|l| (l.description.as_str(), l.balance))
+                .collect::<Vec<_>>(),
+            vec![("", 8_000), ("Salary", 10_000)]
+        );
+        assert_eq!(statement.closing_balance(), 10_000);
+        assert_eq!((statement.paid_in(), statement.paid_out()), (2_000, 500));
+    }
+}
@@ -8,16 +8,45 @@
 
 use chrono::{NaiveDateTime, TimeDelta};
 
+#[cfg(feature = "server")]
+pub mod api;
+#[cfg(feature = "cli")]
+pub mod backup;
+pub mod categorise;
+#[cfg(feature = "cli")]
 pub mod cli;
 pub mod client;
 pub mod configuration;
+pub mod currency;
+#[cfg(feature = "demo")]
+pub mod demo;
+#[cfg(feature = "cli")]
+pub mod encryption;
+pub mod engine;
 pub mod error;
+pub mod export;
+pub mod locale;
+pub mod lock;
 pub mod model;
+pub mod notify;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "auth-server")]
 pub mod routes;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod service;
 pub mod telemetry;
 pub mod tests;
+pub mod timezone;
+#[cfg(feature = "server")]
+pub mod webhook;
+
+pub use engine::{Reporter, SyncEngine};
+pub use export::{Exporter, Registry};
 
 /// Utility function to generate date ranges for paged requests
+#[must_use]
 pub fn date_ranges(
     start: NaiveDateTime,
     end: NaiveDateTime,
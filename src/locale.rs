@@ -0,0 +1,147 @@
+//! Number and date formatting
+//!
+//! `locale` in the configuration, e.g. `de_DE`, sets the decimal point and
+//! thousands separator, where the currency symbol goes and the names of months
+//! in tables, CSV and reports. It defaults to `en_GB`. Export formats such as
+//! beancount, OFX and QIF have fixed conventions of their own and ignore it.
+//!
+//! The locale is set once at startup with [`set`], as amounts are formatted
+//! in too many places to pass it to each one.
+
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use chrono::{NaiveDate, Weekday};
+use pure_rust_locales::locale_match;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppErrors as Error;
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Use `locale` for formatting from now on. Only the first call has an effect.
+pub fn set(locale: Locale) {
+    let _ = LOCALE.set(locale);
+}
+
+/// The locale set at startup, or `en_GB`
+#[must_use]
+pub fn current() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+/// A POSIX locale such as `en_GB` or `de_DE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Locale(chrono::Locale);
+
+impl Locale {
+    /// Separates whole numbers from fractions, e.g. "," in `de_DE`
+    #[must_use]
+    pub fn decimal_point(self) -> &'static str {
+        locale_match!(self.0 => LC_MONETARY::MON_DECIMAL_POINT)
+    }
+
+    /// Groups the thousands of amounts, e.g. "." in `de_DE`
+    #[must_use]
+    pub fn thousands_separator(self) -> &'static str {
+        locale_match!(self.0 => LC_MONETARY::MON_THOUSANDS_SEP)
+    }
+
+    /// Whether the currency symbol comes before the amount, and whether a
+    /// space separates them
+    #[must_use]
+    pub fn symbol_position(self) -> (bool, bool) {
+        (
+            locale_match!(self.0 => LC_MONETARY::P_CS_PRECEDES) == 1,
+            locale_match!(self.0 => LC_MONETARY::P_SEP_BY_SPACE) != 0,
+        )
+    }
+
+    /// A plain decimal such as "-1234.56" with the locale's decimal point
+    #[must_use]
+    pub fn decimal(self, decimal: &str) -> String {
+        decimal.replacen('.', self.decimal_point(), 1)
+    }
+
+    /// A date with the day, month name and year, e.g. "03 Jul 2024"
+    #[must_use]
+    pub fn date(self, date: NaiveDate) -> String {
+        date.format_localized("%d %b %Y", self.0).to_string()
+    }
+
+    /// A date with the day and month name, e.g. "03 Jul"
+    #[must_use]
+    pub fn day_month(self, date: NaiveDate) -> String {
+        date.format_localized("%d %b", self.0).to_string()
+    }
+
+    /// The short name of a weekday, e.g. "Mon"
+    #[must_use]
+    pub fn weekday(self, weekday: Weekday) -> String {
+        // 1 January 2024 was a Monday
+        NaiveDate::from_isoywd_opt(2024, 1, weekday)
+            .map(|date| date.format_localized("%a", self.0).to_string())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self(chrono::Locale::en_GB)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        chrono::Locale::try_from(s.replace('-', "_").as_str())
+            .map(Self)
+            .map_err(|_| Error::Error(format!("Unknown locale '{s}'")))
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Locale> for String {
+    fn from(locale: Locale) -> Self {
+        locale.to_string()
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_posix_and_bcp47_names() {
+        assert_eq!("de_DE".parse::<Locale>().unwrap().to_string(), "de_DE");
+        assert_eq!("fr-FR".parse::<Locale>().unwrap().to_string(), "fr_FR");
+        assert!("xx_XX".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn formats_dates_with_local_month_names() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 7).unwrap();
+        assert_eq!(Locale::default().date(date), "07 Mar 2024");
+        assert_eq!("de_DE".parse::<Locale>().unwrap().date(date), "07 Mär 2024");
+        assert_eq!(
+            "fr_FR".parse::<Locale>().unwrap().day_month(date),
+            "07 mars"
+        );
+    }
+}
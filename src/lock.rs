@@ -0,0 +1,73 @@
+//! Advisory lock for mutating commands
+//!
+//! Commands that write to the database hold an exclusive lock on a file next
+//! to it, so a scheduled update can't interleave with a manual one. The lock
+//! is released by the operating system when the holder exits, even on a crash.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::error::AppErrors as Error;
+
+/// An exclusive lock held for as long as the value is alive
+#[derive(Debug)]
+pub struct DatabaseLock {
+    _file: File,
+}
+
+impl DatabaseLock {
+    /// Acquire the lock for the database at the given path without waiting
+    ///
+    /// # Errors
+    /// Will return `Locked` if another process holds the lock, or an error if
+    /// the lock file can't be opened.
+    pub fn acquire(database_path: &str) -> Result<Self, Error> {
+        let path = lock_path(database_path);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {
+                // record the holder to help anyone investigating a stale lock
+                file.set_len(0)?;
+                writeln!(file, "{}", std::process::id())?;
+                Ok(Self { _file: file })
+            }
+            Err(TryLockError::WouldBlock) => Err(Error::Locked(path.display().to_string())),
+            Err(TryLockError::Error(e)) => Err(Error::FileError(e)),
+        }
+    }
+}
+
+fn lock_path(database_path: &str) -> PathBuf {
+    PathBuf::from(format!("{database_path}.lock"))
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_lock_fails_until_first_is_released() {
+        // Arrange
+        let dir = temp_dir::TempDir::with_prefix("monzo-test").unwrap();
+        let db_path = dir.path().join("db.sqlite");
+        let db_path = db_path.to_str().unwrap();
+
+        // Act
+        let first = DatabaseLock::acquire(db_path).unwrap();
+        let second = DatabaseLock::acquire(db_path);
+        drop(first);
+        let third = DatabaseLock::acquire(db_path);
+
+        // Assert
+        assert!(matches!(second, Err(Error::Locked(_))));
+        assert!(third.is_ok());
+    }
+}
@@ -1,62 +1,864 @@
+use std::process::ExitCode;
+
+use chrono::Datelike;
 use clap::Parser;
 use colored::Colorize;
 
+#[cfg(feature = "server")]
+use monzo_cli::api::ApiState;
+#[cfg(feature = "beancount")]
+use monzo_cli::cli::BeancountCommands;
+#[cfg(feature = "demo")]
+use monzo_cli::cli::DemoCommands;
 use monzo_cli::{
-    cli::{command, Cli, Commands},
-    configuration::get_config,
+    categorise::AutoCategoriser,
+    cli::{
+        alias, command, output, AlertsCommands, AttachmentsCommands, BudgetCommands,
+        CategoriesCommands, Cli, Commands, CompareAgainst, ComparePeriod, DbCommands, ErrorFormat,
+        ImportConflicts, ManualCommands, MerchantsCommands, PotsCommands, ReportCommands,
+        ServiceCommands, TransactionsCommands,
+    },
+    client::{cassette::Cassette, Monzo},
+    configuration::{get_aliases, get_config, ConflictPolicy},
+    encryption::Encryption,
+    engine::{
+        sync::custom_categories, Baseline, BudgetPlan, CategoryAudit, CustomReport, Household,
+        Period, Reconciliation, Schedule, Sweeper, SyncEngine, Verification,
+    },
     error::AppErrors as Error,
-    model::DatabasePool,
+    locale,
+    lock::DatabaseLock,
+    model::{edit::TransactionEdit, filter::Filter, transfer::TransferRules, DatabasePool},
+    notify::Notifiers,
     telemetry::{get_subscriber, init_subscriber},
+    timezone::{local_date, start_of_day},
 };
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
-    let subscriber = get_subscriber("monzo".into(), "error".into(), std::io::stdout);
+async fn main() -> ExitCode {
+    let args = match alias::expand(std::env::args().collect(), &get_aliases()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{} {e}", "ERROR:".red());
+            return ExitCode::from(e.exit_code());
+        }
+    };
+    let cli = Cli::parse_from(args);
+    output::set_quiet(cli.quiet);
+    output::set_pager(!cli.no_pager);
+    output::set_table_view(output::TableView {
+        columns: cli.columns.clone(),
+        sort: cli.sort.clone(),
+    });
+
+    match run(&cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            match cli.error_format {
+                ErrorFormat::Text => eprintln!("{} {e}", "ERROR:".red()),
+                ErrorFormat::Json => eprintln!("{}", e.to_json()),
+            }
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> Result<(), Error> {
+    let subscriber = get_subscriber(
+        "monzo".into(),
+        output::log_level(cli.verbose).into(),
+        std::io::stderr,
+    );
     init_subscriber(subscriber)?;
 
-    let configuration = get_config().expect("Failed to read configuration.");
+    let configuration = get_config()?;
+    locale::set(configuration.locale);
 
     let pool = DatabasePool::new_from_config(configuration.clone()).await?;
-
-    let cli = Cli::parse();
+    let mask_account_numbers = configuration.privacy.mask_account_numbers && !cli.reveal;
 
     match &cli.command {
-        Commands::Balances {} => match command::balances().await {
-            Ok(_) => {}
-            Err(e) => eprintln!("Error: {}", e),
+        Commands::Balances { refresh } => {
+            // only a refresh writes to the database
+            let _lock = refresh
+                .then(|| DatabaseLock::acquire(&configuration.database.database_path))
+                .transpose()?;
+            command::balances(
+                pool.clone(),
+                client(cli)?,
+                *refresh,
+                configuration.base_currency.as_deref(),
+                configuration.timezone,
+                &configuration.nicknames,
+                mask_account_numbers,
+            )
+            .await?;
+            if *refresh {
+                command::alerts_notify(
+                    pool,
+                    client(cli)?,
+                    &configuration.alerts.min_balance,
+                    &Notifiers::from_settings(&configuration.notifications),
+                )
+                .await;
+            }
+        }
+        Commands::Update {
+            all,
+            days,
+            resume,
+            incremental,
+            refresh,
+            profile_sync,
+            format,
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            let end_date = chrono::Utc::now().naive_utc();
+            let start_date = if *all {
+                configuration.start_date
+            } else {
+                let days = days.unwrap_or(configuration.default_days_to_update);
+                end_date - chrono::Duration::days(days)
+            };
+
+            let engine = SyncEngine::new(pool.clone(), client(cli)?)
+                .with_transfer_rules(TransferRules::new(&configuration.transfers)?)
+                .with_category_display(configuration.categories.clone());
+            let engine = match AutoCategoriser::from_settings(&configuration.categoriser) {
+                Some(categoriser) => engine.with_categoriser(categoriser),
+                None => engine,
+            };
+            let engine = if *incremental {
+                engine.incremental()
+            } else {
+                engine
+            };
+            let engine = if *refresh { engine.refresh() } else { engine };
+            let engine = if *profile_sync {
+                engine.profile()
+            } else {
+                engine
+            };
+            command::update(
+                engine,
+                start_date,
+                end_date,
+                *resume,
+                configuration.timezone,
+                &configuration.nicknames,
+                &configuration.pots.descriptions,
+                *format,
+            )
+            .await?;
+            command::alerts_notify(
+                pool.clone(),
+                client(cli)?,
+                &configuration.alerts.min_balance,
+                &Notifiers::from_settings(&configuration.notifications),
+            )
+            .await;
+            if configuration.budgets.feed_alerts && !configuration.budgets.monthly.is_empty() {
+                let tz = configuration.timezone;
+                let plan = BudgetPlan::new(
+                    pool.clone(),
+                    configuration.budgets.clone(),
+                    configuration.base_currency.as_deref().unwrap_or("GBP"),
+                    local_date(configuration.start_date, tz),
+                    tz,
+                );
+                let today = local_date(chrono::Utc::now().naive_utc(), tz);
+                command::budget_feed_alerts(
+                    &plan,
+                    pool,
+                    client(cli)?,
+                    today,
+                    configuration.budgets.feed_account.as_deref(),
+                )
+                .await;
+            }
+        }
+        Commands::Watch {} => {
+            let schedule = Schedule::new(&configuration.watch)?;
+            let sweeper = Sweeper::new(
+                pool.clone(),
+                client(cli)?,
+                configuration.pots.sweeps.clone(),
+            );
+            let engine = SyncEngine::new(pool, client(cli)?)
+                .with_transfer_rules(TransferRules::new(&configuration.transfers)?)
+                .with_category_display(configuration.categories.clone());
+            let engine = match AutoCategoriser::from_settings(&configuration.categoriser) {
+                Some(categoriser) => engine.with_categoriser(categoriser),
+                None => engine,
+            };
+            command::watch(
+                engine,
+                sweeper,
+                schedule,
+                &configuration.database.database_path,
+                configuration.default_days_to_update,
+                configuration.timezone,
+            )
+            .await?;
+        }
+        #[cfg(feature = "server")]
+        Commands::Listen { address } => {
+            command::listen(
+                pool,
+                address,
+                configuration.webhooks.secret.clone(),
+                &configuration.database.database_path,
+                &configuration.server,
+            )
+            .await?;
+        }
+        #[cfg(feature = "server")]
+        Commands::Serve { address } => {
+            let state = ApiState {
+                pool,
+                timezone: configuration.timezone,
+                start_date: configuration.start_date,
+                nicknames: configuration.nicknames.clone(),
+                requests_per_minute: configuration.server.requests_per_minute,
+            };
+            command::serve(state, address, &configuration.server).await?;
+        }
+        Commands::Service { command } => match command {
+            ServiceCommands::Install { interval } => command::service_install(interval)?,
+            ServiceCommands::Uninstall {} => command::service_uninstall()?,
+            ServiceCommands::Status {} => command::service_status()?,
         },
-        Commands::Update { all, days } => {
-            let end_date;
-            let start_date;
-            let config_start_date = configuration.start_date;
-            let config_days_to_update = configuration.default_days_to_update;
-
-            if *all {
-                end_date = chrono::Utc::now().naive_utc();
-                start_date = config_start_date;
-            } else if let Some(days) = days {
-                end_date = chrono::Utc::now().naive_utc();
-                start_date = end_date - chrono::Duration::days(*days);
+        #[cfg(feature = "auth-server")]
+        Commands::Auth {} => {
+            command::auth().await?;
+            if !output::is_quiet() {
+                println!("Auth completed");
+            }
+        }
+        Commands::History { limit } => {
+            command::history(pool, *limit, configuration.timezone).await?;
+        }
+        Commands::Transactions {
+            command:
+                TransactionsCommands::List {
+                    events,
+                    since,
+                    until,
+                    limit,
+                    after,
+                },
+        } => {
+            let tz = configuration.timezone;
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            let since = since.map_or(until - chrono::Duration::days(30), |d| start_of_day(d, tz));
+
+            if *events {
+                command::card_events_list(pool, since, until, tz, &configuration.nicknames).await?;
             } else {
-                end_date = chrono::Utc::now().naive_utc();
-                start_date = end_date - chrono::Duration::days(config_days_to_update);
+                command::transactions_list(
+                    pool,
+                    since,
+                    until,
+                    *limit,
+                    after.as_deref(),
+                    tz,
+                    &configuration.nicknames,
+                )
+                .await?;
             }
+        }
+        Commands::Transactions {
+            command:
+                TransactionsCommands::Set {
+                    filter,
+                    category,
+                    tags,
+                    yes,
+                },
+        } => {
+            let filter: Filter = filter.parse()?;
+            let edit = TransactionEdit::new(category.clone(), tags.clone())?;
+            let _lock = yes
+                .then(|| DatabaseLock::acquire(&configuration.database.database_path))
+                .transpose()?;
+            command::transactions_set(
+                pool,
+                &filter,
+                &edit,
+                *yes,
+                &TransferRules::new(&configuration.transfers)?,
+                configuration.timezone,
+                &configuration.nicknames,
+            )
+            .await?;
+        }
+        Commands::Transactions {
+            command: TransactionsCommands::Categorise {},
+        } => {
+            let categoriser = AutoCategoriser::from_settings(&configuration.categoriser)
+                .ok_or_else(|| Error::Error("No [categoriser] url is configured".into()))?;
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            command::transactions_categorise(pool, &categoriser).await?;
+        }
+        Commands::Query { sql, format } => command::query(pool, sql, *format).await?,
+        #[cfg(feature = "beancount")]
+        Commands::Bq {
+            query,
+            since,
+            until,
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            command::bq(
+                pool,
+                query,
+                since,
+                until,
+                tz,
+                &configuration.nicknames,
+                &configuration.filters,
+            )
+            .await?;
+        }
+        #[cfg(feature = "beancount")]
+        Commands::Beancount {
+            command:
+                BeancountCommands::Ledger {
+                    output,
+                    since,
+                    until,
+                    accounts,
+                },
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            command::beancount_ledger(
+                pool,
+                since,
+                until,
+                output.as_deref(),
+                tz,
+                &configuration.nicknames,
+                accounts,
+                &configuration.calendar,
+                &configuration.filters,
+                mask_account_numbers,
+            )
+            .await?;
+        }
+        #[cfg(feature = "beancount")]
+        Commands::Beancount {
+            command: BeancountCommands::SyncBack { ledger, dry_run },
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            command::beancount_sync_back(pool, ledger, *dry_run).await?;
+        }
+        Commands::Networth {} => {
+            command::networth(pool, client(cli)?, configuration.base_currency.as_deref()).await?;
+        }
+        Commands::Accounts { refresh, json } => {
+            // only a refresh writes to the database
+            let _lock = refresh
+                .then(|| DatabaseLock::acquire(&configuration.database.database_path))
+                .transpose()?;
+            command::accounts(
+                pool,
+                client(cli)?,
+                *refresh,
+                *json,
+                mask_account_numbers,
+                configuration.timezone,
+                &configuration.nicknames,
+            )
+            .await?;
+        }
+        Commands::Alerts {
+            command: AlertsCommands::Check {},
+        } => {
+            command::alerts_check(
+                pool,
+                client(cli)?,
+                &configuration.alerts.min_balance,
+                &Notifiers::from_settings(&configuration.notifications),
+            )
+            .await?;
+        }
+        Commands::Digest { period } => {
+            command::digest(
+                pool,
+                *period,
+                &Notifiers::from_settings(&configuration.notifications),
+            )
+            .await?;
+        }
+        Commands::Compare {
+            period,
+            against,
+            date,
+        } => {
+            let tz = configuration.timezone;
+            let period = match period {
+                ComparePeriod::Week => Period::Week,
+                ComparePeriod::Month => Period::Month,
+            };
+            let baseline = match against {
+                CompareAgainst::Previous => Baseline::Previous,
+                CompareAgainst::Year => Baseline::Year,
+            };
+            let date = date.unwrap_or_else(|| local_date(chrono::Utc::now().naive_utc(), tz));
+            command::compare(pool, period, baseline, date, tz).await?;
+        }
+        Commands::Project { days } => {
+            let tz = configuration.timezone;
+            let today = local_date(chrono::Utc::now().naive_utc(), tz);
+            command::project(pool, today, *days, tz, &configuration.nicknames).await?;
+        }
+        Commands::Today { days, json } => {
+            let tz = configuration.timezone;
+            let plan = BudgetPlan::new(
+                pool.clone(),
+                configuration.budgets.clone(),
+                configuration.base_currency.as_deref().unwrap_or("GBP"),
+                local_date(configuration.start_date, tz),
+                tz,
+            );
+            let today = local_date(chrono::Utc::now().naive_utc(), tz);
+            command::today(pool, &plan, today, *days, *json, tz).await?;
+        }
+        Commands::Statement {
+            account,
+            month,
+            pdf,
+            csv: _,
+            output,
+        } => {
+            let tz = configuration.timezone;
+            let this_month = local_date(chrono::Utc::now().naive_utc(), tz).with_day(1);
+            let month = month
+                .or_else(|| this_month.and_then(|m| m.checked_sub_months(chrono::Months::new(1))))
+                .ok_or_else(|| Error::Error("Can't work out last month".into()))?;
+            command::statement(
+                pool,
+                account.as_deref(),
+                month,
+                *pdf,
+                output.as_deref(),
+                mask_account_numbers,
+                tz,
+                &configuration.nicknames,
+            )
+            .await?;
+        }
+        Commands::Budget { command } => {
+            let tz = configuration.timezone;
+            let plan = BudgetPlan::new(
+                pool.clone(),
+                configuration.budgets.clone(),
+                configuration.base_currency.as_deref().unwrap_or("GBP"),
+                local_date(configuration.start_date, tz),
+                tz,
+            );
+            match command {
+                BudgetCommands::Status { month } => {
+                    let month =
+                        month.unwrap_or_else(|| local_date(chrono::Utc::now().naive_utc(), tz));
+                    command::budget_status(&plan, month).await?;
+                }
+                BudgetCommands::Envelopes { top_up } => {
+                    // topping up refreshes the stored balances first
+                    let _lock = top_up
+                        .then(|| DatabaseLock::acquire(&configuration.database.database_path))
+                        .transpose()?;
+                    let today = local_date(chrono::Utc::now().naive_utc(), tz);
+                    command::budget_envelopes(&plan, pool, client(cli)?, today, *top_up).await?;
+                }
+            }
+        }
+        Commands::Pots {
+            command: PotsCommands::Sweep { dry_run },
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            let sweeper = Sweeper::new(pool, client(cli)?, configuration.pots.sweeps.clone());
+            let today = local_date(chrono::Utc::now().naive_utc(), configuration.timezone);
+            command::pots_sweep(&sweeper, today, *dry_run).await?;
+        }
+        Commands::Attachments {
+            command: AttachmentsCommands::Download { dir },
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            command::attachments_download(pool, dir, configuration.timezone).await?;
+        }
+        Commands::Merchants {
+            command: MerchantsCommands::FetchLogos { dir },
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            command::merchants_fetch_logos(pool, dir).await?;
+        }
+        Commands::Categories {
+            command: CategoriesCommands::Audit { fix },
+        } => {
+            let _lock = fix
+                .then(|| DatabaseLock::acquire(&configuration.database.database_path))
+                .transpose()?;
+            let custom_categories = custom_categories()?;
+            let audit = CategoryAudit::run(
+                &pool,
+                &custom_categories,
+                &configuration.categories,
+                &configuration.budgets,
+                &configuration.transfers,
+            )
+            .await?;
+            command::categories_audit(pool, &audit, *fix).await?;
+        }
+        Commands::Report {
+            command: Some(ReportCommands::Run { name }),
+            ..
+        } => {
+            let definition = configuration.reports.get(name).ok_or_else(|| {
+                let names: Vec<&str> = configuration.reports.keys().map(String::as_str).collect();
+                Error::Error(format!(
+                    "No report named '{name}' under [reports] (defined: {})",
+                    if names.is_empty() {
+                        "none".to_string()
+                    } else {
+                        names.join(", ")
+                    }
+                ))
+            })?;
+            let tz = configuration.timezone;
+            let report = CustomReport::run(
+                pool,
+                name,
+                definition,
+                local_date(configuration.start_date, tz),
+                local_date(chrono::Utc::now().naive_utc(), tz),
+                tz,
+                &configuration.nicknames,
+            )
+            .await?;
+            command::report_run(&report, definition.format)?;
+        }
+        Commands::Report {
+            command: Some(ReportCommands::People { since, until }),
+            ..
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            command::report_people(pool, since, until).await?;
+        }
+        Commands::Report {
+            command: Some(ReportCommands::Savings { since, until }),
+            ..
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            command::report_savings(pool, &configuration.savings, since, until, tz).await?;
+        }
+        Commands::Report {
+            command:
+                Some(ReportCommands::Trips {
+                    since,
+                    until,
+                    gap,
+                    tag,
+                }),
+            ..
+        } => {
+            let _lock = tag
+                .then(|| DatabaseLock::acquire(&configuration.database.database_path))
+                .transpose()?;
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            command::report_trips(pool, since, until, *gap, *tag, tz).await?;
+        }
+        Commands::Report {
+            command: Some(ReportCommands::Drift { since, until }),
+            ..
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            command::report_drift(pool, since, until, tz, &configuration.nicknames).await?;
+        }
+        Commands::Report {
+            command: Some(ReportCommands::Heatmap { since, until, png }),
+            ..
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+            command::report_heatmap(
+                pool,
+                since,
+                until,
+                configuration.base_currency.as_deref().unwrap_or("GBP"),
+                tz,
+                png.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Report {
+            command: None,
+            profiles,
+            since,
+            until,
+            beancount,
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
 
-            match command::update(pool, start_date, end_date).await {
-                Ok(_) => return Ok(()),
-                Err(e) => return Err(Error::Error(e.to_string())),
+            let household = Household::open(
+                &configuration.profiles,
+                profiles,
+                configuration.database.max_connections,
+            )
+            .await?
+            .with_min_amount(pool.min_amount());
+            command::report(
+                &household,
+                since,
+                until,
+                beancount.as_deref(),
+                tz,
+                &configuration.nicknames,
+            )
+            .await?;
+        }
+        Commands::Manual { command } => {
+            // listing doesn't write
+            let _lock = (!matches!(command, ManualCommands::List {}))
+                .then(|| DatabaseLock::acquire(&configuration.database.database_path))
+                .transpose()?;
+            match command {
+                ManualCommands::AddAccount {
+                    name,
+                    kind,
+                    currency,
+                    institution,
+                } => {
+                    command::manual_add_account(pool, name, kind, currency, institution.as_deref())
+                        .await?;
+                }
+                ManualCommands::AddValuation {
+                    account,
+                    amount,
+                    date,
+                } => {
+                    let date = date.unwrap_or_else(|| {
+                        local_date(chrono::Utc::now().naive_utc(), configuration.timezone)
+                    });
+                    command::manual_add_valuation(pool, account, amount, date).await?;
+                }
+                ManualCommands::List {} => command::manual_list(pool).await?,
+            }
+        }
+        Commands::Db {
+            command: DbCommands::Seed { fixture },
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            let rules = TransferRules::new(&configuration.transfers)?;
+            command::db_seed(pool, fixture, &rules).await?;
+        }
+        Commands::Db {
+            command: DbCommands::Prune {},
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            command::db_prune(pool, &configuration.retention).await?;
+        }
+        Commands::Db {
+            command: DbCommands::Classify {},
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            let rules = TransferRules::new(&configuration.transfers)?;
+            command::db_classify(pool, &rules).await?;
+        }
+        #[cfg(feature = "postgres")]
+        Commands::Db {
+            command: DbCommands::MigrateTo { target },
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            command::db_migrate_to(pool, target).await?;
+        }
+        #[cfg(feature = "demo")]
+        Commands::Demo {
+            command: DemoCommands::Seed { seed, years },
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            command::demo_seed(pool, *seed, *years).await?;
+        }
+        Commands::Reconcile { csv } => {
+            let file = std::fs::File::open(csv)
+                .map_err(|e| Error::Error(format!("Can't open '{}': {e}", csv.display())))?;
+            let reconciliation = Reconciliation::run(pool, file, configuration.timezone).await?;
+            command::reconcile(&reconciliation)?;
+        }
+        Commands::Verify {} => {
+            let tz = configuration.timezone;
+            let verification = Verification::run(pool.clone()).await?;
+            let today = local_date(chrono::Utc::now().naive_utc(), tz);
+            command::verify(pool, &verification, today, tz, &configuration.nicknames).await?;
+        }
+        Commands::Import {
+            csv,
+            account,
+            conflicts,
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            let policy =
+                conflicts.map_or(
+                    configuration.import.conflicts,
+                    |conflicts| match conflicts {
+                        ImportConflicts::Ask => ConflictPolicy::Ask,
+                        ImportConflicts::KeepApi => ConflictPolicy::KeepApi,
+                        ImportConflicts::KeepCsv => ConflictPolicy::KeepCsv,
+                        ImportConflicts::KeepBoth => ConflictPolicy::KeepBoth,
+                    },
+                );
+            command::import(pool, csv, account, policy, configuration.timezone).await?;
+        }
+        Commands::Export {
+            format,
+            output,
+            since,
+            until,
+            accounts,
+            diff,
+            encrypt,
+            gpg,
+        } => {
+            let tz = configuration.timezone;
+            let since = since.map_or(configuration.start_date, |d| start_of_day(d, tz));
+            let until = until.map_or(chrono::Utc::now().naive_utc(), |d| {
+                start_of_day(d + chrono::Duration::days(1), tz)
+            });
+
+            match output {
+                Some(output) if *diff => {
+                    command::export_diff(
+                        pool,
+                        format,
+                        since,
+                        until,
+                        output,
+                        tz,
+                        &configuration.nicknames,
+                        accounts,
+                        &configuration.calendar,
+                        &configuration.filters,
+                        mask_account_numbers,
+                    )
+                    .await?;
+                }
+                _ => {
+                    let encryption = Encryption::from_options(encrypt.as_deref(), gpg.as_deref())?;
+                    command::export(
+                        pool,
+                        format,
+                        since,
+                        until,
+                        output.as_deref(),
+                        tz,
+                        &configuration.nicknames,
+                        accounts,
+                        &configuration.calendar,
+                        &configuration.filters,
+                        mask_account_numbers,
+                        encryption.as_ref(),
+                    )
+                    .await?;
+                }
+            }
+        }
+        Commands::Reset {} => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            match command::reset().await {
+                Ok(_) => {
+                    if !output::is_quiet() {
+                        println!("{}", "Database reset complete".green());
+                    }
+                }
+                Err(Error::AbortError) => println!("{}", "Database reset aborted".yellow()),
+                Err(e) => return Err(e),
+            }
+        }
+        Commands::Backup {
+            to,
+            keep,
+            encrypt,
+            gpg,
+        } => {
+            let encryption = Encryption::from_options(encrypt.as_deref(), gpg.as_deref())?;
+            command::backup(
+                pool,
+                to.as_deref(),
+                *keep,
+                encryption.as_ref(),
+                &configuration.backup,
+            )
+            .await?;
+        }
+        Commands::Restore {
+            from,
+            yes,
+            identity,
+        } => {
+            let _lock = DatabaseLock::acquire(&configuration.database.database_path)?;
+            match command::restore(
+                pool,
+                from,
+                *yes,
+                identity.as_deref(),
+                &configuration.database.database_path,
+                configuration.database.max_connections,
+                &configuration.backup,
+            )
+            .await
+            {
+                Ok(_) => {}
+                Err(Error::AbortError) => println!("{}", "Restore aborted".yellow()),
+                Err(e) => return Err(e),
             }
         }
-        Commands::Auth {} => match command::auth().await {
-            Ok(_) => println!("Auth completed"),
-            Err(e) => eprintln!("Error: {}", e),
-        },
-        Commands::Reset {} => match command::reset().await {
-            Ok(_) => println!("{}", "Database reset complete".green()),
-            Err(Error::AbortError) => println!("{}", "Database reset aborted".yellow()),
-            Err(e) => eprintln!("{} Failed to reset the database {}", "ERROR:".red(), e),
-        },
     }
 
     Ok(())
 }
+
+// Create the API client, recording or replaying responses if requested
+fn client(cli: &Cli) -> Result<Monzo, Error> {
+    let mut monzo = Monzo::new()?;
+    if cli.reveal {
+        monzo = monzo.with_masking(false);
+    }
+
+    Ok(match (&cli.record, &cli.replay) {
+        (Some(dir), _) => monzo.with_cassette(Cassette::Record(dir.clone())),
+        (None, Some(dir)) => monzo.with_cassette(Cassette::Replay(dir.clone())),
+        (None, None) => monzo,
+    })
+}
@@ -1,8 +1,12 @@
 //! Models for the account endpoint
 
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
 use async_trait::async_trait;
 use chrono::{DateTime, NaiveDateTime, Utc};
-use serde::Deserialize;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use sqlx::{prelude::FromRow, Pool, Sqlite};
 use tracing_log::log::{error, info};
 
@@ -30,7 +34,7 @@ pub struct AccountResponse {
 }
 
 /// Represents an Account for database operations
-#[derive(Deserialize, Debug, Default, FromRow)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, FromRow)]
 pub struct AccountForDB {
     pub id: String,
     pub closed: bool,
@@ -43,6 +47,25 @@ pub struct AccountForDB {
     pub sort_code: String,
 }
 
+impl AccountForDB {
+    /// The name shown for the account: its nickname, or else its type
+    #[must_use]
+    pub fn name<'a>(&'a self, nicknames: &'a BTreeMap<String, String>) -> &'a str {
+        display_name(nicknames, &self.id, &self.owner_type)
+    }
+
+    /// A copy with all but the last digits of the account number and sort
+    /// code hidden, e.g. `****5678` and `**-**-56`
+    #[must_use]
+    pub fn masked(&self) -> Self {
+        Self {
+            account_number: mask(&self.account_number, 4),
+            sort_code: mask(&self.sort_code, 2),
+            ..self.clone()
+        }
+    }
+}
+
 impl From<AccountResponse> for AccountForDB {
     fn from(acc: AccountResponse) -> Self {
         Self {
@@ -65,6 +88,7 @@ impl From<AccountResponse> for AccountForDB {
 pub trait Service {
     async fn save_account(&self, acc_fc: &AccountForDB) -> Result<(), Error>;
     async fn read_accounts(&self) -> Result<Vec<AccountForDB>, Error>;
+    async fn update_account(&self, acc_fc: &AccountForDB) -> Result<(), Error>;
 }
 
 #[derive(Debug, Clone)]
@@ -160,6 +184,89 @@ impl Service for SqliteAccountService {
             }
         }
     }
+
+    /// Update the details Monzo can change, e.g. when an account is closed
+    #[tracing::instrument(
+        name = "Updating account",
+        skip(self, acc_fc),
+        fields(id = %acc_fc.id)
+    )]
+    async fn update_account(&self, acc_fc: &AccountForDB) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            r"
+                UPDATE accounts
+                SET closed = $2,
+                    description = $3,
+                    account_number = $4,
+                    sort_code = $5
+                WHERE id = $1
+            ",
+            acc_fc.id,
+            acc_fc.closed,
+            acc_fc.description,
+            acc_fc.account_number,
+            acc_fc.sort_code,
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+/// The nickname of account `account_id`, or `fallback` if it has none
+#[must_use]
+pub fn display_name<'a>(
+    nicknames: &'a BTreeMap<String, String>,
+    account_id: &str,
+    fallback: &'a str,
+) -> &'a str {
+    nicknames.get(account_id).map_or(fallback, String::as_str)
+}
+
+/// `text`, e.g. an API response, with the values of its `account_number` and
+/// `sort_code` fields masked as by [`AccountForDB::masked`]
+#[must_use]
+pub fn redact_account_numbers(text: &str) -> String {
+    static FIELD: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#""(account_number|sort_code)"(\s*:\s*)"([^"]*)""#).expect("valid regex")
+    });
+
+    FIELD
+        .replace_all(text, |caps: &Captures| {
+            let visible = if &caps[1] == "account_number" { 4 } else { 2 };
+            format!(
+                "\"{}\"{}\"{}\"",
+                &caps[1],
+                &caps[2],
+                mask(&caps[3], visible)
+            )
+        })
+        .into_owned()
+}
+
+// Replace all but the last `visible` digits with `*`, keeping separators
+fn mask(value: &str, visible: usize) -> String {
+    let digits = value.chars().filter(char::is_ascii_digit).count();
+    let mut seen = 0;
+    value
+        .chars()
+        .map(|c| {
+            if !c.is_ascii_digit() {
+                return c;
+            }
+            seen += 1;
+            if seen + visible > digits {
+                c
+            } else {
+                '*'
+            }
+        })
+        .collect()
 }
 
 // Check if an account is a duplicate
@@ -211,4 +318,59 @@ mod tests {
         // Assert
         assert_eq!(result.len(), 1);
     }
+
+    #[tokio::test]
+    async fn update_account() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteAccountService::new(pool);
+        let mut account = service.read_accounts().await.unwrap().remove(0);
+        account.closed = true;
+
+        // Act
+        service.update_account(&account).await.unwrap();
+
+        // Assert
+        assert!(service.read_accounts().await.unwrap()[0].closed);
+    }
+
+    #[test]
+    fn name_prefers_the_nickname() {
+        let account = AccountForDB {
+            id: "acc_1".to_string(),
+            owner_type: "personal".to_string(),
+            ..Default::default()
+        };
+        let nicknames = BTreeMap::from([("acc_1".to_string(), "Everyday".to_string())]);
+
+        assert_eq!(account.name(&nicknames), "Everyday");
+        assert_eq!(account.name(&BTreeMap::new()), "personal");
+    }
+
+    #[test]
+    fn masked_keeps_the_last_digits() {
+        let account = AccountForDB {
+            account_number: "12345678".to_string(),
+            sort_code: "12-34-56".to_string(),
+            ..Default::default()
+        };
+
+        let masked = account.masked();
+
+        assert_eq!(masked.account_number, "****5678");
+        assert_eq!(masked.sort_code, "**-**-56");
+    }
+
+    #[test]
+    fn account_numbers_in_responses_are_redacted() {
+        let body =
+            r#"{"accounts":[{"id":"acc_1","account_number": "12345678","sort_code":"040004"}]}"#;
+
+        let redacted = redact_account_numbers(body);
+
+        assert_eq!(
+            redacted,
+            r#"{"accounts":[{"id":"acc_1","account_number": "****5678","sort_code":"****04"}]}"#
+        );
+    }
 }
@@ -0,0 +1,215 @@
+//! Models for transaction attachments
+//!
+//! Monzo lists the receipts and other files attached to a transaction with
+//! the transaction. They are stored on every sync, and the local copy made by
+//! `attachments download` is recorded with its content hash so the same file
+//! is never saved twice.
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use tracing_log::log::info;
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// Represents an attachment in the Monzo API
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct AttachmentResponse {
+    pub id: String,
+    pub file_url: String,
+    /// MIME type, e.g. `image/jpeg`
+    pub file_type: String,
+    pub created: DateTime<Utc>,
+}
+
+/// A stored attachment with the date of its transaction
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub id: String,
+    pub transaction_id: String,
+    pub transaction_created: NaiveDateTime,
+    pub url: String,
+    pub file_type: String,
+    pub created: NaiveDateTime,
+    /// Where the file was downloaded to
+    pub path: Option<String>,
+    /// Hex SHA-256 of the downloaded file
+    pub sha256: Option<String>,
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn save_attachments(
+        &self,
+        transaction_id: &str,
+        attachments: &[AttachmentResponse],
+    ) -> Result<usize, Error>;
+    async fn read_attachments(&self) -> Result<Vec<Attachment>, Error>;
+    async fn find_download(&self, sha256: &str) -> Result<Option<String>, Error>;
+    async fn set_download(&self, id: &str, path: &str, sha256: &str) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteAttachmentService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteAttachmentService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteAttachmentService {
+    /// Store the attachments of a transaction that aren't stored yet,
+    /// returning the number stored
+    #[tracing::instrument(name = "Save attachments", skip(self, attachments))]
+    async fn save_attachments(
+        &self,
+        transaction_id: &str,
+        attachments: &[AttachmentResponse],
+    ) -> Result<usize, Error> {
+        let db = self.pool.db();
+        let mut saved = 0;
+
+        for attachment in attachments {
+            let created = attachment.created.naive_utc();
+            saved += sqlx::query!(
+                r"
+                    INSERT OR IGNORE INTO attachments (id, transaction_id, url, file_type, created)
+                    VALUES ($1, $2, $3, $4, $5)
+                ",
+                attachment.id,
+                transaction_id,
+                attachment.file_url,
+                attachment.file_type,
+                created,
+            )
+            .execute(db)
+            .await?
+            .rows_affected();
+        }
+        if saved > 0 {
+            info!("Saved {saved} attachments for transaction: {transaction_id}");
+        }
+
+        Ok(usize::try_from(saved).unwrap_or(usize::MAX))
+    }
+
+    /// All stored attachments, grouped by transaction in the order attached
+    #[tracing::instrument(name = "Read attachments", skip(self))]
+    async fn read_attachments(&self) -> Result<Vec<Attachment>, Error> {
+        let db = self.pool.db();
+
+        let attachments = sqlx::query_as!(
+            Attachment,
+            r"
+                SELECT
+                    a.id,
+                    a.transaction_id,
+                    t.created AS transaction_created,
+                    a.url,
+                    a.file_type,
+                    a.created,
+                    a.path,
+                    a.sha256
+                FROM attachments a
+                JOIN transactions t ON a.transaction_id = t.id
+                ORDER BY t.created, a.transaction_id, a.created, a.id
+            "
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(attachments)
+    }
+
+    /// Where a file with the given hash was downloaded to, if any
+    #[tracing::instrument(name = "Find attachment download", skip(self))]
+    async fn find_download(&self, sha256: &str) -> Result<Option<String>, Error> {
+        let db = self.pool.db();
+
+        let path = sqlx::query_scalar!(
+            r#"
+                SELECT path AS "path!"
+                FROM attachments
+                WHERE sha256 = $1 AND path IS NOT NULL
+                LIMIT 1
+            "#,
+            sha256
+        )
+        .fetch_optional(db)
+        .await?;
+
+        Ok(path)
+    }
+
+    /// Record where an attachment was downloaded to
+    #[tracing::instrument(name = "Set attachment download", skip(self))]
+    async fn set_download(&self, id: &str, path: &str, sha256: &str) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            "UPDATE attachments SET path = $2, sha256 = $3 WHERE id = $1",
+            id,
+            path,
+            sha256
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn saves_attachments_once_and_records_downloads() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteAttachmentService::new(pool);
+        let receipt = AttachmentResponse {
+            id: "attach_1".to_string(),
+            file_url: "https://example.com/receipt.jpg".to_string(),
+            file_type: "image/jpeg".to_string(),
+            created: Utc.with_ymd_and_hms(2024, 5, 2, 12, 0, 0).unwrap(),
+        };
+
+        // Act
+        let first = service
+            .save_attachments("1", std::slice::from_ref(&receipt))
+            .await
+            .unwrap();
+        let second = service.save_attachments("1", &[receipt]).await.unwrap();
+        service
+            .set_download("attach_1", "attachments/1970/01/1-1.jpg", "abc")
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!((first, second), (1, 0));
+        let attachments = service.read_attachments().await.unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].transaction_id, "1");
+        assert_eq!(
+            service.find_download("abc").await.unwrap().as_deref(),
+            Some("attachments/1970/01/1-1.jpg")
+        );
+        assert_eq!(service.find_download("def").await.unwrap(), None);
+    }
+}
@@ -1,6 +1,14 @@
-use serde::Deserialize;
+//! Models for the balance endpoint and stored balance snapshots
 
-#[derive(Deserialize, Debug, Default)]
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tracing_log::log::info;
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct Balance {
     pub balance: i64,
     pub total_balance: i64,
@@ -8,11 +16,305 @@ pub struct Balance {
     pub spend_today: i64,
 }
 
+/// The balance of a pot when a snapshot was taken
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct PotBalance {
+    pub pot_id: String,
+    pub name: String,
+    pub balance: i64,
+    pub currency: String,
+}
+
+/// The balance of an account and its open pots when a snapshot was taken
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct AccountSnapshot {
+    pub account_id: String,
+    pub balance: Balance,
+    pub pots: Vec<PotBalance>,
+}
+
+/// An account's balance in one snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredBalance {
+    pub taken: NaiveDateTime,
+    pub account_id: String,
+    pub balance: i64,
+    pub currency: String,
+}
+
+/// Balances of every account at one point in time
+#[derive(Debug, Default, Clone)]
+pub struct BalanceSnapshot {
+    pub id: i64,
+    pub taken: NaiveDateTime,
+    pub accounts: Vec<AccountSnapshot>,
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn save_snapshot(
+        &self,
+        taken: NaiveDateTime,
+        accounts: &[AccountSnapshot],
+    ) -> Result<i64, Error>;
+    async fn read_latest_snapshot(&self) -> Result<Option<BalanceSnapshot>, Error>;
+    async fn read_snapshots(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<BalanceSnapshot>, Error>;
+    async fn read_account_balances(&self) -> Result<Vec<StoredBalance>, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteBalanceService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteBalanceService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+
+    // The accounts and pots of the snapshot `id`, in the order saved
+    async fn read_snapshot(&self, id: i64, taken: NaiveDateTime) -> Result<BalanceSnapshot, Error> {
+        let db = self.pool.db();
+
+        let accounts = sqlx::query!(
+            r"
+                SELECT account_id, balance, total_balance, currency, spend_today
+                FROM account_balances
+                WHERE snapshot_id = $1
+                ORDER BY rowid
+            ",
+            id,
+        )
+        .fetch_all(db)
+        .await?;
+
+        let pots = sqlx::query_as!(
+            PotRow,
+            r"
+                SELECT account_id, pot_id, name, balance, currency
+                FROM pot_balances
+                WHERE snapshot_id = $1
+                ORDER BY rowid
+            ",
+            id,
+        )
+        .fetch_all(db)
+        .await?;
+
+        let accounts = accounts
+            .into_iter()
+            .map(|row| {
+                let balance = Balance {
+                    balance: row.balance,
+                    total_balance: row.total_balance,
+                    currency: row.currency,
+                    spend_today: row.spend_today,
+                };
+                (row.account_id, balance)
+            })
+            .collect();
+
+        Ok(snapshot(id, taken, accounts, &pots))
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteBalanceService {
+    /// Record the balances of the given accounts, returning the snapshot id
+    #[tracing::instrument(name = "Save balance snapshot", skip(self, accounts))]
+    async fn save_snapshot(
+        &self,
+        taken: NaiveDateTime,
+        accounts: &[AccountSnapshot],
+    ) -> Result<i64, Error> {
+        let mut tx = self.pool.db().begin().await?;
+
+        let snapshot_id = sqlx::query!("INSERT INTO balance_snapshots (taken) VALUES ($1)", taken)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+
+        for account in accounts {
+            sqlx::query!(
+                r"
+                    INSERT INTO account_balances (
+                        snapshot_id,
+                        account_id,
+                        balance,
+                        total_balance,
+                        currency,
+                        spend_today
+                    )
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                ",
+                snapshot_id,
+                account.account_id,
+                account.balance.balance,
+                account.balance.total_balance,
+                account.balance.currency,
+                account.balance.spend_today,
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            for pot in &account.pots {
+                sqlx::query!(
+                    r"
+                        INSERT INTO pot_balances (
+                            snapshot_id,
+                            account_id,
+                            pot_id,
+                            name,
+                            balance,
+                            currency
+                        )
+                        VALUES ($1, $2, $3, $4, $5, $6)
+                    ",
+                    snapshot_id,
+                    account.account_id,
+                    pot.pot_id,
+                    pot.name,
+                    pot.balance,
+                    pot.currency,
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        info!("Saved balance snapshot: {snapshot_id}");
+
+        Ok(snapshot_id)
+    }
+
+    /// The most recent snapshot, with accounts and pots in the order saved
+    #[tracing::instrument(name = "Read latest balance snapshot", skip(self))]
+    async fn read_latest_snapshot(&self) -> Result<Option<BalanceSnapshot>, Error> {
+        let db = self.pool.db();
+
+        let Some(snapshot) = sqlx::query!(
+            r"
+                SELECT id, taken
+                FROM balance_snapshots
+                ORDER BY id DESC
+                LIMIT 1
+            "
+        )
+        .fetch_optional(db)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(self.read_snapshot(snapshot.id, snapshot.taken).await?))
+    }
+
+    /// The snapshots taken between `from` and `until`, oldest first
+    #[tracing::instrument(name = "Read balance snapshots", skip(self))]
+    async fn read_snapshots(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<BalanceSnapshot>, Error> {
+        let rows = sqlx::query!(
+            r"
+                SELECT id, taken
+                FROM balance_snapshots
+                WHERE taken BETWEEN $1 AND $2
+                ORDER BY taken, id
+            ",
+            from,
+            until
+        )
+        .fetch_all(self.pool.db())
+        .await?;
+
+        let mut snapshots = Vec::with_capacity(rows.len());
+        for row in rows {
+            snapshots.push(self.read_snapshot(row.id, row.taken).await?);
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Every stored account balance, oldest first
+    #[tracing::instrument(name = "Read account balances", skip(self))]
+    async fn read_account_balances(&self) -> Result<Vec<StoredBalance>, Error> {
+        let balances = sqlx::query_as!(
+            StoredBalance,
+            r"
+                SELECT s.taken, b.account_id, b.balance, b.currency
+                FROM account_balances b
+                JOIN balance_snapshots s ON s.id = b.snapshot_id
+                ORDER BY s.taken, s.id, b.rowid
+            "
+        )
+        .fetch_all(self.pool.db())
+        .await?;
+
+        Ok(balances)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct PotRow {
+    pub(crate) account_id: String,
+    pub(crate) pot_id: String,
+    pub(crate) name: String,
+    pub(crate) balance: i64,
+    pub(crate) currency: String,
+}
+
+// A snapshot of the accounts' balances and their pots, in the order saved
+pub(crate) fn snapshot(
+    id: i64,
+    taken: NaiveDateTime,
+    accounts: Vec<(String, Balance)>,
+    pots: &[PotRow],
+) -> BalanceSnapshot {
+    let accounts = accounts
+        .into_iter()
+        .map(|(account_id, balance)| AccountSnapshot {
+            pots: pots
+                .iter()
+                .filter(|pot| pot.account_id == account_id)
+                .map(|pot| PotBalance {
+                    pot_id: pot.pot_id.clone(),
+                    name: pot.name.clone(),
+                    balance: pot.balance,
+                    currency: pot.currency.clone(),
+                })
+                .collect(),
+            account_id,
+            balance,
+        })
+        .collect();
+
+    BalanceSnapshot {
+        id,
+        taken,
+        accounts,
+    }
+}
+
 // -- Tests -------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tests::test::test_db;
 
     #[test]
     fn test_deserialize_balance() {
@@ -29,4 +331,80 @@ mod tests {
         assert_eq!(balance.currency, "GBP");
         assert_eq!(balance.spend_today, 0);
     }
+
+    #[tokio::test]
+    async fn reads_the_latest_snapshot() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteBalanceService::new(pool);
+        let account = |balance| AccountSnapshot {
+            account_id: "1".to_string(),
+            balance: Balance {
+                balance,
+                currency: "GBP".to_string(),
+                ..Default::default()
+            },
+            pots: vec![PotBalance {
+                pot_id: "1".to_string(),
+                name: "Savings".to_string(),
+                balance: 250,
+                currency: "GBP".to_string(),
+            }],
+        };
+        let taken = chrono::Utc::now().naive_utc();
+        service
+            .save_snapshot(taken - chrono::Duration::days(1), &[account(1000)])
+            .await
+            .unwrap();
+        service
+            .save_snapshot(taken, &[account(2000)])
+            .await
+            .unwrap();
+
+        // Act
+        let snapshot = service.read_latest_snapshot().await.unwrap().unwrap();
+
+        // Assert
+        assert_eq!(snapshot.taken, taken);
+        assert_eq!(snapshot.accounts.len(), 1);
+        assert_eq!(snapshot.accounts[0].balance.balance, 2000);
+        assert_eq!(snapshot.accounts[0].pots[0].name, "Savings");
+    }
+
+    #[tokio::test]
+    async fn reads_the_snapshots_taken_between_two_dates() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteBalanceService::new(pool);
+        let taken = |day| {
+            chrono::NaiveDate::from_ymd_opt(2024, 6, day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        };
+        for day in [1, 10, 20] {
+            let account = AccountSnapshot {
+                account_id: "1".to_string(),
+                balance: Balance {
+                    balance: i64::from(day),
+                    currency: "GBP".to_string(),
+                    ..Default::default()
+                },
+                pots: vec![],
+            };
+            service.save_snapshot(taken(day), &[account]).await.unwrap();
+        }
+
+        // Act
+        let snapshots = service.read_snapshots(taken(5), taken(25)).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            snapshots
+                .iter()
+                .map(|s| (s.taken, s.accounts[0].balance.balance))
+                .collect::<Vec<_>>(),
+            vec![(taken(10), 10), (taken(20), 20)]
+        );
+    }
 }
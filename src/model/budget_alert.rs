@@ -0,0 +1,102 @@
+//! Models for budget alerts
+//!
+//! Each threshold a category's spending crosses is recorded for the month, so
+//! the alert is posted to the Monzo feed once rather than after every sync.
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn is_posted(
+        &self,
+        category: &str,
+        month: NaiveDate,
+        threshold: i64,
+    ) -> Result<bool, Error>;
+    async fn record_posted(
+        &self,
+        category: &str,
+        month: NaiveDate,
+        threshold: i64,
+        posted: NaiveDateTime,
+    ) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteBudgetAlertService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteBudgetAlertService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteBudgetAlertService {
+    /// Whether the category's alert at `threshold` percent was posted for the
+    /// month
+    #[tracing::instrument(name = "Check budget alert", skip(self))]
+    async fn is_posted(
+        &self,
+        category: &str,
+        month: NaiveDate,
+        threshold: i64,
+    ) -> Result<bool, Error> {
+        let db = self.pool.db();
+        let month = month.format("%Y-%m").to_string();
+
+        let alert = sqlx::query!(
+            r"
+                SELECT category FROM budget_alerts
+                WHERE category = $1 AND month = $2 AND threshold = $3
+            ",
+            category,
+            month,
+            threshold
+        )
+        .fetch_optional(db)
+        .await?;
+
+        Ok(alert.is_some())
+    }
+
+    /// Record that the category's alert at `threshold` percent was posted for
+    /// the month
+    #[tracing::instrument(name = "Record budget alert", skip(self))]
+    async fn record_posted(
+        &self,
+        category: &str,
+        month: NaiveDate,
+        threshold: i64,
+        posted: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let db = self.pool.db();
+        let month = month.format("%Y-%m").to_string();
+
+        sqlx::query!(
+            r"
+                INSERT OR IGNORE INTO budget_alerts (category, month, threshold, posted)
+                VALUES ($1, $2, $3, $4)
+            ",
+            category,
+            month,
+            threshold,
+            posted
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+}
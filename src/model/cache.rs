@@ -0,0 +1,171 @@
+//! Query result caching
+//!
+//! Aggregates such as category, account and pot totals are stored with the
+//! highest transaction rowid they saw, and reused until a transaction is
+//! added. Updates and deletes clear the cache with triggers, so a cached
+//! result is always the one the query would return. This keeps repeated
+//! report runs and dashboards polling `serve` cheap between syncs.
+
+use std::future::Future;
+
+use chrono::{NaiveDateTime, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use tracing_log::log::{info, warn};
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+impl DatabasePool {
+    /// The result of the query named `query` with `params`, from the cache if
+    /// no transactions have been added since it was stored, or else from
+    /// `compute`
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read or `compute` fails.
+    pub async fn cached<T, P, F, Fut>(
+        &self,
+        query: &str,
+        params: &P,
+        compute: F,
+    ) -> Result<T, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        P: Serialize + ?Sized,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        // Postgres results aren't cached
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return compute().await;
+        }
+
+        let fingerprint = fingerprint(query, params)?;
+        let max_rowid = self.max_transaction_rowid().await?;
+
+        let cached = sqlx::query_scalar!(
+            r"
+                SELECT result FROM query_cache
+                WHERE fingerprint = $1 AND max_rowid = $2
+            ",
+            fingerprint,
+            max_rowid
+        )
+        .fetch_optional(self.db())
+        .await?;
+        if let Some(result) = cached {
+            // a result that no longer reads, e.g. after an upgrade, is recomputed
+            match serde_json::from_str(&result) {
+                Ok(result) => {
+                    info!("Cached result for {query}");
+                    return Ok(result);
+                }
+                Err(e) => warn!("Ignoring cached result for {query}: {e}"),
+            }
+        }
+
+        let result = compute().await?;
+        let json = serde_json::to_string(&result)?;
+        let now = Utc::now().naive_utc();
+        sqlx::query!(
+            r"
+                INSERT OR REPLACE INTO query_cache (fingerprint, max_rowid, result, created)
+                VALUES ($1, $2, $3, $4)
+            ",
+            fingerprint,
+            max_rowid,
+            json,
+            now
+        )
+        .execute(self.db())
+        .await?;
+        // results from before the latest transactions can't be used again
+        sqlx::query!("DELETE FROM query_cache WHERE max_rowid != $1", max_rowid)
+            .execute(self.db())
+            .await?;
+
+        Ok(result)
+    }
+
+    /// `until` for a cache key, or `None` if no transaction was created
+    /// after it, so ranges ending now share a cached result
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read.
+    pub async fn range_end_key(
+        &self,
+        until: NaiveDateTime,
+    ) -> Result<Option<NaiveDateTime>, Error> {
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return Ok(Some(until));
+        }
+
+        let open = sqlx::query_scalar!(
+            r#"SELECT COALESCE(MAX(created) <= $1, TRUE) AS "open!: bool" FROM transactions"#,
+            until
+        )
+        .fetch_one(self.db())
+        .await?;
+
+        Ok((!open).then_some(until))
+    }
+
+    async fn max_transaction_rowid(&self) -> Result<i64, Error> {
+        let max = sqlx::query_scalar!(
+            r#"SELECT COALESCE(MAX(rowid), 0) AS "max!: i64" FROM transactions"#
+        )
+        .fetch_one(self.db())
+        .await?;
+
+        Ok(max)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// A fixed length key for the query and its parameters
+fn fingerprint<P: Serialize + ?Sized>(query: &str, params: &P) -> Result<String, Error> {
+    let params = serde_json::to_string(params)?;
+    Ok(format!("{:x}", Sha256::digest(format!("{query}|{params}"))))
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn results_are_reused_until_transactions_change() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let runs = AtomicU32::new(0);
+        let count = || async {
+            runs.fetch_add(1, Ordering::SeqCst);
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions")
+                .fetch_one(pool.db())
+                .await?;
+            Ok(count)
+        };
+
+        // Act
+        let first = pool.cached("count", &(), count).await.unwrap();
+        let second = pool.cached("count", &(), count).await.unwrap();
+        let other = pool.cached("count", &("other",), count).await.unwrap();
+        sqlx::query("UPDATE transactions SET notes = 'changed'")
+            .execute(pool.db())
+            .await
+            .unwrap();
+        let after_update = pool.cached("count", &(), count).await.unwrap();
+
+        // Assert
+        assert_eq!(first, second);
+        assert_eq!(first, other);
+        assert_eq!(first, after_update);
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,179 @@
+//! Models for zero-amount card events
+//!
+//! Monzo reports active card checks and similar events as transactions with a
+//! zero amount. They are stored in their own table so they can be listed
+//! without affecting spending totals.
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing_log::log::info;
+
+use super::{
+    category::{Service as CategoryService, SqliteCategoryService},
+    transaction::{insert_merchant, TransactionResponse},
+    DatabasePool,
+};
+use crate::error::AppErrors as Error;
+
+/// A zero-amount event joined with its account and merchant names
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct CardEvent {
+    pub id: String,
+    pub account_id: String,
+    pub account_name: String,
+    pub currency: String,
+    pub created: NaiveDateTime,
+    pub description: String,
+    pub category_id: String,
+    pub merchant_name: Option<String>,
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn save_card_event(&self, tx_resp: &TransactionResponse) -> Result<(), Error>;
+    async fn read_card_events(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<CardEvent>, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteCardEventService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteCardEventService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteCardEventService {
+    #[tracing::instrument(
+        name = "Create card event",
+        skip(self, tx_resp),
+        fields(tx_id = %tx_resp.id, acc_id = %tx_resp.account_id)
+    )]
+    async fn save_card_event(&self, tx_resp: &TransactionResponse) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        let merchant_id = insert_merchant(self.pool.clone(), tx_resp.merchant.as_ref()).await?;
+        SqliteCategoryService::new(self.pool.clone())
+            .ensure_category(&tx_resp.category_id)
+            .await?;
+        let created = tx_resp.created.naive_utc();
+
+        let result = sqlx::query!(
+            r"
+                INSERT OR IGNORE INTO card_events (
+                    id,
+                    account_id,
+                    merchant_id,
+                    currency,
+                    created,
+                    description,
+                    category_id
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ",
+            tx_resp.id,
+            tx_resp.account_id,
+            merchant_id,
+            tx_resp.currency,
+            created,
+            tx_resp.description,
+            tx_resp.category_id,
+        )
+        .execute(db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::Duplicate("Card event already exists".to_string()));
+        }
+        info!("Created card event: {}", tx_resp.id);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Read card events", skip(self))]
+    async fn read_card_events(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<CardEvent>, Error> {
+        let db = self.pool.db();
+
+        let events = sqlx::query_as!(
+            CardEvent,
+            r#"
+                SELECT
+                    e.id,
+                    e.account_id,
+                    a.owner_type AS account_name,
+                    e.currency,
+                    e.created,
+                    e.description,
+                    e.category_id,
+                    m.name AS "merchant_name?"
+                FROM card_events e
+                INNER JOIN accounts a ON e.account_id = a.id
+                LEFT JOIN merchants m ON e.merchant_id = m.id
+                WHERE e.created BETWEEN $1 AND $2
+                ORDER BY e.created, e.id
+            "#,
+            from,
+            until
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(events)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn saves_and_reads_card_events() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteCardEventService::new(pool);
+        let event = TransactionResponse {
+            id: "tx_check".to_string(),
+            account_id: "1".to_string(),
+            currency: "GBP".to_string(),
+            created: Utc.with_ymd_and_hms(2024, 5, 2, 12, 0, 0).unwrap(),
+            description: "ACTIVE CARD CHECK".to_string(),
+            category_id: "general".to_string(),
+            ..Default::default()
+        };
+
+        // Act
+        service.save_card_event(&event).await.unwrap();
+        let duplicate = service.save_card_event(&event).await;
+        let events = service
+            .read_card_events(NaiveDateTime::default(), Utc::now().naive_utc())
+            .await
+            .unwrap();
+
+        // Assert
+        assert!(matches!(duplicate, Err(Error::Duplicate(_))));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].account_name, "personal");
+        assert_eq!(events[0].description, "ACTIVE CARD CHECK");
+    }
+}
@@ -1,15 +1,65 @@
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
+use serde::Deserialize;
 use sqlx::{Pool, Sqlite};
 use tracing_log::log::{error, info};
 
-use crate::error::AppErrors as Error;
+use crate::{configuration::CategoryDisplay, error::AppErrors as Error};
 
 use super::DatabasePool;
 
-#[derive(Debug, Default, Clone)]
+/// Display names and emoji of Monzo's built-in categories, by id
+const BUILT_IN: [(&str, &str, &str); 18] = [
+    ("general", "General", "📦"),
+    ("eating_out", "Eating out", "🍔"),
+    ("expenses", "Expenses", "💼"),
+    ("transport", "Transport", "🚌"),
+    ("cash", "Cash", "💵"),
+    ("bills", "Bills", "💡"),
+    ("entertainment", "Entertainment", "🎉"),
+    ("shopping", "Shopping", "🛍️"),
+    ("holidays", "Holidays", "🏖️"),
+    ("groceries", "Groceries", "🛒"),
+    ("income", "Income", "💰"),
+    ("savings", "Savings", "🐷"),
+    ("transfers", "Transfers", "🔁"),
+    ("charity", "Charity", "💝"),
+    ("family", "Family", "👪"),
+    ("finances", "Finances", "📈"),
+    ("gifts", "Gifts", "🎁"),
+    ("personal_care", "Personal care", "🧴"),
+];
+
+#[derive(Debug, Default, Clone, Deserialize, sqlx::FromRow)]
 pub struct Category {
     pub id: String,
     pub name: String,
+    /// Name shown in tables and reports instead of `name`
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub emoji: Option<String>,
+}
+
+impl Category {
+    /// The display name with its emoji, e.g. `🛒 Groceries`
+    #[must_use]
+    pub fn label(&self) -> String {
+        let name = self.display_name.as_deref().unwrap_or(&self.name);
+        match &self.emoji {
+            Some(emoji) => format!("{emoji} {name}"),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// How many transactions and card events use a category id, whether or not
+/// the category is stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryUsage {
+    pub id: String,
+    pub count: i64,
 }
 
 // -- Services -------------------------------------------------------------------------
@@ -17,6 +67,15 @@ pub struct Category {
 #[async_trait]
 pub trait Service {
     async fn save_category(&self, category: &Category) -> Result<(), Error>;
+    async fn ensure_category(&self, category_id: &str) -> Result<(), Error>;
+    async fn apply_display(
+        &self,
+        overrides: &BTreeMap<String, CategoryDisplay>,
+    ) -> Result<u64, Error>;
+    async fn read_categories(&self) -> Result<Vec<Category>, Error>;
+    async fn find_category(&self, id_or_name: &str) -> Result<Option<Category>, Error>;
+    async fn read_usage(&self) -> Result<Vec<CategoryUsage>, Error>;
+    async fn rename_category(&self, category_id: &str, name: &str) -> Result<(), Error>;
 }
 
 #[derive(Debug, Clone)]
@@ -44,13 +103,18 @@ impl Service for SqliteCategoryService {
             return Err(Error::Duplicate("Category already exists".to_string()));
         }
 
+        let (built_in_name, built_in_emoji) = built_in(&category_fc.id);
+        let display_name = category_fc.display_name.as_deref().or(built_in_name);
+        let emoji = category_fc.emoji.as_deref().or(built_in_emoji);
         match sqlx::query!(
             r"
-                INSERT INTO categories (id, name)
-                VALUES ($1, $2)
+                INSERT INTO categories (id, name, display_name, emoji)
+                VALUES ($1, $2, $3, $4)
             ",
             category_fc.id,
             category_fc.name,
+            display_name,
+            emoji,
         )
         .execute(db)
         .await
@@ -62,6 +126,140 @@ impl Service for SqliteCategoryService {
             }
         }
     }
+
+    /// Create a category named after its id unless it already exists, so
+    /// rows referencing it satisfy the foreign key
+    #[tracing::instrument(name = "Ensure category", skip(self))]
+    async fn ensure_category(&self, category_id: &str) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        let (display_name, emoji) = built_in(category_id);
+        sqlx::query!(
+            r"
+                INSERT OR IGNORE INTO categories (id, name, display_name, emoji)
+                VALUES ($1, $1, $2, $3)
+            ",
+            category_id,
+            display_name,
+            emoji,
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set the display names and emoji configured under `[categories]`, keyed
+    /// by category id or name. Returns the number of categories changed.
+    #[tracing::instrument(name = "Apply category display", skip(self, overrides))]
+    async fn apply_display(
+        &self,
+        overrides: &BTreeMap<String, CategoryDisplay>,
+    ) -> Result<u64, Error> {
+        let db = self.pool.db();
+
+        let mut changed = 0;
+        for (key, display) in overrides {
+            changed += sqlx::query!(
+                r"
+                    UPDATE categories
+                    SET
+                        display_name = COALESCE($2, display_name),
+                        emoji = COALESCE($3, emoji)
+                    WHERE (id = $1 OR name = $1 COLLATE NOCASE)
+                    AND (
+                        display_name IS NOT COALESCE($2, display_name)
+                        OR emoji IS NOT COALESCE($3, emoji)
+                    )
+                ",
+                key,
+                display.display_name,
+                display.emoji,
+            )
+            .execute(db)
+            .await?
+            .rows_affected();
+        }
+
+        Ok(changed)
+    }
+
+    /// Read every stored category, ordered by id
+    #[tracing::instrument(name = "Read categories", skip(self))]
+    async fn read_categories(&self) -> Result<Vec<Category>, Error> {
+        let categories = sqlx::query_as!(
+            Category,
+            "SELECT id, name, display_name, emoji FROM categories ORDER BY id"
+        )
+        .fetch_all(self.pool.db())
+        .await?;
+
+        Ok(categories)
+    }
+
+    /// Find a category by id, or else by name ignoring case
+    #[tracing::instrument(name = "Find category", skip(self))]
+    async fn find_category(&self, id_or_name: &str) -> Result<Option<Category>, Error> {
+        let category = sqlx::query_as!(
+            Category,
+            r"
+                SELECT id, name, display_name, emoji
+                FROM categories
+                WHERE id = $1 OR name = $1 COLLATE NOCASE
+                ORDER BY id != $1
+                LIMIT 1
+            ",
+            id_or_name
+        )
+        .fetch_optional(self.pool.db())
+        .await?;
+
+        Ok(category)
+    }
+
+    /// Count the transactions and card events of each category id they use
+    #[tracing::instrument(name = "Read category usage", skip(self))]
+    async fn read_usage(&self) -> Result<Vec<CategoryUsage>, Error> {
+        let usage = sqlx::query_as!(
+            CategoryUsage,
+            r#"
+                SELECT category_id AS "id!", COUNT(*) AS "count!: i64"
+                FROM (
+                    SELECT category_id FROM transactions
+                    UNION ALL
+                    SELECT category_id FROM card_events
+                )
+                GROUP BY category_id
+                ORDER BY category_id
+            "#
+        )
+        .fetch_all(self.pool.db())
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Change the stored name of a category
+    #[tracing::instrument(name = "Rename category", skip(self))]
+    async fn rename_category(&self, category_id: &str, name: &str) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE categories SET name = $2 WHERE id = $1",
+            category_id,
+            name
+        )
+        .execute(self.pool.db())
+        .await?;
+
+        Ok(())
+    }
+}
+
+// The built-in display name and emoji for a category id
+pub(crate) fn built_in(category_id: &str) -> (Option<&'static str>, Option<&'static str>) {
+    BUILT_IN
+        .iter()
+        .find(|(id, _, _)| *id == category_id)
+        .map_or((None, None), |(_, name, emoji)| (Some(*name), Some(*emoji)))
 }
 
 // Check if a category is a duplicate
@@ -101,4 +299,44 @@ mod tests {
         // Assert
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn built_in_display_is_overridden_by_configuration() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteCategoryService::new(pool.clone());
+        service.ensure_category("groceries").await.unwrap();
+        let overrides = BTreeMap::from([
+            (
+                "Groceries".to_string(),
+                CategoryDisplay {
+                    emoji: Some("🥦".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "category_1".to_string(),
+                CategoryDisplay {
+                    display_name: Some("Car".to_string()),
+                    emoji: Some("🚗".to_string()),
+                },
+            ),
+        ]);
+
+        // Act
+        let changed = service.apply_display(&overrides).await.unwrap();
+        let again = service.apply_display(&overrides).await.unwrap();
+
+        // Assert
+        let categories = sqlx::query_as!(
+            Category,
+            "SELECT id, name, display_name, emoji FROM categories ORDER BY id"
+        )
+        .fetch_all(pool.db())
+        .await
+        .unwrap();
+        let labels: Vec<_> = categories.iter().map(Category::label).collect();
+        assert_eq!(labels, vec!["🚗 Car", "🥦 Groceries"]);
+        assert_eq!((changed, again), (2, 0));
+    }
 }
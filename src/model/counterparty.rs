@@ -0,0 +1,228 @@
+//! Models for transaction counterparties
+//!
+//! Bank transfers carry the other party's details. For Monzo-to-Monzo (P2P)
+//! payments that includes their Monzo user id, so those people are stored in
+//! the `counterparties` table and linked to their transactions.
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use tracing_log::log::info;
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// The other party to a bank transfer. Monzo sends an empty object for card
+/// payments.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Counterparty {
+    pub account_number: Option<String>,
+    pub sort_code: Option<String>,
+    pub name: Option<String>,
+    pub preferred_name: Option<String>,
+    /// Set for Monzo-to-Monzo payments
+    pub user_id: Option<String>,
+}
+
+impl Counterparty {
+    /// The name to show: the one they chose, or else the account name
+    #[must_use]
+    pub fn display_name(&self) -> Option<&str> {
+        self.preferred_name.as_deref().or(self.name.as_deref())
+    }
+}
+
+/// Money sent to and received from one person
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct PersonTotal {
+    pub counterparty_id: String,
+    pub name: String,
+    pub currency: String,
+    /// Total paid to them in minor units, as a positive number
+    pub sent: i64,
+    /// Total received from them in minor units
+    pub received: i64,
+    pub count: i64,
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn save_counterparty(&self, counterparty: &Counterparty)
+        -> Result<Option<String>, Error>;
+    async fn read_people(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PersonTotal>, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteCounterpartyService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteCounterpartyService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteCounterpartyService {
+    /// Store a P2P counterparty, updating their name if it has changed, and
+    /// return their id. Other counterparties aren't stored.
+    #[tracing::instrument(name = "Save counterparty", skip(self, counterparty))]
+    async fn save_counterparty(
+        &self,
+        counterparty: &Counterparty,
+    ) -> Result<Option<String>, Error> {
+        let (Some(id), Some(name)) = (&counterparty.user_id, counterparty.display_name()) else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r"
+                INSERT INTO counterparties (id, name, account_number, sort_code)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name,
+                    account_number = COALESCE(excluded.account_number, account_number),
+                    sort_code = COALESCE(excluded.sort_code, sort_code)
+            ",
+            id,
+            name,
+            counterparty.account_number,
+            counterparty.sort_code,
+        )
+        .execute(self.pool.db())
+        .await?;
+        info!("Saved counterparty: {id}");
+
+        Ok(Some(id.clone()))
+    }
+
+    /// Money sent and received per person between the given dates, most sent
+    /// first. Payments smaller than the pool's minimum amount are left out.
+    #[tracing::instrument(name = "Read people", skip(self))]
+    async fn read_people(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PersonTotal>, Error> {
+        let min_amount = self.pool.min_amount();
+        let people = sqlx::query_as!(
+            PersonTotal,
+            r#"
+                SELECT
+                    cp.id AS counterparty_id,
+                    cp.name,
+                    t.currency,
+                    COALESCE(SUM(CASE WHEN t.amount < 0 THEN -t.amount ELSE 0 END), 0) AS "sent!: i64",
+                    COALESCE(SUM(CASE WHEN t.amount > 0 THEN t.amount ELSE 0 END), 0) AS "received!: i64",
+                    COUNT(*) AS "count!: i64"
+                FROM transactions t
+                JOIN counterparties cp ON t.counterparty_id = cp.id
+                WHERE t.created BETWEEN $1 AND $2
+                AND NOT t.is_transfer
+                AND ABS(t.amount) >= $3
+                GROUP BY cp.id, t.currency
+                ORDER BY 4 DESC, cp.name
+            "#,
+            from,
+            until,
+            min_amount
+        )
+        .fetch_all(self.pool.db())
+        .await?;
+
+        Ok(people)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        model::transaction::{
+            Service as TransactionService, SqliteTransactionService, TransactionResponse,
+        },
+        tests::test::test_db,
+    };
+
+    fn alex() -> Counterparty {
+        Counterparty {
+            name: Some("Alex Smith".to_string()),
+            preferred_name: Some("Alex".to_string()),
+            user_id: Some("user_alex".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn only_p2p_counterparties_are_saved() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteCounterpartyService::new(pool);
+        let bank = Counterparty {
+            name: Some("Landlord".to_string()),
+            account_number: Some("87654321".to_string()),
+            ..Default::default()
+        };
+
+        // Act
+        let p2p = service.save_counterparty(&alex()).await.unwrap();
+        let other = service.save_counterparty(&bank).await.unwrap();
+
+        // Assert
+        assert_eq!(p2p.as_deref(), Some("user_alex"));
+        assert_eq!(other, None);
+    }
+
+    #[tokio::test]
+    async fn people_total_sent_and_received() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let transactions = SqliteTransactionService::new(pool.clone());
+        for (id, amount) in [("a", -2500), ("b", -1000), ("c", 700)] {
+            let tx = TransactionResponse {
+                id: id.to_string(),
+                account_id: "1".to_string(),
+                category_id: "1".to_string(),
+                amount,
+                currency: "GBP".to_string(),
+                created: Utc::now() - chrono::Duration::days(1),
+                counterparty: Some(alex()),
+                ..Default::default()
+            };
+            transactions.save_transaction(&tx).await.unwrap();
+        }
+
+        // Act
+        let people = SqliteCounterpartyService::new(pool)
+            .read_people(NaiveDateTime::default(), Utc::now().naive_utc())
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            people,
+            vec![PersonTotal {
+                counterparty_id: "user_alex".to_string(),
+                name: "Alex".to_string(),
+                currency: "GBP".to_string(),
+                sent: 3500,
+                received: 700,
+                count: 3,
+            }]
+        );
+    }
+}
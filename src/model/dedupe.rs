@@ -0,0 +1,294 @@
+//! Dedupe keys
+//!
+//! The same payment can reach the database from more than one source: the API
+//! (and webhooks, which share its ids), a CSV export from the app, or a fixture
+//! from another tool. Only the API's ids can be relied on, so each transaction
+//! also stores a key hashed from its account, currency, amount and normalised
+//! payee. Transactions from different sources with the same key within
+//! [`TOLERANCE_DAYS`] of each other are the same payment.
+//!
+//! When the API delivers a payment that an import already stored, the imported
+//! row is merged into it: its tags, category and attachments move across and
+//! its id is kept as an alias, so importing it again does nothing.
+
+use std::fmt;
+
+use chrono::{Duration, NaiveDateTime};
+use sha2::{Digest, Sha256};
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// Days either side of a transaction in which a payment from another source
+/// with the same key is a duplicate
+pub const TOLERANCE_DAYS: i64 = 3;
+
+/// Where a transaction came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The Monzo API, by sync
+    Api,
+    /// A Monzo webhook, until a sync fetches the transaction too
+    Webhook,
+    /// A CSV export from the Monzo app
+    Csv,
+    /// A fixture, e.g. data migrated from another tool
+    Fixture,
+}
+
+impl Source {
+    /// Whether the transaction came from Monzo, with an id that can be
+    /// relied on
+    #[must_use]
+    pub fn is_monzo(self) -> bool {
+        matches!(self, Self::Api | Self::Webhook)
+    }
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Api => "api",
+            Self::Webhook => "webhook",
+            Self::Csv => "csv",
+            Self::Fixture => "fixture",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The dedupe key of a payment: a hash of its account, currency, amount and
+/// normalised payee
+#[must_use]
+pub fn dedupe_key(account_id: &str, currency: &str, amount: i64, payee: &str) -> String {
+    let payee = normalise_payee(payee);
+    let digest = Sha256::digest(format!("{account_id}|{currency}|{amount}|{payee}"));
+    format!("{digest:x}")[..32].to_string()
+}
+
+/// A payee in lower case without punctuation or words with digits, such as
+/// card numbers and references
+#[must_use]
+pub fn normalise_payee(payee: &str) -> String {
+    payee
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !word.chars().any(|c| c.is_ascii_digit()))
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl DatabasePool {
+    /// Set the dedupe key of the transaction `id`, or of every transaction
+    /// without one. Returns the number of keys set.
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read or written.
+    #[tracing::instrument(name = "Set dedupe keys", skip(self))]
+    pub async fn set_dedupe_keys(&self, id: Option<&str>) -> Result<u64, Error> {
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return super::postgres::dedupe::set_dedupe_keys(self, id).await;
+        }
+
+        let rows = sqlx::query!(
+            r#"
+                SELECT
+                    t.id AS "id!",
+                    t.account_id,
+                    t.currency,
+                    t.amount,
+                    COALESCE(m.name, cp.name, t.description) AS "payee!: String"
+                FROM transactions t
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+                LEFT JOIN counterparties cp ON t.counterparty_id = cp.id
+                WHERE t.id = $1 OR ($1 IS NULL AND t.dedupe_key IS NULL)
+            "#,
+            id
+        )
+        .fetch_all(self.db())
+        .await?;
+
+        for row in &rows {
+            let key = dedupe_key(&row.account_id, &row.currency, row.amount, &row.payee);
+            sqlx::query!(
+                "UPDATE transactions SET dedupe_key = $2 WHERE id = $1",
+                row.id,
+                key
+            )
+            .execute(self.db())
+            .await?;
+        }
+
+        Ok(rows.len() as u64)
+    }
+
+    /// The id of a transaction from a source other than `source` with the
+    /// same dedupe key as the transaction `id`, nearest in time within
+    /// [`TOLERANCE_DAYS`]. Sync and webhooks count as one source.
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read.
+    #[tracing::instrument(name = "Find duplicate transaction", skip(self))]
+    pub async fn find_duplicate(&self, id: &str, source: Source) -> Result<Option<String>, Error> {
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return super::postgres::dedupe::find_duplicate(self, id, source).await;
+        }
+
+        let Some(tx) = sqlx::query!(
+            "SELECT dedupe_key, created FROM transactions WHERE id = $1",
+            id
+        )
+        .fetch_optional(self.db())
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let tolerance = Duration::days(TOLERANCE_DAYS);
+        let (from, until): (NaiveDateTime, NaiveDateTime) =
+            (tx.created - tolerance, tx.created + tolerance);
+        let (source, same) = if source.is_monzo() {
+            (Source::Api.as_str(), Source::Webhook.as_str())
+        } else {
+            (source.as_str(), source.as_str())
+        };
+        let duplicate = sqlx::query_scalar!(
+            r#"
+                SELECT id AS "id!"
+                FROM transactions
+                WHERE dedupe_key = $1
+                AND id != $2
+                AND source NOT IN ($3, $4)
+                AND created BETWEEN $5 AND $6
+                ORDER BY abs(julianday(created) - julianday($7))
+                LIMIT 1
+            "#,
+            tx.dedupe_key,
+            id,
+            source,
+            same,
+            from,
+            until,
+            tx.created
+        )
+        .fetch_optional(self.db())
+        .await?;
+
+        Ok(duplicate)
+    }
+
+    /// Merge the transaction `from` into `into`, moving its tags, category
+    /// override and attachments, and keep `from` as an alias of `into`
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be written.
+    #[tracing::instrument(name = "Merge transaction", skip(self))]
+    pub async fn merge_transaction(&self, from: &str, into: &str) -> Result<(), Error> {
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return super::postgres::dedupe::merge_transaction(self, from, into).await;
+        }
+
+        let mut tx = self.db().begin().await?;
+
+        sqlx::query!(
+            "UPDATE OR IGNORE transaction_tags SET transaction_id = $2 WHERE transaction_id = $1",
+            from,
+            into
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM transaction_tags WHERE transaction_id = $1",
+            from
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE OR IGNORE category_overrides SET transaction_id = $2 WHERE transaction_id = $1",
+            from,
+            into
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM category_overrides WHERE transaction_id = $1",
+            from
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE attachments SET transaction_id = $2 WHERE transaction_id = $1",
+            from,
+            into
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r"
+                INSERT OR REPLACE INTO transaction_aliases (alias_id, transaction_id, source)
+                SELECT id, $2, source FROM transactions WHERE id = $1
+            ",
+            from,
+            into
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM transactions WHERE id = $1", from)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The transaction that `id` was merged into, if it was
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read.
+    pub async fn resolve_alias(&self, id: &str) -> Result<Option<String>, Error> {
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return super::postgres::dedupe::resolve_alias(self, id).await;
+        }
+
+        let transaction_id = sqlx::query_scalar!(
+            "SELECT transaction_id FROM transaction_aliases WHERE alias_id = $1",
+            id
+        )
+        .fetch_optional(self.db())
+        .await?;
+
+        Ok(transaction_id)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_ignore_case_punctuation_and_references() {
+        assert_eq!(
+            dedupe_key("acc_1", "GBP", -350, "Costa Coffee"),
+            dedupe_key("acc_1", "GBP", -350, "COSTA COFFEE #4471")
+        );
+        assert_ne!(
+            dedupe_key("acc_1", "GBP", -350, "Costa Coffee"),
+            dedupe_key("acc_1", "GBP", -351, "Costa Coffee")
+        );
+        assert_ne!(
+            dedupe_key("acc_1", "GBP", -350, "Costa Coffee"),
+            dedupe_key("acc_2", "GBP", -350, "Costa Coffee")
+        );
+    }
+}
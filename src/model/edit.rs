@@ -0,0 +1,276 @@
+//! Edits made to transactions by hand
+//!
+//! A category set with `transactions set` is kept in `category_overrides`, so
+//! it survives a pending transaction being reconciled by a later update.
+//! Tags are kept in `transaction_tags` and become beancount tags.
+//!
+//! Categories chosen by an external categoriser are stored the same way.
+
+use chrono::Utc;
+use serde::Serialize;
+use tracing_log::log::info;
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// Changes to make to each selected transaction
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TransactionEdit {
+    /// Category id or name
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl TransactionEdit {
+    /// An edit setting `category` and adding `tags`
+    ///
+    /// # Errors
+    /// Will return an error if there's nothing to change or a tag has
+    /// characters other than letters, digits, `-`, `_`, `/` and `.`.
+    pub fn new(category: Option<String>, tags: Vec<String>) -> Result<Self, Error> {
+        if category.is_none() && tags.is_empty() {
+            return Err(Error::Error(
+                "Nothing to change: give --category or --tag".into(),
+            ));
+        }
+        if let Some(tag) = tags.iter().find(|tag| {
+            tag.is_empty()
+                || !tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_/.".contains(c))
+        }) {
+            return Err(Error::Error(format!(
+                "Invalid tag '{tag}': use letters, digits, '-', '_', '/' and '.'"
+            )));
+        }
+
+        Ok(Self { category, tags })
+    }
+}
+
+/// A transaction without a category, as sent to a categoriser
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct Uncategorised {
+    pub id: String,
+    pub merchant: Option<String>,
+    pub description: String,
+    /// In minor units, negative for spending
+    pub amount: i64,
+    pub currency: String,
+}
+
+/// What applying an edit changed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EditSummary {
+    pub recategorised: u64,
+    pub tagged: u64,
+}
+
+impl DatabasePool {
+    /// Apply `edit` to the transactions with `ids` in one database
+    /// transaction, so either all of them change or none do
+    ///
+    /// # Errors
+    /// Will return an error if the category doesn't exist or the database
+    /// can't be written.
+    pub async fn edit_transactions(
+        &self,
+        ids: &[String],
+        edit: &TransactionEdit,
+    ) -> Result<EditSummary, Error> {
+        let category_id = match &edit.category {
+            Some(category) => Some(
+                self.categories()
+                    .find_category(category)
+                    .await?
+                    .ok_or_else(|| Error::Error(format!("No category named '{category}'")))?
+                    .id,
+            ),
+            None => None,
+        };
+
+        let now = Utc::now().naive_utc();
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            let summary = super::postgres::edit::edit_transactions(
+                self,
+                ids,
+                edit,
+                category_id.as_deref(),
+                now,
+            )
+            .await?;
+            info!("Edited transactions: {summary:?}");
+            return Ok(summary);
+        }
+
+        let mut summary = EditSummary::default();
+        let mut tx = self.db().begin().await?;
+        for id in ids {
+            if let Some(category_id) = &category_id {
+                sqlx::query!(
+                    r"
+                        INSERT INTO category_overrides (transaction_id, category_id, created)
+                        VALUES ($1, $2, $3)
+                        ON CONFLICT (transaction_id) DO UPDATE
+                        SET category_id = excluded.category_id, created = excluded.created
+                    ",
+                    id,
+                    category_id,
+                    now
+                )
+                .execute(&mut *tx)
+                .await?;
+                summary.recategorised += sqlx::query!(
+                    "UPDATE transactions SET category_id = $2 WHERE id = $1 AND category_id != $2",
+                    id,
+                    category_id
+                )
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            }
+            for tag in &edit.tags {
+                summary.tagged += sqlx::query!(
+                    "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag) VALUES ($1, $2)",
+                    id,
+                    tag
+                )
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            }
+        }
+        tx.commit().await?;
+
+        info!("Edited transactions: {summary:?}");
+        Ok(summary)
+    }
+
+    /// Transactions in one of the `categories`, by id or name, that aren't
+    /// transfers and weren't categorised by hand, oldest first
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be read.
+    pub async fn read_uncategorised(
+        &self,
+        categories: &[String],
+    ) -> Result<Vec<Uncategorised>, Error> {
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return super::postgres::edit::read_uncategorised(self, categories).await;
+        }
+
+        let categories = serde_json::to_string(categories)?;
+        let transactions = sqlx::query_as!(
+            Uncategorised,
+            r#"
+                SELECT
+                    t.id,
+                    m.name AS "merchant?",
+                    t.description,
+                    t.amount,
+                    t.currency
+                FROM transactions t
+                JOIN categories c ON t.category_id = c.id
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+                WHERE (
+                    c.id IN (SELECT value FROM json_each($1))
+                    OR c.name IN (SELECT value FROM json_each($1))
+                )
+                AND NOT t.is_transfer
+                AND t.id NOT IN (SELECT transaction_id FROM category_overrides)
+                ORDER BY t.created, t.id
+            "#,
+            categories
+        )
+        .fetch_all(self.db())
+        .await?;
+
+        Ok(transactions)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{
+            category::{Category, Service as CategoryService, SqliteCategoryService},
+            transaction::{
+                Service as TransactionService, SqliteTransactionService, TransactionResponse,
+            },
+        },
+        tests::test::test_db,
+    };
+
+    #[tokio::test]
+    async fn overrides_survive_reconciliation() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        SqliteCategoryService::new(pool.clone())
+            .save_category(&Category {
+                id: "transport".to_string(),
+                name: "transport".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let service = SqliteTransactionService::new(pool.clone());
+        let pending = TransactionResponse {
+            id: "pending".to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount: -280,
+            currency: "GBP".to_string(),
+            description: "TFL".to_string(),
+            ..Default::default()
+        };
+        service.save_transaction(&pending).await.unwrap();
+        let edit = TransactionEdit::new(Some("Transport".to_string()), vec!["commute".to_string()])
+            .unwrap();
+
+        // Act
+        let summary = pool
+            .edit_transactions(&["pending".to_string()], &edit)
+            .await
+            .unwrap();
+        let again = pool
+            .edit_transactions(&["pending".to_string()], &edit)
+            .await
+            .unwrap();
+        // Monzo still reports the original category when it settles
+        service
+            .update_transaction(
+                &TransactionResponse {
+                    settled: Some(Utc::now()),
+                    ..pending
+                },
+                false,
+            )
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            summary,
+            EditSummary {
+                recategorised: 1,
+                tagged: 1
+            }
+        );
+        assert_eq!(again, EditSummary::default());
+        let tx = service.read_transaction("pending").await.unwrap();
+        assert_eq!(tx.category_id, "transport");
+        assert!(tx.settled.is_some());
+    }
+
+    #[test]
+    fn edits_need_a_change_and_valid_tags() {
+        assert!(TransactionEdit::new(None, Vec::new()).is_err());
+        assert!(TransactionEdit::new(None, vec!["two words".to_string()]).is_err());
+        assert!(TransactionEdit::new(None, vec!["trip/paris-2024".to_string()]).is_ok());
+    }
+}
@@ -0,0 +1,282 @@
+//! Filter expressions over stored transactions
+//!
+//! A filter is one or more conditions joined by `and`, each a field, an
+//! operator and a value, e.g. `merchant ~ "TFL" and amount < -5`.
+//!
+//! | Field         | Compared with                                    |
+//! | ------------- | ------------------------------------------------ |
+//! | `merchant`    | merchant name                                    |
+//! | `description` | description                                      |
+//! | `notes`       | notes                                            |
+//! | `category`    | category name                                    |
+//! | `account`     | account id or type                               |
+//! | `pot`         | pot name                                         |
+//! | `tag`         | any of the tags                                  |
+//! | `amount`      | amount in major units, negative for spending     |
+//! | `date`        | local date, YYYY-MM-DD                           |
+//!
+//! `~` matches a case-insensitive regular expression and `=` and `!=` compare
+//! text ignoring case. `<`, `<=`, `>` and `>=` compare amounts and dates.
+
+use std::{cmp::Ordering, str::FromStr};
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    currency, error::AppErrors as Error, model::transaction::ExportTransaction,
+    timezone::local_date,
+};
+
+/// A parsed filter expression
+#[derive(Debug, Clone)]
+pub struct Filter {
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Merchant,
+    Description,
+    Notes,
+    Category,
+    Account,
+    Pot,
+    Tag,
+    Amount,
+    Date,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Matches(Field, Regex),
+    Equals(Field, String, bool),
+    Amount(f64, Vec<Ordering>),
+    Date(NaiveDate, Vec<Ordering>),
+}
+
+impl Filter {
+    /// Whether `tx` meets every condition, with dates in `timezone`
+    #[must_use]
+    pub fn matches(&self, tx: &ExportTransaction, timezone: Tz) -> bool {
+        self.conditions.iter().all(|condition| match condition {
+            Condition::Matches(field, re) => texts(tx, *field).iter().any(|t| re.is_match(t)),
+            Condition::Equals(field, value, equal) => {
+                texts(tx, *field)
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(value))
+                    == *equal
+            }
+            Condition::Amount(value, orderings) => {
+                let value = currency::from_major(*value, &tx.currency);
+                orderings.contains(&tx.amount.cmp(&value))
+            }
+            Condition::Date(value, orderings) => {
+                orderings.contains(&local_date(tx.created, timezone).cmp(value))
+            }
+        })
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut conditions = Vec::new();
+        for (i, clause) in tokens.split(|t| t.eq_ignore_ascii_case("and")).enumerate() {
+            let [field, op, value] = clause else {
+                return Err(invalid(&format!(
+                    "condition {} should be `field operator value`",
+                    i + 1
+                )));
+            };
+            conditions.push(condition(field, op, value)?);
+        }
+
+        Ok(Self { conditions })
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn invalid(reason: &str) -> Error {
+    Error::Error(format!("Invalid filter: {reason}"))
+}
+
+// Split into words, quoted strings and operators
+fn tokenize(s: &str) -> Result<Vec<String>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err(invalid("unclosed quote")),
+                }
+            }
+            tokens.push(token);
+        } else if "~=!<>".contains(c) {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek().filter(|c| "~=!<>".contains(**c)) {
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| !c.is_whitespace() && !"\"~=!<>".contains(**c))
+            {
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err(invalid("it is empty"));
+    }
+    Ok(tokens)
+}
+
+fn condition(field: &str, op: &str, value: &str) -> Result<Condition, Error> {
+    let field = match field.to_ascii_lowercase().as_str() {
+        "merchant" => Field::Merchant,
+        "description" => Field::Description,
+        "notes" => Field::Notes,
+        "category" => Field::Category,
+        "account" => Field::Account,
+        "pot" => Field::Pot,
+        "tag" => Field::Tag,
+        "amount" => Field::Amount,
+        "date" => Field::Date,
+        _ => return Err(invalid(&format!("unknown field `{field}`"))),
+    };
+    let orderings = match op {
+        "=" => vec![Ordering::Equal],
+        "!=" => vec![Ordering::Less, Ordering::Greater],
+        "<" => vec![Ordering::Less],
+        "<=" => vec![Ordering::Less, Ordering::Equal],
+        ">" => vec![Ordering::Greater],
+        ">=" => vec![Ordering::Greater, Ordering::Equal],
+        "~" => Vec::new(),
+        _ => return Err(invalid(&format!("unknown operator `{op}`"))),
+    };
+
+    match (field, op) {
+        (Field::Amount | Field::Date, "~") => Err(invalid("`~` only applies to text fields")),
+        (Field::Amount, _) => {
+            let amount = value
+                .parse()
+                .map_err(|_| invalid(&format!("`{value}` isn't an amount")))?;
+            Ok(Condition::Amount(amount, orderings))
+        }
+        (Field::Date, _) => {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| invalid(&format!("`{value}` isn't a YYYY-MM-DD date")))?;
+            Ok(Condition::Date(date, orderings))
+        }
+        (_, "~") => {
+            let re = RegexBuilder::new(value)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| invalid(&e.to_string()))?;
+            Ok(Condition::Matches(field, re))
+        }
+        (_, "=" | "!=") => Ok(Condition::Equals(field, value.to_string(), op == "=")),
+        _ => Err(invalid(&format!(
+            "`{op}` only applies to amounts and dates"
+        ))),
+    }
+}
+
+// The values of a text field, none if it's empty
+fn texts(tx: &ExportTransaction, field: Field) -> Vec<&str> {
+    match field {
+        Field::Merchant => tx.merchant_name.as_deref().into_iter().collect(),
+        Field::Description => vec![tx.description.as_str()],
+        Field::Notes => tx.notes.as_deref().into_iter().collect(),
+        Field::Category => vec![tx.category_name.as_str()],
+        Field::Account => vec![tx.account_id.as_str(), tx.account_name.as_str()],
+        Field::Pot => tx.pot_name.as_deref().into_iter().collect(),
+        Field::Tag => tx
+            .tags
+            .as_deref()
+            .map(|tags| tags.split(' ').collect())
+            .unwrap_or_default(),
+        Field::Amount | Field::Date => Vec::new(),
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    fn tx() -> ExportTransaction {
+        ExportTransaction {
+            account_id: "acc_1".to_string(),
+            account_name: "personal".to_string(),
+            created: NaiveDateTime::parse_from_str("2024-05-31 23:30:00", "%Y-%m-%d %H:%M:%S")
+                .unwrap(),
+            amount: -280,
+            currency: "GBP".to_string(),
+            description: "TFL TRAVEL CH".to_string(),
+            category_name: "transport".to_string(),
+            merchant_name: Some("Transport for London".to_string()),
+            tags: Some("commute work".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_conditions() {
+        for (expression, expected) in [
+            (r#"merchant ~ "london""#, true),
+            (r#"description~"^TFL" and amount < -2"#, true),
+            ("amount >= -2.80 and category = TRANSPORT", true),
+            ("amount > -2.80", false),
+            ("tag = work", true),
+            ("tag != work", false),
+            ("notes ~ .", false),
+            // 23:30 UTC is the next day in London
+            ("date = 2024-06-01", true),
+        ] {
+            let filter: Filter = expression.parse().unwrap();
+            assert_eq!(
+                filter.matches(&tx(), Tz::Europe__London),
+                expected,
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in [
+            "",
+            "merchant",
+            r#"merchant ~ "TFL"#,
+            "colour = red",
+            "amount ~ 5",
+            "merchant < x",
+            "amount < five",
+            "merchant = a or merchant = b",
+        ] {
+            assert!(expression.parse::<Filter>().is_err(), "{expression}");
+        }
+    }
+}
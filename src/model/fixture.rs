@@ -0,0 +1,352 @@
+//! Fixtures
+//!
+//! Loads accounts, pots, categories, transactions and exchange rates from a
+//! JSON or YAML file.
+//! Accounts and transactions use the Monzo API's shapes (transactions may embed
+//! their merchant); pots add the `account_id` they belong to. Categories
+//! referenced by transactions but not listed are created with their id as name,
+//! as `update` does.
+//!
+//! The whole fixture is validated before anything is written: ids must be
+//! unique and every pot and transaction must belong to an account in the
+//! fixture or already in the database. Rows that already exist are skipped.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+
+use serde::Deserialize;
+use tracing_log::log::info;
+
+use super::{
+    account::{AccountForDB, AccountResponse},
+    category::Category,
+    dedupe::Source,
+    fx_rate::FxRate,
+    pot::{Pot, PotResponse},
+    transaction::TransactionResponse,
+    DatabasePool,
+};
+use crate::error::AppErrors as Error;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Fixture {
+    #[serde(default)]
+    pub accounts: Vec<AccountResponse>,
+    #[serde(default)]
+    pub pots: Vec<FixturePot>,
+    #[serde(default)]
+    pub categories: Vec<Category>,
+    #[serde(default)]
+    pub transactions: Vec<TransactionResponse>,
+    #[serde(default)]
+    pub fx_rates: Vec<FxRate>,
+}
+
+/// A pot and the id of the account it belongs to
+#[derive(Deserialize, Debug)]
+pub struct FixturePot {
+    pub account_id: String,
+    #[serde(flatten)]
+    pub pot: PotResponse,
+}
+
+/// Rows written by [`DatabasePool::load_fixture`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FixtureSummary {
+    pub accounts: usize,
+    pub pots: usize,
+    pub categories: usize,
+    pub transactions: usize,
+    pub fx_rates: usize,
+    /// Rows that were already in the database
+    pub skipped: usize,
+}
+
+impl Fixture {
+    /// Read a fixture file, choosing the format from the extension
+    /// (`.json`, or `.yaml`/`.yml`)
+    ///
+    /// # Errors
+    /// Will return an error if the file can't be read or parsed.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            Some("yaml" | "yml") => Self::from_yaml(&contents),
+            _ => Err(Error::Error(format!(
+                "Unknown fixture format for {}. Use a .json, .yaml or .yml file",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Parse a YAML fixture
+    ///
+    /// # Errors
+    /// Will return an error if the YAML is invalid.
+    pub fn from_yaml(contents: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(contents).map_err(|e| Error::Error(format!("Invalid fixture: {e}")))
+    }
+
+    // Collect every problem so the user can fix them in one pass
+    fn validate(&self, existing_accounts: &HashSet<String>) -> Result<(), Error> {
+        let mut problems = BTreeSet::new();
+
+        let mut accounts = HashSet::new();
+        for account in &self.accounts {
+            if !accounts.insert(account.id.as_str()) {
+                problems.insert(format!("duplicate account {}", account.id));
+            }
+        }
+        accounts.extend(existing_accounts.iter().map(String::as_str));
+
+        let mut pots = HashSet::new();
+        for FixturePot { account_id, pot } in &self.pots {
+            if !pots.insert(&pot.id) {
+                problems.insert(format!("duplicate pot {}", pot.id));
+            }
+            if !accounts.contains(account_id.as_str()) {
+                problems.insert(format!(
+                    "pot {} references unknown account {account_id}",
+                    pot.id
+                ));
+            }
+        }
+
+        let mut transactions = HashSet::new();
+        for tx in &self.transactions {
+            if !transactions.insert(&tx.id) {
+                problems.insert(format!("duplicate transaction {}", tx.id));
+            }
+            if !accounts.contains(tx.account_id.as_str()) {
+                problems.insert(format!(
+                    "transaction {} references unknown account {}",
+                    tx.id, tx.account_id
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            let problems: Vec<String> = problems.into_iter().collect();
+            Err(Error::Error(format!(
+                "Invalid fixture: {}",
+                problems.join("; ")
+            )))
+        }
+    }
+}
+
+impl DatabasePool {
+    /// Validate a fixture and write it to the database
+    ///
+    /// # Errors
+    /// Will return an error if the fixture is invalid or can't be saved.
+    pub async fn load_fixture(&self, fixture: &Fixture) -> Result<FixtureSummary, Error> {
+        let account_service = self.accounts();
+        let existing: Vec<AccountForDB> = account_service.read_accounts().await?;
+        let existing_ids: HashSet<String> = existing.iter().map(|a| a.id.clone()).collect();
+
+        fixture.validate(&existing_ids)?;
+
+        let mut summary = FixtureSummary::default();
+        let mut owner_types: HashMap<String, String> =
+            existing.into_iter().map(|a| (a.id, a.owner_type)).collect();
+
+        for account in &fixture.accounts {
+            owner_types.insert(account.id.clone(), account.owner_type.clone());
+            let account = AccountForDB {
+                id: account.id.clone(),
+                closed: account.closed,
+                created: account.created.naive_utc(),
+                description: account.description.clone(),
+                currency: account.currency.clone(),
+                country_code: account.country_code.clone(),
+                owner_type: account.owner_type.clone(),
+                account_number: account.account_number.clone(),
+                sort_code: account.sort_code.clone(),
+            };
+            count(
+                account_service.save_account(&account).await,
+                &mut summary.accounts,
+                &mut summary.skipped,
+            )?;
+        }
+
+        let pot_service = self.pots();
+        for FixturePot { account_id, pot } in &fixture.pots {
+            let pot = Pot {
+                id: pot.id.clone(),
+                name: pot.name.clone(),
+                balance: pot.balance,
+                currency: pot.currency.clone(),
+                deleted: pot.deleted,
+                pot_type: pot.pot_type.clone(),
+                account_name: owner_types.get(account_id).cloned().unwrap_or_default(),
+            };
+            count(
+                pot_service.save_pot(&pot).await,
+                &mut summary.pots,
+                &mut summary.skipped,
+            )?;
+        }
+
+        let category_service = self.categories();
+        let declared: HashSet<&str> = fixture.categories.iter().map(|c| c.id.as_str()).collect();
+        let undeclared: BTreeSet<&str> = fixture
+            .transactions
+            .iter()
+            .map(|tx| tx.category_id.as_str())
+            .filter(|id| !declared.contains(id))
+            .collect();
+        let categories = fixture
+            .categories
+            .iter()
+            .cloned()
+            .chain(undeclared.into_iter().map(|id| Category {
+                id: id.to_string(),
+                name: id.to_string(),
+                ..Default::default()
+            }));
+        for category in categories {
+            count(
+                category_service.save_category(&category).await,
+                &mut summary.categories,
+                &mut summary.skipped,
+            )?;
+        }
+
+        let tx_service = self.transactions();
+        for tx in &fixture.transactions {
+            count(
+                tx_service.save_transaction_from(tx, Source::Fixture).await,
+                &mut summary.transactions,
+                &mut summary.skipped,
+            )?;
+        }
+
+        let rate_service = self.fx_rates();
+        for rate in &fixture.fx_rates {
+            rate_service.save_rate(rate).await?;
+            summary.fx_rates += 1;
+        }
+
+        info!("Loaded fixture: {summary:?}");
+        Ok(summary)
+    }
+}
+
+// Tally a save, treating rows that already exist as skipped
+fn count(result: Result<(), Error>, saved: &mut usize, skipped: &mut usize) -> Result<(), Error> {
+    match result {
+        Ok(()) => *saved += 1,
+        Err(Error::Duplicate(_)) => *skipped += 1,
+        Err(e) => return Err(e),
+    }
+    Ok(())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    const FIXTURE: &str = r#"
+        accounts:
+          - id: acc_2
+            closed: false
+            created: "2024-01-01T00:00:00Z"
+            description: Business
+            currency: GBP
+            country_code: GB
+            owner_type: business
+            account_number: "87654321"
+            sort_code: "040004"
+        pots:
+          - { id: pot_2, account_id: acc_2, name: Tax, balance: 100, currency: GBP, deleted: false, type: default }
+        transactions:
+          - id: tx_a
+            account_id: acc_2
+            amount: -500
+            currency: GBP
+            local_amount: -500
+            local_currency: GBP
+            created: "2024-02-01T10:00:00Z"
+            description: STAPLES
+            category: office
+            merchant: { id: merch_1, name: Staples, category: office }
+          - id: tx_b
+            account_id: "1"
+            amount: 1000
+            currency: GBP
+            local_amount: 1000
+            local_currency: GBP
+            created: "2024-02-02T10:00:00Z"
+            description: REFUND
+            category: "1"
+        fx_rates:
+          - { date: "2024-02-01", currency: EUR, base: GBP, rate: 0.855 }
+    "#;
+
+    #[tokio::test]
+    async fn loads_a_valid_fixture() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let fixture = Fixture::from_yaml(FIXTURE).unwrap();
+
+        // Act
+        let summary = pool.load_fixture(&fixture).await.unwrap();
+
+        // Assert
+        assert_eq!(
+            summary,
+            FixtureSummary {
+                accounts: 1,
+                pots: 1,
+                categories: 1,
+                transactions: 2,
+                fx_rates: 1,
+                skipped: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn reloading_skips_existing_rows() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let fixture = Fixture::from_yaml(FIXTURE).unwrap();
+        pool.load_fixture(&fixture).await.unwrap();
+
+        // Act
+        let summary = pool.load_fixture(&fixture).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.transactions, 0);
+        assert_eq!(summary.skipped, 6);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_accounts_before_writing() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let fixture =
+            Fixture::from_yaml(&FIXTURE.replace("account_id: \"1\"", "account_id: acc_9")).unwrap();
+
+        // Act
+        let result = pool.load_fixture(&fixture).await;
+
+        // Assert
+        let Err(Error::Error(message)) = result else {
+            panic!("expected a validation error");
+        };
+        assert!(message.contains("tx_b references unknown account acc_9"));
+        let accounts = pool.accounts().read_accounts().await.unwrap();
+        assert_eq!(accounts.len(), 1);
+    }
+}
@@ -0,0 +1,197 @@
+//! Models for exchange rates
+//!
+//! Rates are recorded by `update` from foreign currency card payments, which
+//! report both the account and the local amount, and can be loaded from a
+//! fixture.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use super::{transaction::TransactionResponse, DatabasePool};
+use crate::{currency, error::AppErrors as Error};
+
+/// On `date`, one unit of `currency` was worth `rate` units of `base`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FxRate {
+    pub date: NaiveDate,
+    pub currency: String,
+    pub base: String,
+    pub rate: f64,
+}
+
+impl FxRate {
+    /// The rate applied to a transaction made in a foreign currency, if any
+    #[must_use]
+    pub fn implied(tx: &TransactionResponse) -> Option<Self> {
+        if tx.currency == tx.local_currency || tx.amount == 0 || tx.local_amount == 0 {
+            return None;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let rate = (tx.amount as f64 / 10_f64.powi(exponent(&tx.currency)))
+            / (tx.local_amount as f64 / 10_f64.powi(exponent(&tx.local_currency)));
+
+        Some(Self {
+            date: tx.created.date_naive(),
+            currency: tx.local_currency.clone(),
+            base: tx.currency.clone(),
+            rate,
+        })
+    }
+}
+
+fn exponent(iso_code: &str) -> i32 {
+    i32::try_from(currency::exponent(iso_code)).unwrap_or(i32::MAX)
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn save_rate(&self, rate: &FxRate) -> Result<(), Error>;
+    async fn latest_rates(&self, base: &str) -> Result<HashMap<String, f64>, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteFxRateService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteFxRateService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteFxRateService {
+    /// Save a rate, replacing any rate for the same pair on the same day
+    #[tracing::instrument(name = "Save fx rate", skip(self))]
+    async fn save_rate(&self, rate: &FxRate) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            r"
+                INSERT OR REPLACE INTO fx_rates (date, currency, base, rate)
+                VALUES ($1, $2, $3, $4)
+            ",
+            rate.date,
+            rate.currency,
+            rate.base,
+            rate.rate,
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The most recent rate into `base` of every currency with one, keyed by
+    /// currency. Rates recorded the other way round are inverted.
+    #[tracing::instrument(name = "Read latest fx rates", skip(self))]
+    async fn latest_rates(&self, base: &str) -> Result<HashMap<String, f64>, Error> {
+        let db = self.pool.db();
+
+        let inverse = sqlx::query!(
+            r#"
+                SELECT base AS "currency!", rate
+                FROM fx_rates r
+                WHERE currency = $1
+                AND date = (
+                    SELECT MAX(date) FROM fx_rates
+                    WHERE currency = r.currency AND base = r.base
+                )
+            "#,
+            base,
+        )
+        .fetch_all(db)
+        .await?;
+
+        let direct = sqlx::query!(
+            r#"
+                SELECT currency AS "currency!", rate
+                FROM fx_rates r
+                WHERE base = $1
+                AND date = (
+                    SELECT MAX(date) FROM fx_rates
+                    WHERE currency = r.currency AND base = r.base
+                )
+            "#,
+            base,
+        )
+        .fetch_all(db)
+        .await?;
+
+        // direct rates win over inverted ones
+        let mut rates: HashMap<String, f64> = inverse
+            .into_iter()
+            .filter(|row| row.rate != 0.0)
+            .map(|row| (row.currency, 1.0 / row.rate))
+            .collect();
+        rates.extend(direct.into_iter().map(|row| (row.currency, row.rate)));
+
+        Ok(rates)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    fn rate(date: &str, currency: &str, base: &str, rate: f64) -> FxRate {
+        FxRate {
+            date: date.parse().unwrap(),
+            currency: currency.to_string(),
+            base: base.to_string(),
+            rate,
+        }
+    }
+
+    #[test]
+    fn implied_rate_uses_major_units() {
+        let tx = TransactionResponse {
+            amount: -850,
+            currency: "GBP".to_string(),
+            local_amount: -150_000,
+            local_currency: "JPY".to_string(),
+            ..Default::default()
+        };
+
+        let implied = FxRate::implied(&tx).unwrap();
+
+        assert_eq!(implied.currency, "JPY");
+        assert_eq!(implied.base, "GBP");
+        assert!((implied.rate - 8.5 / 150_000.0).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn latest_rates_prefers_recent_and_direct_rates() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteFxRateService::new(pool);
+        for r in [
+            rate("2024-01-01", "EUR", "GBP", 0.80),
+            rate("2024-02-01", "EUR", "GBP", 0.85),
+            rate("2024-02-01", "GBP", "USD", 1.25),
+        ] {
+            service.save_rate(&r).await.unwrap();
+        }
+
+        // Act
+        let rates = service.latest_rates("GBP").await.unwrap();
+
+        // Assert
+        assert_eq!(rates.len(), 2);
+        assert!((rates["EUR"] - 0.85).abs() < 1e-9);
+        assert!((rates["USD"] - 0.8).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,124 @@
+//! Models for CSV imports
+//!
+//! When a row of an imported CSV export looks like a synced transaction, the
+//! way the conflict was resolved is recorded against the row's id, so the
+//! same export imports the same way every time.
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// How a conflicting CSV row was resolved
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct ImportResolution {
+    pub csv_id: String,
+    /// The synced transaction the row looked like
+    pub transaction_id: String,
+    /// `keep-api`, `keep-csv` or `keep-both`
+    pub resolution: String,
+    pub resolved: NaiveDateTime,
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn read_resolution(&self, csv_id: &str) -> Result<Option<ImportResolution>, Error>;
+    async fn save_resolution(&self, resolution: &ImportResolution) -> Result<(), Error>;
+    async fn overwrite_transaction(
+        &self,
+        transaction_id: &str,
+        amount: i64,
+        created: NaiveDateTime,
+    ) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteImportService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteImportService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteImportService {
+    /// The recorded resolution of a CSV row, if it has one
+    #[tracing::instrument(name = "Read import resolution", skip(self))]
+    async fn read_resolution(&self, csv_id: &str) -> Result<Option<ImportResolution>, Error> {
+        let db = self.pool.db();
+
+        let resolution = sqlx::query_as!(
+            ImportResolution,
+            r"
+                SELECT csv_id, transaction_id, resolution, resolved
+                FROM import_resolutions
+                WHERE csv_id = $1
+            ",
+            csv_id
+        )
+        .fetch_optional(db)
+        .await?;
+
+        Ok(resolution)
+    }
+
+    /// Record the resolution of a CSV row, replacing any earlier one
+    #[tracing::instrument(name = "Save import resolution", skip(self))]
+    async fn save_resolution(&self, resolution: &ImportResolution) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            r"
+                INSERT OR REPLACE INTO import_resolutions (csv_id, transaction_id, resolution, resolved)
+                VALUES ($1, $2, $3, $4)
+            ",
+            resolution.csv_id,
+            resolution.transaction_id,
+            resolution.resolution,
+            resolution.resolved
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the amount and time of a synced transaction with those of a CSV
+    /// row
+    #[tracing::instrument(name = "Overwrite transaction", skip(self))]
+    async fn overwrite_transaction(
+        &self,
+        transaction_id: &str,
+        amount: i64,
+        created: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            r"
+                UPDATE transactions
+                SET amount = $2,
+                    local_amount = CASE WHEN currency = local_currency THEN $2 ELSE local_amount END,
+                    created = $3
+                WHERE id = $1
+            ",
+            transaction_id,
+            amount,
+            created
+        )
+        .execute(db)
+        .await?;
+        self.pool.set_dedupe_keys(Some(transaction_id)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+//! Models for manual accounts
+//!
+//! Accounts held elsewhere (savings, ISAs, a mortgage) can't be synced, so
+//! their balances are recorded by hand as dated valuations. They are included
+//! in net worth and exports alongside the Monzo accounts.
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use strum_macros::{Display, EnumString};
+use tracing_log::log::info;
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// Whether a manual account holds or owes money
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ManualAccountKind {
+    Asset,
+    Liability,
+}
+
+/// An account held outside Monzo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManualAccount {
+    pub id: i64,
+    pub name: String,
+    pub kind: ManualAccountKind,
+    pub institution: Option<String>,
+    pub currency: String,
+    pub created: NaiveDateTime,
+}
+
+/// The balance of a manual account on a date, in minor units. Liabilities
+/// are negative.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ManualValuation {
+    pub account_id: i64,
+    pub date: NaiveDate,
+    pub balance: i64,
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn add_account(
+        &self,
+        name: &str,
+        kind: ManualAccountKind,
+        institution: Option<&str>,
+        currency: &str,
+    ) -> Result<i64, Error>;
+    async fn read_accounts(&self) -> Result<Vec<ManualAccount>, Error>;
+    async fn get_account(&self, name: &str) -> Result<Option<ManualAccount>, Error>;
+    async fn add_valuation(&self, valuation: &ManualValuation) -> Result<(), Error>;
+    async fn read_valuations(&self) -> Result<Vec<ManualValuation>, Error>;
+    async fn latest_valuations(&self) -> Result<Vec<ManualValuation>, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteManualService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteManualService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteManualService {
+    /// Create an account, returning its id
+    ///
+    /// # Errors
+    /// Will return a `Duplicate` error if an account with the name exists.
+    #[tracing::instrument(name = "Add manual account", skip(self))]
+    async fn add_account(
+        &self,
+        name: &str,
+        kind: ManualAccountKind,
+        institution: Option<&str>,
+        currency: &str,
+    ) -> Result<i64, Error> {
+        let db = self.pool.db();
+        if self.get_account(name).await?.is_some() {
+            return Err(Error::Duplicate(format!(
+                "Manual account '{name}' already exists"
+            )));
+        }
+
+        let kind = kind.to_string();
+        let created = Utc::now().naive_utc();
+        let id = sqlx::query!(
+            r"
+                INSERT INTO manual_accounts (name, kind, institution, currency, created)
+                VALUES ($1, $2, $3, $4, $5)
+            ",
+            name,
+            kind,
+            institution,
+            currency,
+            created,
+        )
+        .execute(db)
+        .await?
+        .last_insert_rowid();
+
+        info!("Added manual account {id}: {name}");
+        Ok(id)
+    }
+
+    #[tracing::instrument(name = "Read manual accounts", skip(self))]
+    async fn read_accounts(&self) -> Result<Vec<ManualAccount>, Error> {
+        let db = self.pool.db();
+
+        let rows = sqlx::query_as!(
+            AccountRow,
+            r"
+                SELECT id, name, kind, institution, currency, created
+                FROM manual_accounts
+                ORDER BY name
+            "
+        )
+        .fetch_all(db)
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    #[tracing::instrument(name = "Get manual account", skip(self))]
+    async fn get_account(&self, name: &str) -> Result<Option<ManualAccount>, Error> {
+        let db = self.pool.db();
+
+        let row = sqlx::query_as!(
+            AccountRow,
+            r"
+                SELECT id, name, kind, institution, currency, created
+                FROM manual_accounts
+                WHERE name = $1
+            ",
+            name,
+        )
+        .fetch_optional(db)
+        .await?;
+
+        row.map(TryInto::try_into).transpose()
+    }
+
+    /// Record a valuation, replacing any on the same date
+    #[tracing::instrument(name = "Add manual valuation", skip(self))]
+    async fn add_valuation(&self, valuation: &ManualValuation) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            r"
+                INSERT OR REPLACE INTO manual_valuations (account_id, date, balance)
+                VALUES ($1, $2, $3)
+            ",
+            valuation.account_id,
+            valuation.date,
+            valuation.balance,
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every valuation, by account and then date
+    #[tracing::instrument(name = "Read manual valuations", skip(self))]
+    async fn read_valuations(&self) -> Result<Vec<ManualValuation>, Error> {
+        let db = self.pool.db();
+
+        let valuations = sqlx::query_as!(
+            ManualValuation,
+            r"
+                SELECT account_id, date, balance
+                FROM manual_valuations
+                ORDER BY account_id, date
+            "
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(valuations)
+    }
+
+    /// The most recent valuation of each account that has one
+    #[tracing::instrument(name = "Read latest manual valuations", skip(self))]
+    async fn latest_valuations(&self) -> Result<Vec<ManualValuation>, Error> {
+        let db = self.pool.db();
+
+        let valuations = sqlx::query_as!(
+            ManualValuation,
+            r"
+                SELECT account_id, date, balance
+                FROM manual_valuations v
+                WHERE date = (
+                    SELECT MAX(date) FROM manual_valuations WHERE account_id = v.account_id
+                )
+                ORDER BY account_id
+            "
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(valuations)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+#[derive(sqlx::FromRow)]
+pub(crate) struct AccountRow {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) kind: String,
+    pub(crate) institution: Option<String>,
+    pub(crate) currency: String,
+    pub(crate) created: NaiveDateTime,
+}
+
+impl TryFrom<AccountRow> for ManualAccount {
+    type Error = Error;
+
+    fn try_from(row: AccountRow) -> Result<Self, Error> {
+        let kind = row
+            .kind
+            .parse()
+            .map_err(|_| Error::DbError(format!("Unknown manual account kind {}", row.kind)))?;
+
+        Ok(Self {
+            id: row.id,
+            name: row.name,
+            kind,
+            institution: row.institution,
+            currency: row.currency,
+            created: row.created,
+        })
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn latest_valuation_per_account() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteManualService::new(pool);
+        let isa = service
+            .add_account("ISA", ManualAccountKind::Asset, Some("Vanguard"), "GBP")
+            .await
+            .unwrap();
+        let mortgage = service
+            .add_account("Mortgage", ManualAccountKind::Liability, None, "GBP")
+            .await
+            .unwrap();
+        for (account_id, date, balance) in [
+            (isa, "2024-01-01", 1_000_000),
+            (isa, "2024-02-01", 1_050_000),
+            (mortgage, "2024-01-15", -15_000_000),
+        ] {
+            let valuation = ManualValuation {
+                account_id,
+                date: date.parse().unwrap(),
+                balance,
+            };
+            service.add_valuation(&valuation).await.unwrap();
+        }
+
+        // Act
+        let latest = service.latest_valuations().await.unwrap();
+
+        // Assert
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].balance, 1_050_000);
+        assert_eq!(latest[1].balance, -15_000_000);
+        let accounts = service.read_accounts().await.unwrap();
+        assert_eq!(accounts[0].institution.as_deref(), Some("Vanguard"));
+        assert_eq!(accounts[1].kind, ManualAccountKind::Liability);
+        assert!(matches!(
+            service
+                .add_account("ISA", ManualAccountKind::Asset, None, "GBP")
+                .await,
+            Err(Error::Duplicate(_))
+        ));
+    }
+}
@@ -1,24 +1,56 @@
 //! Models for the merchant endpoint
+//!
+//! Merchants are refreshed whenever a transaction reports them. Changes to a
+//! merchant's name, category or address are written to the `merchant_changes`
+//! log so renamed merchants can be traced.
 
 use async_trait::async_trait;
-use serde::Deserialize;
-use sqlx::{Pool, Sqlite};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer};
 use tracing_log::log::{error, info};
 
 use crate::error::AppErrors as Error;
 
-use super::DatabasePool;
+use super::{
+    category::{Service as CategoryService, SqliteCategoryService},
+    DatabasePool,
+};
 
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct Merchant {
     pub id: String,
     pub name: String,
-    pub category: String,
-    // pub logo: Option<String>,
-    // pub address: Address,
+    #[serde(rename = "category")]
+    pub category_id: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub logo: Option<String>,
+    #[serde(default)]
+    pub address: Option<Address>,
+}
+
+impl Merchant {
+    /// The formatted address, if the API reported one
+    #[must_use]
+    pub fn formatted_address(&self) -> Option<String> {
+        self.address
+            .as_ref()
+            .map(|address| address.formatted.clone())
+            .filter(|formatted| !formatted.is_empty())
+    }
+
+    /// The (latitude, longitude) of the merchant, if the API reported them.
+    /// Online merchants are reported at (0, 0), which is treated as unknown.
+    #[must_use]
+    pub fn location(&self) -> Option<(f64, f64)> {
+        self.address
+            .as_ref()
+            .map(|address| (address.latitude, address.longitude))
+            .filter(|&(latitude, longitude)| latitude != 0.0 || longitude != 0.0)
+    }
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
 pub struct Address {
     pub short_formatted: String,
     pub formatted: String,
@@ -31,12 +63,37 @@ pub struct Address {
     pub postcode: String,
 }
 
+/// A merchant logo and where it is cached
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct MerchantLogo {
+    pub merchant_id: String,
+    pub logo: String,
+    pub logo_path: Option<String>,
+}
+
+/// A change to one of a merchant's details
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct MerchantChange {
+    pub merchant_id: String,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed: NaiveDateTime,
+}
+
 // -- Services -------------------------------------------------------------------------
 
 #[async_trait]
 pub trait Service {
     async fn save_merchant(&self, merchant_fc: &Merchant) -> Result<String, Error>;
     async fn get_merchant(&self, merchant_id: &str) -> Result<Option<Merchant>, Error>;
+    async fn read_merchant_changes(
+        &self,
+        merchant_id: Option<&str>,
+    ) -> Result<Vec<MerchantChange>, Error>;
+    async fn prune_changes(&self, before: NaiveDateTime) -> Result<u64, Error>;
+    async fn read_logos(&self) -> Result<Vec<MerchantLogo>, Error>;
+    async fn set_logo_path(&self, merchant_id: &str, path: &str) -> Result<(), Error>;
 }
 
 #[derive(Debug, Clone)]
@@ -56,57 +113,121 @@ impl SqliteMerchantService {
 #[async_trait]
 impl Service for SqliteMerchantService {
     #[tracing::instrument(
-        name = "Create merchant",
+        name = "Save merchant",
         skip(self, merchant_fc),
-        fields(tx_id = %merchant_fc.id, merchant_id = %merchant_fc.id)
+        fields(merchant_id = %merchant_fc.id)
     )]
-    /// Save a merchant to the database returning the merchant id
+    /// Insert a merchant, or update it if its details have changed, returning
+    /// the merchant id. Either way its `last_seen` time is set to now.
     ///
     /// # Errors
-    /// Will return an error if the merchant already exists or create fails
+    /// Will return an error if the merchant can't be saved
     async fn save_merchant(&self, merchant_fc: &Merchant) -> Result<String, Error> {
         let db = self.pool.db();
 
-        if is_duplicate_merchant(db, &merchant_fc.id).await? {
-            info!("Merchant exists. Skipping");
-            return Err(Error::Duplicate("Merchant already exists".to_string()));
+        SqliteCategoryService::new(self.pool.clone())
+            .ensure_category(&merchant_fc.category_id)
+            .await?;
+
+        let now = Utc::now().naive_utc();
+        let address = merchant_fc.formatted_address();
+        let (latitude, longitude) = merchant_fc.location().unzip();
+
+        let Some(existing) = self.get_merchant(&merchant_fc.id).await? else {
+            return match sqlx::query!(
+                r"
+                    INSERT INTO merchants (
+                        id,
+                        name,
+                        category_id,
+                        address,
+                        latitude,
+                        longitude,
+                        last_seen,
+                        logo
+                    )
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ",
+                merchant_fc.id,
+                merchant_fc.name,
+                merchant_fc.category_id,
+                address,
+                latitude,
+                longitude,
+                now,
+                merchant_fc.logo,
+            )
+            .execute(db)
+            .await
+            {
+                Ok(_) => {
+                    info!("Created merchant: {:?}", merchant_fc.id);
+                    Ok(merchant_fc.id.clone())
+                }
+                Err(e) => {
+                    error!("Failed to create merchant: {:?}", merchant_fc.id);
+                    Err(Error::DbError(e.to_string()))
+                }
+            };
+        };
+
+        let mut tx = db.begin().await?;
+        for (field, old_value, new_value) in changes(&existing, merchant_fc) {
+            info!("Merchant {} {field} changed", merchant_fc.id);
+            sqlx::query!(
+                r"
+                    INSERT INTO merchant_changes (merchant_id, field, old_value, new_value, changed)
+                    VALUES ($1, $2, $3, $4, $5)
+                ",
+                merchant_fc.id,
+                field,
+                old_value,
+                new_value,
+                now,
+            )
+            .execute(&mut *tx)
+            .await?;
         }
 
-        match sqlx::query!(
+        // a merchant reported without an address or logo keeps the one stored,
+        // and a new logo drops the cached image of the old one
+        sqlx::query!(
             r"
-                INSERT INTO merchants (
-                    id,
-                    name,
-                    category
-                )
-                VALUES ($1, $2, $3)
+                UPDATE merchants
+                SET
+                    name = $2,
+                    category_id = $3,
+                    address = COALESCE($4, address),
+                    latitude = COALESCE($5, latitude),
+                    longitude = COALESCE($6, longitude),
+                    last_seen = $7,
+                    logo_path = CASE WHEN $8 IS NULL OR $8 = logo THEN logo_path END,
+                    logo = COALESCE($8, logo)
+                WHERE id = $1
             ",
             merchant_fc.id,
             merchant_fc.name,
-            merchant_fc.category,
+            merchant_fc.category_id,
+            address,
+            latitude,
+            longitude,
+            now,
+            merchant_fc.logo,
         )
-        .execute(db)
-        .await
-        {
-            Ok(_) => {
-                info!("Created merchant: {:?}", merchant_fc.id);
-                Ok(merchant_fc.id.clone())
-            }
-            Err(e) => {
-                error!("Failed to create merchant: {:?}", merchant_fc.id);
-                Err(Error::DbError(e.to_string()))
-            }
-        }
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(merchant_fc.id.clone())
     }
 
     #[tracing::instrument(name = "Get merchant")]
     async fn get_merchant(&self, merchant_id: &str) -> Result<Option<Merchant>, Error> {
         let db = self.pool.db();
 
-        let merchant = sqlx::query_as!(
-            Merchant,
+        let merchant = sqlx::query!(
             r"
-                SELECT *
+                SELECT id, name, category_id, logo, address, latitude, longitude
                 FROM merchants
                 WHERE id = $1
             ",
@@ -115,26 +236,132 @@ impl Service for SqliteMerchantService {
         .fetch_optional(db)
         .await?;
 
-        Ok(merchant)
+        Ok(merchant.map(|m| Merchant {
+            id: m.id,
+            name: m.name,
+            category_id: m.category_id,
+            logo: m.logo,
+            address: m.address.map(|formatted| Address {
+                formatted,
+                latitude: m.latitude.unwrap_or_default(),
+                longitude: m.longitude.unwrap_or_default(),
+                ..Default::default()
+            }),
+        }))
+    }
+
+    /// Read the change log, oldest first, for one merchant or all of them
+    #[tracing::instrument(name = "Read merchant changes", skip(self))]
+    async fn read_merchant_changes(
+        &self,
+        merchant_id: Option<&str>,
+    ) -> Result<Vec<MerchantChange>, Error> {
+        let db = self.pool.db();
+
+        let changes = sqlx::query_as!(
+            MerchantChange,
+            r"
+                SELECT merchant_id, field, old_value, new_value, changed
+                FROM merchant_changes
+                WHERE $1 IS NULL OR merchant_id = $1
+                ORDER BY id
+            ",
+            merchant_id,
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(changes)
+    }
+
+    /// Delete change log entries made before `before`, returning how many
+    #[tracing::instrument(name = "Prune merchant changes", skip(self))]
+    async fn prune_changes(&self, before: NaiveDateTime) -> Result<u64, Error> {
+        let db = self.pool.db();
+
+        let result = sqlx::query!("DELETE FROM merchant_changes WHERE changed < $1", before)
+            .execute(db)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Read the merchants that have a logo
+    #[tracing::instrument(name = "Read merchant logos", skip(self))]
+    async fn read_logos(&self) -> Result<Vec<MerchantLogo>, Error> {
+        let db = self.pool.db();
+
+        let logos = sqlx::query_as!(
+            MerchantLogo,
+            r#"
+                SELECT id AS merchant_id, logo AS "logo!", logo_path
+                FROM merchants
+                WHERE logo IS NOT NULL
+                ORDER BY id
+            "#,
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(logos)
+    }
+
+    /// Record where a merchant's logo is cached
+    #[tracing::instrument(name = "Set merchant logo path", skip(self))]
+    async fn set_logo_path(&self, merchant_id: &str, path: &str) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            "UPDATE merchants SET logo_path = $2 WHERE id = $1",
+            merchant_id,
+            path
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
     }
 }
 
 // -- Utility functions ----------------------------------------------------------------
 
-// Check if a merchant is a duplicate
-async fn is_duplicate_merchant(db: &Pool<Sqlite>, merchant_id: &str) -> Result<bool, Error> {
-    let existing_merchant = sqlx::query!(
-        r"
-            SELECT id
-            FROM merchants
-            WHERE id = $1
-        ",
-        merchant_id,
-    )
-    .fetch_optional(db)
-    .await?;
-
-    Ok(existing_merchant.is_some())
+// The API reports merchants without a logo with an empty string
+fn empty_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let logo: Option<String> = Option::deserialize(deserializer)?;
+    Ok(logo.filter(|logo| !logo.is_empty()))
+}
+
+// The fields that differ between the stored and the reported merchant, as
+// (field, old value, new value). A missing address is not a change.
+pub(crate) fn changes(
+    existing: &Merchant,
+    reported: &Merchant,
+) -> Vec<(&'static str, Option<String>, Option<String>)> {
+    let mut changes = Vec::new();
+
+    if existing.name != reported.name {
+        changes.push((
+            "name",
+            Some(existing.name.clone()),
+            Some(reported.name.clone()),
+        ));
+    }
+    if existing.category_id != reported.category_id {
+        changes.push((
+            "category_id",
+            Some(existing.category_id.clone()),
+            Some(reported.category_id.clone()),
+        ));
+    }
+    let address = reported.formatted_address();
+    if address.is_some() && address != existing.formatted_address() {
+        changes.push(("address", existing.formatted_address(), address));
+    }
+
+    changes
 }
 
 // -- Tests ----------------------------------------------------------------------------
@@ -173,4 +400,135 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap().unwrap().id, merchant.id);
     }
+
+    #[tokio::test]
+    async fn save_merchant_references_a_category() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteMerchantService::new(pool.clone());
+        let merchants = [
+            Merchant {
+                id: "merch_1".to_string(),
+                name: "Tesco".to_string(),
+                category_id: "groceries".to_string(),
+                ..Default::default()
+            },
+            Merchant {
+                id: "merch_2".to_string(),
+                name: "Pret".to_string(),
+                category_id: "1".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        // Act
+        for merchant in &merchants {
+            service.save_merchant(merchant).await.unwrap();
+        }
+
+        // Assert
+        let categories = sqlx::query!("SELECT id, name FROM categories ORDER BY id")
+            .fetch_all(pool.db())
+            .await
+            .unwrap();
+        let categories: Vec<(&str, &str)> = categories
+            .iter()
+            .map(|c| (c.id.as_str(), c.name.as_str()))
+            .collect();
+        // new categories are named after their id; existing names are kept
+        assert_eq!(
+            categories,
+            vec![("1", "category_1"), ("groceries", "groceries")]
+        );
+    }
+
+    #[tokio::test]
+    async fn save_merchant_updates_changed_details() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteMerchantService::new(pool);
+        let original = Merchant {
+            id: "merch_1".to_string(),
+            name: "Tesco".to_string(),
+            category_id: "groceries".to_string(),
+            logo: None,
+            address: Some(Address {
+                formatted: "1 High St".to_string(),
+                latitude: 51.5,
+                longitude: -0.12,
+                ..Default::default()
+            }),
+        };
+        service.save_merchant(&original).await.unwrap();
+        let renamed = Merchant {
+            name: "Tesco Express".to_string(),
+            address: None,
+            ..original.clone()
+        };
+
+        // Act
+        service.save_merchant(&original).await.unwrap();
+        service.save_merchant(&renamed).await.unwrap();
+
+        // Assert
+        let merchant = service.get_merchant("merch_1").await.unwrap().unwrap();
+        assert_eq!(merchant.name, "Tesco Express");
+        assert_eq!(merchant.formatted_address().as_deref(), Some("1 High St"));
+        assert_eq!(merchant.location(), Some((51.5, -0.12)));
+        let changes = service
+            .read_merchant_changes(Some("merch_1"))
+            .await
+            .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "name");
+        assert_eq!(changes[0].old_value.as_deref(), Some("Tesco"));
+        assert_eq!(changes[0].new_value.as_deref(), Some("Tesco Express"));
+    }
+
+    #[tokio::test]
+    async fn new_logo_clears_cached_path() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteMerchantService::new(pool);
+        let merchant = Merchant {
+            id: "merch_1".to_string(),
+            logo: Some("https://example.com/old.png".to_string()),
+            ..Default::default()
+        };
+        service.save_merchant(&merchant).await.unwrap();
+        service
+            .set_logo_path("merch_1", "logos/merch_1.png")
+            .await
+            .unwrap();
+        let unchanged = Merchant {
+            logo: None,
+            ..merchant.clone()
+        };
+        let changed = Merchant {
+            logo: Some("https://example.com/new.png".to_string()),
+            ..merchant.clone()
+        };
+
+        // Act
+        service.save_merchant(&unchanged).await.unwrap();
+        let kept = service.read_logos().await.unwrap();
+        service.save_merchant(&changed).await.unwrap();
+        let cleared = service.read_logos().await.unwrap();
+
+        // Assert
+        assert_eq!(kept[0].logo, "https://example.com/old.png");
+        assert_eq!(kept[0].logo_path.as_deref(), Some("logos/merch_1.png"));
+        assert_eq!(cleared[0].logo, "https://example.com/new.png");
+        assert_eq!(cleared[0].logo_path, None);
+    }
+
+    #[test]
+    fn empty_logo_is_none() {
+        let merchant: Merchant = serde_json::from_str(
+            r#"{"id": "merch_1", "name": "Tesco", "category": "groceries", "logo": ""}"#,
+        )
+        .unwrap();
+
+        assert_eq!(merchant.logo, None);
+    }
 }
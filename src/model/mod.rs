@@ -1,27 +1,115 @@
-use account::AccountForDB;
-use category::Category;
-use chrono::Utc;
-use pot::Pot;
+use fixture::Fixture;
+#[cfg(feature = "postgres")]
+use sqlx::PgPool;
 use sqlx::{
     sqlite::{SqliteConnectOptions, SqlitePoolOptions},
     SqlitePool,
 };
-use transaction::TransactionForDB;
 
-use crate::configuration::Settings;
 use crate::error::AppErrors as Error;
+use crate::{
+    configuration::{DatabaseBackend, PotDescriptions, Settings},
+    currency,
+};
 
 pub mod account;
+pub mod attachment;
 pub mod balance;
+pub mod budget_alert;
+pub mod cache;
+pub mod card_event;
 pub mod category;
+pub mod counterparty;
+pub mod dedupe;
+pub mod edit;
+pub mod filter;
+pub mod fixture;
+pub mod fx_rate;
+pub mod import;
+pub mod manual;
 pub mod merchant;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 pub mod pot;
+pub mod prune;
+pub mod query;
+pub mod sweep;
+pub mod sync_run;
 pub mod transaction;
+pub mod transfer;
+pub mod webhook;
+
+// The services of a pool, each backed by the pool's database
+macro_rules! services {
+    ($($(#[$doc:meta])* $name:ident: $module:ident::$sqlite:ident, $pg:ident;)*) => {
+        impl DatabasePool {
+            $(
+                $(#[$doc])*
+                #[must_use]
+                pub fn $name(&self) -> Box<dyn $module::Service + Send + Sync> {
+                    match &self.backend {
+                        Backend::Sqlite(_) => Box::new($module::$sqlite::new(self.clone())),
+                        #[cfg(feature = "postgres")]
+                        Backend::Postgres(_) => {
+                            Box::new(postgres::$module::$pg::new(self.clone()))
+                        }
+                    }
+                }
+            )*
+        }
+    };
+}
+
+services! {
+    /// Accounts synced from Monzo
+    accounts: account::SqliteAccountService, PgAccountService;
+    /// Transaction attachments and their downloads
+    attachments: attachment::SqliteAttachmentService, PgAttachmentService;
+    /// Balance snapshots
+    balances: balance::SqliteBalanceService, PgBalanceService;
+    /// Budget alerts posted this month
+    budget_alerts: budget_alert::SqliteBudgetAlertService, PgBudgetAlertService;
+    /// Zero-amount card events
+    card_events: card_event::SqliteCardEventService, PgCardEventService;
+    /// Categories and their display names
+    categories: category::SqliteCategoryService, PgCategoryService;
+    /// P2P counterparties
+    counterparties: counterparty::SqliteCounterpartyService, PgCounterpartyService;
+    /// Exchange rates
+    fx_rates: fx_rate::SqliteFxRateService, PgFxRateService;
+    /// Resolutions of CSV rows that looked like synced transactions
+    import_resolutions: import::SqliteImportService, PgImportService;
+    /// Accounts held outside Monzo and their valuations
+    manual_accounts: manual::SqliteManualService, PgManualService;
+    /// Merchants and their change log
+    merchants: merchant::SqliteMerchantService, PgMerchantService;
+    /// Pots synced from Monzo
+    pots: pot::SqlitePotService, PgPotService;
+    /// Pot sweeps made this month
+    sweeps: sweep::SqliteSweepService, PgSweepService;
+    /// The sync run audit log and its checkpoints
+    sync_runs: sync_run::SqliteSyncRunService, PgSyncRunService;
+    /// Transactions and the reads built on them
+    transactions: transaction::SqliteTransactionService, PgTransactionService;
+    /// Webhook deliveries and dead letters
+    webhook_deliveries: webhook::SqliteWebhookService, PgWebhookService;
+}
+
+// The database behind a pool
+#[derive(Debug, Clone)]
+enum Backend {
+    Sqlite(SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(PgPool),
+}
 
 /// A holder for a backing store. Allows swapping out implementations.
 #[derive(Debug, Clone)]
 pub struct DatabasePool {
-    pool: SqlitePool,
+    backend: Backend,
+    /// Smallest absolute amount in minor units of a transaction in reports
+    min_amount: i64,
+    pot_descriptions: PotDescriptions,
 }
 
 impl DatabasePool {
@@ -47,7 +135,14 @@ impl DatabasePool {
         // do a migration
         sqlx::migrate!("./migrations").run(&pool).await?;
 
-        Ok(DatabasePool { pool })
+        let pool = DatabasePool {
+            backend: Backend::Sqlite(pool),
+            min_amount: 0,
+            pot_descriptions: PotDescriptions::default(),
+        };
+        pool.set_dedupe_keys(None).await?;
+
+        Ok(pool)
     }
 
     /// Create a new database pool from the information in configuration
@@ -55,138 +150,126 @@ impl DatabasePool {
     /// # Errors
     /// Will return an error if configuration is not valid or the pool can't be created
     pub async fn new_from_config(config: Settings) -> Result<Self, Error> {
-        Self::new(
-            &config.database.database_path,
-            config.database.max_connections,
-        )
-        .await
+        let pool = match config.database.backend {
+            DatabaseBackend::Sqlite => {
+                Self::new(
+                    &config.database.database_path,
+                    config.database.max_connections,
+                )
+                .await?
+            }
+            #[cfg(feature = "postgres")]
+            DatabaseBackend::Postgres => {
+                let url = config.database.url.as_deref().ok_or_else(|| {
+                    Error::Error("Set `url` under [database] to use Postgres".to_string())
+                })?;
+                Self::new_postgres(url, config.database.max_connections).await?
+            }
+            #[cfg(not(feature = "postgres"))]
+            DatabaseBackend::Postgres => {
+                return Err(Error::Error(
+                    "This build can't connect to Postgres. Rebuild with `--features postgres`"
+                        .to_string(),
+                ))
+            }
+        };
+        let currency = config.base_currency.as_deref().unwrap_or("GBP");
+
+        Ok(pool
+            .with_min_amount(currency::from_major(config.filters.min_amount, currency))
+            .with_pot_descriptions(config.pots.descriptions))
+    }
+
+    /// Leave transactions smaller than `min_amount` minor units, in or out,
+    /// out of reports
+    #[must_use]
+    pub fn with_min_amount(self, min_amount: i64) -> Self {
+        Self {
+            min_amount: min_amount.abs(),
+            ..self
+        }
+    }
+
+    /// Smallest absolute amount in minor units of a transaction in reports
+    #[must_use]
+    pub fn min_amount(&self) -> i64 {
+        self.min_amount
+    }
+
+    /// Describe transfers to and from pots with these templates when reading
+    /// transactions for exports and tables
+    #[must_use]
+    pub fn with_pot_descriptions(self, pot_descriptions: PotDescriptions) -> Self {
+        Self {
+            pot_descriptions,
+            ..self
+        }
+    }
+
+    /// The templates describing transfers to and from pots
+    #[must_use]
+    pub fn pot_descriptions(&self) -> &PotDescriptions {
+        &self.pot_descriptions
     }
 
     /// Returns the sqlx db pool reference
     /// (only for the model layer)
+    ///
+    /// # Panics
+    /// Will panic if the pool is connected to Postgres. Use [`Self::sqlite`]
+    /// where that can happen.
     #[must_use]
     pub fn db(&self) -> &SqlitePool {
-        &self.pool
+        match &self.backend {
+            Backend::Sqlite(pool) => pool,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => panic!("The SQLite pool of a Postgres database was used"),
+        }
     }
 
-    /// Seed the test database with initial data
+    /// The sqlx db pool reference for work that only `SQLite` can do, such
+    /// as copying the database file
     ///
     /// # Errors
-    /// Will return an error if the seed data can't be inserted
-    pub async fn seed_initial_data(&self) -> Result<(), Error> {
-        let db = self.db();
-
-        // -- insert account --------------------------------------------------
-
-        let account = AccountForDB {
-            id: "1".to_string(),
-            closed: false,
-            created: Utc::now().naive_utc(),
-            description: "Main Account".to_string(),
-            currency: "GBP".to_string(),
-            country_code: "GB".to_string(),
-            owner_type: "personal".to_string(),
-            account_number: "12345678".to_string(),
-            sort_code: "12-34-56".to_string(),
-        };
-
-        sqlx::query!(
-            r#"
-            INSERT INTO accounts (
-                id, closed, created, description, currency, country_code, owner_type, account_number, sort_code
-            )
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-            "#,
-            account.id,
-            account.closed,
-            account.created,
-            account.description,
-            account.currency,
-            account.country_code,
-            account.owner_type,
-            account.account_number,
-            account.sort_code,
-        )
-        .execute(db)
-        .await?;
-
-        // -- insert pot --------------------------------------------------
-
-        let pot = Pot {
-            id: "1".to_string(),
-            name: "pot_name".to_string(),
-            balance: 1234,
-            currency: "GBP".to_string(),
-            deleted: false,
-            pot_type: "default".to_string(),
-            account_name: "personal".to_string(),
-        };
+    /// Will return an error naming `what` if the pool is connected to
+    /// Postgres.
+    #[cfg_attr(not(feature = "postgres"), allow(unused_variables))]
+    pub fn sqlite(&self, what: &str) -> Result<&SqlitePool, Error> {
+        match &self.backend {
+            Backend::Sqlite(pool) => Ok(pool),
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(_) => Err(Error::Error(format!(
+                "{what} needs an SQLite database, but the configured backend is Postgres"
+            ))),
+        }
+    }
 
-        sqlx::query!(
-            r#"
-            INSERT INTO pots (id, name, balance, currency, deleted, pot_type, account_name)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
-            "#,
-            pot.id,
-            pot.name,
-            pot.balance,
-            pot.currency,
-            pot.deleted,
-            pot.pot_type,
-            pot.account_name,
-        )
-        .execute(db)
-        .await?;
-
-        // -- insert category --------------------------------------------------
-
-        let category = Category {
-            id: "1".to_string(),
-            name: "category_1".to_string(),
-        };
+    /// The Postgres pool, if the database is Postgres
+    #[cfg(feature = "postgres")]
+    #[must_use]
+    pub fn postgres(&self) -> Option<&PgPool> {
+        match &self.backend {
+            Backend::Postgres(pool) => Some(pool),
+            Backend::Sqlite(_) => None,
+        }
+    }
 
-        sqlx::query!(
-            r#"
-            INSERT INTO categories (id, name)
-            VALUES (?1, ?2)
-            "#,
-            category.id,
-            category.name,
-        )
-        .execute(db)
-        .await?;
-
-        // -- insert transactions --------------------------------------------------
-
-        let mut tx1 = TransactionForDB::default();
-        tx1.id = "1".to_string();
-        tx1.account_id = account.id.clone();
-        tx1.category_id = category.id.clone();
-
-        let mut tx2 = TransactionForDB::default();
-        tx2.id = "2".to_string();
-        tx2.account_id = account.id.clone();
-        tx2.category_id = category.id.clone();
-
-        for tx in vec![tx1, tx2] {
-            sqlx::query!(
-                r#"
-                INSERT INTO transactions (id, account_id, amount, local_amount, currency, local_currency, description, created, category_id)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-                "#,
-                tx.id,
-                tx.account_id,
-                tx.amount,
-                tx.local_amount,
-                tx.currency,
-                tx.local_currency,
-                tx.description,
-                tx.created,
-                tx.category_id,
-            )
-            .execute(db)
-            .await?;
+    /// Close every connection, so the database file can be replaced
+    pub async fn close(&self) {
+        match &self.backend {
+            Backend::Sqlite(pool) => pool.close().await,
+            #[cfg(feature = "postgres")]
+            Backend::Postgres(pool) => pool.close().await,
         }
+    }
+
+    /// Seed the test database with initial data
+    ///
+    /// # Errors
+    /// Will return an error if the seed data can't be inserted
+    pub async fn seed_initial_data(&self) -> Result<(), Error> {
+        let fixture = Fixture::from_yaml(include_str!("../tests/fixtures/seed.yaml"))?;
+        self.load_fixture(&fixture).await?;
 
         Ok(())
     }
@@ -0,0 +1,115 @@
+//! Accounts in Postgres
+
+use async_trait::async_trait;
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        account::{AccountForDB, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgAccountService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgAccountService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgAccountService {
+    #[tracing::instrument(
+        name = "Creating account",
+        skip(self, acc_fc),
+        fields(id = %acc_fc.id)
+    )]
+    async fn save_account(&self, acc_fc: &AccountForDB) -> Result<(), Error> {
+        let result = sqlx::query(
+            r"
+                INSERT INTO accounts (
+                    id,
+                    closed,
+                    created,
+                    description,
+                    currency,
+                    country_code,
+                    owner_type,
+                    account_number,
+                    sort_code
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ON CONFLICT (id) DO NOTHING
+            ",
+        )
+        .bind(&acc_fc.id)
+        .bind(acc_fc.closed)
+        .bind(acc_fc.created)
+        .bind(&acc_fc.description)
+        .bind(&acc_fc.currency)
+        .bind(&acc_fc.country_code)
+        .bind(&acc_fc.owner_type)
+        .bind(&acc_fc.account_number)
+        .bind(&acc_fc.sort_code)
+        .execute(self.pool.pg())
+        .await?;
+
+        if result.rows_affected() == 0 {
+            info!("Account exists. Skipping");
+            return Err(Error::Duplicate("Account already exists".to_string()));
+        }
+        info!("Created account: {}", acc_fc.id);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Getting accounts", skip(self))]
+    async fn read_accounts(&self) -> Result<Vec<AccountForDB>, Error> {
+        let accounts = sqlx::query_as::<_, AccountForDB>(
+            r"
+                SELECT *
+                FROM accounts
+                ORDER BY created, id
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+        info!("Read {} accounts", accounts.len());
+
+        Ok(accounts)
+    }
+
+    /// Update the details Monzo can change, e.g. when an account is closed
+    #[tracing::instrument(
+        name = "Updating account",
+        skip(self, acc_fc),
+        fields(id = %acc_fc.id)
+    )]
+    async fn update_account(&self, acc_fc: &AccountForDB) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                UPDATE accounts
+                SET closed = $2,
+                    description = $3,
+                    account_number = $4,
+                    sort_code = $5
+                WHERE id = $1
+            ",
+        )
+        .bind(&acc_fc.id)
+        .bind(acc_fc.closed)
+        .bind(&acc_fc.description)
+        .bind(&acc_fc.account_number)
+        .bind(&acc_fc.sort_code)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+}
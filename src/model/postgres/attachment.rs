@@ -0,0 +1,117 @@
+//! Transaction attachments in Postgres
+
+use async_trait::async_trait;
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        attachment::{Attachment, AttachmentResponse, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgAttachmentService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgAttachmentService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgAttachmentService {
+    /// Store the attachments of a transaction that aren't stored yet,
+    /// returning the number stored
+    #[tracing::instrument(name = "Save attachments", skip(self, attachments))]
+    async fn save_attachments(
+        &self,
+        transaction_id: &str,
+        attachments: &[AttachmentResponse],
+    ) -> Result<usize, Error> {
+        let mut saved = 0;
+
+        for attachment in attachments {
+            saved += sqlx::query(
+                r"
+                    INSERT INTO attachments (id, transaction_id, url, file_type, created)
+                    VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT (id) DO NOTHING
+                ",
+            )
+            .bind(&attachment.id)
+            .bind(transaction_id)
+            .bind(&attachment.file_url)
+            .bind(&attachment.file_type)
+            .bind(attachment.created.naive_utc())
+            .execute(self.pool.pg())
+            .await?
+            .rows_affected();
+        }
+        if saved > 0 {
+            info!("Saved {saved} attachments for transaction: {transaction_id}");
+        }
+
+        Ok(usize::try_from(saved).unwrap_or(usize::MAX))
+    }
+
+    /// All stored attachments, grouped by transaction in the order attached
+    #[tracing::instrument(name = "Read attachments", skip(self))]
+    async fn read_attachments(&self) -> Result<Vec<Attachment>, Error> {
+        let attachments = sqlx::query_as::<_, Attachment>(
+            r"
+                SELECT
+                    a.id,
+                    a.transaction_id,
+                    t.created AS transaction_created,
+                    a.url,
+                    a.file_type,
+                    a.created,
+                    a.path,
+                    a.sha256
+                FROM attachments a
+                JOIN transactions t ON a.transaction_id = t.id
+                ORDER BY t.created, a.transaction_id, a.created, a.id
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(attachments)
+    }
+
+    /// Where a file with the given hash was downloaded to, if any
+    #[tracing::instrument(name = "Find attachment download", skip(self))]
+    async fn find_download(&self, sha256: &str) -> Result<Option<String>, Error> {
+        let path = sqlx::query_scalar(
+            r"
+                SELECT path
+                FROM attachments
+                WHERE sha256 = $1 AND path IS NOT NULL
+                LIMIT 1
+            ",
+        )
+        .bind(sha256)
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        Ok(path)
+    }
+
+    /// Record where an attachment was downloaded to
+    #[tracing::instrument(name = "Set attachment download", skip(self))]
+    async fn set_download(&self, id: &str, path: &str, sha256: &str) -> Result<(), Error> {
+        sqlx::query("UPDATE attachments SET path = $2, sha256 = $3 WHERE id = $1")
+            .bind(id)
+            .bind(path)
+            .bind(sha256)
+            .execute(self.pool.pg())
+            .await?;
+
+        Ok(())
+    }
+}
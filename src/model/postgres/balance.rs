@@ -0,0 +1,218 @@
+//! Balance snapshots in Postgres
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        balance::{
+            snapshot, AccountSnapshot, Balance, BalanceSnapshot, PotRow, Service, StoredBalance,
+        },
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgBalanceService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgBalanceService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+
+    // The accounts and pots of the snapshot `id`, in the order saved
+    async fn read_snapshot(&self, id: i64, taken: NaiveDateTime) -> Result<BalanceSnapshot, Error> {
+        let db = self.pool.pg();
+
+        let accounts: Vec<(String, i64, i64, String, i64)> = sqlx::query_as(
+            r"
+                SELECT account_id, balance, total_balance, currency, spend_today
+                FROM account_balances
+                WHERE snapshot_id = $1
+                ORDER BY seq
+            ",
+        )
+        .bind(id)
+        .fetch_all(db)
+        .await?;
+
+        let pots = sqlx::query_as::<_, PotRow>(
+            r"
+                SELECT account_id, pot_id, name, balance, currency
+                FROM pot_balances
+                WHERE snapshot_id = $1
+                ORDER BY seq
+            ",
+        )
+        .bind(id)
+        .fetch_all(db)
+        .await?;
+
+        let accounts = accounts
+            .into_iter()
+            .map(
+                |(account_id, balance, total_balance, currency, spend_today)| {
+                    let balance = Balance {
+                        balance,
+                        total_balance,
+                        currency,
+                        spend_today,
+                    };
+                    (account_id, balance)
+                },
+            )
+            .collect();
+
+        Ok(snapshot(id, taken, accounts, &pots))
+    }
+}
+
+#[async_trait]
+impl Service for PgBalanceService {
+    /// Record the balances of the given accounts, returning the snapshot id
+    #[tracing::instrument(name = "Save balance snapshot", skip(self, accounts))]
+    async fn save_snapshot(
+        &self,
+        taken: NaiveDateTime,
+        accounts: &[AccountSnapshot],
+    ) -> Result<i64, Error> {
+        let mut tx = self.pool.pg().begin().await?;
+
+        let snapshot_id: i64 =
+            sqlx::query_scalar("INSERT INTO balance_snapshots (taken) VALUES ($1) RETURNING id")
+                .bind(taken)
+                .fetch_one(&mut *tx)
+                .await?;
+
+        for account in accounts {
+            sqlx::query(
+                r"
+                    INSERT INTO account_balances (
+                        snapshot_id,
+                        account_id,
+                        balance,
+                        total_balance,
+                        currency,
+                        spend_today
+                    )
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                ",
+            )
+            .bind(snapshot_id)
+            .bind(&account.account_id)
+            .bind(account.balance.balance)
+            .bind(account.balance.total_balance)
+            .bind(&account.balance.currency)
+            .bind(account.balance.spend_today)
+            .execute(&mut *tx)
+            .await?;
+
+            for pot in &account.pots {
+                sqlx::query(
+                    r"
+                        INSERT INTO pot_balances (
+                            snapshot_id,
+                            account_id,
+                            pot_id,
+                            name,
+                            balance,
+                            currency
+                        )
+                        VALUES ($1, $2, $3, $4, $5, $6)
+                    ",
+                )
+                .bind(snapshot_id)
+                .bind(&account.account_id)
+                .bind(&pot.pot_id)
+                .bind(&pot.name)
+                .bind(pot.balance)
+                .bind(&pot.currency)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        info!("Saved balance snapshot: {snapshot_id}");
+
+        Ok(snapshot_id)
+    }
+
+    /// The most recent snapshot, with accounts and pots in the order saved
+    #[tracing::instrument(name = "Read latest balance snapshot", skip(self))]
+    async fn read_latest_snapshot(&self) -> Result<Option<BalanceSnapshot>, Error> {
+        let latest: Option<(i64, NaiveDateTime)> = sqlx::query_as(
+            r"
+                SELECT id, taken
+                FROM balance_snapshots
+                ORDER BY id DESC
+                LIMIT 1
+            ",
+        )
+        .fetch_optional(self.pool.pg())
+        .await?;
+        let Some((id, taken)) = latest else {
+            return Ok(None);
+        };
+
+        Ok(Some(self.read_snapshot(id, taken).await?))
+    }
+
+    /// The snapshots taken between `from` and `until`, oldest first
+    #[tracing::instrument(name = "Read balance snapshots", skip(self))]
+    async fn read_snapshots(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<BalanceSnapshot>, Error> {
+        let rows: Vec<(i64, NaiveDateTime)> = sqlx::query_as(
+            r"
+                SELECT id, taken
+                FROM balance_snapshots
+                WHERE taken BETWEEN $1 AND $2
+                ORDER BY taken, id
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        let mut snapshots = Vec::with_capacity(rows.len());
+        for (id, taken) in rows {
+            snapshots.push(self.read_snapshot(id, taken).await?);
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Every stored account balance, oldest first
+    #[tracing::instrument(name = "Read account balances", skip(self))]
+    async fn read_account_balances(&self) -> Result<Vec<StoredBalance>, Error> {
+        let balances: Vec<(NaiveDateTime, String, i64, String)> = sqlx::query_as(
+            r"
+                SELECT s.taken, b.account_id, b.balance, b.currency
+                FROM account_balances b
+                JOIN balance_snapshots s ON s.id = b.snapshot_id
+                ORDER BY s.taken, s.id, b.seq
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(balances
+            .into_iter()
+            .map(|(taken, account_id, balance, currency)| StoredBalance {
+                taken,
+                account_id,
+                balance,
+                currency,
+            })
+            .collect())
+    }
+}
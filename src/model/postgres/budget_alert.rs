@@ -0,0 +1,75 @@
+//! Budget alerts in Postgres
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::{
+    error::AppErrors as Error,
+    model::{budget_alert::Service, DatabasePool},
+};
+
+#[derive(Debug, Clone)]
+pub struct PgBudgetAlertService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgBudgetAlertService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgBudgetAlertService {
+    /// Whether the category's alert at `threshold` percent was posted for the
+    /// month
+    #[tracing::instrument(name = "Check budget alert", skip(self))]
+    async fn is_posted(
+        &self,
+        category: &str,
+        month: NaiveDate,
+        threshold: i64,
+    ) -> Result<bool, Error> {
+        let alert: Option<String> = sqlx::query_scalar(
+            r"
+                SELECT category FROM budget_alerts
+                WHERE category = $1 AND month = $2 AND threshold = $3
+            ",
+        )
+        .bind(category)
+        .bind(month.format("%Y-%m").to_string())
+        .bind(threshold)
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        Ok(alert.is_some())
+    }
+
+    /// Record that the category's alert at `threshold` percent was posted for
+    /// the month
+    #[tracing::instrument(name = "Record budget alert", skip(self))]
+    async fn record_posted(
+        &self,
+        category: &str,
+        month: NaiveDate,
+        threshold: i64,
+        posted: NaiveDateTime,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                INSERT INTO budget_alerts (category, month, threshold, posted)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(category)
+        .bind(month.format("%Y-%m").to_string())
+        .bind(threshold)
+        .bind(posted)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! Zero-amount card events in Postgres
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        card_event::{CardEvent, Service},
+        transaction::{insert_merchant, TransactionResponse},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgCardEventService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgCardEventService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgCardEventService {
+    #[tracing::instrument(
+        name = "Create card event",
+        skip(self, tx_resp),
+        fields(tx_id = %tx_resp.id, acc_id = %tx_resp.account_id)
+    )]
+    async fn save_card_event(&self, tx_resp: &TransactionResponse) -> Result<(), Error> {
+        let merchant_id = insert_merchant(self.pool.clone(), tx_resp.merchant.as_ref()).await?;
+        self.pool
+            .categories()
+            .ensure_category(&tx_resp.category_id)
+            .await?;
+
+        let result = sqlx::query(
+            r"
+                INSERT INTO card_events (
+                    id,
+                    account_id,
+                    merchant_id,
+                    currency,
+                    created,
+                    description,
+                    category_id
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (id) DO NOTHING
+            ",
+        )
+        .bind(&tx_resp.id)
+        .bind(&tx_resp.account_id)
+        .bind(merchant_id)
+        .bind(&tx_resp.currency)
+        .bind(tx_resp.created.naive_utc())
+        .bind(&tx_resp.description)
+        .bind(&tx_resp.category_id)
+        .execute(self.pool.pg())
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::Duplicate("Card event already exists".to_string()));
+        }
+        info!("Created card event: {}", tx_resp.id);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Read card events", skip(self))]
+    async fn read_card_events(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<CardEvent>, Error> {
+        let events = sqlx::query_as::<_, CardEvent>(
+            r"
+                SELECT
+                    e.id,
+                    e.account_id,
+                    a.owner_type AS account_name,
+                    e.currency,
+                    e.created,
+                    e.description,
+                    e.category_id,
+                    m.name AS merchant_name
+                FROM card_events e
+                INNER JOIN accounts a ON e.account_id = a.id
+                LEFT JOIN merchants m ON e.merchant_id = m.id
+                WHERE e.created BETWEEN $1 AND $2
+                ORDER BY e.created, e.id
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(events)
+    }
+}
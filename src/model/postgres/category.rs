@@ -0,0 +1,179 @@
+//! Categories in Postgres
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use tracing_log::log::info;
+
+use crate::{
+    configuration::CategoryDisplay,
+    error::AppErrors as Error,
+    model::{
+        category::{built_in, Category, CategoryUsage, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgCategoryService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgCategoryService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgCategoryService {
+    #[tracing::instrument(name = "Save category", skip(self, category_fc))]
+    async fn save_category(&self, category_fc: &Category) -> Result<(), Error> {
+        let (built_in_name, built_in_emoji) = built_in(&category_fc.id);
+        let display_name = category_fc.display_name.as_deref().or(built_in_name);
+        let emoji = category_fc.emoji.as_deref().or(built_in_emoji);
+
+        let result = sqlx::query(
+            r"
+                INSERT INTO categories (id, name, display_name, emoji)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (id) DO NOTHING
+            ",
+        )
+        .bind(&category_fc.id)
+        .bind(&category_fc.name)
+        .bind(display_name)
+        .bind(emoji)
+        .execute(self.pool.pg())
+        .await?;
+
+        if result.rows_affected() == 0 {
+            info!("Category exists. Skipping");
+            return Err(Error::Duplicate("Category already exists".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Create a category named after its id unless it already exists, so
+    /// rows referencing it satisfy the foreign key
+    #[tracing::instrument(name = "Ensure category", skip(self))]
+    async fn ensure_category(&self, category_id: &str) -> Result<(), Error> {
+        let (display_name, emoji) = built_in(category_id);
+        sqlx::query(
+            r"
+                INSERT INTO categories (id, name, display_name, emoji)
+                VALUES ($1, $1, $2, $3)
+                ON CONFLICT (id) DO NOTHING
+            ",
+        )
+        .bind(category_id)
+        .bind(display_name)
+        .bind(emoji)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Set the display names and emoji configured under `[categories]`, keyed
+    /// by category id or name. Returns the number of categories changed.
+    #[tracing::instrument(name = "Apply category display", skip(self, overrides))]
+    async fn apply_display(
+        &self,
+        overrides: &BTreeMap<String, CategoryDisplay>,
+    ) -> Result<u64, Error> {
+        let mut changed = 0;
+        for (key, display) in overrides {
+            changed += sqlx::query(
+                r"
+                    UPDATE categories
+                    SET
+                        display_name = COALESCE($2, display_name),
+                        emoji = COALESCE($3, emoji)
+                    WHERE (id = $1 OR lower(name) = lower($1))
+                    AND (
+                        display_name IS DISTINCT FROM COALESCE($2, display_name)
+                        OR emoji IS DISTINCT FROM COALESCE($3, emoji)
+                    )
+                ",
+            )
+            .bind(key)
+            .bind(&display.display_name)
+            .bind(&display.emoji)
+            .execute(self.pool.pg())
+            .await?
+            .rows_affected();
+        }
+
+        Ok(changed)
+    }
+
+    /// Read every stored category, ordered by id
+    #[tracing::instrument(name = "Read categories", skip(self))]
+    async fn read_categories(&self) -> Result<Vec<Category>, Error> {
+        let categories = sqlx::query_as::<_, Category>(
+            "SELECT id, name, display_name, emoji FROM categories ORDER BY id",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(categories)
+    }
+
+    /// Find a category by id, or else by name ignoring case
+    #[tracing::instrument(name = "Find category", skip(self))]
+    async fn find_category(&self, id_or_name: &str) -> Result<Option<Category>, Error> {
+        let category = sqlx::query_as::<_, Category>(
+            r"
+                SELECT id, name, display_name, emoji
+                FROM categories
+                WHERE id = $1 OR lower(name) = lower($1)
+                ORDER BY id != $1
+                LIMIT 1
+            ",
+        )
+        .bind(id_or_name)
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        Ok(category)
+    }
+
+    /// Count the transactions and card events of each category id they use
+    #[tracing::instrument(name = "Read category usage", skip(self))]
+    async fn read_usage(&self) -> Result<Vec<CategoryUsage>, Error> {
+        let usage: Vec<(String, i64)> = sqlx::query_as(
+            r"
+                SELECT category_id, COUNT(*)
+                FROM (
+                    SELECT category_id FROM transactions
+                    UNION ALL
+                    SELECT category_id FROM card_events
+                ) used
+                GROUP BY category_id
+                ORDER BY category_id
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(usage
+            .into_iter()
+            .map(|(id, count)| CategoryUsage { id, count })
+            .collect())
+    }
+
+    /// Change the stored name of a category
+    #[tracing::instrument(name = "Rename category", skip(self))]
+    async fn rename_category(&self, category_id: &str, name: &str) -> Result<(), Error> {
+        sqlx::query("UPDATE categories SET name = $2 WHERE id = $1")
+            .bind(category_id)
+            .bind(name)
+            .execute(self.pool.pg())
+            .await?;
+
+        Ok(())
+    }
+}
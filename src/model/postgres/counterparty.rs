@@ -0,0 +1,95 @@
+//! Transaction counterparties in Postgres
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        counterparty::{Counterparty, PersonTotal, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgCounterpartyService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgCounterpartyService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgCounterpartyService {
+    /// Store a P2P counterparty, updating their name if it has changed, and
+    /// return their id. Other counterparties aren't stored.
+    #[tracing::instrument(name = "Save counterparty", skip(self, counterparty))]
+    async fn save_counterparty(
+        &self,
+        counterparty: &Counterparty,
+    ) -> Result<Option<String>, Error> {
+        let (Some(id), Some(name)) = (&counterparty.user_id, counterparty.display_name()) else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            r"
+                INSERT INTO counterparties (id, name, account_number, sort_code)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (id) DO UPDATE SET
+                    name = excluded.name,
+                    account_number = COALESCE(excluded.account_number, counterparties.account_number),
+                    sort_code = COALESCE(excluded.sort_code, counterparties.sort_code)
+            ",
+        )
+        .bind(id)
+        .bind(name)
+        .bind(&counterparty.account_number)
+        .bind(&counterparty.sort_code)
+        .execute(self.pool.pg())
+        .await?;
+        info!("Saved counterparty: {id}");
+
+        Ok(Some(id.clone()))
+    }
+
+    /// Money sent and received per person between the given dates, most sent
+    /// first. Payments smaller than the pool's minimum amount are left out.
+    #[tracing::instrument(name = "Read people", skip(self))]
+    async fn read_people(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PersonTotal>, Error> {
+        let people = sqlx::query_as::<_, PersonTotal>(
+            r"
+                SELECT
+                    cp.id AS counterparty_id,
+                    cp.name,
+                    t.currency,
+                    COALESCE(SUM(CASE WHEN t.amount < 0 THEN -t.amount ELSE 0 END), 0)::BIGINT AS sent,
+                    COALESCE(SUM(CASE WHEN t.amount > 0 THEN t.amount ELSE 0 END), 0)::BIGINT AS received,
+                    COUNT(*) AS count
+                FROM transactions t
+                JOIN counterparties cp ON t.counterparty_id = cp.id
+                WHERE t.created BETWEEN $1 AND $2
+                AND NOT t.is_transfer
+                AND ABS(t.amount) >= $3
+                GROUP BY cp.id, t.currency
+                ORDER BY 4 DESC, cp.name
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .bind(self.pool.min_amount())
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(people)
+    }
+}
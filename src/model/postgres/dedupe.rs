@@ -0,0 +1,166 @@
+//! Dedupe keys in Postgres
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        dedupe::{dedupe_key, Source, TOLERANCE_DAYS},
+        DatabasePool,
+    },
+};
+
+// See [`DatabasePool::set_dedupe_keys`]
+pub(crate) async fn set_dedupe_keys(pool: &DatabasePool, id: Option<&str>) -> Result<u64, Error> {
+    let rows: Vec<(String, String, String, i64, String)> = sqlx::query_as(
+        r"
+            SELECT
+                t.id,
+                t.account_id,
+                t.currency,
+                t.amount,
+                COALESCE(m.name, cp.name, t.description) AS payee
+            FROM transactions t
+            LEFT JOIN merchants m ON t.merchant_id = m.id
+            LEFT JOIN counterparties cp ON t.counterparty_id = cp.id
+            WHERE t.id = $1 OR ($1 IS NULL AND t.dedupe_key IS NULL)
+        ",
+    )
+    .bind(id)
+    .fetch_all(pool.pg())
+    .await?;
+
+    for (id, account_id, currency, amount, payee) in &rows {
+        let key = dedupe_key(account_id, currency, *amount, payee);
+        sqlx::query("UPDATE transactions SET dedupe_key = $2 WHERE id = $1")
+            .bind(id)
+            .bind(key)
+            .execute(pool.pg())
+            .await?;
+    }
+
+    Ok(rows.len() as u64)
+}
+
+// See [`DatabasePool::find_duplicate`]
+pub(crate) async fn find_duplicate(
+    pool: &DatabasePool,
+    id: &str,
+    source: Source,
+) -> Result<Option<String>, Error> {
+    let Some((dedupe_key, created)): Option<(Option<String>, NaiveDateTime)> =
+        sqlx::query_as("SELECT dedupe_key, created FROM transactions WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool.pg())
+            .await?
+    else {
+        return Ok(None);
+    };
+
+    let tolerance = Duration::days(TOLERANCE_DAYS);
+    let (source, same) = if source.is_monzo() {
+        (Source::Api.as_str(), Source::Webhook.as_str())
+    } else {
+        (source.as_str(), source.as_str())
+    };
+    let duplicate = sqlx::query_scalar(
+        r"
+            SELECT id
+            FROM transactions
+            WHERE dedupe_key = $1
+            AND id != $2
+            AND source NOT IN ($3, $4)
+            AND created BETWEEN $5 AND $6
+            ORDER BY abs(EXTRACT(EPOCH FROM created - $7))
+            LIMIT 1
+        ",
+    )
+    .bind(dedupe_key)
+    .bind(id)
+    .bind(source)
+    .bind(same)
+    .bind(created - tolerance)
+    .bind(created + tolerance)
+    .bind(created)
+    .fetch_optional(pool.pg())
+    .await?;
+
+    Ok(duplicate)
+}
+
+// See [`DatabasePool::merge_transaction`]. Tags and an override that `into`
+// already has are dropped rather than moved.
+pub(crate) async fn merge_transaction(
+    pool: &DatabasePool,
+    from: &str,
+    into: &str,
+) -> Result<(), Error> {
+    let mut tx = pool.pg().begin().await?;
+
+    sqlx::query(
+        r"
+            UPDATE transaction_tags SET transaction_id = $2
+            WHERE transaction_id = $1
+            AND tag NOT IN (SELECT tag FROM transaction_tags WHERE transaction_id = $2)
+        ",
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM transaction_tags WHERE transaction_id = $1")
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r"
+            UPDATE category_overrides SET transaction_id = $2
+            WHERE transaction_id = $1
+            AND NOT EXISTS (SELECT 1 FROM category_overrides WHERE transaction_id = $2)
+        ",
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM category_overrides WHERE transaction_id = $1")
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE attachments SET transaction_id = $2 WHERE transaction_id = $1")
+        .bind(from)
+        .bind(into)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r"
+            INSERT INTO transaction_aliases (alias_id, transaction_id, source)
+            SELECT id, $2, source FROM transactions WHERE id = $1
+            ON CONFLICT (alias_id) DO UPDATE
+            SET transaction_id = excluded.transaction_id, source = excluded.source
+        ",
+    )
+    .bind(from)
+    .bind(into)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM transactions WHERE id = $1")
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// See [`DatabasePool::resolve_alias`]
+pub(crate) async fn resolve_alias(pool: &DatabasePool, id: &str) -> Result<Option<String>, Error> {
+    let transaction_id =
+        sqlx::query_scalar("SELECT transaction_id FROM transaction_aliases WHERE alias_id = $1")
+            .bind(id)
+            .fetch_optional(pool.pg())
+            .await?;
+
+    Ok(transaction_id)
+}
@@ -0,0 +1,94 @@
+//! Edits made to transactions by hand, in Postgres
+
+use chrono::NaiveDateTime;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        edit::{EditSummary, TransactionEdit, Uncategorised},
+        DatabasePool,
+    },
+};
+
+// Apply `edit` to the transactions with `ids`, see
+// [`DatabasePool::edit_transactions`]
+pub(crate) async fn edit_transactions(
+    pool: &DatabasePool,
+    ids: &[String],
+    edit: &TransactionEdit,
+    category_id: Option<&str>,
+    now: NaiveDateTime,
+) -> Result<EditSummary, Error> {
+    let mut summary = EditSummary::default();
+    let mut tx = pool.pg().begin().await?;
+    for id in ids {
+        if let Some(category_id) = category_id {
+            sqlx::query(
+                r"
+                    INSERT INTO category_overrides (transaction_id, category_id, created)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (transaction_id) DO UPDATE
+                    SET category_id = excluded.category_id, created = excluded.created
+                ",
+            )
+            .bind(id)
+            .bind(category_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+            summary.recategorised += sqlx::query(
+                "UPDATE transactions SET category_id = $2 WHERE id = $1 AND category_id != $2",
+            )
+            .bind(id)
+            .bind(category_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        for tag in &edit.tags {
+            summary.tagged += sqlx::query(
+                r"
+                    INSERT INTO transaction_tags (transaction_id, tag) VALUES ($1, $2)
+                    ON CONFLICT (transaction_id, tag) DO NOTHING
+                ",
+            )
+            .bind(id)
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+    }
+    tx.commit().await?;
+
+    Ok(summary)
+}
+
+// See [`DatabasePool::read_uncategorised`]
+pub(crate) async fn read_uncategorised(
+    pool: &DatabasePool,
+    categories: &[String],
+) -> Result<Vec<Uncategorised>, Error> {
+    let transactions = sqlx::query_as::<_, Uncategorised>(
+        r"
+            SELECT
+                t.id,
+                m.name AS merchant,
+                t.description,
+                t.amount,
+                t.currency
+            FROM transactions t
+            JOIN categories c ON t.category_id = c.id
+            LEFT JOIN merchants m ON t.merchant_id = m.id
+            WHERE (c.id = ANY($1) OR c.name = ANY($1))
+            AND NOT t.is_transfer
+            AND t.id NOT IN (SELECT transaction_id FROM category_overrides)
+            ORDER BY t.created, t.id
+        ",
+    )
+    .bind(categories)
+    .fetch_all(pool.pg())
+    .await?;
+
+    Ok(transactions)
+}
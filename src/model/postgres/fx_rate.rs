@@ -0,0 +1,95 @@
+//! Exchange rates in Postgres
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        fx_rate::{FxRate, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgFxRateService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgFxRateService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgFxRateService {
+    /// Save a rate, replacing any rate for the same pair on the same day
+    #[tracing::instrument(name = "Save fx rate", skip(self))]
+    async fn save_rate(&self, rate: &FxRate) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                INSERT INTO fx_rates (date, currency, base, rate)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (date, currency, base) DO UPDATE SET rate = excluded.rate
+            ",
+        )
+        .bind(rate.date)
+        .bind(&rate.currency)
+        .bind(&rate.base)
+        .bind(rate.rate)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    /// The most recent rate into `base` of every currency with one, keyed by
+    /// currency. Rates recorded the other way round are inverted.
+    #[tracing::instrument(name = "Read latest fx rates", skip(self))]
+    async fn latest_rates(&self, base: &str) -> Result<HashMap<String, f64>, Error> {
+        let db = self.pool.pg();
+
+        let inverse: Vec<(String, f64)> = sqlx::query_as(
+            r"
+                SELECT base, rate
+                FROM fx_rates r
+                WHERE currency = $1
+                AND date = (
+                    SELECT MAX(date) FROM fx_rates
+                    WHERE currency = r.currency AND base = r.base
+                )
+            ",
+        )
+        .bind(base)
+        .fetch_all(db)
+        .await?;
+
+        let direct: Vec<(String, f64)> = sqlx::query_as(
+            r"
+                SELECT currency, rate
+                FROM fx_rates r
+                WHERE base = $1
+                AND date = (
+                    SELECT MAX(date) FROM fx_rates
+                    WHERE currency = r.currency AND base = r.base
+                )
+            ",
+        )
+        .bind(base)
+        .fetch_all(db)
+        .await?;
+
+        // direct rates win over inverted ones
+        let mut rates: HashMap<String, f64> = inverse
+            .into_iter()
+            .filter(|(_, rate)| *rate != 0.0)
+            .map(|(currency, rate)| (currency, 1.0 / rate))
+            .collect();
+        rates.extend(direct);
+
+        Ok(rates)
+    }
+}
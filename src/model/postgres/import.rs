@@ -0,0 +1,95 @@
+//! CSV import resolutions in Postgres
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        import::{ImportResolution, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgImportService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgImportService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgImportService {
+    /// The recorded resolution of a CSV row, if it has one
+    #[tracing::instrument(name = "Read import resolution", skip(self))]
+    async fn read_resolution(&self, csv_id: &str) -> Result<Option<ImportResolution>, Error> {
+        let resolution = sqlx::query_as::<_, ImportResolution>(
+            r"
+                SELECT csv_id, transaction_id, resolution, resolved
+                FROM import_resolutions
+                WHERE csv_id = $1
+            ",
+        )
+        .bind(csv_id)
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        Ok(resolution)
+    }
+
+    /// Record the resolution of a CSV row, replacing any earlier one
+    #[tracing::instrument(name = "Save import resolution", skip(self))]
+    async fn save_resolution(&self, resolution: &ImportResolution) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                INSERT INTO import_resolutions (csv_id, transaction_id, resolution, resolved)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (csv_id) DO UPDATE SET
+                    transaction_id = excluded.transaction_id,
+                    resolution = excluded.resolution,
+                    resolved = excluded.resolved
+            ",
+        )
+        .bind(&resolution.csv_id)
+        .bind(&resolution.transaction_id)
+        .bind(&resolution.resolution)
+        .bind(resolution.resolved)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the amount and time of a synced transaction with those of a CSV
+    /// row
+    #[tracing::instrument(name = "Overwrite transaction", skip(self))]
+    async fn overwrite_transaction(
+        &self,
+        transaction_id: &str,
+        amount: i64,
+        created: NaiveDateTime,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                UPDATE transactions
+                SET amount = $2,
+                    local_amount = CASE WHEN currency = local_currency THEN $2 ELSE local_amount END,
+                    created = $3
+                WHERE id = $1
+            ",
+        )
+        .bind(transaction_id)
+        .bind(amount)
+        .bind(created)
+        .execute(self.pool.pg())
+        .await?;
+        self.pool.set_dedupe_keys(Some(transaction_id)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! Manual accounts in Postgres
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        manual::{AccountRow, ManualAccount, ManualAccountKind, ManualValuation, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgManualService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgManualService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgManualService {
+    /// Create an account, returning its id
+    ///
+    /// # Errors
+    /// Will return a `Duplicate` error if an account with the name exists.
+    #[tracing::instrument(name = "Add manual account", skip(self))]
+    async fn add_account(
+        &self,
+        name: &str,
+        kind: ManualAccountKind,
+        institution: Option<&str>,
+        currency: &str,
+    ) -> Result<i64, Error> {
+        let id: Option<i64> = sqlx::query_scalar(
+            r"
+                INSERT INTO manual_accounts (name, kind, institution, currency, created)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (name) DO NOTHING
+                RETURNING id
+            ",
+        )
+        .bind(name)
+        .bind(kind.to_string())
+        .bind(institution)
+        .bind(currency)
+        .bind(Utc::now().naive_utc())
+        .fetch_optional(self.pool.pg())
+        .await?;
+        let Some(id) = id else {
+            return Err(Error::Duplicate(format!(
+                "Manual account '{name}' already exists"
+            )));
+        };
+
+        info!("Added manual account {id}: {name}");
+        Ok(id)
+    }
+
+    #[tracing::instrument(name = "Read manual accounts", skip(self))]
+    async fn read_accounts(&self) -> Result<Vec<ManualAccount>, Error> {
+        let rows = sqlx::query_as::<_, AccountRow>(
+            r"
+                SELECT id, name, kind, institution, currency, created
+                FROM manual_accounts
+                ORDER BY name
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        rows.into_iter().map(TryInto::try_into).collect()
+    }
+
+    #[tracing::instrument(name = "Get manual account", skip(self))]
+    async fn get_account(&self, name: &str) -> Result<Option<ManualAccount>, Error> {
+        let row = sqlx::query_as::<_, AccountRow>(
+            r"
+                SELECT id, name, kind, institution, currency, created
+                FROM manual_accounts
+                WHERE name = $1
+            ",
+        )
+        .bind(name)
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        row.map(TryInto::try_into).transpose()
+    }
+
+    /// Record a valuation, replacing any on the same date
+    #[tracing::instrument(name = "Add manual valuation", skip(self))]
+    async fn add_valuation(&self, valuation: &ManualValuation) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                INSERT INTO manual_valuations (account_id, date, balance)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (account_id, date) DO UPDATE SET balance = excluded.balance
+            ",
+        )
+        .bind(valuation.account_id)
+        .bind(valuation.date)
+        .bind(valuation.balance)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every valuation, by account and then date
+    #[tracing::instrument(name = "Read manual valuations", skip(self))]
+    async fn read_valuations(&self) -> Result<Vec<ManualValuation>, Error> {
+        let valuations = sqlx::query_as::<_, ManualValuation>(
+            r"
+                SELECT account_id, date, balance
+                FROM manual_valuations
+                ORDER BY account_id, date
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(valuations)
+    }
+
+    /// The most recent valuation of each account that has one
+    #[tracing::instrument(name = "Read latest manual valuations", skip(self))]
+    async fn latest_valuations(&self) -> Result<Vec<ManualValuation>, Error> {
+        let valuations = sqlx::query_as::<_, ManualValuation>(
+            r"
+                SELECT account_id, date, balance
+                FROM manual_valuations v
+                WHERE date = (
+                    SELECT MAX(date) FROM manual_valuations WHERE account_id = v.account_id
+                )
+                ORDER BY account_id
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(valuations)
+    }
+}
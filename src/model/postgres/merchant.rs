@@ -0,0 +1,229 @@
+//! Merchants in Postgres
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        merchant::{changes, Address, Merchant, MerchantChange, MerchantLogo, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgMerchantService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgMerchantService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgMerchantService {
+    #[tracing::instrument(
+        name = "Save merchant",
+        skip(self, merchant_fc),
+        fields(merchant_id = %merchant_fc.id)
+    )]
+    /// Insert a merchant, or update it if its details have changed, returning
+    /// the merchant id. Either way its `last_seen` time is set to now.
+    ///
+    /// # Errors
+    /// Will return an error if the merchant can't be saved
+    async fn save_merchant(&self, merchant_fc: &Merchant) -> Result<String, Error> {
+        self.pool
+            .categories()
+            .ensure_category(&merchant_fc.category_id)
+            .await?;
+
+        let now = Utc::now().naive_utc();
+        let address = merchant_fc.formatted_address();
+        let (latitude, longitude) = merchant_fc.location().unzip();
+
+        let Some(existing) = self.get_merchant(&merchant_fc.id).await? else {
+            sqlx::query(
+                r"
+                    INSERT INTO merchants (
+                        id,
+                        name,
+                        category_id,
+                        address,
+                        latitude,
+                        longitude,
+                        last_seen,
+                        logo
+                    )
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ",
+            )
+            .bind(&merchant_fc.id)
+            .bind(&merchant_fc.name)
+            .bind(&merchant_fc.category_id)
+            .bind(address)
+            .bind(latitude)
+            .bind(longitude)
+            .bind(now)
+            .bind(&merchant_fc.logo)
+            .execute(self.pool.pg())
+            .await
+            .map_err(|e| Error::DbError(e.to_string()))?;
+            info!("Created merchant: {:?}", merchant_fc.id);
+
+            return Ok(merchant_fc.id.clone());
+        };
+
+        let mut tx = self.pool.pg().begin().await?;
+        for (field, old_value, new_value) in changes(&existing, merchant_fc) {
+            info!("Merchant {} {field} changed", merchant_fc.id);
+            sqlx::query(
+                r"
+                    INSERT INTO merchant_changes (merchant_id, field, old_value, new_value, changed)
+                    VALUES ($1, $2, $3, $4, $5)
+                ",
+            )
+            .bind(&merchant_fc.id)
+            .bind(field)
+            .bind(old_value)
+            .bind(new_value)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // a merchant reported without an address or logo keeps the one stored,
+        // and a new logo drops the cached image of the old one
+        sqlx::query(
+            r"
+                UPDATE merchants
+                SET
+                    name = $2,
+                    category_id = $3,
+                    address = COALESCE($4, address),
+                    latitude = COALESCE($5, latitude),
+                    longitude = COALESCE($6, longitude),
+                    last_seen = $7,
+                    logo_path = CASE WHEN $8 IS NULL OR $8 = logo THEN logo_path END,
+                    logo = COALESCE($8, logo)
+                WHERE id = $1
+            ",
+        )
+        .bind(&merchant_fc.id)
+        .bind(&merchant_fc.name)
+        .bind(&merchant_fc.category_id)
+        .bind(address)
+        .bind(latitude)
+        .bind(longitude)
+        .bind(now)
+        .bind(&merchant_fc.logo)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(merchant_fc.id.clone())
+    }
+
+    #[tracing::instrument(name = "Get merchant")]
+    async fn get_merchant(&self, merchant_id: &str) -> Result<Option<Merchant>, Error> {
+        #[allow(clippy::type_complexity)]
+        let merchant: Option<(
+            String,
+            String,
+            String,
+            Option<String>,
+            Option<String>,
+            Option<f64>,
+            Option<f64>,
+        )> = sqlx::query_as(
+            r"
+                SELECT id, name, category_id, logo, address, latitude, longitude
+                FROM merchants
+                WHERE id = $1
+            ",
+        )
+        .bind(merchant_id)
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        Ok(merchant.map(
+            |(id, name, category_id, logo, address, latitude, longitude)| Merchant {
+                id,
+                name,
+                category_id,
+                logo,
+                address: address.map(|formatted| Address {
+                    formatted,
+                    latitude: latitude.unwrap_or_default(),
+                    longitude: longitude.unwrap_or_default(),
+                    ..Default::default()
+                }),
+            },
+        ))
+    }
+
+    /// Read the change log, oldest first, for one merchant or all of them
+    #[tracing::instrument(name = "Read merchant changes", skip(self))]
+    async fn read_merchant_changes(
+        &self,
+        merchant_id: Option<&str>,
+    ) -> Result<Vec<MerchantChange>, Error> {
+        let changes = sqlx::query_as::<_, MerchantChange>(
+            r"
+                SELECT merchant_id, field, old_value, new_value, changed
+                FROM merchant_changes
+                WHERE $1 IS NULL OR merchant_id = $1
+                ORDER BY id
+            ",
+        )
+        .bind(merchant_id)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(changes)
+    }
+
+    /// Delete change log entries made before `before`, returning how many
+    #[tracing::instrument(name = "Prune merchant changes", skip(self))]
+    async fn prune_changes(&self, before: NaiveDateTime) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM merchant_changes WHERE changed < $1")
+            .bind(before)
+            .execute(self.pool.pg())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Read the merchants that have a logo
+    #[tracing::instrument(name = "Read merchant logos", skip(self))]
+    async fn read_logos(&self) -> Result<Vec<MerchantLogo>, Error> {
+        let logos = sqlx::query_as::<_, MerchantLogo>(
+            r"
+                SELECT id AS merchant_id, logo, logo_path
+                FROM merchants
+                WHERE logo IS NOT NULL
+                ORDER BY id
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(logos)
+    }
+
+    /// Record where a merchant's logo is cached
+    #[tracing::instrument(name = "Set merchant logo path", skip(self))]
+    async fn set_logo_path(&self, merchant_id: &str, path: &str) -> Result<(), Error> {
+        sqlx::query("UPDATE merchants SET logo_path = $2 WHERE id = $1")
+            .bind(merchant_id)
+            .bind(path)
+            .execute(self.pool.pg())
+            .await?;
+
+        Ok(())
+    }
+}
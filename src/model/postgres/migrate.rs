@@ -0,0 +1,439 @@
+//! Copying the database to Postgres
+//!
+//! The target is brought up to date with the Postgres migrations, and the
+//! rows of each table are copied in batches in an order where every table
+//! comes after the tables it references. The copy runs in one Postgres
+//! transaction, so a failure leaves the target as it was, and row counts are
+//! compared at the end. The query cache isn't copied.
+
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::{
+    postgres::{PgPoolOptions, Postgres},
+    sqlite::SqliteRow,
+    QueryBuilder, Row,
+};
+use tracing_log::log::info;
+
+use super::MIGRATOR;
+use crate::{error::AppErrors as Error, model::DatabasePool};
+
+/// Tables that aren't copied: sqlx's migration history, which the target
+/// keeps itself, and cached results
+const SKIPPED: [&str; 2] = ["_sqlx_migrations", "query_cache"];
+
+/// Rows read from the database and written to the target at a time
+const BATCH_SIZE: usize = 500;
+
+/// The most parameters Postgres allows in one statement
+const MAX_PARAMETERS: usize = 65_535;
+
+/// A table being copied, with its rows and those copied so far
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableCopy {
+    pub table: String,
+    pub rows: i64,
+    pub copied: i64,
+}
+
+/// A column's type in the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Real,
+    Boolean,
+    Date,
+    Timestamp,
+    Text,
+    Bytes,
+}
+
+impl ColumnType {
+    /// The type for a column declared as `declared`, by `SQLite`'s affinity
+    /// rules with dates and booleans kept
+    #[must_use]
+    pub fn from_declared(declared: &str) -> Self {
+        let declared = declared.to_ascii_uppercase();
+        if declared.contains("INT") {
+            Self::Integer
+        } else if declared.contains("BOOL") {
+            Self::Boolean
+        } else if declared.contains("TIME") {
+            Self::Timestamp
+        } else if declared.contains("DATE") {
+            Self::Date
+        } else if ["CHAR", "CLOB", "TEXT"]
+            .iter()
+            .any(|t| declared.contains(t))
+        {
+            Self::Text
+        } else if ["REAL", "FLOA", "DOUB", "NUM", "DEC"]
+            .iter()
+            .any(|t| declared.contains(t))
+        {
+            Self::Real
+        } else {
+            Self::Bytes
+        }
+    }
+}
+
+// A value read from SQLite as its column's type
+#[derive(Debug, Clone)]
+enum Value {
+    Integer(Option<i64>),
+    Real(Option<f64>),
+    Boolean(Option<bool>),
+    Date(Option<NaiveDate>),
+    Timestamp(Option<NaiveDateTime>),
+    Text(Option<String>),
+    Bytes(Option<Vec<u8>>),
+}
+
+impl Value {
+    // SQLite doesn't enforce declared types, so values are converted by
+    // SQLite rather than checked against them
+    fn read(row: &SqliteRow, i: usize, kind: ColumnType) -> Result<Self, sqlx::Error> {
+        Ok(match kind {
+            ColumnType::Integer => Self::Integer(row.try_get_unchecked(i)?),
+            ColumnType::Real => Self::Real(row.try_get_unchecked(i)?),
+            ColumnType::Boolean => Self::Boolean(row.try_get_unchecked(i)?),
+            ColumnType::Date => Self::Date(row.try_get_unchecked(i)?),
+            ColumnType::Timestamp => Self::Timestamp(row.try_get_unchecked(i)?),
+            ColumnType::Text => Self::Text(row.try_get_unchecked(i)?),
+            ColumnType::Bytes => Self::Bytes(row.try_get_unchecked(i)?),
+        })
+    }
+
+    fn bind(&self, values: &mut sqlx::query_builder::Separated<'_, '_, Postgres, &'static str>) {
+        match self.clone() {
+            Self::Integer(value) => values.push_bind(value),
+            Self::Real(value) => values.push_bind(value),
+            Self::Boolean(value) => values.push_bind(value),
+            Self::Date(value) => values.push_bind(value),
+            Self::Timestamp(value) => values.push_bind(value),
+            Self::Text(value) => values.push_bind(value),
+            Self::Bytes(value) => values.push_bind(value),
+        };
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Column {
+    name: String,
+    kind: ColumnType,
+    primary_key: i64,
+}
+
+#[derive(Debug, Clone)]
+struct ForeignKey {
+    table: String,
+    from: Vec<String>,
+    // missing for keys that reference the primary key implicitly
+    to: Vec<Option<String>>,
+}
+
+#[derive(Debug, Clone)]
+struct Table {
+    name: String,
+    columns: Vec<Column>,
+    foreign_keys: Vec<ForeignKey>,
+}
+
+impl Table {
+    fn primary_key(&self) -> Vec<&Column> {
+        let mut key: Vec<&Column> = self.columns.iter().filter(|c| c.primary_key > 0).collect();
+        key.sort_by_key(|c| c.primary_key);
+        key
+    }
+
+    // A single integer primary key, which SQLite fills in like a sequence
+    fn identity(&self) -> Option<&Column> {
+        match self.primary_key().as_slice() {
+            [column] if column.kind == ColumnType::Integer => Some(column),
+            _ => None,
+        }
+    }
+}
+
+impl DatabasePool {
+    /// Copy every table to the empty Postgres database at `url`, calling
+    /// `progress` after each batch, and return the tables with the rows
+    /// counted in the target
+    ///
+    /// # Errors
+    /// Will return an error if the database isn't `SQLite`, the target can't
+    /// be reached or migrated or already has rows, a row can't be copied, or
+    /// the row counts differ afterwards.
+    pub async fn migrate_to(
+        &self,
+        url: &str,
+        mut progress: impl FnMut(&TableCopy),
+    ) -> Result<Vec<TableCopy>, Error> {
+        if !url.starts_with("postgres://") && !url.starts_with("postgresql://") {
+            return Err(Error::Error(format!(
+                "'{}' isn't a postgres:// url",
+                redact(url)
+            )));
+        }
+        let tables = self.tables().await?;
+        let target = PgPoolOptions::new().max_connections(1).connect(url).await?;
+
+        let mut tx = target.begin().await?;
+        MIGRATOR.run(&mut *tx).await?;
+        let mut existing = Vec::new();
+        for table in &tables {
+            let rows: bool = sqlx::query_scalar(&format!(
+                "SELECT EXISTS (SELECT 1 FROM {})",
+                quote(&table.name)
+            ))
+            .fetch_one(&mut *tx)
+            .await?;
+            if rows {
+                existing.push(table.name.as_str());
+            }
+        }
+        if !existing.is_empty() {
+            return Err(Error::Error(format!(
+                "The target already has rows in {}. Migrate to an empty database",
+                existing.join(", ")
+            )));
+        }
+
+        let mut copies = Vec::new();
+        for table in &tables {
+            copies.push(self.copy_table(&mut tx, table, &mut progress).await?);
+        }
+        tx.commit().await?;
+
+        // verify
+        let mut mismatched = Vec::new();
+        for copy in &mut copies {
+            copy.copied =
+                sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", quote(&copy.table)))
+                    .fetch_one(&target)
+                    .await?;
+            if copy.copied != copy.rows {
+                mismatched.push(format!("{} ({} of {})", copy.table, copy.copied, copy.rows));
+            }
+        }
+        target.close().await;
+        if !mismatched.is_empty() {
+            return Err(Error::Error(format!(
+                "Row counts differ after copying: {}",
+                mismatched.join(", ")
+            )));
+        }
+        info!("Copied {} tables to {}", copies.len(), redact(url));
+
+        Ok(copies)
+    }
+
+    // Copy the rows of `table` to the target
+    async fn copy_table(
+        &self,
+        tx: &mut sqlx::Transaction<'_, Postgres>,
+        table: &Table,
+        progress: &mut impl FnMut(&TableCopy),
+    ) -> Result<TableCopy, Error> {
+        let db = self.sqlite("db migrate-to")?;
+        let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", quote(&table.name)))
+            .fetch_one(db)
+            .await?;
+        let mut copy = TableCopy {
+            table: table.name.clone(),
+            rows,
+            copied: 0,
+        };
+        progress(&copy);
+
+        let columns = list(table.columns.iter().map(|c| &c.name));
+        let batch = BATCH_SIZE.min(MAX_PARAMETERS / table.columns.len().max(1));
+        let mut after = i64::MIN;
+        loop {
+            let page = sqlx::query(&format!(
+                "SELECT rowid, {columns} FROM {} WHERE rowid > $1 ORDER BY rowid LIMIT $2",
+                quote(&table.name)
+            ))
+            .bind(after)
+            .bind(i64::try_from(batch).unwrap_or(i64::MAX))
+            .fetch_all(db)
+            .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = last.try_get(0)?;
+            let page = page
+                .iter()
+                .map(|row| {
+                    table
+                        .columns
+                        .iter()
+                        .enumerate()
+                        .map(|(i, column)| Value::read(row, i + 1, column.kind))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+            let mut insert: QueryBuilder<Postgres> =
+                QueryBuilder::new(format!("INSERT INTO {} ({columns}) ", quote(&table.name)));
+            insert.push_values(page.iter(), |mut values, row| {
+                for value in row {
+                    value.bind(&mut values);
+                }
+            });
+            insert.build().execute(&mut **tx).await?;
+
+            copy.copied += i64::try_from(page.len()).unwrap_or(i64::MAX);
+            progress(&copy);
+        }
+
+        if let Some(column) = table.identity() {
+            // carry on numbering after the copied rows
+            sqlx::query(&format!(
+                "SELECT setval(pg_get_serial_sequence($1, $2), COALESCE(MAX({}), 0) + 1, false) FROM {}",
+                quote(&column.name),
+                quote(&table.name)
+            ))
+            .bind(quote(&table.name))
+            .bind(&column.name)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(copy)
+    }
+
+    // The tables to copy, each after the tables it references
+    async fn tables(&self) -> Result<Vec<Table>, Error> {
+        let db = self.sqlite("db migrate-to")?;
+        let names: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT IN ($1, $2)
+             ORDER BY name",
+        )
+        .bind(SKIPPED[0])
+        .bind(SKIPPED[1])
+        .fetch_all(db)
+        .await?;
+
+        let mut tables = Vec::new();
+        for name in names {
+            let columns =
+                sqlx::query("SELECT name, type, pk FROM pragma_table_info($1) ORDER BY cid")
+                    .bind(&name)
+                    .fetch_all(db)
+                    .await?
+                    .iter()
+                    .map(|row| {
+                        Ok(Column {
+                            name: row.try_get(0)?,
+                            kind: ColumnType::from_declared(&row.try_get::<String, _>(1)?),
+                            primary_key: row.try_get(2)?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+            let mut foreign_keys: BTreeMap<i64, ForeignKey> = BTreeMap::new();
+            for row in sqlx::query(
+                r#"SELECT id, "table", "from", "to" FROM pragma_foreign_key_list($1) ORDER BY id, seq"#,
+            )
+            .bind(&name)
+            .fetch_all(db)
+            .await?
+            {
+                let key = foreign_keys.entry(row.try_get(0)?).or_insert(ForeignKey {
+                    table: row.try_get(1)?,
+                    from: Vec::new(),
+                    to: Vec::new(),
+                });
+                key.from.push(row.try_get(2)?);
+                key.to.push(row.try_get(3)?);
+            }
+
+            tables.push(Table {
+                name,
+                columns,
+                foreign_keys: foreign_keys.into_values().collect(),
+            });
+        }
+
+        dependency_order(tables)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// `tables` with each after the tables it references
+fn dependency_order(mut tables: Vec<Table>) -> Result<Vec<Table>, Error> {
+    let mut ordered: Vec<Table> = Vec::new();
+    while !tables.is_empty() {
+        let ready = tables.iter().position(|table| {
+            table
+                .foreign_keys
+                .iter()
+                .all(|key| key.table == table.name || ordered.iter().any(|t| t.name == key.table))
+        });
+        let Some(ready) = ready else {
+            let names: Vec<&str> = tables.iter().map(|t| t.name.as_str()).collect();
+            return Err(Error::Error(format!(
+                "Can't order tables that reference each other: {}",
+                names.join(", ")
+            )));
+        };
+        ordered.push(tables.remove(ready));
+    }
+
+    Ok(ordered)
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn list<S: AsRef<str>>(names: impl IntoIterator<Item = S>) -> String {
+    names
+        .into_iter()
+        .map(|name| quote(name.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `url` without its password, for messages
+#[must_use]
+pub fn redact(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(Some("***"));
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn tables_come_after_the_tables_they_reference() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+
+        // Act
+        let tables = pool.tables().await.unwrap();
+
+        // Assert
+        let position = |name: &str| tables.iter().position(|t| t.name == name).unwrap();
+        assert!(position("accounts") < position("transactions"));
+        assert!(position("categories") < position("transactions"));
+        assert!(position("transactions") < position("transaction_tags"));
+        assert!(position("account_balances") < position("pot_balances"));
+        assert!(!tables.iter().any(|t| SKIPPED.contains(&t.name.as_str())));
+        assert_eq!(ColumnType::from_declared("DATETIME"), ColumnType::Timestamp);
+        assert_eq!(ColumnType::from_declared("DATE"), ColumnType::Date);
+    }
+}
@@ -0,0 +1,407 @@
+//! The Postgres backend
+//!
+//! With `backend = "postgres"` under `[database]`, the pool connects to the
+//! server at `url` and the services below replace the `SQLite` ones, so a
+//! household can share one database. The schema is kept by the migrations in
+//! `migrations/postgres`, which mirror the `SQLite` ones.
+//!
+//! Queries are checked at runtime rather than by sqlx's macros, which can only
+//! check against one database. Aggregates aren't cached, and commands that
+//! work on the database file (`backup`, `restore`, `export anonymise`,
+//! `query`) need `SQLite`.
+
+use sqlx::{
+    migrate::Migrator,
+    postgres::{PgConnectOptions, PgPoolOptions},
+    PgPool,
+};
+
+use super::{Backend, DatabasePool};
+use crate::{configuration::PotDescriptions, error::AppErrors as Error};
+
+pub mod account;
+pub mod attachment;
+pub mod balance;
+pub mod budget_alert;
+pub mod card_event;
+pub mod category;
+pub mod counterparty;
+pub(crate) mod dedupe;
+pub(crate) mod edit;
+pub mod fx_rate;
+pub mod import;
+pub mod manual;
+pub mod merchant;
+mod migrate;
+pub mod pot;
+pub mod sweep;
+pub mod sync_run;
+pub mod transaction;
+pub(crate) mod transfer;
+pub mod webhook;
+
+pub use migrate::{redact, ColumnType, TableCopy};
+
+/// The Postgres schema
+pub(crate) static MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+impl DatabasePool {
+    /// Connect to the Postgres database at `url` and bring its schema up to
+    /// date
+    ///
+    /// # Errors
+    /// Will return an error if `url` isn't valid or the database can't be
+    /// reached or migrated.
+    #[tracing::instrument(name = "Creating a Postgres pool", skip(url))]
+    pub async fn new_postgres(url: &str, max_connections: u32) -> Result<Self, Error> {
+        let options: PgConnectOptions = url
+            .parse()
+            .map_err(|e| Error::Error(format!("Invalid Postgres url '{}': {e}", redact(url))))?;
+
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+        MIGRATOR.run(&pool).await?;
+
+        let pool = DatabasePool {
+            backend: Backend::Postgres(pool),
+            min_amount: 0,
+            pot_descriptions: PotDescriptions::default(),
+        };
+        pool.set_dedupe_keys(None).await?;
+
+        Ok(pool)
+    }
+
+    // The Postgres pool, for the Postgres services
+    pub(crate) fn pg(&self) -> &PgPool {
+        self.postgres()
+            .expect("A Postgres service was used with an SQLite database")
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+// These run against the server at `TEST_POSTGRES_URL`, each in a schema of its
+// own, and pass without doing anything when it isn't set
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::{
+        configuration::{Retention, Transfers},
+        model::{
+            category::Category,
+            counterparty::Counterparty,
+            dedupe::Source,
+            edit::TransactionEdit,
+            transaction::{SyncPoint, TransactionResponse},
+            transfer::TransferRules,
+        },
+        tests::test::{test_db, test_pg_db, test_pg_url},
+    };
+
+    fn transaction(id: &str, amount: i64, description: &str) -> TransactionResponse {
+        TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount,
+            currency: "GBP".to_string(),
+            local_amount: amount,
+            local_currency: "GBP".to_string(),
+            description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_the_seeded_data() {
+        // Arrange
+        let Some(pool) = test_pg_db().await else {
+            return;
+        };
+
+        // Act
+        let accounts = pool.accounts().read_accounts().await.unwrap();
+        let categories = pool.categories().read_categories().await.unwrap();
+        let transactions = pool.transactions().read_transactions().await.unwrap();
+        let totals = pool.transactions().read_account_totals().await.unwrap();
+
+        // Assert
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(categories[0].name, "category_1");
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(totals[0].account_name, "personal");
+        assert_eq!(totals[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn reconcile_settles_a_pending_transaction() {
+        // Arrange
+        let Some(pool) = test_pg_db().await else {
+            return;
+        };
+        let service = pool.transactions();
+        let pending = transaction("tx_pending", -4200, "TESCO");
+        service.save_transaction(&pending).await.unwrap();
+        let settled = TransactionResponse {
+            amount: -4350,
+            settled: Some(Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap()),
+            ..pending.clone()
+        };
+
+        // Act
+        let still_pending = service.update_transaction(&pending, false).await.unwrap();
+        let reconciled = service.update_transaction(&settled, false).await.unwrap();
+
+        // Assert
+        assert!(!still_pending);
+        assert!(reconciled);
+        let tx = service.read_transaction("tx_pending").await.unwrap();
+        assert_eq!(tx.amount, -4350);
+        assert_eq!(tx.pending_amount, Some(-4200));
+        let drift = service
+            .read_settlement_drift(tx.created, tx.created)
+            .await
+            .unwrap();
+        assert_eq!(drift[0].difference(), -150);
+    }
+
+    #[tokio::test]
+    async fn aggregates_match_sqlite() {
+        // Arrange
+        let Some(pool) = test_pg_db().await else {
+            return;
+        };
+        let (sqlite, _tmp) = test_db().await;
+        let at = |day| Utc.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap();
+        let transactions = [
+            TransactionResponse {
+                created: at(10),
+                settled: Some(at(11)),
+                ..transaction("tx_settled", -100, "TESCO")
+            },
+            TransactionResponse {
+                created: at(5),
+                ..transaction("tx_pending", -400, "PRET")
+            },
+        ];
+        for tx in &transactions {
+            pool.transactions().save_transaction(tx).await.unwrap();
+            sqlite.transactions().save_transaction(tx).await.unwrap();
+        }
+        let from = at(1).naive_utc() - Duration::days(3650);
+        let until = Utc::now().naive_utc();
+
+        // Act
+        let points = pool.transactions().read_sync_points().await.unwrap();
+
+        // Assert
+        assert_eq!(
+            points,
+            vec![SyncPoint {
+                account_id: "1".to_string(),
+                latest: at(10).naive_utc(),
+                oldest_pending: Some(at(5).naive_utc()),
+            }]
+        );
+        assert_eq!(
+            points,
+            sqlite.transactions().read_sync_points().await.unwrap()
+        );
+        let pg = pool
+            .transactions()
+            .read_category_totals(from, until)
+            .await
+            .unwrap();
+        let lite = sqlite
+            .transactions()
+            .read_category_totals(from, until)
+            .await
+            .unwrap();
+        assert_eq!(format!("{pg:?}"), format!("{lite:?}"));
+        let pg = pool.transactions().read_account_totals().await.unwrap();
+        let lite = sqlite.transactions().read_account_totals().await.unwrap();
+        assert_eq!(format!("{pg:?}"), format!("{lite:?}"));
+        let pg = pool
+            .transactions()
+            .read_export_data(from, until)
+            .await
+            .unwrap();
+        let lite = sqlite
+            .transactions()
+            .read_export_data(from, until)
+            .await
+            .unwrap();
+        assert_eq!(format!("{pg:?}"), format!("{lite:?}"));
+    }
+
+    #[tokio::test]
+    async fn the_api_transaction_replaces_an_imported_one() {
+        // Arrange
+        let Some(pool) = test_pg_db().await else {
+            return;
+        };
+        let service = pool.transactions();
+        let created = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let imported = TransactionResponse {
+            created,
+            ..transaction("csv_1", -1250, "Coffee Shop")
+        };
+        service
+            .save_transaction_from(&imported, Source::Csv)
+            .await
+            .unwrap();
+
+        // Act
+        service
+            .save_transaction(&TransactionResponse {
+                created: created + Duration::minutes(2),
+                ..transaction("tx_1", -1250, "COFFEE SHOP")
+            })
+            .await
+            .unwrap();
+
+        // Assert
+        assert!(service.read_transaction("csv_1").await.is_err());
+        assert!(service.read_transaction("tx_1").await.is_ok());
+        assert_eq!(
+            pool.resolve_alias("csv_1").await.unwrap().as_deref(),
+            Some("tx_1")
+        );
+    }
+
+    #[tokio::test]
+    async fn edits_categorise_and_tag() {
+        // Arrange
+        let Some(pool) = test_pg_db().await else {
+            return;
+        };
+        pool.categories()
+            .save_category(&Category {
+                id: "transport".to_string(),
+                name: "transport".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let edit = TransactionEdit::new(Some("transport".to_string()), vec!["commute".to_string()])
+            .unwrap();
+
+        // Act
+        let before = pool
+            .read_uncategorised(&["category_1".to_string()])
+            .await
+            .unwrap();
+        let summary = pool
+            .edit_transactions(&["1".to_string()], &edit)
+            .await
+            .unwrap();
+        let after = pool
+            .read_uncategorised(&["category_1".to_string()])
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(before.len(), 2);
+        assert_eq!((summary.recategorised, summary.tagged), (1, 1));
+        assert_eq!(
+            after.iter().map(|tx| tx.id.as_str()).collect::<Vec<_>>(),
+            vec!["2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn classifies_transfers() {
+        // Arrange
+        let Some(pool) = test_pg_db().await else {
+            return;
+        };
+        let own = Counterparty {
+            account_number: Some("12345678".to_string()),
+            sort_code: Some("123456".to_string()),
+            ..Default::default()
+        };
+        for tx in [
+            transaction("pot", -1000, "1"),
+            TransactionResponse {
+                counterparty: Some(own),
+                ..transaction("own", -1000, "TRANSFER")
+            },
+            transaction("card", -1000, "AMERICAN EXPRESS"),
+            transaction("shop", -1000, "TESCO"),
+        ] {
+            pool.transactions().save_transaction(&tx).await.unwrap();
+        }
+        let rules = TransferRules::new(&Transfers {
+            categories: Vec::new(),
+            descriptions: vec!["^american express".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Act
+        let summary = pool.classify_transfers(&rules).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.transfers, 3);
+        for (id, expected) in [
+            ("pot", true),
+            ("own", true),
+            ("card", true),
+            ("shop", false),
+        ] {
+            let tx = pool.transactions().read_transaction(id).await.unwrap();
+            assert_eq!(tx.is_transfer, expected, "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn prune_keeps_the_latest_run() {
+        // Arrange
+        let Some(pool) = test_pg_db().await else {
+            return;
+        };
+        let old = Utc::now().naive_utc() - Duration::days(1000);
+        for _ in 0..2 {
+            pool.sync_runs().start_run(old, old).await.unwrap();
+        }
+        sqlx::query("UPDATE sync_runs SET started = $1")
+            .bind(old)
+            .execute(pool.pg())
+            .await
+            .unwrap();
+
+        // Act
+        let summary = pool.prune(&Retention::default()).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.runs, 1);
+        assert_eq!(pool.sync_runs().read_runs(10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn migrated_data_reads_the_same() {
+        // Arrange
+        let Some(url) = test_pg_url().await else {
+            return;
+        };
+        let (sqlite, _tmp) = test_db().await;
+
+        // Act
+        let copies = sqlite.migrate_to(&url, |_| {}).await.unwrap();
+        let pool = super::DatabasePool::new_postgres(&url, 1).await.unwrap();
+
+        // Assert
+        assert!(copies.iter().all(|copy| copy.copied == copy.rows));
+        let pg = pool.transactions().read_transactions().await.unwrap();
+        let lite = sqlite.transactions().read_transactions().await.unwrap();
+        assert_eq!(format!("{pg:?}"), format!("{lite:?}"));
+        let pg = pool.accounts().read_accounts().await.unwrap();
+        let lite = sqlite.accounts().read_accounts().await.unwrap();
+        assert_eq!(format!("{pg:?}"), format!("{lite:?}"));
+        assert!(sqlite.migrate_to(&url, |_| {}).await.is_err());
+    }
+}
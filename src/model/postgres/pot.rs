@@ -0,0 +1,131 @@
+//! Pots in Postgres
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        pot::{Pot, PotMovement, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgPotService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgPotService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgPotService {
+    #[tracing::instrument(
+        name = "Save pot",
+        skip(self, pot_fc),
+        fields(tx_id = %pot_fc.id, merchant_id = %pot_fc.id)
+    )]
+    async fn save_pot(&self, pot_fc: &Pot) -> Result<(), Error> {
+        let result = sqlx::query(
+            r"
+                INSERT INTO pots (
+                    id,
+                    name,
+                    account_name,
+                    balance,
+                    currency,
+                    deleted,
+                    pot_type
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (id) DO NOTHING
+            ",
+        )
+        .bind(&pot_fc.id)
+        .bind(&pot_fc.name)
+        .bind(&pot_fc.account_name)
+        .bind(pot_fc.balance)
+        .bind(&pot_fc.currency)
+        .bind(pot_fc.deleted)
+        .bind(&pot_fc.pot_type)
+        .execute(self.pool.pg())
+        .await?;
+
+        if result.rows_affected() == 0 {
+            info!("Pot exists. Skipping");
+            return Err(Error::Duplicate("Pot already exists".to_string()));
+        }
+        info!("Created pot: {:?}", pot_fc.id);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get pots")]
+    async fn read_pots(&self) -> Result<Vec<Pot>, Error> {
+        let pots = sqlx::query_as::<_, Pot>("SELECT * FROM pots")
+            .fetch_all(self.pool.pg())
+            .await?;
+
+        Ok(pots)
+    }
+
+    #[tracing::instrument(name = "Get pot")]
+    async fn read_pot_by_id(&self, pot_id: &str) -> Result<Option<Pot>, Error> {
+        let pot = sqlx::query_as::<_, Pot>("SELECT * FROM pots WHERE id = $1")
+            .bind(pot_id)
+            .fetch_optional(self.pool.pg())
+            .await?;
+
+        Ok(pot)
+    }
+
+    #[tracing::instrument(name = "Get pot by type")]
+    async fn read_pot_by_type(&self, pot_type: &str) -> Result<Option<Pot>, Error> {
+        let pot = sqlx::query_as::<_, Pot>("SELECT * FROM pots WHERE pot_type = $1 LIMIT 1")
+            .bind(pot_type)
+            .fetch_optional(self.pool.pg())
+            .await?;
+
+        Ok(pot)
+    }
+
+    /// Read transactions into or out of pots between `from` and `until`,
+    /// oldest first, leaving out those smaller than the pool's minimum amount
+    #[tracing::instrument(name = "Read pot movements", skip(self))]
+    async fn read_pot_movements(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PotMovement>, Error> {
+        let movements = sqlx::query_as::<_, PotMovement>(
+            r"
+                SELECT
+                    t.created,
+                    p.id AS pot_id,
+                    p.name AS pot_name,
+                    p.pot_type,
+                    t.amount,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS currency
+                FROM transactions t
+                JOIN pots p ON t.description = p.id
+                JOIN accounts a ON t.account_id = a.id
+                WHERE t.created BETWEEN $1 AND $2
+                AND ABS(t.amount) >= $3
+                ORDER BY t.created, t.id
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .bind(self.pool.min_amount())
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(movements)
+    }
+}
@@ -0,0 +1,65 @@
+//! Pot sweeps in Postgres
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::{
+    error::AppErrors as Error,
+    model::{sweep::Service, DatabasePool},
+};
+
+#[derive(Debug, Clone)]
+pub struct PgSweepService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgSweepService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgSweepService {
+    /// Whether the rule has swept for the month
+    #[tracing::instrument(name = "Check sweep", skip(self))]
+    async fn is_swept(&self, rule: &str, month: NaiveDate) -> Result<bool, Error> {
+        let sweep: Option<String> = sqlx::query_scalar(
+            r"
+                SELECT rule FROM sweeps
+                WHERE rule = $1 AND month = $2
+            ",
+        )
+        .bind(rule)
+        .bind(month.format("%Y-%m").to_string())
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        Ok(sweep.is_some())
+    }
+
+    /// Record that the rule has swept for the month
+    #[tracing::instrument(name = "Record sweep", skip(self))]
+    async fn record_swept(
+        &self,
+        rule: &str,
+        month: NaiveDate,
+        swept: NaiveDateTime,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                INSERT INTO sweeps (rule, month, swept)
+                VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(rule)
+        .bind(month.format("%Y-%m").to_string())
+        .bind(swept)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! Sync runs and their checkpoints in Postgres
+
+use std::{collections::HashSet, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        sync_run::{RunCounts, Service, SyncProfile, SyncRun},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgSyncRunService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgSyncRunService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgSyncRunService {
+    #[tracing::instrument(name = "Start sync run", skip(self))]
+    async fn start_run(
+        &self,
+        range_start: NaiveDateTime,
+        range_end: NaiveDateTime,
+    ) -> Result<i64, Error> {
+        let started = Utc::now().naive_utc();
+
+        let run_id: i64 = sqlx::query_scalar(
+            r"
+                INSERT INTO sync_runs (started, range_start, range_end)
+                VALUES ($1, $2, $3)
+                RETURNING id
+            ",
+        )
+        .bind(started)
+        .bind(range_start)
+        .bind(range_end)
+        .fetch_one(self.pool.pg())
+        .await?;
+        info!("Started sync run: {run_id}");
+
+        Ok(run_id)
+    }
+
+    #[tracing::instrument(name = "Finish sync run", skip(self))]
+    async fn finish_run(
+        &self,
+        run_id: i64,
+        counts: RunCounts,
+        error: Option<String>,
+    ) -> Result<(), Error> {
+        let finished = Utc::now().naive_utc();
+
+        sqlx::query(
+            r"
+                UPDATE sync_runs
+                SET finished = $1,
+                    accounts = $2,
+                    inserted = inserted + $3,
+                    updated = updated + $4,
+                    skipped = skipped + $5,
+                    error = $6
+                WHERE id = $7
+            ",
+        )
+        .bind(finished)
+        .bind(counts.accounts)
+        .bind(counts.inserted)
+        .bind(counts.updated)
+        .bind(counts.skipped)
+        .bind(error)
+        .bind(run_id)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Save sync run profile", skip(self))]
+    async fn save_profile(&self, run_id: i64, profile: &SyncProfile) -> Result<(), Error> {
+        let millis = |duration: Duration| i64::try_from(duration.as_millis()).unwrap_or(i64::MAX);
+
+        sqlx::query(
+            r"
+                UPDATE sync_runs
+                SET fetch_ms = $1,
+                    dedupe_ms = $2,
+                    insert_ms = $3,
+                    store_ms = $4,
+                    classify_ms = $5
+                WHERE id = $6
+            ",
+        )
+        .bind(millis(profile.fetch))
+        .bind(millis(profile.dedupe))
+        .bind(millis(profile.insert))
+        .bind(millis(profile.store))
+        .bind(millis(profile.classify))
+        .bind(run_id)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Read sync runs", skip(self))]
+    async fn read_runs(&self, limit: i64) -> Result<Vec<SyncRun>, Error> {
+        let runs = sqlx::query_as::<_, SyncRun>(
+            r"
+                SELECT *
+                FROM sync_runs
+                ORDER BY id DESC
+                LIMIT $1
+            ",
+        )
+        .bind(limit)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(runs)
+    }
+
+    /// Record that a window of an account has been fetched and persisted
+    #[tracing::instrument(name = "Checkpoint sync window", skip(self))]
+    async fn checkpoint(
+        &self,
+        run_id: i64,
+        account_id: &str,
+        window_start: NaiveDateTime,
+        window_end: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let completed = Utc::now().naive_utc();
+
+        sqlx::query(
+            r"
+                INSERT INTO sync_state (run_id, account_id, window_start, window_end, completed)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (run_id, account_id, window_start) DO UPDATE
+                SET window_end = excluded.window_end, completed = excluded.completed
+            ",
+        )
+        .bind(run_id)
+        .bind(account_id)
+        .bind(window_start)
+        .bind(window_end)
+        .bind(completed)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    /// The (account id, window start) pairs already completed by a run
+    #[tracing::instrument(name = "Read completed windows", skip(self))]
+    async fn completed_windows(
+        &self,
+        run_id: i64,
+    ) -> Result<HashSet<(String, NaiveDateTime)>, Error> {
+        let rows: Vec<(String, NaiveDateTime)> = sqlx::query_as(
+            r"
+                SELECT account_id, window_start
+                FROM sync_state
+                WHERE run_id = $1
+            ",
+        )
+        .bind(run_id)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Delete runs started before `before`, and their checkpoints, keeping the
+    /// most recent run. Returns the number of runs and checkpoints deleted.
+    #[tracing::instrument(name = "Prune sync runs", skip(self))]
+    async fn prune_runs(&self, before: NaiveDateTime) -> Result<(u64, u64), Error> {
+        let mut tx = self.pool.pg().begin().await?;
+
+        let checkpoints = sqlx::query(
+            r"
+                DELETE FROM sync_state
+                WHERE run_id IN (
+                    SELECT id FROM sync_runs
+                    WHERE started < $1
+                    AND id < (SELECT MAX(id) FROM sync_runs)
+                )
+            ",
+        )
+        .bind(before)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let runs = sqlx::query(
+            r"
+                DELETE FROM sync_runs
+                WHERE started < $1
+                AND id < (SELECT MAX(id) FROM sync_runs)
+            ",
+        )
+        .bind(before)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+        info!("Pruned {runs} sync runs and {checkpoints} checkpoints");
+
+        Ok((runs, checkpoints))
+    }
+}
@@ -0,0 +1,652 @@
+//! Transactions in Postgres
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use tracing_log::log::info;
+
+#[cfg(feature = "beancount")]
+use crate::model::transaction::BeancountTransaction;
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        category::Category,
+        dedupe::Source,
+        pot::Pot,
+        transaction::{
+            describe_pot_transfers, insert_counterparty, insert_merchant, AccountTotal,
+            CategoryTotal, ExportTransaction, PotTotal, Service, SettlementDrift, SyncPoint,
+            TransactionForDB, TransactionResponse,
+        },
+        DatabasePool,
+    },
+};
+
+// The columns of an export row, for a query filtering and ordering `t`
+const EXPORT_SELECT: &str = r"
+    SELECT
+        t.id,
+        t.account_id,
+        a.owner_type AS account_name,
+        t.created,
+        t.settled,
+        t.amount,
+        COALESCE(NULLIF(t.currency, ''), a.currency) AS currency,
+        t.local_amount,
+        t.local_currency,
+        t.description,
+        t.notes,
+        c.name AS category_name,
+        COALESCE(c.emoji || ' ', '') || COALESCE(c.display_name, c.name) AS category_label,
+        m.name AS merchant_name,
+        p.name AS pot_name,
+        t.is_transfer,
+        cp.name AS counterparty_name,
+        t.repayment_account_id,
+        (
+            SELECT string_agg(tag, ' ' ORDER BY tag)
+            FROM transaction_tags
+            WHERE transaction_id = t.id
+        ) AS tags,
+        m.latitude,
+        m.longitude,
+        COALESCE(m.logo_path, m.logo) AS merchant_logo
+    FROM transactions t
+    JOIN accounts a ON t.account_id = a.id
+    JOIN categories c ON t.category_id = c.id
+    LEFT JOIN merchants m ON t.merchant_id = m.id
+    LEFT JOIN pots p ON t.description = p.id
+    LEFT JOIN counterparties cp ON t.counterparty_id = cp.id
+";
+
+#[derive(Debug, Clone)]
+pub struct PgTransactionService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgTransactionService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgTransactionService {
+    #[tracing::instrument(
+        name = "Create transaction",
+        skip(self, tx_resp),
+        fields(tx_id = %tx_resp.id, acc_id = %tx_resp.account_id)
+    )]
+    async fn save_transaction_from(
+        &self,
+        tx_resp: &TransactionResponse,
+        source: Source,
+    ) -> Result<(), Error> {
+        let tx = TransactionForDB::from((*tx_resp).clone());
+
+        let existing: Option<String> =
+            sqlx::query_scalar("SELECT id FROM transactions WHERE id = $1")
+                .bind(&tx.id)
+                .fetch_optional(self.pool.pg())
+                .await?;
+        if existing.is_some() || self.pool.resolve_alias(&tx.id).await?.is_some() {
+            info!("Transaction exists. Skipping");
+            return Err(Error::Duplicate("Transaction already exists".to_string()));
+        }
+
+        let merchant_id = insert_merchant(self.pool.clone(), tx_resp.merchant.as_ref()).await?;
+        let counterparty_id =
+            insert_counterparty(self.pool.clone(), tx_resp.counterparty.as_ref()).await?;
+
+        info!("Inserting transaction");
+        sqlx::query(
+            r"
+                INSERT INTO transactions (
+                    id,
+                    account_id,
+                    merchant_id,
+                    amount,
+                    currency,
+                    local_amount,
+                    local_currency,
+                    created,
+                    description,
+                    notes,
+                    settled,
+                    updated,
+                    category_id,
+                    counterparty_account_number,
+                    counterparty_sort_code,
+                    counterparty_id,
+                    source,
+                    decline_reason
+                )
+                VALUES (
+                    $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18
+                )
+            ",
+        )
+        .bind(&tx.id)
+        .bind(&tx.account_id)
+        .bind(merchant_id)
+        .bind(tx.amount)
+        .bind(&tx.currency)
+        .bind(tx.local_amount)
+        .bind(&tx.local_currency)
+        .bind(tx.created)
+        .bind(&tx.description)
+        .bind(&tx.notes)
+        .bind(tx.settled)
+        .bind(tx.updated)
+        .bind(&tx.category_id)
+        .bind(&tx.counterparty_account_number)
+        .bind(&tx.counterparty_sort_code)
+        .bind(counterparty_id)
+        .bind(source.as_str())
+        .bind(&tx_resp.decline_reason)
+        .execute(self.pool.pg())
+        .await
+        .map_err(|e| Error::DbError(e.to_string()))?;
+        info!("Created transaction: {}", tx.id);
+
+        self.pool.set_dedupe_keys(Some(&tx.id)).await?;
+        // the API's transaction replaces one imported from elsewhere
+        if source.is_monzo() {
+            if let Some(duplicate) = self.pool.find_duplicate(&tx.id, source).await? {
+                info!("Merging {duplicate} into {}", tx.id);
+                self.pool.merge_transaction(&duplicate, &tx.id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bring a stored transaction up to date with the API: while it's pending,
+    /// or when Monzo last updated it after the stored copy, such as a note
+    /// edited in the app. With `refresh` any difference is updated.
+    /// Returns true if the stored transaction changed
+    #[tracing::instrument(
+        name = "Update transaction",
+        skip(self, tx_resp),
+        fields(tx_id = %tx_resp.id)
+    )]
+    async fn update_transaction(
+        &self,
+        tx_resp: &TransactionResponse,
+        refresh: bool,
+    ) -> Result<bool, Error> {
+        let tx = TransactionForDB::from((*tx_resp).clone());
+        let merchant_id = insert_merchant(self.pool.clone(), tx_resp.merchant.as_ref()).await?;
+
+        let result = sqlx::query(
+            r"
+                UPDATE transactions
+                SET
+                    merchant_id = $2,
+                    pending_amount = COALESCE(
+                        pending_amount,
+                        CASE WHEN settled IS NULL AND amount != $3 THEN amount END
+                    ),
+                    amount = $3,
+                    local_amount = $4,
+                    local_currency = $5,
+                    description = $6,
+                    settled = $7,
+                    updated = $8,
+                    category_id = COALESCE(
+                        (SELECT category_id FROM category_overrides WHERE transaction_id = $1),
+                        $9
+                    ),
+                    notes = $10,
+                    decline_reason = $12
+                WHERE id = $1
+                AND (settled IS NULL OR COALESCE(updated, '-infinity') < $8 OR $11)
+                AND (
+                    merchant_id IS DISTINCT FROM $2
+                    OR amount != $3
+                    OR local_amount != $4
+                    OR local_currency != $5
+                    OR description != $6
+                    OR settled IS DISTINCT FROM $7
+                    OR notes IS DISTINCT FROM $10
+                    OR decline_reason IS DISTINCT FROM $12
+                    OR category_id != COALESCE(
+                        (SELECT category_id FROM category_overrides WHERE transaction_id = $1),
+                        $9
+                    )
+                )
+            ",
+        )
+        .bind(&tx.id)
+        .bind(merchant_id)
+        .bind(tx.amount)
+        .bind(tx.local_amount)
+        .bind(&tx.local_currency)
+        .bind(&tx.description)
+        .bind(tx.settled)
+        .bind(tx.updated)
+        .bind(&tx.category_id)
+        .bind(&tx.notes)
+        .bind(refresh)
+        .bind(&tx_resp.decline_reason)
+        .execute(self.pool.pg())
+        .await?;
+
+        let changed = result.rows_affected() > 0;
+        if changed {
+            info!("Updated transaction: {}", tx.id);
+            self.pool.set_dedupe_keys(Some(&tx.id)).await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Record that a sync has fetched a transaction stored from a webhook
+    #[tracing::instrument(name = "Mark transaction synced", skip(self))]
+    async fn mark_synced(&self, tx_id: &str) -> Result<(), Error> {
+        sqlx::query("UPDATE transactions SET source = $2 WHERE id = $1 AND source = $3")
+            .bind(tx_id)
+            .bind(Source::Api.as_str())
+            .bind(Source::Webhook.as_str())
+            .execute(self.pool.pg())
+            .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Read transactions", skip(self))]
+    async fn read_transactions(&self) -> Result<Vec<TransactionForDB>, Error> {
+        let txs = sqlx::query_as::<_, TransactionForDB>(
+            r"
+                SELECT *
+                FROM transactions
+                ORDER BY created, id
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+        info!("Read {} transactions", txs.len());
+
+        Ok(txs)
+    }
+
+    /// Read up to `limit` transactions ordered by date, starting after the
+    /// transaction with id `after`. Pass the last id of a page to get the next.
+    #[tracing::instrument(name = "Read transactions page", skip(self))]
+    async fn read_transactions_page(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TransactionForDB>, Error> {
+        let transactions = sqlx::query_as::<_, TransactionForDB>(
+            r"
+                SELECT *
+                FROM transactions
+                WHERE $1 IS NULL
+                OR (created, id) > (SELECT created, id FROM transactions WHERE id = $1)
+                ORDER BY created, id
+                LIMIT $2
+            ",
+        )
+        .bind(after)
+        .bind(limit)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(transactions)
+    }
+
+    #[tracing::instrument(name = "Read transactions for dates", skip(self))]
+    async fn read_transactions_for_dates(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<TransactionForDB>, Error> {
+        let transactions = sqlx::query_as::<_, TransactionForDB>(
+            r"
+                SELECT *
+                FROM transactions
+                WHERE created
+                BETWEEN $1 AND $2
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(transactions)
+    }
+
+    #[tracing::instrument(name = "Read transaction", skip(self))]
+    async fn read_transaction(&self, tx_id: &str) -> Result<TransactionForDB, Error> {
+        let tx = sqlx::query_as::<_, TransactionForDB>("SELECT * FROM transactions WHERE id = $1")
+            .bind(tx_id)
+            .fetch_one(self.pool.pg())
+            .await
+            .map_err(|e| Error::DbError(e.to_string()))?;
+        info!("Read {:?} transaction", tx);
+
+        Ok(tx)
+    }
+
+    #[tracing::instrument(name = "Delete all transactions", skip(self))]
+    async fn delete_all_transactions(&self) -> Result<(), Error> {
+        sqlx::query("DELETE FROM transactions")
+            .execute(self.pool.pg())
+            .await
+            .map_err(|e| Error::DbError(e.to_string()))?;
+        info!("Deleted all transactions");
+
+        Ok(())
+    }
+
+    /// Read data anf format for processing in the beancouint module
+    #[cfg(feature = "beancount")]
+    #[tracing::instrument(name = "Read beancount data", skip(self))]
+    async fn read_beancount_data(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<BeancountTransaction>, Error> {
+        let transactions = sqlx::query_as::<_, BeancountTransaction>(
+            r"
+                SELECT
+                    t.id,
+                    t.created,
+                    t.settled,
+                    a.owner_type AS account_name,
+                    t.amount,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS currency,
+                    t.local_amount,
+                    t.local_currency,
+                    t.description,
+                    t.notes,
+                    p.name AS pot_name,
+                    c.name AS category_name,
+                    m.name AS merchant_name
+                FROM transactions t
+                JOIN accounts a ON t.account_id = a.id
+                JOIN categories c ON t.category_id = c.id
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+                LEFT JOIN pots p ON t.description = p.id
+                WHERE t.created
+                BETWEEN $1 AND $2
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(transactions)
+    }
+
+    /// Read transactions with joined names, ordered by account then date
+    #[tracing::instrument(name = "Read export data", skip(self))]
+    async fn read_export_data(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<ExportTransaction>, Error> {
+        let sql = format!(
+            r"
+                {EXPORT_SELECT}
+                WHERE t.created
+                BETWEEN $1 AND $2
+                ORDER BY t.account_id, t.created, t.id
+            "
+        );
+        let transactions = sqlx::query_as::<_, ExportTransaction>(&sql)
+            .bind(from)
+            .bind(until)
+            .fetch_all(self.pool.pg())
+            .await?;
+
+        Ok(describe_pot_transfers(&self.pool, transactions))
+    }
+
+    /// Read transactions as for an export, leaving out those smaller than the
+    /// pool's minimum amount, for reports
+    #[tracing::instrument(name = "Read report data", skip(self))]
+    async fn read_report_data(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<ExportTransaction>, Error> {
+        let min_amount = self.pool.min_amount();
+        let mut transactions = self.read_export_data(from, until).await?;
+        transactions.retain(|tx| tx.amount.abs() >= min_amount);
+
+        Ok(transactions)
+    }
+
+    /// Read up to `limit` transactions with joined names created between
+    /// `from` and `until`, ordered by date and starting after the transaction
+    /// with id `after`
+    #[tracing::instrument(name = "Read export page", skip(self))]
+    async fn read_export_page(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<ExportTransaction>, Error> {
+        let sql = format!(
+            r"
+                {EXPORT_SELECT}
+                WHERE t.created BETWEEN $1 AND $2
+                AND (
+                    $3 IS NULL
+                    OR (t.created, t.id) > (SELECT created, id FROM transactions WHERE id = $3)
+                )
+                ORDER BY t.created, t.id
+                LIMIT $4
+            "
+        );
+        let transactions = sqlx::query_as::<_, ExportTransaction>(&sql)
+            .bind(from)
+            .bind(until)
+            .bind(after)
+            .bind(limit)
+            .fetch_all(self.pool.pg())
+            .await?;
+
+        Ok(describe_pot_transfers(&self.pool, transactions))
+    }
+
+    /// Sum transactions per category, largest spend first. Transfers and
+    /// transactions smaller than the pool's minimum amount are left out.
+    #[tracing::instrument(name = "Read category totals", skip(self))]
+    async fn read_category_totals(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<CategoryTotal>, Error> {
+        let totals = sqlx::query_as::<_, CategoryTotal>(
+            r"
+                SELECT
+                    c.name AS category_name,
+                    MIN(COALESCE(c.emoji || ' ', '') || COALESCE(c.display_name, c.name)) AS category_label,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS currency,
+                    SUM(t.amount)::BIGINT AS total,
+                    COUNT(*) AS count
+                FROM transactions t
+                JOIN accounts a ON t.account_id = a.id
+                JOIN categories c ON t.category_id = c.id
+                WHERE t.created
+                BETWEEN $1 AND $2
+                AND NOT t.is_transfer
+                AND ABS(t.amount) >= $3
+                GROUP BY c.name, 3
+                ORDER BY 4
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .bind(self.pool.min_amount())
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(totals)
+    }
+
+    /// Sum all synced transactions per account and currency. A transaction
+    /// without a currency is in its account's, as is an account without any.
+    #[tracing::instrument(name = "Read account totals", skip(self))]
+    async fn read_account_totals(&self) -> Result<Vec<AccountTotal>, Error> {
+        let totals = sqlx::query_as::<_, AccountTotal>(
+            r"
+                SELECT
+                    a.id AS account_id,
+                    a.owner_type AS account_name,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS currency,
+                    COALESCE(SUM(t.amount), 0)::BIGINT AS total,
+                    COUNT(t.id) AS count
+                FROM accounts a
+                LEFT JOIN transactions t ON t.account_id = a.id
+                GROUP BY a.id, 3
+                ORDER BY a.id, 3
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(totals)
+    }
+
+    /// The newest and oldest pending transaction synced from the API per
+    /// account. Transactions only a webhook has delivered don't move the
+    /// newest on, as a sync may not have fetched the ones before them.
+    #[tracing::instrument(name = "Read sync points", skip(self))]
+    async fn read_sync_points(&self) -> Result<Vec<SyncPoint>, Error> {
+        let points = sqlx::query_as::<_, SyncPoint>(
+            r"
+                WITH latest AS (
+                    SELECT account_id, MAX(created) AS created
+                    FROM transactions
+                    WHERE source = 'api'
+                    GROUP BY account_id
+                )
+                SELECT
+                    t.account_id,
+                    MIN(l.created) AS latest,
+                    -- declined payments and authorisations that expired without
+                    -- settling, unchanged for 30 days, never settle
+                    MIN(
+                        CASE WHEN t.settled IS NULL
+                            AND t.decline_reason IS NULL
+                            AND t.amount != 0
+                            AND COALESCE(t.updated, t.created) >= l.created - INTERVAL '30 days'
+                        THEN t.created END
+                    ) AS oldest_pending
+                FROM transactions t
+                JOIN latest l ON l.account_id = t.account_id
+                WHERE t.source IN ('api', 'webhook')
+                GROUP BY t.account_id
+                ORDER BY t.account_id
+            ",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(points)
+    }
+
+    /// Sum transactions created between `from` and `until` per account, pot
+    /// and currency
+    #[tracing::instrument(name = "Read pot totals", skip(self))]
+    async fn read_pot_totals(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PotTotal>, Error> {
+        let totals = sqlx::query_as::<_, PotTotal>(
+            r"
+                SELECT
+                    t.account_id,
+                    p.id AS pot_id,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS currency,
+                    SUM(t.amount)::BIGINT AS total
+                FROM transactions t
+                JOIN accounts a ON t.account_id = a.id
+                LEFT JOIN pots p ON t.description = p.id
+                WHERE t.created
+                BETWEEN $1 AND $2
+                GROUP BY t.account_id, p.id, 3
+                ORDER BY t.account_id, p.id, 3
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(totals)
+    }
+
+    /// Settled transactions created between `from` and `until` whose amount
+    /// differs from the one first stored while pending
+    #[tracing::instrument(name = "Read settlement drift", skip(self))]
+    async fn read_settlement_drift(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<SettlementDrift>, Error> {
+        let drift = sqlx::query_as::<_, SettlementDrift>(
+            r"
+                SELECT
+                    t.id,
+                    t.account_id,
+                    a.owner_type AS account_name,
+                    t.created,
+                    t.settled,
+                    t.description,
+                    m.name AS merchant_name,
+                    t.currency,
+                    t.pending_amount,
+                    t.amount
+                FROM transactions t
+                JOIN accounts a ON t.account_id = a.id
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+                WHERE t.created BETWEEN $1 AND $2
+                AND t.settled IS NOT NULL
+                AND t.pending_amount IS NOT NULL
+                AND t.pending_amount != t.amount
+                ORDER BY t.created
+            ",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(drift)
+    }
+
+    // get the set of categories for a given account
+    async fn get_categories_for_account(&self, account_id: &str) -> Result<Vec<Category>, Error> {
+        let categories = sqlx::query_as::<_, Category>(
+            r"
+                SELECT DISTINCT c.id, c.name, c.display_name, c.emoji
+                FROM categories c
+                JOIN transactions t ON c.id = t.category_id
+                WHERE t.account_id = $1
+            ",
+        )
+        .bind(account_id)
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(categories)
+    }
+
+    async fn get_pots_for_account(&self, account_name: &str) -> Result<Vec<Pot>, Error> {
+        let pots = sqlx::query_as::<_, Pot>("SELECT * FROM pots WHERE account_name = $1")
+            .bind(account_name)
+            .fetch_all(self.pool.pg())
+            .await?;
+
+        Ok(pots)
+    }
+}
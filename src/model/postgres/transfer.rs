@@ -0,0 +1,66 @@
+//! Transfer classification in Postgres
+
+use tracing_log::log::info;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        transfer::{classify, Row, TransferRules, TransferSummary},
+        DatabasePool,
+    },
+};
+
+// See [`DatabasePool::classify_transfers`]
+pub(crate) async fn classify_transfers(
+    pool: &DatabasePool,
+    rules: &TransferRules,
+) -> Result<TransferSummary, Error> {
+    let db = pool.pg();
+
+    let pots = sqlx::query_scalar("SELECT id FROM pots")
+        .fetch_all(db)
+        .await?;
+    let accounts = sqlx::query_as("SELECT account_number, sort_code FROM accounts")
+        .fetch_all(db)
+        .await?;
+    let liabilities =
+        sqlx::query_as("SELECT id, name FROM manual_accounts WHERE kind = 'liability'")
+            .fetch_all(db)
+            .await?;
+
+    let rows = sqlx::query_as::<_, Row>(
+        r"
+            SELECT
+                t.id,
+                t.description,
+                c.name AS category_name,
+                m.name AS merchant_name,
+                t.counterparty_account_number,
+                t.counterparty_sort_code,
+                t.is_transfer,
+                t.repayment_account_id
+            FROM transactions t
+            JOIN categories c ON t.category_id = c.id
+            LEFT JOIN merchants m ON t.merchant_id = m.id
+        ",
+    )
+    .fetch_all(db)
+    .await?;
+
+    let (summary, changes) = classify(rules, pots, accounts, liabilities, rows)?;
+    let mut tx = db.begin().await?;
+    for (id, is_transfer, repayment_account_id) in changes {
+        sqlx::query(
+            "UPDATE transactions SET is_transfer = $2, repayment_account_id = $3 WHERE id = $1",
+        )
+        .bind(id)
+        .bind(is_transfer)
+        .bind(repayment_account_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    info!("Classified transfers: {summary:?}");
+    Ok(summary)
+}
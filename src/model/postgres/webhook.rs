@@ -0,0 +1,100 @@
+//! Webhook deliveries in Postgres
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        webhook::{DeadLetter, Service},
+        DatabasePool,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PgWebhookService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl PgWebhookService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Service for PgWebhookService {
+    /// Whether a delivery of the transaction has been handled
+    #[tracing::instrument(name = "Check webhook delivery", skip(self))]
+    async fn is_delivered(&self, transaction_id: &str) -> Result<bool, Error> {
+        let delivery: Option<String> = sqlx::query_scalar(
+            "SELECT transaction_id FROM webhook_deliveries WHERE transaction_id = $1",
+        )
+        .bind(transaction_id)
+        .fetch_optional(self.pool.pg())
+        .await?;
+
+        Ok(delivery.is_some())
+    }
+
+    /// Record that a delivery of the transaction has been handled
+    #[tracing::instrument(name = "Record webhook delivery", skip(self))]
+    async fn record_delivery(
+        &self,
+        transaction_id: &str,
+        event_type: &str,
+        received: NaiveDateTime,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r"
+                INSERT INTO webhook_deliveries (transaction_id, event_type, received)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (transaction_id) DO NOTHING
+            ",
+        )
+        .bind(transaction_id)
+        .bind(event_type)
+        .bind(received)
+        .execute(self.pool.pg())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Keep a payload that couldn't be parsed, returning its id
+    #[tracing::instrument(name = "Save webhook dead letter", skip(self, body))]
+    async fn save_dead_letter(
+        &self,
+        body: &str,
+        error: &str,
+        received: NaiveDateTime,
+    ) -> Result<i64, Error> {
+        let id = sqlx::query_scalar(
+            r"
+                INSERT INTO webhook_dead_letters (received, body, error)
+                VALUES ($1, $2, $3)
+                RETURNING id
+            ",
+        )
+        .bind(received)
+        .bind(body)
+        .bind(error)
+        .fetch_one(self.pool.pg())
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Read the dead letters, oldest first
+    #[tracing::instrument(name = "Read webhook dead letters", skip(self))]
+    async fn read_dead_letters(&self) -> Result<Vec<DeadLetter>, Error> {
+        let letters = sqlx::query_as::<_, DeadLetter>(
+            "SELECT id, received, body, error FROM webhook_dead_letters ORDER BY id",
+        )
+        .fetch_all(self.pool.pg())
+        .await?;
+
+        Ok(letters)
+    }
+}
@@ -1,6 +1,7 @@
 //! Models for the pot endpoint
 
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use serde::Deserialize;
 use sqlx::{Pool, Sqlite};
 use tracing_log::log::{error, info};
@@ -27,7 +28,7 @@ pub struct PotResponse {
 }
 
 // Represents a Pot in the app
-#[derive(Debug, Default)]
+#[derive(sqlx::FromRow, Debug, Default)]
 pub struct Pot {
     pub id: String,
     pub name: String,
@@ -53,6 +54,18 @@ impl From<(PotResponse, String)> for Pot {
     }
 }
 
+/// Money moved between an account and one of its pots. A negative amount is
+/// paid into the pot.
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct PotMovement {
+    pub created: NaiveDateTime,
+    pub pot_id: String,
+    pub pot_name: String,
+    pub pot_type: String,
+    pub amount: i64,
+    pub currency: String,
+}
+
 // -- Services -------------------------------------------------------------------------
 
 #[async_trait]
@@ -61,6 +74,11 @@ pub trait Service {
     async fn read_pots(&self) -> Result<Vec<Pot>, Error>;
     async fn read_pot_by_id(&self, pot_id: &str) -> Result<Option<Pot>, Error>;
     async fn read_pot_by_type(&self, pot_type: &str) -> Result<Option<Pot>, Error>;
+    async fn read_pot_movements(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PotMovement>, Error>;
 }
 
 #[derive(Debug, Clone)]
@@ -187,6 +205,44 @@ impl Service for SqlitePotService {
 
         Ok(pot)
     }
+
+    /// Read transactions into or out of pots between `from` and `until`,
+    /// oldest first, leaving out those smaller than the pool's minimum amount
+    #[tracing::instrument(name = "Read pot movements", skip(self))]
+    async fn read_pot_movements(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PotMovement>, Error> {
+        let db = self.pool.db();
+        let min_amount = self.pool.min_amount();
+
+        let movements = sqlx::query_as!(
+            PotMovement,
+            r#"
+                SELECT
+                    t.created,
+                    p.id AS pot_id,
+                    p.name AS pot_name,
+                    p.pot_type,
+                    t.amount,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS "currency!: String"
+                FROM transactions t
+                JOIN pots p ON t.description = p.id
+                JOIN accounts a ON t.account_id = a.id
+                WHERE t.created BETWEEN $1 AND $2
+                AND ABS(t.amount) >= $3
+                ORDER BY t.created, t.id
+            "#,
+            from,
+            until,
+            min_amount,
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(movements)
+    }
 }
 
 // -- Utility functions ----------------------------------------------------------------
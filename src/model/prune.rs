@@ -0,0 +1,127 @@
+//! Retention
+//!
+//! Deletes housekeeping rows older than the configured [`Retention`] and
+//! vacuums the database to give the space back. Accounts, pots and
+//! transactions are never touched.
+
+use chrono::{Duration, Utc};
+use tracing_log::log::info;
+
+use super::DatabasePool;
+use crate::{configuration::Retention, error::AppErrors as Error};
+
+/// Rows deleted by [`DatabasePool::prune`] and the space reclaimed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PruneSummary {
+    pub runs: u64,
+    pub checkpoints: u64,
+    pub merchant_changes: u64,
+    pub reclaimed_bytes: i64,
+}
+
+impl DatabasePool {
+    /// Delete sync history and merchant changes older than the retention
+    /// settings, then vacuum
+    ///
+    /// # Errors
+    /// Will return an error if the database can't be written.
+    pub async fn prune(&self, retention: &Retention) -> Result<PruneSummary, Error> {
+        let now = Utc::now().naive_utc();
+        let before = self.size().await?;
+
+        let (runs, checkpoints) = self
+            .sync_runs()
+            .prune_runs(now - Duration::days(retention.sync_history_days))
+            .await?;
+
+        let merchant_changes = match retention.merchant_changes_days {
+            Some(days) => {
+                self.merchants()
+                    .prune_changes(now - Duration::days(days))
+                    .await?
+            }
+            None => 0,
+        };
+
+        self.vacuum().await?;
+        // the file can grow slightly when vacuuming a database with little to free
+        let reclaimed_bytes = (before - self.size().await?).max(0);
+
+        let summary = PruneSummary {
+            runs,
+            checkpoints,
+            merchant_changes,
+            reclaimed_bytes,
+        };
+        info!("Pruned database: {summary:?}");
+
+        Ok(summary)
+    }
+
+    // Give the space of deleted rows back
+    async fn vacuum(&self) -> Result<(), Error> {
+        #[cfg(feature = "postgres")]
+        if let Some(db) = self.postgres() {
+            sqlx::query("VACUUM sync_runs, sync_state, merchant_changes")
+                .execute(db)
+                .await?;
+            return Ok(());
+        }
+
+        sqlx::query("VACUUM").execute(self.db()).await?;
+        Ok(())
+    }
+
+    // Size of the main database file in bytes, or of the Postgres database
+    async fn size(&self) -> Result<i64, Error> {
+        #[cfg(feature = "postgres")]
+        if let Some(db) = self.postgres() {
+            let size: i64 = sqlx::query_scalar("SELECT pg_database_size(current_database())")
+                .fetch_one(db)
+                .await?;
+            return Ok(size);
+        }
+
+        let size: i64 = sqlx::query_scalar(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        )
+        .fetch_one(self.db())
+        .await?;
+
+        Ok(size)
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::sync_run::{Service as SyncRunService, SqliteSyncRunService},
+        tests::test::test_db,
+    };
+
+    #[tokio::test]
+    async fn prune_keeps_the_latest_run_and_changes_by_default() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteSyncRunService::new(pool.clone());
+        let old = Utc::now().naive_utc() - Duration::days(1000);
+        for _ in 0..2 {
+            service.start_run(old, old).await.unwrap();
+        }
+        sqlx::query!("UPDATE sync_runs SET started = $1", old)
+            .execute(pool.db())
+            .await
+            .unwrap();
+
+        // Act
+        let summary = pool.prune(&Retention::default()).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.runs, 1);
+        assert_eq!(summary.merchant_changes, 0);
+        assert_eq!(service.read_runs(10).await.unwrap().len(), 1);
+    }
+}
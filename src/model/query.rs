@@ -0,0 +1,256 @@
+//! Ad hoc read-only queries
+//!
+//! Runs arbitrary SQL for `monzo query`, returning every value as text so any
+//! result set can be printed. The query runs on its own connection, opened
+//! read-only, and must be a single statement without `PRAGMA` or `ATTACH`.
+
+use sqlx::{
+    sqlite::SqliteConnectOptions, Column, ConnectOptions, Connection, Executor, Row,
+    SqliteConnection, TypeInfo, ValueRef,
+};
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// The columns and rows returned by a query. NULL values are `None`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+impl DatabasePool {
+    /// Run a read-only SQL statement
+    ///
+    /// # Errors
+    /// Will return an error if the SQL is invalid, isn't a single statement,
+    /// uses `PRAGMA` or `ATTACH`, or tries to write.
+    pub async fn query_read_only(&self, sql: &str) -> Result<QueryResult, Error> {
+        check(sql)?;
+
+        let filename = (*self.sqlite("query")?.connect_options())
+            .clone()
+            .get_filename();
+        let mut conn = SqliteConnectOptions::new()
+            .filename(filename)
+            .read_only(true)
+            .connect()
+            .await?;
+
+        let result = run(&mut conn, sql).await;
+        conn.close().await?;
+
+        result
+    }
+}
+
+// Reject anything but a single statement, and the statements that could
+// reach another database or change the connection
+fn check(sql: &str) -> Result<(), Error> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut statements = 0;
+    let mut in_statement = false;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+        } else if !word.is_empty() {
+            words.push(std::mem::take(&mut word).to_uppercase());
+        }
+
+        match c {
+            // comments separate words but aren't part of a statement
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().find(|&next| next == '\n');
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                chars.by_ref().find(|&next| {
+                    let end = previous == '*' && next == '/';
+                    previous = next;
+                    end
+                });
+                continue;
+            }
+            // quoted strings and identifiers
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                chars.by_ref().find(|&next| next == close);
+            }
+            _ => {}
+        }
+
+        if c == ';' {
+            in_statement = false;
+        } else if !c.is_whitespace() && !in_statement {
+            in_statement = true;
+            statements += 1;
+        }
+    }
+    if !word.is_empty() {
+        words.push(word.to_uppercase());
+    }
+
+    if statements > 1 {
+        return Err(Error::Error("Only a single statement can be run".into()));
+    }
+    if let Some(word) = words
+        .iter()
+        .find(|w| matches!(w.as_str(), "PRAGMA" | "ATTACH"))
+    {
+        return Err(Error::Error(format!("{word} can't be used in a query")));
+    }
+
+    Ok(())
+}
+
+async fn run(conn: &mut SqliteConnection, sql: &str) -> Result<QueryResult, Error> {
+    let rows = sqlx::query(sql)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| Error::Error(format!("Query failed: {e}")))?;
+
+    let columns = match rows.first() {
+        Some(row) => row.columns().iter().map(|c| c.name().to_string()).collect(),
+        None => conn
+            .describe(sql)
+            .await?
+            .columns()
+            .iter()
+            .map(|c| c.name().to_string())
+            .collect(),
+    };
+
+    let rows = rows
+        .iter()
+        .map(|row| (0..row.len()).map(|i| text(row, i)).collect())
+        .collect::<Result<_, Error>>()?;
+
+    Ok(QueryResult { columns, rows })
+}
+
+// Decode a value by its SQLite storage class
+fn text(row: &sqlx::sqlite::SqliteRow, index: usize) -> Result<Option<String>, Error> {
+    let value = row.try_get_raw(index)?;
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    let text = match value.type_info().name() {
+        "INTEGER" => row.try_get::<i64, _>(index)?.to_string(),
+        "REAL" => row.try_get::<f64, _>(index)?.to_string(),
+        "BLOB" => format!("<{} bytes>", row.try_get::<Vec<u8>, _>(index)?.len()),
+        _ => row.try_get::<String, _>(index)?,
+    };
+
+    Ok(Some(text))
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn reads_views() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+
+        // Act
+        let result = pool
+            .query_read_only(
+                "SELECT month, category_name, total, count FROM monthly_category_totals",
+            )
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            result.columns,
+            vec!["month", "category_name", "total", "count"]
+        );
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                Some("1970-01".to_string()),
+                Some("category_1".to_string()),
+                Some("0".to_string()),
+                Some("2".to_string()),
+            ]]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_writes() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+
+        // Act
+        let result = pool.query_read_only("DELETE FROM transactions").await;
+
+        // Assert
+        assert!(result.is_err());
+        let count = pool
+            .query_read_only("SELECT COUNT(*) AS n FROM transactions")
+            .await
+            .unwrap();
+        assert_eq!(count.rows, vec![vec![Some("2".to_string())]]);
+        // the pool's connections can still write
+        sqlx::query("DELETE FROM transactions")
+            .execute(pool.db())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn query_only_cant_be_turned_off() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+
+        // Act
+        let result = pool
+            .query_read_only("PRAGMA query_only = OFF; DELETE FROM transactions; SELECT 1")
+            .await;
+
+        // Assert
+        assert!(result.is_err());
+        let count = pool
+            .query_read_only("SELECT COUNT(*) AS n FROM transactions")
+            .await
+            .unwrap();
+        assert_eq!(count.rows, vec![vec![Some("2".to_string())]]);
+    }
+
+    #[test]
+    fn check_allows_a_single_statement() {
+        assert!(check("SELECT 1;  -- one; two\n").is_ok());
+        assert!(check("SELECT ';' AS \"a;b\" /* ; */").is_ok());
+        assert!(check("SELECT * FROM pragma_table_info('transactions')").is_ok());
+    }
+
+    #[test]
+    fn check_rejects_several_statements_pragma_and_attach() {
+        assert!(check("SELECT 1; SELECT 2").is_err());
+        assert!(check("pragma query_only = off").is_err());
+        assert!(check("ATTACH 'other.db' AS other").is_err());
+    }
+
+    #[tokio::test]
+    async fn empty_results_keep_their_columns() {
+        let (pool, _tmp) = test_db().await;
+
+        let result = pool
+            .query_read_only("SELECT day, balance FROM daily_balances WHERE day > '2100-01-01'")
+            .await
+            .unwrap();
+
+        assert_eq!(result.columns, vec!["day", "balance"]);
+        assert!(result.rows.is_empty());
+    }
+}
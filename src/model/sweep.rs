@@ -0,0 +1,86 @@
+//! Models for pot sweeps
+//!
+//! Each sweep deposited is recorded for its rule and month, so a rule that
+//! stays due until the end of the month only sweeps once.
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn is_swept(&self, rule: &str, month: NaiveDate) -> Result<bool, Error>;
+    async fn record_swept(
+        &self,
+        rule: &str,
+        month: NaiveDate,
+        swept: NaiveDateTime,
+    ) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteSweepService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteSweepService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteSweepService {
+    /// Whether the rule has swept for the month
+    #[tracing::instrument(name = "Check sweep", skip(self))]
+    async fn is_swept(&self, rule: &str, month: NaiveDate) -> Result<bool, Error> {
+        let db = self.pool.db();
+        let month = month.format("%Y-%m").to_string();
+
+        let sweep = sqlx::query!(
+            r"
+                SELECT rule FROM sweeps
+                WHERE rule = $1 AND month = $2
+            ",
+            rule,
+            month
+        )
+        .fetch_optional(db)
+        .await?;
+
+        Ok(sweep.is_some())
+    }
+
+    /// Record that the rule has swept for the month
+    #[tracing::instrument(name = "Record sweep", skip(self))]
+    async fn record_swept(
+        &self,
+        rule: &str,
+        month: NaiveDate,
+        swept: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let db = self.pool.db();
+        let month = month.format("%Y-%m").to_string();
+
+        sqlx::query!(
+            r"
+                INSERT OR IGNORE INTO sweeps (rule, month, swept)
+                VALUES ($1, $2, $3)
+            ",
+            rule,
+            month,
+            swept
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+}
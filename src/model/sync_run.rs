@@ -0,0 +1,499 @@
+//! Models for the sync run audit log and its checkpoints
+
+use std::{collections::HashSet, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use tracing_log::log::{error, info};
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// A record of one update run
+#[derive(Debug, Default, Clone, sqlx::FromRow)]
+pub struct SyncRun {
+    pub id: i64,
+    pub started: NaiveDateTime,
+    pub finished: Option<NaiveDateTime>,
+    pub range_start: NaiveDateTime,
+    pub range_end: NaiveDateTime,
+    pub accounts: i64,
+    pub inserted: i64,
+    pub updated: i64,
+    pub skipped: i64,
+    pub error: Option<String>,
+    /// Phase timings in milliseconds, for runs made with `--profile-sync`
+    pub fetch_ms: Option<i64>,
+    pub dedupe_ms: Option<i64>,
+    pub insert_ms: Option<i64>,
+    pub store_ms: Option<i64>,
+    pub classify_ms: Option<i64>,
+}
+
+impl SyncRun {
+    /// The phase timings of a profiled run
+    #[must_use]
+    pub fn profile(&self) -> Option<SyncProfile> {
+        let millis = |ms: Option<i64>| Some(Duration::from_millis(u64::try_from(ms?).ok()?));
+        Some(SyncProfile {
+            fetch: millis(self.fetch_ms)?,
+            dedupe: millis(self.dedupe_ms)?,
+            insert: millis(self.insert_ms)?,
+            store: millis(self.store_ms)?,
+            classify: millis(self.classify_ms)?,
+        })
+    }
+}
+
+/// Counts recorded when a run finishes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunCounts {
+    pub accounts: i64,
+    pub inserted: i64,
+    pub updated: i64,
+    pub skipped: i64,
+}
+
+/// Time spent in each phase of a run
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncProfile {
+    /// Requests to the Monzo API
+    pub fetch: Duration,
+    /// Saving transactions that were already stored, and updating those that
+    /// changed
+    pub dedupe: Duration,
+    /// Inserting new transactions
+    pub insert: Duration,
+    /// Storing accounts, pots, balances, categories, card events and rates
+    pub store: Duration,
+    /// Classifying transfers and categorising after fetching
+    pub classify: Duration,
+}
+
+impl SyncProfile {
+    /// The time spent in all phases
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.fetch + self.dedupe + self.insert + self.store + self.classify
+    }
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn start_run(
+        &self,
+        range_start: NaiveDateTime,
+        range_end: NaiveDateTime,
+    ) -> Result<i64, Error>;
+    async fn finish_run(
+        &self,
+        run_id: i64,
+        counts: RunCounts,
+        error: Option<String>,
+    ) -> Result<(), Error>;
+    async fn save_profile(&self, run_id: i64, profile: &SyncProfile) -> Result<(), Error>;
+    async fn read_runs(&self, limit: i64) -> Result<Vec<SyncRun>, Error>;
+    async fn checkpoint(
+        &self,
+        run_id: i64,
+        account_id: &str,
+        window_start: NaiveDateTime,
+        window_end: NaiveDateTime,
+    ) -> Result<(), Error>;
+    async fn completed_windows(
+        &self,
+        run_id: i64,
+    ) -> Result<HashSet<(String, NaiveDateTime)>, Error>;
+    async fn prune_runs(&self, before: NaiveDateTime) -> Result<(u64, u64), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteSyncRunService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteSyncRunService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteSyncRunService {
+    #[tracing::instrument(name = "Start sync run", skip(self))]
+    async fn start_run(
+        &self,
+        range_start: NaiveDateTime,
+        range_end: NaiveDateTime,
+    ) -> Result<i64, Error> {
+        let db = self.pool.db();
+        let started = Utc::now().naive_utc();
+
+        match sqlx::query!(
+            r"
+                INSERT INTO sync_runs (started, range_start, range_end)
+                VALUES ($1, $2, $3)
+            ",
+            started,
+            range_start,
+            range_end,
+        )
+        .execute(db)
+        .await
+        {
+            Ok(result) => {
+                info!("Started sync run: {}", result.last_insert_rowid());
+                Ok(result.last_insert_rowid())
+            }
+            Err(e) => {
+                error!("Failed to start sync run: {}", e);
+                Err(Error::DbError(e.to_string()))
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Finish sync run", skip(self))]
+    async fn finish_run(
+        &self,
+        run_id: i64,
+        counts: RunCounts,
+        error: Option<String>,
+    ) -> Result<(), Error> {
+        let db = self.pool.db();
+        let finished = Utc::now().naive_utc();
+
+        sqlx::query!(
+            r"
+                UPDATE sync_runs
+                SET finished = $1,
+                    accounts = $2,
+                    inserted = inserted + $3,
+                    updated = updated + $4,
+                    skipped = skipped + $5,
+                    error = $6
+                WHERE id = $7
+            ",
+            finished,
+            counts.accounts,
+            counts.inserted,
+            counts.updated,
+            counts.skipped,
+            error,
+            run_id,
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Save sync run profile", skip(self))]
+    async fn save_profile(&self, run_id: i64, profile: &SyncProfile) -> Result<(), Error> {
+        let db = self.pool.db();
+        let millis = |duration: Duration| i64::try_from(duration.as_millis()).unwrap_or(i64::MAX);
+        let (fetch, dedupe, insert, store, classify) = (
+            millis(profile.fetch),
+            millis(profile.dedupe),
+            millis(profile.insert),
+            millis(profile.store),
+            millis(profile.classify),
+        );
+
+        sqlx::query!(
+            r"
+                UPDATE sync_runs
+                SET fetch_ms = $1,
+                    dedupe_ms = $2,
+                    insert_ms = $3,
+                    store_ms = $4,
+                    classify_ms = $5
+                WHERE id = $6
+            ",
+            fetch,
+            dedupe,
+            insert,
+            store,
+            classify,
+            run_id,
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Read sync runs", skip(self))]
+    async fn read_runs(&self, limit: i64) -> Result<Vec<SyncRun>, Error> {
+        let db = self.pool.db();
+
+        let runs = sqlx::query_as!(
+            SyncRun,
+            r"
+                SELECT *
+                FROM sync_runs
+                ORDER BY id DESC
+                LIMIT $1
+            ",
+            limit,
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(runs)
+    }
+
+    /// Record that a window of an account has been fetched and persisted
+    #[tracing::instrument(name = "Checkpoint sync window", skip(self))]
+    async fn checkpoint(
+        &self,
+        run_id: i64,
+        account_id: &str,
+        window_start: NaiveDateTime,
+        window_end: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let db = self.pool.db();
+        let completed = Utc::now().naive_utc();
+
+        sqlx::query!(
+            r"
+                INSERT OR REPLACE INTO sync_state (run_id, account_id, window_start, window_end, completed)
+                VALUES ($1, $2, $3, $4, $5)
+            ",
+            run_id,
+            account_id,
+            window_start,
+            window_end,
+            completed,
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The (account id, window start) pairs already completed by a run
+    #[tracing::instrument(name = "Read completed windows", skip(self))]
+    async fn completed_windows(
+        &self,
+        run_id: i64,
+    ) -> Result<HashSet<(String, NaiveDateTime)>, Error> {
+        let db = self.pool.db();
+
+        let rows = sqlx::query!(
+            r"
+                SELECT account_id, window_start
+                FROM sync_state
+                WHERE run_id = $1
+            ",
+            run_id,
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.account_id, row.window_start))
+            .collect())
+    }
+
+    /// Delete runs started before `before`, and their checkpoints, keeping the
+    /// most recent run. Returns the number of runs and checkpoints deleted.
+    #[tracing::instrument(name = "Prune sync runs", skip(self))]
+    async fn prune_runs(&self, before: NaiveDateTime) -> Result<(u64, u64), Error> {
+        let mut tx = self.pool.db().begin().await?;
+
+        let checkpoints = sqlx::query!(
+            r"
+                DELETE FROM sync_state
+                WHERE run_id IN (
+                    SELECT id FROM sync_runs
+                    WHERE started < $1
+                    AND id < (SELECT MAX(id) FROM sync_runs)
+                )
+            ",
+            before,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let runs = sqlx::query!(
+            r"
+                DELETE FROM sync_runs
+                WHERE started < $1
+                AND id < (SELECT MAX(id) FROM sync_runs)
+            ",
+            before,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+        info!("Pruned {runs} sync runs and {checkpoints} checkpoints");
+
+        Ok((runs, checkpoints))
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    #[tokio::test]
+    async fn start_and_finish_run() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteSyncRunService::new(pool);
+        let now = Utc::now().naive_utc();
+        let counts = RunCounts {
+            accounts: 2,
+            inserted: 5,
+            updated: 1,
+            skipped: 3,
+        };
+
+        // Act
+        let run_id = service.start_run(now, now).await.unwrap();
+        service.finish_run(run_id, counts, None).await.unwrap();
+        let runs = service.read_runs(10).await.unwrap();
+
+        // Assert
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].inserted, 5);
+        assert!(runs[0].finished.is_some());
+    }
+
+    #[tokio::test]
+    async fn profiles_are_read_back_from_the_run() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteSyncRunService::new(pool);
+        let now = Utc::now().naive_utc();
+        let profile = SyncProfile {
+            fetch: Duration::from_millis(1200),
+            insert: Duration::from_millis(30),
+            ..Default::default()
+        };
+        let profiled = service.start_run(now, now).await.unwrap();
+        let unprofiled = service.start_run(now, now).await.unwrap();
+
+        // Act
+        service.save_profile(profiled, &profile).await.unwrap();
+        let runs = service.read_runs(10).await.unwrap();
+
+        // Assert
+        let run = |id| runs.iter().find(|run| run.id == id).unwrap();
+        assert_eq!(run(profiled).profile(), Some(profile));
+        assert_eq!(run(unprofiled).profile(), None);
+        assert_eq!(profile.total(), Duration::from_millis(1230));
+    }
+
+    #[tokio::test]
+    async fn read_runs_newest_first() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteSyncRunService::new(pool);
+        let now = Utc::now().naive_utc();
+
+        // Act
+        let first = service.start_run(now, now).await.unwrap();
+        let second = service.start_run(now, now).await.unwrap();
+        let runs = service.read_runs(1).await.unwrap();
+
+        // Assert
+        assert!(second > first);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].id, second);
+    }
+
+    #[tokio::test]
+    async fn checkpoints_are_read_back() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteSyncRunService::new(pool);
+        let now = Utc::now().naive_utc();
+        let run_id = service.start_run(now, now).await.unwrap();
+
+        // Act
+        service.checkpoint(run_id, "1", now, now).await.unwrap();
+        service.checkpoint(run_id, "1", now, now).await.unwrap();
+        let completed = service.completed_windows(run_id).await.unwrap();
+
+        // Assert
+        assert_eq!(completed.len(), 1);
+        assert!(completed.contains(&("1".to_string(), now)));
+    }
+
+    #[tokio::test]
+    async fn finishing_a_resumed_run_accumulates_counts() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteSyncRunService::new(pool);
+        let now = Utc::now().naive_utc();
+        let counts = RunCounts {
+            inserted: 2,
+            ..Default::default()
+        };
+        let run_id = service.start_run(now, now).await.unwrap();
+
+        // Act
+        service
+            .finish_run(run_id, counts, Some("interrupted".to_string()))
+            .await
+            .unwrap();
+        service.finish_run(run_id, counts, None).await.unwrap();
+        let run = service.read_runs(1).await.unwrap().remove(0);
+
+        // Assert
+        assert_eq!(run.inserted, 4);
+        assert!(run.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn prune_keeps_recent_and_latest_runs() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteSyncRunService::new(pool.clone());
+        let now = Utc::now().naive_utc();
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let id = service.start_run(now, now).await.unwrap();
+            service.checkpoint(id, "1", now, now).await.unwrap();
+            ids.push(id);
+        }
+        // the first and last runs are old
+        let old = now - chrono::Duration::days(400);
+        for id in [ids[0], ids[2]] {
+            sqlx::query!("UPDATE sync_runs SET started = $1 WHERE id = $2", old, id)
+                .execute(pool.db())
+                .await
+                .unwrap();
+        }
+
+        // Act
+        let pruned = service
+            .prune_runs(now - chrono::Duration::days(365))
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(pruned, (1, 1));
+        let remaining: Vec<i64> = service
+            .read_runs(10)
+            .await
+            .unwrap()
+            .iter()
+            .map(|run| run.id)
+            .collect();
+        assert_eq!(remaining, vec![ids[2], ids[1]]);
+    }
+}
@@ -2,15 +2,13 @@
 #![allow(dead_code)]
 use async_trait::async_trait;
 use chrono::{DateTime, NaiveDateTime, Utc};
-use serde::{Deserialize, Deserializer};
-use sqlx::{FromRow, Pool, Sqlite};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::{Pool, Sqlite};
 use tracing_log::log::{error, info};
 
 use super::{
-    category::Category,
-    merchant::{Merchant, Service as MerchantService, SqliteMerchantService},
-    pot::Pot,
-    DatabasePool,
+    attachment::AttachmentResponse, category::Category, counterparty::Counterparty, dedupe::Source,
+    merchant::Merchant, pot::Pot, DatabasePool,
 };
 use crate::error::AppErrors as Error;
 
@@ -34,10 +32,20 @@ pub struct TransactionResponse {
     pub created: DateTime<Utc>,
     pub description: String,
     pub notes: Option<String>,
-    #[serde(deserialize_with = "deserialize_optional_datetime")]
+    #[serde(default, deserialize_with = "deserialize_optional_datetime")]
     pub settled: Option<DateTime<Utc>>,
     pub updated: Option<DateTime<Utc>>,
-    pub category: String,
+    #[serde(rename = "category")]
+    pub category_id: String,
+    /// The other side of a bank transfer
+    #[serde(default)]
+    pub counterparty: Option<Counterparty>,
+    /// Receipts and other files attached in the app
+    #[serde(default)]
+    pub attachments: Option<Vec<AttachmentResponse>>,
+    /// Why the payment was declined, e.g. `INSUFFICIENT_FUNDS`
+    #[serde(default)]
+    pub decline_reason: Option<String>,
 }
 
 /// Represents a transaction from the database
@@ -56,6 +64,22 @@ pub struct TransactionForDB {
     pub settled: Option<NaiveDateTime>,
     pub updated: Option<NaiveDateTime>,
     pub category_id: String,
+    pub counterparty_account_number: Option<String>,
+    pub counterparty_sort_code: Option<String>,
+    /// Whether the transaction moves money between the user's own accounts
+    pub is_transfer: bool,
+    /// The person paid or paid by, for P2P payments
+    pub counterparty_id: Option<String>,
+    /// The manual liability account a card repayment pays off
+    pub repayment_account_id: Option<i64>,
+    /// A hash of the account, currency, amount and payee, see [`super::dedupe`]
+    pub dedupe_key: Option<String>,
+    /// Where the transaction came from: `api`, `csv` or `fixture`
+    pub source: String,
+    /// The amount first stored while pending, if it has changed since
+    pub pending_amount: Option<i64>,
+    /// Why the payment was declined
+    pub decline_reason: Option<String>,
 }
 
 impl From<TransactionResponse> for TransactionForDB {
@@ -73,13 +97,26 @@ impl From<TransactionResponse> for TransactionForDB {
             notes: tx.notes,
             settled: tx.settled.map(|utc_time| utc_time.naive_utc()),
             updated: tx.updated.map(|utc_time| utc_time.naive_utc()),
-            category_id: tx.category,
+            category_id: tx.category_id,
+            counterparty_account_number: tx
+                .counterparty
+                .as_ref()
+                .and_then(|c| c.account_number.clone()),
+            counterparty_sort_code: tx.counterparty.as_ref().and_then(|c| c.sort_code.clone()),
+            is_transfer: false,
+            counterparty_id: tx.counterparty.and_then(|c| c.user_id),
+            repayment_account_id: None,
+            dedupe_key: None,
+            source: Source::Api.to_string(),
+            pending_amount: None,
+            decline_reason: tx.decline_reason,
         }
     }
 }
 
 /// A structure for holding Beancount Transaction data
-#[derive(FromRow, Debug, Clone)]
+#[cfg(feature = "beancount")]
+#[derive(sqlx::FromRow, Debug, Clone)]
 pub struct BeancountTransaction {
     pub id: String,
     pub created: NaiveDateTime,
@@ -96,12 +133,181 @@ pub struct BeancountTransaction {
     pub pot_name: Option<String>,
 }
 
+/// A transaction joined with its account, category, merchant and pot names, for exporters
+#[derive(sqlx::FromRow, Debug, Default, Clone)]
+pub struct ExportTransaction {
+    pub id: String,
+    pub account_id: String,
+    pub account_name: String,
+    pub created: NaiveDateTime,
+    pub settled: Option<NaiveDateTime>,
+    pub amount: i64,
+    /// The transaction's currency, or its account's if it has none
+    pub currency: String,
+    pub local_amount: i64,
+    pub local_currency: String,
+    pub description: String,
+    pub notes: Option<String>,
+    pub category_name: String,
+    /// The category's emoji and display name, for tables and reports
+    pub category_label: String,
+    pub merchant_name: Option<String>,
+    pub pot_name: Option<String>,
+    /// Whether the transaction moves money between the user's own accounts
+    pub is_transfer: bool,
+    /// The person paid or paid by, for P2P payments
+    pub counterparty_name: Option<String>,
+    /// The manual liability account a card repayment pays off
+    pub repayment_account_id: Option<i64>,
+    /// Tags added by hand, space separated
+    pub tags: Option<String>,
+    /// Merchant coordinates, if the API reported them
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// The merchant's cached logo file, or else its logo URL
+    pub merchant_logo: Option<String>,
+}
+
+/// Spending and income per category
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct CategoryTotal {
+    pub category_name: String,
+    /// The category's emoji and display name
+    pub category_label: String,
+    pub currency: String,
+    pub total: i64,
+    pub count: i64,
+}
+
+/// Where an incremental update of an account can start from
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct SyncPoint {
+    pub account_id: String,
+    /// The newest synced transaction
+    pub latest: NaiveDateTime,
+    /// The oldest synced transaction that may still settle: pending, not
+    /// declined, and changed in the 30 days before `latest`
+    pub oldest_pending: Option<NaiveDateTime>,
+}
+
+impl SyncPoint {
+    /// The earliest transaction an update must fetch again to bring the
+    /// account up to date
+    #[must_use]
+    pub fn start(&self) -> NaiveDateTime {
+        self.oldest_pending
+            .map_or(self.latest, |pending| pending.min(self.latest))
+    }
+}
+
+/// The net of all synced transactions per account in one currency
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct AccountTotal {
+    pub account_id: String,
+    pub account_name: String,
+    pub currency: String,
+    pub total: i64,
+    /// Number of transactions
+    pub count: i64,
+}
+
+/// A transaction that settled for a different amount than it was pending for
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct SettlementDrift {
+    pub id: String,
+    pub account_id: String,
+    pub account_name: String,
+    pub created: NaiveDateTime,
+    pub settled: NaiveDateTime,
+    pub description: String,
+    pub merchant_name: Option<String>,
+    pub currency: String,
+    pub pending_amount: i64,
+    pub amount: i64,
+}
+
+impl SettlementDrift {
+    /// How much the amount changed by on settling; negative if more was spent
+    #[must_use]
+    pub fn difference(&self) -> i64 {
+        self.amount - self.pending_amount
+    }
+}
+
+/// The net of an account's transactions in one currency to or from one of its
+/// pots, or of its other transactions when `pot_id` is `None`
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PotTotal {
+    pub account_id: String,
+    pub pot_id: Option<String>,
+    pub currency: String,
+    pub total: i64,
+}
+
+/// What saving a transaction that may already be stored did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Upsert {
+    Inserted,
+    Updated,
+    Unchanged,
+}
+
 // -- Services -------------------------------------------------------------------------
 
 #[async_trait]
 pub trait Service {
-    async fn save_transaction(&self, tx_resp: &TransactionResponse) -> Result<(), Error>;
+    async fn save_transaction(&self, tx_resp: &TransactionResponse) -> Result<(), Error> {
+        self.save_transaction_from(tx_resp, Source::Api).await
+    }
+    async fn save_transaction_from(
+        &self,
+        tx_resp: &TransactionResponse,
+        source: Source,
+    ) -> Result<(), Error>;
+    /// Save a new transaction, or update the stored one if it has changed
+    async fn save_or_update_transaction(
+        &self,
+        tx_resp: &TransactionResponse,
+        refresh: bool,
+    ) -> Result<Upsert, Error> {
+        self.save_or_update_transaction_from(tx_resp, refresh, Source::Api)
+            .await
+    }
+    /// Save a new transaction from `source`, or update the stored one if it
+    /// has changed. A sync marks a transaction stored from a webhook as synced.
+    async fn save_or_update_transaction_from(
+        &self,
+        tx_resp: &TransactionResponse,
+        refresh: bool,
+        source: Source,
+    ) -> Result<Upsert, Error> {
+        match self.save_transaction_from(tx_resp, source).await {
+            Ok(()) => Ok(Upsert::Inserted),
+            Err(Error::Duplicate(_)) => {
+                if source == Source::Api {
+                    self.mark_synced(&tx_resp.id).await?;
+                }
+                if self.update_transaction(tx_resp, refresh).await? {
+                    Ok(Upsert::Updated)
+                } else {
+                    Ok(Upsert::Unchanged)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+    async fn update_transaction(
+        &self,
+        tx_resp: &TransactionResponse,
+        refresh: bool,
+    ) -> Result<bool, Error>;
+    async fn mark_synced(&self, tx_id: &str) -> Result<(), Error>;
     async fn read_transactions(&self) -> Result<Vec<TransactionForDB>, Error>;
+    async fn read_transactions_page(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TransactionForDB>, Error>;
     async fn read_transactions_for_dates(
         &self,
         from: NaiveDateTime,
@@ -109,11 +315,46 @@ pub trait Service {
     ) -> Result<Vec<TransactionForDB>, Error>;
     async fn read_transaction(&self, tx_id: &str) -> Result<TransactionForDB, Error>;
     async fn delete_all_transactions(&self) -> Result<(), Error>;
+    #[cfg(feature = "beancount")]
     async fn read_beancount_data(
         &self,
         from: NaiveDateTime,
         until: NaiveDateTime,
     ) -> Result<Vec<BeancountTransaction>, Error>;
+    async fn read_export_data(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<ExportTransaction>, Error>;
+    async fn read_report_data(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<ExportTransaction>, Error>;
+    async fn read_export_page(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<ExportTransaction>, Error>;
+    async fn read_category_totals(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<CategoryTotal>, Error>;
+    async fn read_account_totals(&self) -> Result<Vec<AccountTotal>, Error>;
+    async fn read_sync_points(&self) -> Result<Vec<SyncPoint>, Error>;
+    async fn read_pot_totals(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PotTotal>, Error>;
+    async fn read_settlement_drift(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<SettlementDrift>, Error>;
     async fn get_categories_for_account(&self, account_id: &str) -> Result<Vec<Category>, Error>;
     async fn get_pots_for_account(&self, account_id: &str) -> Result<Vec<Pot>, Error>;
 }
@@ -139,19 +380,28 @@ impl Service for SqliteTransactionService {
         skip(self, tx_resp),
         fields(tx_id = %tx_resp.id, acc_id = %tx_resp.account_id)
     )]
-    async fn save_transaction(&self, tx_resp: &TransactionResponse) -> Result<(), Error> {
+    async fn save_transaction_from(
+        &self,
+        tx_resp: &TransactionResponse,
+        source: Source,
+    ) -> Result<(), Error> {
         let db = self.pool.db();
 
         let tx = TransactionForDB::from((*tx_resp).clone());
 
-        if is_duplicate_transaction(db, &tx.id).await? {
+        if is_duplicate_transaction(db, &tx.id).await?
+            || self.pool.resolve_alias(&tx.id).await?.is_some()
+        {
             info!("Transaction exists. Skipping");
             return Err(Error::Duplicate("Transaction already exists".to_string()));
         }
 
-        let merchant_id = insert_merchant(self.pool.clone(), &tx_resp.merchant).await?;
+        let merchant_id = insert_merchant(self.pool.clone(), tx_resp.merchant.as_ref()).await?;
+        let counterparty_id =
+            insert_counterparty(self.pool.clone(), tx_resp.counterparty.as_ref()).await?;
 
         info!("Inserting transaction");
+        let source_name = source.as_str();
         match sqlx::query!(
             r"
                 INSERT INTO transactions (
@@ -167,9 +417,16 @@ impl Service for SqliteTransactionService {
                     notes,
                     settled,
                     updated,
-                    category_id
+                    category_id,
+                    counterparty_account_number,
+                    counterparty_sort_code,
+                    counterparty_id,
+                    source,
+                    decline_reason
+                )
+                VALUES (
+                    $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18
                 )
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
             ",
             tx.id,
             tx.account_id,
@@ -184,12 +441,25 @@ impl Service for SqliteTransactionService {
             tx.settled,
             tx.updated,
             tx.category_id,
+            tx.counterparty_account_number,
+            tx.counterparty_sort_code,
+            counterparty_id,
+            source_name,
+            tx_resp.decline_reason,
         )
         .execute(db)
         .await
         {
             Ok(_) => {
                 info!("Created transaction: {}", tx.id);
+                self.pool.set_dedupe_keys(Some(&tx.id)).await?;
+                // the API's transaction replaces one imported from elsewhere
+                if source.is_monzo() {
+                    if let Some(duplicate) = self.pool.find_duplicate(&tx.id, source).await? {
+                        info!("Merging {duplicate} into {}", tx.id);
+                        self.pool.merge_transaction(&duplicate, &tx.id).await?;
+                    }
+                }
                 Ok(())
             }
             Err(e) => {
@@ -205,6 +475,104 @@ impl Service for SqliteTransactionService {
         }
     }
 
+    /// Bring a stored transaction up to date with the API: while it's pending,
+    /// or when Monzo last updated it after the stored copy, such as a note
+    /// edited in the app. With `refresh` any difference is updated.
+    /// Returns true if the stored transaction changed
+    #[tracing::instrument(
+        name = "Update transaction",
+        skip(self, tx_resp),
+        fields(tx_id = %tx_resp.id)
+    )]
+    async fn update_transaction(
+        &self,
+        tx_resp: &TransactionResponse,
+        refresh: bool,
+    ) -> Result<bool, Error> {
+        let db = self.pool.db();
+
+        let tx = TransactionForDB::from((*tx_resp).clone());
+        let merchant_id = insert_merchant(self.pool.clone(), tx_resp.merchant.as_ref()).await?;
+
+        let result = sqlx::query!(
+            r"
+                UPDATE transactions
+                SET
+                    merchant_id = $2,
+                    pending_amount = COALESCE(
+                        pending_amount,
+                        CASE WHEN settled IS NULL AND amount != $3 THEN amount END
+                    ),
+                    amount = $3,
+                    local_amount = $4,
+                    local_currency = $5,
+                    description = $6,
+                    settled = $7,
+                    updated = $8,
+                    category_id = COALESCE(
+                        (SELECT category_id FROM category_overrides WHERE transaction_id = $1),
+                        $9
+                    ),
+                    notes = $10,
+                    decline_reason = $12
+                WHERE id = $1
+                AND (settled IS NULL OR COALESCE(updated, '') < $8 OR $11)
+                AND (
+                    merchant_id IS NOT $2
+                    OR amount != $3
+                    OR local_amount != $4
+                    OR local_currency != $5
+                    OR description != $6
+                    OR settled IS NOT $7
+                    OR notes IS NOT $10
+                    OR decline_reason IS NOT $12
+                    OR category_id != COALESCE(
+                        (SELECT category_id FROM category_overrides WHERE transaction_id = $1),
+                        $9
+                    )
+                )
+            ",
+            tx.id,
+            merchant_id,
+            tx.amount,
+            tx.local_amount,
+            tx.local_currency,
+            tx.description,
+            tx.settled,
+            tx.updated,
+            tx.category_id,
+            tx.notes,
+            refresh,
+            tx_resp.decline_reason,
+        )
+        .execute(db)
+        .await?;
+
+        let changed = result.rows_affected() > 0;
+        if changed {
+            info!("Updated transaction: {}", tx.id);
+            self.pool.set_dedupe_keys(Some(&tx.id)).await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Record that a sync has fetched a transaction stored from a webhook
+    #[tracing::instrument(name = "Mark transaction synced", skip(self))]
+    async fn mark_synced(&self, tx_id: &str) -> Result<(), Error> {
+        let (api, webhook) = (Source::Api.as_str(), Source::Webhook.as_str());
+        sqlx::query!(
+            "UPDATE transactions SET source = $2 WHERE id = $1 AND source = $3",
+            tx_id,
+            api,
+            webhook
+        )
+        .execute(self.pool.db())
+        .await?;
+
+        Ok(())
+    }
+
     #[tracing::instrument(name = "Read transactions", skip(self))]
     async fn read_transactions(&self) -> Result<Vec<TransactionForDB>, Error> {
         let db = self.pool.db();
@@ -215,6 +583,7 @@ impl Service for SqliteTransactionService {
             r"
                 SELECT *
                 FROM transactions
+                ORDER BY created, id
             "
         )
         .fetch_all(db)
@@ -231,6 +600,35 @@ impl Service for SqliteTransactionService {
         }
     }
 
+    /// Read up to `limit` transactions ordered by date, starting after the
+    /// transaction with id `after`. Pass the last id of a page to get the next.
+    #[tracing::instrument(name = "Read transactions page", skip(self))]
+    async fn read_transactions_page(
+        &self,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TransactionForDB>, Error> {
+        let db = self.pool.db();
+
+        let transactions = sqlx::query_as!(
+            TransactionForDB,
+            r"
+                SELECT *
+                FROM transactions
+                WHERE $1 IS NULL
+                OR (created, id) > (SELECT created, id FROM transactions WHERE id = $1)
+                ORDER BY created, id
+                LIMIT $2
+            ",
+            after,
+            limit
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(transactions)
+    }
+
     #[tracing::instrument(name = "Read transactions for dates", skip(self))]
     async fn read_transactions_for_dates(
         &self,
@@ -300,6 +698,7 @@ impl Service for SqliteTransactionService {
     }
 
     /// Read data anf format for processing in the beancouint module
+    #[cfg(feature = "beancount")]
     #[tracing::instrument(name = "Read beancount data", skip(self))]
     async fn read_beancount_data(
         &self,
@@ -310,14 +709,14 @@ impl Service for SqliteTransactionService {
 
         let transactions = sqlx::query_as!(
             BeancountTransaction,
-            r"
+            r#"
                 SELECT
                     t.id,
                     t.created,
                     t.settled,
                     a.owner_type AS account_name,
                     t.amount,
-                    a.currency,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS "currency!: String",
                     t.local_amount,
                     t.local_currency,
                     t.description,
@@ -334,7 +733,7 @@ impl Service for SqliteTransactionService {
                 WHERE t.created
                 BETWEEN $1 AND $2
 
-            ",
+            "#,
             from,
             until
         )
@@ -344,13 +743,346 @@ impl Service for SqliteTransactionService {
         Ok(transactions)
     }
 
+    /// Read transactions with joined names, ordered by account then date
+    #[tracing::instrument(name = "Read export data", skip(self))]
+    async fn read_export_data(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<ExportTransaction>, Error> {
+        let db = self.pool.db();
+
+        let transactions = sqlx::query_as!(
+            ExportTransaction,
+            r#"
+                SELECT
+                    t.id,
+                    t.account_id,
+                    a.owner_type AS account_name,
+                    t.created,
+                    t.settled,
+                    t.amount,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS "currency!: String",
+                    t.local_amount,
+                    t.local_currency,
+                    t.description,
+                    t.notes,
+                    c.name AS category_name,
+                    COALESCE(c.emoji || ' ', '') || COALESCE(c.display_name, c.name) AS "category_label!: String",
+                    m.name AS "merchant_name?",
+                    p.name AS "pot_name?",
+                    t.is_transfer AS "is_transfer: bool",
+                    cp.name AS "counterparty_name?",
+                    t.repayment_account_id,
+                    (
+                        SELECT group_concat(tag, ' ')
+                        FROM (SELECT tag FROM transaction_tags WHERE transaction_id = t.id ORDER BY tag)
+                    ) AS "tags?: String",
+                    m.latitude,
+                    m.longitude,
+                    COALESCE(m.logo_path, m.logo) AS "merchant_logo?: String"
+                FROM transactions t
+                JOIN accounts a ON t.account_id = a.id
+                JOIN categories c ON t.category_id = c.id
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+                LEFT JOIN pots p ON t.description = p.id
+                LEFT JOIN counterparties cp ON t.counterparty_id = cp.id
+                WHERE t.created
+                BETWEEN $1 AND $2
+                ORDER BY t.account_id, t.created, t.id
+            "#,
+            from,
+            until
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(describe_pot_transfers(&self.pool, transactions))
+    }
+
+    /// Read transactions as for an export, leaving out those smaller than the
+    /// pool's minimum amount, for reports
+    #[tracing::instrument(name = "Read report data", skip(self))]
+    async fn read_report_data(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<ExportTransaction>, Error> {
+        let min_amount = self.pool.min_amount();
+        let mut transactions = self.read_export_data(from, until).await?;
+        transactions.retain(|tx| tx.amount.abs() >= min_amount);
+
+        Ok(transactions)
+    }
+
+    /// Read up to `limit` transactions with joined names created between
+    /// `from` and `until`, ordered by date and starting after the transaction
+    /// with id `after`
+    #[tracing::instrument(name = "Read export page", skip(self))]
+    async fn read_export_page(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<ExportTransaction>, Error> {
+        let db = self.pool.db();
+
+        let transactions = sqlx::query_as!(
+            ExportTransaction,
+            r#"
+                SELECT
+                    t.id,
+                    t.account_id,
+                    a.owner_type AS account_name,
+                    t.created,
+                    t.settled,
+                    t.amount,
+                    COALESCE(NULLIF(t.currency, ''), a.currency) AS "currency!: String",
+                    t.local_amount,
+                    t.local_currency,
+                    t.description,
+                    t.notes,
+                    c.name AS category_name,
+                    COALESCE(c.emoji || ' ', '') || COALESCE(c.display_name, c.name) AS "category_label!: String",
+                    m.name AS "merchant_name?",
+                    p.name AS "pot_name?",
+                    t.is_transfer AS "is_transfer: bool",
+                    cp.name AS "counterparty_name?",
+                    t.repayment_account_id,
+                    (
+                        SELECT group_concat(tag, ' ')
+                        FROM (SELECT tag FROM transaction_tags WHERE transaction_id = t.id ORDER BY tag)
+                    ) AS "tags?: String",
+                    m.latitude,
+                    m.longitude,
+                    COALESCE(m.logo_path, m.logo) AS "merchant_logo?: String"
+                FROM transactions t
+                JOIN accounts a ON t.account_id = a.id
+                JOIN categories c ON t.category_id = c.id
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+                LEFT JOIN pots p ON t.description = p.id
+                LEFT JOIN counterparties cp ON t.counterparty_id = cp.id
+                WHERE t.created BETWEEN $1 AND $2
+                AND (
+                    $3 IS NULL
+                    OR (t.created, t.id) > (SELECT created, id FROM transactions WHERE id = $3)
+                )
+                ORDER BY t.created, t.id
+                LIMIT $4
+            "#,
+            from,
+            until,
+            after,
+            limit
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(describe_pot_transfers(&self.pool, transactions))
+    }
+
+    /// Sum transactions per category, largest spend first. Transfers and
+    /// transactions smaller than the pool's minimum amount are left out.
+    #[tracing::instrument(name = "Read category totals", skip(self))]
+    async fn read_category_totals(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<CategoryTotal>, Error> {
+        let db = self.pool.db();
+        let min_amount = self.pool.min_amount();
+        let key = (from, self.pool.range_end_key(until).await?, min_amount);
+
+        self.pool
+            .cached("category_totals", &key, || async {
+                sqlx::query_as!(
+                    CategoryTotal,
+                    r#"
+                        SELECT
+                            c.name AS "category_name!",
+                            MIN(COALESCE(c.emoji || ' ', '') || COALESCE(c.display_name, c.name)) AS "category_label!: String",
+                            COALESCE(NULLIF(t.currency, ''), a.currency) AS "currency!: String",
+                            SUM(t.amount) AS "total!: i64",
+                            COUNT(*) AS "count!: i64"
+                        FROM transactions t
+                        JOIN accounts a ON t.account_id = a.id
+                        JOIN categories c ON t.category_id = c.id
+                        WHERE t.created
+                        BETWEEN $1 AND $2
+                        AND NOT t.is_transfer
+                        AND ABS(t.amount) >= $3
+                        GROUP BY c.name, 3
+                        ORDER BY 4
+                    "#,
+                    from,
+                    until,
+                    min_amount
+                )
+                .fetch_all(db)
+                .await
+                .map_err(Error::from)
+            })
+            .await
+    }
+
+    /// Sum all synced transactions per account and currency. A transaction
+    /// without a currency is in its account's, as is an account without any.
+    #[tracing::instrument(name = "Read account totals", skip(self))]
+    async fn read_account_totals(&self) -> Result<Vec<AccountTotal>, Error> {
+        let db = self.pool.db();
+
+        self.pool
+            .cached("account_totals", &(), || async {
+                sqlx::query_as!(
+                    AccountTotal,
+                    r#"
+                        SELECT
+                            a.id AS account_id,
+                            a.owner_type AS account_name,
+                            COALESCE(NULLIF(t.currency, ''), a.currency) AS "currency!: String",
+                            COALESCE(SUM(t.amount), 0) AS "total!: i64",
+                            COUNT(t.id) AS "count!: i64"
+                        FROM accounts a
+                        LEFT JOIN transactions t ON t.account_id = a.id
+                        GROUP BY a.id, 3
+                        ORDER BY a.id, 3
+                    "#
+                )
+                .fetch_all(db)
+                .await
+                .map_err(Error::from)
+            })
+            .await
+    }
+
+    /// The newest and oldest pending transaction synced from the API per
+    /// account. Transactions only a webhook has delivered don't move the
+    /// newest on, as a sync may not have fetched the ones before them.
+    #[tracing::instrument(name = "Read sync points", skip(self))]
+    async fn read_sync_points(&self) -> Result<Vec<SyncPoint>, Error> {
+        let points = sqlx::query_as!(
+            SyncPoint,
+            r#"
+                WITH latest AS (
+                    SELECT account_id, MAX(created) AS created
+                    FROM transactions
+                    WHERE source = 'api'
+                    GROUP BY account_id
+                )
+                SELECT
+                    t.account_id AS "account_id!",
+                    l.created AS "latest!: NaiveDateTime",
+                    -- declined payments and authorisations that expired without
+                    -- settling, unchanged for 30 days, never settle
+                    MIN(
+                        CASE WHEN t.settled IS NULL
+                            AND t.decline_reason IS NULL
+                            AND t.amount != 0
+                            AND COALESCE(t.updated, t.created) >= datetime(l.created, '-30 days')
+                        THEN t.created END
+                    ) AS "oldest_pending: NaiveDateTime"
+                FROM transactions t
+                JOIN latest l ON l.account_id = t.account_id
+                WHERE t.source IN ('api', 'webhook')
+                GROUP BY t.account_id
+                ORDER BY t.account_id
+            "#
+        )
+        .fetch_all(self.pool.db())
+        .await?;
+
+        Ok(points)
+    }
+
+    /// Sum transactions created between `from` and `until` per account, pot
+    /// and currency
+    #[tracing::instrument(name = "Read pot totals", skip(self))]
+    async fn read_pot_totals(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<PotTotal>, Error> {
+        let db = self.pool.db();
+        let key = (from, self.pool.range_end_key(until).await?);
+
+        self.pool
+            .cached("pot_totals", &key, || async {
+                sqlx::query_as!(
+                    PotTotal,
+                    r#"
+                        SELECT
+                            t.account_id,
+                            p.id AS "pot_id?",
+                            COALESCE(NULLIF(t.currency, ''), a.currency) AS "currency!: String",
+                            SUM(t.amount) AS "total!: i64"
+                        FROM transactions t
+                        JOIN accounts a ON t.account_id = a.id
+                        LEFT JOIN pots p ON t.description = p.id
+                        WHERE t.created
+                        BETWEEN $1 AND $2
+                        GROUP BY t.account_id, p.id, 3
+                        ORDER BY t.account_id, p.id, 3
+                    "#,
+                    from,
+                    until
+                )
+                .fetch_all(db)
+                .await
+                .map_err(Error::from)
+            })
+            .await
+    }
+
+    /// Settled transactions created between `from` and `until` whose amount
+    /// differs from the one first stored while pending
+    #[tracing::instrument(name = "Read settlement drift", skip(self))]
+    async fn read_settlement_drift(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> Result<Vec<SettlementDrift>, Error> {
+        let db = self.pool.db();
+
+        let drift = sqlx::query_as!(
+            SettlementDrift,
+            r#"
+                SELECT
+                    t.id,
+                    t.account_id,
+                    a.owner_type AS account_name,
+                    t.created,
+                    t.settled AS "settled!",
+                    t.description,
+                    m.name AS "merchant_name?",
+                    t.currency,
+                    t.pending_amount AS "pending_amount!",
+                    t.amount
+                FROM transactions t
+                JOIN accounts a ON t.account_id = a.id
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+                WHERE t.created BETWEEN $1 AND $2
+                AND t.settled IS NOT NULL
+                AND t.pending_amount IS NOT NULL
+                AND t.pending_amount != t.amount
+                ORDER BY t.created
+            "#,
+            from,
+            until
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(drift)
+    }
+
     // get the set of categories for a given account
     async fn get_categories_for_account(&self, account_id: &str) -> Result<Vec<Category>, Error> {
         let db = self.pool.db();
         let categories = sqlx::query_as!(
             Category,
             r"
-                SELECT DISTINCT c.id, c.name
+                SELECT DISTINCT c.id, c.name, c.display_name, c.emoji
                 FROM categories c
                 JOIN transactions t ON c.id = t.category_id
                 WHERE t.account_id = $1
@@ -421,20 +1153,44 @@ async fn is_duplicate_transaction(db: &Pool<Sqlite>, tx_id: &str) -> Result<bool
 ///
 /// # Errors
 /// Will return an error if a merchant could not be retrieved from the database
-async fn insert_merchant(
+// Save the counterparty of a P2P payment, returning their id
+pub(crate) async fn insert_counterparty(
+    pool: DatabasePool,
+    counterparty: Option<&Counterparty>,
+) -> Result<Option<String>, Error> {
+    match counterparty {
+        Some(counterparty) => pool.counterparties().save_counterparty(counterparty).await,
+        None => Ok(None),
+    }
+}
+
+pub(crate) async fn insert_merchant(
     pool: DatabasePool,
-    merchant: &Option<Merchant>,
+    merchant: Option<&Merchant>,
 ) -> Result<Option<String>, Error> {
-    if merchant.is_none() {
+    let Some(merchant) = merchant else {
         return Ok(None);
+    };
+
+    match pool.merchants().save_merchant(merchant).await {
+        Ok(_) | Err(Error::Duplicate(_)) => Ok(Some(merchant.id.clone())),
+        Err(e) => Err(e),
     }
+}
 
-    let merchant_service = SqliteMerchantService::new(pool);
-    let merchant = merchant.as_ref().unwrap();
-    match merchant_service.save_merchant(&merchant).await {
-        Ok(_) | Err(Error::Duplicate(_)) => return Ok(Some(merchant.id.clone())),
-        Err(e) => return Err(e),
+// Replace the pot ids Monzo gives as the descriptions of pot transfers
+// with the pool's templates
+pub(crate) fn describe_pot_transfers(
+    pool: &DatabasePool,
+    mut transactions: Vec<ExportTransaction>,
+) -> Vec<ExportTransaction> {
+    let descriptions = pool.pot_descriptions();
+    for tx in &mut transactions {
+        if let Some(pot_name) = &tx.pot_name {
+            tx.description = descriptions.describe(pot_name, tx.amount);
+        }
     }
+    transactions
 }
 
 // -- Tests ----------------------------------------------------------------------------
@@ -444,16 +1200,18 @@ mod tests {
     use chrono::{TimeZone, Utc};
 
     use super::*;
-    use crate::tests::test::test_db;
+    use crate::{configuration::PotDescriptions, tests::test::test_db};
 
     #[tokio::test]
     async fn save_transaction() {
         // Arrange
         let (pool, _tmp) = test_db().await;
         let service = SqliteTransactionService::new(pool);
-        let mut tx_resp = TransactionResponse::default();
-        tx_resp.account_id = "1".to_string();
-        tx_resp.category = "1".to_string();
+        let tx_resp = TransactionResponse {
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            ..Default::default()
+        };
 
         // Act
         let result = service.save_transaction(&tx_resp).await;
@@ -462,6 +1220,122 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn reconcile_settles_a_pending_transaction() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool);
+        let pending = TransactionResponse {
+            id: "tx_pending".to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount: -4200,
+            ..Default::default()
+        };
+        service.save_transaction(&pending).await.unwrap();
+        let settled = TransactionResponse {
+            amount: -4350,
+            settled: Some(Utc.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap()),
+            ..pending.clone()
+        };
+
+        // Act
+        let still_pending = service.update_transaction(&pending, false).await.unwrap();
+        let reconciled = service.update_transaction(&settled, false).await.unwrap();
+        let after_settling = service
+            .update_transaction(
+                &TransactionResponse {
+                    amount: -1,
+                    ..settled.clone()
+                },
+                false,
+            )
+            .await
+            .unwrap();
+
+        // Assert
+        assert!(!still_pending);
+        assert!(reconciled);
+        assert!(!after_settling);
+        let tx = service.read_transaction("tx_pending").await.unwrap();
+        assert_eq!(tx.amount, -4350);
+        assert!(tx.settled.is_some());
+        assert_eq!(tx.pending_amount, Some(-4200));
+        let drift = service
+            .read_settlement_drift(tx.created, tx.created)
+            .await
+            .unwrap();
+        assert_eq!(drift.len(), 1);
+        assert_eq!(drift[0].difference(), -150);
+    }
+
+    #[tokio::test]
+    async fn settled_transactions_update_when_monzo_updates_them() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool);
+        let updated = |day| Some(Utc.with_ymd_and_hms(2024, 5, day, 0, 0, 0).unwrap());
+        let settled = TransactionResponse {
+            id: "tx_settled".to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount: -4200,
+            settled: updated(2),
+            updated: updated(2),
+            ..Default::default()
+        };
+        let noted = TransactionResponse {
+            notes: Some("Birthday present".to_string()),
+            ..settled.clone()
+        };
+
+        // Act
+        let inserted = service
+            .save_or_update_transaction(&settled, false)
+            .await
+            .unwrap();
+        let not_newer = service
+            .save_or_update_transaction(&noted, false)
+            .await
+            .unwrap();
+        let refreshed = service
+            .save_or_update_transaction(&noted, true)
+            .await
+            .unwrap();
+        let newer = service
+            .save_or_update_transaction(
+                &TransactionResponse {
+                    notes: Some("Gift".to_string()),
+                    updated: updated(3),
+                    ..settled.clone()
+                },
+                false,
+            )
+            .await
+            .unwrap();
+        let unchanged = service
+            .save_or_update_transaction(
+                &TransactionResponse {
+                    notes: Some("Gift".to_string()),
+                    updated: updated(3),
+                    ..settled
+                },
+                true,
+            )
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(inserted, Upsert::Inserted);
+        assert_eq!(not_newer, Upsert::Unchanged);
+        assert_eq!(refreshed, Upsert::Updated);
+        assert_eq!(newer, Upsert::Updated);
+        assert_eq!(unchanged, Upsert::Unchanged);
+        let tx = service.read_transaction("tx_settled").await.unwrap();
+        assert_eq!(tx.notes.as_deref(), Some("Gift"));
+        assert_eq!(tx.pending_amount, None);
+    }
+
     #[tokio::test]
     async fn read_transactions() {
         // Arrange
@@ -477,6 +1351,36 @@ mod tests {
         assert!(txs.len() == 2);
     }
 
+    #[tokio::test]
+    async fn read_transactions_page_continues_after_the_cursor() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool);
+        for (id, day) in [("tx_c", 3), ("tx_a", 1), ("tx_b", 2)] {
+            let tx = TransactionResponse {
+                id: id.to_string(),
+                account_id: "1".to_string(),
+                category_id: "1".to_string(),
+                created: Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+                ..Default::default()
+            };
+            service.save_transaction(&tx).await.unwrap();
+        }
+
+        // Act
+        let first = service.read_transactions_page(None, 3).await.unwrap();
+        let second = service
+            .read_transactions_page(Some(&first[2].id), 3)
+            .await
+            .unwrap();
+
+        // Assert
+        let ids = |txs: &[TransactionForDB]| txs.iter().map(|tx| tx.id.clone()).collect::<Vec<_>>();
+        // the two seeded transactions are from 1970
+        assert_eq!(ids(&first), vec!["1", "2", "tx_a"]);
+        assert_eq!(ids(&second), vec!["tx_b", "tx_c"]);
+    }
+
     #[tokio::test]
     #[ignore = "Not implemented"]
     async fn read_transactions_for_dates() {
@@ -511,9 +1415,249 @@ mod tests {
         let tx_id = "1";
 
         // Act
-        let tx = service.read_transaction(&tx_id).await.unwrap();
+        let tx = service.read_transaction(tx_id).await.unwrap();
 
         //Assert
         assert_eq!(tx.id, "1".to_string());
     }
+
+    #[tokio::test]
+    async fn reports_leave_out_transactions_below_the_minimum_amount() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query("UPDATE transactions SET amount = CASE id WHEN '1' THEN -5 ELSE -2500 END")
+            .execute(pool.db())
+            .await
+            .unwrap();
+        let service = SqliteTransactionService::new(pool.with_min_amount(10));
+        let from = NaiveDateTime::default();
+        let until = Utc::now().naive_utc();
+
+        // Act
+        let stored = service.read_export_data(from, until).await.unwrap();
+        let reported = service.read_report_data(from, until).await.unwrap();
+        let totals = service.read_category_totals(from, until).await.unwrap();
+
+        // Assert
+        assert_eq!(stored.len(), 2);
+        assert_eq!(
+            reported.iter().map(|tx| tx.id.as_str()).collect::<Vec<_>>(),
+            vec!["2"]
+        );
+        assert_eq!((totals[0].count, totals[0].total), (1, -2500));
+    }
+
+    #[tokio::test]
+    async fn read_category_totals() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool);
+        let from = NaiveDateTime::default();
+        let until = Utc::now().naive_utc();
+
+        // Act
+        let totals = service.read_category_totals(from, until).await.unwrap();
+
+        // Assert
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].category_name, "category_1");
+        assert_eq!(totals[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn read_account_totals() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool);
+
+        // Act
+        let totals = service.read_account_totals().await.unwrap();
+
+        // Assert
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].account_name, "personal");
+        assert_eq!(totals[0].total, 0);
+        assert_eq!(totals[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn account_totals_are_split_by_currency() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET currency = 'EUR', amount = 500 WHERE id = '1';
+             UPDATE transactions SET amount = -250 WHERE id = '2'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let service = SqliteTransactionService::new(pool);
+
+        // Act
+        let totals = service.read_account_totals().await.unwrap();
+
+        // Assert
+        assert_eq!(
+            totals
+                .iter()
+                .map(|t| (t.currency.as_str(), t.total, t.count))
+                .collect::<Vec<_>>(),
+            vec![("EUR", 500, 1), ("GBP", -250, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_points_ignore_pending_rows_that_will_never_settle() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool);
+        let at = |month, day| Utc.with_ymd_and_hms(2024, month, day, 12, 0, 0).unwrap();
+        let tx = |id: &str, amount, created| TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount,
+            created,
+            ..Default::default()
+        };
+        let transactions = [
+            TransactionResponse {
+                settled: Some(at(5, 11)),
+                ..tx("tx_settled", -100, at(5, 10))
+            },
+            TransactionResponse {
+                decline_reason: Some("INSUFFICIENT_FUNDS".to_string()),
+                ..tx("tx_declined", -200, at(5, 1))
+            },
+            // an authorisation that expired without settling
+            tx("tx_expired", -300, at(3, 1)),
+            tx("tx_pending", -400, at(5, 5)),
+        ];
+        for tx in &transactions {
+            service.save_transaction(tx).await.unwrap();
+        }
+
+        // Act
+        let points = service.read_sync_points().await.unwrap();
+
+        // Assert
+        assert_eq!(
+            points,
+            vec![SyncPoint {
+                account_id: "1".to_string(),
+                latest: at(5, 10).naive_utc(),
+                oldest_pending: Some(at(5, 5).naive_utc()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn webhook_transactions_dont_move_the_sync_point() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool);
+        let at = |day| Utc.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap();
+        let tx = |id: &str, created| TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            category_id: "1".to_string(),
+            amount: -100,
+            created,
+            settled: Some(created),
+            ..Default::default()
+        };
+        service
+            .save_transaction(&tx("tx_synced", at(1)))
+            .await
+            .unwrap();
+        // the sync that would have fetched 2 to 19 May never ran
+        let delivered = tx("tx_delivered", at(20));
+        service
+            .save_or_update_transaction_from(&delivered, false, Source::Webhook)
+            .await
+            .unwrap();
+
+        // Act
+        let before_sync = service.read_sync_points().await.unwrap();
+        service
+            .save_or_update_transaction(&delivered, false)
+            .await
+            .unwrap();
+        let after_sync = service.read_sync_points().await.unwrap();
+
+        // Assert
+        assert_eq!(before_sync[0].latest, at(1).naive_utc());
+        assert_eq!(after_sync[0].latest, at(20).naive_utc());
+        let stored = service.read_transaction("tx_delivered").await.unwrap();
+        assert_eq!(stored.source, "api");
+    }
+
+    #[tokio::test]
+    async fn read_pot_totals() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query("UPDATE transactions SET description = '1', amount = -500 WHERE id = '2'")
+            .execute(pool.db())
+            .await
+            .unwrap();
+        let service = SqliteTransactionService::new(pool);
+
+        // Act
+        let totals = service
+            .read_pot_totals(NaiveDateTime::default(), Utc::now().naive_utc())
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            totals,
+            vec![
+                PotTotal {
+                    account_id: "1".to_string(),
+                    pot_id: None,
+                    currency: "GBP".to_string(),
+                    total: 0,
+                },
+                PotTotal {
+                    account_id: "1".to_string(),
+                    pot_id: Some("1".to_string()),
+                    currency: "GBP".to_string(),
+                    total: -500,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn pot_transfers_are_described_by_the_templates() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        sqlx::query(
+            "UPDATE transactions SET description = '1', amount = -500 WHERE id = '1';
+             UPDATE transactions SET description = '1', amount = 200 WHERE id = '2'",
+        )
+        .execute(pool.db())
+        .await
+        .unwrap();
+        let pool = pool.with_pot_descriptions(PotDescriptions {
+            deposit: "Saved into {pot_name}".to_string(),
+            ..Default::default()
+        });
+        let service = SqliteTransactionService::new(pool);
+
+        // Act
+        let transactions = service
+            .read_export_data(NaiveDateTime::default(), Utc::now().naive_utc())
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|tx| tx.description.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Saved into pot_name", "Transfer from pot_name"]
+        );
+    }
 }
@@ -0,0 +1,396 @@
+//! Transfer classification
+//!
+//! Moving money between the user's own accounts and pots, or paying off a
+//! credit card, isn't spending. [`DatabasePool::classify_transfers`] sets the
+//! `is_transfer` flag on every transaction so the aggregation queries can
+//! leave transfers out. A transaction is a transfer if:
+//!
+//! - its description is the id of a pot, or
+//! - its counterparty's account number and sort code are one of the stored
+//!   accounts, or
+//! - it matches a category or description rule under `[transfers]`, or
+//! - it matches a `[transfers.repayments]` rule, which also records the manual
+//!   liability account it pays off.
+
+use std::collections::{HashMap, HashSet};
+
+use regex::{Regex, RegexBuilder};
+use tracing_log::log::info;
+
+use super::DatabasePool;
+use crate::{configuration::Transfers, error::AppErrors as Error};
+
+/// The configured rules, ready to match
+#[derive(Debug, Default, Clone)]
+pub struct TransferRules {
+    categories: Vec<String>,
+    descriptions: Vec<Regex>,
+    /// Manual account name and the pattern of its repayments
+    repayments: Vec<(String, Regex)>,
+}
+
+impl TransferRules {
+    /// Compile the `[transfers]` rules
+    ///
+    /// # Errors
+    /// Will return an error if a description pattern isn't a valid regex.
+    pub fn new(settings: &Transfers) -> Result<Self, Error> {
+        let descriptions = settings
+            .descriptions
+            .iter()
+            .map(|pattern| compile(pattern))
+            .collect::<Result<_, _>>()?;
+        let repayments = settings
+            .repayments
+            .iter()
+            .map(|(account, pattern)| Ok((account.clone(), compile(pattern)?)))
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self {
+            categories: settings.categories.clone(),
+            descriptions,
+            repayments,
+        })
+    }
+
+    fn matches(&self, category: &str, description: &str) -> bool {
+        self.categories
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(category))
+            || self.descriptions.iter().any(|re| re.is_match(description))
+    }
+
+    // The name of the account repaid by a transaction, if a rule matches
+    fn repaid_account(&self, description: &str, merchant: Option<&str>) -> Option<&str> {
+        self.repayments
+            .iter()
+            .find(|(_, re)| re.is_match(description) || merchant.is_some_and(|m| re.is_match(m)))
+            .map(|(account, _)| account.as_str())
+    }
+}
+
+/// Transactions whose flag was changed by [`DatabasePool::classify_transfers`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransferSummary {
+    pub transfers: u64,
+    /// Transfers that repay a manual liability account
+    pub repayments: u64,
+    pub changed: u64,
+}
+
+// A transaction as read for classifying
+#[derive(sqlx::FromRow)]
+pub(crate) struct Row {
+    pub(crate) id: String,
+    pub(crate) description: String,
+    pub(crate) category_name: String,
+    pub(crate) merchant_name: Option<String>,
+    pub(crate) counterparty_account_number: Option<String>,
+    pub(crate) counterparty_sort_code: Option<String>,
+    pub(crate) is_transfer: bool,
+    pub(crate) repayment_account_id: Option<i64>,
+}
+
+// A new (id, is transfer, repayment account id) for a transaction
+pub(crate) type Change = (String, bool, Option<i64>);
+
+impl DatabasePool {
+    /// Flag every transaction that is a transfer and clear the flag on those
+    /// that no longer are, e.g. after the rules change
+    ///
+    /// # Errors
+    /// Will return an error if a repayment rule names an account that isn't a
+    /// manual liability, or the database can't be read or written.
+    pub async fn classify_transfers(
+        &self,
+        rules: &TransferRules,
+    ) -> Result<TransferSummary, Error> {
+        #[cfg(feature = "postgres")]
+        if self.postgres().is_some() {
+            return super::postgres::transfer::classify_transfers(self, rules).await;
+        }
+
+        let pots = sqlx::query_scalar!("SELECT id FROM pots")
+            .fetch_all(self.db())
+            .await?;
+        let accounts = sqlx::query!("SELECT account_number, sort_code FROM accounts")
+            .fetch_all(self.db())
+            .await?
+            .into_iter()
+            .map(|row| (row.account_number, row.sort_code))
+            .collect();
+        let liabilities =
+            sqlx::query!("SELECT id, name FROM manual_accounts WHERE kind = 'liability'")
+                .fetch_all(self.db())
+                .await?
+                .into_iter()
+                .map(|row| (row.id, row.name))
+                .collect();
+
+        let rows = sqlx::query_as!(
+            Row,
+            r#"
+                SELECT
+                    t.id,
+                    t.description,
+                    c.name AS category_name,
+                    m.name AS "merchant_name?",
+                    t.counterparty_account_number,
+                    t.counterparty_sort_code,
+                    t.is_transfer AS "is_transfer: bool",
+                    t.repayment_account_id
+                FROM transactions t
+                JOIN categories c ON t.category_id = c.id
+                LEFT JOIN merchants m ON t.merchant_id = m.id
+            "#
+        )
+        .fetch_all(self.db())
+        .await?;
+
+        let (summary, changes) = classify(rules, pots, accounts, liabilities, rows)?;
+        let mut tx = self.db().begin().await?;
+        for (id, is_transfer, repayment_account_id) in changes {
+            sqlx::query!(
+                "UPDATE transactions SET is_transfer = $2, repayment_account_id = $3 WHERE id = $1",
+                id,
+                is_transfer,
+                repayment_account_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        info!("Classified transfers: {summary:?}");
+        Ok(summary)
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+// Classify the transactions `rows` given the ids of the pots, the (account
+// number, sort code) of the accounts and the (id, name) of the manual
+// liabilities, returning the summary and the transactions whose flag changed
+pub(crate) fn classify(
+    rules: &TransferRules,
+    pots: Vec<String>,
+    accounts: Vec<(String, String)>,
+    liabilities: Vec<(i64, String)>,
+    rows: Vec<Row>,
+) -> Result<(TransferSummary, Vec<Change>), Error> {
+    let pots: HashSet<String> = pots.into_iter().collect();
+    let accounts: HashSet<(String, String)> = accounts
+        .into_iter()
+        .map(|(number, sort_code)| (number, normalise(&sort_code)))
+        .collect();
+    let liabilities: HashMap<String, i64> = liabilities
+        .into_iter()
+        .map(|(id, name)| (name.to_lowercase(), id))
+        .collect();
+    for (account, _) in &rules.repayments {
+        if !liabilities.contains_key(&account.to_lowercase()) {
+            return Err(Error::Error(format!(
+                "Repayment rule for '{account}' needs a manual liability account of that name"
+            )));
+        }
+    }
+
+    let mut summary = TransferSummary::default();
+    let mut changes = Vec::new();
+    for row in rows {
+        let own_account = match (
+            &row.counterparty_account_number,
+            &row.counterparty_sort_code,
+        ) {
+            (Some(number), Some(sort_code)) => {
+                accounts.contains(&(number.clone(), normalise(sort_code)))
+            }
+            _ => false,
+        };
+        let repayment_account_id = rules
+            .repaid_account(&row.description, row.merchant_name.as_deref())
+            .and_then(|account| liabilities.get(&account.to_lowercase()).copied());
+        let is_transfer = pots.contains(&row.description)
+            || own_account
+            || rules.matches(&row.category_name, &row.description)
+            || repayment_account_id.is_some();
+
+        if is_transfer {
+            summary.transfers += 1;
+        }
+        if repayment_account_id.is_some() {
+            summary.repayments += 1;
+        }
+        if is_transfer != row.is_transfer || repayment_account_id != row.repayment_account_id {
+            changes.push((row.id, is_transfer, repayment_account_id));
+            summary.changed += 1;
+        }
+    }
+
+    Ok((summary, changes))
+}
+
+fn compile(pattern: &str) -> Result<Regex, Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| Error::Error(format!("Invalid transfer pattern '{pattern}': {e}")))
+}
+
+// Sort codes come with and without dashes
+fn normalise(sort_code: &str) -> String {
+    sort_code.chars().filter(char::is_ascii_digit).collect()
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{
+        model::{
+            counterparty::Counterparty,
+            manual::{ManualAccountKind, Service as ManualService, SqliteManualService},
+            transaction::{
+                Service as TransactionService, SqliteTransactionService, TransactionResponse,
+            },
+        },
+        tests::test::test_db,
+    };
+
+    fn transaction(
+        id: &str,
+        description: &str,
+        counterparty: Option<Counterparty>,
+    ) -> TransactionResponse {
+        TransactionResponse {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            amount: -1000,
+            currency: "GBP".to_string(),
+            local_amount: -1000,
+            local_currency: "GBP".to_string(),
+            description: description.to_string(),
+            category_id: "1".to_string(),
+            counterparty,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn classifies_pots_own_accounts_and_rules() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let service = SqliteTransactionService::new(pool.clone());
+        let own = Counterparty {
+            account_number: Some("12345678".to_string()),
+            sort_code: Some("123456".to_string()),
+            ..Default::default()
+        };
+        for tx in [
+            transaction("pot", "1", None),
+            transaction("own", "TRANSFER", Some(own)),
+            transaction("card", "AMERICAN EXPRESS", None),
+            transaction("shop", "TESCO", None),
+        ] {
+            service.save_transaction(&tx).await.unwrap();
+        }
+        let rules = TransferRules::new(&Transfers {
+            categories: Vec::new(),
+            descriptions: vec!["^american express".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Act
+        let summary = pool.classify_transfers(&rules).await.unwrap();
+
+        // Assert
+        assert_eq!(summary.transfers, 3);
+        for (id, expected) in [
+            ("pot", true),
+            ("own", true),
+            ("card", true),
+            ("shop", false),
+        ] {
+            let tx = service.read_transaction(id).await.unwrap();
+            assert_eq!(tx.is_transfer, expected, "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn repayments_record_the_card_account() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let card = SqliteManualService::new(pool.clone())
+            .add_account("Amex", ManualAccountKind::Liability, None, "GBP")
+            .await
+            .unwrap();
+        SqliteTransactionService::new(pool.clone())
+            .save_transaction(&transaction("card", "AMERICAN EXPRESS", None))
+            .await
+            .unwrap();
+        let rules = |account: &str| {
+            TransferRules::new(&Transfers {
+                repayments: BTreeMap::from([(account.to_string(), "^american".to_string())]),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        // Act
+        let summary = pool.classify_transfers(&rules("amex")).await.unwrap();
+        let unknown = pool.classify_transfers(&rules("Visa")).await;
+
+        // Assert
+        assert_eq!(summary.repayments, 1);
+        let tx = SqliteTransactionService::new(pool)
+            .read_transaction("card")
+            .await
+            .unwrap();
+        assert!(tx.is_transfer);
+        assert_eq!(tx.repayment_account_id, Some(card));
+        assert!(unknown.is_err());
+    }
+
+    #[tokio::test]
+    async fn rule_changes_clear_the_flag() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let everything = TransferRules::new(&Transfers {
+            categories: vec!["CATEGORY_1".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        pool.classify_transfers(&everything).await.unwrap();
+
+        // Act
+        let summary = pool
+            .classify_transfers(&TransferRules::default())
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            summary,
+            TransferSummary {
+                transfers: 0,
+                repayments: 0,
+                changed: 2
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        let settings = Transfers {
+            categories: Vec::new(),
+            descriptions: vec!["(".to_string()],
+            ..Default::default()
+        };
+
+        assert!(TransferRules::new(&settings).is_err());
+    }
+}
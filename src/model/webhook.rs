@@ -0,0 +1,134 @@
+//! Models for webhook deliveries
+//!
+//! Monzo retries a webhook until it's acknowledged, so the same transaction
+//! can arrive more than once. Each delivered transaction id is recorded, and
+//! payloads that fail to parse are kept as dead letters.
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use super::DatabasePool;
+use crate::error::AppErrors as Error;
+
+/// A payload that couldn't be parsed
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub received: NaiveDateTime,
+    pub body: String,
+    pub error: String,
+}
+
+// -- Services -------------------------------------------------------------------------
+
+#[async_trait]
+pub trait Service {
+    async fn is_delivered(&self, transaction_id: &str) -> Result<bool, Error>;
+    async fn record_delivery(
+        &self,
+        transaction_id: &str,
+        event_type: &str,
+        received: NaiveDateTime,
+    ) -> Result<(), Error>;
+    async fn save_dead_letter(
+        &self,
+        body: &str,
+        error: &str,
+        received: NaiveDateTime,
+    ) -> Result<i64, Error>;
+    async fn read_dead_letters(&self) -> Result<Vec<DeadLetter>, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteWebhookService {
+    pub(crate) pool: DatabasePool,
+}
+
+impl SqliteWebhookService {
+    #[must_use]
+    pub fn new(pool: DatabasePool) -> Self {
+        Self { pool }
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Service for SqliteWebhookService {
+    /// Whether a delivery of the transaction has been handled
+    #[tracing::instrument(name = "Check webhook delivery", skip(self))]
+    async fn is_delivered(&self, transaction_id: &str) -> Result<bool, Error> {
+        let db = self.pool.db();
+
+        let delivery = sqlx::query!(
+            "SELECT transaction_id FROM webhook_deliveries WHERE transaction_id = $1",
+            transaction_id
+        )
+        .fetch_optional(db)
+        .await?;
+
+        Ok(delivery.is_some())
+    }
+
+    /// Record that a delivery of the transaction has been handled
+    #[tracing::instrument(name = "Record webhook delivery", skip(self))]
+    async fn record_delivery(
+        &self,
+        transaction_id: &str,
+        event_type: &str,
+        received: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let db = self.pool.db();
+
+        sqlx::query!(
+            r"
+                INSERT OR IGNORE INTO webhook_deliveries (transaction_id, event_type, received)
+                VALUES ($1, $2, $3)
+            ",
+            transaction_id,
+            event_type,
+            received
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Keep a payload that couldn't be parsed, returning its id
+    #[tracing::instrument(name = "Save webhook dead letter", skip(self, body))]
+    async fn save_dead_letter(
+        &self,
+        body: &str,
+        error: &str,
+        received: NaiveDateTime,
+    ) -> Result<i64, Error> {
+        let db = self.pool.db();
+
+        let result = sqlx::query!(
+            "INSERT INTO webhook_dead_letters (received, body, error) VALUES ($1, $2, $3)",
+            received,
+            body,
+            error
+        )
+        .execute(db)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Read the dead letters, oldest first
+    #[tracing::instrument(name = "Read webhook dead letters", skip(self))]
+    async fn read_dead_letters(&self) -> Result<Vec<DeadLetter>, Error> {
+        let db = self.pool.db();
+
+        let letters = sqlx::query_as!(
+            DeadLetter,
+            "SELECT id, received, body, error FROM webhook_dead_letters ORDER BY id"
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(letters)
+    }
+}
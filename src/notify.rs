@@ -0,0 +1,215 @@
+//! Notifications
+//!
+//! Short messages such as the spending digest are sent through every channel
+//! configured under `[notifications]`: a shell command, which receives the
+//! message on stdin, and/or a webhook, which receives it as JSON.
+//!
+//! ```toml
+//! [notifications]
+//! command = "mail -s \"$MONZO_TITLE\" me@example.com"
+//! webhook_url = "https://ntfy.sh/my-monzo"
+//! ```
+
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{configuration::Notifications, error::AppErrors as Error};
+
+/// A message for the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+/// A channel that notifications are sent through
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Send a notification
+    ///
+    /// # Errors
+    /// Will return an error if the notification can't be delivered.
+    async fn send(&self, notification: &Notification) -> Result<(), Error>;
+}
+
+/// Runs a shell command with the body on stdin and the title in `MONZO_TITLE`
+pub struct CommandNotifier {
+    command: String,
+}
+
+impl CommandNotifier {
+    #[must_use]
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+}
+
+/// Posts the notification as JSON `{"title": ..., "body": ...}`
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    #[must_use]
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+/// All configured channels
+#[derive(Default)]
+pub struct Notifiers {
+    channels: Vec<Box<dyn Notifier>>,
+}
+
+impl Notifiers {
+    /// The channels configured in `[notifications]`
+    #[must_use]
+    pub fn from_settings(settings: &Notifications) -> Self {
+        let mut notifiers = Self::default();
+        if let Some(command) = &settings.command {
+            notifiers.add(Box::new(CommandNotifier::new(command.clone())));
+        }
+        if let Some(url) = &settings.webhook_url {
+            notifiers.add(Box::new(WebhookNotifier::new(url.clone())));
+        }
+        notifiers
+    }
+
+    /// Add a channel
+    pub fn add(&mut self, notifier: Box<dyn Notifier>) {
+        self.channels.push(notifier);
+    }
+
+    /// Whether no channels are configured
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// Send a notification through every channel
+    ///
+    /// # Errors
+    /// Will return the first delivery error, after trying every channel.
+    pub async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        let mut result = Ok(());
+        for channel in &self.channels {
+            if let Err(e) = channel.send(notification).await {
+                tracing::warn!("Failed to send notification: {e}");
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
+
+// -- Service Implementations ----------------------------------------------------------
+
+#[async_trait]
+impl Notifier for CommandNotifier {
+    #[tracing::instrument(name = "Notify by command", skip(self, notification))]
+    async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("MONZO_TITLE", &notification.title)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(notification.body.as_bytes()).await?;
+        }
+
+        let status = child.wait().await?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::Error(format!(
+                "Notification command '{}' failed with {status}",
+                self.command
+            )))
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    #[tracing::instrument(name = "Notify by webhook", skip(self, notification))]
+    async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        self.client
+            .post(&self.url)
+            .json(notification)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn notification() -> Notification {
+        Notification {
+            title: "Weekly digest".to_string(),
+            body: "Spent £12.50".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_json() {
+        // Arrange
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(body_json(notification()))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let notifiers = Notifiers::from_settings(&Notifications {
+            command: None,
+            webhook_url: Some(format!("{}/hook", server.uri())),
+        });
+
+        // Act
+        let result = notifiers.send(&notification()).await;
+
+        // Assert
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn command_receives_title_and_body() {
+        // Arrange
+        let dir = temp_dir::TempDir::new().unwrap();
+        let file = dir.child("message.txt");
+        let notifier = CommandNotifier::new(format!(
+            "{{ echo \"$MONZO_TITLE\"; cat; }} > '{}'",
+            file.display()
+        ));
+
+        // Act
+        notifier.send(&notification()).await.unwrap();
+
+        // Assert
+        let message = std::fs::read_to_string(file).unwrap();
+        assert_eq!(message, "Weekly digest\nSpent £12.50");
+    }
+}
@@ -0,0 +1,295 @@
+//! Python bindings
+//!
+//! Exposes the local database query layer to Python as the `monzo_cli` module.
+//! Build with [maturin](https://www.maturin.rs) (`maturin develop --release`)
+//! and query an existing database from a notebook:
+//!
+//! ```python
+//! import monzo_cli
+//!
+//! db = monzo_cli.Database("monzo.db")
+//! for total in db.category_totals():
+//!     print(total.category_name, monzo_cli.to_decimal(total.total, total.currency))
+//! ```
+//!
+//! Amounts are integers in minor units, as stored in the database. Use
+//! `to_decimal` rather than dividing by 100: not every currency has two
+//! decimal places.
+// pyo3 0.22 macro expansions trip these lints
+#![allow(clippy::needless_pass_by_value, clippy::useless_conversion)]
+
+use chrono::{NaiveDateTime, Utc};
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+use crate::{
+    error::AppErrors as Error,
+    model::{
+        transaction::{
+            AccountTotal as AccountTotalRow, CategoryTotal as CategoryTotalRow, ExportTransaction,
+        },
+        DatabasePool,
+    },
+};
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        PyRuntimeError::new_err(e.to_string())
+    }
+}
+
+/// A transaction with its account, category, merchant and pot names
+#[pyclass(frozen, get_all)]
+#[derive(Clone)]
+pub struct Transaction {
+    id: String,
+    account_id: String,
+    account_name: String,
+    created: NaiveDateTime,
+    settled: Option<NaiveDateTime>,
+    amount: i64,
+    currency: String,
+    local_amount: i64,
+    local_currency: String,
+    description: String,
+    notes: Option<String>,
+    category_name: String,
+    merchant_name: Option<String>,
+    pot_name: Option<String>,
+}
+
+impl From<ExportTransaction> for Transaction {
+    fn from(tx: ExportTransaction) -> Self {
+        Self {
+            id: tx.id,
+            account_id: tx.account_id,
+            account_name: tx.account_name,
+            created: tx.created,
+            settled: tx.settled,
+            amount: tx.amount,
+            currency: tx.currency,
+            local_amount: tx.local_amount,
+            local_currency: tx.local_currency,
+            description: tx.description,
+            notes: tx.notes,
+            category_name: tx.category_name,
+            merchant_name: tx.merchant_name,
+            pot_name: tx.pot_name,
+        }
+    }
+}
+
+/// The total and number of transactions in a category
+#[pyclass(frozen, get_all)]
+#[derive(Clone)]
+pub struct CategoryTotal {
+    category_name: String,
+    currency: String,
+    total: i64,
+    count: i64,
+}
+
+impl From<CategoryTotalRow> for CategoryTotal {
+    fn from(row: CategoryTotalRow) -> Self {
+        Self {
+            category_name: row.category_name,
+            currency: row.currency,
+            total: row.total,
+            count: row.count,
+        }
+    }
+}
+
+/// An account or pot balance
+///
+/// Account balances are the net of the synced transactions, so they match the
+/// Monzo balance only when the full history has been downloaded.
+#[pyclass(frozen, get_all)]
+#[derive(Clone)]
+pub struct Balance {
+    id: String,
+    name: String,
+    currency: String,
+    amount: i64,
+    is_pot: bool,
+}
+
+impl From<AccountTotalRow> for Balance {
+    fn from(row: AccountTotalRow) -> Self {
+        Self {
+            id: row.account_id,
+            name: row.account_name,
+            currency: row.currency,
+            amount: row.total,
+            is_pot: false,
+        }
+    }
+}
+
+/// A connection to a local monzo-cli database
+#[pyclass(frozen)]
+pub struct Database {
+    pool: DatabasePool,
+    runtime: tokio::runtime::Runtime,
+}
+
+#[pymethods]
+impl Database {
+    /// Open the database at `path`, applying any pending migrations
+    #[new]
+    #[pyo3(signature = (path, max_connections = 1))]
+    fn new(py: Python<'_>, path: &str, max_connections: u32) -> PyResult<Self> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let pool =
+            py.allow_threads(|| runtime.block_on(DatabasePool::new(path, max_connections)))?;
+
+        Ok(Self { pool, runtime })
+    }
+
+    /// Transactions created between `since` and `until` (defaults: all time, now)
+    #[pyo3(signature = (since = None, until = None))]
+    fn transactions(
+        &self,
+        py: Python<'_>,
+        since: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+    ) -> PyResult<Vec<Transaction>> {
+        let (since, until) = range(since, until);
+        let service = self.pool.transactions();
+        let rows = py.allow_threads(|| {
+            self.runtime
+                .block_on(service.read_export_data(since, until))
+        })?;
+
+        Ok(rows.into_iter().map(Transaction::from).collect())
+    }
+
+    /// Totals per category between `since` and `until`, largest spend first
+    #[pyo3(signature = (since = None, until = None))]
+    fn category_totals(
+        &self,
+        py: Python<'_>,
+        since: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+    ) -> PyResult<Vec<CategoryTotal>> {
+        let (since, until) = range(since, until);
+        let service = self.pool.transactions();
+        let rows = py.allow_threads(|| {
+            self.runtime
+                .block_on(service.read_category_totals(since, until))
+        })?;
+
+        Ok(rows.into_iter().map(CategoryTotal::from).collect())
+    }
+
+    /// Account balances followed by the balances of pots that haven't been deleted
+    fn balances(&self, py: Python<'_>) -> PyResult<Vec<Balance>> {
+        let transactions = self.pool.transactions();
+        let pots = self.pool.pots();
+
+        let (accounts, pots) = py.allow_threads(|| {
+            self.runtime.block_on(async {
+                Ok::<_, Error>((
+                    transactions.read_account_totals().await?,
+                    pots.read_pots().await?,
+                ))
+            })
+        })?;
+
+        let mut balances: Vec<Balance> = accounts.into_iter().map(Balance::from).collect();
+        balances.extend(pots.into_iter().filter(|p| !p.deleted).map(|p| Balance {
+            id: p.id,
+            name: p.name,
+            currency: p.currency,
+            amount: p.balance,
+            is_pot: true,
+        }));
+
+        Ok(balances)
+    }
+}
+
+fn range(
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+) -> (NaiveDateTime, NaiveDateTime) {
+    (
+        since.unwrap_or_default(),
+        until.unwrap_or_else(|| Utc::now().naive_utc()),
+    )
+}
+
+/// Format an amount in minor units as a decimal string using the currency's
+/// number of decimal places
+#[pyfunction]
+fn to_decimal(minor: i64, currency: &str) -> String {
+    crate::currency::decimal(minor, currency)
+}
+
+#[pymodule]
+fn monzo_cli(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(to_decimal, m)?)?;
+    m.add_class::<Database>()?;
+    m.add_class::<Transaction>()?;
+    m.add_class::<CategoryTotal>()?;
+    m.add_class::<Balance>()?;
+    Ok(())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test::test_db;
+
+    // the query methods block on the database's own runtime, so the tests can't be async
+    fn with_database(test: impl FnOnce(Python<'_>, &Database)) {
+        pyo3::prepare_freethreaded_python();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (pool, _tmp) = runtime.block_on(test_db());
+        let db = Database { pool, runtime };
+
+        Python::with_gil(|py| test(py, &db));
+    }
+
+    #[test]
+    fn transactions_returns_the_stored_transactions() {
+        with_database(|py, db| {
+            // Act
+            let txs = db.transactions(py, None, None).unwrap();
+
+            // Assert
+            assert_eq!(txs.len(), 2);
+            assert!(txs.iter().all(|tx| tx.account_id == "1"));
+        });
+    }
+
+    #[test]
+    fn category_totals_group_by_category() {
+        with_database(|py, db| {
+            // Act
+            let totals = db.category_totals(py, None, None).unwrap();
+
+            // Assert
+            assert_eq!(totals.len(), 1);
+            assert_eq!(totals[0].category_name, "category_1");
+            assert_eq!(totals[0].currency, "GBP");
+            assert_eq!(totals[0].count, 2);
+        });
+    }
+
+    #[test]
+    fn balances_list_accounts_then_pots() {
+        with_database(|py, db| {
+            // Act
+            let balances = db.balances(py).unwrap();
+
+            // Assert
+            assert_eq!(balances.len(), 2);
+            assert!(!balances[0].is_pot);
+            assert_eq!(balances[0].id, "1");
+            assert!(balances[1].is_pot);
+            assert_eq!(balances[1].amount, 1234);
+        });
+    }
+}
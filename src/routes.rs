@@ -5,12 +5,17 @@ use axum::{
 use reqwest::Response;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::watch;
 
+use crate::configuration::{get_config, AccessTokens, OathCredentials};
 use crate::error::AppErrors as Error;
-use crate::{
-    cli::command::auth::AuthorisationState,
-    configuration::{get_config, AccessTokens, OathCredentials},
-};
+
+// Shared state for the callback server: signals when the access tokens arrive
+#[derive(Clone)]
+pub struct AuthorisationState {
+    pub token_tx: Arc<watch::Sender<Option<AccessTokens>>>,
+}
 
 // Structure for representing the authcode request response
 #[derive(Deserialize, Debug)]
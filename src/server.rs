@@ -0,0 +1,232 @@
+//! Serving `serve` and `listen`
+//!
+//! [`run`] serves a router over HTTP, or over HTTPS when a certificate and key
+//! are configured, until Ctrl-C. [`require_token`] rejects API requests that
+//! don't carry the configured bearer token, and [`rate_limit`] those from
+//! clients that have made too many.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        HeaderMap, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{configuration::Server, error::AppErrors as Error};
+
+/// Serve `app` on `address` until interrupted, over HTTPS if `settings` has a
+/// certificate and key
+///
+/// # Errors
+/// Will return an error if the address is invalid or can't be bound, or if
+/// the certificate or key can't be read.
+pub async fn run(app: Router, address: &str, settings: &Server) -> Result<(), Error> {
+    let address: SocketAddr = address
+        .parse()
+        .map_err(|e| Error::Error(format!("Invalid address {address}: {e}")))?;
+
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            _ = tokio::signal::ctrl_c().await;
+            handle.graceful_shutdown(None);
+        }
+    });
+
+    match (&settings.tls_cert, &settings.tls_key) {
+        (Some(cert), Some(key)) => {
+            let tls = RustlsConfig::from_pem_file(cert, key).await?;
+            axum_server::bind_rustls(address, tls)
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+        }
+        (None, None) => {
+            axum_server::bind(address)
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+        }
+        _ => {
+            return Err(Error::Error(
+                "Set both `tls_cert` and `tls_key` in `[server]` to serve HTTPS".into(),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// The scheme `settings` serves, `https` or `http`
+#[must_use]
+pub fn scheme(settings: &Server) -> &'static str {
+    if settings.tls_cert.is_some() {
+        "https"
+    } else {
+        "http"
+    }
+}
+
+/// A new random bearer token
+#[must_use]
+pub fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// The bearer token in a request's `Authorization` header, if any
+#[must_use]
+pub fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Whether `given` is `expected`, compared by digest so the comparison doesn't
+/// reveal a matching prefix
+#[must_use]
+pub fn tokens_match(given: &str, expected: &str) -> bool {
+    Sha256::digest(given) == Sha256::digest(expected)
+}
+
+/// Middleware passing on requests that carry the bearer token
+///
+/// # Errors
+/// Will return `UNAUTHORIZED` for requests without it.
+pub async fn require_token(
+    State(token): State<String>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    match bearer(request.headers()) {
+        Some(given) if tokens_match(given, &token) => Ok(next.run(request).await),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// Requests allowed per client in each minute, counted from its first
+/// request in the minute
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    per_minute: u32,
+    clients: Arc<Mutex<HashMap<IpAddr, (Instant, u32)>>>,
+}
+
+impl RateLimit {
+    const WINDOW: Duration = Duration::from_mins(1);
+
+    #[must_use]
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            clients: Arc::default(),
+        }
+    }
+
+    /// Count a request from `client` at `now`
+    ///
+    /// # Errors
+    /// Will return how long until the client may try again if it has used up
+    /// its requests.
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut clients = self
+            .clients
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // forget clients whose minute is up, so the map doesn't keep growing
+        if clients.len() > 1024 {
+            clients.retain(|_, (start, _)| now.duration_since(*start) < Self::WINDOW);
+        }
+
+        let (start, count) = clients.entry(client).or_insert((now, 0));
+        if now.duration_since(*start) >= Self::WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= self.per_minute {
+            return Err(Self::WINDOW.saturating_sub(now.duration_since(*start)));
+        }
+        *count += 1;
+
+        Ok(())
+    }
+}
+
+/// Middleware answering 429 to clients over their rate limit
+pub async fn rate_limit(
+    State(limit): State<RateLimit>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match limit.check(client.ip(), Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, wait.as_secs().max(1).to_string())],
+        )
+            .into_response(),
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn reads_bearer_tokens() {
+        assert_eq!(bearer(&headers("Bearer s3cret")), Some("s3cret"));
+        assert_eq!(bearer(&headers("Basic czNjcmV0")), None);
+        assert_eq!(bearer(&HeaderMap::new()), None);
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3cre", "s3cret"));
+        assert_ne!(generate_token(), generate_token());
+        assert_eq!(generate_token().len(), 64);
+    }
+
+    #[test]
+    fn limits_each_client_per_minute() {
+        // Arrange
+        let limit = RateLimit::new(2);
+        let (dashboard, script) = ("10.0.0.2".parse().unwrap(), "10.0.0.3".parse().unwrap());
+        let start = Instant::now();
+
+        // Act
+        let first = limit.check(dashboard, start);
+        let second = limit.check(dashboard, start + Duration::from_secs(1));
+        let third = limit.check(dashboard, start + Duration::from_secs(20));
+        let other = limit.check(script, start + Duration::from_secs(20));
+        let next_minute = limit.check(dashboard, start + Duration::from_mins(1));
+
+        // Assert
+        assert_eq!(first, Ok(()));
+        assert_eq!(second, Ok(()));
+        assert_eq!(third, Err(Duration::from_secs(40)));
+        assert_eq!(other, Ok(()));
+        assert_eq!(next_minute, Ok(()));
+    }
+}
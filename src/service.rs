@@ -0,0 +1,381 @@
+//! Scheduled updates as a user service
+//!
+//! Installs a user-level systemd timer on Linux, or a launchd agent on macOS,
+//! that runs `update` from the current directory, so it finds the same
+//! `configuration.toml`. Nothing needs root.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::error::AppErrors as Error;
+
+/// Name of the systemd units and the launchd label
+const NAME: &str = "monzo-cli-update";
+const LABEL: &str = "com.monzo-cli.update";
+
+/// The service manager of the platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceManager {
+    Systemd,
+    Launchd,
+}
+
+impl ServiceManager {
+    /// The service manager for this platform
+    ///
+    /// # Errors
+    /// Will return an error on platforms other than Linux and macOS.
+    pub fn current() -> Result<Self, Error> {
+        if cfg!(target_os = "macos") {
+            Ok(Self::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(Self::Systemd)
+        } else {
+            Err(Error::Error(
+                "Services can only be installed on Linux (systemd) and macOS (launchd)".into(),
+            ))
+        }
+    }
+}
+
+/// The scheduled update service of the current user
+#[derive(Debug, Clone)]
+pub struct UpdateService {
+    manager: ServiceManager,
+    home: PathBuf,
+}
+
+impl UpdateService {
+    #[must_use]
+    pub fn new(manager: ServiceManager, home: PathBuf) -> Self {
+        Self { manager, home }
+    }
+
+    /// The service for this platform and the user's home directory
+    ///
+    /// # Errors
+    /// Will return an error on unsupported platforms or if `HOME` isn't set.
+    pub fn for_current_user() -> Result<Self, Error> {
+        let home =
+            std::env::var_os("HOME").ok_or_else(|| Error::Error("HOME is not set".into()))?;
+
+        Ok(Self::new(ServiceManager::current()?, PathBuf::from(home)))
+    }
+
+    /// Where the service files are installed
+    #[must_use]
+    pub fn paths(&self) -> Vec<PathBuf> {
+        match self.manager {
+            ServiceManager::Systemd => {
+                let units = self.home.join(".config/systemd/user");
+                vec![
+                    units.join(format!("{NAME}.service")),
+                    units.join(format!("{NAME}.timer")),
+                ]
+            }
+            ServiceManager::Launchd => vec![self.plist_path()],
+        }
+    }
+
+    /// The files making up the service, with their contents, for running
+    /// `executable` in `directory` every `interval` seconds
+    #[must_use]
+    pub fn files(
+        &self,
+        executable: &Path,
+        directory: &Path,
+        interval: u64,
+    ) -> Vec<(PathBuf, String)> {
+        let contents = match self.manager {
+            ServiceManager::Systemd => vec![
+                systemd_service(executable, directory),
+                systemd_timer(interval),
+            ],
+            ServiceManager::Launchd => vec![launchd_plist(executable, directory, interval)],
+        };
+
+        self.paths().into_iter().zip(contents).collect()
+    }
+
+    /// Write the service files and enable the schedule, returning the files
+    /// written
+    ///
+    /// # Errors
+    /// Will return an error if a file can't be written or the service manager
+    /// fails.
+    pub fn install(
+        &self,
+        executable: &Path,
+        directory: &Path,
+        interval: u64,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let files = self.files(executable, directory, interval);
+        for (path, contents) in &files {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+
+        match self.manager {
+            ServiceManager::Systemd => {
+                run("systemctl", &["--user", "daemon-reload"])?;
+                run(
+                    "systemctl",
+                    &["--user", "enable", "--now", &format!("{NAME}.timer")],
+                )?;
+            }
+            ServiceManager::Launchd => {
+                let plist = self.plist_path();
+                let plist = plist.to_string_lossy();
+                // replace a previous installation
+                _ = run("launchctl", &["unload", "-w", &plist]);
+                run("launchctl", &["load", "-w", &plist])?;
+            }
+        }
+
+        Ok(files.into_iter().map(|(path, _)| path).collect())
+    }
+
+    /// Disable the schedule and remove the service files, returning the files
+    /// removed
+    ///
+    /// # Errors
+    /// Will return an error if a file can't be removed or the service manager
+    /// fails.
+    pub fn uninstall(&self) -> Result<Vec<PathBuf>, Error> {
+        let installed: Vec<PathBuf> = self
+            .paths()
+            .into_iter()
+            .filter(|path| path.exists())
+            .collect();
+        if installed.is_empty() {
+            return Ok(installed);
+        }
+
+        match self.manager {
+            ServiceManager::Systemd => {
+                run(
+                    "systemctl",
+                    &["--user", "disable", "--now", &format!("{NAME}.timer")],
+                )?;
+            }
+            ServiceManager::Launchd => {
+                run(
+                    "launchctl",
+                    &["unload", "-w", &self.plist_path().to_string_lossy()],
+                )?;
+            }
+        }
+        for path in &installed {
+            fs::remove_file(path)?;
+        }
+        if self.manager == ServiceManager::Systemd {
+            run("systemctl", &["--user", "daemon-reload"])?;
+        }
+
+        Ok(installed)
+    }
+
+    /// Whether the service is installed, and the service manager's report on
+    /// it
+    ///
+    /// # Errors
+    /// Will return an error if the service manager can't be run.
+    pub fn status(&self) -> Result<(bool, String), Error> {
+        let installed = self.paths().iter().all(|path| path.exists());
+        if !installed {
+            return Ok((false, String::new()));
+        }
+
+        let output = match self.manager {
+            ServiceManager::Systemd => Command::new("systemctl")
+                .args(["--user", "list-timers", &format!("{NAME}.timer"), "--all"])
+                .args(["--no-pager"])
+                .output()?,
+            ServiceManager::Launchd => Command::new("launchctl").args(["list", LABEL]).output()?,
+        };
+
+        Ok((true, String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    fn plist_path(&self) -> PathBuf {
+        self.home
+            .join("Library/LaunchAgents")
+            .join(format!("{LABEL}.plist"))
+    }
+}
+
+/// Parse an interval such as `90s`, `30m`, `1h` or `1d` into seconds
+///
+/// # Errors
+/// Will return an error if the interval isn't a positive number with one of
+/// those units.
+pub fn parse_interval(interval: &str) -> Result<u64, Error> {
+    let invalid = || {
+        Error::Error(format!(
+            "Invalid interval '{interval}', expected e.g. 30m, 1h or 1d"
+        ))
+    };
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (number, unit) = interval.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    match number.checked_mul(scale) {
+        None | Some(0) => Err(invalid()),
+        Some(seconds) => Ok(seconds),
+    }
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn systemd_service(executable: &Path, directory: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Update Monzo transactions\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         WorkingDirectory={}\n\
+         ExecStart=\"{}\" --quiet update\n",
+        directory.display(),
+        executable.display()
+    )
+}
+
+fn systemd_timer(interval: u64) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Update Monzo transactions every {interval} seconds\n\
+         \n\
+         [Timer]\n\
+         OnBootSec=5min\n\
+         OnUnitActiveSec={interval}s\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n"
+    )
+}
+
+fn launchd_plist(executable: &Path, directory: &Path, interval: u64) -> String {
+    let directory = escape(&directory.to_string_lossy());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>--quiet</string>
+        <string>update</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>{directory}</string>
+    <key>StartInterval</key>
+    <integer>{interval}</integer>
+    <key>StandardErrorPath</key>
+    <string>{directory}/update.log</string>
+</dict>
+</plist>
+"#,
+        escape(&executable.to_string_lossy())
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Run a service manager command, failing if it does
+fn run(program: &str, args: &[&str]) -> Result<(), Error> {
+    let output = Command::new(program).args(args).output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::Error(format!(
+            "`{program} {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("90s").unwrap(), 90);
+        assert_eq!(parse_interval("30m").unwrap(), 1800);
+        assert_eq!(parse_interval("1h").unwrap(), 3600);
+        assert_eq!(parse_interval("1d").unwrap(), 86_400);
+        for invalid in ["", "h", "0h", "1w", "1.5h", "-1h"] {
+            assert!(parse_interval(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn systemd_timer_runs_update_in_the_directory() {
+        // Arrange
+        let service = UpdateService::new(ServiceManager::Systemd, PathBuf::from("/home/alex"));
+
+        // Act
+        let files = service.files(
+            Path::new("/usr/local/bin/monzo-cli"),
+            Path::new("/home/alex/monzo"),
+            3600,
+        );
+
+        // Assert
+        assert_eq!(
+            files[0].0,
+            PathBuf::from("/home/alex/.config/systemd/user/monzo-cli-update.service")
+        );
+        assert!(files[0].1.contains("WorkingDirectory=/home/alex/monzo\n"));
+        assert!(files[0]
+            .1
+            .contains("ExecStart=\"/usr/local/bin/monzo-cli\" --quiet update\n"));
+        assert!(files[1].1.contains("OnUnitActiveSec=3600s\n"));
+    }
+
+    #[test]
+    fn launchd_plist_escapes_paths() {
+        // Arrange
+        let service = UpdateService::new(ServiceManager::Launchd, PathBuf::from("/Users/alex"));
+
+        // Act
+        let files = service.files(
+            Path::new("/opt/monzo-cli"),
+            Path::new("/Users/alex/R&D"),
+            1800,
+        );
+
+        // Assert
+        assert_eq!(
+            files[0].0,
+            PathBuf::from("/Users/alex/Library/LaunchAgents/com.monzo-cli.update.plist")
+        );
+        assert!(files[0].1.contains("<string>/Users/alex/R&amp;D</string>"));
+        assert!(files[0].1.contains("<integer>1800</integer>"));
+    }
+}
@@ -0,0 +1,61 @@
+//! Mock Monzo API
+//!
+//! A [wiremock] server serving canned accounts, pots, balance, whoami and
+//! transactions payloads from `fixtures/`, so client and sync tests run offline.
+
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::client::Monzo;
+
+/// Access token the mock server expects in the `Authorization` header
+pub const ACCESS_TOKEN: &str = "test-access-token";
+/// Id of the account in the fixtures
+pub const ACCOUNT_ID: &str = "acc_00009237aqC8c5umZmrRdh";
+
+const FIXTURES: [(&str, &str); 5] = [
+    ("/accounts", include_str!("fixtures/accounts.json")),
+    ("/pots", include_str!("fixtures/pots.json")),
+    ("/balance", include_str!("fixtures/balance.json")),
+    ("/ping/whoami", include_str!("fixtures/whoami.json")),
+    ("/transactions", include_str!("fixtures/transactions.json")),
+];
+
+pub struct MockMonzo {
+    server: MockServer,
+}
+
+impl MockMonzo {
+    /// Start a server with every fixture mounted
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+
+        for (route, body) in FIXTURES {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .and(header("authorization", format!("Bearer {ACCESS_TOKEN}")))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
+                .mount(&server)
+                .await;
+        }
+
+        Self { server }
+    }
+
+    /// A client pointed at the mock server
+    ///
+    /// # Panics
+    /// Will panic if the client can't be built.
+    #[must_use]
+    pub fn client(&self) -> Monzo {
+        Monzo::with_base_url(&self.server.uri(), ACCESS_TOKEN).unwrap()
+    }
+
+    /// The underlying server, for mounting extra mocks or inspecting requests
+    #[must_use]
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+}
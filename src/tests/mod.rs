@@ -1,13 +1,15 @@
+#[cfg(test)]
+pub mod mock;
+
 #[cfg(test)]
 pub mod test {
-    use crate::client::Monzo;
     use crate::model::DatabasePool;
     use crate::telemetry::{get_subscriber, init_subscriber};
-    use once_cell::sync::Lazy;
+    use std::sync::LazyLock;
     use temp_dir::TempDir;
 
-    // Ensure that the `tracing` stack is only initialised once using `once_cell`
-    static TRACING: Lazy<()> = Lazy::new(|| {
+    // Ensure that the `tracing` stack is only initialised once using `LazyLock`
+    static TRACING: LazyLock<()> = LazyLock::new(|| {
         let default_filter_level = "info".to_string();
         let subscriber_name = "test".to_string();
         // We cannot assign the output of `get_subscriber` to a variable based on the
@@ -20,14 +22,15 @@ pub mod test {
         } else {
             let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
             let _ = init_subscriber(subscriber);
-        };
+        }
     });
 
-    /// Create ephemeral test db. Folder is deleted when the TempDir goes out of scope.
+    /// Create ephemeral test db. Folder is deleted when the `TempDir` goes out of scope.
+    ///
+    /// # Panics
+    /// Will panic if the database can't be created or seeded.
     pub async fn test_db() -> (DatabasePool, TempDir) {
-        use crate::model::DatabasePool;
-
-        Lazy::force(&TRACING);
+        LazyLock::force(&TRACING);
 
         let dir = temp_dir::TempDir::with_prefix("monzo-test").unwrap();
         let db_path = dir.path().join("dev.db?mode=rwc");
@@ -36,18 +39,49 @@ pub mod test {
             .await
             .unwrap();
 
-        let _ = pool
-            .seed_initial_data()
+        pool.seed_initial_data()
             .await
             .expect("Failed to seed initial data");
 
         (pool, dir)
     }
 
-    pub fn get_client() -> Monzo {
-        match Monzo::new() {
-            Ok(client) => client,
-            Err(e) => panic!("Error creating client: {e}"),
-        }
+    /// Create an empty schema in the Postgres database at `TEST_POSTGRES_URL`,
+    /// returning a url that uses it, or `None` if the variable isn't set
+    ///
+    /// # Panics
+    /// Will panic if the schema can't be created.
+    #[cfg(feature = "postgres")]
+    pub async fn test_pg_url() -> Option<String> {
+        LazyLock::force(&TRACING);
+
+        let url = std::env::var("TEST_POSTGRES_URL").ok()?;
+        let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+        let pool = sqlx::PgPool::connect(&url).await.unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {schema}"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        Some(format!("{url}{separator}options[search_path]={schema}"))
+    }
+
+    /// Create a seeded Postgres test db in a schema of its own, or `None` if
+    /// `TEST_POSTGRES_URL` isn't set
+    ///
+    /// # Panics
+    /// Will panic if the database can't be created or seeded.
+    #[cfg(feature = "postgres")]
+    pub async fn test_pg_db() -> Option<DatabasePool> {
+        let url = test_pg_url().await?;
+        let pool = DatabasePool::new_postgres(&url, 1).await.unwrap();
+
+        pool.seed_initial_data()
+            .await
+            .expect("Failed to seed initial data");
+
+        Some(pool)
     }
 }
@@ -0,0 +1,80 @@
+//! Display timezone
+//!
+//! Times are stored as naive UTC. Dates shown to the user, and the day
+//! boundaries of `--since`/`--until` ranges, use the configured display
+//! timezone so that a late-evening purchase during BST lands on the day it
+//! was made.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+
+/// Convert a stored UTC time to local time in `tz`
+#[must_use]
+pub fn to_local(utc: NaiveDateTime, tz: Tz) -> NaiveDateTime {
+    tz.from_utc_datetime(&utc).naive_local()
+}
+
+/// Convert a local time in `tz` to UTC, taking the earlier time if it's
+/// ambiguous and leaving times in a DST gap unchanged
+#[must_use]
+pub fn to_utc(local: NaiveDateTime, tz: Tz) -> NaiveDateTime {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .map_or(local, |local| local.naive_utc())
+}
+
+/// The calendar day in `tz` of a stored UTC time
+#[must_use]
+pub fn local_date(utc: NaiveDateTime, tz: Tz) -> NaiveDate {
+    to_local(utc, tz).date()
+}
+
+/// The UTC time at which `date` starts in `tz`. If midnight falls in a DST
+/// gap the day starts at the first valid local time after it.
+#[must_use]
+pub fn start_of_day(date: NaiveDate, tz: Tz) -> NaiveDateTime {
+    let midnight = date.and_time(NaiveTime::MIN);
+    (0..=2)
+        .find_map(|hour| {
+            tz.from_local_datetime(&(midnight + chrono::Duration::hours(hour)))
+                .earliest()
+        })
+        .map_or(midnight, |local| local.naive_utc())
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::London;
+
+    fn utc(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn late_evening_during_bst_is_the_next_utc_day() {
+        // 23:30 BST on 1 July is 22:30 UTC; 00:30 BST on 2 July is 23:30 UTC on 1 July
+        assert_eq!(
+            local_date(utc("2024-07-01 22:30:00"), London),
+            NaiveDate::from_ymd_opt(2024, 7, 1).unwrap()
+        );
+        assert_eq!(
+            local_date(utc("2024-07-01 23:30:00"), London),
+            NaiveDate::from_ymd_opt(2024, 7, 2).unwrap()
+        );
+        assert_eq!(
+            local_date(utc("2024-01-01 23:30:00"), London),
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        let day = NaiveDate::from_ymd_opt(2024, 7, 2).unwrap();
+
+        assert_eq!(start_of_day(day, London), utc("2024-07-01 23:00:00"));
+        assert_eq!(start_of_day(day, Tz::UTC), utc("2024-07-02 00:00:00"));
+    }
+}
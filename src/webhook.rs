@@ -0,0 +1,314 @@
+//! Webhook receiver
+//!
+//! [`router`] accepts Monzo `transaction.created` webhooks on `POST /webhook`
+//! and stores each transaction as `update` would, so it shows up without
+//! waiting for the next sync. Until a sync fetches it too, the transaction's
+//! source is `webhook`, so it doesn't count as synced.
+//!
+//! When a secret is configured a delivery must carry it, either as a `token`
+//! query parameter on the registered URL, as a bearer token, or as an
+//! HMAC-SHA256 of the body in the `X-Signature-256` header (`sha256=<hex>`),
+//! for relays that sign what they forward. A transaction already delivered is
+//! acknowledged without being stored again, and a payload that can't be
+//! parsed is kept as a dead letter and acknowledged, since retrying it won't
+//! help.
+
+use std::collections::HashMap;
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use tracing_log::log::{error, info, warn};
+
+use crate::{
+    error::AppErrors as Error,
+    lock::DatabaseLock,
+    model::{dedupe::Source, transaction::TransactionResponse, DatabasePool},
+    server::{bearer, tokens_match},
+};
+
+/// The header carrying the signature of a delivery's body
+pub const SIGNATURE_HEADER: &str = "x-signature-256";
+
+/// What the receiver needs to handle a delivery
+#[derive(Debug, Clone)]
+pub struct WebhookState {
+    pub pool: DatabasePool,
+    /// Shared secret deliveries must carry, if any
+    pub secret: Option<String>,
+    /// Database to lock while a delivery is stored
+    pub database_path: Option<String>,
+}
+
+/// What happened to a delivery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    /// The transaction was stored
+    Stored(String),
+    /// The transaction had been delivered before
+    Replayed(String),
+    /// An event other than a new transaction
+    Ignored(String),
+    /// The payload couldn't be parsed and was kept with this id
+    DeadLetter(i64),
+}
+
+#[derive(Deserialize)]
+struct WebhookEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    data: serde_json::Value,
+}
+
+/// The receiver's routes
+pub fn router(state: WebhookState) -> Router {
+    Router::new()
+        .route("/webhook", post(receive))
+        .with_state(state)
+}
+
+async fn receive(
+    State(state): State<WebhookState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let token = params
+        .get("token")
+        .map(String::as_str)
+        .or_else(|| bearer(&headers));
+    if !authorised(state.secret.as_deref(), token, signature, &body) {
+        warn!("Rejected a webhook delivery without a valid secret");
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    // Monzo retries deliveries that aren't acknowledged
+    let _lock = match state.database_path.as_deref().map(DatabaseLock::acquire) {
+        Some(Err(Error::Locked(_))) => return StatusCode::SERVICE_UNAVAILABLE,
+        Some(Err(e)) => {
+            error!("Locking the database: {e}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+        lock => lock,
+    };
+
+    match handle(&state.pool, &body).await {
+        Ok(delivery) => {
+            info!("Webhook delivery: {delivery:?}");
+            StatusCode::OK
+        }
+        Err(e) => {
+            error!("Handling webhook delivery: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Whether a delivery carries `secret` as its `token` or as the key of its
+/// `signature`. Every delivery is authorised when there's no secret.
+#[must_use]
+pub fn authorised(
+    secret: Option<&str>,
+    token: Option<&str>,
+    signature: Option<&str>,
+    body: &[u8],
+) -> bool {
+    let Some(secret) = secret else {
+        return true;
+    };
+
+    if token.is_some_and(|token| tokens_match(token, secret)) {
+        return true;
+    }
+
+    let Some(expected) = signature
+        .and_then(|signature| signature.strip_prefix("sha256="))
+        .and_then(decode_hex)
+    else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Store the transaction in a delivery unless it was delivered before
+///
+/// # Errors
+/// Will return an error if the database can't be read or written.
+pub async fn handle(pool: &DatabasePool, body: &[u8]) -> Result<Delivery, Error> {
+    let webhooks = pool.webhook_deliveries();
+    let received = Utc::now().naive_utc();
+
+    let parsed = serde_json::from_slice::<WebhookEvent>(body).and_then(|event| {
+        if event.event_type == "transaction.created" {
+            serde_json::from_value::<TransactionResponse>(event.data)
+                .map(|tx| (event.event_type, Some(tx)))
+        } else {
+            Ok((event.event_type, None))
+        }
+    });
+    let (event_type, tx_resp) = match parsed {
+        Ok((event_type, Some(tx_resp))) => (event_type, tx_resp),
+        Ok((event_type, None)) => return Ok(Delivery::Ignored(event_type)),
+        Err(e) => {
+            let body = String::from_utf8_lossy(body);
+            let id = webhooks
+                .save_dead_letter(&body, &e.to_string(), received)
+                .await?;
+            warn!("Kept unparseable webhook payload as dead letter {id}: {e}");
+            return Ok(Delivery::DeadLetter(id));
+        }
+    };
+
+    if webhooks.is_delivered(&tx_resp.id).await? {
+        return Ok(Delivery::Replayed(tx_resp.id));
+    }
+
+    pool.categories()
+        .ensure_category(&tx_resp.category_id)
+        .await?;
+    if tx_resp.amount == 0 {
+        match pool.card_events().save_card_event(&tx_resp).await {
+            Ok(()) | Err(Error::Duplicate(_)) => (),
+            Err(e) => return Err(e),
+        }
+    } else {
+        let transactions = pool.transactions();
+        transactions
+            .save_or_update_transaction_from(&tx_resp, false, Source::Webhook)
+            .await?;
+        if let Some(attachments) = &tx_resp.attachments {
+            pool.attachments()
+                .save_attachments(&tx_resp.id, attachments)
+                .await?;
+        }
+    }
+    webhooks
+        .record_delivery(&tx_resp.id, &event_type, received)
+        .await?;
+
+    Ok(Delivery::Stored(tx_resp.id))
+}
+
+// -- Utility functions ----------------------------------------------------------------
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// -- Tests ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::tests::test::test_db;
+
+    fn payload(id: &str) -> Vec<u8> {
+        json!({
+            "type": "transaction.created",
+            "data": {
+                "id": id,
+                "account_id": "1",
+                "merchant": null,
+                "amount": -350,
+                "currency": "GBP",
+                "local_amount": -350,
+                "local_currency": "GBP",
+                "created": "2024-05-01T08:30:00Z",
+                "description": "PRET A MANGER",
+                "notes": null,
+                "settled": "",
+                "updated": null,
+                "category": "eating_out"
+            }
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        let mut signature = "sha256=".to_string();
+        for byte in mac.finalize().into_bytes() {
+            let _ = write!(signature, "{byte:02x}");
+        }
+        signature
+    }
+
+    #[test]
+    fn deliveries_need_the_secret_when_there_is_one() {
+        let body = payload("tx_1");
+        let signature = sign("s3cret", &body);
+
+        assert!(authorised(None, None, None, &body));
+        assert!(authorised(Some("s3cret"), Some("s3cret"), None, &body));
+        assert!(authorised(Some("s3cret"), None, Some(&signature), &body));
+        assert!(!authorised(Some("s3cret"), None, None, &body));
+        assert!(!authorised(Some("s3cret"), Some("guess"), None, &body));
+        assert!(!authorised(Some("s3cret"), None, Some(&signature), b"{}"));
+        assert!(!authorised(Some("s3cret"), None, Some("sha256=zz"), &body));
+    }
+
+    #[tokio::test]
+    async fn replays_are_stored_once() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+        let body = payload("tx_1");
+
+        // Act
+        let first = handle(&pool, &body).await.unwrap();
+        let second = handle(&pool, &body).await.unwrap();
+
+        // Assert
+        assert_eq!(first, Delivery::Stored("tx_1".to_string()));
+        assert_eq!(second, Delivery::Replayed("tx_1".to_string()));
+        let tx = pool.transactions().read_transaction("tx_1").await.unwrap();
+        assert_eq!(tx.amount, -350);
+    }
+
+    #[tokio::test]
+    async fn unparseable_payloads_become_dead_letters() {
+        // Arrange
+        let (pool, _tmp) = test_db().await;
+
+        // Act
+        let delivery = handle(&pool, b"{\"type\": \"transaction.created\", \"data\": {}}")
+            .await
+            .unwrap();
+        let ignored = handle(&pool, b"{\"type\": \"account.updated\", \"data\": {}}")
+            .await
+            .unwrap();
+
+        // Assert
+        assert!(matches!(delivery, Delivery::DeadLetter(_)));
+        assert_eq!(ignored, Delivery::Ignored("account.updated".to_string()));
+        let letters = pool.webhook_deliveries().read_dead_letters().await.unwrap();
+        assert_eq!(letters.len(), 1);
+        assert!(letters[0].error.contains("missing field"));
+    }
+}